@@ -0,0 +1,35 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use array_lib::ArrayDim;
+
+/// random in-range subscripts for an axis
+fn subscripts(n:usize, size:u32, seed:u64) -> Vec<u32> {
+    let mut state = seed;
+    (0..n).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % size as u64) as u32
+    }).collect()
+}
+
+/// addresses of 10^6 gridding coordinates on a 256^3 grid and a 128^3 x 8 grid
+fn calc_addrs(c:&mut Criterion) {
+    let n = 1_000_000;
+    let d3 = ArrayDim::from_shape(&[256, 256, 256]);
+    let (is, js, ks) = (subscripts(n, 256, 1), subscripts(n, 256, 2), subscripts(n, 256, 3));
+    let mut out = vec![0u32; n];
+    let mut group = c.benchmark_group("calc_addrs 3D 1e6");
+    group.bench_function("scalar", |b| b.iter(|| d3.calc_addrs(&is, &js, &ks, &mut out)));
+    group.bench_function("simd", |b| b.iter(|| d3.calc_addrs_simd(&is, &js, &ks, &mut out)));
+    group.finish();
+
+    let d4 = ArrayDim::from_shape(&[128, 128, 128, 8]);
+    let (is, js, ks, ls) = (subscripts(n, 128, 1), subscripts(n, 128, 2), subscripts(n, 128, 3), subscripts(n, 8, 4));
+    let mut group = c.benchmark_group("calc_addrs 4D 1e6");
+    group.bench_function("scalar", |b| b.iter(|| d4.calc_addrs4(&is, &js, &ks, &ls, &mut out)));
+    group.bench_function("simd", |b| b.iter(|| d4.calc_addrs4_simd(&is, &js, &ks, &ls, &mut out)));
+    group.finish();
+}
+
+criterion_group!(benches, calc_addrs);
+criterion_main!(benches);
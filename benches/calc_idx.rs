@@ -0,0 +1,33 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use array_lib::ArrayDim;
+
+/// converts every address of a 5D array with odd axis sizes to subscripts
+fn calc_idx(c:&mut Criterion) {
+    let dims = ArrayDim::from_shape(&[67, 45, 33, 7, 5]);
+    let indexer = dims.indexer();
+    let mut group = c.benchmark_group("calc_idx 67x45x33x7x5");
+    group.bench_function("ArrayDim", |b| b.iter(|| {
+        (0..dims.numel()).map(|a| dims.calc_idx(black_box(a))[3]).sum::<usize>()
+    }));
+    group.bench_function("DimIndexer", |b| b.iter(|| {
+        (0..dims.numel()).map(|a| indexer.calc_idx(black_box(a))[3]).sum::<usize>()
+    }));
+    group.finish();
+}
+
+/// round trips every address of a 3D volume through subscripts, where only the first 3 of the 16
+/// axes are walked
+fn volume_3d(c:&mut Criterion) {
+    let dims = ArrayDim::from_shape(&[128, 128, 64]);
+    let mut group = c.benchmark_group("calc_idx and calc_addr 128x128x64");
+    group.bench_function("calc_idx", |b| b.iter(|| {
+        (0..dims.numel()).map(|a| dims.calc_idx(black_box(a))[2]).sum::<usize>()
+    }));
+    group.bench_function("calc_addr", |b| b.iter(|| {
+        (0..dims.numel()).map(|a| dims.calc_addr(&dims.calc_idx(black_box(a)))).sum::<usize>()
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, calc_idx, volume_3d);
+criterion_main!(benches);
@@ -0,0 +1,33 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use array_lib::ArrayDim;
+use array_lib::io_nifti::{read_nifti, write_nifti};
+
+/// reads a 1 GB f32 volume as its stored type, which skips the per-element cast, and as f64
+fn read_1gb(c:&mut Criterion) {
+    let dims = ArrayDim::from_shape(&[1024, 1024, 256]);
+    let x:Vec<f32> = (0..dims.numel()).map(|i| (i % 4096) as f32).collect();
+    write_nifti("bench_nifti_read", &x, dims);
+    drop(x);
+    let mut group = c.benchmark_group("read 1 GB f32 nifti");
+    group.sample_size(10);
+    group.bench_function("as f32", |b| b.iter(|| read_nifti::<f32>("bench_nifti_read.nii")));
+    group.bench_function("as f64", |b| b.iter(|| read_nifti::<f64>("bench_nifti_read.nii")));
+    group.finish();
+    std::fs::remove_file("bench_nifti_read.nii").unwrap();
+}
+
+/// reads a 512 MB int16 volume as f32, where the conversion runs in parallel
+fn read_i16_as_f32(c:&mut Criterion) {
+    let dims = ArrayDim::from_shape(&[1024, 1024, 256]);
+    let x:Vec<i16> = (0..dims.numel()).map(|i| (i % 4096) as i16).collect();
+    write_nifti("bench_nifti_read_i16", &x, dims);
+    drop(x);
+    let mut group = c.benchmark_group("read 512 MB int16 nifti");
+    group.sample_size(10);
+    group.bench_function("as f32", |b| b.iter(|| read_nifti::<f32>("bench_nifti_read_i16.nii")));
+    group.finish();
+    std::fs::remove_file("bench_nifti_read_i16.nii").unwrap();
+}
+
+criterion_group!(benches, read_1gb, read_i16_as_f32);
+criterion_main!(benches);
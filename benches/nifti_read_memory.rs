@@ -0,0 +1,70 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use num_complex::Complex32;
+use array_lib::ArrayDim;
+use array_lib::io_nifti::{read_nifti, read_nifti_complex, write_nifti};
+
+/// tracks the bytes currently allocated and the most allocated at once
+struct PeakAlloc {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout:Layout) -> *mut u8 {
+        let p = unsafe { System.alloc(layout) };
+        if !p.is_null() {
+            let now = self.current.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            self.peak.fetch_max(now, Ordering::Relaxed);
+        }
+        p
+    }
+
+    unsafe fn dealloc(&self, ptr:*mut u8, layout:Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        self.current.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOC:PeakAlloc = PeakAlloc { current: AtomicUsize::new(0), peak: AtomicUsize::new(0) };
+
+/// runs f, returning the most memory allocated while it ran above what was allocated before
+fn peak_during<R>(f:impl FnOnce() -> R) -> (R, usize) {
+    let base = ALLOC.current.load(Ordering::Relaxed);
+    ALLOC.peak.store(base, Ordering::Relaxed);
+    let r = f();
+    (r, ALLOC.peak.load(Ordering::Relaxed) - base)
+}
+
+/// reports the peak memory of reading a 512 MB complex volume and a 256 MB real volume, as a
+/// multiple of the size of the returned data. Run with
+/// cargo bench --features io-nifti --bench nifti_read_memory
+fn main() {
+    let dims = ArrayDim::from_shape(&[512, 512, 256]);
+    let z:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32, 1.)).collect();
+    write_nifti("bench_nifti_memory_c", &z, dims);
+    drop(z);
+    let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+    write_nifti("bench_nifti_memory", &x, dims);
+    drop(x);
+
+    let report = |name:&str, data_bytes:usize, peak:usize| {
+        println!("{}: peak {} MB for {} MB of data ({:.2}x)", name, peak >> 20, data_bytes >> 20, peak as f64 / data_bytes as f64);
+    };
+    let ((z, ..), peak) = peak_during(|| read_nifti_complex::<f32>("bench_nifti_memory_c.nii"));
+    report("complex f32 as complex f32", z.len() * 8, peak);
+    drop(z);
+    let ((z, ..), peak) = peak_during(|| read_nifti_complex::<f64>("bench_nifti_memory_c.nii"));
+    report("complex f32 as complex f64", z.len() * 16, peak);
+    drop(z);
+    let ((x, ..), peak) = peak_during(|| read_nifti::<f32>("bench_nifti_memory.nii"));
+    report("f32 as f32", x.len() * 4, peak);
+    drop(x);
+    let ((z, ..), peak) = peak_during(|| read_nifti_complex::<f32>("bench_nifti_memory.nii"));
+    report("f32 as complex f32", z.len() * 8, peak);
+    drop(z);
+
+    std::fs::remove_file("bench_nifti_memory_c.nii").unwrap();
+    std::fs::remove_file("bench_nifti_memory.nii").unwrap();
+}
@@ -0,0 +1,27 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use ndarray::ShapeBuilder;
+use array_lib::ArrayDim;
+use array_lib::io_nifti::{write_nifti_with_options, NiftiWriteOptions};
+
+/// writes a 2 GB f32 volume with the streaming writer and with the nifti crate writer, which
+/// first copies the volume into an owned ndarray. Peak memory of the streaming writer stays near
+/// the size of the input
+fn write_2gb(c:&mut Criterion) {
+    let dims = ArrayDim::from_shape(&[1024, 1024, 512]);
+    let x:Vec<f32> = (0..dims.numel()).map(|i| (i % 4096) as f32).collect();
+    let mut group = c.benchmark_group("write 2 GB f32 nifti");
+    group.sample_size(10);
+    group.bench_function("streamed", |b| b.iter(|| {
+        write_nifti_with_options("bench_nifti_streamed.nii", &x, dims, None, &NiftiWriteOptions::new()).unwrap()
+    }));
+    group.bench_function("ndarray", |b| b.iter(|| {
+        let arr = ndarray::Array::from_shape_vec([1024, 1024, 512].f(), x.clone()).unwrap();
+        nifti::writer::WriterOptions::new("bench_nifti_ndarray.nii").write_nifti(&arr).unwrap()
+    }));
+    group.finish();
+    std::fs::remove_file("bench_nifti_streamed.nii").unwrap();
+    std::fs::remove_file("bench_nifti_ndarray.nii").unwrap();
+}
+
+criterion_group!(benches, write_2gb);
+criterion_main!(benches);
@@ -0,0 +1,32 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use num_complex::Complex32;
+use array_lib::ArrayDim;
+
+/// swaps the first two axes of a 1024 x 1024 x 128 f32 volume
+fn transpose(c:&mut Criterion) {
+    let dims = ArrayDim::from_shape(&[1024, 1024, 128]);
+    let src:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+    let mut dst = dims.alloc(0f32);
+    let order = [1, 0, 2];
+    let mut group = c.benchmark_group("transpose 1024x1024x128 f32");
+    group.sample_size(10);
+    group.bench_function("naive", |b| b.iter(|| dims.permute_naive(&src, &mut dst, &order)));
+    group.bench_function("blocked", |b| b.iter(|| dims.permute_blocked(&src, &mut dst, &order)));
+    group.finish();
+}
+
+/// moves the coil axis of [x, y, z, coil, echo] complex data to the front
+fn coil_axis(c:&mut Criterion) {
+    let dims = ArrayDim::from_shape(&[256, 128, 64, 8, 2]);
+    let src:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32, 0.)).collect();
+    let mut dst = dims.alloc(Complex32::ZERO);
+    let order = [3, 0, 1, 2, 4];
+    let mut group = c.benchmark_group("coil axis to front 256x128x64x8x2 c32");
+    group.sample_size(10);
+    group.bench_function("naive", |b| b.iter(|| dims.permute_naive(&src, &mut dst, &order)));
+    group.bench_function("blocked", |b| b.iter(|| dims.permute_blocked(&src, &mut dst, &order)));
+    group.finish();
+}
+
+criterion_group!(benches, transpose, coil_axis);
+criterion_main!(benches);
@@ -0,0 +1,4 @@
+//! the static library of the array-lib C interface, declared in include/array_lib.h. It is built
+//! separately from array-lib so that only this crate is a staticlib, leaving array-lib buildable
+//! without std
+pub use array_lib::ffi::*;
@@ -0,0 +1,32 @@
+//! builds tests/test_cfl.c against the static library and include/array_lib.h, and runs it
+#![cfg(unix)]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn test_c_abi() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    // integration tests run from target/<profile>/deps, next to the library
+    let exe = std::env::current_exe().unwrap();
+    let lib = exe.parent().and_then(|deps| deps.parent()).unwrap().join("libarray_lib_ffi.a");
+    assert!(lib.is_file(), "{} not found", lib.display());
+
+    let program = out_dir.join("test_cfl");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(cc)
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-I").arg(manifest_dir.join("../include"))
+        .arg(manifest_dir.join("tests/test_cfl.c"))
+        .arg(&lib)
+        .args(["-lpthread", "-ldl", "-lm"])
+        .arg("-o").arg(&program)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "failed to compile the C test program");
+
+    let status = Command::new(&program).current_dir(&out_dir).status().unwrap();
+    assert!(status.success(), "the C test program failed");
+}
@@ -0,0 +1,87 @@
+// exercises the C interface against the static library. Run by tests/c_abi.rs
+#include <stdio.h>
+#include <string.h>
+#include "array_lib.h"
+
+#define CHECK(call, expected) do { \
+    ArrayLibStatus s = (call); \
+    if (s != (expected)) { \
+        fprintf(stderr, "%s:%d: %s returned %d (%s)\n", __FILE__, __LINE__, #call, s, array_lib_status_message(s)); \
+        return 1; \
+    } \
+} while (0)
+
+int main(void) {
+    size_t shape[3] = {4, 3, 2};
+    ArrayDim *dims = NULL;
+    CHECK(arraydim_from_shape(shape, 3, &dims), ARRAY_LIB_STATUS_OK);
+
+    size_t numel = 0, ndim = 0, addr = 0;
+    CHECK(arraydim_numel(dims, &numel), ARRAY_LIB_STATUS_OK);
+    CHECK(arraydim_ndim(dims, &ndim), ARRAY_LIB_STATUS_OK);
+    if (numel != 24 || ndim != 3) {
+        fprintf(stderr, "numel = %zu and ndim = %zu\n", numel, ndim);
+        return 1;
+    }
+    size_t idx[3] = {1, 2, 1};
+    CHECK(arraydim_calc_addr(dims, idx, 3, &addr), ARRAY_LIB_STATUS_OK);
+    if (addr != 1 + 2 * 4 + 1 * 12) {
+        fprintf(stderr, "address of [1, 2, 1] is %zu\n", addr);
+        return 1;
+    }
+    size_t outside[3] = {0, 3, 0};
+    CHECK(arraydim_calc_addr(dims, outside, 3, &addr), ARRAY_LIB_STATUS_OUT_OF_BOUNDS);
+
+    // element k holds k + i * (100 + k)
+    float data[2 * 24];
+    for (size_t k = 0; k < numel; k++) {
+        data[2 * k] = (float)k;
+        data[2 * k + 1] = 100.0f + (float)k;
+    }
+    CHECK(cfl_write("test_ffi_c", data, numel, dims), ARRAY_LIB_STATUS_OK);
+    CHECK(cfl_write("test_ffi_c", data, numel - 1, dims), ARRAY_LIB_STATUS_LENGTH_MISMATCH);
+
+    float *read = NULL;
+    size_t len = 0;
+    ArrayDim *read_dims = NULL;
+    CHECK(cfl_read("test_ffi_c", &read, &len, &read_dims), ARRAY_LIB_STATUS_OK);
+    size_t read_shape[16] = {0};
+    CHECK(arraydim_shape(read_dims, read_shape, 16), ARRAY_LIB_STATUS_OK);
+    if (len != numel || memcmp(read_shape, shape, sizeof(shape)) != 0 || memcmp(read, data, sizeof(data)) != 0) {
+        fprintf(stderr, "read back a different array\n");
+        return 1;
+    }
+    CHECK(arraydim_shape(read_dims, read_shape, 2), ARRAY_LIB_STATUS_LENGTH_MISMATCH);
+
+    // the data file names the pair as well as the base name
+    float *read_cfl = NULL;
+    size_t cfl_len = 0;
+    ArrayDim *cfl_dims = NULL;
+    CHECK(cfl_read("test_ffi_c.cfl", &read_cfl, &cfl_len, &cfl_dims), ARRAY_LIB_STATUS_OK);
+    if (cfl_len != numel || memcmp(read_cfl, data, sizeof(data)) != 0) {
+        fprintf(stderr, "read back a different array from test_ffi_c.cfl\n");
+        return 1;
+    }
+    cfl_free(read_cfl, cfl_len);
+    arraydim_free(cfl_dims);
+
+    // errors leave the outputs untouched
+    float *missing = NULL;
+    CHECK(cfl_read("test_ffi_missing", &missing, &len, &read_dims), ARRAY_LIB_STATUS_IO);
+    CHECK(cfl_read(NULL, &missing, &len, &read_dims), ARRAY_LIB_STATUS_NULL_POINTER);
+    size_t empty[2] = {4, 0};
+    ArrayDim *invalid = NULL;
+    CHECK(arraydim_from_shape(empty, 2, &invalid), ARRAY_LIB_STATUS_INVALID_SHAPE);
+    if (missing != NULL || invalid != NULL) {
+        fprintf(stderr, "outputs were written on error\n");
+        return 1;
+    }
+
+    cfl_free(read, len);
+    arraydim_free(read_dims);
+    arraydim_free(dims);
+    arraydim_free(NULL);
+    remove("test_ffi_c.cfl");
+    remove("test_ffi_c.hdr");
+    return 0;
+}
@@ -0,0 +1,120 @@
+
+// C interface to the array-lib layout math and cfl IO. Arrays are column-major, so the first
+// subscript varies fastest.
+//
+// Ownership:
+// - ArrayDim handles from arraydim_from_shape and cfl_read are owned by the caller and must be
+//   released with arraydim_free
+// - data buffers from cfl_read are owned by the caller and must be released with cfl_free,
+//   passing the length that cfl_read returned. They must not be passed to free()
+// - buffers and paths passed in are borrowed for the duration of the call only
+// - status messages are static strings that must not be freed
+//
+// Every function returns a status code and writes its outputs only on success. Panics inside
+// the library are caught and reported as ARRAY_LIB_STATUS_PANIC.
+
+#ifndef ARRAY_LIB_H
+#define ARRAY_LIB_H
+
+// Generated by cbindgen from src/ffi.rs. Don't edit by hand
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// the result of a call
+typedef enum ArrayLibStatus {
+  // the call succeeded
+  ARRAY_LIB_STATUS_OK = 0,
+  // a required pointer argument was null
+  ARRAY_LIB_STATUS_NULL_POINTER = 1,
+  // the shape has an axis of length 0 or non-singleton axes past the 16 supported
+  ARRAY_LIB_STATUS_INVALID_SHAPE = 2,
+  // a subscript lies outside its axis
+  ARRAY_LIB_STATUS_OUT_OF_BOUNDS = 3,
+  // a buffer length doesn't match the number of elements of the array
+  ARRAY_LIB_STATUS_LENGTH_MISMATCH = 4,
+  // the path isn't valid UTF-8
+  ARRAY_LIB_STATUS_INVALID_PATH = 5,
+  // the file couldn't be read or written
+  ARRAY_LIB_STATUS_IO = 6,
+  // the file isn't a valid cfl, as for a malformed header or a data file of the wrong size
+  ARRAY_LIB_STATUS_MALFORMED_FILE = 7,
+  // the array is too large to allocate
+  ARRAY_LIB_STATUS_ALLOC = 8,
+  // the library panicked. This is a bug
+  ARRAY_LIB_STATUS_PANIC = 9,
+} ArrayLibStatus;
+
+typedef struct ArrayDim ArrayDim;
+
+// a static description of a status, which must not be freed
+const char *array_lib_status_message(ArrayLibStatus status);
+
+// creates an array handle from the ndim axis lengths in shape. Axes past ndim are singleton, and
+// shape may be null when ndim is 0. The handle is written to out and must be released with
+// arraydim_free
+//
+// # Safety
+// shape must point to ndim readable values and out must be writable
+ArrayLibStatus arraydim_from_shape(const size_t *shape, size_t ndim, ArrayDim **out);
+
+// releases an array handle. Null handles are ignored
+//
+// # Safety
+// dims must be null or a handle from this library that hasn't been released
+void arraydim_free(ArrayDim *dims);
+
+// writes the number of elements of the array to out
+//
+// # Safety
+// dims must be a live handle and out must be writable
+ArrayLibStatus arraydim_numel(const ArrayDim *dims, size_t *out);
+
+// writes the number of axes up to the last non-singleton one (at least 1) to out. This is the
+// number of axis lengths arraydim_shape writes
+//
+// # Safety
+// dims must be a live handle and out must be writable
+ArrayLibStatus arraydim_ndim(const ArrayDim *dims, size_t *out);
+
+// writes the axis lengths up to the last non-singleton axis to out, which holds len values.
+// Returns ARRAY_LIB_STATUS_LENGTH_MISMATCH if len is less than arraydim_ndim
+//
+// # Safety
+// dims must be a live handle and out must have room for len values
+ArrayLibStatus arraydim_shape(const ArrayDim *dims, size_t *out, size_t len);
+
+// writes the column-major address of the n subscripts in idx to out. Axes past n have a
+// subscript of 0. Returns ARRAY_LIB_STATUS_OUT_OF_BOUNDS if a subscript lies outside its axis
+//
+// # Safety
+// dims must be a live handle, idx must point to n readable values and out must be writable
+ArrayLibStatus arraydim_calc_addr(const ArrayDim *dims, const size_t *idx, size_t n, size_t *out);
+
+// reads a cfl file pair given the base name or either file, where only a .cfl or .hdr extension
+// is dropped from the name. The data is written to out_data as interleaved real and imaginary
+// floats, with the number of complex elements in out_len and the array handle in out_dims. The
+// data must be released with cfl_free and the handle with arraydim_free
+//
+// # Safety
+// path must be a nul-terminated string and the outputs must be writable
+ArrayLibStatus cfl_read(const char *path, float **out_data, size_t *out_len, ArrayDim **out_dims);
+
+// releases data returned by cfl_read, where len is the number of complex elements it returned.
+// Null pointers are ignored
+//
+// # Safety
+// data must be null or a buffer from cfl_read with its length, that hasn't been released
+void cfl_free(float *data, size_t len);
+
+// writes a cfl file pair from len complex elements held as interleaved real and imaginary floats.
+// len must be the number of elements of the array
+//
+// # Safety
+// path must be a nul-terminated string, data must point to 2 * len readable floats and dims
+// must be a live handle
+ArrayLibStatus cfl_write(const char *path, const float *data, size_t len, const ArrayDim *dims);
+
+#endif  /* ARRAY_LIB_H */
@@ -0,0 +1,8 @@
+//! the array_lib python extension module. The functions are defined in array_lib::python
+use pyo3::prelude::*;
+
+#[pymodule]
+#[pyo3(name = "array_lib")]
+fn module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    array_lib::python::register(m)
+}
@@ -0,0 +1,53 @@
+# build the module into the active environment with `maturin develop` in python/, then run
+# `pytest python/tests`
+import numpy as np
+import pytest
+
+import array_lib
+
+
+def ramp(shape, order="F"):
+    n = int(np.prod(shape))
+    re = np.arange(n, dtype=np.float32)
+    return (re - 1j * re[::-1]).astype(np.complex64).reshape(shape, order=order)
+
+
+@pytest.mark.parametrize("shape", [(4, 3, 2), (5,), (4, 1, 3), (2, 3, 1, 1, 2), (4, 3, 1), (5, 1, 1, 1, 1, 1)])
+def test_cfl_round_trip(tmp_path, shape):
+    x = ramp(shape)
+    base = str(tmp_path / "round_trip")
+    array_lib.write_cfl(base, x)
+    y = array_lib.read_cfl(base)
+    assert y.dtype == np.complex64
+    assert y.shape == x.shape
+    assert y.flags.f_contiguous
+    np.testing.assert_array_equal(y, x)
+
+
+def test_cfl_layout(tmp_path):
+    # the file holds the column-major order of the array, with the shape in the header
+    x = ramp((4, 3, 2))
+    base = tmp_path / "layout"
+    array_lib.write_cfl(str(base), x)
+    np.testing.assert_array_equal(np.fromfile(str(base) + ".cfl", dtype=np.complex64), x.ravel(order="F"))
+    dims = [int(d) for d in (tmp_path / "layout.hdr").read_text().splitlines()[1].split()]
+    assert dims == [4, 3, 2]
+
+
+def test_cfl_trailing_singletons(tmp_path):
+    # trailing singleton axes are listed in the header and come back, and either file names the pair
+    x = ramp((4, 3, 1))
+    array_lib.write_cfl(str(tmp_path / "singleton.cfl"), x)
+    dims = [int(d) for d in (tmp_path / "singleton.hdr").read_text().splitlines()[1].split()]
+    assert dims == [4, 3, 1]
+    y = array_lib.read_cfl(str(tmp_path / "singleton.hdr"))
+    assert y.shape == (4, 3, 1)
+    np.testing.assert_array_equal(y, x)
+
+
+def test_cfl_from_c_order(tmp_path):
+    # C-ordered and strided arrays are copied to column-major order before writing
+    for x in [ramp((4, 3, 2), order="C"), ramp((8, 3, 2))[::2, :, ::-1]]:
+        base = str(tmp_path / "c_order")
+        array_lib.write_cfl(base, x)
+        np.testing.assert_array_equal(array_lib.read_cfl(base), x)
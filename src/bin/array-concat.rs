@@ -0,0 +1,353 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::Parser;
+use array_lib::ArrayDim;
+use array_lib::io::same_file;
+use array_lib::io_cfl::{cfl_paths, read_cfl_dims, try_read_cfl, CflIoError, CflViewMut};
+use array_lib::io_nifti::{nifti_output_path, read_nifti_header, NiftiHeader, NiftiIoError, NiftiStreamReader, NiftiStreamWriter, NiftiWriteOptions};
+use bytemuck::Pod;
+use nifti::DataElement;
+use num_complex::{Complex32, Complex64};
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex32;
+    use array_lib::ArrayDim;
+    use array_lib::io_cfl::{cfl_paths, read_cfl, write_cfl};
+    use array_lib::io_nifti::{read_nifti, write_nifti_with_options, NiftiHeader, NiftiWriteOptions};
+    use clap::Parser;
+    use crate::{run, Args, ArrayConcatError};
+
+    fn remove_cfl(base:&str) {
+        let (hdr, cfl) = cfl_paths(base);
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+    }
+
+    #[test]
+    fn test_concat_and_stack() {
+        let dims = ArrayDim::from_shape(&[2,3]);
+        let inputs:Vec<Vec<Complex32>> = (0..3).map(|f| {
+            (0..6).map(|i| Complex32::new((10 * f + i) as f32, f as f32)).collect()
+        }).collect();
+        for (f, x) in inputs.iter().enumerate() {
+            write_cfl(format!("test_array_concat_{}", f),x,dims);
+        }
+        write_cfl("test_array_concat_bad",&[Complex32::ZERO; 8],ArrayDim::from_shape(&[2,4]));
+
+        let concat = |extra:&[&str], files:&[&str]| run(Args::parse_from(
+            ["array-concat"].iter().chain(extra).chain(files)
+        ));
+        let files = ["test_array_concat_0", "test_array_concat_1", "test_array_concat_2"];
+        concat(&["--axis", "0", "-o", "test_array_concat_out"], &files).unwrap();
+        let (cat, cat_dims) = read_cfl("test_array_concat_out");
+        concat(&["--axis", "2", "--new-axis", "-o", "test_array_concat_out"], &files).unwrap();
+        let (stack, stack_dims) = read_cfl("test_array_concat_out");
+        let bad = concat(&["--axis", "0", "-o", "test_array_concat_out"], &["test_array_concat_0", "test_array_concat_bad"]);
+        // writing over an input is refused before anything is written
+        let in_place = concat(&["--axis", "0", "-o", "test_array_concat_1.cfl"], &files);
+        let (in_place_x, _) = read_cfl("test_array_concat_1");
+
+        for base in files.iter().chain(&["test_array_concat_bad", "test_array_concat_out"]) {
+            remove_cfl(base);
+        }
+
+        // concatenating along the first axis interleaves the inputs within each column
+        assert_eq!(cat_dims.shape_ns(), &[6,3]);
+        for (f, x) in inputs.iter().enumerate() {
+            for j in 0..3 {
+                for i in 0..2 {
+                    assert_eq!(cat[cat_dims.calc_addr(&[2 * f + i, j])], x[dims.calc_addr(&[i, j])]);
+                }
+            }
+        }
+
+        // stacking along a new last axis lays the inputs out one after another
+        assert_eq!(stack_dims.shape_ns(), &[2,3,3]);
+        assert_eq!(stack, inputs.concat());
+
+        match bad {
+            Err(ArrayConcatError::ShapeMismatch{path, ..}) => assert_eq!(path.to_str(), Some("test_array_concat_bad")),
+            _ => panic!("expected a shape mismatch"),
+        }
+        assert!(matches!(in_place, Err(ArrayConcatError::InPlace(_))));
+        assert_eq!(in_place_x, inputs[1]);
+    }
+
+    #[test]
+    fn test_concat_nifti() {
+        let dims = ArrayDim::from_shape(&[2,3,2]);
+        let mut h = NiftiHeader::default();
+        h.pixdim = [1., 0.5, 0.5, 2., 1., 1., 1., 1.];
+        let inputs:Vec<Vec<i16>> = (0..2).map(|f| (0..12).map(|i| 100 * f + i).collect()).collect();
+        for (f, x) in inputs.iter().enumerate() {
+            write_nifti_with_options(format!("test_array_concat_{}.nii", f),x,dims,Some(&h),&NiftiWriteOptions::new()).unwrap();
+        }
+        write_nifti_with_options("test_array_concat_f32.nii",&[0f32; 12],dims,None,&NiftiWriteOptions::new()).unwrap();
+
+        let concat = |extra:&[&str], files:&[&str]| run(Args::parse_from(
+            ["array-concat"].iter().chain(extra).chain(files)
+        ));
+        let files = ["test_array_concat_0.nii", "test_array_concat_1.nii"];
+        concat(&["--axis", "1", "-o", "test_array_concat_nii_out.nii"], &files).unwrap();
+        let (cat, cat_dims, cat_h) = read_nifti::<i16>("test_array_concat_nii_out.nii");
+        concat(&["--axis", "3", "--new-axis", "-o", "test_array_concat_nii_out.nii.gz"], &files).unwrap();
+        let (stack, stack_dims, _) = read_nifti::<i16>("test_array_concat_nii_out.nii.gz");
+        let bad = concat(&["--axis", "0", "-o", "test_array_concat_bad.nii"], &["test_array_concat_0.nii", "test_array_concat_f32.nii"]);
+        let in_place = concat(&["--axis", "1", "-o", "test_array_concat_0"], &files);
+        let (in_place_x, ..) = read_nifti::<i16>("test_array_concat_0.nii");
+
+        for f in files.iter().chain(&["test_array_concat_f32.nii", "test_array_concat_nii_out.nii", "test_array_concat_nii_out.nii.gz"]) {
+            std::fs::remove_file(f).unwrap();
+        }
+
+        // the datatype and voxel sizes of the first input are kept
+        assert_eq!(cat_h.datatype, 4);
+        assert_eq!(cat_h.pixdim[1..4], [0.5, 0.5, 2.]);
+        assert_eq!(cat_dims.shape_ns(), &[2,6,2]);
+        for (f, x) in inputs.iter().enumerate() {
+            for k in 0..2 {
+                for j in 0..3 {
+                    for i in 0..2 {
+                        assert_eq!(cat[cat_dims.calc_addr(&[i, 3 * f + j, k])], x[dims.calc_addr(&[i, j, k])]);
+                    }
+                }
+            }
+        }
+
+        assert_eq!(stack_dims.shape_ns(), &[2,3,2,2]);
+        assert_eq!(stack, inputs.concat());
+
+        assert!(matches!(bad, Err(ArrayConcatError::DatatypeMismatch(_))));
+        assert!(!std::path::Path::new("test_array_concat_bad.nii").exists());
+        assert!(matches!(in_place, Err(ArrayConcatError::InPlace(_))));
+        assert_eq!(in_place_x, inputs[0]);
+    }
+}
+
+#[derive(Parser)]
+struct Args {
+    /// input files, all cfl or all nifti. Inputs are joined in the order given
+    #[clap(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// output file, in the format of the inputs
+    #[clap(short, long)]
+    output: PathBuf,
+
+    /// the axis to join along
+    #[clap(long)]
+    axis: usize,
+
+    /// stack the inputs along a new axis inserted at --axis instead of concatenating along an
+    /// existing one
+    #[clap(long)]
+    new_axis: bool,
+}
+
+#[derive(Debug)]
+enum ArrayConcatError {
+    Cfl(CflIoError),
+    Nifti(NiftiIoError),
+    MixedFormats(PathBuf),
+    InPlace(PathBuf),
+    DatatypeMismatch(PathBuf),
+    InvalidAxis(usize),
+    ShapeMismatch{path: PathBuf, expected: Vec<usize>, actual: Vec<usize>},
+}
+
+impl Display for ArrayConcatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArrayConcatError::Cfl(e) => write!(f, "{}", e),
+            ArrayConcatError::Nifti(e) => write!(f, "{}", e),
+            ArrayConcatError::MixedFormats(path) => write!(
+                f, "{} is not in the format of the first input. Inputs must be all cfl or all nifti", path.display()
+            ),
+            ArrayConcatError::InPlace(path) => write!(f, "output {} would overwrite an input", path.display()),
+            ArrayConcatError::DatatypeMismatch(path) => write!(
+                f, "{} is not stored with the datatype and scaling of the first input", path.display()
+            ),
+            ArrayConcatError::InvalidAxis(axis) => write!(f, "axis {} is out of range", axis),
+            ArrayConcatError::ShapeMismatch{path, expected, actual} => write!(
+                f, "{} has shape {:?}, which doesn't match the shape {:?} of the first input outside of the joined axis",
+                path.display(), actual, expected
+            ),
+        }
+    }
+}
+
+impl From<CflIoError> for ArrayConcatError {
+    fn from(err: CflIoError) -> Self {
+        ArrayConcatError::Cfl(err)
+    }
+}
+
+impl From<NiftiIoError> for ArrayConcatError {
+    fn from(err: NiftiIoError) -> Self {
+        ArrayConcatError::Nifti(err)
+    }
+}
+
+fn is_nifti(path:&Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_lowercase();
+    name.ends_with(".nii") || name.ends_with(".nii.gz")
+}
+
+/// the dimensions of an input as joined. Stacked inputs get a singleton axis inserted at the
+/// stacking axis, which leaves their memory layout unchanged
+fn joined_dims(dims:ArrayDim, axis:usize, new_axis:bool) -> Result<ArrayDim, ArrayConcatError> {
+    let shape = dims.shape();
+    if axis >= shape.len() || (new_axis && shape[shape.len() - 1] != 1) {
+        return Err(ArrayConcatError::InvalidAxis(axis));
+    }
+    if !new_axis {
+        return Ok(dims);
+    }
+    let mut stacked = shape[..axis].to_vec();
+    stacked.push(1);
+    stacked.extend_from_slice(&shape[axis..shape.len() - 1]);
+    Ok(ArrayDim::from_shape(&stacked))
+}
+
+/// validates that all inputs match outside of the joined axis, returning the dimensions of each
+/// input as joined and the output dimensions
+fn plan(inputs:&[(PathBuf, ArrayDim)], axis:usize, new_axis:bool) -> Result<(Vec<ArrayDim>, ArrayDim), ArrayConcatError> {
+    let mut joined:Vec<ArrayDim> = vec![];
+    for (path, dims) in inputs {
+        let d = joined_dims(*dims, axis, new_axis)?;
+        if joined.first().is_some_and(|first| first.with_dim(axis, 1).shape() != d.with_dim(axis, 1).shape()) {
+            return Err(ArrayConcatError::ShapeMismatch{
+                path: path.clone(),
+                expected: inputs[0].1.shape_ns().to_vec(),
+                actual: dims.shape_ns().to_vec(),
+            });
+        }
+        joined.push(d);
+    }
+    let total = joined.iter().map(|d| d.shape()[axis]).sum();
+    let out_dims = joined[0].with_dim(axis, total);
+    Ok((joined, out_dims))
+}
+
+/// copies an input into the output at an offset along the joined axis
+fn place<T:Copy>(out:&mut [T], out_dims:&ArrayDim, axis:usize, offset:usize, data:&[T], dims:&ArrayDim) {
+    let mut start = [0; 16];
+    start[axis] = offset;
+    let mut n = 0;
+    for (addr, len) in out_dims.region_runs(&start, dims.shape()) {
+        out[addr..addr + len].copy_from_slice(&data[n..n + len]);
+        n += len;
+    }
+}
+
+/// the scaling of stored values, where a slope of 0 means unscaled
+fn scaling(h:&NiftiHeader) -> (f32, f32) {
+    if h.scl_slope == 0. { (1., 0.) } else { (h.scl_slope, h.scl_inter) }
+}
+
+/// joins nifti inputs, keeping the header and datatype of the first. The other inputs must be
+/// stored with the same datatype and scaling, so their values are copied as stored
+fn concat_nifti(args:&Args, joined:&[ArrayDim], out_dims:ArrayDim) -> Result<PathBuf, ArrayConcatError> {
+    let (_, ref_header) = read_nifti_header(&args.inputs[0])?;
+    for path in &args.inputs[1..] {
+        let (_, h) = read_nifti_header(path)?;
+        if h.datatype != ref_header.datatype || scaling(&h) != scaling(&ref_header) {
+            return Err(ArrayConcatError::DatatypeMismatch(path.clone()));
+        }
+    }
+    macro_rules! concat_as {
+        ($t:ty) => {
+            stream_blocks::<$t>(args, joined, out_dims, &ref_header)
+        };
+    }
+    match ref_header.datatype {
+        2 => concat_as!(u8),
+        4 => concat_as!(i16),
+        8 => concat_as!(i32),
+        16 => concat_as!(f32),
+        32 => concat_as!(Complex32),
+        64 => concat_as!(f64),
+        256 => concat_as!(i8),
+        512 => concat_as!(u16),
+        768 => concat_as!(u32),
+        1024 => concat_as!(i64),
+        1280 => concat_as!(u64),
+        1792 => concat_as!(Complex64),
+        t => Err(NiftiIoError::Unsupported(format!("datatype {} in {}", t, args.inputs[0].display())).into()),
+    }
+}
+
+/// streams the output in order. The output is made of blocks holding everything up to and
+/// including the joined axis, taken from each input in turn, so every input is read through once
+/// and no more than one block of an input is held in memory
+fn stream_blocks<T:DataElement + Pod>(args:&Args, joined:&[ArrayDim], out_dims:ArrayDim, ref_header:&NiftiHeader) -> Result<PathBuf, ArrayConcatError> {
+    let mut readers = args.inputs.iter().map(NiftiStreamReader::<T>::open).collect::<Result<Vec<_>, _>>()?;
+    let mut w = NiftiStreamWriter::<T>::create(&args.output, out_dims, Some(ref_header), &NiftiWriteOptions::new())?;
+    let blocks:Vec<usize> = joined.iter().map(|d| d.shape()[..=args.axis].iter().product()).collect();
+    let n_outer:usize = out_dims.shape()[args.axis + 1..].iter().product();
+    for _ in 0..n_outer {
+        for (r, &n) in readers.iter_mut().zip(&blocks) {
+            w.write_chunk(&r.read_chunk(n)?)?;
+        }
+    }
+    Ok(w.finish()?)
+}
+
+/// returns an error if the output would overwrite one of the inputs before it is read. Cfl inputs
+/// are compared by their data files and nifti inputs by the resolved output path
+fn check_output(output:&Path, inputs:&[PathBuf], nifti:bool) -> Result<(), ArrayConcatError> {
+    let data_file = |p:&Path| if nifti { p.to_path_buf() } else { cfl_paths(p).1 };
+    let out = if nifti { nifti_output_path(output) } else { data_file(output) };
+    if inputs.iter().any(|p| same_file(&data_file(p), &out)) {
+        return Err(ArrayConcatError::InPlace(output.to_path_buf()));
+    }
+    Ok(())
+}
+
+fn run(args:Args) -> Result<(), ArrayConcatError> {
+    let nifti = is_nifti(&args.inputs[0]);
+    if let Some(path) = args.inputs.iter().find(|p| is_nifti(p) != nifti) {
+        return Err(ArrayConcatError::MixedFormats(path.clone()));
+    }
+    check_output(&args.output, &args.inputs, nifti)?;
+
+    // only the headers are read up front, then each input is read on its own and copied into
+    // place in the output
+    let mut headers = vec![];
+    for path in &args.inputs {
+        let dims = if nifti { read_nifti_header(path)?.0 } else { read_cfl_dims(path)? };
+        headers.push((path.clone(), dims));
+    }
+    let (joined, out_dims) = plan(&headers, args.axis, args.new_axis)?;
+
+    if nifti {
+        let out = concat_nifti(&args, &joined, out_dims)?;
+        println!("wrote {}", out.display());
+    } else {
+        // the output is memory-mapped, so only one input is held in memory at a time
+        let mut view = CflViewMut::create(&args.output, out_dims)?;
+        let out = view.as_mut_slice().expect("memory maps are page aligned");
+        let mut offset = 0;
+        for (path, dims) in args.inputs.iter().zip(&joined) {
+            let (x, _) = try_read_cfl(path)?;
+            place(out, &out_dims, args.axis, offset, &x, dims);
+            offset += dims.shape()[args.axis];
+        }
+        view.flush()?;
+        println!("wrote {}", args.output.display());
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
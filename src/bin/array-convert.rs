@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use clap::Parser;
+use array_lib::io::{read_array, write_array, ArrayWriteOptions, IoError};
+
+#[derive(Parser)]
+struct Args {
+    /// input array. The format is taken from the extension: a cfl base name or .cfl/.hdr, .nii,
+    /// .nii.gz, .nrrd, .nhdr, .mrd or .npy
+    input: PathBuf,
+    /// output array, with the format taken from the extension as for the input. A path without an
+    /// extension is written as cfl
+    output: PathBuf,
+
+    /// gzip the data of nrrd outputs
+    #[clap(long)]
+    gzip: bool,
+}
+
+fn run(args:Args) -> Result<(), IoError> {
+    let array = read_array(&args.input)?;
+    let out = write_array(&args.output, &array, &ArrayWriteOptions::new().gzip(args.gzip))?;
+    println!("dims = {:?}", array.dims().shape_ns());
+    println!("wrote {}", out.display());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
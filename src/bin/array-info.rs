@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use clap::Parser;
+use array_lib::info::{array_info, describe_array, ArrayInfoError};
+
+#[derive(Parser)]
+struct Args {
+    /// array file to describe. The format is taken from the extension: a cfl base name or
+    /// .cfl/.hdr, .nii, .nii.gz, .nrrd, .nhdr, .mrd or .npy
+    file: PathBuf,
+
+    /// read the data to report a summary of the values: the range and where it is reached, the
+    /// mean, standard deviation, NaN and Inf counts and the fraction of zeros. Complex data is
+    /// summarized by magnitude. Only the header is read otherwise
+    #[clap(long)]
+    stats: bool,
+
+    /// print the info as json
+    #[clap(long)]
+    json: bool,
+}
+
+fn run(args:Args) -> Result<(), ArrayInfoError> {
+    let info = array_info(&args.file, false)?;
+    let summary = if args.stats { Some(describe_array(&args.file)?) } else { None };
+    if args.json {
+        let mut j = serde_json::to_value(&info).expect("array info is always serializable");
+        j["summary"] = serde_json::to_value(&summary).expect("array summaries are always serializable");
+        println!("{}", serde_json::to_string_pretty(&j).unwrap());
+    } else {
+        println!("{}", info);
+        if let Some(summary) = summary {
+            println!("summary: {}", summary);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
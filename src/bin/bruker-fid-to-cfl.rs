@@ -1,12 +1,13 @@
 use std::fs::File;
-use std::io::{BufReader, Read};
-use std::path::PathBuf;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
 use clap::Parser;
 use bruker_jcamp_rs::{parse_paravision_params, PvError, PvValue};
 use num_complex::Complex32;
 use rayon::prelude::*;
 use array_lib::ArrayDim;
-use array_lib::io_cfl::write_cfl;
+use array_lib::io_bruker::{decode_fid_chunk, read_bruker_jobs, BrukerDataError, BrukerJob, ByteOrder, FidEncoding, FidLayout, SampleFormat};
+use array_lib::io_cfl::{write_cfl, CflChunkWriter, CflIoError};
 
 //* Bruker acqp definitions to infer fid file layout *//
 /// number of echoes in a TR, usually within an inner loop of the ppg
@@ -20,9 +21,705 @@ const RECEIVERS: &str = "ACQ_ReceiverSelect";
 
 const WORD_SIZE:&str = "ACQ_word_size";
 
+/// scan and protocol names used for the default output name of a scan directory
+const SCAN_NAME:&str = "ACQ_scan_name";
+const PROTOCOL_NAME:&str = "ACQ_protocol_name";
+
+/// the sample format of the raw data, which takes precedence over the word size when present
+const RAW_DATA_FORMAT:&str = "GO_raw_data_format";
+
+/// byte order of the fid data, either "little" or "big"
+const BYTE_ORDER:&str = "BYTORDA";
+
+/// "continuous" when readouts are packed without block padding
+const GO_BLOCK_SIZE:&str = "GO_block_size";
+
+//* Bruker method definitions, which take precedence over acqp when a method file is given *//
+/// the acquired matrix size, including any oversampling
+const ENC_MATRIX:&str = "PVM_EncMatrix";
+
+/// the image matrix size, used when PVM_EncMatrix is missing
+const MATRIX:&str = "PVM_Matrix";
+
+/// number of projections (spokes) for radial acquisitions
+const N_PROJECTIONS:&str = "NPro";
+
 /// block size in bytes for the standard Bruker "KBlock" format
 const BLOCK_SIZE: usize = 1024;
 
+/// approximate bytes of fid data read at once when streaming
+const STREAM_BYTES: usize = 1 << 26;
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use num_complex::Complex32;
+    use array_lib::ArrayDim;
+    use array_lib::io_bruker::BrukerJob;
+    use array_lib::io_cfl::{cfl_paths, read_cfl, write_cfl};
+    use crate::{check_layout, convert, decode_fid, default_cfl_name, discover_scan, job_dims, job_paths, parse_selection, resolve_shape, stream_fid_to_cfl, FidToCflError, MethodParams, Selection, SizeTolerance, Source};
+    use array_lib::io_bruker::{decode_fid_chunk, ByteOrder, FidEncoding, FidLayout, SampleFormat};
+
+    /// encodes samples as 16-bit words with each readout group padded to whole blocks
+    fn padded_i16_fid(samples:&[Complex32], chunk_size_samples:usize) -> Vec<u8> {
+        let mut fid = vec![];
+        for readout in samples.chunks_exact(chunk_size_samples) {
+            fid.extend(readout.iter().flat_map(|s| [s.re as i16, s.im as i16]).flat_map(|x| x.to_le_bytes()));
+            fid.resize(fid.len().div_ceil(1024) * 1024, 0);
+        }
+        fid
+    }
+
+    fn encoding(layout:FidLayout, format:SampleFormat, byte_order:ByteOrder) -> FidEncoding {
+        FidEncoding{layout, format, byte_order}
+    }
+
+    #[test]
+    fn test_decode_16_bit() {
+        // interleaved real and imaginary i16 words, followed by block padding
+        let samples:Vec<Complex32> = (0..6).map(|i| Complex32::new(i as f32 - 3., -(i as f32) * 100.)).collect();
+        let mut bytes:Vec<u8> = samples.iter()
+            .flat_map(|s| [s.re as i16, s.im as i16])
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        bytes.resize(64, 0);
+        let mut decoded = vec![Complex32::ZERO; 6];
+        decode_fid_chunk(&bytes, SampleFormat::I16, ByteOrder::Little, &mut decoded);
+        assert_eq!(decoded, samples);
+        assert_eq!(SampleFormat::I16.bytes_per_sample(), 4);
+    }
+
+    #[test]
+    fn test_decode_float() {
+        let samples = [Complex32::new(0.25, -1.5), Complex32::new(1e6, 3.)];
+        let bytes:Vec<u8> = samples.iter().flat_map(|s| [s.re, s.im]).flat_map(|x| x.to_le_bytes()).collect();
+        let mut decoded = vec![Complex32::ZERO; 2];
+        decode_fid_chunk(&bytes, SampleFormat::F32, ByteOrder::Little, &mut decoded);
+        assert_eq!(decoded, samples);
+    }
+
+    #[test]
+    fn test_padded_and_continuous() {
+        // 3 readouts of 100 32-bit samples (800 bytes), padded to 1024 bytes each
+        let samples:Vec<Complex32> = (0..300).map(|i| Complex32::new(i as f32, -(i as f32))).collect();
+        let mut padded = vec![];
+        let mut continuous = vec![];
+        for readout in samples.chunks_exact(100) {
+            let bytes:Vec<u8> = readout.iter().flat_map(|s| [s.re as i32, s.im as i32]).flat_map(|x| x.to_le_bytes()).collect();
+            continuous.extend_from_slice(&bytes);
+            padded.extend_from_slice(&bytes);
+            padded.resize(padded.len() + 224, 0);
+        }
+
+        let format = SampleFormat::I32;
+        assert_eq!(FidLayout::detect(padded.len(), 100, 300, format), Some(FidLayout::Padded));
+        assert_eq!(FidLayout::detect(continuous.len(), 100, 300, format), Some(FidLayout::Continuous));
+        assert_eq!(FidLayout::detect(1000, 100, 300, format), None);
+
+        let dims = ArrayDim::from_shape(&[100,1,1,3]);
+        let all = Selection::all(&dims);
+        let from_padded = decode_fid(&padded, encoding(FidLayout::Padded, format, ByteOrder::Little), &dims, &all);
+        let from_continuous = decode_fid(&continuous, encoding(FidLayout::Continuous, format, ByteOrder::Little), &dims, &all);
+        assert_eq!(from_padded, samples);
+        assert_eq!(from_continuous, samples);
+    }
+
+    #[test]
+    fn test_streaming() {
+        // 40 padded readouts of 50 16-bit samples, streamed 2 readouts at a time
+        let dims = ArrayDim::from_shape(&[50,1,1,8,5]);
+        let samples:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new((i % 3000) as f32, -((i % 7) as f32))).collect();
+        let fid = padded_i16_fid(&samples, 50);
+        let enc = encoding(FidLayout::Padded, SampleFormat::I16, ByteOrder::Little);
+        let all = Selection::all(&dims);
+
+        write_cfl("test_fid_in_memory", &decode_fid(&fid, enc, &dims, &all), dims);
+        stream_fid_to_cfl(Cursor::new(&fid), "test_fid_streamed", enc, &dims, &all, 40, 3000).unwrap();
+
+        let files = [cfl_paths("test_fid_in_memory"), cfl_paths("test_fid_streamed")];
+        let [in_memory, streamed] = files.clone().map(|(_,cfl)| std::fs::read(cfl).unwrap());
+        let (y, y_dims) = read_cfl("test_fid_streamed");
+        files.into_iter().for_each(|(hdr,cfl)| {
+            std::fs::remove_file(hdr).unwrap();
+            std::fs::remove_file(cfl).unwrap();
+        });
+        assert_eq!(streamed, in_memory);
+        assert_eq!(y, samples);
+        assert_eq!(y_dims.shape(), dims.shape());
+    }
+
+    #[test]
+    fn test_selection() {
+        // 6 samples, 3 receivers, 2 echoes, 2 phase encodes and 3 repeats
+        let dims = ArrayDim::from_shape(&[6,3,2,2,1,3]);
+        let samples:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32, 1.)).collect();
+        let fid = padded_i16_fid(&samples, 36);
+        let enc = encoding(FidLayout::Padded, SampleFormat::I16, ByteOrder::Little);
+
+        let sel = Selection{
+            receivers: parse_selection("0,2", "receivers", 3).unwrap(),
+            echoes: parse_selection("1", "echoes", 2).unwrap(),
+            repeats: parse_selection("1-2", "repeats", 3).unwrap(),
+        };
+        let out_dims = sel.output_dims(&dims);
+        assert_eq!(out_dims.shape_ns(), &[6,2,1,2,1,2]);
+
+        let decoded = decode_fid(&fid, enc, &dims, &sel);
+        // all 6 readout groups are in the file, streamed one at a time
+        stream_fid_to_cfl(Cursor::new(&fid), "test_fid_selection", enc, &dims, &sel, 6, 0).unwrap();
+        let (streamed, streamed_dims) = read_cfl("test_fid_selection");
+        let (hdr, cfl) = cfl_paths("test_fid_selection");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert_eq!(streamed, decoded);
+        assert_eq!(streamed_dims.shape(), out_dims.shape());
+
+        // the same elements sliced from the full conversion
+        let full = decode_fid(&fid, enc, &dims, &Selection::all(&dims));
+        for (i, x) in decoded.iter().enumerate() {
+            let mut idx = out_dims.calc_idx(i);
+            idx[1] = sel.receivers[idx[1]];
+            idx[2] = sel.echoes[idx[2]];
+            idx[5] = sel.repeats[idx[5]];
+            assert_eq!(*x, full[dims.calc_addr(&idx)]);
+        }
+
+        assert_eq!(parse_selection("0-2,4", "echoes", 5).unwrap(), vec![0,1,2,4]);
+        assert!(matches!(parse_selection("3", "receivers", 3), Err(FidToCflError::SelectionOutOfRange{index: 3, available: 3, ..})));
+        assert!(matches!(parse_selection("a", "receivers", 3), Err(FidToCflError::InvalidSelection(..))));
+    }
+
+    #[test]
+    fn test_big_endian() {
+        let samples:Vec<Complex32> = (0..8).map(|i| Complex32::new(i as f32 * 1000. - 70000., i as f32)).collect();
+        let words:Vec<i32> = samples.iter().flat_map(|s| [s.re as i32, s.im as i32]).collect();
+        let little:Vec<u8> = words.iter().flat_map(|x| x.to_le_bytes()).collect();
+        let big:Vec<u8> = words.iter().flat_map(|x| x.to_be_bytes()).collect();
+        let dims = ArrayDim::from_shape(&[4,1,1,2]);
+        let all = Selection::all(&dims);
+        let reference = decode_fid(&little, encoding(FidLayout::Continuous, SampleFormat::I32, ByteOrder::Little), &dims, &all);
+        assert_eq!(reference, samples);
+        assert_eq!(decode_fid(&big, encoding(FidLayout::Continuous, SampleFormat::I32, ByteOrder::Big), &dims, &all), reference);
+    }
+
+    #[test]
+    fn test_scan_directory() {
+        // a ParaVision 360 scan with its data in rawdata.job0, and a scan without a method file
+        std::fs::create_dir_all("test_fid_scan/7/pdata/1").unwrap();
+        std::fs::create_dir_all("test_fid_scan/8").unwrap();
+        for f in ["rawdata.job0", "acqp", "method"] {
+            std::fs::write(format!("test_fid_scan/7/{}", f), b"").unwrap();
+        }
+        std::fs::write("test_fid_scan/8/fid", b"").unwrap();
+        let scan = discover_scan("test_fid_scan/7".as_ref());
+        let incomplete = discover_scan("test_fid_scan/8/".as_ref());
+        std::fs::remove_dir_all("test_fid_scan").unwrap();
+
+        let scan = scan.unwrap();
+        assert_eq!(scan.fid.to_str(), Some("test_fid_scan/7/rawdata.job0"));
+        assert_eq!(scan.acqp.to_str(), Some("test_fid_scan/7/acqp"));
+        assert_eq!(scan.method.to_str(), Some("test_fid_scan/7/method"));
+        match incomplete {
+            Err(FidToCflError::MissingScanFiles{missing, ..}) => assert_eq!(missing, vec!["acqp", "method"]),
+            r => panic!("expected missing scan files, got {:?}", r),
+        }
+
+        // the scan number comes from the directory name, or from ACQ_scan_name
+        let name = default_cfl_name("data/7".as_ref(), Some("<T2_TurboRARE (E7)>"), Some("<T2_TurboRARE 3D>"));
+        assert_eq!(name.to_str(), Some("7_T2_TurboRARE_3D"));
+        let name = default_cfl_name(".".as_ref(), Some("<1_Localizer (E12)>"), Some("<1_Localizer>"));
+        assert_eq!(name.to_str(), Some("12_1_Localizer"));
+        assert_eq!(default_cfl_name("scan".as_ref(), None, None).to_str(), Some("scan"));
+    }
+
+    #[test]
+    fn test_method_precedence() {
+        // acqp reports 256 real words per readout and 64 x 32 phase encodes
+        let acq_size = [256,64,32];
+        let acqp_only = resolve_shape(&acq_size, None, None);
+        assert_eq!(acqp_only.readout, (256, Source::Acqp));
+        assert_eq!(acqp_only.oversampling, (1, Source::Acqp));
+        assert_eq!(acqp_only.phase_encodes, (vec![64,32], Source::Acqp));
+
+        // the method disagrees on the readout and phase encodes
+        let method = MethodParams{enc_matrix: Some(vec![128,60,30]), matrix: Some(vec![64,60,30]), n_projections: None};
+        let shape = resolve_shape(&acq_size, Some(&method), None);
+        assert_eq!(shape.readout, (128, Source::Method));
+        assert_eq!(shape.oversampling, (2, Source::Method));
+        assert_eq!(shape.phase_encodes, (vec![60,30], Source::Method));
+        assert_eq!(shape.dims(4, 2, 3).shape_ns(), &[128,4,2,60,30,3]);
+
+        // the oversampling flag overrides both files
+        let shape = resolve_shape(&acq_size, Some(&method), Some(4));
+        assert_eq!(shape.readout, (64, Source::Flag));
+        assert_eq!(shape.oversampling, (4, Source::Flag));
+        assert_eq!(shape.phase_encodes.1, Source::Method);
+
+        // radial spoke counts replace the phase encodes, and a readout that does not divide
+        // ACQ_size falls back to acqp
+        let radial = MethodParams{enc_matrix: None, matrix: Some(vec![100]), n_projections: Some(402)};
+        let shape = resolve_shape(&acq_size, Some(&radial), None);
+        assert_eq!(shape.readout, (256, Source::Acqp));
+        assert_eq!(shape.phase_encodes, (vec![402], Source::Method));
+        assert_eq!(shape.dims(1, 1, 1).shape_ns(), &[256,1,1,402]);
+    }
+
+    #[test]
+    fn test_jobs() {
+        // an imaging job of 8 samples x 2 receivers x 6 scans and a navigator job of 4 samples x 2
+        // receivers x 5 scans, in continuous 32-bit job files
+        let jobs = [
+            BrukerJob{scan_size: 16, transaction_blocks: 6, title: String::from("job0")},
+            BrukerJob{scan_size: 8, transaction_blocks: 5, title: String::from("job1")},
+        ];
+        let imaging_dims = ArrayDim::from_shape(&[20,2,1,3,2]);
+        let job_dims = jobs.iter().enumerate().map(|(i, job)| job_dims(job, i, &imaging_dims, 2)).collect::<Vec<_>>();
+        assert_eq!(job_dims[0].shape_ns(), &[8,2,1,3,2]);
+        assert_eq!(job_dims[1].shape_ns(), &[4,2,1,5]);
+
+        std::fs::create_dir_all("test_fid_jobs").unwrap();
+        let samples:Vec<Vec<Complex32>> = job_dims.iter().enumerate()
+            .map(|(j, d)| (0..d.numel()).map(|i| Complex32::new(i as f32, j as f32)).collect())
+            .collect();
+        for (j, samples) in samples.iter().enumerate() {
+            let bytes:Vec<u8> = samples.iter().flat_map(|s| [s.re as i32, s.im as i32]).flat_map(|x| x.to_le_bytes()).collect();
+            std::fs::write(format!("test_fid_jobs/rawdata.job{}", j), bytes).unwrap();
+        }
+
+        // a single job is read from its own file, and all jobs are written with a suffix
+        let (fid, cfl) = job_paths("test_fid_jobs/rawdata.job0".as_ref(), "test_fid_jobs/out".as_ref(), 1, false);
+        assert_eq!(fid.to_str(), Some("test_fid_jobs/rawdata.job1"));
+        assert_eq!(cfl.to_str(), Some("test_fid_jobs/out"));
+        let mut outputs = vec![];
+        for (j, dims) in job_dims.iter().enumerate() {
+            let (fid, cfl) = job_paths("test_fid_jobs/rawdata.job0".as_ref(), "test_fid_jobs/out".as_ref(), j, true);
+            assert_eq!(cfl.to_str().unwrap(), format!("test_fid_jobs/out_job{}", j));
+            let extent = check_layout(&fid, FidLayout::Continuous, dims, SampleFormat::I32, SizeTolerance::default()).unwrap();
+            let enc = encoding(extent.layout, SampleFormat::I32, ByteOrder::Little);
+            convert(&fid, &cfl, enc, dims, &Selection::all(dims), extent.chunks, j == 1).unwrap();
+            outputs.push(read_cfl(&cfl));
+        }
+        let wrong_size = check_layout("test_fid_jobs/rawdata.job1".as_ref(), FidLayout::Continuous, &job_dims[0], SampleFormat::I32, SizeTolerance::default());
+        std::fs::remove_dir_all("test_fid_jobs").unwrap();
+        for ((y, y_dims), (samples, dims)) in outputs.iter().zip(samples.iter().zip(&job_dims)) {
+            assert_eq!(y, samples);
+            assert_eq!(y_dims.shape(), dims.shape());
+        }
+        assert!(matches!(wrong_size, Err(FidToCflError::UnexpectedFileSize{actual: 320, ..})));
+    }
+
+    #[test]
+    fn test_short_fid() {
+        // 3 repeats of 4 padded readouts of 50 16-bit samples, stopped after 6 readouts and part
+        // of the 7th
+        let dims = ArrayDim::from_shape(&[50,1,1,4,1,3]);
+        let samples:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32, 1.)).collect();
+        let fid = padded_i16_fid(&samples, 50);
+        std::fs::write("test_fid_short", &fid[..6 * 1024 + 100]).unwrap();
+        let strict = check_layout("test_fid_short".as_ref(), FidLayout::Padded, &dims, SampleFormat::I16, SizeTolerance::default());
+        let tol = SizeTolerance{allow_short: true, ..Default::default()};
+        let extent = check_layout("test_fid_short".as_ref(), FidLayout::Padded, &dims, SampleFormat::I16, tol).unwrap();
+        assert!(matches!(strict, Err(FidToCflError::UnexpectedFileSize{expected: 12288, actual: 6244, ..})));
+        assert_eq!((extent.layout, extent.dims.shape(), extent.chunks), (FidLayout::Padded, dims.with_dim(5, 2).shape(), 6));
+
+        // the complete readouts are converted and the rest of the second repeat is zero-filled
+        let enc = encoding(extent.layout, SampleFormat::I16, ByteOrder::Little);
+        let all = Selection::all(&extent.dims);
+        convert("test_fid_short".as_ref(), "test_fid_short_in_memory".as_ref(), enc, &extent.dims, &all, extent.chunks, true).unwrap();
+        convert("test_fid_short".as_ref(), "test_fid_short_streamed".as_ref(), enc, &extent.dims, &all, extent.chunks, false).unwrap();
+        let outputs = ["test_fid_short_in_memory", "test_fid_short_streamed"].map(|f| {
+            let y = read_cfl(f);
+            let (hdr, cfl) = cfl_paths(f);
+            std::fs::remove_file(hdr).unwrap();
+            std::fs::remove_file(cfl).unwrap();
+            y
+        });
+
+        // trailing bytes are ignored when allowed
+        std::fs::write("test_fid_short", [fid.as_slice(), &[0u8; 10]].concat()).unwrap();
+        let tol = SizeTolerance{allow_extra: true, ..Default::default()};
+        let extra = check_layout("test_fid_short".as_ref(), FidLayout::Padded, &dims, SampleFormat::I16, tol).unwrap();
+        std::fs::remove_file("test_fid_short").unwrap();
+        assert_eq!((extra.layout, extra.dims.shape(), extra.chunks), (FidLayout::Padded, dims.shape(), 12));
+
+        for (y, y_dims) in outputs {
+            assert_eq!(y_dims.shape(), extent.dims.shape());
+            assert_eq!(&y[..300], &samples[..300]);
+            assert!(y[300..].iter().all(|x| *x == Complex32::ZERO));
+        }
+    }
+
+}
+
+/// the receivers, echoes and repeats to convert, as indices into the acquisition. The fid is
+/// converted to dims of [samples, receivers, echoes, y, z, repeats], where each contiguous group
+/// of readouts in the file spans the first 3 axes
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Selection {
+    receivers: Vec<usize>,
+    echoes: Vec<usize>,
+    repeats: Vec<usize>,
+}
+
+impl Selection {
+    fn all(dims:&ArrayDim) -> Selection {
+        Selection {
+            receivers: (0..dims.shape()[1]).collect(),
+            echoes: (0..dims.shape()[2]).collect(),
+            repeats: (0..dims.shape()[5]).collect(),
+        }
+    }
+
+    /// the dims of the converted data
+    fn output_dims(&self, dims:&ArrayDim) -> ArrayDim {
+        dims.with_dim(1, self.receivers.len()).with_dim(2, self.echoes.len()).with_dim(5, self.repeats.len())
+    }
+}
+
+/// where a layout value was taken from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Flag,
+    Method,
+    Acqp,
+}
+
+/// the layout parameters read from a method file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct MethodParams {
+    enc_matrix: Option<Vec<usize>>,
+    matrix: Option<Vec<usize>>,
+    n_projections: Option<usize>,
+}
+
+impl MethodParams {
+    fn from_file(method_file:&Path) -> Result<MethodParams, FidToCflError> {
+        let method = parse_paravision_params(method_file)?;
+        let vec_usize = |name:&str| method.params.get(name).and_then(|v| v.to_vec_usize());
+        Ok(MethodParams {
+            enc_matrix: vec_usize(ENC_MATRIX),
+            matrix: vec_usize(MATRIX),
+            n_projections: method.params.get(N_PROJECTIONS).and_then(|v| v.to_usize()),
+        })
+    }
+}
+
+/// the readout size, oversampling factor and phase encode counts of the acquisition, with the
+/// source of each value
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AcqShape {
+    readout: (usize, Source),
+    oversampling: (usize, Source),
+    phase_encodes: (Vec<usize>, Source),
+}
+
+impl AcqShape {
+    /// dims of [samples, receivers, echoes, y, z, repeats], where any phase encodes past the
+    /// second are folded into z
+    fn dims(&self, receivers:usize, n_echoes:usize, n_repeats:usize) -> ArrayDim {
+        let pe = &self.phase_encodes.0;
+        let dim_y = pe.first().copied().unwrap_or(1);
+        let dim_z = pe.iter().skip(1).product::<usize>();
+        ArrayDim::from_shape(&[self.readout.0, receivers, n_echoes, dim_y, dim_z, n_repeats])
+    }
+}
+
+/// resolves the acquisition shape from ACQ_size, an optional method file and the oversampling
+/// flag. The flag takes precedence over the method file, which takes precedence over acqp:
+/// - readout: ACQ_size[0] / --f-oversample, else PVM_EncMatrix[0] (or PVM_Matrix[0]) when it
+///   divides ACQ_size[0], else ACQ_size[0]
+/// - oversampling: --f-oversample, else ACQ_size[0] / the method readout, else 1
+/// - phase encodes: NPro for radial scans, else PVM_EncMatrix[1..] (or PVM_Matrix[1..]), else
+///   ACQ_size[1..]
+fn resolve_shape(acq_size:&[usize], method:Option<&MethodParams>, f_oversample:Option<usize>) -> AcqShape {
+    let method_matrix = method.and_then(|m| m.enc_matrix.as_ref().or(m.matrix.as_ref()));
+    let method_readout = method_matrix.and_then(|m| m.first().copied())
+        .filter(|&r| r > 0 && acq_size[0] % r == 0);
+    let (readout, oversampling) = match (f_oversample, method_readout) {
+        (Some(f), _) => ((acq_size[0] / f, Source::Flag), (f, Source::Flag)),
+        (None, Some(r)) => ((r, Source::Method), (acq_size[0] / r, Source::Method)),
+        (None, None) => ((acq_size[0], Source::Acqp), (1, Source::Acqp)),
+    };
+    let phase_encodes = if let Some(n_pro) = method.and_then(|m| m.n_projections) {
+        (vec![n_pro], Source::Method)
+    } else if let Some(m) = method_matrix.filter(|m| m.len() > 1) {
+        (m[1..].to_vec(), Source::Method)
+    } else {
+        (acq_size[1..].to_vec(), Source::Acqp)
+    };
+    AcqShape{readout, oversampling, phase_encodes}
+}
+
+/// parses a comma separated list of indices and inclusive ranges, such as "0,2-4"
+fn parse_selection(spec:&str, name:&str, available:usize) -> Result<Vec<usize>, FidToCflError> {
+    let invalid = || FidToCflError::InvalidSelection(format!("{} = {}", name, spec));
+    let mut indices = vec![];
+    for part in spec.split(',').map(|p| p.trim()) {
+        let (start, end) = match part.split_once('-') {
+            Some((a, b)) => (a.trim().parse().map_err(|_| invalid())?, b.trim().parse().map_err(|_| invalid())?),
+            None => {
+                let i = part.parse().map_err(|_| invalid())?;
+                (i, i)
+            }
+        };
+        if end < start {
+            return Err(invalid());
+        }
+        if end >= available {
+            return Err(FidToCflError::SelectionOutOfRange{name: name.to_string(), index: end, available});
+        }
+        indices.extend(start..=end);
+    }
+    Ok(indices)
+}
+
+/// the fid, acqp and method files of a Bruker scan directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ScanFiles {
+    fid: PathBuf,
+    acqp: PathBuf,
+    method: PathBuf,
+}
+
+/// finds the fid (or rawdata.job0 for ParaVision 360), acqp and method files of a scan directory,
+/// listing every missing file in the error
+fn discover_scan(scan_dir:&Path) -> Result<ScanFiles, FidToCflError> {
+    let find = |names:&[&str]| names.iter().map(|n| scan_dir.join(n)).find(|p| p.is_file());
+    match (find(&["fid", "rawdata.job0"]), find(&["acqp"]), find(&["method"])) {
+        (Some(fid), Some(acqp), Some(method)) => Ok(ScanFiles{fid, acqp, method}),
+        (fid, acqp, method) => {
+            let missing = [(fid.is_none(), "fid or rawdata.job0"), (acqp.is_none(), "acqp"), (method.is_none(), "method")]
+                .into_iter()
+                .filter_map(|(missing, name)| missing.then(|| name.to_string()))
+                .collect();
+            Err(FidToCflError::MissingScanFiles{dir: scan_dir.to_path_buf(), missing})
+        }
+    }
+}
+
+/// the default cfl name for a scan directory from the scan number and protocol name, such as
+/// 7_T2_TurboRARE. The scan number is the directory name, or the (E<n>) suffix of ACQ_scan_name
+fn default_cfl_name(scan_dir:&Path, scan_name:Option<&str>, protocol_name:Option<&str>) -> PathBuf {
+    let clean = |s:&str| s.trim().trim_start_matches('<').trim_end_matches('>').trim().to_string();
+    let is_number = |n:&String| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit());
+    let from_dir = scan_dir.file_name().map(|n| n.to_string_lossy().to_string()).filter(is_number);
+    let from_scan_name = scan_name.map(clean)
+        .and_then(|s| s.rsplit_once("(E").and_then(|(_, n)| n.strip_suffix(')').map(String::from)))
+        .filter(is_number);
+    let scan_number = from_dir.or(from_scan_name).unwrap_or_else(|| String::from("scan"));
+    let protocol:String = protocol_name.map(clean).unwrap_or_default().chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    if protocol.is_empty() {
+        PathBuf::from(scan_number)
+    } else {
+        PathBuf::from(format!("{}_{}", scan_number, protocol))
+    }
+}
+
+/// the fid file of a job and the cfl file to write it to. Jobs after the first are read from the
+/// rawdata.job<n> file next to the given fid file, and a _job<n> suffix is added to the cfl file
+/// when every job is converted
+fn job_paths(fid_file:&Path, cfl_file:&Path, job:usize, suffix:bool) -> (PathBuf, PathBuf) {
+    let is_job_file = fid_file.file_name().is_some_and(|n| n.to_string_lossy().starts_with("rawdata.job"));
+    let fid_file = if job == 0 && !is_job_file {
+        fid_file.to_path_buf()
+    } else {
+        fid_file.with_file_name(format!("rawdata.job{}", job))
+    };
+    let cfl_file = if suffix {
+        let name = cfl_file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        cfl_file.with_file_name(format!("{}_job{}", name, job))
+    } else {
+        cfl_file.to_path_buf()
+    };
+    (fid_file, cfl_file)
+}
+
+/// the dims of a job from its scan size and number of scans (transaction blocks). The imaging job
+/// (job 0) keeps the shape of the acquisition, while other jobs such as navigators are converted
+/// to [samples, receivers, 1, scans]
+fn job_dims(job:&BrukerJob, index:usize, imaging_dims:&ArrayDim, oversampling_factor:usize) -> ArrayDim {
+    let dim_x = job.scan_size / oversampling_factor;
+    if index == 0 {
+        imaging_dims.with_dim(0, dim_x)
+    } else {
+        ArrayDim::from_shape(&[dim_x, imaging_dims.shape()[1], 1, job.transaction_blocks])
+    }
+}
+
+/// how fid files that don't match the expected size are converted
+#[derive(Debug, Clone, Copy, Default)]
+struct SizeTolerance {
+    /// convert the complete readout groups of a short file, as when an acquisition was stopped early
+    allow_short: bool,
+    /// ignore bytes past the expected end of the file, such as appended dummy scans
+    allow_extra: bool,
+}
+
+/// the part of a fid file to convert
+#[derive(Debug, Clone, Copy)]
+struct FidExtent {
+    layout: FidLayout,
+    /// the dims to convert, with fewer repeats than acquired for a short file
+    dims: ArrayDim,
+    /// the number of complete readout groups in the file that are converted. Any readout groups
+    /// of dims past these are zero-filled
+    chunks: usize,
+}
+
+/// checks the expected layout against the size of the fid file, falling back to the layout that
+/// matches the file size with a warning. Files that match neither layout are an error unless the
+/// tolerance allows them
+fn check_layout(fid_file:&Path, layout:FidLayout, dims:&ArrayDim, format:SampleFormat, tol:SizeTolerance) -> Result<FidExtent, FidToCflError> {
+    let chunk_size_samples = dims.shape()[..3].iter().product::<usize>();
+    let total_samples = dims.numel();
+    let n_chunks = total_samples / chunk_size_samples;
+    let full = |layout| FidExtent{layout, dims: *dims, chunks: n_chunks};
+    let fid_file_size = std::fs::metadata(fid_file).map_err(FidToCflError::IO)?.len() as usize;
+    if fid_file_size == layout.file_size(chunk_size_samples, total_samples, format) {
+        return Ok(full(layout));
+    }
+    if let Some(detected) = FidLayout::detect(fid_file_size, chunk_size_samples, total_samples, format) {
+        log::warn!("{} size matches the {:?} layout rather than {:?}",fid_file.display(),detected,layout);
+        return Ok(full(detected));
+    }
+
+    let expected = layout.file_size(chunk_size_samples, total_samples, format);
+    let diagnostic = size_diagnostic(fid_file, layout, dims, format, fid_file_size);
+    if fid_file_size > expected && tol.allow_extra {
+        log::warn!("{}\nIgnoring the last {} bytes",diagnostic,fid_file_size - expected);
+        return Ok(full(layout));
+    }
+    let chunks = fid_file_size / layout.chunk_stride(chunk_size_samples, format);
+    if fid_file_size < expected && tol.allow_short && chunks > 0 {
+        let chunks_per_repeat = dims.shape()[3..5].iter().product::<usize>();
+        let repeats = chunks.div_ceil(chunks_per_repeat);
+        log::warn!("{}\nConverting {} complete readout groups as {} of {} repeats, zero-filling the rest",diagnostic,chunks,repeats,dims.shape()[5]);
+        return Ok(FidExtent{layout, dims: dims.with_dim(5, repeats), chunks});
+    }
+    log::error!("{}\nUse --allow-short to convert the complete readout groups of a short file, or --allow-extra to ignore trailing bytes",diagnostic);
+    Err(FidToCflError::UnexpectedFileSize{layout, expected, actual: fid_file_size})
+}
+
+/// describes how the size of a fid file differs from the size expected for the layout and dims
+fn size_diagnostic(fid_file:&Path, layout:FidLayout, dims:&ArrayDim, format:SampleFormat, actual:usize) -> String {
+    let chunk_size_samples = dims.shape()[..3].iter().product::<usize>();
+    let n_chunks = dims.numel() / chunk_size_samples;
+    let stride = layout.chunk_stride(chunk_size_samples, format);
+    let expected = layout.file_size(chunk_size_samples, dims.numel(), format);
+    let factor = actual as f64 / expected as f64;
+    let mut msg = format!(
+        "{} is {} bytes, but {} bytes are expected for {} readout groups of {} samples in the {:?} layout ({} bytes or {} blocks per group). \
+        The file holds {:.4} times the expected data, or {} complete readout groups",
+        fid_file.display(), actual, expected, n_chunks, chunk_size_samples, layout, stride, stride.div_ceil(BLOCK_SIZE), factor, actual / stride,
+    );
+    // a whole factor often comes from oversampling, echoes or receivers that were read incorrectly
+    let whole = |f:f64| f.round() >= 2. && (f - f.round()).abs() < 1e-3;
+    if whole(factor) {
+        msg.push_str(&format!(". The readout groups may be {} times larger than derived from the parameters", factor.round()));
+    } else if whole(1. / factor) {
+        msg.push_str(&format!(". The readout groups may be {} times smaller than derived from the parameters", (1. / factor).round()));
+    }
+    msg
+}
+
+/// converts the selected readouts of a fid file to a cfl, either in memory or streamed. Only the
+/// first chunks readout groups are read from the file, with any others zero-filled
+fn convert(fid_file:&Path, cfl_file:&Path, enc:FidEncoding, dims:&ArrayDim, sel:&Selection, chunks:usize, in_memory:bool) -> Result<(), FidToCflError> {
+    let mut f = File::open(fid_file).map_err(FidToCflError::IO)?;
+    if in_memory {
+        let mut fid_bytes = vec![];
+        f.read_to_end(&mut fid_bytes).map_err(FidToCflError::IO)?;
+        let chunk_size_samples = dims.shape()[..3].iter().product::<usize>();
+        let stride = enc.layout.chunk_stride(chunk_size_samples, enc.format);
+        fid_bytes.truncate(chunks * stride);
+        fid_bytes.resize(dims.numel() / chunk_size_samples * stride, 0);
+        let fid_data = decode_fid(&fid_bytes, enc, dims, sel);
+        write_cfl(cfl_file,&fid_data,sel.output_dims(dims));
+        Ok(())
+    } else {
+        stream_fid_to_cfl(f, cfl_file, enc, dims, sel, chunks, STREAM_BYTES)
+    }
+}
+
+/// decodes the selected readouts of a fid file held in memory
+fn decode_fid(fid_bytes:&[u8], enc:FidEncoding, dims:&ArrayDim, sel:&Selection) -> Vec<Complex32> {
+    let out_dims = sel.output_dims(dims);
+    let mut fid_data = vec![Complex32::ZERO; out_dims.numel()];
+    let chunk_size_samples = dims.shape()[..3].iter().product::<usize>();
+    let stride = enc.layout.chunk_stride(chunk_size_samples, enc.format);
+    // each repeat is a contiguous range of readout groups in both the file and the output
+    let chunks_per_repeat = dims.shape()[3..5].iter().product::<usize>();
+    let out_per_repeat = out_dims.shape()[..5].iter().product::<usize>();
+    for (&repeat, fid_data) in sel.repeats.iter().zip(fid_data.chunks_exact_mut(out_per_repeat)) {
+        let start = repeat * chunks_per_repeat * stride;
+        decode_chunks(&fid_bytes[start..start + chunks_per_repeat * stride], enc, dims, sel, fid_data);
+    }
+    fid_data
+}
+
+/// decodes whole readout groups in parallel, filling fid_data with the selected receivers and echoes
+fn decode_chunks(fid_bytes:&[u8], enc:FidEncoding, dims:&ArrayDim, sel:&Selection, fid_data:&mut [Complex32]) {
+    let chunk_size_samples = dims.shape()[..3].iter().product::<usize>();
+    let stride = enc.layout.chunk_stride(chunk_size_samples, enc.format);
+    let out_chunk_size = dims.shape()[0] * sel.receivers.len() * sel.echoes.len();
+    fid_bytes.par_chunks_exact(stride).zip(fid_data.par_chunks_exact_mut(out_chunk_size)).for_each(|(chunk_bytes,fid_data)| {
+        decode_selected(chunk_bytes, enc, dims, sel, fid_data);
+    });
+}
+
+/// decodes the selected receivers and echoes of a readout group. Unselected readouts are skipped
+fn decode_selected(chunk_bytes:&[u8], enc:FidEncoding, dims:&ArrayDim, sel:&Selection, fid_data:&mut [Complex32]) {
+    let [n_read, n_receivers, ..] = *dims.shape();
+    let bytes_per_readout = n_read * enc.format.bytes_per_sample();
+    let mut readouts = fid_data.chunks_exact_mut(n_read);
+    for &echo in &sel.echoes {
+        for &receiver in &sel.receivers {
+            let start = (echo * n_receivers + receiver) * bytes_per_readout;
+            let readout = readouts.next().expect("fid_data holds every selected readout");
+            decode_fid_chunk(&chunk_bytes[start..start + bytes_per_readout], enc.format, enc.byte_order, readout);
+        }
+    }
+}
+
+/// converts the selected readouts of a fid to a cfl a group of readouts at a time, so only about
+/// batch_bytes of fid data and the decoded samples are held in memory regardless of the file size.
+/// Unselected repeats are skipped without being read. Only the first chunks readout groups are
+/// read, with any others zero-filled
+fn stream_fid_to_cfl(fid:impl Read + Seek, cfl_file:impl AsRef<Path>, enc:FidEncoding, dims:&ArrayDim, sel:&Selection, chunks:usize, batch_bytes:usize) -> Result<(), FidToCflError> {
+    let chunk_size_samples = dims.shape()[..3].iter().product::<usize>();
+    let stride = enc.layout.chunk_stride(chunk_size_samples, enc.format);
+    let chunks_per_repeat = dims.shape()[3..5].iter().product::<usize>();
+    let out_chunk_size = dims.shape()[0] * sel.receivers.len() * sel.echoes.len();
+    let batch = (batch_bytes / stride).clamp(1, chunks_per_repeat.max(1));
+
+    let mut r = BufReader::new(fid);
+    let mut fid_bytes = vec![0u8; batch * stride];
+    let mut stage = vec![Complex32::ZERO; batch * out_chunk_size];
+    let mut w = CflChunkWriter::create(cfl_file, ArrayDim::from_shape(&[out_chunk_size])).map_err(FidToCflError::Cfl)?;
+
+    for &repeat in &sel.repeats {
+        r.seek(SeekFrom::Start((repeat * chunks_per_repeat * stride) as u64)).map_err(FidToCflError::IO)?;
+        let mut remaining = chunks_per_repeat;
+        while remaining > 0 {
+            let n = batch.min(remaining);
+            let first = repeat * chunks_per_repeat + chunks_per_repeat - remaining;
+            let in_file = n.min(chunks.saturating_sub(first));
+            r.read_exact(&mut fid_bytes[..in_file * stride]).map_err(FidToCflError::IO)?;
+            fid_bytes[in_file * stride..n * stride].fill(0);
+            let stage = &mut stage[..n * out_chunk_size];
+            decode_chunks(&fid_bytes[..n * stride], enc, dims, sel, stage);
+            for frame in stage.chunks_exact(out_chunk_size) {
+                w.append_frame(frame).map_err(FidToCflError::Cfl)?;
+            }
+            remaining -= n;
+        }
+    }
+    w.finish_with_dims(sel.output_dims(dims)).map_err(FidToCflError::Cfl)?;
+    Ok(())
+}
+
 #[derive(Debug)]
 enum FidToCflError {
     FieldNotFound(String),
@@ -30,6 +727,14 @@ enum FidToCflError {
     IO(std::io::Error),
     PV(PvError),
     UnexpectedDataType(String),
+    UnexpectedFileSize{layout: FidLayout, expected: usize, actual: usize},
+    Cfl(CflIoError),
+    InvalidSelection(String),
+    SelectionOutOfRange{name: String, index: usize, available: usize},
+    Bruker(BrukerDataError),
+    JobOutOfRange{job: usize, available: usize},
+    MissingScanFiles{dir: PathBuf, missing: Vec<String>},
+    MissingArgument(String),
 }
 
 impl From<PvError> for FidToCflError {
@@ -40,18 +745,73 @@ impl From<PvError> for FidToCflError {
 
 #[derive(Parser)]
 struct Args {
-    /// path to Bruker fid file to parse
+    /// path to Bruker fid file to parse, or a scan directory holding the fid (or rawdata.job0),
+    /// acqp and method files
     fid_file: PathBuf,
-    /// output cfl file
-    cfl_file: PathBuf,
-    /// path to Bruker acquisition parameters file
-    acqp_file: PathBuf,
+    /// output cfl file. Optional for a scan directory
+    cfl_file: Option<PathBuf>,
+    /// path to Bruker acquisition parameters file. Not needed for a scan directory
+    acqp_file: Option<PathBuf>,
+
+    /// output cfl file, in place of the cfl_file argument. For a scan directory this defaults to
+    /// a name from the scan number and protocol name, such as 7_T2_TurboRARE
+    #[clap(short, long)]
+    output: Option<PathBuf>,
 
     /// oversampling factor for cases where the acq_size is reported as some factor of the readout size.
-    /// This is usually 2 for radial scans
+    /// This is usually 2 for radial scans. Overrides the readout size from the method file
     #[clap(short, long)]
     f_oversample: Option<usize>,
 
+    /// path to the Bruker method file. When given, the readout size and phase encodes are taken
+    /// from PVM_EncMatrix (or PVM_Matrix) and NPro in preference to ACQ_size
+    #[clap(long)]
+    method: Option<PathBuf>,
+
+    /// read readouts packed without block padding, as when GO_block_size is continuous. By default
+    /// this is read from the acqp file, or detected from the fid file size
+    #[clap(long)]
+    no_block_padding: bool,
+
+    /// read the whole fid file into memory before converting, which can be faster for small files.
+    /// By default the fid file is converted a few readouts at a time
+    #[clap(long)]
+    in_memory: bool,
+
+    /// echoes to convert as a comma separated list of indices and inclusive ranges, such as 0,2-4.
+    /// Defaults to all echoes
+    #[clap(long)]
+    echoes: Option<String>,
+
+    /// repeats to convert, as with echoes
+    #[clap(long)]
+    repeats: Option<String>,
+
+    /// receivers to convert, as with echoes. Indices count the active receivers
+    #[clap(long)]
+    receivers: Option<String>,
+
+    /// the acquisition job to convert for ParaVision 360 data split into rawdata.job<n> files.
+    /// Defaults to the job of the fid file name, or the imaging job (0)
+    #[clap(long)]
+    job: Option<usize>,
+
+    /// convert every job listed in ACQ_jobs, writing each to the cfl file with a _job<n> suffix.
+    /// Echo and repeat selections only apply to the imaging job
+    #[clap(long)]
+    all_jobs: bool,
+
+    /// convert the complete readout groups of a fid file shorter than expected, as when the
+    /// acquisition was stopped early. The last converted repeat is zero-filled and later repeats
+    /// are dropped
+    #[clap(long)]
+    allow_short: bool,
+
+    /// ignore any bytes past the expected end of the fid file, such as appended dummy scans
+    #[clap(long)]
+    allow_extra: bool,
+
+    #[clap(long)]
     debug:bool,
 }
 
@@ -60,10 +820,32 @@ fn main() -> Result<(), FidToCflError> {
     use FidToCflError::*;
 
     let args = Args::parse();
+    // --debug adds the layout details to the warnings
+    array_lib::logging::init_stderr(if args.debug {log::LevelFilter::Debug} else {log::LevelFilter::Warn});
+
+    // explicit files, or the files found in a scan directory
+    let scan_dir = args.fid_file.is_dir().then(|| args.fid_file.clone());
+    let (fid_file, acqp_file, method_file) = match &scan_dir {
+        Some(dir) => {
+            let scan = discover_scan(dir)?;
+            (scan.fid, scan.acqp, args.method.clone().or(Some(scan.method)))
+        }
+        None => {
+            let acqp_file = args.acqp_file.clone().ok_or_else(|| MissingArgument(String::from("acqp_file")))?;
+            (args.fid_file.clone(), acqp_file, args.method.clone())
+        }
+    };
 
-    let oversampling_factor = args.f_oversample.unwrap_or(1);
+    let acqp = parse_paravision_params(&acqp_file)?;
 
-    let acqp = parse_paravision_params(&args.acqp_file)?;
+    let cfl_file = match (args.output.clone().or(args.cfl_file.clone()), &scan_dir) {
+        (Some(cfl_file), _) => cfl_file,
+        (None, Some(dir)) => {
+            let name = |n:&str| acqp.params.get(n).map(|v| v.to_string());
+            default_cfl_name(dir, name(SCAN_NAME).as_deref(), name(PROTOCOL_NAME).as_deref())
+        }
+        (None, None) => Err(MissingArgument(String::from("cfl_file")))?,
+    };
 
     let acq_size = acqp.params.get(ACQ_SIZE).ok_or_else(|| FieldNotFound(String::from(ACQ_SIZE)))?;
     let receivers = acqp.params.get(RECEIVERS).ok_or_else(|| FieldNotFound(String::from(RECEIVERS)))?;
@@ -78,75 +860,119 @@ fn main() -> Result<(), FidToCflError> {
 
 
     let word_size = acqp.params.get(WORD_SIZE).ok_or_else(|| FieldNotFound(String::from(WORD_SIZE)))?.to_string();
+    let raw_data_format = acqp.params.get(RAW_DATA_FORMAT).map(|f| f.to_string());
+
+    let sample_format = SampleFormat::from_acqp(raw_data_format.as_deref(), &word_size)
+        .ok_or_else(|| UnexpectedDataType(raw_data_format.unwrap_or(word_size)))?;
 
-    let bytes_per_sample = match word_size.as_str() {
-        "_32_BIT" => {
-            8 // 8 bytes per complex data point
+    let bytes_per_sample = sample_format.bytes_per_sample();
+
+    let byte_order = match acqp.params.get(BYTE_ORDER).map(|b| b.to_string()).as_deref() {
+        Some("little") => ByteOrder::Little,
+        Some("big") => ByteOrder::Big,
+        Some(b) => Err(UnexpectedDataType(format!("{} = {}", BYTE_ORDER, b)))?,
+        None => {
+            log::warn!("{} not found. Assuming little-endian data", BYTE_ORDER);
+            ByteOrder::Little
         }
-        _=> Err(UnexpectedDataType(word_size))?
     };
 
+    let method = method_file.as_deref().map(MethodParams::from_file).transpose()?;
+    let shape = resolve_shape(&acq_size, method.as_ref(), args.f_oversample);
+    let oversampling_factor = shape.oversampling.0;
+    let dims = shape.dims(receivers, n_echoes, n_repeats);
+
     // this is the data ordering usually streaming off the scanner. These data points should be contiguous in the fid file
-    let chunk_size_samples = acq_size[0]/oversampling_factor * receivers * n_echoes;
+    let chunk_size_samples = shape.readout.0 * receivers * n_echoes;
 
-    let total_samples = chunk_size_samples * acq_size[1..].iter().product::<usize>() * n_repeats;
+    let total_samples = dims.numel();
 
     let n_chunks = total_samples / chunk_size_samples;
 
     let samples_per_block = BLOCK_SIZE / bytes_per_sample;
 
-
+    // the expected layout, which is checked against the file size below
+    let block_size = acqp.params.get(GO_BLOCK_SIZE).map(|b| b.to_string());
+    let is_job_file = fid_file.file_name().is_some_and(|n| n.to_string_lossy().starts_with("rawdata.job"));
+    let layout = if args.no_block_padding || block_size.as_deref() == Some("continuous") || is_job_file {
+        FidLayout::Continuous
+    } else {
+        FidLayout::Padded
+    };
 
     // ceil division for blocks per chunk
     let blocks_per_chunk = (chunk_size_samples * bytes_per_sample + BLOCK_SIZE - 1) / BLOCK_SIZE;
 
     let n_fid_samples = n_chunks * blocks_per_chunk * samples_per_block;
-    let expected_fid_file_size_bytes = n_chunks * blocks_per_chunk * BLOCK_SIZE;
-
-    if args.debug {
-        println!("acq_size = {:?}",acq_size);
-        println!("receivers = {:?}",receivers);
-        println!("n_echoes = {:?}",n_echoes);
-        println!("n_repeats = {:?}",n_repeats);
-        println!("samples_per_block = {:?}",samples_per_block);
-        println!("n_fid_samples = {:?}",n_fid_samples);
-        println!("blocks_per_chunk = {:?}",blocks_per_chunk);
-        println!("expected_fid_file_size_bytes = {:?}",expected_fid_file_size_bytes);
+    let expected_fid_file_size_bytes = layout.file_size(chunk_size_samples, total_samples, sample_format);
+
+    log::debug!("fid_file = {}",fid_file.display());
+    log::debug!("acqp_file = {}",acqp_file.display());
+    log::debug!("cfl_file = {}",cfl_file.display());
+    log::debug!("acq_size = {:?}",acq_size);
+    log::debug!("readout = {} (from {:?})",shape.readout.0,shape.readout.1);
+    log::debug!("oversampling = {} (from {:?})",shape.oversampling.0,shape.oversampling.1);
+    log::debug!("phase_encodes = {:?} (from {:?})",shape.phase_encodes.0,shape.phase_encodes.1);
+    log::debug!("receivers = {:?}",receivers);
+    log::debug!("n_echoes = {:?}",n_echoes);
+    log::debug!("n_repeats = {:?}",n_repeats);
+    log::debug!("sample_format = {:?}",sample_format);
+    log::debug!("byte_order = {:?}",byte_order);
+    log::debug!("layout = {:?}",layout);
+    log::debug!("samples_per_block = {:?}",samples_per_block);
+    log::debug!("n_fid_samples = {:?}",n_fid_samples);
+    log::debug!("blocks_per_chunk = {:?}",blocks_per_chunk);
+    log::debug!("expected_fid_file_size_bytes = {:?}",expected_fid_file_size_bytes);
+
+    let mut sel = Selection::all(&dims);
+    if let Some(spec) = &args.receivers {
+        sel.receivers = parse_selection(spec, "receivers", receivers)?;
+    }
+    if let Some(spec) = &args.echoes {
+        sel.echoes = parse_selection(spec, "echoes", n_echoes)?;
+    }
+    if let Some(spec) = &args.repeats {
+        sel.repeats = parse_selection(spec, "repeats", n_repeats)?;
+    }
+    let jobs = read_bruker_jobs(&acqp_file).map_err(Bruker)?;
+    log::debug!("jobs = {:?}",jobs);
+    let n_jobs = jobs.len().max(1);
+    let job_indices:Vec<usize> = if args.all_jobs {
+        (0..n_jobs).collect()
+    } else {
+        let from_name = fid_file.file_name()
+            .and_then(|n| n.to_string_lossy().strip_prefix("rawdata.job").and_then(|j| j.parse().ok()));
+        vec![args.job.or(from_name).unwrap_or(0)]
+    };
+    if let Some(&job) = job_indices.iter().find(|&&j| j >= n_jobs) {
+        return Err(JobOutOfRange{job, available: n_jobs});
     }
 
-    let mut f = File::open(args.fid_file).map_err(IO)?;
-
-    let mut fid_bytes = vec![];
-    f.read_to_end(&mut fid_bytes).map_err(IO)?;
-
-    assert_eq!(
-        fid_bytes.len(),
-        expected_fid_file_size_bytes,
-        "unexpected fid file size. Expected {}, got {} bytes",expected_fid_file_size_bytes,fid_bytes.len()
-    );
-
-    let mut fid_data = vec![Complex32::ZERO; total_samples];
-
-    let bytes_per_chunk = chunk_size_samples * bytes_per_sample;
-
-    fid_bytes.par_chunks_exact(blocks_per_chunk * BLOCK_SIZE).zip(fid_data.par_chunks_exact_mut(chunk_size_samples)).for_each(|(chunk_bytes,fid_data)| {
-        let x = &chunk_bytes[0..bytes_per_chunk]; // only read the bytes we care about
-        let y:&[i32] = bytemuck::cast_slice(x);
-        y.chunks_exact(2).zip(fid_data.iter_mut()).for_each(|(i,f)| {
-            *f = Complex32::new(
-                i[0] as f32,
-                i[1] as f32
-            );
-        });
-    });
-
-    let dim_x = acq_size[0]/oversampling_factor;
-    let dim_y = acq_size[1];
-    let dim_z = *acq_size.get(2).unwrap_or(&1usize);
-
-    let dims = ArrayDim::from_shape(&[dim_x,receivers,n_echoes,dim_y,dim_z,n_repeats]);
-    assert_eq!(dims.numel(),fid_data.len(),"incorrect dimensions");
-    write_cfl(args.cfl_file,&fid_data,dims);
+    let tol = SizeTolerance{allow_short: args.allow_short, allow_extra: args.allow_extra};
+    for job in job_indices {
+        let (fid_file, cfl_file) = job_paths(&fid_file, &cfl_file, job, args.all_jobs);
+        let job_dims = jobs.get(job).map(|j| job_dims(j, job, &dims, oversampling_factor)).unwrap_or(dims);
+        // job files are always continuous
+        let layout = if job == 0 { layout } else { FidLayout::Continuous };
+        let extent = check_layout(&fid_file, layout, &job_dims, sample_format, tol)?;
+        let mut job_sel = if job == 0 {
+            sel.clone()
+        } else {
+            Selection{receivers: sel.receivers.clone(), ..Selection::all(&extent.dims)}
+        };
+        // repeats missing from a short file can't be converted
+        let n_repeats = extent.dims.shape()[5];
+        if let Some(&index) = job_sel.repeats.iter().find(|&&r| r >= n_repeats) {
+            log::warn!("skipping repeats from {} that are missing from {}",index,fid_file.display());
+            job_sel.repeats.retain(|&r| r < n_repeats);
+            if job_sel.repeats.is_empty() {
+                return Err(SelectionOutOfRange{name: String::from("repeats"), index, available: n_repeats});
+            }
+        }
+        log::debug!("job {}: {} -> {} with dims {:?}, {:?} layout and {} readout groups",job,fid_file.display(),cfl_file.display(),extent.dims.shape_ns(),extent.layout,extent.chunks);
+        let enc = FidEncoding{layout: extent.layout, format: sample_format, byte_order};
+        convert(&fid_file, &cfl_file, enc, &extent.dims, &job_sel, extent.chunks, args.in_memory)?;
+    }
 
     Ok(())
 
@@ -1,49 +1,341 @@
 use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
-use clap::Parser;
-use num_complex::Complex32;
+use clap::{Parser, ValueEnum};
 use rayon::prelude::*;
 use array_lib::ArrayDim;
-use array_lib::io_cfl::write_cfl;
+use array_lib::io_cfl::{write_cfl_from_real_f64, CflIoError};
+
+#[cfg(test)]
+mod tests {
+    use array_lib::io_cfl::{cfl_paths, write_cfl_from_real_f64, read_cfl};
+    use crate::{decode_traj, load_traj, max_radius, parse_axis_order, traj_components, traj_summary, transform_traj, zero_fill_3d, TrajDtype, TrajToCflError};
+
+    #[test]
+    fn test_2d_f32() {
+        // 2 components x 4 samples x 2 readouts of f32
+        let traj:Vec<f32> = (0..16).map(|i| i as f32 * 0.25 - 3.).collect();
+        let bytes:Vec<u8> = traj.iter().flat_map(|x| x.to_le_bytes()).collect();
+        std::fs::write("test_traj_2d_f32", &bytes).unwrap();
+        let bytes = std::fs::read("test_traj_2d_f32").unwrap();
+        std::fs::remove_file("test_traj_2d_f32").unwrap();
+
+        let decoded = decode_traj(&bytes, TrajDtype::F32).unwrap();
+        assert_eq!(decoded, traj.iter().map(|x| *x as f64).collect::<Vec<f64>>());
+        // 16 values are not divisible by 3 x 4, so 2 components are detected
+        assert_eq!(traj_components(decoded.len(), 4, None, bytes.len(), false).unwrap(), (2, 16));
+
+        let filled = zero_fill_3d(&decoded);
+        assert_eq!(filled.len(), 24);
+        assert_eq!(filled[..6], [decoded[0], decoded[1], 0., decoded[2], decoded[3], 0.]);
+    }
+
+    #[test]
+    fn test_3d_f64() {
+        // 3 components x 5 samples x 3 readouts of f64
+        let traj:Vec<f64> = (0..45).map(|i| (i as f64 - 15.) / 30.).collect();
+        let bytes:Vec<u8> = traj.iter().flat_map(|x| x.to_le_bytes()).collect();
+        let decoded = decode_traj(&bytes, TrajDtype::F64).unwrap();
+        assert_eq!(decoded, traj);
+        assert_eq!(traj_components(decoded.len(), 5, None, bytes.len(), false).unwrap(), (3, 45));
+
+        write_cfl_from_real_f64("test_traj_3d_f64", &decoded, array_lib::ArrayDim::from_shape(&[3,5,3])).unwrap();
+        let (y, y_dims) = read_cfl("test_traj_3d_f64");
+        let (hdr, cfl) = cfl_paths("test_traj_3d_f64");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert_eq!(y_dims.shape_ns(), &[3,5,3]);
+        assert!(y.iter().zip(&traj).all(|(y, x)| y.re == *x as f32 && y.im == 0.));
+
+        // the wrong readout size is reported with the divisors that were tried
+        match traj_components(decoded.len(), 7, None, bytes.len(), false) {
+            Err(TrajToCflError::UnexpectedLength{file_bytes: 360, values: 45, tried, remainders}) => {
+                assert_eq!(tried, vec![21, 14]);
+                assert_eq!(remainders, vec![3, 3]);
+            }
+            r => panic!("expected an unexpected length error, got {:?}", r),
+        }
+        assert!(matches!(traj_components(45, 5, Some(2), 360, false), Err(TrajToCflError::UnexpectedLength{..})));
+        assert!(matches!(traj_components(45, 5, Some(4), 360, false), Err(TrajToCflError::UnexpectedDataType(..))));
+        // a ragged tail is dropped when truncating
+        assert_eq!(traj_components(decoded.len(), 7, None, bytes.len(), true).unwrap(), (3, 42));
+        assert!(matches!(decode_traj(&bytes[..20], TrajDtype::F64), Err(TrajToCflError::UnexpectedLength{..})));
+    }
+
+    #[test]
+    fn test_skip_header() {
+        // an 80 byte header before 3 components x 4 samples x 2 readouts of f32
+        let traj:Vec<f32> = (0..24).map(|i| i as f32 / 24. - 0.5).collect();
+        let header:Vec<u8> = (0..80).map(|i| i as u8).collect();
+        let bytes:Vec<u8> = header.into_iter().chain(traj.iter().flat_map(|x| x.to_le_bytes())).collect();
+        std::fs::write("test_traj_header", &bytes).unwrap();
+        let bytes = std::fs::read("test_traj_header").unwrap();
+        std::fs::remove_file("test_traj_header").unwrap();
+
+        // without skipping, the header doesn't divide into whole readouts
+        let unskipped = load_traj(&bytes, TrajDtype::F32, 0).unwrap();
+        assert!(matches!(traj_components(unskipped.len(), 4, None, bytes.len(), false), Err(TrajToCflError::UnexpectedLength{values: 44, ..})));
+
+        let decoded = load_traj(&bytes, TrajDtype::F32, 80).unwrap();
+        let (components, n_values) = traj_components(decoded.len(), 4, None, bytes.len(), false).unwrap();
+        assert_eq!((components, n_values), (3, 24));
+        write_cfl_from_real_f64("test_traj_header", &decoded, array_lib::ArrayDim::from_shape(&[3,4,2])).unwrap();
+        let reference:Vec<f64> = traj.iter().map(|x| *x as f64).collect();
+        write_cfl_from_real_f64("test_traj_header_ref", &reference, array_lib::ArrayDim::from_shape(&[3,4,2])).unwrap();
+        let outputs = ["test_traj_header", "test_traj_header_ref"].map(|f| {
+            let (hdr, cfl) = cfl_paths(f);
+            let y = std::fs::read(&cfl).unwrap();
+            std::fs::remove_file(hdr).unwrap();
+            std::fs::remove_file(cfl).unwrap();
+            y
+        });
+        assert_eq!(outputs[0], outputs[1]);
+        assert!(matches!(load_traj(&bytes, TrajDtype::F32, 200), Err(TrajToCflError::SkipPastEnd{skip_bytes: 200, file_bytes: 176})));
+    }
+
+    #[test]
+    fn test_scale_and_axis_order() {
+        // points normalized to a radius of 0.5, with the largest at (0.3, -0.4, 0)
+        let mut traj = vec![0.1, 0.2, -0.05, 0.3, -0.4, 0., -0.25, 0., 0.25];
+        assert!((max_radius(&traj, 3) - 0.5).abs() < 1e-12);
+
+        // normalizing to a 128 grid scales the largest radius to 64, with kz moved first
+        let scale = 64. / max_radius(&traj, 3);
+        let order = parse_axis_order("2,0,1", 3).unwrap();
+        transform_traj(&mut traj, 3, scale, &order);
+        let expected = [-6.4, 12.8, 25.6, 0., 38.4, -51.2, 32., -32., 0.];
+        assert!(traj.iter().zip(&expected).all(|(x, e)| (x - e).abs() < 1e-9));
+        assert!((max_radius(&traj, 3) - 64.).abs() < 1e-9);
+
+        let summary = traj_summary(&traj, 3);
+        let close = |(lo, hi):(f64, f64), (e_lo, e_hi):(f64, f64)| (lo - e_lo).abs() < 1e-9 && (hi - e_hi).abs() < 1e-9;
+        assert!(close(summary[0], (-6.4, 32.)));
+        assert!(close(summary[2], (-51.2, 25.6)));
+
+        assert_eq!(parse_axis_order("1,0", 2).unwrap(), vec![1,0]);
+        assert!(matches!(parse_axis_order("0,0,1", 3), Err(TrajToCflError::InvalidAxisOrder(..))));
+        assert!(matches!(parse_axis_order("0,1", 3), Err(TrajToCflError::InvalidAxisOrder(..))));
+    }
+
+}
+
+/// the sample type of the trajectory file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum TrajDtype {
+    F32,
+    F64,
+}
+
+impl TrajDtype {
+    fn size(&self) -> usize {
+        match self {
+            TrajDtype::F32 => 4,
+            TrajDtype::F64 => 8,
+        }
+    }
+}
 
 #[derive(Parser, Debug)]
 struct Args {
     traj_file: PathBuf,
     cfl_file: PathBuf,
     readout_size: usize,
+
+    /// number of coordinates per trajectory point, 2 or 3. By default this is detected from the
+    /// file length, preferring 3 when both divide it
+    #[clap(long)]
+    components: Option<usize>,
+
+    /// sample type of the trajectory file
+    #[clap(long, value_enum, default_value_t = TrajDtype::F64)]
+    dtype: TrajDtype,
+
+    /// write 2D trajectories with a third component of zeros, for tools that only accept 3D
+    /// trajectories
+    #[clap(long)]
+    zero_fill_3d: bool,
+
+    /// multiplies every coordinate by a factor
+    #[clap(long, conflicts_with = "normalize_to")]
+    scale: Option<f64>,
+
+    /// scales the trajectory so the largest radius is N/2 grid cells, as BART expects for an
+    /// N point grid
+    #[clap(long)]
+    normalize_to: Option<f64>,
+
+    /// the input component for each output component as a comma separated list, such as 2,0,1
+    /// to move kz first. Applied after zero filling
+    #[clap(long)]
+    axis_order: Option<String>,
+
+    /// bytes of header to skip at the start of the file
+    #[clap(long, conflicts_with = "skip_samples")]
+    skip_bytes: Option<usize>,
+
+    /// samples of header to skip at the start of the file, as with skip_bytes
+    #[clap(long)]
+    skip_samples: Option<usize>,
+
+    /// drop any samples past the last whole readout instead of returning an error
+    #[clap(long)]
+    truncate: bool,
 }
 
 #[derive(Debug)]
-enum FidToCflError {
+enum TrajToCflError {
     IO(std::io::Error),
     UnexpectedDataType(String),
+    /// the file length does not divide into whole readouts for any of the tried divisors, leaving
+    /// the remainders of each
+    UnexpectedLength{file_bytes: usize, values: usize, tried: Vec<usize>, remainders: Vec<usize>},
+    /// the header to skip is longer than the file
+    SkipPastEnd{skip_bytes: usize, file_bytes: usize},
+    Cfl(CflIoError),
+    InvalidAxisOrder(String),
 }
 
-fn main() -> Result<(), FidToCflError> {
+/// decodes little-endian trajectory samples after skip_bytes of header
+fn load_traj(bytes:&[u8], dtype:TrajDtype, skip_bytes:usize) -> Result<Vec<f64>, TrajToCflError> {
+    let samples = bytes.get(skip_bytes..).ok_or(TrajToCflError::SkipPastEnd{skip_bytes, file_bytes: bytes.len()})?;
+    decode_traj(samples, dtype)
+}
+
+/// decodes little-endian trajectory samples
+fn decode_traj(bytes:&[u8], dtype:TrajDtype) -> Result<Vec<f64>, TrajToCflError> {
+    if bytes.len() % dtype.size() != 0 {
+        return Err(TrajToCflError::UnexpectedLength{
+            file_bytes: bytes.len(),
+            values: bytes.len() / dtype.size(),
+            tried: vec![dtype.size()],
+            remainders: vec![bytes.len() % dtype.size()],
+        });
+    }
+    let samples = bytes.chunks_exact(dtype.size());
+    Ok(match dtype {
+        TrajDtype::F32 => samples.map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64).collect(),
+        TrajDtype::F64 => samples.map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect(),
+    })
+}
+
+/// the number of components per trajectory point and the number of values that make whole
+/// readouts. Without a requested number, 3 and then 2 components are tried. Values that don't
+/// divide into whole readouts are an error, unless truncate is set, in which case the values past
+/// the last whole readout for the first candidate are dropped
+fn traj_components(n_values:usize, readout_size:usize, components:Option<usize>, file_bytes:usize, truncate:bool) -> Result<(usize, usize), TrajToCflError> {
+    let candidates = match components {
+        Some(c) if c == 2 || c == 3 => vec![c],
+        Some(c) => return Err(TrajToCflError::UnexpectedDataType(format!("{} components per point, expected 2 or 3", c))),
+        None => vec![3, 2],
+    };
+    let fits = |c:usize| readout_size > 0 && n_values % (c * readout_size) == 0;
+    let Some(c) = candidates.iter().copied().find(|&c| fits(c)) else {
+        let tried:Vec<usize> = candidates.iter().map(|c| c * readout_size).collect();
+        if truncate && readout_size > 0 && n_values >= tried[0] {
+            let kept = n_values - n_values % tried[0];
+            log::warn!("dropping the last {} of {} samples, which don't make a whole readout of {} components",n_values - kept,n_values,candidates[0]);
+            return Ok((candidates[0], kept));
+        }
+        return Err(TrajToCflError::UnexpectedLength{
+            file_bytes,
+            values: n_values,
+            remainders: tried.iter().map(|&d| if d > 0 { n_values % d } else { n_values }).collect(),
+            tried,
+        });
+    };
+    if components.is_none() && c == 3 && fits(2) {
+        log::warn!("the trajectory could have 2 or 3 components per point. Assuming 3, use --components to override");
+    }
+    Ok((c, n_values))
+}
+
+/// inserts a zero third component after each 2D trajectory point
+fn zero_fill_3d(traj:&[f64]) -> Vec<f64> {
+    traj.chunks_exact(2).flat_map(|p| [p[0], p[1], 0.]).collect()
+}
+
+/// parses a permutation of the trajectory components, such as "2,0,1"
+fn parse_axis_order(spec:&str, components:usize) -> Result<Vec<usize>, TrajToCflError> {
+    let invalid = || TrajToCflError::InvalidAxisOrder(format!("{} is not a permutation of {} components", spec, components));
+    let order = spec.split(',').map(|a| a.trim().parse::<usize>()).collect::<Result<Vec<usize>, _>>().map_err(|_| invalid())?;
+    let mut sorted = order.clone();
+    sorted.sort_unstable();
+    if sorted != (0..components).collect::<Vec<usize>>() {
+        return Err(invalid());
+    }
+    Ok(order)
+}
+
+/// the largest distance of a trajectory point from the origin
+fn max_radius(traj:&[f64], components:usize) -> f64 {
+    traj.par_chunks_exact(components)
+        .map(|p| p.iter().map(|x| x * x).sum::<f64>().sqrt())
+        .reduce(|| 0., f64::max)
+}
+
+/// scales each trajectory point and reorders its components in place
+fn transform_traj(traj:&mut [f64], components:usize, scale:f64, axis_order:&[usize]) {
+    traj.par_chunks_exact_mut(components).for_each(|p| {
+        let mut point = [0f64; 3];
+        point[..components].copy_from_slice(p);
+        p.iter_mut().zip(axis_order).for_each(|(x, &a)| *x = point[a] * scale);
+    });
+}
+
+/// the minimum and maximum of each component
+fn traj_summary(traj:&[f64], components:usize) -> Vec<(f64, f64)> {
+    (0..components).map(|c| {
+        traj.iter().skip(c).step_by(components).fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &x| (lo.min(x), hi.max(x)))
+    }).collect()
+}
+
+fn main() -> Result<(), TrajToCflError> {
 
     let args = Args::parse();
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
 
     let mut traj_bytes:Vec<u8> = vec![];
 
-    let mut f = File::open(args.traj_file).map_err(FidToCflError::IO)?;
-    f.read_to_end(&mut traj_bytes).map_err(FidToCflError::IO)?;
-    let traj:&[f64] = bytemuck::cast_slice(&traj_bytes);
+    let mut f = File::open(args.traj_file).map_err(TrajToCflError::IO)?;
+    f.read_to_end(&mut traj_bytes).map_err(TrajToCflError::IO)?;
+    let skip_bytes = args.skip_bytes.or(args.skip_samples.map(|n| n * args.dtype.size())).unwrap_or(0);
+    let mut traj = load_traj(&traj_bytes, args.dtype, skip_bytes)?;
 
-    assert_eq!(traj.len()%(3*args.readout_size), 0, "number of traj samples must be divisible by 3");
+    let (components, n_values) = traj_components(traj.len(), args.readout_size, args.components, traj_bytes.len(), args.truncate)?;
+    traj.truncate(n_values);
 
-    let points_per_channel = traj.len() / (3*args.readout_size);
+    let points_per_channel = traj.len() / (components*args.readout_size);
 
-    let mut cfl_data = vec![Complex32::ZERO; traj.len()];
-    cfl_data.par_iter_mut().zip(traj.par_iter()).for_each(|(c,f)|{
-       // write to real part
-        *c = Complex32::new(*f as f32, 0.);
-    });
+    let (mut traj, components) = if components == 2 && args.zero_fill_3d {
+        (zero_fill_3d(&traj), 3)
+    } else {
+        (traj, components)
+    };
 
-    let cfl_dims = ArrayDim::from_shape(&[3, args.readout_size, points_per_channel]);
+    let scale = match (args.scale, args.normalize_to) {
+        (Some(scale), _) => scale,
+        (None, Some(n)) => {
+            let r = max_radius(&traj, components);
+            if r > 0. { n / 2. / r } else { 1. }
+        }
+        (None, None) => 1.,
+    };
+    let axis_order = match &args.axis_order {
+        Some(spec) => parse_axis_order(spec, components)?,
+        None => (0..components).collect(),
+    };
+    transform_traj(&mut traj, components, scale, &axis_order);
 
-    write_cfl(args.cfl_file,&cfl_data,cfl_dims);
+    println!("scale = {}",scale);
+    for (c, (lo, hi)) in traj_summary(&traj, components).iter().enumerate() {
+        println!("component {}: min = {}, max = {}",c,lo,hi);
+    }
+
+    let cfl_dims = ArrayDim::from_shape(&[components, args.readout_size, points_per_channel]);
+
+    // trajectory coordinates are written to the real part
+    write_cfl_from_real_f64(args.cfl_file,&traj,cfl_dims).map_err(TrajToCflError::Cfl)?;
 
     Ok(())
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,258 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::{Parser, Subcommand};
+use num_complex::Complex32;
+use array_lib::ArrayDim;
+use array_lib::io::same_file;
+use array_lib::io_cfl::{cfl_paths, try_write_cfl, CflChunkWriter, CflIoError, CflView};
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex32;
+    use array_lib::ArrayDim;
+    use array_lib::io_cfl::{cfl_paths, read_cfl, write_cfl};
+    use crate::{binary_op, rss, scale, CflMathError};
+
+    fn remove_cfl(base:&str) {
+        let (hdr, cfl) = cfl_paths(base);
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+    }
+
+    #[test]
+    fn test_scale_then_rss() {
+        // 3 coils of a 4 x 2 image
+        let dims = ArrayDim::from_shape(&[4,2,3]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32 - 5., 0.5 * i as f32)).collect();
+        write_cfl("test_cfl_math_in",&x,dims);
+        scale("test_cfl_math_in".as_ref(),"test_cfl_math_scaled".as_ref(),Complex32::new(0.,2.)).unwrap();
+        let out_dims = rss("test_cfl_math_scaled".as_ref(),"test_cfl_math_rss".as_ref(),2).unwrap();
+        let (y,y_dims) = read_cfl("test_cfl_math_rss");
+        remove_cfl("test_cfl_math_in");
+        remove_cfl("test_cfl_math_scaled");
+        remove_cfl("test_cfl_math_rss");
+
+        assert_eq!(out_dims.shape_ns(),&[4,2]);
+        assert_eq!(y_dims.shape_ns(),&[4,2]);
+        for i in 0..8 {
+            let expected = (0..3).map(|c| (x[i + 8 * c] * Complex32::new(0.,2.)).norm_sqr()).sum::<f32>().sqrt();
+            assert!((y[i].re - expected).abs() < 1e-4 && y[i].im == 0.);
+        }
+
+        // rss along the first axis is computed within each frame
+        write_cfl("test_cfl_math_in0",&x,dims);
+        rss("test_cfl_math_in0".as_ref(),"test_cfl_math_rss0".as_ref(),0).unwrap();
+        let (y,y_dims) = read_cfl("test_cfl_math_rss0");
+        remove_cfl("test_cfl_math_in0");
+        remove_cfl("test_cfl_math_rss0");
+        assert_eq!(y_dims.shape_ns(),&[1,2,3]);
+        let expected = x[4..8].iter().map(|x| x.norm_sqr()).sum::<f32>().sqrt();
+        assert!((y[1].re - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_broadcast_and_mismatch() {
+        let dims = ArrayDim::from_shape(&[3,2]);
+        let x:Vec<Complex32> = (0..6).map(|i| Complex32::new(i as f32, 1.)).collect();
+        let w = [Complex32::new(2.,0.),Complex32::new(0.,1.),Complex32::new(-1.,0.)];
+        write_cfl("test_cfl_math_x",&x,dims);
+        write_cfl("test_cfl_math_w",&w,ArrayDim::from_shape(&[3]));
+        write_cfl("test_cfl_math_bad",&w[..2],ArrayDim::from_shape(&[2]));
+
+        // a weight along the first axis is broadcast over the second, then added back to x
+        binary_op("test_cfl_math_x".as_ref(),"test_cfl_math_w".as_ref(),"test_cfl_math_xw".as_ref(),|a, b| a * b).unwrap();
+        binary_op("test_cfl_math_xw".as_ref(),"test_cfl_math_x".as_ref(),"test_cfl_math_sum".as_ref(),|a, b| a + b).unwrap();
+        let (y,_) = read_cfl("test_cfl_math_sum");
+        let r = binary_op("test_cfl_math_x".as_ref(),"test_cfl_math_bad".as_ref(),"test_cfl_math_out".as_ref(),|a, b| a + b);
+        for f in ["test_cfl_math_x","test_cfl_math_w","test_cfl_math_bad","test_cfl_math_xw","test_cfl_math_sum"] {
+            remove_cfl(f);
+        }
+        let expected:Vec<Complex32> = x.iter().enumerate().map(|(i, x)| x * w[i % 3] + x).collect();
+        assert_eq!(y,expected);
+        match r {
+            Err(e @ CflMathError::ShapeMismatch{..}) => assert_eq!(e.to_string(),"shapes [3, 2] and [2] are not compatible"),
+            r => panic!("expected a shape mismatch, got {:?}", r.map(|d| d.shape_ns().to_vec())),
+        }
+    }
+
+}
+
+#[derive(Subcommand)]
+enum Op {
+    /// multiply by a real factor
+    Scale { factor:f32, input:PathBuf, output:PathBuf },
+    /// add another cfl, broadcast over its singleton dimensions
+    Add { input:PathBuf, other:PathBuf, output:PathBuf },
+    /// multiply elementwise by another cfl, broadcast over its singleton dimensions
+    Mul { input:PathBuf, other:PathBuf, output:PathBuf },
+    /// complex conjugate
+    Conj { input:PathBuf, output:PathBuf },
+    /// magnitude, written as the real part
+    Magnitude { input:PathBuf, output:PathBuf },
+    /// root sum of squares along an axis, which becomes singleton
+    Rss { axis:usize, input:PathBuf, output:PathBuf },
+}
+
+/// elementwise operations on cfl files. Data is streamed one frame along the last axis at a time
+#[derive(Parser)]
+struct Args {
+    #[command(subcommand)]
+    op: Op,
+}
+
+#[derive(Debug)]
+enum CflMathError {
+    Cfl(CflIoError),
+    ShapeMismatch{a: Vec<usize>, b: Vec<usize>},
+    InvalidAxis(usize),
+    InPlace(PathBuf),
+}
+
+impl Display for CflMathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CflMathError::Cfl(e) => write!(f, "{}", e),
+            CflMathError::ShapeMismatch {a, b} => write!(f, "shapes {:?} and {:?} are not compatible", a, b),
+            CflMathError::InvalidAxis(axis) => write!(f, "axis {} is out of range", axis),
+            CflMathError::InPlace(path) => write!(f, "output {} would overwrite an input", path.display()),
+        }
+    }
+}
+
+impl From<CflIoError> for CflMathError {
+    fn from(err: CflIoError) -> Self {
+        CflMathError::Cfl(err)
+    }
+}
+
+/// the frames a cfl is streamed in: the frame dimensions, the axis frames are stacked along, and
+/// the number of frames
+fn frames(dims:&ArrayDim) -> (ArrayDim, usize, usize) {
+    let axis = dims.shape_ns().len().saturating_sub(1);
+    (dims.with_dim(axis, 1), axis, dims.shape()[axis])
+}
+
+/// returns an error if the output would overwrite one of the inputs while it is being read
+fn check_output(output:&Path, inputs:&[&Path]) -> Result<(), CflMathError> {
+    let (_, out) = cfl_paths(output);
+    if inputs.iter().any(|p| same_file(&cfl_paths(p).1, &out)) {
+        return Err(CflMathError::InPlace(output.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// streams a cfl through a function applied to each frame, given the address of the first
+/// element of the frame, and writes the result with the same dimensions
+fn map_frames(input:&Path, output:&Path, mut f:impl FnMut(usize, &mut [Complex32])) -> Result<ArrayDim, CflMathError> {
+    check_output(output, &[input])?;
+    let view = CflView::open(input)?;
+    let dims = view.dims();
+    let (frame_dims, axis, n_frames) = frames(&dims);
+    let mut w = CflChunkWriter::create_along(output, frame_dims, axis)?;
+    for i in 0..n_frames {
+        let mut frame = view.slab(axis, i);
+        f(i * frame_dims.numel(), &mut frame);
+        w.append_frame(&frame)?;
+    }
+    Ok(w.finish_with_dims(dims)?)
+}
+
+fn scale(input:&Path, output:&Path, factor:Complex32) -> Result<ArrayDim, CflMathError> {
+    map_frames(input, output, |_, x| x.iter_mut().for_each(|x| *x *= factor))
+}
+
+/// combines a cfl with another, elementwise. The other cfl must have the same size as the input
+/// along each axis, or be singleton to be broadcast along it
+fn binary_op(input:&Path, other:&Path, output:&Path, op:impl Fn(Complex32, Complex32) -> Complex32) -> Result<ArrayDim, CflMathError> {
+    check_output(output, &[other])?;
+    let dims = CflView::open(input)?.dims();
+    let other = CflView::open(other)?;
+    let o_dims = other.dims();
+    if dims.shape().iter().zip(o_dims.shape()).any(|(a, b)| a != b && *b != 1) {
+        return Err(CflMathError::ShapeMismatch{a: dims.shape_ns().to_vec(), b: o_dims.shape_ns().to_vec()});
+    }
+    let (_, axis, _) = frames(&dims);
+    let same_shape = dims.shape() == o_dims.shape();
+    map_frames(input, output, |start, x| {
+        if same_shape {
+            let y = other.slab(axis, start / x.len().max(1));
+            x.iter_mut().zip(y).for_each(|(x, y)| *x = op(*x, y));
+        } else {
+            for (i, x) in x.iter_mut().enumerate() {
+                let mut idx = dims.calc_idx(start + i);
+                idx.iter_mut().zip(o_dims.shape()).filter(|(_, d)| **d == 1).for_each(|(i, _)| *i = 0);
+                *x = op(*x, other.get(&idx));
+            }
+        }
+    })
+}
+
+/// root sum of squares along an axis. Frames are reduced one at a time, or accumulated when the
+/// axis is the one frames are stacked along
+fn rss(input:&Path, output:&Path, axis:usize) -> Result<ArrayDim, CflMathError> {
+    check_output(output, &[input])?;
+    let view = CflView::open(input)?;
+    let dims = view.dims();
+    if axis >= dims.shape().len() {
+        return Err(CflMathError::InvalidAxis(axis));
+    }
+    let out_dims = dims.with_dim(axis, 1);
+    let (frame_dims, frame_axis, n_frames) = frames(&dims);
+    if dims.shape()[axis] == 1 {
+        return map_frames(input, output, |_, x| x.iter_mut().for_each(|x| *x = Complex32::new(x.norm(), 0.)));
+    }
+
+    if axis == frame_axis {
+        let mut acc = vec![0f32; frame_dims.numel()];
+        for i in 0..n_frames {
+            acc.iter_mut().zip(view.slab(frame_axis, i)).for_each(|(a, x)| *a += x.norm_sqr());
+        }
+        let out:Vec<Complex32> = acc.into_iter().map(|a| Complex32::new(a.sqrt(), 0.)).collect();
+        try_write_cfl(output, &out, out_dims)?;
+        return Ok(out_dims);
+    }
+
+    let reduced_dims = frame_dims.with_dim(axis, 1);
+    let mut w = CflChunkWriter::create_along(output, reduced_dims, frame_axis)?;
+    let mut reduced = reduced_dims.alloc(Complex32::ZERO);
+    for i in 0..n_frames {
+        let frame = view.slab(frame_axis, i);
+        for (j, r) in reduced.iter_mut().enumerate() {
+            let mut idx = reduced_dims.calc_idx(j);
+            let mut sum = 0.;
+            for k in 0..dims.shape()[axis] {
+                idx[axis] = k;
+                sum += frame[frame_dims.calc_addr(&idx)].norm_sqr();
+            }
+            *r = Complex32::new(sum.sqrt(), 0.);
+        }
+        w.append_frame(&reduced)?;
+    }
+    Ok(w.finish_with_dims(out_dims)?)
+}
+
+fn run(args:Args) -> Result<ArrayDim, CflMathError> {
+    match args.op {
+        Op::Scale { factor, input, output } => scale(&input, &output, Complex32::new(factor, 0.)),
+        Op::Add { input, other, output } => binary_op(&input, &other, &output, |a, b| a + b),
+        Op::Mul { input, other, output } => binary_op(&input, &other, &output, |a, b| a * b),
+        Op::Conj { input, output } => map_frames(&input, &output, |_, x| x.iter_mut().for_each(|x| *x = x.conj())),
+        Op::Magnitude { input, output } => map_frames(&input, &output, |_, x| x.iter_mut().for_each(|x| *x = Complex32::new(x.norm(), 0.))),
+        Op::Rss { axis, input, output } => rss(&input, &output, axis),
+    }
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(dims) => {
+            println!("wrote array of shape {:?}", dims.shape_ns());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use clap::{Parser, ValueEnum};
+use array_lib::convert::{CflToNiftiOptions, ConvertError, NiftiComponent, NiftiDtype};
+use array_lib::io::{read_array, write_array, ArrayWriteOptions};
+use array_lib::io_nifti::nifti_output_path;
+
+/// the part of the complex data to write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum What {
+    Magnitude,
+    Phase,
+    Real,
+    Imag,
+    Complex,
+}
+
+/// the stored type of the nifti
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Dtype {
+    Float32,
+    Int16,
+    Uint16,
+}
+
+#[derive(Parser)]
+struct Args {
+    /// cfl file to read
+    cfl_file: PathBuf,
+    /// output nifti file. A .nii.gz extension writes a compressed file, and .nii is appended to
+    /// any other name
+    nifti_file: PathBuf,
+
+    /// the part of the complex data to write
+    #[clap(long, value_enum, default_value_t = What::Magnitude)]
+    what: What,
+
+    /// voxel size as x,y,z, written to pixdim
+    #[clap(long)]
+    voxel_size: Option<String>,
+
+    /// the stored type. Integer types are scaled to their full range with scl_slope and scl_inter
+    #[clap(long, value_enum, default_value_t = Dtype::Float32)]
+    dtype: Dtype,
+
+    /// export a single position along frame_axis
+    #[clap(long)]
+    frame: Option<usize>,
+
+    /// the axis that frame indexes
+    #[clap(long, default_value_t = 3)]
+    frame_axis: usize,
+}
+
+fn run(args:Args) -> Result<(), ConvertError> {
+    let component = match args.what {
+        What::Magnitude => NiftiComponent::Magnitude,
+        What::Phase => NiftiComponent::Phase,
+        What::Real => NiftiComponent::Real,
+        What::Imag => NiftiComponent::Imag,
+        What::Complex => NiftiComponent::Complex,
+    };
+    let dtype = match args.dtype {
+        Dtype::Float32 => NiftiDtype::Float32,
+        Dtype::Int16 => NiftiDtype::Int16,
+        Dtype::Uint16 => NiftiDtype::Uint16,
+    };
+    let mut opts = CflToNiftiOptions::new().component(component);
+    if let Some(spec) = &args.voxel_size {
+        let spacing:Vec<f64> = spec.split(',').filter_map(|x| x.trim().parse().ok()).collect();
+        let spacing:[f64; 3] = spacing.try_into()
+            .map_err(|_| ConvertError::Unsupported(format!("voxel size {} must be 3 numbers", spec)))?;
+        opts = opts.spacing(spacing);
+    }
+    if let Some(frame) = args.frame {
+        opts = opts.frame(args.frame_axis, frame);
+    }
+    let array = opts.apply(&read_array(&args.cfl_file)?)?;
+    // an output without a .nii or .nii.gz extension gets .nii, rather than being taken as a cfl
+    let out = write_array(nifti_output_path(&args.nifti_file), &array, &ArrayWriteOptions::new().nifti_dtype(dtype))?;
+    println!("wrote {}", out.display());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
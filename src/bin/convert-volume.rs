@@ -0,0 +1,101 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::{Parser, ValueEnum};
+use array_lib::convert_volume::{nifti_to_nrrd, nrrd_to_nifti, VolumeConvertError, VolumeConvertOptions};
+use array_lib::io_nrrd::NrrdDtype;
+
+/// the encoding of nrrd outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum NrrdEncoding {
+    Raw,
+    Gzip,
+}
+
+#[derive(Parser)]
+struct Args {
+    /// input volume. The direction of the conversion is taken from its extension: .nrrd and .nhdr
+    /// are converted to nifti, and .nii and .nii.gz are converted to nrrd
+    input: PathBuf,
+    /// output volume
+    output: PathBuf,
+
+    /// the element type of the output, as named in nrrd headers (int16, uint8, float, ...).
+    /// Defaults to the element type of the input
+    #[clap(long)]
+    dtype: Option<String>,
+
+    /// the encoding of nrrd outputs
+    #[clap(long, value_enum, default_value_t = NrrdEncoding::Raw)]
+    encoding: NrrdEncoding,
+
+    /// gzip nifti outputs
+    #[clap(long)]
+    compress: bool,
+}
+
+#[derive(Debug)]
+enum ConvertVolumeError {
+    Convert(VolumeConvertError),
+    UnknownFormat(PathBuf),
+    InvalidDtype(String),
+}
+
+impl Display for ConvertVolumeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertVolumeError::Convert(e) => write!(f, "{}", e),
+            ConvertVolumeError::UnknownFormat(path) => write!(
+                f, "cannot tell the format of {}. Expected a .nrrd, .nhdr, .nii or .nii.gz file", path.display()
+            ),
+            ConvertVolumeError::InvalidDtype(s) => write!(f, "unknown element type {}", s),
+        }
+    }
+}
+
+impl From<VolumeConvertError> for ConvertVolumeError {
+    fn from(err: VolumeConvertError) -> Self {
+        ConvertVolumeError::Convert(err)
+    }
+}
+
+/// true if the file is a nrrd, false if it is a nifti
+fn is_nrrd(path:&Path) -> Result<bool, ConvertVolumeError> {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_lowercase();
+    if name.ends_with(".nrrd") || name.ends_with(".nhdr") {
+        Ok(true)
+    } else if name.ends_with(".nii") || name.ends_with(".nii.gz") {
+        Ok(false)
+    } else {
+        Err(ConvertVolumeError::UnknownFormat(path.to_path_buf()))
+    }
+}
+
+fn run(args:Args) -> Result<(), ConvertVolumeError> {
+    let mut opts = VolumeConvertOptions::new()
+        .gzip(args.encoding == NrrdEncoding::Gzip)
+        .compress(args.compress);
+    if let Some(dtype) = &args.dtype {
+        let dtype = NrrdDtype::from_header(dtype).ok_or_else(|| ConvertVolumeError::InvalidDtype(dtype.to_string()))?;
+        opts = opts.dtype(dtype);
+    }
+    if is_nrrd(&args.input)? {
+        let out = nrrd_to_nifti(&args.input, &args.output, &opts)?;
+        println!("wrote {}", out.display());
+    } else {
+        nifti_to_nrrd(&args.input, &args.output, &opts)?;
+        println!("wrote {}", args.output.display());
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
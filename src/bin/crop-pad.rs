@@ -0,0 +1,387 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::{ArgGroup, Parser, ValueEnum};
+use num_complex::{Complex, Complex32};
+use array_lib::{ArrayDim, PadMode};
+use array_lib::io_cfl::{try_read_cfl, try_write_cfl, CflIoError};
+use array_lib::io_nifti::{nifti_affine, nifti_qform_affine, read_nifti_header, try_read_nifti, try_read_nifti_complex, write_nifti_with_options, NiftiIoError, NiftiWriteOptions};
+use array_lib::io_nrrd::{read_nrrd_scaled, write_nrrd_as, Encoding, NrrdDtype, NrrdIoError, NrrdWriteOptions, ScalePolicy};
+
+#[cfg(test)]
+mod tests {
+    use array_lib::ArrayDim;
+    use num_complex::Complex32;
+    use array_lib::io_nifti::{nifti_affine, nifti_qform_affine, read_nifti, read_nifti_complex, set_nifti_affine, write_nifti_with_options, NiftiHeader, NiftiWriteOptions};
+    use clap::Parser;
+    use crate::{run, Args, CropPadError};
+
+    #[test]
+    fn test_off_center_nifti_crop() {
+        // anisotropic voxels rotated 30 degrees about z
+        let (s, c) = (30f64.to_radians().sin(), 30f64.to_radians().cos());
+        let affine = [
+            [0.5 * c, -0.8 * s, 0., 10.],
+            [0.5 * s, 0.8 * c, 0., -20.],
+            [0., 0., 2., 5.],
+            [0., 0., 0., 1.],
+        ];
+        let mut h = NiftiHeader::default();
+        set_nifti_affine(&mut h, affine);
+        let dims = ArrayDim::from_shape(&[8,6,4]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+        write_nifti_with_options("test_crop_pad_in.nii",&x,dims,Some(&h),&NiftiWriteOptions::new()).unwrap();
+
+        let crop_pad = |extra:&[&str], out:&str| run(Args::parse_from(
+            ["crop-pad", "test_crop_pad_in.nii", out].iter().chain(extra)
+        ));
+        crop_pad(&["--offset", "2,1,0", "--size", "4,3,4"], "test_crop_pad_offset.nii").unwrap();
+        // the centered crop of the same size starts at the same voxel
+        crop_pad(&["--crop", "4,4,4"], "test_crop_pad_centered.nii").unwrap();
+        let bad = crop_pad(&["--crop", "10,6,4"], "test_crop_pad_bad.nii");
+
+        let (y,y_dims,y_h) = read_nifti::<f32>("test_crop_pad_offset.nii");
+        let (z,z_dims,z_h) = read_nifti::<f32>("test_crop_pad_centered.nii");
+        for f in ["test_crop_pad_in.nii", "test_crop_pad_offset.nii", "test_crop_pad_centered.nii"] {
+            std::fs::remove_file(f).unwrap();
+        }
+
+        let (expected,expected_dims) = dims.copy_region(&x,&[2,1,0],&[4,3,4]);
+        assert_eq!(y,expected);
+        assert_eq!(y_dims.shape(),expected_dims.shape());
+        assert_eq!(z_dims.shape_ns(),&[4,4,4]);
+        assert_eq!(z[0],x[dims.calc_addr(&[2,1,0])]);
+
+        // the new origin is the world position of voxel (2,1,0) of the input
+        let origin:Vec<f64> = (0..3).map(|r| affine[r][0] * 2. + affine[r][1] + affine[r][3]).collect();
+        for a in [nifti_affine(&y_h), nifti_affine(&z_h)] {
+            for ((row, expected), o) in a.iter().zip(&affine).zip(&origin) {
+                assert!((row[3] - o).abs() < 1e-4, "origin {:?} != {:?}", a, origin);
+                assert!(row[..3].iter().zip(&expected[..3]).all(|(a, b)| (a - b).abs() < 1e-5));
+            }
+        }
+
+        assert!(matches!(bad,Err(CropPadError::InvalidSize{axis: 0, size: 10, dim: 8, ..})));
+    }
+
+    #[test]
+    fn test_qform_nifti_crop() {
+        // int32 values past the precision of f32, placed by a qform rotated 90 degrees about z
+        let mut h = NiftiHeader::default();
+        h.sform_code = 0;
+        h.qform_code = 1;
+        h.quatern_b = 0.;
+        h.quatern_c = 0.;
+        h.quatern_d = std::f32::consts::FRAC_1_SQRT_2;
+        h.quatern_x = 10.;
+        h.quatern_y = 20.;
+        h.quatern_z = 30.;
+        h.pixdim = [1., 2., 3., 4., 1., 1., 1., 1.];
+        let dims = ArrayDim::from_shape(&[5,4,3]);
+        let x:Vec<i32> = (0..dims.numel()).map(|i| (1 << 24) + 1 + i as i32).collect();
+        write_nifti_with_options("test_crop_pad_qform.nii",&x,dims,Some(&h),&NiftiWriteOptions::new()).unwrap();
+        run(Args::parse_from(["crop-pad", "test_crop_pad_qform.nii", "test_crop_pad_qform_out.nii", "--offset", "1,2,0", "--size", "2,2,3"])).unwrap();
+        let (y,y_dims,y_h) = read_nifti::<i32>("test_crop_pad_qform_out.nii");
+        std::fs::remove_file("test_crop_pad_qform.nii").unwrap();
+        std::fs::remove_file("test_crop_pad_qform_out.nii").unwrap();
+
+        let (expected,expected_dims) = dims.copy_region(&x,&[1,2,0],&[2,2,3]);
+        assert_eq!(y,expected);
+        assert_eq!(y_dims.shape(),expected_dims.shape());
+        assert_eq!(y_h.datatype,8);
+        // no sform is added, and the qform origin moves along the rotated axes
+        assert_eq!(y_h.sform_code,0);
+        let a = nifti_qform_affine(&y_h);
+        assert_eq!(nifti_affine(&y_h),a);
+        for (r, o) in [4., 22., 30.].iter().enumerate() {
+            assert!((a[r][3] - o).abs() < 1e-4, "origin {:?}", a);
+        }
+    }
+
+    #[test]
+    fn test_complex_nifti_crop() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32, -(i as f32))).collect();
+        write_nifti_with_options("test_crop_pad_complex.nii",&x,dims,None,&NiftiWriteOptions::new()).unwrap();
+        run(Args::parse_from(["crop-pad", "test_crop_pad_complex.nii", "test_crop_pad_complex_out.nii", "--size", "5,3,2", "--offset", "-1,0,0"])).unwrap();
+        let (y,y_dims,_) = read_nifti_complex::<f32>("test_crop_pad_complex_out.nii");
+        std::fs::remove_file("test_crop_pad_complex.nii").unwrap();
+        std::fs::remove_file("test_crop_pad_complex_out.nii").unwrap();
+        assert_eq!(y_dims.shape_ns(),&[5,3,2]);
+        assert_eq!(y[0],Complex32::new(0.,0.));
+        assert_eq!(y[2],x[1]);
+        assert_eq!(y[4],x[3]);
+    }
+}
+
+/// how padded values are filled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Mode {
+    /// the --fill value
+    Constant,
+    /// the nearest edge value
+    Edge,
+    /// values from the opposite edge
+    Wrap,
+}
+
+impl From<Mode> for PadMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Constant => PadMode::Constant,
+            Mode::Edge => PadMode::Edge,
+            Mode::Wrap => PadMode::Wrap,
+        }
+    }
+}
+
+#[derive(Parser)]
+#[clap(group(ArgGroup::new("region").required(true).args(["crop", "pad", "size"])))]
+struct Args {
+    /// input volume: a cfl base name or .cfl/.hdr, .nii, .nii.gz, .nrrd or .nhdr
+    input: PathBuf,
+    /// output volume, written in the format of the input
+    output: PathBuf,
+
+    /// centered crop to the given sizes, as x,y,z[,..]. Axes that aren't given are kept
+    #[clap(long)]
+    crop: Option<String>,
+
+    /// centered pad to the given sizes, as x,y,z[,..]. Axes that aren't given are kept
+    #[clap(long)]
+    pad: Option<String>,
+
+    /// the size of an explicit region, as x,y,z[,..]. The region may extend past the input, in
+    /// which case it is padded
+    #[clap(long)]
+    size: Option<String>,
+
+    /// the offset of the region given by --size, as x,y,z[,..]. Negative offsets pad before the
+    /// start of the input. Defaults to 0
+    #[clap(long, requires = "size", allow_hyphen_values = true)]
+    offset: Option<String>,
+
+    /// the value of padded voxels for constant padding
+    #[clap(long, default_value_t = 0.)]
+    fill: f32,
+
+    /// how padded voxels are filled
+    #[clap(long, value_enum, default_value_t = Mode::Constant)]
+    mode: Mode,
+}
+
+#[derive(Debug)]
+enum CropPadError {
+    Cfl(CflIoError),
+    Nifti(NiftiIoError),
+    Nrrd(NrrdIoError),
+    InvalidList(String),
+    InvalidSize{op: &'static str, axis: usize, size: usize, dim: usize},
+}
+
+impl Display for CropPadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CropPadError::Cfl(e) => write!(f, "{}", e),
+            CropPadError::Nifti(e) => write!(f, "{}", e),
+            CropPadError::Nrrd(e) => write!(f, "{}", e),
+            CropPadError::InvalidList(s) => write!(f, "invalid list {}. Expected comma separated integers", s),
+            CropPadError::InvalidSize{op, axis, size, dim} => write!(
+                f, "can't {} axis {} of size {} to {}", op, axis, dim, size
+            ),
+        }
+    }
+}
+
+impl From<CflIoError> for CropPadError {
+    fn from(err: CflIoError) -> Self {
+        CropPadError::Cfl(err)
+    }
+}
+
+impl From<NiftiIoError> for CropPadError {
+    fn from(err: NiftiIoError) -> Self {
+        CropPadError::Nifti(err)
+    }
+}
+
+impl From<NrrdIoError> for CropPadError {
+    fn from(err: NrrdIoError) -> Self {
+        CropPadError::Nrrd(err)
+    }
+}
+
+/// parses a comma separated list of integers
+fn parse_list<T:std::str::FromStr>(s:&str) -> Result<Vec<T>, CropPadError> {
+    s.split(',')
+        .map(|v| v.trim().parse().map_err(|_| CropPadError::InvalidList(s.to_string())))
+        .collect()
+}
+
+/// the offset and size of the output region. Centered crops must not be larger than the input
+/// and centered pads must not be smaller
+fn region(args:&Args, dims:&ArrayDim) -> Result<(Vec<isize>, Vec<usize>), CropPadError> {
+    if let Some(size) = &args.size {
+        let size:Vec<usize> = parse_list(size)?;
+        if let Some(axis) = size.iter().position(|&s| s == 0) {
+            return Err(CropPadError::InvalidSize{op: "resize", axis, size: 0, dim: dims.shape()[axis]});
+        }
+        let offset = match &args.offset {
+            Some(offset) => parse_list(offset)?,
+            None => vec![0; size.len()],
+        };
+        return Ok((offset, size));
+    }
+    let (op, size) = match (&args.crop, &args.pad) {
+        (Some(crop), _) => ("crop", crop),
+        (None, Some(pad)) => ("pad", pad),
+        (None, None) => unreachable!("clap requires a region"),
+    };
+    let size:Vec<usize> = parse_list(size)?;
+    for (axis, (&s, &d)) in size.iter().zip(dims.shape()).enumerate() {
+        if (op == "crop" && s > d) || (op == "pad" && s < d) || s == 0 {
+            return Err(CropPadError::InvalidSize{op, axis, size: s, dim: d});
+        }
+    }
+    let offset = dims.centered_offset(&size)[..size.len()].to_vec();
+    Ok((offset, size))
+}
+
+fn is_nifti(path:&Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_lowercase();
+    name.ends_with(".nii") || name.ends_with(".nii.gz")
+}
+
+fn is_nrrd(path:&Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_lowercase();
+    name.ends_with(".nrrd") || name.ends_with(".nhdr")
+}
+
+/// the world space shift of the first voxel of a region at an offset along each of the spatial
+/// axes, given the column vectors of those axes
+fn origin_shift(axes:&[[f64; 3]], offset:&[isize]) -> [f64; 3] {
+    let mut shift = [0.; 3];
+    for (axis, &o) in axes.iter().zip(offset) {
+        shift.iter_mut().zip(axis).for_each(|(s, a)| *s += a * o as f64);
+    }
+    shift
+}
+
+/// the column vectors of the spatial axes of an affine
+fn affine_columns(affine:&[[f64; 4]; 4]) -> Vec<[f64; 3]> {
+    (0..3).map(|c| [affine[0][c], affine[1][c], affine[2][c]]).collect()
+}
+
+/// crops or pads a nifti in its stored datatype, moving the origins of the sform and qform (where
+/// they are set) to the first voxel of the region. Scaled data is written as float32 with the
+/// scaling applied
+fn crop_pad_nifti(args:&Args, mode:PadMode) -> Result<PathBuf, CropPadError> {
+    let (dims, h) = read_nifti_header(&args.input)?;
+    let (offset, size) = region(args, &dims)?;
+
+    let mut out_h = h.clone();
+    if h.sform_code > 0 {
+        let shift = origin_shift(&affine_columns(&nifti_affine(&h)), &offset);
+        for (row, s) in [&mut out_h.srow_x, &mut out_h.srow_y, &mut out_h.srow_z].into_iter().zip(shift) {
+            row[3] = (row[3] as f64 + s) as f32;
+        }
+    }
+    if h.qform_code > 0 {
+        let shift = origin_shift(&affine_columns(&nifti_qform_affine(&h)), &offset);
+        out_h.quatern_x = (out_h.quatern_x as f64 + shift[0]) as f32;
+        out_h.quatern_y = (out_h.quatern_y as f64 + shift[1]) as f32;
+        out_h.quatern_z = (out_h.quatern_z as f64 + shift[2]) as f32;
+    }
+    let scaled = !(h.scl_slope == 0. || h.scl_slope == 1.) || h.scl_inter != 0.;
+    out_h.scl_slope = 1.;
+    out_h.scl_inter = 0.;
+
+    let opts = NiftiWriteOptions::new();
+    let int_fill = args.fill.round();
+    macro_rules! crop_as {
+        ($t:ty, $fill:expr) => {{
+            let (x, ..) = try_read_nifti::<$t>(&args.input)?;
+            let (y, y_dims) = dims.crop_pad(&x, &offset, &size, mode, $fill);
+            write_nifti_with_options(&args.output, &y, y_dims, Some(&out_h), &opts)
+        }};
+    }
+    macro_rules! crop_complex_as {
+        ($t:ty) => {{
+            let (x, ..) = try_read_nifti_complex::<$t>(&args.input)?;
+            let (y, y_dims) = dims.crop_pad(&x, &offset, &size, mode, Complex::new(args.fill as $t, 0.));
+            write_nifti_with_options(&args.output, &y, y_dims, Some(&out_h), &opts)
+        }};
+    }
+    let out = match h.datatype {
+        _ if scaled => crop_as!(f32, args.fill),
+        2 => crop_as!(u8, int_fill as u8),
+        4 => crop_as!(i16, int_fill as i16),
+        8 => crop_as!(i32, int_fill as i32),
+        32 => crop_complex_as!(f32),
+        64 => crop_as!(f64, args.fill as f64),
+        256 => crop_as!(i8, int_fill as i8),
+        512 => crop_as!(u16, int_fill as u16),
+        768 => crop_as!(u32, int_fill as u32),
+        1024 => crop_as!(i64, int_fill as i64),
+        1280 => crop_as!(u64, int_fill as u64),
+        1792 => crop_complex_as!(f64),
+        _ => crop_as!(f32, args.fill),
+    }?;
+    Ok(out)
+}
+
+/// crops or pads a nrrd, moving the space origin to the first voxel of the region
+fn crop_pad_nrrd(args:&Args, mode:PadMode) -> Result<(), CropPadError> {
+    let (x, dims, h) = read_nrrd_scaled(&args.input)?;
+    let (offset, size) = region(args, &dims)?;
+    let (y, y_dims) = dims.crop_pad(&x, &offset, &size, mode, args.fill);
+
+    let encoding = if h.field("encoding") == Some("gzip") { Encoding::Gzip } else { Encoding::Raw };
+    let mut opts = NrrdWriteOptions::new().encoding(encoding);
+    if let Some(mut geometry) = h.geometry() {
+        let directions = geometry.directions.clone()
+            .unwrap_or_else(|| vec![[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]]);
+        let axes:Vec<[f64; 3]> = directions.iter().zip(&geometry.spacings).map(|(d, s)| d.map(|d| d * s)).collect();
+        let offset:Vec<isize> = geometry.spatial_axes().iter().map(|&a| offset.get(a).copied().unwrap_or(0)).collect();
+        let shift = origin_shift(&axes, &offset);
+        let origin = geometry.origin.unwrap_or([0.; 3]);
+        geometry.origin = Some([origin[0] + shift[0], origin[1] + shift[1], origin[2] + shift[2]]);
+        opts = opts.with_geometry(geometry);
+    }
+    write_nrrd_as(&args.output, &y, y_dims, h.dtype().unwrap_or(NrrdDtype::Float32), ScalePolicy::None, &opts)?;
+    Ok(())
+}
+
+fn run(args:Args) -> Result<(), CropPadError> {
+    let mode = PadMode::from(args.mode);
+    if is_nifti(&args.input) {
+        let out = crop_pad_nifti(&args, mode)?;
+        println!("wrote {}", out.display());
+        return Ok(());
+    }
+    if is_nrrd(&args.input) {
+        crop_pad_nrrd(&args, mode)?;
+    } else {
+        let base = match args.input.extension().and_then(|e| e.to_str()) {
+            Some("cfl" | "hdr") => args.input.with_extension(""),
+            _ => args.input.clone(),
+        };
+        let (x, dims) = try_read_cfl(&base)?;
+        let (offset, size) = region(&args, &dims)?;
+        let (y, y_dims) = dims.crop_pad(&x, &offset, &size, mode, Complex32::new(args.fill, 0.));
+        try_write_cfl(&args.output, &y, y_dims)?;
+    }
+    println!("wrote {}", args.output.display());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
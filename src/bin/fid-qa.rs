@@ -0,0 +1,298 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use clap::Parser;
+use num_complex::{Complex32, Complex64};
+use serde::Serialize;
+use array_lib::ArrayDim;
+use array_lib::io_bruker::{decode_fid_chunk, read_fid_params, resolve_fid_layout, BrukerDataError, FidEncoding};
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use array_lib::ArrayDim;
+    use array_lib::io_bruker::{ByteOrder, FidEncoding, FidLayout, SampleFormat};
+    use crate::{fid_qa, QaOptions, Spike};
+
+    #[test]
+    fn test_spike_and_dc() {
+        // 64 samples x 2 receivers x 64 phase encodes of uniform noise in [-100, 100)
+        let dims = ArrayDim::from_shape(&[64,2,1,64,1,1]);
+        let mut state = 1u64;
+        let mut noise = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) as f64 / (1u64 << 31) as f64 * 200. - 100.) as i16
+        };
+        let mut words:Vec<i16> = (0..2 * dims.numel()).map(|_| noise()).collect();
+        // a DC offset on the real part of receiver 1
+        for (i, w) in words.chunks_exact_mut(2).enumerate() {
+            if (i / 64) % 2 == 1 {
+                w[0] += 500;
+            }
+        }
+        // a spike near the end of readout group 3 on receiver 0, and a clipped sample on receiver 1
+        words[2 * (3 * 128 + 60)] = 20000;
+        words[2 * (5 * 128 + 64 + 10) + 1] = i16::MAX;
+        let bytes:Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+
+        let enc = FidEncoding{layout: FidLayout::Continuous, format: SampleFormat::I16, byte_order: ByteOrder::Little};
+        let report = fid_qa(Cursor::new(bytes), &dims, enc, &QaOptions::default()).unwrap();
+
+        assert_eq!(report.receivers.len(), 2);
+        assert!(!report.receivers[0].dc_flagged);
+        assert!(report.receivers[1].dc_flagged);
+        assert!((report.receivers[1].dc_re - 500.).abs() < 10.);
+        // uniform noise has a standard deviation of 100 / sqrt(3) in each channel
+        assert!((report.receivers[0].noise - 57.7).abs() < 15.);
+        assert_eq!(report.receivers[0].clipped, 0);
+        assert_eq!(report.receivers[1].clipped, 1);
+        assert_eq!(report.receivers[0].spikes, 1);
+        assert_eq!(report.receivers[1].spikes, 0);
+        assert_eq!(report.spikes.len(), 1);
+        let Spike{chunk, echo, receiver, sample, ..} = report.spikes[0];
+        assert_eq!((chunk, echo, receiver, sample), (3, 0, 0, 60));
+    }
+
+}
+
+#[derive(Parser)]
+struct Args {
+    /// path to a Bruker fid file, or a scan directory holding the fid (or rawdata.job0) and acqp
+    fid_file: PathBuf,
+    /// path to the Bruker acquisition parameters file. Not needed for a scan directory
+    acqp_file: Option<PathBuf>,
+
+    /// samples in the readout tails further than this many median absolute deviations above the
+    /// median magnitude are reported as spikes
+    #[clap(long, default_value_t = 10.)]
+    spike_k: f64,
+
+    /// the fraction of each readout at its end used to estimate the noise floor and find spikes
+    #[clap(long, default_value_t = 0.25)]
+    tail: f64,
+
+    /// DC offsets larger than this multiple of the noise floor are flagged
+    #[clap(long, default_value_t = 0.5)]
+    dc_k: f64,
+
+    /// print the report as json
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Debug)]
+enum FidQaError {
+    IO(PathBuf, std::io::Error),
+    Bruker(BrukerDataError),
+    MissingArgument(String),
+}
+
+impl Display for FidQaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FidQaError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            FidQaError::Bruker(e) => write!(f, "{}", e),
+            FidQaError::MissingArgument(name) => write!(f, "missing argument {}", name),
+        }
+    }
+}
+
+impl From<BrukerDataError> for FidQaError {
+    fn from(err: BrukerDataError) -> Self {
+        FidQaError::Bruker(err)
+    }
+}
+
+/// thresholds for flagging samples and receivers
+#[derive(Debug, Clone, Copy)]
+struct QaOptions {
+    spike_k: f64,
+    tail: f64,
+    dc_k: f64,
+}
+
+impl Default for QaOptions {
+    fn default() -> Self {
+        QaOptions { spike_k: 10., tail: 0.25, dc_k: 0.5 }
+    }
+}
+
+/// the metrics of a single receiver
+#[derive(Debug, Clone, Serialize)]
+struct ReceiverQa {
+    receiver: usize,
+    /// the mean of the complex samples
+    dc_re: f64,
+    dc_im: f64,
+    dc_flagged: bool,
+    /// the standard deviation of the noise in each channel, estimated from the median magnitude
+    /// of the readout tails
+    noise: f64,
+    /// samples with a real or imaginary word at the limit of the word size
+    clipped: usize,
+    spikes: usize,
+}
+
+/// a sample in a readout tail far above the noise floor of its receiver
+#[derive(Debug, Clone, Copy, Serialize)]
+struct Spike {
+    /// the readout group in the fid file
+    chunk: usize,
+    echo: usize,
+    receiver: usize,
+    sample: usize,
+    magnitude: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct QaReport {
+    dims: Vec<usize>,
+    format: String,
+    clip_limit: Option<f32>,
+    receivers: Vec<ReceiverQa>,
+    spikes: Vec<Spike>,
+}
+
+impl Display for QaReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "dims [samples, receivers, echoes, y, z, repeats]: {:?}", self.dims)?;
+        writeln!(f, "sample format: {}", self.format)?;
+        writeln!(f, "{:>8} {:>24} {:>10} {:>8} {:>8}", "receiver", "dc", "noise", "clipped", "spikes")?;
+        for r in &self.receivers {
+            let dc = format!("{:.2}{:+.2}i", r.dc_re, r.dc_im);
+            let flag = if r.dc_flagged { "  DC OFFSET" } else { "" };
+            writeln!(f, "{:>8} {:>24} {:>10.2} {:>8} {:>8}{}", r.receiver, dc, r.noise, r.clipped, r.spikes, flag)?;
+        }
+        for s in &self.spikes {
+            writeln!(f, "spike: chunk {} echo {} receiver {} sample {} magnitude {:.1}", s.chunk, s.echo, s.receiver, s.sample, s.magnitude)?;
+        }
+        Ok(())
+    }
+}
+
+/// the median of some values, which are reordered
+fn median(x:&mut [f64]) -> f64 {
+    if x.is_empty() {
+        return 0.;
+    }
+    let mid = x.len() / 2;
+    *x.select_nth_unstable_by(mid, |a, b| a.total_cmp(b)).1
+}
+
+/// reads a fid one readout group at a time, accumulating the DC offset and clipped samples of
+/// each receiver and keeping the samples of the readout tails for the noise and spike estimates
+fn fid_qa(fid:impl Read, dims:&ArrayDim, enc:FidEncoding, opts:&QaOptions) -> Result<QaReport, std::io::Error> {
+    let [n_read, n_receivers, n_echoes, ..] = *dims.shape();
+    let chunk_size_samples = n_read * n_receivers * n_echoes;
+    let stride = enc.layout.chunk_stride(chunk_size_samples, enc.format);
+    let n_chunks = dims.numel() / chunk_size_samples;
+    let tail_start = n_read - ((n_read as f64 * opts.tail).round() as usize).clamp(1, n_read);
+    let clip_limit = enc.format.max_value();
+
+    let mut sums = vec![Complex64::ZERO; n_receivers];
+    let mut clipped = vec![0usize; n_receivers];
+    let mut tails:Vec<Vec<Complex32>> = vec![Vec::with_capacity(n_chunks * n_echoes * (n_read - tail_start)); n_receivers];
+    let mut r = BufReader::new(fid);
+    let mut bytes = vec![0u8; stride];
+    let mut chunk = vec![Complex32::ZERO; chunk_size_samples];
+    for _ in 0..n_chunks {
+        r.read_exact(&mut bytes)?;
+        decode_fid_chunk(&bytes, enc.format, enc.byte_order, &mut chunk);
+        for (i, readout) in chunk.chunks_exact(n_read).enumerate() {
+            let receiver = i % n_receivers;
+            sums[receiver] += readout.iter().map(|x| Complex64::new(x.re as f64, x.im as f64)).sum::<Complex64>();
+            if let Some(limit) = clip_limit {
+                clipped[receiver] += readout.iter().filter(|x| x.re.abs() >= limit || x.im.abs() >= limit).count();
+            }
+            tails[receiver].extend_from_slice(&readout[tail_start..]);
+        }
+    }
+
+    let n_per_receiver = (n_chunks * n_read * n_echoes).max(1) as f64;
+    let tail_len = n_read - tail_start;
+    let mut receivers = vec![];
+    let mut spikes = vec![];
+    for (receiver, tail) in tails.iter().enumerate() {
+        let dc = sums[receiver] / n_per_receiver;
+        let mags:Vec<f64> = tail.iter().map(|x| (Complex64::new(x.re as f64, x.im as f64) - dc).norm()).collect();
+        let med = median(&mut mags.clone());
+        let mad = median(&mut mags.iter().map(|m| (m - med).abs()).collect::<Vec<f64>>());
+        // the median of a rayleigh distribution is sigma * sqrt(2 ln 2)
+        let noise = med / (2. * 2f64.ln()).sqrt();
+
+        let n_spikes = spikes.len();
+        if mad > 0. {
+            let threshold = med + opts.spike_k * mad;
+            for (i, &m) in mags.iter().enumerate().filter(|(_, m)| **m > threshold) {
+                let group = i / tail_len;
+                spikes.push(Spike {
+                    chunk: group / n_echoes,
+                    echo: group % n_echoes,
+                    receiver,
+                    sample: tail_start + i % tail_len,
+                    magnitude: m,
+                });
+            }
+        }
+        receivers.push(ReceiverQa {
+            receiver,
+            dc_re: dc.re,
+            dc_im: dc.im,
+            dc_flagged: noise > 0. && dc.norm() > opts.dc_k * noise,
+            noise,
+            clipped: clipped[receiver],
+            spikes: spikes.len() - n_spikes,
+        });
+    }
+
+    Ok(QaReport {
+        dims: dims.shape_ns().to_vec(),
+        format: format!("{:?}", enc.format),
+        clip_limit,
+        receivers,
+        spikes,
+    })
+}
+
+fn run(args:Args) -> Result<(), FidQaError> {
+    let (fid_file, acqp_file) = if args.fid_file.is_dir() {
+        let dir = &args.fid_file;
+        let fid = [dir.join("fid"), dir.join("rawdata.job0")].into_iter().find(|f| f.is_file())
+            .ok_or_else(|| FidQaError::Bruker(BrukerDataError::FidNotFound(dir.join("fid"))))?;
+        (fid, dir.join("acqp"))
+    } else {
+        let acqp = args.acqp_file.clone().ok_or_else(|| FidQaError::MissingArgument(String::from("acqp_file")))?;
+        (args.fid_file.clone(), acqp)
+    };
+
+    let params = read_fid_params(&acqp_file)?;
+    let dims = params.dims();
+    // the layout is checked against the size of the fid file as in bruker-fid-to-cfl
+    let enc = resolve_fid_layout(&fid_file, &dims, params.encoding)?;
+
+    let io_err = |e| FidQaError::IO(fid_file.clone(), e);
+
+    let opts = QaOptions { spike_k: args.spike_k, tail: args.tail, dc_k: args.dc_k };
+    let f = File::open(&fid_file).map_err(io_err)?;
+    let report = fid_qa(f, &dims, enc, &opts).map_err(io_err)?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report).expect("report is always serializable"));
+    } else {
+        println!("fid: {}", fid_file.display());
+        print!("{}", report);
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
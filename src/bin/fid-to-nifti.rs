@@ -0,0 +1,228 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::Parser;
+use num_complex::Complex32;
+use array_lib::ArrayDim;
+use array_lib::fft::fft_centered;
+use array_lib::io_bruker::{decode_fid, read_fid_params, read_spatial_resolution, resolve_fid_layout, BrukerDataError};
+use array_lib::io_nifti::{set_nifti_affine, write_nifti_with_options, NiftiHeader, NiftiIoError, NiftiWriteOptions};
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use num_complex::Complex32;
+    use array_lib::ArrayDim;
+    use array_lib::fft::fft_centered;
+    use array_lib::io_nifti::read_nifti;
+    use clap::Parser;
+    use crate::{run, Args};
+
+    const ACQP:&str = "##$ACQ_size=( 2 )
+8 6
+##$ACQ_ReceiverSelect=( 2 )
+Yes Yes
+##$NECHOES=1
+##$NR=1
+##$ACQ_word_size=_32_BIT
+##$GO_raw_data_format=GO_32BIT_FLOAT
+##$BYTORDA=little
+##$GO_block_size=continuous
+##END=
+";
+
+    const METHOD:&str = "##$PVM_SpatResol=( 2 )
+0.1 0.2
+##$PVM_SliceThick=0.5
+##END=
+";
+
+    /// writes a 2 coil scan of a rectangular phantom, where the second coil has half the
+    /// sensitivity of the first
+    fn write_scan(dir:&Path, phantom:&[f32], dims:&ArrayDim) {
+        let mut fid = vec![];
+        let mut coils = vec![];
+        for sensitivity in [1., 0.5] {
+            let mut k:Vec<Complex32> = phantom.iter().map(|&p| Complex32::new(p * sensitivity, 0.)).collect();
+            fft_centered(&mut k,dims,&[0,1],false);
+            coils.push(k);
+        }
+        // readouts of all coils are interleaved in the fid
+        for y in 0..dims.shape()[1] {
+            for k in &coils {
+                for x in 0..dims.shape()[0] {
+                    let v = k[dims.calc_addr(&[x,y])];
+                    fid.extend_from_slice(&v.re.to_le_bytes());
+                    fid.extend_from_slice(&v.im.to_le_bytes());
+                }
+            }
+        }
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("fid"),fid).unwrap();
+        std::fs::write(dir.join("acqp"),ACQP).unwrap();
+        std::fs::write(dir.join("method"),METHOD).unwrap();
+    }
+
+    #[test]
+    fn test_phantom_recon() {
+        let dims = ArrayDim::from_shape(&[8,6]);
+        let phantom:Vec<f32> = (0..dims.numel()).map(|i| {
+            let [x, y, ..] = dims.calc_idx(i);
+            if (2..6).contains(&x) && (1..4).contains(&y) { 1. + x as f32 } else { 0. }
+        }).collect();
+        let dir = Path::new("test_fid_to_nifti");
+        write_scan(dir,&phantom,&dims);
+
+        let recon = |extra:&[&str], out:&str| run(Args::parse_from(
+            ["fid-to-nifti", "test_fid_to_nifti", out].iter().chain(extra)
+        ));
+        let rss = recon(&[], "test_fid_to_nifti/rss.nii").unwrap();
+        let per_coil = recon(&["--per-coil"], "test_fid_to_nifti/coils.nii").unwrap();
+        let (x,x_dims,h) = read_nifti::<f32>(&rss);
+        let (c,c_dims,_) = read_nifti::<f32>(&per_coil);
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(x_dims.shape_ns(),&[8,6]);
+        assert_eq!(&h.pixdim[1..4],&[0.1,0.2,0.5]);
+        // the rss of the coil sensitivities scales the phantom
+        let gain = 1.25f32.sqrt();
+        assert!(x.iter().zip(&phantom).all(|(x,p)| (x - p * gain).abs() < 1e-4));
+
+        assert_eq!(c_dims.shape_ns(),&[8,6,1,2]);
+        assert!(c[48..].iter().zip(&phantom).all(|(c,p)| (c - p * 0.5).abs() < 1e-4));
+    }
+}
+
+#[derive(Parser)]
+struct Args {
+    /// Bruker scan directory holding the fid (or rawdata.job0), acqp and method files of a fully
+    /// sampled Cartesian acquisition
+    scan_dir: PathBuf,
+    /// output nifti
+    output: PathBuf,
+
+    /// write the magnitude of k-space instead of reconstructing images
+    #[clap(long)]
+    kspace: bool,
+
+    /// write each coil along the 4th dimension instead of combining coils by root sum of squares
+    #[clap(long)]
+    per_coil: bool,
+}
+
+#[derive(Debug)]
+enum FidToNiftiError {
+    Bruker(BrukerDataError),
+    Nifti(NiftiIoError),
+    IO(PathBuf, std::io::Error),
+    MissingFile(PathBuf),
+}
+
+impl Display for FidToNiftiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FidToNiftiError::Bruker(e) => write!(f, "{}", e),
+            FidToNiftiError::Nifti(e) => write!(f, "{}", e),
+            FidToNiftiError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            FidToNiftiError::MissingFile(path) => write!(f, "{} not found", path.display()),
+        }
+    }
+}
+
+impl From<BrukerDataError> for FidToNiftiError {
+    fn from(err: BrukerDataError) -> Self {
+        FidToNiftiError::Bruker(err)
+    }
+}
+
+impl From<NiftiIoError> for FidToNiftiError {
+    fn from(err: NiftiIoError) -> Self {
+        FidToNiftiError::Nifti(err)
+    }
+}
+
+/// reorders fid data from [samples, receivers, echoes, y, z, repeats] to image order of
+/// [x, y, z, coil, echo, repeat]
+fn to_image_order(fid:&[Complex32], dims:&ArrayDim) -> (Vec<Complex32>, ArrayDim) {
+    // permute only takes axes up to the last non-singleton one, which keep their relative order
+    let n = dims.shape_ns().len();
+    let order:Vec<usize> = [0, 3, 4, 1, 2, 5].into_iter().filter(|&a| a < n).collect();
+    let mut out = vec![Complex32::ZERO; fid.len()];
+    dims.permute(fid, &mut out, &order);
+    let s = dims.shape();
+    (out, ArrayDim::from_shape(&[s[0], s[3], s[4], s[1], s[2], s[5]]))
+}
+
+/// the root sum of squares over the coil axis (3) of an image
+fn rss_coils(x:&[Complex32], dims:&ArrayDim) -> (Vec<f32>, ArrayDim) {
+    let volume = dims.shape()[..3].iter().product::<usize>();
+    let n_coils = dims.shape()[3];
+    let out:Vec<f32> = x.chunks_exact(volume * n_coils).flat_map(|coils| {
+        (0..volume).map(move |i| coils.iter().skip(i).step_by(volume).map(|c| c.norm_sqr()).sum::<f32>().sqrt())
+    }).collect();
+    (out, dims.with_dim(3, 1))
+}
+
+/// the voxel-to-world affine from the voxel size in the method file, or 1 mm voxels if the method
+/// file or PVM_SpatResol is missing
+fn voxel_affine(method:&Path) -> [[f64; 4]; 4] {
+    let resolution = match read_spatial_resolution(method) {
+        Ok(r) => r,
+        Err(e) => {
+            log::warn!("using 1 mm voxels as the spatial resolution could not be read: {}", e);
+            vec![]
+        }
+    };
+    let mut affine = [[0.; 4]; 4];
+    for (axis, row) in affine.iter_mut().enumerate() {
+        row[axis] = resolution.get(axis).copied().unwrap_or(1.);
+    }
+    affine
+}
+
+fn run(args:Args) -> Result<PathBuf, FidToNiftiError> {
+    let dir = &args.scan_dir;
+    let fid_file = [dir.join("fid"), dir.join("rawdata.job0")].into_iter().find(|f| f.is_file())
+        .ok_or_else(|| FidToNiftiError::MissingFile(dir.join("fid")))?;
+    let acqp_file = dir.join("acqp");
+    if !acqp_file.is_file() {
+        return Err(FidToNiftiError::MissingFile(acqp_file));
+    }
+
+    let params = read_fid_params(&acqp_file)?;
+    let dims = params.dims();
+    let enc = resolve_fid_layout(&fid_file, &dims, params.encoding)?;
+    let fid_bytes = std::fs::read(&fid_file).map_err(|e| FidToNiftiError::IO(fid_file.clone(), e))?;
+    let fid = decode_fid(&fid_bytes, enc, &dims);
+    drop(fid_bytes);
+
+    let (mut x, img_dims) = to_image_order(&fid, &dims);
+    drop(fid);
+    if !args.kspace {
+        fft_centered(&mut x, &img_dims, &[0, 1, 2], true);
+    }
+    let (mag, mag_dims) = if args.per_coil {
+        (x.iter().map(|v| v.norm()).collect(), img_dims)
+    } else {
+        rss_coils(&x, &img_dims)
+    };
+
+    let mut h = NiftiHeader::default();
+    set_nifti_affine(&mut h, voxel_affine(&dir.join("method")));
+    let out = write_nifti_with_options(&args.output, &mag, mag_dims, Some(&h), &NiftiWriteOptions::new())?;
+    Ok(out)
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(out) => {
+            println!("wrote {}", out.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
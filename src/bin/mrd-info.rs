@@ -0,0 +1,206 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::Parser;
+use serde::Serialize;
+use array_lib::ArrayDim;
+use array_lib::io_mrd::{inspect_mrd, read_mrd_params, MrdIoError};
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex32;
+    use serde_json::Value;
+    use crate::mrd_info;
+
+    /// writes a complex f32 MRD with the parameter text after the data
+    fn write_test_mrd(path:&str, dims:[usize;6], n_samples:usize, ppr:&str) {
+        let mut bytes = vec![0u8; 512];
+        for (d, o) in dims.iter().zip([0, 4, 8, 12, 152, 156]) {
+            bytes[o..o + 4].copy_from_slice(&(*d as i32).to_le_bytes());
+        }
+        bytes[18..20].copy_from_slice(&0x15i16.to_le_bytes());
+        let data = vec![Complex32::ONE; n_samples];
+        bytes.extend_from_slice(bytemuck::cast_slice(&data));
+        bytes.extend_from_slice(&[0u8;120]);
+        bytes.extend_from_slice(ppr.as_bytes());
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_mrd_info() {
+        let ppr = ":VAR no_samples, 8\r\n:VAR no_views, 4\r\n:VAR te, 2.5\r\n:VAR tr, 100\r\n";
+        write_test_mrd("test_mrd_info.mrd",[8,4,1,2,1,1],64,ppr);
+        let good = mrd_info("test_mrd_info.mrd").unwrap();
+        let j:Value = serde_json::from_str(&serde_json::to_string(&good).unwrap()).unwrap();
+
+        // the views don't match the PPR
+        write_test_mrd("test_mrd_info.mrd",[8,4,1,2,1,1],64,&ppr.replace("no_views, 4","no_views, 5"));
+        let mismatched = mrd_info("test_mrd_info.mrd").unwrap();
+        // the data is cut short
+        write_test_mrd("test_mrd_info.mrd",[8,6,1,2,1,1],64,ppr);
+        let truncated = mrd_info("test_mrd_info.mrd").unwrap();
+        std::fs::remove_file("test_mrd_info.mrd").unwrap();
+
+        assert_eq!(j["shape"],serde_json::json!([8,4,1,2]));
+        assert_eq!(j["dtype"],"complex float32");
+        assert_eq!(j["samples"],8);
+        assert_eq!(j["views"],4);
+        assert_eq!(j["slices"],2);
+        assert_eq!(j["echo_time"],2.5);
+        assert_eq!(j["repetition_time"],100.);
+        assert_eq!(j["expected_samples"],64);
+        assert_eq!(j["expected_bytes"],512 + 64 * 8);
+        assert_eq!(j["issues"],serde_json::json!([]));
+
+        assert_eq!(mismatched.issues,vec![String::from("PPR no_views is 5 but the header size is 4")]);
+        assert_eq!(truncated.expected_samples,96);
+        assert_eq!(truncated.issues.len(),1);
+        assert!(truncated.issues[0].contains("96 elements"));
+    }
+}
+
+#[derive(Parser)]
+struct Args {
+    /// MRD file to describe. Only the header and parameter text are read
+    file: PathBuf,
+
+    /// print the info as json
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Debug)]
+enum MrdInfoError {
+    Mrd(MrdIoError),
+}
+
+impl Display for MrdInfoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MrdInfoError::Mrd(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<MrdIoError> for MrdInfoError {
+    fn from(err: MrdIoError) -> Self {
+        MrdInfoError::Mrd(err)
+    }
+}
+
+/// the layout and key parameters of an MRD along with any inconsistencies found between the
+/// header, the parameter text and the size of the file
+#[derive(Debug, Clone, Serialize)]
+struct MrdInfo {
+    file: PathBuf,
+    /// the array shape with trailing singleton dimensions removed
+    shape: Vec<usize>,
+    dtype: String,
+    complex: bool,
+    samples: usize,
+    views: usize,
+    views_2: usize,
+    slices: usize,
+    echoes: usize,
+    experiments: usize,
+    echo_time: Option<f64>,
+    repetition_time: Option<f64>,
+    fov: Option<f64>,
+    channels: Option<usize>,
+    expected_samples: usize,
+    available_samples: usize,
+    /// the size of the header and data, before any parameter text
+    expected_bytes: u64,
+    file_size: u64,
+    issues: Vec<String>,
+}
+
+impl Display for MrdInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let opt = |x:Option<f64>| x.map(|x| x.to_string()).unwrap_or(String::from("-"));
+        writeln!(f, "file: {}", self.file.display())?;
+        writeln!(f, "shape: {:?}", self.shape)?;
+        writeln!(f, "dtype: {}", self.dtype)?;
+        writeln!(f, "samples: {}", self.samples)?;
+        writeln!(f, "views: {}", self.views)?;
+        writeln!(f, "views_2: {}", self.views_2)?;
+        writeln!(f, "slices: {}", self.slices)?;
+        writeln!(f, "echoes: {}", self.echoes)?;
+        writeln!(f, "experiments: {}", self.experiments)?;
+        if let Some(n) = self.channels {
+            writeln!(f, "channels: {}", n)?;
+        }
+        writeln!(f, "TE: {}", opt(self.echo_time))?;
+        writeln!(f, "TR: {}", opt(self.repetition_time))?;
+        writeln!(f, "FOV: {}", opt(self.fov))?;
+        writeln!(f, "expected samples: {} ({} bytes with the header)", self.expected_samples, self.expected_bytes)?;
+        write!(f, "file size: {} bytes", self.file_size)
+    }
+}
+
+/// describes an MRD without reading the data. The size check is the one applied by the MRD
+/// readers, so a file reported without issues will convert
+fn mrd_info(path:impl AsRef<Path>) -> Result<MrdInfo, MrdInfoError> {
+    let path = path.as_ref();
+    let layout = inspect_mrd(path)?;
+    let params = read_mrd_params(path)?;
+
+    let mut issues = vec![];
+    if let Err(e) = layout.validate() {
+        issues.push(e.to_string());
+    }
+    for (var, ppr, header) in params.dim_mismatches() {
+        issues.push(format!("PPR {} is {} but the header size is {}", var, ppr, header));
+    }
+    if params.ppr().is_empty() && layout.file_size > layout.expected_data_end() {
+        issues.push(format!(
+            "{} bytes follow the data without any parameter text", layout.file_size - layout.expected_data_end()
+        ));
+    }
+
+    Ok(MrdInfo {
+        file: path.to_path_buf(),
+        shape: ArrayDim::from_shape(&layout.dims).shape_ns().to_vec(),
+        dtype: layout.dtype.to_string(),
+        complex: layout.complex,
+        samples: params.samples,
+        views: params.views,
+        views_2: params.views_2,
+        slices: params.slices,
+        echoes: params.echoes,
+        experiments: params.experiments,
+        echo_time: params.echo_time,
+        repetition_time: params.repetition_time,
+        fov: params.fov,
+        channels: params.channels(),
+        expected_samples: layout.expected_samples(),
+        available_samples: layout.available_samples(),
+        expected_bytes: layout.expected_data_end(),
+        file_size: layout.file_size,
+        issues,
+    })
+}
+
+fn run(args:Args) -> Result<(), MrdInfoError> {
+    let info = mrd_info(&args.file)?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&info).expect("mrd info is always serializable"));
+    } else {
+        println!("{}", info);
+        for issue in &info.issues {
+            println!("WARNING: {}", issue);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
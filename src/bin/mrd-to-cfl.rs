@@ -1,7 +1,82 @@
-use std::path::PathBuf;
-use clap::Parser;
-use array_lib::io_cfl::write_cfl;
-use array_lib::io_mrd;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use clap::{Parser, ValueEnum};
+use num_complex::Complex32;
+use array_lib::ArrayDim;
+use array_lib::io_cfl::{cfl_base_name, try_write_cfl, CflChunkWriter, CflIoError};
+use array_lib::io_mrd::{self, read_mrd_subset, MrdIoError, MrdSelection, MrdStreamError, CHANNELS_VAR};
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex32;
+    use array_lib::io_cfl::{cfl_paths, read_cfl};
+    use clap::Parser;
+    use crate::{run, Args, MrdToCflError};
+
+    /// writes a complex f32 MRD with the given dimensions and PPR text
+    fn write_test_mrd(path:&str, dims:[usize;6], data:&[Complex32], ppr:&str) {
+        let mut bytes = vec![0u8; 512];
+        for (d, o) in dims.iter().zip([0, 4, 8, 12, 152, 156]) {
+            bytes[o..o + 4].copy_from_slice(&(*d as i32).to_le_bytes());
+        }
+        bytes[18..20].copy_from_slice(&0x15i16.to_le_bytes());
+        bytes.extend_from_slice(bytemuck::cast_slice(data));
+        bytes.extend_from_slice(&[0u8;120]);
+        bytes.extend_from_slice(ppr.as_bytes());
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    fn remove_cfl(base:&str) {
+        let (hdr, cfl) = cfl_paths(base);
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+    }
+
+    #[test]
+    fn test_channel_extraction() {
+        // 4 channels along the slice dimension
+        let dims = [4, 3, 1, 4, 1, 1];
+        let data:Vec<_> = (0..48).map(|i| Complex32::new(i as f32, -(i as f32))).collect();
+        write_test_mrd("test_mrd_to_cfl_ch.mrd", dims, &data, ":VAR no_receivers, 4\r\n");
+
+        let convert = |extra:&[&str], out:&str| run(Args::parse_from(
+            ["mrd-to-cfl", "test_mrd_to_cfl_ch.mrd", out].iter().chain(extra)
+        ));
+        convert(&[], "test_mrd_to_cfl_full").unwrap();
+        convert(&["--channels", "2"], "test_mrd_to_cfl_ch2").unwrap();
+        convert(&["--split-channels", "--channels", "1-2"], "test_mrd_to_cfl_split").unwrap();
+        convert(&["--combine", "rss"], "test_mrd_to_cfl_rss").unwrap();
+        let bad = convert(&["--channels", "0,4"], "test_mrd_to_cfl_bad");
+
+        let (full, full_dims) = read_cfl("test_mrd_to_cfl_full");
+        let (ch2, ch2_dims) = read_cfl("test_mrd_to_cfl_ch2");
+        let (split2, _) = read_cfl("test_mrd_to_cfl_split_2");
+        let (rss, rss_dims) = read_cfl("test_mrd_to_cfl_rss");
+        std::fs::remove_file("test_mrd_to_cfl_ch.mrd").unwrap();
+        for base in ["test_mrd_to_cfl_full", "test_mrd_to_cfl_ch2", "test_mrd_to_cfl_split_1", "test_mrd_to_cfl_split_2", "test_mrd_to_cfl_rss"] {
+            remove_cfl(base);
+        }
+
+        let (expected, expected_dims) = full_dims.copy_region(&full, &[0, 0, 0, 2], &[4, 3, 1, 1]);
+        assert_eq!(ch2, expected);
+        assert_eq!(ch2_dims.shape(), expected_dims.shape());
+        assert_eq!(split2, expected);
+
+        assert_eq!(rss_dims.shape_ns(), &[4, 3]);
+        let x = (0..4).map(|c| full[5 + 12 * c].norm_sqr()).sum::<f32>().sqrt();
+        assert!((rss[5].re - x).abs() < 1e-3);
+
+        assert!(matches!(bad, Err(MrdToCflError::ChannelOutOfRange{index: 4, available: 4})));
+    }
+}
+
+/// how to combine channels into a single output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Combine {
+    /// root sum of squares of the channel magnitudes
+    Rss,
+}
 
 #[derive(Parser)]
 struct Args {
@@ -9,10 +84,171 @@ struct Args {
     mrd_file:PathBuf,
     /// output cfl file
     cfl_file:PathBuf,
+
+    /// channels to convert as a comma separated list of indices and inclusive ranges, such as
+    /// "0,2-3". All channels are converted by default
+    #[clap(long)]
+    channels:Option<String>,
+
+    /// write each channel to its own cfl, suffixed with the channel index
+    #[clap(long, conflicts_with = "combine")]
+    split_channels:bool,
+
+    /// combine the selected channels into a single image
+    #[clap(long, value_enum)]
+    combine:Option<Combine>,
+}
+
+#[derive(Debug)]
+enum MrdToCflError {
+    Mrd(MrdIoError),
+    Cfl(CflIoError),
+    Stream(MrdStreamError<CflIoError>),
+    NoChannelAxis(PathBuf),
+    InvalidSelection(String),
+    ChannelOutOfRange{index: usize, available: usize},
+}
+
+impl Display for MrdToCflError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MrdToCflError::Mrd(e) => write!(f, "{}", e),
+            MrdToCflError::Cfl(e) => write!(f, "{}", e),
+            MrdToCflError::Stream(e) => write!(f, "{}", e),
+            MrdToCflError::NoChannelAxis(path) => write!(
+                f, "cannot locate the channel dimension of {}. Expected a {} parameter matching the size of a dimension",
+                path.display(), CHANNELS_VAR
+            ),
+            MrdToCflError::InvalidSelection(s) => write!(f, "invalid channel selection {}", s),
+            MrdToCflError::ChannelOutOfRange{index, available} => write!(
+                f, "channel {} is out of range. {} channels are available", index, available
+            ),
+        }
+    }
 }
 
-fn main() {
-    let args = Args::parse();
-    let (data,dims,_) = io_mrd::read_mrd(args.mrd_file);
-    write_cfl(args.cfl_file,&data,dims);
-}
\ No newline at end of file
+impl From<MrdIoError> for MrdToCflError {
+    fn from(err: MrdIoError) -> Self {
+        MrdToCflError::Mrd(err)
+    }
+}
+
+impl From<CflIoError> for MrdToCflError {
+    fn from(err: CflIoError) -> Self {
+        MrdToCflError::Cfl(err)
+    }
+}
+
+impl From<MrdStreamError<CflIoError>> for MrdToCflError {
+    fn from(err: MrdStreamError<CflIoError>) -> Self {
+        MrdToCflError::Stream(err)
+    }
+}
+
+/// parses a comma separated list of channel indices and inclusive ranges, such as "0,2-3"
+fn parse_channels(spec:&str, available:usize) -> Result<Vec<usize>, MrdToCflError> {
+    let invalid = || MrdToCflError::InvalidSelection(spec.to_string());
+    let mut indices = vec![];
+    for part in spec.split(',').map(|p| p.trim()) {
+        let (start, end) = match part.split_once('-') {
+            Some((a, b)) => (a.trim().parse().map_err(|_| invalid())?, b.trim().parse().map_err(|_| invalid())?),
+            None => {
+                let i = part.parse().map_err(|_| invalid())?;
+                (i, i)
+            }
+        };
+        if end < start {
+            return Err(invalid());
+        }
+        if end >= available {
+            return Err(MrdToCflError::ChannelOutOfRange{index: end, available});
+        }
+        indices.extend(start..=end);
+    }
+    Ok(indices)
+}
+
+/// the cfl base name of a single channel, with any .cfl or .hdr extension dropped
+fn channel_name(base:&Path, channel:usize) -> PathBuf {
+    let mut name = cfl_base_name(base).into_os_string();
+    name.push(format!("_{}", channel));
+    PathBuf::from(name)
+}
+
+/// converts the full mrd. The mrd is streamed one frame at a time, where a frame spans all but
+/// the last non-singleton dimension, so the chunked writer reproduces the full dimensions on
+/// finish
+fn convert(mrd_file:&Path, cfl_file:&Path, shape:&[usize; 6]) -> Result<(), MrdToCflError> {
+    let dims = ArrayDim::from_shape(shape);
+    let last = dims.shape_ns().len().saturating_sub(1);
+    let frame_dims = ArrayDim::from_shape(&dims.shape()[..last]);
+
+    let mut w = CflChunkWriter::create_along(cfl_file, frame_dims, last)?;
+    io_mrd::stream_mrd(mrd_file, frame_dims.numel(), |frame, _| w.append_frame(frame))?;
+    w.finish()?;
+    Ok(())
+}
+
+fn run(args:Args) -> Result<(), MrdToCflError> {
+    let params = io_mrd::read_mrd_params(&args.mrd_file)?;
+    let shape = params.shape();
+    if args.channels.is_none() && !args.split_channels && args.combine.is_none() {
+        return convert(&args.mrd_file, &args.cfl_file, &shape);
+    }
+
+    let axis = params.channel_axis().ok_or_else(|| MrdToCflError::NoChannelAxis(args.mrd_file.clone()))?;
+    let available = shape[axis];
+    let channels = match &args.channels {
+        Some(spec) => parse_channels(spec, available)?,
+        None => (0..available).collect(),
+    };
+    // each channel is read on its own as the hyperslab at its index along the channel axis
+    let read_channel = |c:usize| read_mrd_subset(&args.mrd_file, &MrdSelection::new().axis(axis, c..c + 1))
+        .map(|(data, dims, _)| (data, dims));
+
+    if args.split_channels {
+        for &c in &channels {
+            let (data, dims) = read_channel(c)?;
+            let out = channel_name(&args.cfl_file, c);
+            try_write_cfl(&out, &data, dims)?;
+            println!("wrote {}", out.display());
+        }
+    } else if let Some(Combine::Rss) = args.combine {
+        let mut sum_sq = vec![];
+        let mut out_dims = ArrayDim::from_shape(&shape).with_dim(axis, 1);
+        for &c in &channels {
+            let (data, dims) = read_channel(c)?;
+            sum_sq.resize(data.len(), 0f32);
+            sum_sq.iter_mut().zip(&data).for_each(|(s, x)| *s += x.norm_sqr());
+            out_dims = dims;
+        }
+        let rss:Vec<_> = sum_sq.into_iter().map(|s| Complex32::new(s.sqrt(), 0.)).collect();
+        try_write_cfl(&args.cfl_file, &rss, out_dims)?;
+    } else {
+        let out_dims = ArrayDim::from_shape(&shape).with_dim(axis, channels.len());
+        let mut out = vec![Complex32::ZERO; out_dims.numel()];
+        for (i, &c) in channels.iter().enumerate() {
+            let (data, dims) = read_channel(c)?;
+            let mut offset = [0; 6];
+            offset[axis] = i;
+            let mut n = 0;
+            for (addr, len) in out_dims.region_runs(&offset, &dims.shape()[..6]) {
+                out[addr..addr + len].copy_from_slice(&data[n..n + len]);
+                n += len;
+            }
+        }
+        try_write_cfl(&args.cfl_file, &out, out_dims)?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+use clap::{Parser, ValueEnum};
+use array_lib::convert::{ConvertError, NiftiComponent, NiftiToCflOptions};
+use array_lib::io::{read_array, write_array, ArrayWriteOptions};
+use array_lib::io_cfl::{cfl_base_name, cfl_paths};
+
+/// the part of the nifti to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Take {
+    Real,
+    Magnitude,
+}
+
+#[derive(Parser)]
+struct Args {
+    /// nifti file to read (.nii or .nii.gz)
+    nifti_file: PathBuf,
+    /// output cfl file
+    cfl_file: PathBuf,
+
+    /// remove singleton dimensions
+    #[clap(long)]
+    squeeze: bool,
+
+    /// keep only the real part or magnitude, for data that is already magnitude-like. By default
+    /// complex data is kept as is
+    #[clap(long, value_enum)]
+    take: Option<Take>,
+
+    /// permutes the axes as a comma separated list of input axes, such as 2,0,1 to move the third
+    /// axis first. Applied before squeezing
+    #[clap(long)]
+    dim_order: Option<String>,
+}
+
+fn run(args:Args) -> Result<(), ConvertError> {
+    let mut opts = NiftiToCflOptions::new().squeeze(args.squeeze);
+    match args.take {
+        Some(Take::Real) => opts = opts.component(NiftiComponent::Real),
+        Some(Take::Magnitude) => opts = opts.component(NiftiComponent::Magnitude),
+        None => {}
+    }
+    if let Some(spec) = &args.dim_order {
+        let order = spec.split(',').map(|a| a.trim().parse::<usize>()).collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| ConvertError::InvalidDimOrder(spec.to_string()))?;
+        opts = opts.dim_order(&order);
+    }
+    let array = opts.apply(&read_array(&args.nifti_file)?)?;
+    // written by its .cfl path so any base name, including one with dots, is taken as a cfl
+    let (_, cfl) = cfl_paths(cfl_base_name(&args.cfl_file));
+    write_array(cfl, &array, &ArrayWriteOptions::new())?;
+    println!("dims = {:?}", array.dims().shape_ns());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
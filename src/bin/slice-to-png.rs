@@ -0,0 +1,176 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use clap::{Parser, ValueEnum};
+use array_lib::info::{read_array, ArrayInfoError};
+use array_lib::io_png::{write_png_montage, write_png_slice_with_options, PngIoError, PngSliceOptions};
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex32;
+    use array_lib::ArrayDim;
+    use array_lib::io_cfl::{cfl_paths, write_cfl};
+    use array_lib::io_png::read_png_gray;
+    use clap::Parser;
+    use crate::{run, Args, SliceToPngError};
+
+    #[test]
+    fn test_montage() {
+        // 4 slices of 4x3 whose magnitude is the element index
+        let dims = ArrayDim::from_shape(&[4,3,4]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::from_polar(i as f32, 1.)).collect();
+        write_cfl("test_slice_to_png",&x,dims);
+
+        let to_png = |extra:&[&str]| run(Args::parse_from(
+            ["slice-to-png", "test_slice_to_png", "test_slice_to_png.png"].iter().chain(extra)
+        ));
+        to_png(&["--montage", "--cols", "3", "--window", "0,255"]).unwrap();
+        let (y,y_dims) = read_png_gray("test_slice_to_png.png").unwrap();
+        let bad_index = to_png(&["--index", "4"]);
+        let bad_axis = to_png(&["--axis", "3", "--index", "0"]);
+
+        let (hdr,cfl) = cfl_paths("test_slice_to_png");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        std::fs::remove_file("test_slice_to_png.png").unwrap();
+
+        // 3 columns and 2 rows of tiles
+        assert_eq!(y_dims.shape_ns(),&[12,6]);
+        let px = |col:usize, row:usize| y[row * 12 + col];
+        assert_eq!(px(1,0),1);
+        assert_eq!(px(4,0),12);
+        assert_eq!(px(3,2),11);
+        // the last row of slice 3, with the unused tiles to its right left black
+        assert_eq!([px(0,5),px(5,5)],[44,0]);
+
+        assert!(matches!(bad_index,Err(SliceToPngError::IndexOutOfRange{index: 4, axis: 2, size: 4})));
+        assert!(matches!(bad_axis,Err(SliceToPngError::Png(..))));
+    }
+}
+
+/// the scalar written for complex inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum What {
+    Magnitude,
+    Phase,
+}
+
+#[derive(Parser)]
+struct Args {
+    /// input array. The format is taken from the extension: a cfl base name or .cfl/.hdr, .nii,
+    /// .nii.gz, .nrrd, .nhdr, .mrd or .npy
+    input: PathBuf,
+    /// output png
+    output: PathBuf,
+
+    /// the axis to slice along
+    #[clap(long, default_value_t = 2)]
+    axis: usize,
+
+    /// the index of the slice. Defaults to the middle slice
+    #[clap(long, conflicts_with = "montage")]
+    index: Option<usize>,
+
+    /// tile every slice along the axis into a single image
+    #[clap(long)]
+    montage: bool,
+
+    /// the number of columns of the montage. Defaults to a square grid
+    #[clap(long, requires = "montage")]
+    cols: Option<usize>,
+
+    /// the values mapped to black and white, as lo,hi
+    #[clap(long, conflicts_with = "auto_window")]
+    window: Option<String>,
+
+    /// window from the 2nd to the 98th percentile. This is the default without --window
+    #[clap(long)]
+    auto_window: bool,
+
+    /// the scalar to write for complex inputs. Real inputs are written as is
+    #[clap(long, value_enum, default_value_t = What::Magnitude)]
+    what: What,
+}
+
+#[derive(Debug)]
+enum SliceToPngError {
+    Read(ArrayInfoError),
+    Png(PngIoError),
+    InvalidWindow(String),
+    IndexOutOfRange{index: usize, axis: usize, size: usize},
+}
+
+impl Display for SliceToPngError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SliceToPngError::Read(e) => write!(f, "{}", e),
+            SliceToPngError::Png(e) => write!(f, "{}", e),
+            SliceToPngError::InvalidWindow(s) => write!(f, "invalid window {}. Expected lo,hi with lo < hi", s),
+            SliceToPngError::IndexOutOfRange{index, axis, size} => write!(
+                f, "index {} is out of range for axis {} of size {}", index, axis, size
+            ),
+        }
+    }
+}
+
+impl From<ArrayInfoError> for SliceToPngError {
+    fn from(err: ArrayInfoError) -> Self {
+        SliceToPngError::Read(err)
+    }
+}
+
+impl From<PngIoError> for SliceToPngError {
+    fn from(err: PngIoError) -> Self {
+        SliceToPngError::Png(err)
+    }
+}
+
+/// parses a window given as lo,hi
+fn parse_window(s:&str) -> Result<(f32, f32), SliceToPngError> {
+    let invalid = || SliceToPngError::InvalidWindow(s.to_string());
+    let (lo, hi) = s.split_once(',').ok_or_else(invalid)?;
+    let lo:f32 = lo.trim().parse().map_err(|_| invalid())?;
+    let hi:f32 = hi.trim().parse().map_err(|_| invalid())?;
+    if lo < hi { Ok((lo, hi)) } else { Err(invalid()) }
+}
+
+fn run(args:Args) -> Result<(), SliceToPngError> {
+    let mut opts = PngSliceOptions::new();
+    if let Some(window) = &args.window {
+        let (lo, hi) = parse_window(window)?;
+        opts = opts.window(lo, hi);
+    }
+
+    let (x, dims, complex) = read_array(&args.input)?;
+    let values:Vec<f32> = x.iter().map(|v| match (complex, args.what) {
+        (false, _) => v.re as f32,
+        (true, What::Magnitude) => v.norm() as f32,
+        (true, What::Phase) => v.arg() as f32,
+    }).collect();
+
+    // axes past the last are singleton, so only the middle slice default needs the size
+    let size = dims.shape().get(args.axis).copied().unwrap_or(1);
+    if args.montage {
+        let cols = args.cols.unwrap_or_else(|| (size as f64).sqrt().ceil() as usize);
+        write_png_montage(&args.output, &values, &dims, args.axis, cols, &opts)?;
+    } else {
+        let index = args.index.unwrap_or(size / 2);
+        if index >= size {
+            return Err(SliceToPngError::IndexOutOfRange{index, axis: args.axis, size});
+        }
+        write_png_slice_with_options(&args.output, &values, &dims, args.axis, index, &opts)?;
+    }
+    println!("wrote {}", args.output.display());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    array_lib::logging::init_stderr(log::LevelFilter::Warn);
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
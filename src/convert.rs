@@ -0,0 +1,429 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use num_complex::Complex32;
+use crate::ArrayDim;
+use crate::io_cfl::{try_read_cfl, try_write_cfl, CflIoError};
+use crate::io::{ArrayData, ArrayMeta, IoError};
+use crate::io_nifti::{try_read_nifti_complex, set_nifti_affine, write_nifti_scaled, write_nifti_with_options, NiftiHeader, NiftiIoError, NiftiWriteOptions};
+pub use crate::io_nifti::NiftiDtype;
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex32;
+    use crate::ArrayDim;
+    use crate::convert::{cfl_to_nifti, nifti_to_cfl, nifti_to_cfl_with_options, CflToNiftiOptions, ConvertError, NiftiComponent, NiftiDtype, NiftiToCflOptions};
+    use crate::io_cfl::{cfl_paths, read_cfl, write_cfl, CflIoError};
+    use crate::io_nifti::{nifti_affine, read_nifti, read_nifti_complex, write_nifti};
+
+    fn test_data(dims:&ArrayDim) -> Vec<Complex32> {
+        (0..dims.numel()).map(|i| Complex32::new(i as f32,-2. * i as f32)).collect()
+    }
+
+    fn remove_cfl(base:&str) {
+        let (hdr,cfl) = cfl_paths(base);
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+    }
+
+    #[test]
+    fn test_cfl_to_nifti() {
+        let dims = ArrayDim::from_shape(&[6,5,4,2]);
+        let x = test_data(&dims);
+        write_cfl("test_convert_c2n",&x,dims);
+
+        let opts = CflToNiftiOptions::new().component(NiftiComponent::Complex).spacing([0.5,0.5,2.]);
+        let out = cfl_to_nifti("test_convert_c2n","test_convert_c2n",&opts).unwrap();
+        let (y,y_dims,h) = read_nifti_complex::<f32>(&out);
+        assert_eq!(y,x);
+        assert_eq!(y_dims.shape(),dims.shape());
+        assert_eq!(nifti_affine(&h)[2][2],2.);
+
+        let out = cfl_to_nifti("test_convert_c2n","test_convert_c2n",&CflToNiftiOptions::new()).unwrap();
+        let (mag,..) = read_nifti::<f32>(&out);
+        assert_eq!(mag,x.iter().map(|x| x.norm()).collect::<Vec<f32>>());
+
+        let out = cfl_to_nifti("test_convert_c2n","test_convert_c2n",&CflToNiftiOptions::new().component(NiftiComponent::Imag)).unwrap();
+        let (im,..) = read_nifti::<f32>(&out);
+        assert_eq!(im,x.iter().map(|x| x.im).collect::<Vec<f32>>());
+
+        std::fs::remove_file(out).unwrap();
+        remove_cfl("test_convert_c2n");
+    }
+
+    #[test]
+    fn test_cfl_to_nifti_options() {
+        let dims = ArrayDim::from_shape(&[6,5,4,3]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::from_polar(i as f32, i as f32 * 0.1)).collect();
+        write_cfl("test_convert_c2n_opts",&x,dims);
+
+        // the phase of the second position along axis 3
+        let opts = CflToNiftiOptions::new().component(NiftiComponent::Phase).frame(3,1);
+        let out = cfl_to_nifti("test_convert_c2n_opts","test_convert_c2n_opts",&opts).unwrap();
+        let (phase,phase_dims,_) = read_nifti::<f32>(&out);
+        assert_eq!(phase_dims.shape_ns(),&[6,5,4]);
+        let frame_offset = dims.calc_addr(&[0,0,0,1]);
+        assert_eq!(phase,x[frame_offset..frame_offset + 120].iter().map(|x| x.arg()).collect::<Vec<f32>>());
+
+        // magnitude as int16, scaled to the full range of the type
+        let opts = CflToNiftiOptions::new().dtype(NiftiDtype::Int16).spacing([0.5,0.5,1.]);
+        let out = cfl_to_nifti("test_convert_c2n_opts","test_convert_c2n_opts",&opts).unwrap();
+        let (mag,mag_dims,h) = read_nifti::<f32>(&out);
+        assert_eq!(mag_dims.shape(),dims.shape());
+        assert_eq!(h.datatype,4);
+        let max = x.iter().map(|x| x.norm()).fold(0.,f32::max);
+        assert!((h.scl_slope - max / i16::MAX as f32).abs() < 1e-6);
+        assert!(mag.iter().zip(&x).all(|(m,x)| (m - x.norm()).abs() <= h.scl_slope));
+
+        // signed phase as uint16 is offset to its minimum
+        let opts = CflToNiftiOptions::new().component(NiftiComponent::Phase).dtype(NiftiDtype::Uint16);
+        let out = cfl_to_nifti("test_convert_c2n_opts","test_convert_c2n_opts",&opts).unwrap();
+        let (phase,_,h) = read_nifti::<f32>(&out);
+        assert_eq!(h.datatype,512);
+        assert!(h.scl_inter < 0.);
+        assert!(phase.iter().zip(&x).all(|(p,x)| (p - x.arg()).abs() <= h.scl_slope));
+
+        // dims above 3 are collapsed into nifti dim 4
+        let dims5 = ArrayDim::from_shape(&[4,3,2,2,3]);
+        write_cfl("test_convert_c2n_opts",&test_data(&dims5),dims5);
+        let out = cfl_to_nifti("test_convert_c2n_opts","test_convert_c2n_opts",&CflToNiftiOptions::new()).unwrap();
+        let (_,read_dims,_) = read_nifti::<f32>(&out);
+        assert_eq!(read_dims.shape_ns(),&[4,3,2,6]);
+
+        let r = cfl_to_nifti("test_convert_c2n_opts","test_convert_c2n_opts",&CflToNiftiOptions::new().frame(4,3));
+        assert!(matches!(r,Err(ConvertError::InvalidFrame(..))));
+        let r = cfl_to_nifti("test_convert_c2n_opts","test_convert_c2n_opts",&CflToNiftiOptions::new().component(NiftiComponent::Complex).dtype(NiftiDtype::Int16));
+        assert!(matches!(r,Err(ConvertError::Unsupported(..))));
+
+        std::fs::remove_file(out).unwrap();
+        remove_cfl("test_convert_c2n_opts");
+    }
+
+    #[test]
+    fn test_nifti_to_cfl() {
+        let dims = ArrayDim::from_shape(&[6,5,4,2]);
+        let x = test_data(&dims);
+        write_nifti("test_convert_n2c",&x,dims);
+        let y_dims = nifti_to_cfl("test_convert_n2c.nii","test_convert_n2c").unwrap();
+        let (y,read_dims) = read_cfl("test_convert_n2c");
+        assert_eq!(y,x);
+        assert_eq!(y_dims.shape(),dims.shape());
+        assert_eq!(read_dims.shape(),dims.shape());
+
+        // real nifti data is written with zero imaginary parts
+        let re:Vec<f32> = x.iter().map(|x| x.re).collect();
+        write_nifti("test_convert_n2c",&re,dims);
+        nifti_to_cfl("test_convert_n2c.nii","test_convert_n2c").unwrap();
+        let (y,_) = read_cfl("test_convert_n2c");
+        assert!(y.iter().zip(re.iter()).all(|(y,r)| y.re == *r && y.im == 0.));
+
+        std::fs::remove_file("test_convert_n2c.nii").unwrap();
+        remove_cfl("test_convert_n2c");
+
+        assert!(matches!(nifti_to_cfl("does_not_exist.nii","test_convert_n2c"),Err(ConvertError::Nifti(..))));
+        assert!(matches!(cfl_to_nifti("does_not_exist","test_convert_n2c",&CflToNiftiOptions::new()),Err(ConvertError::Cfl(CflIoError::MissingHeader(..)))));
+    }
+
+    #[test]
+    fn test_nifti_to_cfl_options() {
+        let dims = ArrayDim::from_shape(&[6,1,4,2]);
+        let x = test_data(&dims);
+        write_nifti("test_convert_n2c_opts",&x,dims);
+
+        // magnitude with the third axis moved first, then squeezed to [4,6,2]
+        let opts = NiftiToCflOptions::new().component(NiftiComponent::Magnitude).dim_order(&[2,0,1,3]).squeeze(true);
+        let y_dims = nifti_to_cfl_with_options("test_convert_n2c_opts.nii","test_convert_n2c_opts",&opts).unwrap();
+        let (y,read_dims) = read_cfl("test_convert_n2c_opts");
+        assert_eq!(y_dims.shape_ns(),&[4,6,2]);
+        assert_eq!(read_dims.shape(),y_dims.shape());
+        for (i,y) in y.iter().enumerate() {
+            let [s,r,t,..] = y_dims.calc_idx(i);
+            assert_eq!(*y,Complex32::new(x[dims.calc_addr(&[r,0,s,t])].norm(),0.));
+        }
+
+        let opts = NiftiToCflOptions::new().component(NiftiComponent::Real);
+        nifti_to_cfl_with_options("test_convert_n2c_opts.nii","test_convert_n2c_opts",&opts).unwrap();
+        let (y,_) = read_cfl("test_convert_n2c_opts");
+        assert!(y.iter().zip(x.iter()).all(|(y,x)| y.re == x.re && y.im == 0.));
+
+        let opts = NiftiToCflOptions::new().dim_order(&[1,0]);
+        let r = nifti_to_cfl_with_options("test_convert_n2c_opts.nii","test_convert_n2c_opts",&opts);
+        std::fs::remove_file("test_convert_n2c_opts.nii").unwrap();
+        remove_cfl("test_convert_n2c_opts");
+        assert!(matches!(r,Err(ConvertError::InvalidDimOrder(..))));
+    }
+
+}
+
+#[derive(Debug)]
+pub enum ConvertError {
+    Cfl(CflIoError),
+    Nifti(NiftiIoError),
+    Io(IoError),
+    InvalidDimOrder(String),
+    InvalidFrame(String),
+    Unsupported(String),
+}
+
+impl Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::Cfl(e) => write!(f, "{}", e),
+            ConvertError::Nifti(e) => write!(f, "{}", e),
+            ConvertError::Io(e) => write!(f, "{}", e),
+            ConvertError::InvalidDimOrder(msg) => write!(f, "invalid dimension order: {}", msg),
+            ConvertError::InvalidFrame(msg) => write!(f, "invalid frame: {}", msg),
+            ConvertError::Unsupported(msg) => write!(f, "unsupported conversion: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+impl From<CflIoError> for ConvertError {
+    fn from(err: CflIoError) -> Self {
+        ConvertError::Cfl(err)
+    }
+}
+
+impl From<NiftiIoError> for ConvertError {
+    fn from(err: NiftiIoError) -> Self {
+        ConvertError::Nifti(err)
+    }
+}
+
+impl From<IoError> for ConvertError {
+    fn from(err: IoError) -> Self {
+        ConvertError::Io(err)
+    }
+}
+
+/// the part of complex data written to a nifti
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NiftiComponent {
+    #[default]
+    Magnitude,
+    /// the angle in radians
+    Phase,
+    Real,
+    Imag,
+    Complex,
+}
+
+/// options for converting cfl to nifti
+#[derive(Clone, Debug, Default)]
+pub struct CflToNiftiOptions {
+    component: NiftiComponent,
+    affine: Option<[[f64;4];4]>,
+    dtype: NiftiDtype,
+    frame: Option<(usize, usize)>,
+    write_opts: NiftiWriteOptions,
+}
+
+impl CflToNiftiOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn component(mut self, component:NiftiComponent) -> Self {
+        self.component = component;
+        self
+    }
+
+    /// sets the voxel-to-world affine of the output
+    pub fn affine(mut self, affine:[[f64;4];4]) -> Self {
+        self.affine = Some(affine);
+        self
+    }
+
+    /// sets the voxel size of the output with no rotation or translation
+    pub fn spacing(self, spacing:[f64;3]) -> Self {
+        self.affine([
+            [spacing[0], 0., 0., 0.],
+            [0., spacing[1], 0., 0.],
+            [0., 0., spacing[2], 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    /// the stored type of real components. Complex data is always stored as float32 pairs
+    pub fn dtype(mut self, dtype:NiftiDtype) -> Self {
+        self.dtype = dtype;
+        self
+    }
+
+    /// writes a single position along an axis, which is removed from the output
+    pub fn frame(mut self, axis:usize, index:usize) -> Self {
+        self.frame = Some((axis, index));
+        self
+    }
+
+    pub fn write_opts(mut self, write_opts:NiftiWriteOptions) -> Self {
+        self.write_opts = write_opts;
+        self
+    }
+
+    /// takes the frame and component of an array, returning real data unless the complex
+    /// component is kept. The affine, if set, is carried as a nifti header in the metadata, and
+    /// the stored type is left to the writer
+    pub fn apply(&self, array:&ArrayData) -> Result<ArrayData, ConvertError> {
+        let data = array.to_complex();
+        let (data, dims) = match self.frame {
+            Some((axis, index)) => select_frame(&data, array.dims(), axis, index)?,
+            None => (data, *array.dims()),
+        };
+        let meta = self.affine.map(|affine| {
+            let mut h = NiftiHeader::default();
+            set_nifti_affine(&mut h, affine);
+            ArrayMeta::Nifti(Box::new(h))
+        });
+        let real = |f:fn(&Complex32) -> f32| data.iter().map(f).collect::<Vec<f32>>();
+        let data = match self.component {
+            NiftiComponent::Magnitude => real(|x| x.norm()),
+            NiftiComponent::Phase => real(|x| x.arg()),
+            NiftiComponent::Real => real(|x| x.re),
+            NiftiComponent::Imag => real(|x| x.im),
+            NiftiComponent::Complex => return Ok(ArrayData::Complex{data, dims, meta}),
+        };
+        Ok(ArrayData::Real{data, dims, meta})
+    }
+}
+
+/// takes a single position along an axis of an array, dropping the axis
+fn select_frame(data:&[Complex32], dims:&ArrayDim, axis:usize, index:usize) -> Result<(Vec<Complex32>, ArrayDim), ConvertError> {
+    if axis >= crate::N_DIMS {
+        return Err(ConvertError::InvalidFrame(format!("axis {} is out of range", axis)));
+    }
+    let mut offset = [0; crate::N_DIMS];
+    offset[axis] = index;
+    let mut size = *dims.shape();
+    size[axis] = 1;
+    dims.region_dims(&offset, &size).map_err(ConvertError::InvalidFrame)?;
+    let (frame, _) = dims.copy_region(data, &offset, &size);
+    let mut shape = dims.shape().to_vec();
+    shape.remove(axis);
+    Ok((frame, ArrayDim::from_shape(&shape)))
+}
+
+/// converts a cfl to a nifti, writing the component given in the options. Dimensions above 3 are
+/// collapsed into nifti dimension 4, as with write_nifti. Returns the path of the written nifti
+pub fn cfl_to_nifti(cfl_file_base_name:impl AsRef<Path>, nifti_file:impl AsRef<Path>, opts:&CflToNiftiOptions) -> Result<PathBuf, ConvertError> {
+    let (data, dims) = try_read_cfl(cfl_file_base_name)?;
+    let out = match opts.apply(&ArrayData::Complex{data, dims, meta: None})? {
+        ArrayData::Complex{data, dims, meta} if opts.dtype == NiftiDtype::Float32 => {
+            write_nifti_with_options(nifti_file, &data, dims, nifti_header(&meta), &opts.write_opts)
+        }
+        ArrayData::Complex{..} => {
+            return Err(ConvertError::Unsupported(format!("complex data can't be stored as {:?}", opts.dtype)));
+        }
+        ArrayData::Real{data, dims, meta} => {
+            write_nifti_scaled(nifti_file, &data, dims, nifti_header(&meta), opts.dtype, &opts.write_opts)
+        }
+    }?;
+    Ok(out)
+}
+
+/// the nifti header carried in the metadata of an array
+fn nifti_header(meta:&Option<ArrayMeta>) -> Option<&NiftiHeader> {
+    match meta {
+        Some(ArrayMeta::Nifti(h)) => Some(h.as_ref()),
+        _=> None,
+    }
+}
+
+/// options for converting nifti to cfl
+#[derive(Clone, Debug)]
+pub struct NiftiToCflOptions {
+    component: NiftiComponent,
+    dim_order: Option<Vec<usize>>,
+    squeeze: bool,
+}
+
+impl Default for NiftiToCflOptions {
+    fn default() -> Self {
+        NiftiToCflOptions {
+            component: NiftiComponent::Complex,
+            dim_order: None,
+            squeeze: false,
+        }
+    }
+}
+
+impl NiftiToCflOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the part of the nifti data to keep. Magnitude and real parts are written with zero
+    /// imaginary parts. Defaults to the complex data
+    pub fn component(mut self, component:NiftiComponent) -> Self {
+        self.component = component;
+        self
+    }
+
+    /// permutes the axes of the nifti, as with ArrayDim::permute
+    pub fn dim_order(mut self, order:&[usize]) -> Self {
+        self.dim_order = Some(order.to_vec());
+        self
+    }
+
+    /// removes singleton dimensions after any permutation
+    pub fn squeeze(mut self, squeeze:bool) -> Self {
+        self.squeeze = squeeze;
+        self
+    }
+
+    /// keeps the component of an array, then permutes and squeezes its axes. The result is
+    /// complex, with any metadata dropped
+    pub fn apply(&self, array:&ArrayData) -> Result<ArrayData, ConvertError> {
+        let mut data = array.to_complex();
+        let dims = *array.dims();
+        match self.component {
+            NiftiComponent::Magnitude => data.iter_mut().for_each(|x| *x = Complex32::new(x.norm(), 0.)),
+            NiftiComponent::Phase => data.iter_mut().for_each(|x| *x = Complex32::new(x.arg(), 0.)),
+            NiftiComponent::Real => data.iter_mut().for_each(|x| x.im = 0.),
+            NiftiComponent::Imag => data.iter_mut().for_each(|x| x.re = 0.),
+            NiftiComponent::Complex => {}
+        }
+
+        let (data, dims) = match &self.dim_order {
+            Some(order) => {
+                let ndim = dims.shape_ns().len();
+                let mut sorted = order.clone();
+                sorted.sort_unstable();
+                if sorted != (0..ndim).collect::<Vec<usize>>() {
+                    return Err(ConvertError::InvalidDimOrder(format!(
+                        "{:?} is not a permutation of the {} axes of shape {:?}", order, ndim, dims.shape_ns()
+                    )));
+                }
+                let mut permuted = vec![Complex32::ZERO; data.len()];
+                let dims = dims.permute(&data, &mut permuted, order);
+                (permuted, dims)
+            }
+            None => (data, dims),
+        };
+        let dims = if self.squeeze { ArrayDim::from_shape(&dims.shape_squeeze()) } else { dims };
+        Ok(ArrayData::Complex{data, dims, meta: None})
+    }
+}
+
+/// converts a real or complex nifti to a cfl. Real data is written with zero imaginary parts.
+/// Returns the dimensions of the written cfl
+pub fn nifti_to_cfl(nifti_file:impl AsRef<Path>, cfl_file_base_name:impl AsRef<Path>) -> Result<ArrayDim, ConvertError> {
+    nifti_to_cfl_with_options(nifti_file, cfl_file_base_name, &NiftiToCflOptions::new())
+}
+
+/// converts a nifti to a cfl, keeping the component given in the options. The axes are permuted
+/// before singleton dimensions are squeezed. Returns the dimensions of the written cfl
+pub fn nifti_to_cfl_with_options(nifti_file:impl AsRef<Path>, cfl_file_base_name:impl AsRef<Path>, opts:&NiftiToCflOptions) -> Result<ArrayDim, ConvertError> {
+    let nifti_file = nifti_file.as_ref();
+    if !nifti_file.is_file() {
+        let err = std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} not found", nifti_file.display()));
+        return Err(ConvertError::Nifti(NiftiIoError::IO(err)));
+    }
+    let (data, dims, _) = try_read_nifti_complex::<f32>(nifti_file)?;
+    let ArrayData::Complex{data, dims, ..} = opts.apply(&ArrayData::Complex{data, dims, meta: None})? else {
+        unreachable!("nifti_to_cfl options always give complex data")
+    };
+    try_write_cfl(cfl_file_base_name, &data, dims)?;
+    Ok(dims)
+}
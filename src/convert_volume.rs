@@ -0,0 +1,221 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use num_traits::{Bounded, NumCast, ToPrimitive, Zero};
+use crate::io_nifti::{nifti_affine, nifti_output_path, set_nifti_affine, try_read_nifti, write_nifti_with_options, NiftiHeader, NiftiIoError, NiftiWriteOptions};
+use crate::io_nrrd::{nifti_affine_to_nrrd_geometry, nrrd_geometry_to_nifti_affine, read_nrrd_scaled, write_nrrd_as, Encoding, NrrdDtype, NrrdIoError, NrrdWriteOptions, ScalePolicy, Space};
+
+#[cfg(test)]
+mod tests {
+    use crate::ArrayDim;
+    use crate::convert_volume::{nifti_to_nrrd, nrrd_to_nifti, VolumeConvertOptions};
+    use crate::io_nifti::{nifti_affine, read_nifti};
+    use crate::io_nrrd::{geometry_to_affine, read_nrrd_header, read_nrrd_scaled, write_nrrd_with_options, NrrdDtype, NrrdGeometry, NrrdWriteOptions, Space};
+
+    fn assert_affine_eq(a:[[f64;4];4], b:[[f64;4];4]) {
+        for r in 0..4 {
+            for c in 0..4 {
+                assert!((a[r][c] - b[r][c]).abs() < 1e-5, "affines differ at [{}][{}]: {:?} vs {:?}", r, c, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_oblique_round_trip() {
+        // anisotropic voxels rotated 30 degrees about z, in LPS as Slicer writes them
+        let (s, c) = (30f64.to_radians().sin(), 30f64.to_radians().cos());
+        let geometry = NrrdGeometry::new(vec![0.5, 0.8, 2.], Space::LeftPosteriorSuperior)
+            .with_directions(vec![[c, s, 0.], [-s, c, 0.], [0., 0., 1.]])
+            .with_origin([10., -20., 5.]);
+        let dims = ArrayDim::from_shape(&[5,4,3]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32 * 1.5 - 7.).collect();
+        write_nrrd_with_options("test_volume_oblique.nrrd",&x,dims,&NrrdWriteOptions::new().with_geometry(geometry.clone())).unwrap();
+        let expected = geometry_to_affine(&geometry).unwrap();
+        // LPS to RAS negates the first two rows
+        assert!((expected[0][3] + 10.).abs() < 1e-12 && (expected[1][3] - 20.).abs() < 1e-12);
+
+        let out = nrrd_to_nifti("test_volume_oblique.nrrd","test_volume_oblique",&VolumeConvertOptions::new().compress(true)).unwrap();
+        assert_eq!(out.to_str(),Some("test_volume_oblique.nii.gz"));
+        let (y,y_dims,h) = read_nifti::<f32>(&out);
+        assert_eq!(y,x);
+        assert_eq!(y_dims.shape(),dims.shape());
+        assert_affine_eq(nifti_affine(&h),expected);
+
+        // back to nrrd as int16 with gzip encoding
+        let opts = VolumeConvertOptions::new().dtype(NrrdDtype::Int16).gzip(true);
+        nifti_to_nrrd(&out,"test_volume_back.nrrd",&opts).unwrap();
+        let (_,back) = read_nrrd_header("test_volume_back.nrrd").unwrap();
+        let (z,..) = read_nrrd_scaled("test_volume_back.nrrd").unwrap();
+        std::fs::remove_file("test_volume_oblique.nrrd").unwrap();
+        std::fs::remove_file(&out).unwrap();
+        std::fs::remove_file("test_volume_back.nrrd").unwrap();
+        assert_eq!(back.dtype(),Some(NrrdDtype::Int16));
+        assert_eq!(back.field("space"),Some("left-posterior-superior"));
+        assert_eq!(back.field("encoding"),Some("gzip"));
+        assert_affine_eq(geometry_to_affine(&back.geometry().unwrap()).unwrap(),expected);
+        assert_eq!(z,x.iter().map(|x| x.round()).collect::<Vec<f32>>());
+    }
+
+}
+
+/// errors that can occur converting between nrrd and nifti
+#[derive(Debug)]
+pub enum VolumeConvertError {
+    Nrrd(NrrdIoError),
+    Nifti(NiftiIoError),
+}
+
+impl Display for VolumeConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VolumeConvertError::Nrrd(e) => write!(f, "{}", e),
+            VolumeConvertError::Nifti(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for VolumeConvertError {}
+
+impl From<NrrdIoError> for VolumeConvertError {
+    fn from(err: NrrdIoError) -> Self {
+        VolumeConvertError::Nrrd(err)
+    }
+}
+
+impl From<NiftiIoError> for VolumeConvertError {
+    fn from(err: NiftiIoError) -> Self {
+        VolumeConvertError::Nifti(err)
+    }
+}
+
+/// options for converting between nrrd and nifti
+#[derive(Debug, Clone)]
+pub struct VolumeConvertOptions {
+    dtype: Option<NrrdDtype>,
+    gzip: bool,
+    compress: bool,
+    space: Space,
+}
+
+impl Default for VolumeConvertOptions {
+    fn default() -> Self {
+        VolumeConvertOptions {
+            dtype: None,
+            gzip: false,
+            compress: false,
+            space: Space::LeftPosteriorSuperior,
+        }
+    }
+}
+
+impl VolumeConvertOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the element type of the output. Values are rounded and clamped for integer types. Defaults
+    /// to the element type of the input
+    pub fn dtype(mut self, dtype:NrrdDtype) -> Self {
+        self.dtype = Some(dtype);
+        self
+    }
+
+    /// gzip the data of nrrd outputs. Raw encoding is used otherwise
+    pub fn gzip(mut self, gzip:bool) -> Self {
+        self.gzip = gzip;
+        self
+    }
+
+    /// gzip nifti outputs, adding a .gz extension if needed
+    pub fn compress(mut self, compress:bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// the world space of nrrd outputs. Defaults to LPS, as written by Slicer
+    pub fn space(mut self, space:Space) -> Self {
+        self.space = space;
+        self
+    }
+}
+
+/// the nrrd element type of a nifti datatype code, if there is one
+fn nifti_dtype(datatype:i16) -> Option<NrrdDtype> {
+    let dtype = match datatype {
+        2 => NrrdDtype::UInt8,
+        4 => NrrdDtype::Int16,
+        8 => NrrdDtype::Int32,
+        16 => NrrdDtype::Float32,
+        64 => NrrdDtype::Float64,
+        256 => NrrdDtype::Int8,
+        512 => NrrdDtype::UInt16,
+        768 => NrrdDtype::UInt32,
+        1024 => NrrdDtype::Int64,
+        1280 => NrrdDtype::UInt64,
+        _=> return None,
+    };
+    Some(dtype)
+}
+
+/// converts values to an element type, rounding and clamping for integer types
+fn cast_values<T:NumCast + Bounded + ToPrimitive + Zero>(x:&[f32], integer:bool) -> Vec<T> {
+    let lo = T::min_value().to_f64().unwrap_or(f64::MIN);
+    let hi = T::max_value().to_f64().unwrap_or(f64::MAX);
+    x.iter().map(|&v| {
+        let v = if integer { (v as f64).round() } else { v as f64 };
+        T::from(v.clamp(lo, hi)).unwrap_or_else(T::zero)
+    }).collect()
+}
+
+/// converts a nrrd to a nifti, carrying the spacing, directions and origin of the nrrd over to
+/// the sform of the nifti with the flips from the nrrd space to RAS. Returns the path of the
+/// written nifti
+pub fn nrrd_to_nifti(nrrd_file:impl AsRef<Path>, nifti_file:impl AsRef<Path>, opts:&VolumeConvertOptions) -> Result<PathBuf, VolumeConvertError> {
+    let (data, dims, header) = read_nrrd_scaled(nrrd_file)?;
+    let dtype = opts.dtype.or(header.dtype()).unwrap_or(NrrdDtype::Float32);
+
+    let mut h = NiftiHeader::default();
+    set_nifti_affine(&mut h, nrrd_geometry_to_nifti_affine(&header)?);
+
+    let mut out = nifti_output_path(nifti_file);
+    if opts.compress && out.extension().is_some_and(|e| e == "nii") {
+        out.as_mut_os_string().push(".gz");
+    }
+    let integer = dtype.is_integer();
+    let write_opts = NiftiWriteOptions::default();
+    macro_rules! write_as {
+        ($t:ty) => { write_nifti_with_options(&out, &cast_values::<$t>(&data, integer), dims, Some(&h), &write_opts) };
+    }
+    let out = match dtype {
+        NrrdDtype::Int8 => write_as!(i8),
+        NrrdDtype::UInt8 => write_as!(u8),
+        NrrdDtype::Int16 => write_as!(i16),
+        NrrdDtype::UInt16 => write_as!(u16),
+        NrrdDtype::Int32 => write_as!(i32),
+        NrrdDtype::UInt32 => write_as!(u32),
+        NrrdDtype::Int64 => write_as!(i64),
+        NrrdDtype::UInt64 => write_as!(u64),
+        NrrdDtype::Float32 => write_as!(f32),
+        NrrdDtype::Float64 => write_as!(f64),
+    }?;
+    Ok(out)
+}
+
+/// converts a nifti to a nrrd, carrying the voxel-to-world affine of the nifti over to the space
+/// directions and origin of the nrrd in the space given in the options
+pub fn nifti_to_nrrd(nifti_file:impl AsRef<Path>, nrrd_file:impl AsRef<Path>, opts:&VolumeConvertOptions) -> Result<(), VolumeConvertError> {
+    let (data, dims, h) = try_read_nifti::<f32>(nifti_file)?;
+    let dtype = opts.dtype.or(nifti_dtype(h.datatype)).unwrap_or(NrrdDtype::Float32);
+
+    // only as many spatial axes as the array has
+    let mut geometry = nifti_affine_to_nrrd_geometry(nifti_affine(&h), opts.space)?;
+    let n_spatial = dims.shape_ns().len().min(3);
+    geometry.spacings.truncate(n_spatial);
+    if let Some(d) = geometry.directions.as_mut() {
+        d.truncate(n_spatial);
+    }
+
+    let encoding = if opts.gzip { Encoding::Gzip } else { Encoding::Raw };
+    let write_opts = NrrdWriteOptions::new().encoding(encoding).with_geometry(geometry);
+    write_nrrd_as(nrrd_file, &data, dims, dtype, ScalePolicy::None, &write_opts)?;
+    Ok(())
+}
@@ -0,0 +1,190 @@
+use std::fmt::Display;
+use num_complex::Complex32;
+use serde::Serialize;
+use crate::ArrayDim;
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex32;
+    use crate::dim;
+    use crate::describe::{describe, describe_complex};
+
+    #[test]
+    fn test_describe() {
+        let dims = dim![3,4,2];
+        let mut x = vec![1f32; dims.numel()];
+        x[dims.calc_addr(&[2,1,0])] = -7.;
+        x[dims.calc_addr(&[0,3,1])] = 9.;
+        x[dims.calc_addr(&[1,0,0])] = f32::NAN;
+        x[dims.calc_addr(&[1,1,1])] = f32::INFINITY;
+        x[dims.calc_addr(&[2,2,1])] = f32::NEG_INFINITY;
+        x[dims.calc_addr(&[0,0,0])] = 0.;
+        x[dims.calc_addr(&[0,1,0])] = -0.;
+
+        let s = describe(&x, &dims);
+        assert_eq!(s.shape, vec![3,4,2]);
+        assert_eq!(s.dtype, "float32");
+        assert!(!s.magnitude);
+        assert_eq!(s.min, -7.);
+        assert_eq!(s.max, 9.);
+        assert_eq!(s.argmin, Some(vec![2,1,0]));
+        assert_eq!(s.argmax, Some(vec![0,3,1]));
+        assert_eq!(s.nan_count, 1);
+        assert_eq!(s.inf_count, 2);
+        assert_eq!(s.zero_fraction, 2. / 24.);
+        // the statistics are of the 21 finite values
+        let mean = (17. - 7. + 9.) / 21.;
+        assert!((s.mean - mean).abs() < 1e-12);
+        let var = (17. * (1. - mean) * (1. - mean) + 2. * mean * mean + (-7. - mean) * (-7. - mean) + (9. - mean) * (9. - mean)) / 21.;
+        assert!((s.std - var.sqrt()).abs() < 1e-12);
+
+        let line = s.to_string();
+        assert!(line.starts_with("[3, 4, 2] float32 min -7 at [2, 1, 0] max 9 at [0, 3, 1]"), "{}", line);
+        assert!(line.ends_with("NaN 1 Inf 2 zeros 8.33%"), "{}", line);
+    }
+
+    #[test]
+    fn test_describe_complex() {
+        let dims = dim![2,3];
+        let mut x = vec![Complex32::new(0., 1.); dims.numel()];
+        x[dims.calc_addr(&[1,2])] = Complex32::new(3., -4.);
+        x[dims.calc_addr(&[0,1])] = Complex32::new(0., 0.);
+        x[dims.calc_addr(&[1,0])] = Complex32::new(1., f32::NAN);
+        x[dims.calc_addr(&[0,2])] = Complex32::new(f32::INFINITY, 2.);
+
+        let s = describe_complex(&x, &dims);
+        assert_eq!(s.dtype, "complex float32");
+        assert!(s.magnitude);
+        assert_eq!(s.min, 0.);
+        assert_eq!(s.max, 5.);
+        assert_eq!(s.argmin, Some(vec![0,1]));
+        assert_eq!(s.argmax, Some(vec![1,2]));
+        assert_eq!(s.nan_count, 1);
+        assert_eq!(s.inf_count, 1);
+        assert_eq!(s.zero_fraction, 1. / 6.);
+        assert_eq!(s.mean, 1.75);
+    }
+
+    #[test]
+    fn test_describe_non_finite() {
+        let dims = dim![2];
+        let s = describe(&[f32::NAN, f32::INFINITY], &dims);
+        assert!(s.min.is_nan() && s.mean.is_nan());
+        assert_eq!(s.argmin, None);
+        assert_eq!(s.argmax, None);
+        assert_eq!(s.zero_fraction, 0.);
+        assert_eq!(s.to_string(), "[2] float32 min - max - mean NaN std NaN NaN 1 Inf 1 zeros 0.00%");
+    }
+}
+
+/// summary statistics of an array, for debugging and logs. The extrema, mean and standard
+/// deviation are of the finite values, with NaNs and infinities counted separately. For complex
+/// data they are of the magnitudes
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArraySummary {
+    /// the shape with trailing singleton dimensions removed
+    pub shape: Vec<usize>,
+    /// the element type, i.e. "float32" or "complex float32"
+    pub dtype: String,
+    /// whether the statistics are of magnitudes
+    pub magnitude: bool,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std: f64,
+    /// the index of the first minimum along the axes of shape, if any values are finite
+    pub argmin: Option<Vec<usize>>,
+    /// the index of the first maximum along the axes of shape, if any values are finite
+    pub argmax: Option<Vec<usize>>,
+    pub nan_count: usize,
+    pub inf_count: usize,
+    /// the fraction of all values that are exactly 0
+    pub zero_fraction: f64,
+}
+
+impl Display for ArraySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} {}", self.shape, self.dtype)?;
+        if self.magnitude {
+            write!(f, " |x|")?;
+        }
+        match (&self.argmin, &self.argmax) {
+            (Some(lo), Some(hi)) => write!(f, " min {} at {:?} max {} at {:?}", self.min, lo, self.max, hi)?,
+            _ => write!(f, " min - max -")?,
+        }
+        write!(f, " mean {:.6} std {:.6} NaN {} Inf {} zeros {:.2}%", self.mean, self.std, self.nan_count, self.inf_count, 100. * self.zero_fraction)
+    }
+}
+
+/// summarizes a real array. See ArraySummary
+pub fn describe(data:&[f32], dims:&ArrayDim) -> ArraySummary {
+    summarize(data.iter().map(|&x| x as f64), dims, "float32", false)
+}
+
+/// summarizes a complex array by the magnitudes of its values. A value is NaN if either part is
+/// NaN, and infinite if either part is infinite otherwise
+pub fn describe_complex(data:&[Complex32], dims:&ArrayDim) -> ArraySummary {
+    summarize(data.iter().map(|x| complex_magnitude(x.re as f64, x.im as f64)), dims, "complex float32", true)
+}
+
+/// the magnitude of a complex value, which is NaN if either part is
+pub(crate) fn complex_magnitude(re:f64, im:f64) -> f64 {
+    if re.is_nan() || im.is_nan() { f64::NAN } else { re.hypot(im) }
+}
+
+/// summarizes values in column-major order, where the values are magnitudes if magnitude is set
+pub(crate) fn summarize(values:impl Iterator<Item = f64>, dims:&ArrayDim, dtype:&str, magnitude:bool) -> ArraySummary {
+    let (mut min, mut max, mut sum, mut sum_sq) = (f64::INFINITY, f64::NEG_INFINITY, 0., 0.);
+    let (mut min_addr, mut max_addr) = (None, None);
+    let (mut n, mut total, mut nan_count, mut inf_count, mut zero_count) = (0usize, 0usize, 0usize, 0usize, 0usize);
+    for (addr, v) in values.enumerate() {
+        total += 1;
+        if v.is_nan() {
+            nan_count += 1;
+            continue;
+        }
+        if v.is_infinite() {
+            inf_count += 1;
+            continue;
+        }
+        if v == 0. {
+            zero_count += 1;
+        }
+        if v < min {
+            min = v;
+            min_addr = Some(addr);
+        }
+        if v > max {
+            max = v;
+            max_addr = Some(addr);
+        }
+        sum += v;
+        sum_sq += v * v;
+        n += 1;
+    }
+    assert_eq!(total, dims.numel(), "data must be the same size as array");
+
+    let (min, max, mean, std) = if n == 0 {
+        (f64::NAN, f64::NAN, f64::NAN, f64::NAN)
+    } else {
+        let mean = sum / n as f64;
+        (min, max, mean, (sum_sq / n as f64 - mean * mean).max(0.).sqrt())
+    };
+    let shape = dims.shape_ns().to_vec();
+    let index = |addr:usize| dims.calc_idx(addr)[..shape.len()].to_vec();
+    let (argmin, argmax) = (min_addr.map(index), max_addr.map(index));
+    ArraySummary {
+        shape,
+        dtype: dtype.to_string(),
+        magnitude,
+        min,
+        max,
+        mean,
+        std,
+        argmin,
+        argmax,
+        nan_count,
+        inf_count,
+        zero_fraction: if total == 0 { 0. } else { zero_count as f64 / total as f64 },
+    }
+}
@@ -0,0 +1,213 @@
+use core::fmt::{Display, Formatter};
+use core::ops::Neg;
+#[cfg(not(feature = "std"))]
+use alloc::{string::String, vec::Vec};
+use num_complex::Complex;
+use num_traits::Zero;
+use crate::{ArrayDim, N_DIMS};
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use num_complex::Complex32;
+    use crate::dim;
+    use crate::display::{format_slice, DisplaySlice};
+
+    #[test]
+    fn test_format_slice() {
+        let dims = dim![3,4];
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32 * 0.5 - 1.).collect();
+        let expected = concat!(
+            "     0    1    2    3\n",
+            "0 -1.0  0.5  2.0  3.5\n",
+            "1 -0.5  1.0  2.5  4.0\n",
+            "2  0.0  1.5  3.0  4.5",
+        );
+        assert_eq!(format_slice(&x, &dims, 2, 0, 0, 1), expected);
+
+        // the width and precision can come from the format string
+        let expected = concat!(
+            "       0      1      2      3\n",
+            "0  -1.00   0.50   2.00   3.50\n",
+            "1  -0.50   1.00   2.50   4.00\n",
+            "2   0.00   1.50   3.00   4.50",
+        );
+        assert_eq!(format!("{:6.2}", DisplaySlice::new(&x, &dims, 2, 0)), expected);
+        assert_eq!(DisplaySlice::new(&x, &dims, 2, 0).width(6).precision(2).to_string(), expected);
+    }
+
+    #[test]
+    fn test_format_truncated_slice() {
+        let dims = dim![12,12];
+        let x:Vec<i32> = (0..dims.numel() as i32).collect();
+        let expected = concat!(
+            "      0   1   2 ...   9  10  11\n",
+            "  0   0  12  24 ... 108 120 132\n",
+            "  1   1  13  25 ... 109 121 133\n",
+            "  2   2  14  26 ... 110 122 134\n",
+            "... ... ... ... ... ... ... ...\n",
+            "  9   9  21  33 ... 117 129 141\n",
+            " 10  10  22  34 ... 118 130 142\n",
+            " 11  11  23  35 ... 119 131 143",
+        );
+        assert_eq!(format_slice(&x, &dims, 2, 0, 0, 0), expected);
+    }
+
+    #[test]
+    fn test_format_complex_slice() {
+        // the plane of axes 1 and 2 at index 1 of axis 0
+        let dims = dim![2; 3];
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32, -(i as f32) / 4.)).collect();
+        let expected = concat!(
+            "           0          1\n",
+            "0 1.00-0.25i 5.00-1.25i\n",
+            "1 3.00-0.75i 7.00-1.75i",
+        );
+        assert_eq!(format_slice(&x, &dims, 0, 1, 0, 2), expected);
+    }
+}
+
+/// axes longer than this are truncated when a slice is formatted
+pub const SLICE_MAX_ITEMS:usize = 10;
+
+/// the number of entries kept at each end of a truncated axis
+pub const SLICE_EDGE_ITEMS:usize = 3;
+
+/// values that can be formatted in a slice
+pub trait FormatElement {
+    /// formats the value with the given number of digits after the decimal point. Integers ignore
+    /// the precision
+    fn format_element(&self, precision:usize) -> String;
+}
+
+macro_rules! format_float {
+    ($($t:ty),+) => {$(
+        impl FormatElement for $t {
+            fn format_element(&self, precision:usize) -> String {
+                format!("{:.*}", precision, self)
+            }
+        }
+    )+};
+}
+
+macro_rules! format_int {
+    ($($t:ty),+) => {$(
+        impl FormatElement for $t {
+            fn format_element(&self, _precision:usize) -> String {
+                format!("{}", self)
+            }
+        }
+    )+};
+}
+
+format_float!(f32, f64);
+format_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// complex values are formatted as a+bi
+impl<T:FormatElement + PartialOrd + Zero + Neg<Output = T> + Copy> FormatElement for Complex<T> {
+    fn format_element(&self, precision:usize) -> String {
+        let (sign, im) = if self.im < T::zero() { ('-', -self.im) } else { ('+', self.im) };
+        format!("{}{}{}i", self.re.format_element(precision), sign, im.format_element(precision))
+    }
+}
+
+/// formats a 2D slice of an array as a grid with the row and column indices, for debugging. The
+/// slice is the plane of the first two axes other than axis, taken at index along axis (and at 0
+/// along any later axes), with rows along the lower axis. Entries are right-aligned to at least
+/// width characters, and axes longer than SLICE_MAX_ITEMS are truncated to their first and last
+/// SLICE_EDGE_ITEMS entries, with ellipses between them
+pub fn format_slice<T:FormatElement>(data:&[T], dims:&ArrayDim, axis:usize, index:usize, width:usize, precision:usize) -> String {
+    assert_eq!(data.len(), dims.numel(), "data must be the same size as array");
+    assert!(axis < N_DIMS, "axis {} is out of range", axis);
+    assert!(index < dims.shape()[axis], "index {} is out of range for axis {} of size {}", index, axis, dims.shape()[axis]);
+
+    let mut plane = (0..N_DIMS).filter(|&a| a != axis);
+    let (row_axis, col_axis) = (plane.next().unwrap(), plane.next().unwrap());
+    let rows = shown_indices(dims.shape()[row_axis]);
+    let cols = shown_indices(dims.shape()[col_axis]);
+
+    let mut base = [0usize; N_DIMS];
+    base[axis] = index;
+    let cells:Vec<Vec<String>> = rows.iter().map(|&r| cols.iter().map(|&c| match (r, c) {
+        (Some(r), Some(c)) => {
+            let mut idx = base;
+            idx[row_axis] = r;
+            idx[col_axis] = c;
+            data[dims.calc_addr(&idx)].format_element(precision)
+        }
+        _ => String::from("..."),
+    }).collect()).collect();
+
+    let cell_width = cells.iter().flatten().map(|s| s.chars().count())
+        .chain(cols.iter().map(|&c| index_label(c).len()))
+        .fold(width, usize::max);
+    let label_width = rows.iter().map(|&r| index_label(r).len()).max().unwrap_or(0);
+
+    let mut out = format!("{:>w$}", "", w = label_width);
+    for &c in &cols {
+        out.push_str(&format!(" {:>w$}", index_label(c), w = cell_width));
+    }
+    for (&r, row) in rows.iter().zip(&cells) {
+        out.push_str(&format!("\n{:>w$}", index_label(r), w = label_width));
+        for cell in row {
+            out.push_str(&format!(" {:>w$}", cell, w = cell_width));
+        }
+    }
+    out
+}
+
+/// displays a 2D slice of an array as format_slice does. The width and precision default to 0
+/// and 3, and are taken from the format string when given, as in {:8.2}
+#[derive(Clone, Copy, Debug)]
+pub struct DisplaySlice<'a, T> {
+    data: &'a [T],
+    dims: &'a ArrayDim,
+    axis: usize,
+    index: usize,
+    width: usize,
+    precision: usize,
+}
+
+impl<'a, T:FormatElement> DisplaySlice<'a, T> {
+
+    /// the slice at index along axis
+    pub fn new(data:&'a [T], dims:&'a ArrayDim, axis:usize, index:usize) -> Self {
+        DisplaySlice { data, dims, axis, index, width: 0, precision: 3 }
+    }
+
+    pub fn width(mut self, width:usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn precision(mut self, precision:usize) -> Self {
+        self.precision = precision;
+        self
+    }
+
+}
+
+impl<T:FormatElement> Display for DisplaySlice<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let width = f.width().unwrap_or(self.width);
+        let precision = f.precision().unwrap_or(self.precision);
+        f.write_str(&format_slice(self.data, self.dims, self.axis, self.index, width, precision))
+    }
+}
+
+/// the indices shown along an axis of the given size, with None for the ellipsis
+fn shown_indices(size:usize) -> Vec<Option<usize>> {
+    if size <= SLICE_MAX_ITEMS {
+        return (0..size).map(Some).collect();
+    }
+    (0..SLICE_EDGE_ITEMS).map(Some)
+        .chain([None])
+        .chain((size - SLICE_EDGE_ITEMS..size).map(Some))
+        .collect()
+}
+
+fn index_label(index:Option<usize>) -> String {
+    match index {
+        Some(i) => format!("{}", i),
+        None => String::from("..."),
+    }
+}
@@ -0,0 +1,320 @@
+/*
+    C interface to the array layout math and cfl IO. The header include/array_lib.h is generated
+    from this module by cbindgen (see cbindgen.toml for the command), with the ownership rules for
+    handles and buffers in its preamble. The static library is built by the array-lib-ffi crate in
+    ffi/. Every function returns a status, and panics are caught at the boundary
+ */
+
+use std::ffi::{c_char, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use num_complex::Complex32;
+use crate::ArrayDim;
+use crate::io_cfl::{cfl_base_name, try_read_cfl, try_write_cfl, CflIoError};
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use crate::ArrayDim;
+    use crate::ffi::*;
+
+    #[test]
+    fn test_ffi_round_trip() {
+        let shape = [4usize, 3, 2];
+        let mut dims:*mut ArrayDim = ptr::null_mut();
+        assert_eq!(unsafe { arraydim_from_shape(shape.as_ptr(), shape.len(), &mut dims) }, ArrayLibStatus::Ok);
+
+        let mut numel = 0;
+        assert_eq!(unsafe { arraydim_numel(dims, &mut numel) }, ArrayLibStatus::Ok);
+        assert_eq!(numel, 24);
+        let mut addr = 0;
+        assert_eq!(unsafe { arraydim_calc_addr(dims, [1usize, 2, 1].as_ptr(), 3, &mut addr) }, ArrayLibStatus::Ok);
+        assert_eq!(addr, 1 + 2 * 4 + 12);
+        assert_eq!(unsafe { arraydim_calc_addr(dims, [4usize, 0, 0].as_ptr(), 3, &mut addr) }, ArrayLibStatus::OutOfBounds);
+
+        let data:Vec<f32> = (0..2 * numel).map(|i| i as f32).collect();
+        let path = c"test_ffi_round_trip";
+        assert_eq!(unsafe { cfl_write(path.as_ptr(), data.as_ptr(), numel, dims) }, ArrayLibStatus::Ok);
+        assert_eq!(unsafe { cfl_write(path.as_ptr(), data.as_ptr(), numel - 1, dims) }, ArrayLibStatus::LengthMismatch);
+
+        let mut read_dims:*mut ArrayDim = ptr::null_mut();
+        let mut read_data:*mut f32 = ptr::null_mut();
+        let mut len = 0;
+        assert_eq!(unsafe { cfl_read(path.as_ptr(), &mut read_data, &mut len, &mut read_dims) }, ArrayLibStatus::Ok);
+        assert_eq!(len, numel);
+        assert_eq!(unsafe { std::slice::from_raw_parts(read_data, 2 * len) }, &data[..]);
+        assert_eq!(unsafe { *read_dims }, unsafe { *dims });
+        unsafe {
+            cfl_free(read_data, len);
+            arraydim_free(read_dims);
+        }
+
+        // either file of the pair names it
+        for name in [c"test_ffi_round_trip.cfl", c"test_ffi_round_trip.hdr"] {
+            assert_eq!(unsafe { cfl_read(name.as_ptr(), &mut read_data, &mut len, &mut read_dims) }, ArrayLibStatus::Ok);
+            assert_eq!(unsafe { std::slice::from_raw_parts(read_data, 2 * len) }, &data[..]);
+            unsafe {
+                cfl_free(read_data, len);
+                arraydim_free(read_dims);
+            }
+        }
+
+        unsafe { arraydim_free(dims) };
+        std::fs::remove_file("test_ffi_round_trip.cfl").unwrap();
+        std::fs::remove_file("test_ffi_round_trip.hdr").unwrap();
+    }
+
+    #[test]
+    fn test_ffi_errors() {
+        let mut dims:*mut ArrayDim = ptr::null_mut();
+        assert_eq!(unsafe { arraydim_from_shape([4usize, 0].as_ptr(), 2, &mut dims) }, ArrayLibStatus::InvalidShape);
+        assert!(dims.is_null());
+        assert_eq!(unsafe { arraydim_numel(ptr::null(), &mut 0) }, ArrayLibStatus::NullPointer);
+
+        let mut data:*mut f32 = ptr::null_mut();
+        let status = unsafe { cfl_read(c"test_ffi_missing".as_ptr(), &mut data, &mut 0, &mut dims) };
+        assert_eq!(status, ArrayLibStatus::Io);
+        assert!(data.is_null());
+        let msg = unsafe { CStr::from_ptr(array_lib_status_message(status)) };
+        assert_eq!(msg.to_str().unwrap(), "the file couldn't be read or written");
+
+        // panics don't cross the boundary
+        assert_eq!(guard(|| panic!("test panic")), ArrayLibStatus::Panic);
+    }
+}
+
+/// the result of a call
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArrayLibStatus {
+    /// the call succeeded
+    Ok = 0,
+    /// a required pointer argument was null
+    NullPointer = 1,
+    /// the shape has an axis of length 0 or non-singleton axes past the 16 supported
+    InvalidShape = 2,
+    /// a subscript lies outside its axis
+    OutOfBounds = 3,
+    /// a buffer length doesn't match the number of elements of the array
+    LengthMismatch = 4,
+    /// the path isn't valid UTF-8
+    InvalidPath = 5,
+    /// the file couldn't be read or written
+    Io = 6,
+    /// the file isn't a valid cfl, as for a malformed header or a data file of the wrong size
+    MalformedFile = 7,
+    /// the array is too large to allocate
+    Alloc = 8,
+    /// the library panicked. This is a bug
+    Panic = 9,
+}
+
+/// a static description of a status, which must not be freed
+#[unsafe(no_mangle)]
+pub extern "C" fn array_lib_status_message(status: ArrayLibStatus) -> *const c_char {
+    let msg = match status {
+        ArrayLibStatus::Ok => c"ok",
+        ArrayLibStatus::NullPointer => c"a required pointer argument was null",
+        ArrayLibStatus::InvalidShape => c"the shape has an axis of length 0 or more than 16 non-singleton axes",
+        ArrayLibStatus::OutOfBounds => c"a subscript lies outside its axis",
+        ArrayLibStatus::LengthMismatch => c"the buffer length doesn't match the number of elements",
+        ArrayLibStatus::InvalidPath => c"the path isn't valid UTF-8",
+        ArrayLibStatus::Io => c"the file couldn't be read or written",
+        ArrayLibStatus::MalformedFile => c"the file isn't a valid cfl",
+        ArrayLibStatus::Alloc => c"the array is too large to allocate",
+        ArrayLibStatus::Panic => c"internal error",
+    };
+    msg.as_ptr()
+}
+
+/// creates an array handle from the ndim axis lengths in shape. Axes past ndim are singleton, and
+/// shape may be null when ndim is 0. The handle is written to out and must be released with
+/// arraydim_free
+///
+/// # Safety
+/// shape must point to ndim readable values and out must be writable
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arraydim_from_shape(shape: *const usize, ndim: usize, out: *mut *mut ArrayDim) -> ArrayLibStatus {
+    guard(|| {
+        let shape = unsafe { slice_or_empty(shape, ndim)? };
+        let out = unsafe { out.as_mut().ok_or(ArrayLibStatus::NullPointer)? };
+        let dims = ArrayDim::try_from_shape(shape).map_err(|_| ArrayLibStatus::InvalidShape)?;
+        *out = Box::into_raw(Box::new(dims));
+        Ok(())
+    })
+}
+
+/// releases an array handle. Null handles are ignored
+///
+/// # Safety
+/// dims must be null or a handle from this library that hasn't been released
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arraydim_free(dims: *mut ArrayDim) {
+    if !dims.is_null() {
+        drop(unsafe { Box::from_raw(dims) });
+    }
+}
+
+/// writes the number of elements of the array to out
+///
+/// # Safety
+/// dims must be a live handle and out must be writable
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arraydim_numel(dims: *const ArrayDim, out: *mut usize) -> ArrayLibStatus {
+    guard(|| {
+        let dims = unsafe { dims.as_ref().ok_or(ArrayLibStatus::NullPointer)? };
+        let out = unsafe { out.as_mut().ok_or(ArrayLibStatus::NullPointer)? };
+        *out = dims.numel();
+        Ok(())
+    })
+}
+
+/// writes the number of axes up to the last non-singleton one (at least 1) to out. This is the
+/// number of axis lengths arraydim_shape writes
+///
+/// # Safety
+/// dims must be a live handle and out must be writable
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arraydim_ndim(dims: *const ArrayDim, out: *mut usize) -> ArrayLibStatus {
+    guard(|| {
+        let dims = unsafe { dims.as_ref().ok_or(ArrayLibStatus::NullPointer)? };
+        let out = unsafe { out.as_mut().ok_or(ArrayLibStatus::NullPointer)? };
+        *out = dims.ndim();
+        Ok(())
+    })
+}
+
+/// writes the axis lengths up to the last non-singleton axis to out, which holds len values.
+/// Returns ARRAY_LIB_STATUS_LENGTH_MISMATCH if len is less than arraydim_ndim
+///
+/// # Safety
+/// dims must be a live handle and out must have room for len values
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arraydim_shape(dims: *const ArrayDim, out: *mut usize, len: usize) -> ArrayLibStatus {
+    guard(|| {
+        let dims = unsafe { dims.as_ref().ok_or(ArrayLibStatus::NullPointer)? };
+        if out.is_null() {
+            return Err(ArrayLibStatus::NullPointer);
+        }
+        let shape = dims.shape_ns();
+        if len < shape.len() {
+            return Err(ArrayLibStatus::LengthMismatch);
+        }
+        let out = unsafe { std::slice::from_raw_parts_mut(out, shape.len()) };
+        out.copy_from_slice(shape);
+        Ok(())
+    })
+}
+
+/// writes the column-major address of the n subscripts in idx to out. Axes past n have a
+/// subscript of 0. Returns ARRAY_LIB_STATUS_OUT_OF_BOUNDS if a subscript lies outside its axis
+///
+/// # Safety
+/// dims must be a live handle, idx must point to n readable values and out must be writable
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arraydim_calc_addr(dims: *const ArrayDim, idx: *const usize, n: usize, out: *mut usize) -> ArrayLibStatus {
+    guard(|| {
+        let dims = unsafe { dims.as_ref().ok_or(ArrayLibStatus::NullPointer)? };
+        let idx = unsafe { slice_or_empty(idx, n)? };
+        let out = unsafe { out.as_mut().ok_or(ArrayLibStatus::NullPointer)? };
+        // subscripts past the 16 axes index singleton axes
+        let in_bounds = idx.iter().enumerate().all(|(k, &i)| i < dims.shape().get(k).copied().unwrap_or(1));
+        if !in_bounds {
+            return Err(ArrayLibStatus::OutOfBounds);
+        }
+        *out = dims.calc_addr(idx);
+        Ok(())
+    })
+}
+
+/// reads a cfl file pair given the base name or either file, where only a .cfl or .hdr extension
+/// is dropped from the name. The data is written to out_data as interleaved real and imaginary
+/// floats, with the number of complex elements in out_len and the array handle in out_dims. The
+/// data must be released with cfl_free and the handle with arraydim_free
+///
+/// # Safety
+/// path must be a nul-terminated string and the outputs must be writable
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cfl_read(path: *const c_char, out_data: *mut *mut f32, out_len: *mut usize, out_dims: *mut *mut ArrayDim) -> ArrayLibStatus {
+    guard(|| {
+        let path = unsafe { path_str(path)? };
+        let out_data = unsafe { out_data.as_mut().ok_or(ArrayLibStatus::NullPointer)? };
+        let out_len = unsafe { out_len.as_mut().ok_or(ArrayLibStatus::NullPointer)? };
+        let out_dims = unsafe { out_dims.as_mut().ok_or(ArrayLibStatus::NullPointer)? };
+        let (data, dims) = try_read_cfl(cfl_base_name(path)).map_err(cfl_status)?;
+        // a boxed slice has no spare capacity, so the length alone is enough to release it
+        let data = data.into_boxed_slice();
+        *out_len = data.len();
+        *out_data = Box::into_raw(data) as *mut f32;
+        *out_dims = Box::into_raw(Box::new(dims));
+        Ok(())
+    })
+}
+
+/// releases data returned by cfl_read, where len is the number of complex elements it returned.
+/// Null pointers are ignored
+///
+/// # Safety
+/// data must be null or a buffer from cfl_read with its length, that hasn't been released
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cfl_free(data: *mut f32, len: usize) {
+    if !data.is_null() {
+        drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(data as *mut Complex32, len)) });
+    }
+}
+
+/// writes a cfl file pair from len complex elements held as interleaved real and imaginary floats.
+/// len must be the number of elements of the array
+///
+/// # Safety
+/// path must be a nul-terminated string, data must point to 2 * len readable floats and dims
+/// must be a live handle
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cfl_write(path: *const c_char, data: *const f32, len: usize, dims: *const ArrayDim) -> ArrayLibStatus {
+    guard(|| {
+        let path = unsafe { path_str(path)? };
+        let dims = unsafe { dims.as_ref().ok_or(ArrayLibStatus::NullPointer)? };
+        if data.is_null() {
+            return Err(ArrayLibStatus::NullPointer);
+        }
+        if len != dims.numel() {
+            return Err(ArrayLibStatus::LengthMismatch);
+        }
+        // Complex32 is a pair of floats, with the alignment of a float
+        let data = unsafe { std::slice::from_raw_parts(data as *const Complex32, len) };
+        try_write_cfl(path, data, *dims).map_err(cfl_status)
+    })
+}
+
+/// runs a call, turning its error or a panic into a status
+fn guard(f: impl FnOnce() -> Result<(), ArrayLibStatus>) -> ArrayLibStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => ArrayLibStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => ArrayLibStatus::Panic,
+    }
+}
+
+/// a slice of n values, allowing a null pointer when n is 0
+unsafe fn slice_or_empty<'a>(ptr: *const usize, n: usize) -> Result<&'a [usize], ArrayLibStatus> {
+    match (ptr.is_null(), n) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err(ArrayLibStatus::NullPointer),
+        (false, _) => Ok(unsafe { std::slice::from_raw_parts(ptr, n) }),
+    }
+}
+
+unsafe fn path_str<'a>(path: *const c_char) -> Result<&'a str, ArrayLibStatus> {
+    if path.is_null() {
+        return Err(ArrayLibStatus::NullPointer);
+    }
+    unsafe { CStr::from_ptr(path) }.to_str().map_err(|_| ArrayLibStatus::InvalidPath)
+}
+
+fn cfl_status(e: CflIoError) -> ArrayLibStatus {
+    match e {
+        CflIoError::IO(..) | CflIoError::MissingHeader(_) | CflIoError::MissingData(_) => ArrayLibStatus::Io,
+        CflIoError::InconsistentArraySize {..} => ArrayLibStatus::LengthMismatch,
+        CflIoError::Alloc(..) => ArrayLibStatus::Alloc,
+        _ => ArrayLibStatus::MalformedFile,
+    }
+}
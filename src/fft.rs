@@ -0,0 +1,77 @@
+use num_complex::Complex32;
+use rayon::prelude::*;
+use rustfft::FftPlanner;
+use crate::ArrayDim;
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex32;
+    use crate::ArrayDim;
+    use crate::fft::fft_centered;
+
+    #[test]
+    fn test_centered_delta() {
+        // a delta at the center transforms to a constant along the transformed axes only
+        let dims = ArrayDim::from_shape(&[4,5,2]);
+        let mut x = dims.alloc(Complex32::ZERO);
+        x[dims.calc_addr(&[2,2,1])] = Complex32::ONE;
+        fft_centered(&mut x,&dims,&[0,1],false);
+        for (i,v) in x.iter().enumerate() {
+            let expected = if dims.calc_idx(i)[2] == 1 { 1. } else { 0. };
+            assert!((v - Complex32::new(expected,0.)).norm() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dims = ArrayDim::from_shape(&[6,3,5]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,(i % 7) as f32)).collect();
+        let mut y = x.clone();
+        fft_centered(&mut y,&dims,&[0,1,2],false);
+        fft_centered(&mut y,&dims,&[0,1,2],true);
+        assert!(x.iter().zip(&y).all(|(a,b)| (a - b).norm() < 1e-3));
+    }
+
+    #[test]
+    fn test_phantom_round_trip() {
+        use crate::phantom::{shepp_logan_2d, with_smooth_phase};
+        let dims = ArrayDim::from_shape(&[32,24]);
+        let x = with_smooth_phase(&shepp_logan_2d(&dims),&dims);
+        let mut y = x.clone();
+        fft_centered(&mut y,&dims,&[0,1],false);
+        // the dc term is the sum of the image
+        let sum:Complex32 = x.iter().sum();
+        assert!((y[dims.calc_addr(&[16,12])] - sum).norm() < 1e-3 * sum.norm());
+        fft_centered(&mut y,&dims,&[0,1],true);
+        assert!(x.iter().zip(&y).all(|(a,b)| (a - b).norm() < 1e-4));
+    }
+}
+
+/// performs a centered fft in place along each of the given axes, where the center of an axis is
+/// the sample at index n / 2. The inverse transform is scaled by 1 / n along each axis, so a
+/// forward transform followed by an inverse returns the input
+pub fn fft_centered(data:&mut [Complex32], dims:&ArrayDim, axes:&[usize], inverse:bool) {
+    assert_eq!(data.len(), dims.numel(), "data must be the same size as array");
+    let mut planner = FftPlanner::<f32>::new();
+    for &axis in axes {
+        let n = dims.shape()[axis];
+        if n == 1 {
+            continue;
+        }
+        let fft = if inverse { planner.plan_fft_inverse(n) } else { planner.plan_fft_forward(n) };
+        let scale = if inverse { 1. / n as f32 } else { 1. };
+        let stride = dims.strides()[axis];
+        // each block holds stride lines along the axis, interleaved
+        data.par_chunks_exact_mut(n * stride).for_each(|block| {
+            let mut line = vec![Complex32::ZERO; n];
+            let mut scratch = vec![Complex32::ZERO; fft.get_inplace_scratch_len()];
+            for start in 0..stride {
+                line.iter_mut().zip(block[start..].iter().step_by(stride)).for_each(|(l, x)| *l = *x);
+                line.rotate_left(n / 2);
+                fft.process_with_scratch(&mut line, &mut scratch);
+                line.rotate_right(n / 2);
+                block[start..].iter_mut().step_by(stride).zip(&line).for_each(|(x, l)| *x = l * scale);
+            }
+        });
+    }
+}
@@ -0,0 +1,507 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use num_complex::Complex64;
+use serde::Serialize;
+use crate::ArrayDim;
+use crate::describe::{complex_magnitude, summarize, ArraySummary};
+pub use crate::io::ArrayFormat;
+#[cfg(feature = "io-cfl")]
+use crate::io_cfl::{cfl_paths, read_cfl_dims, try_read_cfl, CflIoError};
+#[cfg(feature = "io-nifti")]
+use crate::io_nifti::{read_nifti_header, try_read_nifti, try_read_nifti_complex, NiftiIoError};
+#[cfg(feature = "io-nrrd")]
+use crate::io_nrrd::{read_nrrd_header, read_nrrd_scaled, NrrdDtype, NrrdIoError};
+#[cfg(feature = "io-mrd")]
+use crate::io_mrd::{read_mrd_layout, try_read_mrd, MrdIoError};
+#[cfg(feature = "io-npy")]
+use crate::io_npy::{read_npy_dyn, read_npy_header, NpyArray, NpyError};
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use crate::ArrayDim;
+    use crate::info::{array_info, describe_array, ArrayFormat, ArrayInfoError};
+
+    /// runs array_info with stats and parses its json
+    fn info_json(file:&str) -> Value {
+        let info = array_info(file,true).unwrap();
+        serde_json::from_str(&info.to_json()).unwrap()
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(ArrayFormat::detect("a/b.nii.gz"),Some(ArrayFormat::Nifti));
+        assert_eq!(ArrayFormat::detect("b.NHDR"),Some(ArrayFormat::Nrrd));
+        assert_eq!(ArrayFormat::detect("b.mrd"),Some(ArrayFormat::Mrd));
+        assert_eq!(ArrayFormat::detect("b.npy"),Some(ArrayFormat::Npy));
+        assert_eq!(ArrayFormat::detect("b.cfl"),Some(ArrayFormat::Cfl));
+        assert_eq!(ArrayFormat::detect("b.txt"),None);
+        assert!(matches!(array_info("test_info_missing.txt",false),Err(ArrayInfoError::UnknownFormat(..))));
+    }
+
+    #[cfg(feature = "io-cfl")]
+    #[test]
+    fn test_cfl_info() {
+        use num_complex::Complex32;
+        let dims = ArrayDim::from_shape(&[2,2]);
+        let x = [Complex32::new(3.,4.),Complex32::new(-1.,0.),Complex32::new(0.,1.),Complex32::new(2.,0.)];
+        crate::io_cfl::write_cfl("test_info_cfl",&x,dims);
+        let header = array_info("test_info_cfl.cfl",false).unwrap();
+        let j = info_json("test_info_cfl");
+        std::fs::remove_file("test_info_cfl.cfl").unwrap();
+        std::fs::remove_file("test_info_cfl.hdr").unwrap();
+        assert!(header.stats.is_none());
+        assert_eq!(header.shape,vec![2,2]);
+        assert_eq!(j["format"],"cfl");
+        assert_eq!(j["shape"],serde_json::json!([2,2]));
+        assert_eq!(j["dtype"],"complex float32");
+        assert_eq!(j["complex"],true);
+        // real parts, with the magnitude range
+        assert_eq!(j["stats"]["min"],-1.);
+        assert_eq!(j["stats"]["max"],3.);
+        assert_eq!(j["stats"]["magnitude_min"],1.);
+        assert_eq!(j["stats"]["magnitude_max"],5.);
+    }
+
+    #[cfg(feature = "io-nifti")]
+    #[test]
+    fn test_nifti_info() {
+        let dims = ArrayDim::from_shape(&[2,2,1,3]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+        crate::io_nifti::write_nifti("test_info_nifti.nii",&x,dims);
+        let j = info_json("test_info_nifti.nii");
+        let file_size = std::fs::metadata("test_info_nifti.nii").unwrap().len();
+        std::fs::remove_file("test_info_nifti.nii").unwrap();
+        assert_eq!(j["format"],"nifti");
+        assert_eq!(j["shape"],serde_json::json!([2,2,1,3]));
+        assert_eq!(j["dtype"],"float32");
+        assert_eq!(j["file_size"],file_size);
+        assert_eq!(j["stats"]["mean"],5.5);
+        assert!(j["stats"]["magnitude_min"].is_null());
+    }
+
+    #[cfg(feature = "io-nrrd")]
+    #[test]
+    fn test_nrrd_info() {
+        let dims = ArrayDim::from_shape(&[4]);
+        crate::io_nrrd::write_nrrd_with_options("test_info_nrrd.nrrd",&[1f32,f32::NAN,3.,5.],dims,&crate::io_nrrd::NrrdWriteOptions::new()).unwrap();
+        let j = info_json("test_info_nrrd.nrrd");
+        std::fs::remove_file("test_info_nrrd.nrrd").unwrap();
+        assert_eq!(j["format"],"nrrd");
+        assert_eq!(j["dtype"],"float32");
+        // NaNs are counted and left out of the other statistics
+        assert_eq!(j["stats"]["nan_count"],1);
+        assert_eq!(j["stats"]["min"],1.);
+        assert_eq!(j["stats"]["mean"],3.);
+        assert!((j["stats"]["std"].as_f64().unwrap() - (8f64 / 3.).sqrt()).abs() < 1e-12);
+    }
+
+    #[cfg(feature = "io-mrd")]
+    #[test]
+    fn test_mrd_info() {
+        use num_complex::Complex32;
+        let mut bytes = vec![0u8; crate::io_mrd::MRD_HEADER_SIZE];
+        for (o, d) in [(0, 3i32), (4, 2), (8, 1), (12, 1), (152, 1), (156, 1)] {
+            bytes[o..o + 4].copy_from_slice(&d.to_le_bytes());
+        }
+        // complex f32
+        bytes[18..20].copy_from_slice(&0x15i16.to_le_bytes());
+        let x:Vec<Complex32> = (0..6).map(|i| Complex32::new(i as f32, 0.)).collect();
+        bytes.extend_from_slice(bytemuck::cast_slice(&x));
+        std::fs::write("test_info_mrd.mrd",bytes).unwrap();
+        let header = array_info("test_info_mrd.mrd",false).unwrap();
+        let j = info_json("test_info_mrd.mrd");
+        std::fs::remove_file("test_info_mrd.mrd").unwrap();
+        assert_eq!(header.dtype,"complex float32");
+        assert_eq!(j["format"],"mrd");
+        assert_eq!(j["shape"],serde_json::json!([3,2]));
+        assert_eq!(j["stats"]["max"],5.);
+        assert_eq!(j["stats"]["magnitude_max"],5.);
+    }
+
+    #[cfg(feature = "io-npy")]
+    #[test]
+    fn test_npy_info() {
+        use crate::io_npy::{write_npy, Order};
+        let dims = ArrayDim::from_shape(&[3,2]);
+        write_npy("test_info_npy.npy",&[1i16,-2,3,4,5,-6],dims,Order::C).unwrap();
+        let j = info_json("test_info_npy.npy");
+        std::fs::remove_file("test_info_npy.npy").unwrap();
+        assert_eq!(j["format"],"npy");
+        assert_eq!(j["shape"],serde_json::json!([3,2]));
+        assert_eq!(j["dtype"],"int16");
+        assert_eq!(j["complex"],false);
+        assert_eq!(j["stats"]["min"],-6.);
+        assert_eq!(j["stats"]["max"],5.);
+        assert_eq!(j["stats"]["nan_count"],0);
+
+        // the summary keeps the stored type and locates the extrema
+        write_npy("test_info_npy_describe.npy",&[1i16,-2,3,4,5,-6],dims,Order::Fortran).unwrap();
+        let s = describe_array("test_info_npy_describe.npy").unwrap();
+        std::fs::remove_file("test_info_npy_describe.npy").unwrap();
+        assert_eq!(s.dtype,"int16");
+        assert_eq!(s.argmin,Some(vec![2,1]));
+        assert_eq!(s.argmax,Some(vec![1,1]));
+    }
+
+}
+
+#[derive(Debug)]
+pub enum ArrayInfoError {
+    UnknownFormat(PathBuf),
+    /// the format was detected but support for it is not compiled in
+    Disabled(ArrayFormat),
+    IO(PathBuf, std::io::Error),
+    #[cfg(feature = "io-cfl")]
+    Cfl(CflIoError),
+    #[cfg(feature = "io-nifti")]
+    Nifti(NiftiIoError),
+    #[cfg(feature = "io-nrrd")]
+    Nrrd(NrrdIoError),
+    #[cfg(feature = "io-mrd")]
+    Mrd(MrdIoError),
+    #[cfg(feature = "io-npy")]
+    Npy(NpyError),
+}
+
+impl Display for ArrayInfoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArrayInfoError::UnknownFormat(path) => write!(f, "cannot tell the format of {} from its extension", path.display()),
+            ArrayInfoError::Disabled(format) => write!(f, "{} support is not enabled. Build with the {} feature", format, format.feature()),
+            ArrayInfoError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            #[cfg(feature = "io-cfl")]
+            ArrayInfoError::Cfl(e) => write!(f, "{}", e),
+            #[cfg(feature = "io-nifti")]
+            ArrayInfoError::Nifti(e) => write!(f, "{}", e),
+            #[cfg(feature = "io-nrrd")]
+            ArrayInfoError::Nrrd(e) => write!(f, "{}", e),
+            #[cfg(feature = "io-mrd")]
+            ArrayInfoError::Mrd(e) => write!(f, "{}", e),
+            #[cfg(feature = "io-npy")]
+            ArrayInfoError::Npy(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ArrayInfoError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> ArrayInfoError {
+    let path = path.to_path_buf();
+    move |e| ArrayInfoError::IO(path, e)
+}
+
+/// summary statistics of the values of an array. For complex data the statistics are of the real
+/// parts, and the magnitude range is given as well. NaNs are counted and otherwise ignored
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArrayStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std: f64,
+    pub nan_count: usize,
+    pub magnitude_min: Option<f64>,
+    pub magnitude_max: Option<f64>,
+}
+
+/// the shape, element type and size of an array file, with statistics if the data was read
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArrayInfo {
+    pub path: PathBuf,
+    pub format: ArrayFormat,
+    /// the shape with trailing singleton dimensions removed
+    pub shape: Vec<usize>,
+    /// the stored element type, i.e. "int16" or "complex float32"
+    pub dtype: String,
+    pub complex: bool,
+    /// the size of the file in bytes. For cfls this is the size of the header and data files
+    pub file_size: u64,
+    pub stats: Option<ArrayStats>,
+}
+
+impl ArrayInfo {
+    /// the info as pretty printed json
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("array info is always serializable")
+    }
+}
+
+impl Display for ArrayInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "file: {}", self.path.display())?;
+        writeln!(f, "format: {}", self.format)?;
+        writeln!(f, "shape: {:?}", self.shape)?;
+        writeln!(f, "dtype: {}", self.dtype)?;
+        write!(f, "file size: {} bytes", self.file_size)?;
+        if let Some(s) = &self.stats {
+            let part = if self.complex { " (real part)" } else { "" };
+            write!(f, "\nmin{}: {}\nmax{}: {}\nmean{}: {}\nstd{}: {}\nNaNs: {}", part, s.min, part, s.max, part, s.mean, part, s.std, s.nan_count)?;
+            if let (Some(lo), Some(hi)) = (s.magnitude_min, s.magnitude_max) {
+                write!(f, "\nmagnitude range: [{}, {}]", lo, hi)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// computes statistics over values given as complex numbers. The magnitude range is only
+/// reported for complex data
+fn array_stats(values:impl Iterator<Item = Complex64>, complex:bool) -> ArrayStats {
+    let (mut min, mut max, mut sum, mut sum_sq) = (f64::INFINITY, f64::NEG_INFINITY, 0., 0.);
+    let (mut mag_min, mut mag_max) = (f64::INFINITY, f64::NEG_INFINITY);
+    let (mut n, mut nan_count) = (0usize, 0usize);
+    for v in values {
+        if v.re.is_nan() || v.im.is_nan() {
+            nan_count += 1;
+            continue;
+        }
+        min = min.min(v.re);
+        max = max.max(v.re);
+        sum += v.re;
+        sum_sq += v.re * v.re;
+        let mag = v.norm();
+        mag_min = mag_min.min(mag);
+        mag_max = mag_max.max(mag);
+        n += 1;
+    }
+    let (mean, std) = if n == 0 {
+        (f64::NAN, f64::NAN)
+    } else {
+        let mean = sum / n as f64;
+        (mean, (sum_sq / n as f64 - mean * mean).max(0.).sqrt())
+    };
+    ArrayStats {
+        min,
+        max,
+        mean,
+        std,
+        nan_count,
+        magnitude_min: (complex && n > 0).then_some(mag_min),
+        magnitude_max: (complex && n > 0).then_some(mag_max),
+    }
+}
+
+/// reads the shape, element type and size of an array file, detecting the format from its
+/// extension. Only the header is read unless stats are requested, in which case the data is read
+/// to compute min, max, mean, standard deviation and the number of NaNs
+pub fn array_info(file:impl AsRef<Path>, stats:bool) -> Result<ArrayInfo, ArrayInfoError> {
+    let path = file.as_ref();
+    let format = ArrayFormat::detect(path).ok_or_else(|| ArrayInfoError::UnknownFormat(path.to_path_buf()))?;
+    let file_size = |p:&Path| std::fs::metadata(p).map(|m| m.len()).map_err(io_err(p));
+
+    let (dims, dtype, complex, size, stats):(ArrayDim, String, bool, u64, Option<ArrayStats>) = match format {
+        #[cfg(feature = "io-cfl")]
+        ArrayFormat::Cfl => {
+            let base = if path.extension().is_some() { path.with_extension("") } else { path.to_path_buf() };
+            let dims = read_cfl_dims(&base).map_err(ArrayInfoError::Cfl)?;
+            let (hdr, cfl) = cfl_paths(&base);
+            let size = file_size(&hdr)? + file_size(&cfl)?;
+            let stats = if stats {
+                let (x, _) = try_read_cfl(&base).map_err(ArrayInfoError::Cfl)?;
+                Some(array_stats(x.iter().map(|v| Complex64::new(v.re as f64, v.im as f64)), true))
+            } else {
+                None
+            };
+            (dims, String::from("complex float32"), true, size, stats)
+        }
+        #[cfg(feature = "io-nifti")]
+        ArrayFormat::Nifti => {
+            let (dims, h) = read_nifti_header(path).map_err(ArrayInfoError::Nifti)?;
+            let complex = matches!(h.datatype, 32 | 1792);
+            let stats = if !stats {
+                None
+            } else if complex {
+                let (x, ..) = try_read_nifti_complex::<f64>(path).map_err(ArrayInfoError::Nifti)?;
+                Some(array_stats(x.into_iter(), true))
+            } else {
+                let (x, ..) = try_read_nifti::<f64>(path).map_err(ArrayInfoError::Nifti)?;
+                Some(array_stats(x.into_iter().map(|v| Complex64::new(v, 0.)), false))
+            };
+            (dims, nifti_dtype_name(h.datatype), complex, file_size(path)?, stats)
+        }
+        #[cfg(feature = "io-nrrd")]
+        ArrayFormat::Nrrd => {
+            let (dims, h) = read_nrrd_header(path).map_err(ArrayInfoError::Nrrd)?;
+            let dtype = match h.dtype() {
+                Some(NrrdDtype::Float32) => String::from("float32"),
+                Some(NrrdDtype::Float64) => String::from("float64"),
+                Some(t) => t.header_str().to_string(),
+                None => String::from("unknown"),
+            };
+            let stats = if stats {
+                let (x, ..) = read_nrrd_scaled(path).map_err(ArrayInfoError::Nrrd)?;
+                Some(array_stats(x.iter().map(|&v| Complex64::new(v as f64, 0.)), false))
+            } else {
+                None
+            };
+            (dims, dtype, false, file_size(path)?, stats)
+        }
+        #[cfg(feature = "io-mrd")]
+        ArrayFormat::Mrd => {
+            let (dims, dtype, complex) = read_mrd_layout(path).map_err(ArrayInfoError::Mrd)?;
+            let stats = if stats {
+                let (x, ..) = try_read_mrd(path).map_err(ArrayInfoError::Mrd)?;
+                Some(array_stats(x.iter().map(|v| Complex64::new(v.re as f64, v.im as f64)), complex))
+            } else {
+                None
+            };
+            (dims, dtype.to_string(), complex, file_size(path)?, stats)
+        }
+        #[cfg(feature = "io-npy")]
+        ArrayFormat::Npy => {
+            let (descr, dims) = read_npy_header(path).map_err(ArrayInfoError::Npy)?;
+            let stats = if stats {
+                let (x, _) = read_npy_dyn(path).map_err(ArrayInfoError::Npy)?;
+                Some(npy_stats(&x))
+            } else {
+                None
+            };
+            let (dtype, complex) = npy_dtype_name(&descr);
+            (dims, dtype, complex, file_size(path)?, stats)
+        }
+        #[allow(unreachable_patterns)]
+        _=> return Err(ArrayInfoError::Disabled(format)),
+    };
+
+    Ok(ArrayInfo {
+        path: path.to_path_buf(),
+        format,
+        shape: dims.shape_ns().to_vec(),
+        dtype,
+        complex,
+        file_size: size,
+        stats,
+    })
+}
+
+/// reads the data of an array file as complex values, detecting the format from its extension.
+/// Also returns whether the stored data is complex. Real data is returned with zero imaginary
+/// parts and nrrds are returned with their scaling applied
+pub fn read_array(file:impl AsRef<Path>) -> Result<(Vec<Complex64>, ArrayDim, bool), ArrayInfoError> {
+    let path = file.as_ref();
+    let format = ArrayFormat::detect(path).ok_or_else(|| ArrayInfoError::UnknownFormat(path.to_path_buf()))?;
+    match format {
+        #[cfg(feature = "io-cfl")]
+        ArrayFormat::Cfl => {
+            let base = if path.extension().is_some() { path.with_extension("") } else { path.to_path_buf() };
+            let (x, dims) = try_read_cfl(&base).map_err(ArrayInfoError::Cfl)?;
+            Ok((x.iter().map(|v| Complex64::new(v.re as f64, v.im as f64)).collect(), dims, true))
+        }
+        #[cfg(feature = "io-nifti")]
+        ArrayFormat::Nifti => {
+            let (_, h) = read_nifti_header(path).map_err(ArrayInfoError::Nifti)?;
+            if matches!(h.datatype, 32 | 1792) {
+                let (x, dims, _) = try_read_nifti_complex::<f64>(path).map_err(ArrayInfoError::Nifti)?;
+                Ok((x, dims, true))
+            } else {
+                let (x, dims, _) = try_read_nifti::<f64>(path).map_err(ArrayInfoError::Nifti)?;
+                Ok((x.into_iter().map(|v| Complex64::new(v, 0.)).collect(), dims, false))
+            }
+        }
+        #[cfg(feature = "io-nrrd")]
+        ArrayFormat::Nrrd => {
+            let (x, dims, ..) = read_nrrd_scaled(path).map_err(ArrayInfoError::Nrrd)?;
+            Ok((x.iter().map(|&v| Complex64::new(v as f64, 0.)).collect(), dims, false))
+        }
+        #[cfg(feature = "io-mrd")]
+        ArrayFormat::Mrd => {
+            let (_, _, complex) = read_mrd_layout(path).map_err(ArrayInfoError::Mrd)?;
+            let (x, dims, ..) = try_read_mrd(path).map_err(ArrayInfoError::Mrd)?;
+            Ok((x.iter().map(|v| Complex64::new(v.re as f64, v.im as f64)).collect(), dims, complex))
+        }
+        #[cfg(feature = "io-npy")]
+        ArrayFormat::Npy => {
+            let (x, dims) = read_npy_dyn(path).map_err(ArrayInfoError::Npy)?;
+            let complex = matches!(x, NpyArray::C64(_) | NpyArray::C128(_));
+            Ok((npy_complex(&x), dims, complex))
+        }
+        #[allow(unreachable_patterns)]
+        _=> Err(ArrayInfoError::Disabled(format)),
+    }
+}
+
+/// reads an array file and summarizes its values with describe, detecting the format from its
+/// extension. Complex data is summarized by magnitude
+pub fn describe_array(file:impl AsRef<Path>) -> Result<ArraySummary, ArrayInfoError> {
+    let info = array_info(&file, false)?;
+    let (x, dims, complex) = read_array(&file)?;
+    let summary = if complex {
+        summarize(x.iter().map(|v| complex_magnitude(v.re, v.im)), &dims, &info.dtype, true)
+    } else {
+        summarize(x.iter().map(|v| v.re), &dims, &info.dtype, false)
+    };
+    Ok(summary)
+}
+
+/// the name of a nifti datatype code
+#[cfg(feature = "io-nifti")]
+fn nifti_dtype_name(datatype:i16) -> String {
+    let name = match datatype {
+        2 => "uint8",
+        4 => "int16",
+        8 => "int32",
+        16 => "float32",
+        32 => "complex float32",
+        64 => "float64",
+        128 => "rgb24",
+        256 => "int8",
+        512 => "uint16",
+        768 => "uint32",
+        1024 => "int64",
+        1280 => "uint64",
+        1536 => "float128",
+        1792 => "complex float64",
+        2304 => "rgba32",
+        t => return format!("unknown ({})", t),
+    };
+    name.to_string()
+}
+
+/// the name of an npy type descriptor and whether it is complex
+#[cfg(feature = "io-npy")]
+fn npy_dtype_name(descr:&str) -> (String, bool) {
+    let name = match descr.trim_start_matches(['<', '>', '|', '=']) {
+        "f4" => "float32",
+        "f8" => "float64",
+        "i1" => "int8",
+        "i2" => "int16",
+        "i4" => "int32",
+        "i8" => "int64",
+        "u1" => "uint8",
+        "u2" => "uint16",
+        "u4" => "uint32",
+        "u8" => "uint64",
+        "c8" => "complex float32",
+        "c16" => "complex float64",
+        _=> return (descr.to_string(), false),
+    };
+    (name.to_string(), name.starts_with("complex"))
+}
+
+#[cfg(feature = "io-npy")]
+fn npy_stats(x:&NpyArray) -> ArrayStats {
+    let complex = matches!(x, NpyArray::C64(_) | NpyArray::C128(_));
+    array_stats(npy_complex(x).into_iter(), complex)
+}
+
+/// the values of an npy array as complex numbers
+#[cfg(feature = "io-npy")]
+fn npy_complex(x:&NpyArray) -> Vec<Complex64> {
+    macro_rules! real {
+        ($x:expr) => { $x.iter().map(|&v| Complex64::new(v as f64, 0.)).collect() };
+    }
+    match x {
+        NpyArray::F32(x) => real!(x),
+        NpyArray::F64(x) => real!(x),
+        NpyArray::I8(x) => real!(x),
+        NpyArray::I16(x) => real!(x),
+        NpyArray::I32(x) => real!(x),
+        NpyArray::I64(x) => real!(x),
+        NpyArray::U8(x) => real!(x),
+        NpyArray::U16(x) => real!(x),
+        NpyArray::U32(x) => real!(x),
+        NpyArray::U64(x) => real!(x),
+        NpyArray::C64(x) => x.iter().map(|v| Complex64::new(v.re as f64, v.im as f64)).collect(),
+        NpyArray::C128(x) => x.clone(),
+    }
+}
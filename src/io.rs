@@ -0,0 +1,459 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use num_complex::Complex32;
+use serde::Serialize;
+use crate::ArrayDim;
+#[cfg(feature = "io-cfl")]
+use crate::io_cfl::{cfl_base_name, try_read_cfl, try_write_cfl, CflIoError};
+#[cfg(feature = "io-nifti")]
+use crate::io_nifti::{read_nifti_header, try_read_nifti, try_read_nifti_complex, write_nifti_scaled, write_nifti_with_options, NiftiDtype, NiftiHeader, NiftiIoError, NiftiWriteOptions};
+#[cfg(feature = "io-nrrd")]
+use crate::io_nrrd::{read_nrrd_complex, read_nrrd_header, read_nrrd_scaled, write_nrrd_complex, write_nrrd_with_options, Encoding, NrrdHeader, NrrdIoError, NrrdWriteOptions};
+#[cfg(feature = "io-mrd")]
+use crate::io_mrd::{try_read_mrd, write_mrd, MrdIoError};
+#[cfg(feature = "io-npy")]
+use crate::io_npy::{read_npy_dyn, write_npy, NpyArray, NpyError, Order};
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::ArrayDim;
+    use crate::io::{read_array, write_array, ArrayData, ArrayFormat, ArrayWriteOptions, IoError};
+
+    #[test]
+    fn test_output_format() {
+        assert_eq!(ArrayFormat::for_output("a/b.nii.gz"),Some(ArrayFormat::Nifti));
+        assert_eq!(ArrayFormat::for_output("b.nhdr"),Some(ArrayFormat::Nrrd));
+        assert_eq!(ArrayFormat::for_output("b"),Some(ArrayFormat::Cfl));
+        assert_eq!(ArrayFormat::for_output("b.hdr"),Some(ArrayFormat::Cfl));
+        assert_eq!(ArrayFormat::for_output("b.txt"),None);
+        assert_eq!(ArrayFormat::for_output("b.01_scan.cfl"),Some(ArrayFormat::Cfl));
+
+        let x = ArrayData::Real{data: vec![1.,2.], dims: ArrayDim::from_shape(&[2]), meta: None};
+        assert!(matches!(write_array("test_io_unknown.txt",&x,&ArrayWriteOptions::default()),Err(IoError::UnknownFormat(..))));
+        assert!(matches!(read_array("test_io_unknown.txt"),Err(IoError::UnknownFormat(..))));
+        assert!(!Path::new("test_io_unknown.txt").exists());
+    }
+
+    #[cfg(feature = "io-cfl")]
+    #[test]
+    fn test_dotted_cfl_base() {
+        use num_complex::Complex32;
+        let dims = ArrayDim::from_shape(&[3,2]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,1.)).collect();
+        let data = ArrayData::Complex{data: x.clone(), dims, meta: None};
+
+        // a new dotted base name is given with its .cfl extension, which is the only part dropped
+        let base = write_array("test_io.01_scan.cfl",&data,&ArrayWriteOptions::default()).unwrap();
+        assert_eq!(base,Path::new("test_io.01_scan"));
+        assert_eq!(ArrayFormat::detect("test_io.01_scan"),Some(ArrayFormat::Cfl));
+        assert_eq!(ArrayFormat::for_output("test_io.01_scan"),Some(ArrayFormat::Cfl));
+        let from_base = read_array("test_io.01_scan").unwrap();
+        let from_hdr = read_array("test_io.01_scan.hdr").unwrap();
+        std::fs::remove_file("test_io.01_scan.cfl").unwrap();
+        std::fs::remove_file("test_io.01_scan.hdr").unwrap();
+        assert!(matches!(&from_base,ArrayData::Complex{data,..} if *data == x));
+        assert!(matches!(&from_hdr,ArrayData::Complex{data,..} if *data == x));
+        assert_eq!(ArrayFormat::detect("test_io.01_scan"),None);
+    }
+
+    #[cfg(all(feature = "io-cfl", feature = "io-nifti"))]
+    #[test]
+    fn test_cfl_nifti_round_trip() {
+        use num_complex::Complex32;
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,-(i as f32))).collect();
+        let data = ArrayData::Complex{data: x.clone(), dims, meta: None};
+
+        write_array("test_io_facade",&data,&ArrayWriteOptions::default()).unwrap();
+        let from_cfl = read_array("test_io_facade").unwrap();
+        assert_eq!(from_cfl.dims(),&dims);
+        assert!(matches!(&from_cfl,ArrayData::Complex{data,..} if *data == x));
+
+        // complex data survives a nifti round trip, and the nifti header is kept as metadata
+        let out = write_array("test_io_facade.nii",&from_cfl,&ArrayWriteOptions::default()).unwrap();
+        let from_nifti = read_array(&out).unwrap();
+        std::fs::remove_file("test_io_facade.cfl").unwrap();
+        std::fs::remove_file("test_io_facade.hdr").unwrap();
+        std::fs::remove_file(&out).unwrap();
+        assert!(from_nifti.meta().is_some());
+        assert!(matches!(&from_nifti,ArrayData::Complex{data,..} if *data == x));
+    }
+
+    #[cfg(all(feature = "io-nrrd", feature = "io-npy"))]
+    #[test]
+    fn test_nrrd_npy_round_trip() {
+        let dims = ArrayDim::from_shape(&[5,2,3]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32 * 0.5).collect();
+        let data = ArrayData::Real{data: x.clone(), dims, meta: None};
+
+        let opts = ArrayWriteOptions::new().gzip(true);
+        write_array("test_io_facade.npy",&data,&opts).unwrap();
+        let from_npy = read_array("test_io_facade.npy").unwrap();
+        write_array("test_io_facade.nrrd",&from_npy,&opts).unwrap();
+        let from_nrrd = read_array("test_io_facade.nrrd").unwrap();
+        std::fs::remove_file("test_io_facade.npy").unwrap();
+        std::fs::remove_file("test_io_facade.nrrd").unwrap();
+        assert_eq!(from_nrrd.dims(),&dims);
+        assert!(matches!(&from_nrrd,ArrayData::Real{data,..} if *data == x));
+    }
+}
+
+/// the array file formats, detected from their extensions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrayFormat {
+    Cfl,
+    Nifti,
+    Nrrd,
+    Mrd,
+    Npy,
+}
+
+impl Display for ArrayFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ArrayFormat::Cfl => "cfl",
+            ArrayFormat::Nifti => "nifti",
+            ArrayFormat::Nrrd => "nrrd",
+            ArrayFormat::Mrd => "mrd",
+            ArrayFormat::Npy => "npy",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ArrayFormat {
+
+    /// the format of a file from its extension. A .hdr file is a cfl header only if the matching
+    /// .cfl exists. A path without a known extension is a cfl base name if its .hdr exists, so
+    /// base names containing dots are found
+    pub fn detect(file:impl AsRef<Path>) -> Option<ArrayFormat> {
+        let path = file.as_ref();
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        match Self::from_name(path) {
+            Some(ArrayFormat::Cfl) if name.ends_with(".cfl") => Some(ArrayFormat::Cfl),
+            Some(ArrayFormat::Cfl) if name.ends_with(".hdr") => path.with_extension("cfl").is_file().then_some(ArrayFormat::Cfl),
+            Some(ArrayFormat::Cfl) | None => with_suffix(path, ".hdr").is_file().then_some(ArrayFormat::Cfl),
+            format => format,
+        }
+    }
+
+    /// the format to write a file in from its extension. Paths without an extension and paths
+    /// ending in .cfl or .hdr are cfl base names. A path with an unknown extension is a cfl base
+    /// name only if its .hdr already exists; name new cfls with a dot as `<base>.cfl`
+    pub fn for_output(file:impl AsRef<Path>) -> Option<ArrayFormat> {
+        let path = file.as_ref();
+        Self::from_name(path).or_else(|| with_suffix(path, ".hdr").is_file().then_some(ArrayFormat::Cfl))
+    }
+
+    /// the feature that compiles in support for the format
+    pub fn feature(&self) -> &'static str {
+        match self {
+            ArrayFormat::Cfl => "io-cfl",
+            ArrayFormat::Nifti => "io-nifti",
+            ArrayFormat::Nrrd => "io-nrrd",
+            ArrayFormat::Mrd => "io-mrd",
+            ArrayFormat::Npy => "io-npy",
+        }
+    }
+
+    fn from_name(path:&Path) -> Option<ArrayFormat> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        let format = if name.ends_with(".nii") || name.ends_with(".nii.gz") {
+            ArrayFormat::Nifti
+        } else if name.ends_with(".nrrd") || name.ends_with(".nhdr") {
+            ArrayFormat::Nrrd
+        } else if name.ends_with(".mrd") {
+            ArrayFormat::Mrd
+        } else if name.ends_with(".npy") {
+            ArrayFormat::Npy
+        } else if name.ends_with(".cfl") || name.ends_with(".hdr") || path.extension().is_none() {
+            ArrayFormat::Cfl
+        } else {
+            return None;
+        };
+        Some(format)
+    }
+
+}
+
+/// the header an array was read with, reused when the array is written back to the same format
+#[derive(Debug, Clone)]
+pub enum ArrayMeta {
+    #[cfg(feature = "io-nifti")]
+    Nifti(Box<NiftiHeader>),
+    #[cfg(feature = "io-nrrd")]
+    Nrrd(NrrdHeader),
+    /// the mrd file the array was read from, used as the reference for writing
+    #[cfg(feature = "io-mrd")]
+    Mrd(PathBuf),
+}
+
+/// an array read from or written to any supported format, as real or complex single precision
+/// values with its dims and the header of the file it came from
+#[derive(Debug, Clone)]
+pub enum ArrayData {
+    Real{data: Vec<f32>, dims: ArrayDim, meta: Option<ArrayMeta>},
+    Complex{data: Vec<Complex32>, dims: ArrayDim, meta: Option<ArrayMeta>},
+}
+
+impl ArrayData {
+
+    pub fn dims(&self) -> &ArrayDim {
+        match self {
+            ArrayData::Real{dims, ..} | ArrayData::Complex{dims, ..} => dims,
+        }
+    }
+
+    pub fn meta(&self) -> Option<&ArrayMeta> {
+        match self {
+            ArrayData::Real{meta, ..} | ArrayData::Complex{meta, ..} => meta.as_ref(),
+        }
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(self, ArrayData::Complex{..})
+    }
+
+    /// the values as complex numbers, with zero imaginary parts for real data
+    pub fn to_complex(&self) -> Vec<Complex32> {
+        match self {
+            ArrayData::Real{data, ..} => data.iter().map(|&x| Complex32::new(x, 0.)).collect(),
+            ArrayData::Complex{data, ..} => data.clone(),
+        }
+    }
+
+}
+
+/// options controlling how write_array writes each format
+#[derive(Debug, Clone, Default)]
+pub struct ArrayWriteOptions {
+    /// gzip the data of nrrd outputs. Nifti outputs are compressed by a .nii.gz extension instead
+    pub gzip: bool,
+    /// the stored type of real nifti outputs. Complex nifti outputs are always float32 pairs
+    #[cfg(feature = "io-nifti")]
+    pub nifti_dtype: NiftiDtype,
+}
+
+impl ArrayWriteOptions {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn gzip(mut self, gzip: bool) -> Self {
+        self.gzip = gzip;
+        self
+    }
+
+    #[cfg(feature = "io-nifti")]
+    pub fn nifti_dtype(mut self, nifti_dtype: NiftiDtype) -> Self {
+        self.nifti_dtype = nifti_dtype;
+        self
+    }
+
+}
+
+#[derive(Debug)]
+pub enum IoError {
+    UnknownFormat(PathBuf),
+    /// the format was detected but support for it is not compiled in
+    Disabled(ArrayFormat),
+    /// the array can't be written in the format
+    Unsupported{format: ArrayFormat, msg: String},
+    #[cfg(feature = "io-cfl")]
+    Cfl(CflIoError),
+    #[cfg(feature = "io-nifti")]
+    Nifti(NiftiIoError),
+    #[cfg(feature = "io-nrrd")]
+    Nrrd(NrrdIoError),
+    #[cfg(feature = "io-mrd")]
+    Mrd(MrdIoError),
+    #[cfg(feature = "io-npy")]
+    Npy(NpyError),
+}
+
+impl Display for IoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IoError::UnknownFormat(path) => write!(f, "cannot tell the format of {} from its extension", path.display()),
+            IoError::Disabled(format) => write!(f, "{} support is not enabled. Build with the {} feature", format, format.feature()),
+            IoError::Unsupported{format, msg} => write!(f, "can't write {}: {}", format, msg),
+            #[cfg(feature = "io-cfl")]
+            IoError::Cfl(e) => write!(f, "{}", e),
+            #[cfg(feature = "io-nifti")]
+            IoError::Nifti(e) => write!(f, "{}", e),
+            #[cfg(feature = "io-nrrd")]
+            IoError::Nrrd(e) => write!(f, "{}", e),
+            #[cfg(feature = "io-mrd")]
+            IoError::Mrd(e) => write!(f, "{}", e),
+            #[cfg(feature = "io-npy")]
+            IoError::Npy(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for IoError {}
+
+/// returns true if two paths name the same file, either as given or once resolved. Paths that
+/// don't exist yet only match as given
+pub fn same_file(a:&Path, b:&Path) -> bool {
+    a == b || std::fs::canonicalize(a).ok().is_some_and(|c| std::fs::canonicalize(b).ok() == Some(c))
+}
+
+/// a path with a suffix appended to its file name, as cfl headers and data are named from a base
+fn with_suffix(path:&Path, suffix:&str) -> PathBuf {
+    let mut s = path.as_os_str().to_os_string();
+    s.push(suffix);
+    PathBuf::from(s)
+}
+
+/// reads an array file of any supported format, detecting the format from its extension. Complex
+/// data (cfl, mrd, complex nifti, nrrd and npy) is read as complex values, and everything else as
+/// real values. Nrrds are read with their scaling applied. The header of the file is returned as
+/// metadata for nifti, nrrd and mrd
+pub fn read_array(file:impl AsRef<Path>) -> Result<ArrayData, IoError> {
+    let path = file.as_ref();
+    let format = ArrayFormat::detect(path).ok_or_else(|| IoError::UnknownFormat(path.to_path_buf()))?;
+    match format {
+        #[cfg(feature = "io-cfl")]
+        ArrayFormat::Cfl => {
+            let (data, dims) = try_read_cfl(cfl_base_name(path)).map_err(IoError::Cfl)?;
+            Ok(ArrayData::Complex{data, dims, meta: None})
+        }
+        #[cfg(feature = "io-nifti")]
+        ArrayFormat::Nifti => {
+            let (_, h) = read_nifti_header(path).map_err(IoError::Nifti)?;
+            if matches!(h.datatype, 32 | 1792) {
+                let (data, dims, h) = try_read_nifti_complex::<f32>(path).map_err(IoError::Nifti)?;
+                Ok(ArrayData::Complex{data, dims, meta: Some(ArrayMeta::Nifti(Box::new(h)))})
+            } else {
+                let (data, dims, h) = try_read_nifti::<f32>(path).map_err(IoError::Nifti)?;
+                Ok(ArrayData::Real{data, dims, meta: Some(ArrayMeta::Nifti(Box::new(h)))})
+            }
+        }
+        #[cfg(feature = "io-nrrd")]
+        ArrayFormat::Nrrd => {
+            let (_, h) = read_nrrd_header(path).map_err(IoError::Nrrd)?;
+            let complex = h.sizes().is_some_and(|s| s.first() == Some(&2)) &&
+                h.kinds().first().is_some_and(|k| k == "complex");
+            if complex {
+                let (data, dims, _) = read_nrrd_complex::<f32>(path).map_err(IoError::Nrrd)?;
+                Ok(ArrayData::Complex{data, dims, meta: Some(ArrayMeta::Nrrd(h))})
+            } else {
+                let (data, dims, h) = read_nrrd_scaled(path).map_err(IoError::Nrrd)?;
+                Ok(ArrayData::Real{data, dims, meta: Some(ArrayMeta::Nrrd(h))})
+            }
+        }
+        #[cfg(feature = "io-mrd")]
+        ArrayFormat::Mrd => {
+            let (data, dims, _) = try_read_mrd(path).map_err(IoError::Mrd)?;
+            Ok(ArrayData::Complex{data, dims, meta: Some(ArrayMeta::Mrd(path.to_path_buf()))})
+        }
+        #[cfg(feature = "io-npy")]
+        ArrayFormat::Npy => {
+            let (x, dims) = read_npy_dyn(path).map_err(IoError::Npy)?;
+            macro_rules! real {
+                ($x:expr) => { ArrayData::Real{data: $x.iter().map(|&v| v as f32).collect(), dims, meta: None} };
+            }
+            Ok(match x {
+                NpyArray::F32(data) => ArrayData::Real{data, dims, meta: None},
+                NpyArray::F64(x) => real!(x),
+                NpyArray::I8(x) => real!(x),
+                NpyArray::I16(x) => real!(x),
+                NpyArray::I32(x) => real!(x),
+                NpyArray::I64(x) => real!(x),
+                NpyArray::U8(x) => real!(x),
+                NpyArray::U16(x) => real!(x),
+                NpyArray::U32(x) => real!(x),
+                NpyArray::U64(x) => real!(x),
+                NpyArray::C64(data) => ArrayData::Complex{data, dims, meta: None},
+                NpyArray::C128(x) => ArrayData::Complex{
+                    data: x.iter().map(|v| Complex32::new(v.re as f32, v.im as f32)).collect(), dims, meta: None
+                },
+            })
+        }
+        #[allow(unreachable_patterns)]
+        _=> Err(IoError::Disabled(format)),
+    }
+}
+
+/// writes an array in the format given by the extension of the path, returning the path actually
+/// written. A path without an extension is a cfl base name. Real data written to cfl gets zero
+/// imaginary parts. Metadata is only used when writing back to the format it was read from: nifti
+/// headers are used as the reference header, nrrd headers supply the geometry and key-value pairs,
+/// and an mrd file is used as the reference that mrd outputs require
+pub fn write_array(file:impl AsRef<Path>, array:&ArrayData, opts:&ArrayWriteOptions) -> Result<PathBuf, IoError> {
+    let path = file.as_ref();
+    let format = ArrayFormat::for_output(path).ok_or_else(|| IoError::UnknownFormat(path.to_path_buf()))?;
+    let dims = *array.dims();
+    match format {
+        #[cfg(feature = "io-cfl")]
+        ArrayFormat::Cfl => {
+            let base = cfl_base_name(path);
+            match array {
+                ArrayData::Complex{data, ..} => try_write_cfl(&base, data, dims),
+                ArrayData::Real{..} => try_write_cfl(&base, &array.to_complex(), dims),
+            }.map_err(IoError::Cfl)?;
+            Ok(base)
+        }
+        #[cfg(feature = "io-nifti")]
+        ArrayFormat::Nifti => {
+            let header = match array.meta() {
+                Some(ArrayMeta::Nifti(h)) => Some(h.as_ref()),
+                _=> None,
+            };
+            let nifti_opts = NiftiWriteOptions::new();
+            match array {
+                ArrayData::Real{data, ..} => write_nifti_scaled(path, data, dims, header, opts.nifti_dtype, &nifti_opts),
+                ArrayData::Complex{data, ..} if opts.nifti_dtype == NiftiDtype::Float32 => {
+                    write_nifti_with_options(path, data, dims, header, &nifti_opts)
+                }
+                ArrayData::Complex{..} => {
+                    let msg = format!("complex data can't be stored as {:?}", opts.nifti_dtype);
+                    return Err(IoError::Unsupported{format, msg});
+                }
+            }.map_err(IoError::Nifti)
+        }
+        #[cfg(feature = "io-nrrd")]
+        ArrayFormat::Nrrd => {
+            let detached = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("nhdr"));
+            let encoding = if opts.gzip { Encoding::Gzip } else { Encoding::Raw };
+            let mut nrrd_opts = NrrdWriteOptions::new().attached(!detached).encoding(encoding);
+            if let Some(ArrayMeta::Nrrd(h)) = array.meta() {
+                if let Some(mut geometry) = h.geometry() {
+                    // the complex axis of a complex nrrd isn't one of the array axes
+                    if matches!(array, ArrayData::Complex{..}) && h.kinds().first().is_some_and(|k| k == "complex") {
+                        geometry.axes = geometry.axes.map(|axes| axes.iter().map(|a| a - 1).collect());
+                    }
+                    nrrd_opts = nrrd_opts.with_geometry(geometry);
+                }
+                nrrd_opts = nrrd_opts.with_meta(h.key_values().clone());
+            }
+            match array {
+                ArrayData::Real{data, ..} => write_nrrd_with_options(path, data, dims, &nrrd_opts),
+                ArrayData::Complex{data, ..} => write_nrrd_complex(path, data, dims, &nrrd_opts),
+            }.map_err(IoError::Nrrd)?;
+            Ok(path.to_path_buf())
+        }
+        #[cfg(feature = "io-mrd")]
+        ArrayFormat::Mrd => {
+            let Some(ArrayMeta::Mrd(reference)) = array.meta() else {
+                return Err(IoError::Unsupported{format, msg: String::from("mrd outputs need an array read from an mrd file")});
+            };
+            write_mrd(path, &array.to_complex(), dims, reference).map_err(IoError::Mrd)?;
+            Ok(path.to_path_buf())
+        }
+        #[cfg(feature = "io-npy")]
+        ArrayFormat::Npy => {
+            match array {
+                ArrayData::Real{data, ..} => write_npy(path, data, dims, Order::Fortran),
+                ArrayData::Complex{data, ..} => write_npy(path, data, dims, Order::Fortran),
+            }.map_err(IoError::Npy)?;
+            Ok(path.to_path_buf())
+        }
+        #[allow(unreachable_patterns)]
+        _=> Err(IoError::Disabled(format)),
+    }
+}
@@ -0,0 +1,250 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use crate::ArrayDim;
+
+#[cfg(test)]
+mod tests {
+    use crate::io_analyze::{read_analyze, read_analyze_with_options, AnalyzeIoError, AnalyzeReadOptions};
+
+    /// writes a 3 x 2 x 2 int16 analyze pair with 2 x 2 x 4 mm voxels in the given byte order
+    fn write_fixture(base:&str, big_endian:bool) {
+        let i16_bytes = |x:i16| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() };
+        let i32_bytes = |x:i32| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() };
+        let f32_bytes = |x:f32| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() };
+        let mut h = vec![0u8; 348];
+        h[0..4].copy_from_slice(&i32_bytes(348));
+        h[32..36].copy_from_slice(&i32_bytes(16384));
+        h[38] = b'r';
+        for (i, d) in [3i16, 3, 2, 2, 1].iter().enumerate() {
+            h[40 + 2 * i..42 + 2 * i].copy_from_slice(&i16_bytes(*d));
+        }
+        h[70..72].copy_from_slice(&i16_bytes(4));
+        h[72..74].copy_from_slice(&i16_bytes(16));
+        for (i, p) in [0f32, 2., 2., 4.].iter().enumerate() {
+            h[76 + 4 * i..80 + 4 * i].copy_from_slice(&f32_bytes(*p));
+        }
+        // SPM scale factor
+        h[112..116].copy_from_slice(&f32_bytes(0.5));
+        h[148..152].copy_from_slice(b"test");
+        std::fs::write(format!("{}.hdr", base), h).unwrap();
+        let img:Vec<u8> = (0..12i16).flat_map(|x| i16_bytes(x - 4)).collect();
+        std::fs::write(format!("{}.img", base), img).unwrap();
+    }
+
+    #[test]
+    fn test_byte_swapped() {
+        write_fixture("test_analyze_be",true);
+        let (x,dims,h) = read_analyze("test_analyze_be.img").unwrap();
+        std::fs::remove_file("test_analyze_be.hdr").unwrap();
+        std::fs::remove_file("test_analyze_be.img").unwrap();
+        assert!(h.big_endian);
+        assert_eq!(dims.shape_ns(),&[3,2,2]);
+        assert_eq!(h.pixdim[1..4],[2.,2.,4.]);
+        assert_eq!(h.description,"test");
+        assert_eq!(x,(0..12).map(|x| (x - 4) as f32 * 0.5).collect::<Vec<f32>>());
+    }
+
+    #[test]
+    fn test_flip() {
+        write_fixture("test_analyze_le",false);
+        let (x,dims,h) = read_analyze_with_options("test_analyze_le",&AnalyzeReadOptions::new().flip_lr(true)).unwrap();
+        std::fs::remove_file("test_analyze_le.hdr").unwrap();
+        std::fs::remove_file("test_analyze_le.img").unwrap();
+        assert!(!h.big_endian);
+        for (i,x) in x.iter().enumerate() {
+            let [r,c,s,..] = dims.calc_idx(i);
+            let raw = dims.calc_addr(&[2 - r,c,s]) as f32 - 4.;
+            assert_eq!(*x,raw * 0.5);
+        }
+
+        assert!(matches!(read_analyze("test_analyze_missing.hdr"),Err(AnalyzeIoError::IO(..))));
+    }
+
+}
+
+#[derive(Debug)]
+pub enum AnalyzeIoError {
+    IO(PathBuf, std::io::Error),
+    Header{path: PathBuf, msg: String},
+    Unsupported{path: PathBuf, msg: String},
+    SizeMismatch{path: PathBuf, expected: usize, actual: usize},
+}
+
+impl Display for AnalyzeIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnalyzeIoError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            AnalyzeIoError::Header {path, msg} => write!(f, "invalid analyze header {}: {}", path.display(), msg),
+            AnalyzeIoError::Unsupported {path, msg} => write!(f, "unsupported analyze file {}: {}", path.display(), msg),
+            AnalyzeIoError::SizeMismatch {path, expected, actual} => write!(
+                f, "{} has {} bytes of image data but the header requires {}", path.display(), actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AnalyzeIoError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> AnalyzeIoError {
+    let path = path.to_path_buf();
+    move |e| AnalyzeIoError::IO(path, e)
+}
+
+/// the size of an analyze header, which is also stored as its first field
+const ANALYZE_HEADER_BYTES: usize = 348;
+
+/// the fields of an analyze 7.5 header needed to interpret the image
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalyzeHeader {
+    /// true if the header and image were written big-endian
+    pub big_endian: bool,
+    /// dim[0] is the number of dimensions
+    pub dim: [i16; 8],
+    pub datatype: i16,
+    pub bitpix: i16,
+    /// voxel sizes in pixdim[1..]
+    pub pixdim: [f32; 8],
+    /// byte offset of the data in the .img file
+    pub vox_offset: f32,
+    /// the SPM scale factor (funused1), or 0 if unused
+    pub scale: f32,
+    /// slice orientation code. 0 is transverse, 1 coronal and 2 sagittal, and 3 to 5 are the
+    /// flipped versions of each
+    pub orient: u8,
+    /// the SPM origin in voxels (originator), 1-based
+    pub origin: [i16; 3],
+    pub description: String,
+}
+
+/// options for reading an analyze image
+#[derive(Debug, Clone, Default)]
+pub struct AnalyzeReadOptions {
+    flip_lr: bool,
+}
+
+impl AnalyzeReadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// reverses the first axis. Analyze does not record whether the first axis runs from right
+    /// to left (radiological) or left to right (neurological), so set this when the images are
+    /// known to be radiological to get the same orientation as nifti
+    pub fn flip_lr(mut self, flip_lr:bool) -> Self {
+        self.flip_lr = flip_lr;
+        self
+    }
+}
+
+/// returns the .hdr and .img paths for a path to either file or their shared base name
+fn analyze_paths(path:&Path) -> (PathBuf, PathBuf) {
+    let base = match path.extension().and_then(|e| e.to_str()) {
+        Some("hdr") | Some("img") => path.with_extension(""),
+        _=> path.to_path_buf(),
+    };
+    (base.with_extension("hdr"), base.with_extension("img"))
+}
+
+impl AnalyzeHeader {
+    /// parses a header, detecting the byte order from the sizeof_hdr field
+    fn parse(path:&Path, bytes:&[u8]) -> Result<AnalyzeHeader, AnalyzeIoError> {
+        let header_err = |msg:String| AnalyzeIoError::Header{path: path.to_path_buf(), msg};
+        if bytes.len() < ANALYZE_HEADER_BYTES {
+            return Err(header_err(format!("header is only {} bytes", bytes.len())));
+        }
+        let size:[u8; 4] = bytes[0..4].try_into().unwrap();
+        let big_endian = if i32::from_le_bytes(size) == ANALYZE_HEADER_BYTES as i32 {
+            false
+        } else if i32::from_be_bytes(size) == ANALYZE_HEADER_BYTES as i32 {
+            true
+        } else {
+            return Err(header_err(format!("sizeof_hdr is not {} in either byte order", ANALYZE_HEADER_BYTES)));
+        };
+        let i16_at = |at:usize| {
+            let b = [bytes[at], bytes[at + 1]];
+            if big_endian { i16::from_be_bytes(b) } else { i16::from_le_bytes(b) }
+        };
+        let f32_at = |at:usize| {
+            let b = bytes[at..at + 4].try_into().unwrap();
+            if big_endian { f32::from_be_bytes(b) } else { f32::from_le_bytes(b) }
+        };
+        let description = &bytes[148..228];
+        let end = description.iter().position(|&b| b == 0).unwrap_or(description.len());
+        Ok(AnalyzeHeader {
+            big_endian,
+            dim: std::array::from_fn(|i| i16_at(40 + 2 * i)),
+            datatype: i16_at(70),
+            bitpix: i16_at(72),
+            pixdim: std::array::from_fn(|i| f32_at(76 + 4 * i)),
+            vox_offset: f32_at(108),
+            scale: f32_at(112),
+            orient: bytes[252],
+            origin: std::array::from_fn(|i| i16_at(253 + 2 * i)),
+            description: String::from_utf8_lossy(&description[..end]).trim().to_string(),
+        })
+    }
+
+    /// the shape of the image from dim
+    pub fn shape(&self) -> Vec<usize> {
+        let n = self.dim[0].clamp(1, 7) as usize;
+        self.dim[1..=n].iter().map(|&d| d.max(1) as usize).collect()
+    }
+}
+
+/// reads an analyze 7.5 image from a .hdr/.img pair, given the path to either file or their base
+/// name (see read_analyze_with_options)
+pub fn read_analyze(file:impl AsRef<Path>) -> Result<(Vec<f32>, ArrayDim, AnalyzeHeader), AnalyzeIoError> {
+    read_analyze_with_options(file, &AnalyzeReadOptions::new())
+}
+
+/// reads an analyze 7.5 image, byte-swapping as needed. Values are multiplied by the SPM scale
+/// factor when it is set. The orientation of the first axis is ambiguous in analyze, so the data
+/// is returned in stored order unless flip_lr is set
+pub fn read_analyze_with_options(file:impl AsRef<Path>, opts:&AnalyzeReadOptions) -> Result<(Vec<f32>, ArrayDim, AnalyzeHeader), AnalyzeIoError> {
+    let (hdr_path, img_path) = analyze_paths(file.as_ref());
+    let hdr_bytes = std::fs::read(&hdr_path).map_err(io_err(&hdr_path))?;
+    let h = AnalyzeHeader::parse(&hdr_path, &hdr_bytes)?;
+
+    let dims = ArrayDim::try_from_shape(&h.shape()).map_err(|e| AnalyzeIoError::Header{path: hdr_path.clone(), msg: e.to_string()})?;
+    let word_size = match h.datatype {
+        2 => 1,
+        4 => 2,
+        8 | 16 => 4,
+        64 => 8,
+        d => return Err(AnalyzeIoError::Unsupported{path: hdr_path, msg: format!("datatype {}", d)}),
+    };
+
+    let img = std::fs::read(&img_path).map_err(io_err(&img_path))?;
+    let offset = h.vox_offset.max(0.) as usize;
+    let expected = dims.numel() * word_size;
+    if img.len() < offset + expected {
+        return Err(AnalyzeIoError::SizeMismatch{path: img_path, expected: offset + expected, actual: img.len()});
+    }
+    let samples = img[offset..offset + expected].chunks_exact(word_size);
+    macro_rules! decode {
+        ($t:ty) => {
+            samples.map(|b| {
+                let b = b.try_into().unwrap();
+                (if h.big_endian { <$t>::from_be_bytes(b) } else { <$t>::from_le_bytes(b) }) as f32
+            }).collect()
+        };
+    }
+    let mut data:Vec<f32> = match h.datatype {
+        2 => samples.map(|b| b[0] as f32).collect(),
+        4 => decode!(i16),
+        8 => decode!(i32),
+        16 => decode!(f32),
+        _=> decode!(f64),
+    };
+
+    if h.scale != 0. && h.scale.is_finite() {
+        data.iter_mut().for_each(|x| *x *= h.scale);
+    }
+
+    if opts.flip_lr {
+        let n = dims.shape()[0];
+        data.chunks_exact_mut(n).for_each(|row| row.reverse());
+    }
+
+    Ok((data, dims, h))
+}
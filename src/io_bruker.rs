@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
@@ -8,6 +9,93 @@ use crate::{io_bruker, io_cfl, ArrayDim};
 use bruker_jcamp_rs::{parse_paravision_params, PvError};
 use rayon::prelude::*;
 
+#[cfg(test)]
+mod tests {
+    use crate::io_bruker::{read_bruker_2dseq, read_bruker_jobs, read_spatial_resolution, BrukerDataError, VisuWordType};
+
+    const VISU_PARS:&str = "##TITLE=Parameter List
+##JCAMPDX=4.24
+$$ @vis= VisuCoreSize
+##$VisuCoreFrameCount=4
+##$VisuCoreDim=2
+##$VisuCoreSize=( 2 )
+3 2
+##$VisuCoreWordType=_16BIT_SGN_INT
+##$VisuCoreByteOrder=littleEndian
+##$VisuCoreDataSlope=( 4 )
+1 2 0.5 4
+##$VisuCoreDataOffs=( 4 )
+@4*(1)
+##$VisuFGOrderDescDim=2
+##$VisuFGOrderDesc=( 2 )
+(2, <FG_SLICE>, <>, 0, 2) (2, <FG_ECHO>, <>, 2, 1)
+##END=
+";
+
+    #[test]
+    fn test_2dseq() {
+        let dir = std::path::Path::new("test_bruker_2dseq");
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("visu_pars"),VISU_PARS).unwrap();
+        let raw:Vec<i16> = (0..24).map(|i| i - 5).collect();
+        let bytes:Vec<u8> = raw.iter().flat_map(|x| x.to_le_bytes()).collect();
+        std::fs::write(dir.join("2dseq"),&bytes).unwrap();
+
+        let (x,dims,info) = read_bruker_2dseq(dir).unwrap();
+        assert_eq!(dims.shape_ns(),&[3,2,2,2]);
+        assert_eq!(info.word_type,VisuWordType::I16);
+        assert_eq!(info.frame_groups.iter().map(|g| g.id.as_str()).collect::<Vec<_>>(),vec!["FG_SLICE","FG_ECHO"]);
+        let slopes = [1.,2.,0.5,4.];
+        for (i,(x,r)) in x.iter().zip(&raw).enumerate() {
+            assert_eq!(*x,*r as f32 * slopes[i / 6] + 1.);
+        }
+
+        // a truncated 2dseq is rejected
+        std::fs::write(dir.join("2dseq"),&bytes[..40]).unwrap();
+        let r = read_bruker_2dseq(dir);
+        std::fs::remove_dir_all(dir).unwrap();
+        assert!(matches!(r,Err(BrukerDataError::SeqSize{expected: 48, actual: 40,..})));
+    }
+
+    #[test]
+    fn test_jobs() {
+        let acqp = "##$ACQ_jobs_size=2
+##$ACQ_jobs=( 2 )
+(256, 1536, 1, 0, 0, 0, 1, 0, <job0>, <>, Acquisition) (128, 40, 1, 0, 0, 0, 1, 0, <job1>, <>, 
+Navigator)
+##$ACQ_ReceiverSelect=( 1 )
+Yes
+##END=
+";
+        std::fs::write("test_bruker_acqp_jobs",acqp).unwrap();
+        let jobs = read_bruker_jobs("test_bruker_acqp_jobs").unwrap();
+        std::fs::write("test_bruker_acqp_jobs","##$NR=1\n##END=\n").unwrap();
+        let no_jobs = read_bruker_jobs("test_bruker_acqp_jobs").unwrap();
+        std::fs::remove_file("test_bruker_acqp_jobs").unwrap();
+        assert_eq!(jobs.len(),2);
+        assert_eq!((jobs[0].scan_size,jobs[0].transaction_blocks,jobs[0].title.as_str()),(256,1536,"job0"));
+        assert_eq!((jobs[1].scan_size,jobs[1].transaction_blocks,jobs[1].title.as_str()),(128,40,"job1"));
+        assert!(no_jobs.is_empty());
+    }
+
+    #[test]
+    fn test_spatial_resolution() {
+        let method = "##$PVM_SpatResol=( 2 )
+0.1 0.125
+##$PVM_SliceThick=0.5
+##END=
+";
+        std::fs::write("test_bruker_method_resol",method).unwrap();
+        let resolution = read_spatial_resolution("test_bruker_method_resol").unwrap();
+        std::fs::write("test_bruker_method_resol","##$PVM_SliceThick=0.5\n##END=\n").unwrap();
+        let missing = read_spatial_resolution("test_bruker_method_resol");
+        std::fs::remove_file("test_bruker_method_resol").unwrap();
+        assert_eq!(resolution,vec![0.1,0.125,0.5]);
+        assert!(matches!(missing,Err(BrukerDataError::Method{..})));
+    }
+
+}
+
 
 
 
@@ -33,6 +121,14 @@ pub enum BrukerDataError {
     UnexpectedEOF(PathBuf),
     InconsistentArraySize{expected: usize, actual: usize},
     PV(PvError),
+    SeqNotFound(PathBuf),
+    VisuParsNotFound(PathBuf),
+    VisuPars{path: PathBuf, msg: String},
+    SeqSize{path: PathBuf, expected: usize, actual: usize},
+    IO{path: PathBuf, msg: String},
+    Acqp{path: PathBuf, msg: String},
+    Method{path: PathBuf, msg: String},
+    FidSize{path: PathBuf, padded: usize, continuous: usize, actual: usize},
 }
 
 impl Display for BrukerDataError {
@@ -196,4 +292,523 @@ fn get_acq_pars(acq_dir:impl AsRef<Path>) -> Result<[usize;2],PvError> {
     let n_coils = params.n_coils().unwrap_or(1);
     let n_read = acq_size[0];
     Ok([n_read,n_coils])
-}
\ No newline at end of file
+}
+
+/// the sample type of a 2dseq file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VisuWordType {
+    U8,
+    I16,
+    I32,
+    F32,
+}
+
+impl VisuWordType {
+    fn from_visu(word_type:&str) -> Option<VisuWordType> {
+        match word_type {
+            "_8BIT_UNSGN_INT" => Some(VisuWordType::U8),
+            "_16BIT_SGN_INT" => Some(VisuWordType::I16),
+            "_32BIT_SGN_INT" => Some(VisuWordType::I32),
+            "_32BIT_FLOAT" => Some(VisuWordType::F32),
+            _=> None,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            VisuWordType::U8 => 1,
+            VisuWordType::I16 => 2,
+            VisuWordType::I32 | VisuWordType::F32 => 4,
+        }
+    }
+
+    fn decode(&self, b:&[u8], big_endian:bool) -> f64 {
+        macro_rules! decode {
+            ($t:ty) => {{
+                let b = b.try_into().unwrap();
+                (if big_endian { <$t>::from_be_bytes(b) } else { <$t>::from_le_bytes(b) }) as f64
+            }};
+        }
+        match self {
+            VisuWordType::U8 => b[0] as f64,
+            VisuWordType::I16 => decode!(i16),
+            VisuWordType::I32 => decode!(i32),
+            VisuWordType::F32 => decode!(f32),
+        }
+    }
+}
+
+/// a frame group from VisuFGOrderDesc, such as FG_SLICE or FG_ECHO
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VisuFrameGroup {
+    pub id: String,
+    pub len: usize,
+}
+
+/// the parameters from visu_pars needed to interpret a 2dseq file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VisuInfo {
+    /// the size of each frame (VisuCoreSize)
+    pub core_size: Vec<usize>,
+    pub frame_count: usize,
+    pub word_type: VisuWordType,
+    pub big_endian: bool,
+    /// the per-frame scaling of the stored values
+    pub slopes: Vec<f64>,
+    pub offsets: Vec<f64>,
+    /// frame groups in order from fastest to slowest varying
+    pub frame_groups: Vec<VisuFrameGroup>,
+}
+
+/// splits a JCAMP-DX parameter file such as acqp or visu_pars into its parameters, with the
+/// values left unparsed
+fn jcamp_params(text:&str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut current:Option<(String, String)> = None;
+    for line in text.lines() {
+        if line.starts_with("$$") {
+            continue;
+        }
+        if let Some(record) = line.strip_prefix("##") {
+            if let Some((key, value)) = current.take() {
+                params.insert(key, value);
+            }
+            if let Some((key, value)) = record.split_once('=') {
+                current = Some((key.trim_start_matches('$').to_string(), value.to_string()));
+            }
+        } else if let Some((_, value)) = current.as_mut() {
+            value.push('\n');
+            value.push_str(line);
+        }
+    }
+    if let Some((key, value)) = current {
+        params.insert(key, value);
+    }
+    params
+}
+
+/// splits a parameter value into its elements. The size header of arrays is dropped, strings in
+/// angle brackets and structs in parentheses are kept whole, and run-length encoded values of the
+/// form @n*(x) are expanded
+fn jcamp_values(value:&str) -> Vec<String> {
+    let value = value.trim();
+    let body = match value.strip_prefix('(').and_then(|v| v.split_once(')')) {
+        Some((header, body)) if header.chars().all(|c| c.is_ascii_digit() || c == ',' || c.is_whitespace()) => body,
+        _=> value,
+    };
+    let mut values = vec![];
+    let mut chars = body.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut token = String::new();
+        match c {
+            '<' | '(' => {
+                let close = if c == '<' { '>' } else { ')' };
+                token.push(c);
+                chars.next();
+                for c in chars.by_ref() {
+                    token.push(c);
+                    if c == close {
+                        break;
+                    }
+                }
+            }
+            _=> while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+        }
+        let repeated = token.strip_prefix('@')
+            .and_then(|t| t.split_once("*("))
+            .and_then(|(n, x)| Some((n.parse::<usize>().ok()?, x.strip_suffix(')')?.to_string())));
+        match repeated {
+            Some((n, x)) => values.extend(std::iter::repeat_n(x, n)),
+            None => values.push(token),
+        }
+    }
+    values
+}
+
+impl VisuInfo {
+
+    /// parses the 2dseq parameters from a visu_pars file
+    pub fn from_file(visu_pars:impl AsRef<Path>) -> Result<VisuInfo, BrukerDataError> {
+        let path = visu_pars.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| BrukerDataError::IO{path: path.to_path_buf(), msg: e.to_string()})?;
+        let params = jcamp_params(&text);
+        let err = |msg:String| BrukerDataError::VisuPars{path: path.to_path_buf(), msg};
+
+        let values = |key:&str| params.get(key).map(|v| jcamp_values(v));
+        let numbers = |key:&str| -> Result<Option<Vec<f64>>, BrukerDataError> {
+            values(key).map(|v| v.iter().map(|x| x.parse::<f64>()).collect::<Result<Vec<_>, _>>())
+                .transpose().map_err(|_| err(format!("{} is not a list of numbers", key)))
+        };
+        let scalar = |key:&str| params.get(key).map(|v| v.trim().to_string());
+
+        let core_size:Vec<usize> = numbers("VisuCoreSize")?
+            .ok_or_else(|| err(String::from("VisuCoreSize not found")))?
+            .iter().map(|&x| x as usize).collect();
+        if core_size.contains(&0) {
+            return Err(err(format!("invalid VisuCoreSize {:?}", core_size)));
+        }
+        let frame_count = match scalar("VisuCoreFrameCount") {
+            Some(n) => n.parse().map_err(|_| err(format!("invalid VisuCoreFrameCount {}", n)))?,
+            None => 1,
+        };
+        let word_type = scalar("VisuCoreWordType").ok_or_else(|| err(String::from("VisuCoreWordType not found")))?;
+        let word_type = VisuWordType::from_visu(&word_type).ok_or_else(|| err(format!("unsupported word type {}", word_type)))?;
+        let big_endian = scalar("VisuCoreByteOrder").is_some_and(|b| b == "bigEndian");
+
+        // scaling is given per frame, or once for all frames
+        let per_frame = |key:&str, default:f64| -> Result<Vec<f64>, BrukerDataError> {
+            match numbers(key)? {
+                None => Ok(vec![default; frame_count]),
+                Some(x) if x.len() == 1 => Ok(vec![x[0]; frame_count]),
+                Some(x) if x.len() == frame_count => Ok(x),
+                Some(x) => Err(err(format!("{} has {} values for {} frames", key, x.len(), frame_count))),
+            }
+        };
+        let slopes = per_frame("VisuCoreDataSlope", 1.)?;
+        let offsets = per_frame("VisuCoreDataOffs", 0.)?;
+
+        let mut frame_groups = vec![];
+        for desc in values("VisuFGOrderDesc").unwrap_or_default() {
+            let fields:Vec<&str> = desc.trim_start_matches('(').trim_end_matches(')').split(',').map(|f| f.trim()).collect();
+            let len = fields[0].parse().map_err(|_| err(format!("invalid frame group {}", desc)))?;
+            let id = fields.get(1).map(|id| id.trim_matches(|c| c == '<' || c == '>')).unwrap_or_default();
+            frame_groups.push(VisuFrameGroup{id: id.to_string(), len});
+        }
+
+        Ok(VisuInfo{core_size, frame_count, word_type, big_endian, slopes, offsets, frame_groups})
+    }
+
+    /// the shape of the image volume, which is the frame size followed by the frame groups. If
+    /// the frame groups do not account for all frames, the frames are stacked along one axis
+    pub fn shape(&self) -> Vec<usize> {
+        let mut shape = self.core_size.clone();
+        let grouped:usize = self.frame_groups.iter().map(|g| g.len).product();
+        if !self.frame_groups.is_empty() && grouped == self.frame_count {
+            shape.extend(self.frame_groups.iter().map(|g| g.len));
+        } else {
+            if !self.frame_groups.is_empty() {
+                crate::log_event!(warn, "frame groups account for {} of {} frames. Frames will not be grouped", grouped, self.frame_count);
+            }
+            shape.push(self.frame_count);
+        }
+        shape
+    }
+}
+
+/// reads reconstructed images from a 2dseq file with its visu_pars in a processed data directory
+/// (pdata/n). Stored values are scaled per frame by VisuCoreDataSlope and VisuCoreDataOffs, and
+/// frames are grouped into axes by VisuFGOrderDesc, so a multi-slice multi-echo scan has the shape
+/// [x, y, slices, echoes]
+pub fn read_bruker_2dseq(proc_dir:impl AsRef<Path>) -> Result<(Vec<f32>, ArrayDim, VisuInfo), BrukerDataError> {
+    let seq_file = proc_dir.as_ref().join("2dseq");
+    let visu_file = proc_dir.as_ref().join("visu_pars");
+
+    if !seq_file.is_file() {
+        Err(BrukerDataError::SeqNotFound(seq_file.clone()))?
+    }
+
+    if !visu_file.is_file() {
+        Err(BrukerDataError::VisuParsNotFound(visu_file.clone()))?
+    }
+
+    let info = VisuInfo::from_file(&visu_file)?;
+    let shape = info.shape();
+    if shape.len() > crate::N_DIMS {
+        return Err(BrukerDataError::VisuPars{path: visu_file, msg: format!("{} dimensions are not supported", shape.len())});
+    }
+    let dims = ArrayDim::try_from_shape(&shape).map_err(|e| BrukerDataError::VisuPars{path: visu_file.clone(), msg: e.to_string()})?;
+
+    let bytes = std::fs::read(&seq_file).map_err(|e| BrukerDataError::IO{path: seq_file.clone(), msg: e.to_string()})?;
+    let word_size = info.word_type.size();
+    if bytes.len() != dims.numel() * word_size {
+        return Err(BrukerDataError::SeqSize{path: seq_file, expected: dims.numel() * word_size, actual: bytes.len()});
+    }
+
+    let frame_len:usize = info.core_size.iter().product();
+    let mut data = dims.alloc(0f32);
+    data.par_chunks_exact_mut(frame_len)
+        .zip(bytes.par_chunks_exact(frame_len * word_size))
+        .enumerate()
+        .for_each(|(frame, (x, b))| {
+            let (slope, offset) = (info.slopes[frame], info.offsets[frame]);
+            x.iter_mut().zip(b.chunks_exact(word_size)).for_each(|(x, b)| {
+                *x = (info.word_type.decode(b, info.big_endian) * slope + offset) as f32;
+            });
+        });
+
+    Ok((data, dims, info))
+}
+
+/// a ParaVision 360 acquisition job from ACQ_jobs. Each job is written to its own rawdata.job<n>
+/// file, with job 0 holding the imaging data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BrukerJob {
+    /// the size of each scan (readout), in the same units as ACQ_size[0]
+    pub scan_size: usize,
+    /// the number of scans in the job
+    pub transaction_blocks: usize,
+    pub title: String,
+}
+
+/// reads the acquisition jobs from an acqp file. Acquisitions from ParaVision versions without
+/// jobs return an empty list
+pub fn read_bruker_jobs(acqp_file:impl AsRef<Path>) -> Result<Vec<BrukerJob>, BrukerDataError> {
+    let path = acqp_file.as_ref();
+    let text = std::fs::read_to_string(path).map_err(|e| BrukerDataError::IO{path: path.to_path_buf(), msg: e.to_string()})?;
+    let params = jcamp_params(&text);
+    let Some(value) = params.get("ACQ_jobs") else {
+        return Ok(vec![]);
+    };
+    jcamp_values(value).iter().map(|job| {
+        let fields:Vec<&str> = job.trim_start_matches('(').trim_end_matches(')').split(',').map(|f| f.trim()).collect();
+        let invalid = || BrukerDataError::Acqp{path: path.to_path_buf(), msg: format!("invalid ACQ_jobs entry {}", job)};
+        let scan_size = fields[0].parse().map_err(|_| invalid())?;
+        let transaction_blocks = fields.get(1).and_then(|f| f.parse().ok()).ok_or_else(invalid)?;
+        let title = fields.iter()
+            .filter_map(|f| f.strip_prefix('<').and_then(|f| f.strip_suffix('>')))
+            .find(|f| !f.is_empty())
+            .unwrap_or_default();
+        Ok(BrukerJob{scan_size, transaction_blocks, title: title.to_string()})
+    }).collect()
+}
+
+/// the format of each real and imaginary word in the fid file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    I16,
+    I32,
+    F32,
+}
+
+impl SampleFormat {
+    /// detects the format from GO_raw_data_format, falling back to ACQ_word_size
+    pub fn from_acqp(raw_data_format:Option<&str>, word_size:&str) -> Option<SampleFormat> {
+        match raw_data_format {
+            Some("GO_16BIT_SGN_INT") => return Some(SampleFormat::I16),
+            Some("GO_32BIT_SGN_INT") => return Some(SampleFormat::I32),
+            Some("GO_32BIT_FLOAT") => return Some(SampleFormat::F32),
+            _=> {}
+        }
+        match word_size {
+            "_16_BIT" => Some(SampleFormat::I16),
+            "_32_BIT" => Some(SampleFormat::I32),
+            _=> None,
+        }
+    }
+
+    /// bytes per complex data point
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            SampleFormat::I16 => 4,
+            SampleFormat::I32 | SampleFormat::F32 => 8,
+        }
+    }
+
+    /// the largest value a word can hold, or None for floating point words
+    pub fn max_value(&self) -> Option<f32> {
+        match self {
+            SampleFormat::I16 => Some(i16::MAX as f32),
+            SampleFormat::I32 => Some(i32::MAX as f32),
+            SampleFormat::F32 => None,
+        }
+    }
+}
+
+/// how readouts are laid out in the fid file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FidLayout {
+    /// each readout is padded to a multiple of BLOCK_SIZE
+    Padded,
+    /// readouts are packed without padding (GO_block_size = continuous, or PV360 rawdata.job0)
+    Continuous,
+}
+
+impl FidLayout {
+    /// bytes from the start of one readout to the next
+    pub fn chunk_stride(&self, chunk_size_samples:usize, format:SampleFormat) -> usize {
+        let chunk_bytes = chunk_size_samples * format.bytes_per_sample();
+        match self {
+            FidLayout::Padded => chunk_bytes.div_ceil(BLOCK_SIZE) * BLOCK_SIZE,
+            FidLayout::Continuous => chunk_bytes,
+        }
+    }
+
+    pub fn file_size(&self, chunk_size_samples:usize, total_samples:usize, format:SampleFormat) -> usize {
+        total_samples / chunk_size_samples * self.chunk_stride(chunk_size_samples, format)
+    }
+
+    /// the layout that matches the size of a file. Padded is preferred when readouts already fill
+    /// whole blocks, in which case the layouts are the same
+    pub fn detect(file_size:usize, chunk_size_samples:usize, total_samples:usize, format:SampleFormat) -> Option<FidLayout> {
+        [FidLayout::Padded, FidLayout::Continuous].into_iter()
+            .find(|l| l.file_size(chunk_size_samples, total_samples, format) == file_size)
+    }
+}
+
+/// how samples are stored in the fid file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FidEncoding {
+    pub layout: FidLayout,
+    pub format: SampleFormat,
+    pub byte_order: ByteOrder,
+}
+
+/// the byte order of the fid file from BYTORDA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+/// decodes the leading samples of a chunk of fid bytes into complex values, swapping bytes as needed
+pub fn decode_fid_chunk(chunk_bytes:&[u8], format:SampleFormat, byte_order:ByteOrder, fid_data:&mut [Complex32]) {
+    let x = &chunk_bytes[0..fid_data.len() * format.bytes_per_sample()]; // only read the bytes we care about
+    let word_size = format.bytes_per_sample() / 2;
+    macro_rules! word {
+        ($t:ty, $b:expr) => {{
+            let b = $b.try_into().unwrap();
+            if byte_order == ByteOrder::Big { <$t>::from_be_bytes(b) } else { <$t>::from_le_bytes(b) }
+        }};
+    }
+    x.chunks_exact(2 * word_size).zip(fid_data.iter_mut()).for_each(|(i,f)| {
+        let (re, im) = i.split_at(word_size);
+        *f = match format {
+            SampleFormat::I16 => Complex32::new(word!(i16, re) as f32, word!(i16, im) as f32),
+            SampleFormat::I32 => Complex32::new(word!(i32, re) as f32, word!(i32, im) as f32),
+            SampleFormat::F32 => Complex32::new(word!(f32, re), word!(f32, im)),
+        };
+    });
+}
+
+/// the acquisition size and sample encoding of a fid file, read from acqp
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FidParams {
+    /// ACQ_size, with the readout size first
+    pub acq_size: Vec<usize>,
+    /// the number of active receivers
+    pub receivers: usize,
+    pub n_echoes: usize,
+    pub n_repeats: usize,
+    /// the expected encoding. The layout is Continuous when GO_block_size is continuous and
+    /// Padded otherwise, and should be checked against the size of the fid file
+    pub encoding: FidEncoding,
+}
+
+impl FidParams {
+    /// dims of [samples, receivers, echoes, y, z, repeats], where any phase encodes past the
+    /// second are folded into z. Each group of readouts spanning the first 3 axes is contiguous in
+    /// the fid file
+    pub fn dims(&self) -> ArrayDim {
+        let dim_y = self.acq_size.get(1).copied().unwrap_or(1);
+        let dim_z = self.acq_size.iter().skip(2).product::<usize>();
+        ArrayDim::from_shape(&[self.acq_size[0], self.receivers, self.n_echoes, dim_y, dim_z, self.n_repeats])
+    }
+}
+
+/// reads the acquisition size and sample encoding of a fid file from ACQ_size,
+/// ACQ_ReceiverSelect, NECHOES, NR, GO_raw_data_format (or ACQ_word_size), BYTORDA and
+/// GO_block_size. Data is assumed to be little-endian when BYTORDA is missing
+pub fn read_fid_params(acqp_file:impl AsRef<Path>) -> Result<FidParams, BrukerDataError> {
+    let path = acqp_file.as_ref();
+    if !path.is_file() {
+        return Err(BrukerDataError::ACQPNotFound(path.to_path_buf()));
+    }
+    let acqp = parse_paravision_params(path)?;
+    let acqp_err = |msg:String| BrukerDataError::Acqp{path: path.to_path_buf(), msg};
+    let param = |name:&str| acqp.params.get(name).ok_or_else(|| acqp_err(format!("{} not found", name)));
+    let invalid = |name:&str| acqp_err(format!("{} has an unexpected format", name));
+
+    let acq_size = param("ACQ_size")?.to_vec_usize().filter(|s| !s.is_empty()).ok_or_else(|| invalid("ACQ_size"))?;
+    if acq_size.contains(&0) {
+        return Err(acqp_err(format!("ACQ_size {:?} has a size of 0", acq_size)));
+    }
+    let receivers = param("ACQ_ReceiverSelect")?.to_vec_bool().ok_or_else(|| invalid("ACQ_ReceiverSelect"))?
+        .iter().filter(|r| **r).count();
+    let n_echoes = param("NECHOES")?.to_usize().ok_or_else(|| invalid("NECHOES"))?;
+    let n_repeats = param("NR")?.to_usize().ok_or_else(|| invalid("NR"))?;
+
+    let word_size = param("ACQ_word_size")?.to_string();
+    let raw_data_format = acqp.params.get("GO_raw_data_format").map(|f| f.to_string());
+    let format = SampleFormat::from_acqp(raw_data_format.as_deref(), &word_size)
+        .ok_or_else(|| acqp_err(format!("unsupported sample format {}", raw_data_format.unwrap_or(word_size))))?;
+    let byte_order = match acqp.params.get("BYTORDA").map(|b| b.to_string()).as_deref() {
+        Some("big") => ByteOrder::Big,
+        Some("little") | None => ByteOrder::Little,
+        Some(b) => return Err(acqp_err(format!("unexpected byte order {}", b))),
+    };
+    let layout = match acqp.params.get("GO_block_size").map(|b| b.to_string()).as_deref() {
+        Some("continuous") => FidLayout::Continuous,
+        _=> FidLayout::Padded,
+    };
+
+    Ok(FidParams {
+        acq_size,
+        receivers: receivers.max(1),
+        n_echoes: n_echoes.max(1),
+        n_repeats: n_repeats.max(1),
+        encoding: FidEncoding { layout, format, byte_order },
+    })
+}
+
+/// decodes every readout group of a fid file held in memory, giving data with the dims of
+/// FidParams::dims
+pub fn decode_fid(fid_bytes:&[u8], enc:FidEncoding, dims:&ArrayDim) -> Vec<Complex32> {
+    let chunk_size_samples = dims.shape()[..3].iter().product::<usize>();
+    let stride = enc.layout.chunk_stride(chunk_size_samples, enc.format);
+    let mut fid_data = vec![Complex32::ZERO; dims.numel()];
+    fid_data.par_chunks_exact_mut(chunk_size_samples).zip(fid_bytes.par_chunks(stride)).for_each(|(f, chunk)| {
+        decode_fid_chunk(chunk, enc.format, enc.byte_order, f);
+    });
+    fid_data
+}
+
+/// checks the layout of an encoding against the size of a fid file, switching to the layout the
+/// size matches if it differs. ParaVision 360 rawdata.job files are always continuous
+pub fn resolve_fid_layout(fid_file:impl AsRef<Path>, dims:&ArrayDim, mut enc:FidEncoding) -> Result<FidEncoding, BrukerDataError> {
+    let path = fid_file.as_ref();
+    if path.file_name().is_some_and(|n| n.to_string_lossy().starts_with("rawdata.job")) {
+        enc.layout = FidLayout::Continuous;
+    }
+    let actual = std::fs::metadata(path).map_err(|e| BrukerDataError::IO{path: path.to_path_buf(), msg: e.to_string()})?.len() as usize;
+    let chunk_size_samples = dims.shape()[..3].iter().product::<usize>();
+    if actual != enc.layout.file_size(chunk_size_samples, dims.numel(), enc.format) {
+        let detected = FidLayout::detect(actual, chunk_size_samples, dims.numel(), enc.format).ok_or(BrukerDataError::FidSize{
+            path: path.to_path_buf(),
+            padded: FidLayout::Padded.file_size(chunk_size_samples, dims.numel(), enc.format),
+            continuous: FidLayout::Continuous.file_size(chunk_size_samples, dims.numel(), enc.format),
+            actual,
+        })?;
+        crate::log_event!(warn, "{} size matches the {:?} layout rather than {:?}", path.display(), detected, enc.layout);
+        enc.layout = detected;
+    }
+    Ok(enc)
+}
+
+/// reads the voxel size in mm from PVM_SpatResol in a method file. For 2D acquisitions the slice
+/// thickness from PVM_SliceThick is appended when present
+pub fn read_spatial_resolution(method_file:impl AsRef<Path>) -> Result<Vec<f64>, BrukerDataError> {
+    let path = method_file.as_ref();
+    let text = std::fs::read_to_string(path).map_err(|e| BrukerDataError::IO{path: path.to_path_buf(), msg: e.to_string()})?;
+    let params = jcamp_params(&text);
+    let err = |msg:String| BrukerDataError::Method{path: path.to_path_buf(), msg};
+    let numbers = |key:&str| -> Result<Option<Vec<f64>>, BrukerDataError> {
+        params.get(key).map(|v| jcamp_values(v).iter().map(|x| x.parse::<f64>()).collect::<Result<Vec<_>, _>>())
+            .transpose().map_err(|_| err(format!("{} is not a list of numbers", key)))
+    };
+    let mut resolution = numbers("PVM_SpatResol")?.ok_or_else(|| err(String::from("PVM_SpatResol not found")))?;
+    let thickness = numbers("PVM_SliceThick")?.and_then(|t| t.first().copied());
+    if let Some(thickness) = thickness.filter(|_| resolution.len() == 2) {
+        resolution.push(thickness);
+    }
+    Ok(resolution)
+}
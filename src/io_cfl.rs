@@ -1,23 +1,1404 @@
-use std::path::Path;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::mem::MaybeUninit;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use memmap2::{Mmap, MmapMut};
 use num_complex::Complex32;
-use crate::ArrayDim;
+use rayon::prelude::*;
+use crate::{AllocError, ArrayDim, N_DIMS};
 use cfl;
 
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex32;
+    use crate::ArrayDim;
+    use crate::io_cfl::{try_write_cfl_with_options, CflWriteOptions, read_cfl_series, write_cfl_series, CflSeries, write_cfl_from_parts, write_cfl_from_real, write_cfl_from_real_f64, read_cfl_magnitude, read_cfl_real, try_read_cfl_magnitude, CflChunkWriter, CflView, CflViewMut, read_cfl_region, read_cfl_slab, cfl_paths, read_cfl, read_cfl_shape, try_read_cfl, try_write_cfl, write_cfl, CflIoError, process_cfl_chunks, process_cfl_chunks_with_options, CflPipelineOptions, write_ranges};
+
+    #[test]
+    fn test_round_trip() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,-(i as f32))).collect();
+        write_cfl("test_cfl_round_trip",&x,dims);
+        let (y,read_dims) = read_cfl("test_cfl_round_trip");
+        let (hdr,cfl) = cfl_paths("test_cfl_round_trip");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert_eq!(x,y);
+        assert_eq!(dims.shape(),read_dims.shape());
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_read_cfl() {
+        // compares read_cfl against the previous zero-initialized single threaded read. Run with
+        // cargo test --release --features io-cfl bench_read_cfl -- --ignored --nocapture
+        use std::io::Read;
+        let dims = ArrayDim::from_shape(&[512,512,256]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,-(i as f32))).collect();
+        write_cfl("bench_read_cfl",&x,dims);
+        let (_,cfl) = cfl_paths("bench_read_cfl");
+
+        let now = std::time::Instant::now();
+        let mut reference = vec![Complex32::ZERO; dims.numel()];
+        std::fs::File::open(&cfl).unwrap().read_exact(bytemuck::cast_slice_mut(&mut reference)).unwrap();
+        let t_ref = now.elapsed();
+
+        let now = std::time::Instant::now();
+        let (y,_) = read_cfl("bench_read_cfl");
+        let t = now.elapsed();
+
+        let (hdr,cfl) = cfl_paths("bench_read_cfl");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert_eq!(y,reference);
+        println!("reference: {:?}, read_cfl: {:?}",t_ref,t);
+    }
+
+    #[test]
+    fn test_parallel_write() {
+        // ranges that don't divide the data evenly
+        let bytes:Vec<u8> = (0..100_003u32).map(|i| (i * 31 % 251) as u8).collect();
+        write_ranges(std::path::Path::new("test_cfl_ranges.bin"),&bytes,4096).unwrap();
+        let written = std::fs::read("test_cfl_ranges.bin").unwrap();
+        std::fs::remove_file("test_cfl_ranges.bin").unwrap();
+        assert_eq!(written,bytes);
+
+        // small files fall back to a sequential write
+        let dims = ArrayDim::from_shape(&[5,4,3]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,1.)).collect();
+        let opts = CflWriteOptions::new().parallel(true).sync(true);
+        try_write_cfl_with_options("test_cfl_parallel_write",&x,dims,&opts).unwrap();
+        let (y,y_dims) = try_read_cfl("test_cfl_parallel_write").unwrap();
+        let (hdr,cfl) = cfl_paths("test_cfl_parallel_write");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert_eq!(y,x);
+        assert_eq!(y_dims.shape(),dims.shape());
+    }
+
+    #[test]
+    #[ignore]
+    fn test_parallel_write_large() {
+        // compares checksums of a 3 GB cfl written in parallel and sequentially. Run with
+        // cargo test --release --features io-cfl test_parallel_write_large -- --ignored --nocapture
+        use std::hash::Hasher;
+        use std::io::Read;
+        let checksum = |path:&std::path::Path| {
+            let mut f = std::fs::File::open(path).unwrap();
+            let mut h = std::collections::hash_map::DefaultHasher::new();
+            let mut buf = vec![0u8; 1 << 24];
+            loop {
+                let n = f.read(&mut buf).unwrap();
+                if n == 0 {
+                    break h.finish();
+                }
+                h.write(&buf[..n]);
+            }
+        };
+        let dims = ArrayDim::from_shape(&[1024,1024,384]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,-(i as f32))).collect();
+
+        let now = std::time::Instant::now();
+        try_write_cfl_with_options("test_cfl_sequential",&x,dims,&CflWriteOptions::new()).unwrap();
+        let t_seq = now.elapsed();
+        let now = std::time::Instant::now();
+        try_write_cfl_with_options("test_cfl_parallel",&x,dims,&CflWriteOptions::new().parallel(true)).unwrap();
+        let t_par = now.elapsed();
+
+        let (seq_hdr,seq) = cfl_paths("test_cfl_sequential");
+        let (par_hdr,par) = cfl_paths("test_cfl_parallel");
+        let (seq_sum,par_sum) = (checksum(&seq),checksum(&par));
+        for f in [seq_hdr,seq,par_hdr,par] {
+            std::fs::remove_file(f).unwrap();
+        }
+        assert_eq!(seq_sum,par_sum);
+        println!("sequential: {:?}, parallel: {:?}",t_seq,t_par);
+    }
+
+    #[test]
+    fn test_large_read() {
+        // spans more than one parallel read range
+        let dims = ArrayDim::from_shape(&[1024,1024,9]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,1.)).collect();
+        write_cfl("test_cfl_large_read",&x,dims);
+        let (y,_) = read_cfl("test_cfl_large_read");
+        let (hdr,cfl) = cfl_paths("test_cfl_large_read");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert!(y == x);
+    }
+
+    #[test]
+    fn test_hdr_dims() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,0.)).collect();
+        let (hdr,cfl) = cfl_paths("test_cfl_hdr_dims");
+
+        // trailing singletons are trimmed to BART's minimum of 5 by default
+        write_cfl("test_cfl_hdr_dims",&x,dims);
+        assert_eq!(std::fs::read_to_string(&hdr).unwrap(),"# Dimensions\n4 3 2 1 1 \n");
+        let (y,y_dims) = read_cfl("test_cfl_hdr_dims");
+        assert_eq!(y,x);
+        assert_eq!(y_dims.shape(),dims.shape());
+
+        // a rank keeps trailing singletons up to it in place of the minimum of 5
+        try_write_cfl_with_options("test_cfl_hdr_dims",&x,dims,&CflWriteOptions::new().rank(4)).unwrap();
+        assert_eq!(read_cfl_shape("test_cfl_hdr_dims").unwrap(),vec![4,3,2,1]);
+        try_write_cfl_with_options("test_cfl_hdr_dims",&x,dims,&CflWriteOptions::new().rank(2)).unwrap();
+        assert_eq!(read_cfl_shape("test_cfl_hdr_dims").unwrap(),vec![4,3,2]);
+
+        try_write_cfl_with_options("test_cfl_hdr_dims",&x,dims,&CflWriteOptions::new().full_dims(true)).unwrap();
+        let line = std::fs::read_to_string(&hdr).unwrap().lines().nth(1).unwrap().to_string();
+        assert_eq!(line.split_whitespace().count(),16);
+        let (y,y_dims) = read_cfl("test_cfl_hdr_dims");
+        assert_eq!(y,x);
+        assert_eq!(y_dims.shape(),dims.shape());
+
+        // headers with fewer than 5 entries are also accepted
+        std::fs::write(&hdr,"# Dimensions\n24\n").unwrap();
+        let (_,y_dims) = read_cfl("test_cfl_hdr_dims");
+        assert_eq!(y_dims.shape_ns(),&[24]);
+
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_cfl() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        try_write_cfl("test_cfl_truncated",&dims.alloc(Complex32::ONE),dims).unwrap();
+        let (hdr,cfl) = cfl_paths("test_cfl_truncated");
+        std::fs::OpenOptions::new().write(true).open(&cfl).unwrap().set_len(100).unwrap();
+        let r = try_read_cfl("test_cfl_truncated");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        match r {
+            Err(CflIoError::SizeMismatch {expected,actual,..}) => {
+                assert_eq!(expected,24 * 8);
+                assert_eq!(actual,100);
+            }
+            _=> panic!("expected a size mismatch error"),
+        }
+    }
+
+    #[test]
+    fn test_garbage_hdr() {
+        let (hdr,cfl) = cfl_paths("test_cfl_garbage");
+        std::fs::write(&hdr,"not a cfl header\n").unwrap();
+        std::fs::write(&cfl,[0u8;8]).unwrap();
+        let r1 = try_read_cfl("test_cfl_garbage");
+        std::fs::write(&hdr,"# Dimensions\n4 x 2\n").unwrap();
+        let r2 = try_read_cfl("test_cfl_garbage");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert!(matches!(r1,Err(CflIoError::MalformedHeader{..})));
+        assert!(matches!(r2,Err(CflIoError::MalformedHeader{..})));
+    }
+
+    #[test]
+    fn test_missing_files() {
+        assert!(matches!(try_read_cfl("does_not_exist"),Err(CflIoError::MissingHeader(..))));
+        let (hdr,_) = cfl_paths("test_cfl_missing_data");
+        std::fs::write(&hdr,"# Dimensions\n1 1 \n").unwrap();
+        let r = try_read_cfl("test_cfl_missing_data");
+        std::fs::remove_file(hdr).unwrap();
+        assert!(matches!(r,Err(CflIoError::MissingData(..))));
+    }
+
+    #[test]
+    fn test_write_size_mismatch() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let r = try_write_cfl("test_cfl_bad_len",&[Complex32::ZERO;10],dims);
+        assert!(matches!(r,Err(CflIoError::InconsistentArraySize{expected:24,actual:10})));
+        assert!(!cfl_paths("test_cfl_bad_len").0.exists());
+    }
+
+    #[test]
+    fn test_read_region() {
+        let dims = ArrayDim::from_shape(&[6,5,4,3]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,1.)).collect();
+        write_cfl("test_cfl_region",&x,dims);
+
+        // crosses several non-contiguous runs along axes 1, 2 and 3
+        let offset = [1,2,1,1];
+        let size = [3,2,2,2];
+        let (region,region_dims) = read_cfl_region("test_cfl_region",&offset,&size).unwrap();
+        let (expected,expected_dims) = dims.copy_region(&x,&offset,&size);
+        assert_eq!(region,expected);
+        assert_eq!(region_dims.shape(),expected_dims.shape());
+
+        let (slab,slab_dims) = read_cfl_slab("test_cfl_region",2,3).unwrap();
+        let (expected,_) = dims.copy_region(&x,&[0,0,3],&[6,5,1,3]);
+        assert_eq!(slab,expected);
+        assert_eq!(slab_dims.shape_ns(),&[6,5,1,3]);
+
+        let bad = read_cfl_region("test_cfl_region",&[5],&[2]);
+        let bad_axis = read_cfl_slab("test_cfl_region",3,3);
+
+        let (hdr,cfl) = cfl_paths("test_cfl_region");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert!(matches!(bad,Err(CflIoError::InvalidRegion(..))));
+        assert!(matches!(bad_axis,Err(CflIoError::InvalidRegion(..))));
+    }
+
+    #[test]
+    fn test_cfl_view() {
+        let dims = ArrayDim::from_shape(&[6,5,4]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,-(i as f32))).collect();
+        write_cfl("test_cfl_view",&x,dims);
+
+        let view = CflView::open("test_cfl_view").unwrap();
+        assert_eq!(view.dims().shape(),dims.shape());
+        assert_eq!(view.get(&[2,3,1]),x[dims.calc_addr(&[2,3,1])]);
+        let (expected,_) = dims.copy_region(&x,&[0,2],&[6,1,4]);
+        assert_eq!(view.slab(1,2),expected);
+        if let Some(s) = view.as_slice() {
+            assert_eq!(s,x.as_slice());
+        }
+        assert_eq!(view.to_vec(),x);
+        drop(view);
+
+        let mut view = CflViewMut::open("test_cfl_view").unwrap();
+        view.set(&[1,1,1],Complex32::new(100.,200.));
+        if let Some(s) = view.as_mut_slice() {
+            s[0] = Complex32::new(-1.,-2.);
+        } else {
+            view.set(&[0,0,0],Complex32::new(-1.,-2.));
+        }
+        view.flush().unwrap();
+        drop(view);
+
+        let (y,_) = read_cfl("test_cfl_view");
+        let (hdr,cfl) = cfl_paths("test_cfl_view");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert_eq!(y[dims.calc_addr(&[1,1,1])],Complex32::new(100.,200.));
+        assert_eq!(y[0],Complex32::new(-1.,-2.));
+        assert_eq!(y[1],x[1]);
+    }
+
+    #[test]
+    fn test_view_create() {
+        let dims = ArrayDim::from_shape(&[3,2]);
+        let mut view = CflViewMut::create("test_cfl_view_create",dims).unwrap();
+        view.set(&[2,1],Complex32::new(1.,2.));
+        drop(view);
+
+        let (y,y_dims) = read_cfl("test_cfl_view_create");
+        let (hdr,cfl) = cfl_paths("test_cfl_view_create");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert_eq!(y_dims.shape(),dims.shape());
+        assert_eq!(y[5],Complex32::new(1.,2.));
+        assert!(y[..5].iter().all(|&x| x == Complex32::ZERO));
+    }
+
+    #[test]
+    fn test_chunk_writer() {
+        let frame_dims = ArrayDim::from_shape(&[4,3]);
+        let frames:Vec<Vec<Complex32>> = (0..3).map(|f| {
+            (0..frame_dims.numel()).map(|i| Complex32::new(i as f32,f as f32)).collect()
+        }).collect();
+
+        let mut w = CflChunkWriter::create("test_cfl_chunks",frame_dims).unwrap();
+        frames.iter().for_each(|f| w.append_frame(f).unwrap());
+        assert!(matches!(w.append_frame(&[Complex32::ZERO;5]),Err(CflIoError::InconsistentArraySize{expected:12,actual:5})));
+        assert_eq!(w.frames(),3);
+        let dims = w.finish().unwrap();
+
+        let (y,read_dims) = read_cfl("test_cfl_chunks");
+        let (hdr,cfl) = cfl_paths("test_cfl_chunks");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert_eq!(read_dims.shape_ns(),&[4,3,3]);
+        assert_eq!(dims.shape(),read_dims.shape());
+        assert_eq!(y,frames.concat());
+
+        let mut w = CflChunkWriter::create("test_cfl_chunks_abort",frame_dims).unwrap();
+        w.append_frame(&frames[0]).unwrap();
+        w.abort().unwrap();
+        let (hdr,cfl) = cfl_paths("test_cfl_chunks_abort");
+        assert!(!hdr.exists());
+        assert!(!cfl.exists());
+
+        let w = CflChunkWriter::create("test_cfl_chunks_empty",frame_dims).unwrap();
+        assert!(matches!(w.finish(),Err(CflIoError::NoFrames(..))));
+        assert!(!cfl_paths("test_cfl_chunks_empty").1.exists());
+    }
+
+    #[test]
+    fn test_chunk_writer_with_dims() {
+        // frames of 4 written in order fill a 2 x 2 x 3 array
+        let mut w = CflChunkWriter::create("test_cfl_chunks_dims",ArrayDim::from_shape(&[4])).unwrap();
+        let x:Vec<Complex32> = (0..12).map(|i| Complex32::new(i as f32,0.)).collect();
+        x.chunks_exact(4).for_each(|f| w.append_frame(f).unwrap());
+        let dims = w.finish_with_dims(ArrayDim::from_shape(&[2,2,3])).unwrap();
+        let (y,read_dims) = read_cfl("test_cfl_chunks_dims");
+        let (hdr,cfl) = cfl_paths("test_cfl_chunks_dims");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert_eq!(read_dims.shape(),dims.shape());
+        assert_eq!(read_dims.shape_ns(),&[2,2,3]);
+        assert_eq!(y,x);
+
+        let mut w = CflChunkWriter::create("test_cfl_chunks_dims_bad",ArrayDim::from_shape(&[4])).unwrap();
+        w.append_frame(&x[..4]).unwrap();
+        assert!(matches!(w.finish_with_dims(ArrayDim::from_shape(&[2,3])),Err(CflIoError::InconsistentArraySize{expected:6,actual:4})));
+        assert!(!cfl_paths("test_cfl_chunks_dims_bad").1.exists());
+    }
+
+    #[test]
+    fn test_process_chunks() {
+        // [x, y, coil, slice] processed a few slices at a time
+        let dims = ArrayDim::from_shape(&[6,5,3,7]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,(i % 7) as f32)).collect();
+        write_cfl("test_cfl_process",&x,dims);
+
+        let identity = |slab:&[Complex32], d:&ArrayDim| (slab.to_vec(), *d);
+        for pipelined in [false, true] {
+            let opts = CflPipelineOptions::new().max_slabs(3).pipelined(pipelined);
+            let out_dims = process_cfl_chunks_with_options("test_cfl_process","test_cfl_process_copy",3,identity,&opts).unwrap();
+            let (y,y_dims) = read_cfl("test_cfl_process_copy");
+            assert_eq!(out_dims.shape(),dims.shape());
+            assert_eq!(y_dims.shape(),dims.shape());
+            assert_eq!(y,x);
+        }
+
+        // the root sum of squares over coils of each slice
+        let rss = |slab:&[Complex32], d:&ArrayDim| {
+            let n = d.shape()[0] * d.shape()[1];
+            let out:Vec<Complex32> = (0..n).map(|i| {
+                Complex32::new(slab.iter().skip(i).step_by(n).map(|c| c.norm_sqr()).sum::<f32>().sqrt(),0.)
+            }).collect();
+            (out, ArrayDim::from_shape(&d.shape()[..2]))
+        };
+        let out_dims = process_cfl_chunks("test_cfl_process","test_cfl_process_rss",3,rss).unwrap();
+        let (y,y_dims) = read_cfl("test_cfl_process_rss");
+        assert_eq!(out_dims.shape_ns(),&[6,5,1,7]);
+        assert_eq!(y_dims.shape(),out_dims.shape());
+        let expected = (x[dims.calc_addr(&[2,4,0,5])].norm_sqr() + x[dims.calc_addr(&[2,4,1,5])].norm_sqr()
+            + x[dims.calc_addr(&[2,4,2,5])].norm_sqr()).sqrt();
+        assert!((y[y_dims.calc_addr(&[2,4,0,5])].re - expected).abs() < 1e-3);
+
+        // only the slowest axis is contiguous, and slabs must all have the same shape
+        assert!(matches!(process_cfl_chunks("test_cfl_process","test_cfl_process_bad",2,identity),Err(CflIoError::InvalidRegion(..))));
+        let ragged = |slab:&[Complex32], _:&ArrayDim| {
+            let n = if slab[0].re == 0. { 2 } else { 1 };
+            (slab[..n].to_vec(), ArrayDim::from_shape(&[n]))
+        };
+        let err = process_cfl_chunks("test_cfl_process","test_cfl_process_bad",3,ragged).unwrap_err();
+        assert!(matches!(err,CflIoError::SlabShapeMismatch{index:1,..}));
+        assert!(!cfl_paths("test_cfl_process_bad").1.exists());
+
+        for base in ["test_cfl_process","test_cfl_process_copy","test_cfl_process_rss"] {
+            let (hdr,cfl) = cfl_paths(base);
+            std::fs::remove_file(hdr).unwrap();
+            std::fs::remove_file(cfl).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_read_magnitude() {
+        // large enough to span several conversion chunks
+        let dims = ArrayDim::from_shape(&[256,300,2]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new((i % 17) as f32 - 8.,(i % 5) as f32)).collect();
+        write_cfl("test_cfl_mag",&x,dims);
+        let (mag,mag_dims) = read_cfl_magnitude("test_cfl_mag");
+        let (re,_) = read_cfl_real("test_cfl_mag");
+        let (full,_) = read_cfl("test_cfl_mag");
+        let (hdr,cfl) = cfl_paths("test_cfl_mag");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert_eq!(mag_dims.shape(),dims.shape());
+        assert_eq!(mag,full.iter().map(|x| x.norm()).collect::<Vec<f32>>());
+        assert_eq!(re,full.iter().map(|x| x.re).collect::<Vec<f32>>());
+        assert!(matches!(try_read_cfl_magnitude("does_not_exist"),Err(CflIoError::MissingHeader(..))));
+    }
+
+    #[test]
+    fn test_write_from_real() {
+        let dims = ArrayDim::from_shape(&[256,300,2]);
+        let re:Vec<f32> = (0..dims.numel()).map(|i| i as f32 * 0.5).collect();
+        let im:Vec<f32> = (0..dims.numel()).map(|i| -(i as f32)).collect();
+        let re64:Vec<f64> = re.iter().map(|&x| x as f64).collect();
+
+        write_cfl_from_real("test_cfl_from_real",&re,dims).unwrap();
+        let (y,read_dims) = read_cfl("test_cfl_from_real");
+        assert_eq!(read_dims.shape(),dims.shape());
+        assert!(y.iter().zip(re.iter()).all(|(y,x)| y.re == *x && y.im == 0.));
+
+        write_cfl_from_real_f64("test_cfl_from_real",&re64,dims).unwrap();
+        let (y,_) = read_cfl("test_cfl_from_real");
+        assert!(y.iter().zip(re.iter()).all(|(y,x)| y.re == *x && y.im == 0.));
+
+        write_cfl_from_parts("test_cfl_from_real",&re,&im,dims).unwrap();
+        let (y,_) = read_cfl("test_cfl_from_real");
+        assert!(y.iter().zip(re.iter().zip(im.iter())).all(|(y,(r,i))| y.re == *r && y.im == *i));
+
+        let bad = write_cfl_from_parts("test_cfl_from_real",&re,&im[1..],dims);
+        let (hdr,cfl) = cfl_paths("test_cfl_from_real");
+        std::fs::remove_file(hdr).unwrap();
+        std::fs::remove_file(cfl).unwrap();
+        assert!(matches!(bad,Err(CflIoError::InconsistentArraySize{..})));
+    }
+
+    #[test]
+    fn test_series() {
+        std::fs::create_dir_all("test_cfl_series").unwrap();
+        let dims = ArrayDim::from_shape(&[4,3,5]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,1.)).collect();
+
+        let paths = write_cfl_series("test_cfl_series/ksp_",&x,dims,2).unwrap();
+        assert_eq!(paths.len(),5);
+        assert_eq!(paths[1],std::path::PathBuf::from("test_cfl_series/ksp_001"));
+
+        // numbered discovery stacks along a new trailing axis in numeric order
+        let (y,y_dims) = read_cfl_series(CflSeries::Numbered("test_cfl_series/ksp_".into())).unwrap();
+        assert_eq!(y_dims.shape_ns(),&[4,3,5]);
+        assert_eq!(y,x);
+
+        // explicit paths are stacked in the order given
+        let reversed:Vec<_> = paths.iter().rev().cloned().collect();
+        let (y,_) = read_cfl_series(CflSeries::Paths(reversed)).unwrap();
+        let n = 12;
+        (0..5).for_each(|i| assert_eq!(&y[i*n..(i+1)*n],&x[(4-i)*n..(5-i)*n]));
+
+        // a member with different dims is named in the error
+        write_cfl("test_cfl_series/ksp_005",&[Complex32::ZERO;6],ArrayDim::from_shape(&[6]));
+        let r = read_cfl_series(CflSeries::Numbered("test_cfl_series/ksp_".into()));
+        std::fs::remove_dir_all("test_cfl_series").unwrap();
+        match r {
+            Err(CflIoError::SeriesMismatch {path,..}) => assert!(path.ends_with("ksp_005")),
+            _=> panic!("expected a series mismatch error"),
+        }
+    }
+
+}
+
+#[derive(Debug)]
+pub enum CflIoError {
+    IO(PathBuf, std::io::Error),
+    MissingHeader(PathBuf),
+    MissingData(PathBuf),
+    MalformedHeader{path: PathBuf, msg: String},
+    SizeMismatch{path: PathBuf, expected: u64, actual: u64},
+    InconsistentArraySize{expected: usize, actual: usize},
+    InvalidRegion(String),
+    NoFrames(PathBuf),
+    SeriesMismatch{path: PathBuf, msg: String},
+    SlabShapeMismatch{index: usize, expected: Vec<usize>, actual: Vec<usize>},
+    /// the array described by a header can't be allocated
+    Alloc(PathBuf, AllocError),
+}
+
+impl Display for CflIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CflIoError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            CflIoError::MissingHeader(path) => write!(f, "cfl header {} not found", path.display()),
+            CflIoError::MissingData(path) => write!(f, "cfl data file {} not found", path.display()),
+            CflIoError::MalformedHeader {path, msg} => write!(f, "malformed cfl header {}: {}", path.display(), msg),
+            CflIoError::SizeMismatch {path, expected, actual} => write!(
+                f, "cfl data file {} is {} bytes but the header requires {} bytes", path.display(), actual, expected
+            ),
+            CflIoError::InconsistentArraySize {expected, actual} => write!(
+                f, "array dimensions require {} elements but {} were supplied", expected, actual
+            ),
+            CflIoError::InvalidRegion(msg) => write!(f, "invalid region: {}", msg),
+            CflIoError::NoFrames(path) => write!(f, "no frames were written to {}", path.display()),
+            CflIoError::SeriesMismatch {path, msg} => write!(f, "series member {}: {}", path.display(), msg),
+            CflIoError::SlabShapeMismatch {index, expected, actual} => write!(
+                f, "slab {} was processed to shape {:?} but earlier slabs have shape {:?}", index, actual, expected
+            ),
+            CflIoError::Alloc(path, e) => write!(f, "can't read {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for CflIoError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> CflIoError {
+    let path = path.to_path_buf();
+    move |e| CflIoError::IO(path, e)
+}
+
+/// returns the header and data file paths for a cfl base name
+pub fn cfl_paths(cfl_file_base_name:impl AsRef<Path>) -> (PathBuf, PathBuf) {
+    let base = cfl_file_base_name.as_ref();
+    let with_ext = |ext:&str| {
+        let mut s = OsString::from(base.as_os_str());
+        s.push(ext);
+        PathBuf::from(s)
+    };
+    (with_ext(".hdr"), with_ext(".cfl"))
+}
+
+/// the cfl base name of a path given as the base name or either file of the pair. Only a .cfl or
+/// .hdr extension is dropped, so base names containing dots are kept whole
+pub fn cfl_base_name(path:impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("cfl" | "hdr") => path.with_extension(""),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// the minimum number of dimensions written to a trimmed header, following BART convention
+const MIN_HDR_DIMS: usize = 5;
+
+/// options for writing cfl headers
+#[derive(Clone, Debug, Default)]
+pub struct CflWriteOptions {
+    full_dims: bool,
+    rank: Option<usize>,
+    parallel: bool,
+    sync: bool,
+}
+
+impl CflWriteOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// write all 16 dimensions to the header instead of trimming trailing singleton dimensions
+    /// (keeping at least 5)
+    pub fn full_dims(mut self, full_dims:bool) -> Self {
+        self.full_dims = full_dims;
+        self
+    }
+
+    /// the number of dimensions to write to a trimmed header in place of BART's minimum of 5, so
+    /// that trailing singleton dimensions up to the rank are kept. Dimensions past the last
+    /// non-singleton one are always written
+    pub fn rank(mut self, rank:usize) -> Self {
+        self.rank = Some(rank);
+        self
+    }
+
+    /// write large data files in ranges on separate file handles in parallel. This helps on fast
+    /// local storage and may not on network or spinning disks. Data smaller than two ranges is
+    /// always written sequentially
+    pub fn parallel(mut self, parallel:bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// sync the data and header to disk before returning, for when the files must survive a crash
+    /// or power loss. By default the operating system decides when written data reaches the disk
+    pub fn sync(mut self, sync:bool) -> Self {
+        self.sync = sync;
+        self
+    }
+}
+
+/// parses the dimensions line following "# Dimensions" from a cfl header
+fn read_cfl_hdr(hdr:&Path) -> Result<Vec<usize>, CflIoError> {
+    if !hdr.is_file() {
+        return Err(CflIoError::MissingHeader(hdr.to_path_buf()));
+    }
+    let malformed = |msg:String| CflIoError::MalformedHeader{path: hdr.to_path_buf(), msg};
+    let text = std::fs::read_to_string(hdr).map_err(io_err(hdr))?;
+    let mut lines = text.lines();
+    lines.find(|l| l.trim() == "# Dimensions")
+        .ok_or_else(|| malformed("missing '# Dimensions' line".to_string()))?;
+    let line = lines.find(|l| !l.trim().is_empty())
+        .ok_or_else(|| malformed("missing dimensions".to_string()))?;
+    let dims = line.split_whitespace().map(|d| {
+        d.parse::<usize>().map_err(|_| malformed(format!("invalid dimension '{}'", d)))
+    }).collect::<Result<Vec<usize>,_>>()?;
+    if dims.is_empty() {
+        return Err(malformed("missing dimensions".to_string()));
+    }
+    if dims.len() > N_DIMS && dims[N_DIMS..].iter().any(|&d| d != 1) {
+        return Err(malformed(format!("more than {} non-singleton dimensions", N_DIMS)));
+    }
+    if dims.contains(&0) {
+        return Err(malformed("zero-length dimension".to_string()));
+    }
+    Ok(dims.into_iter().take(N_DIMS).collect())
+}
+
+/// writes a cfl header declaring the given dimensions
+fn write_cfl_hdr(hdr:&Path, dims:&ArrayDim, opts:&CflWriteOptions) -> Result<(), CflIoError> {
+    let dims = if opts.full_dims {
+        dims.shape().as_slice()
+    } else {
+        let rank = opts.rank.unwrap_or(MIN_HDR_DIMS).clamp(1, N_DIMS);
+        &dims.shape()[..dims.shape_ns().len().max(rank)]
+    };
+    let mut s = String::from("# Dimensions\n");
+    dims.iter().for_each(|d| s.push_str(&format!("{} ", d)));
+    s.push('\n');
+    if !opts.sync {
+        return std::fs::write(hdr, s).map_err(io_err(hdr));
+    }
+    let mut f = File::create(hdr).map_err(io_err(hdr))?;
+    f.write_all(s.as_bytes()).map_err(io_err(hdr))?;
+    f.sync_all().map_err(io_err(hdr))
+}
+
+/// reads the dimensions of a cfl and checks that the data file is the expected size
+fn open_cfl(cfl_file_base_name:impl AsRef<Path>) -> Result<(ArrayDim, PathBuf), CflIoError> {
+    let (hdr, cfl) = cfl_paths(cfl_file_base_name);
+    let dims = ArrayDim::from_shape(&read_cfl_hdr(&hdr)?);
+    if !cfl.is_file() {
+        return Err(CflIoError::MissingData(cfl));
+    }
+    let expected = (dims.numel() * size_of::<Complex32>()) as u64;
+    let actual = std::fs::metadata(&cfl).map_err(io_err(&cfl))?.len();
+    if expected != actual {
+        return Err(CflIoError::SizeMismatch{path: cfl, expected, actual});
+    }
+    Ok((dims, cfl))
+}
+
+/// reads the dimensions of a cfl from its header without reading the data. The data file is
+/// checked to be the size the header requires
+pub fn read_cfl_dims(cfl_file_base_name:impl AsRef<Path>) -> Result<ArrayDim, CflIoError> {
+    open_cfl(cfl_file_base_name).map(|(dims, _)| dims)
+}
+
+/// reads the dimensions declared in a cfl header, including any trailing singleton dimensions it
+/// lists, up to 16
+pub fn read_cfl_shape(cfl_file_base_name:impl AsRef<Path>) -> Result<Vec<usize>, CflIoError> {
+    read_cfl_hdr(&cfl_paths(cfl_file_base_name).0)
+}
+
+/// reads a cfl file pair, returning an error for missing files, malformed headers, or a data
+/// file that doesn't match the size declared in the header
+pub fn try_read_cfl(cfl_file_base_name:impl AsRef<Path>) -> Result<(Vec<Complex32>, ArrayDim), CflIoError> {
+    let (dims, cfl) = open_cfl(cfl_file_base_name)?;
+    let n = dims.numel();
+    let mut data:Vec<Complex32> = dims.try_with_capacity().map_err(|e| CflIoError::Alloc(cfl.clone(), e))?;
+    let spare = &mut data.spare_capacity_mut()[..n];
+    let per_range = PARALLEL_READ_BYTES / size_of::<Complex32>();
+    if n < per_range {
+        let mut f = File::open(&cfl).map_err(io_err(&cfl))?;
+        read_into_uninit(&mut f, spare).map_err(io_err(&cfl))?;
+    } else {
+        // large files are read in ranges on separate file handles
+        spare.par_chunks_mut(per_range).enumerate().try_for_each(|(i, range)| {
+            let mut f = File::open(&cfl)?;
+            f.seek(SeekFrom::Start((i * PARALLEL_READ_BYTES) as u64))?;
+            read_into_uninit(&mut f, range)
+        }).map_err(io_err(&cfl))?;
+    }
+    // SAFETY: read_into_uninit only succeeds once it has written every element of its range, and
+    // the ranges cover the first n elements
+    unsafe { data.set_len(n) };
+    Ok((data, dims))
+}
+
+/// writes a cfl file pair, returning an error if the array doesn't match the dimensions
+pub fn try_write_cfl(cfl_file_base_name:impl AsRef<Path>, data: &[Complex32], dims: ArrayDim) -> Result<(), CflIoError> {
+    try_write_cfl_with_options(cfl_file_base_name, data, dims, &CflWriteOptions::default())
+}
+
+/// writes a cfl file pair with header options, returning an error if the array doesn't match the
+/// dimensions
+pub fn try_write_cfl_with_options(cfl_file_base_name:impl AsRef<Path>, data: &[Complex32], dims: ArrayDim, opts:&CflWriteOptions) -> Result<(), CflIoError> {
+    check_len(data.len(), &dims)?;
+    let (hdr, cfl) = cfl_paths(cfl_file_base_name);
+    let bytes:&[u8] = bytemuck::cast_slice(data);
+    let f = if opts.parallel && bytes.len() >= 2 * PARALLEL_WRITE_BYTES {
+        write_ranges(&cfl, bytes, PARALLEL_WRITE_BYTES)?
+    } else {
+        let mut w = BufWriter::new(File::create(&cfl).map_err(io_err(&cfl))?);
+        w.write_all(bytes).map_err(io_err(&cfl))?;
+        w.into_inner().map_err(|e| e.into_error()).map_err(io_err(&cfl))?
+    };
+    if opts.sync {
+        f.sync_all().map_err(io_err(&cfl))?;
+    }
+    drop(f);
+    write_cfl_hdr(&hdr, &dims, opts)
+}
+
+/// writes bytes to a new file in ranges of range_len, each on its own file handle. The file is
+/// sized up front so the ranges can be written in any order. Returns the handle the file was
+/// created with
+fn write_ranges(path:&Path, bytes:&[u8], range_len:usize) -> Result<File, CflIoError> {
+    let f = File::create(path).map_err(io_err(path))?;
+    f.set_len(bytes.len() as u64).map_err(io_err(path))?;
+    bytes.par_chunks(range_len).enumerate().try_for_each(|(i, range)| {
+        let mut f = OpenOptions::new().write(true).open(path)?;
+        f.seek(SeekFrom::Start((i * range_len) as u64))?;
+        f.write_all(range)
+    }).map_err(io_err(path))?;
+    Ok(f)
+}
+
+/// reads the runs of a region of a cfl data file into out
+fn read_region_runs(cfl:&Path, dims:&ArrayDim, offset:&[usize], size:&[usize], out:&mut [Complex32]) -> Result<(), CflIoError> {
+    let el_size = size_of::<Complex32>();
+    let mut f = BufReader::new(File::open(cfl).map_err(io_err(cfl))?);
+    let mut n_read = 0;
+    // runs are skipped to with relative seeks, which keep the buffer when the next run is in it
+    let mut pos = 0;
+    for (addr, len) in dims.region_runs(offset, size) {
+        f.seek_relative((addr * el_size) as i64 - pos as i64).map_err(io_err(cfl))?;
+        f.read_exact(bytemuck::cast_slice_mut(&mut out[n_read..n_read + len])).map_err(io_err(cfl))?;
+        pos = (addr + len) * el_size;
+        n_read += len;
+    }
+    Ok(())
+}
+
+/// reads a hyper-rectangular region from a cfl file without reading the entire data file. Only
+/// the contiguous runs of the region along axis 0 are read. Axes not covered by offset and size
+/// default to an offset of 0 and a size of 1
+pub fn read_cfl_region(cfl_file_base_name:impl AsRef<Path>, offset:&[usize], size:&[usize]) -> Result<(Vec<Complex32>, ArrayDim), CflIoError> {
+    let (dims, cfl) = open_cfl(cfl_file_base_name)?;
+    let region = dims.region_dims(offset, size).map_err(CflIoError::InvalidRegion)?;
+    let mut out = region.try_alloc(Complex32::ZERO).map_err(|e| CflIoError::Alloc(cfl.clone(), e))?;
+    read_region_runs(&cfl, &dims, offset, size, &mut out)?;
+    Ok((out, region))
+}
+
+/// returns the offset and size of the region covering a single index along an axis
+fn slab_region(dims:&ArrayDim, axis:usize, index:usize) -> Result<([usize; N_DIMS], [usize; N_DIMS]), CflIoError> {
+    if axis >= N_DIMS {
+        return Err(CflIoError::InvalidRegion(format!("axis {} is out of range", axis)));
+    }
+    let mut offset = [0usize; N_DIMS];
+    let mut size = *dims.shape();
+    offset[axis] = index;
+    size[axis] = 1;
+    dims.region_dims(&offset, &size).map_err(CflIoError::InvalidRegion)?;
+    Ok((offset, size))
+}
+
+/// reads a single index along an axis from a cfl file. The returned dimensions keep the axis with
+/// a size of 1
+pub fn read_cfl_slab(cfl_file_base_name:impl AsRef<Path>, axis:usize, index:usize) -> Result<(Vec<Complex32>, ArrayDim), CflIoError> {
+    let (hdr, _) = cfl_paths(&cfl_file_base_name);
+    let dims = ArrayDim::from_shape(&read_cfl_hdr(&hdr)?);
+    let (offset, size) = slab_region(&dims, axis, index)?;
+    read_cfl_region(cfl_file_base_name, &offset, &size)
+}
+
+/// returns the element at an address of a mapped cfl. The mapping may not be aligned for
+/// Complex32, so the element is copied out of the bytes
+fn mapped_get(bytes:&[u8], addr:usize) -> Complex32 {
+    let el_size = size_of::<Complex32>();
+    bytemuck::pod_read_unaligned(&bytes[addr * el_size..(addr + 1) * el_size])
+}
+
+/// copies the elements of a region of a mapped cfl
+fn mapped_slab(bytes:&[u8], dims:&ArrayDim, axis:usize, index:usize) -> Vec<Complex32> {
+    let (offset, size) = slab_region(dims, axis, index).unwrap_or_else(|e| panic!("{}", e));
+    let el_size = size_of::<Complex32>();
+    let mut out = Vec::with_capacity(size.iter().product());
+    for (addr, len) in dims.region_runs(&offset, &size) {
+        let run = &bytes[addr * el_size..(addr + len) * el_size];
+        out.extend(run.chunks_exact(el_size).map(bytemuck::pod_read_unaligned::<Complex32>));
+    }
+    out
+}
+
+fn check_idx(dims:&ArrayDim, idx:&[usize]) {
+    assert!(
+        idx.len() <= N_DIMS && idx.iter().zip(dims.shape().iter()).all(|(i, d)| i < d),
+        "index {:?} is out of bounds for shape {:?}", idx, dims.shape_ns()
+    );
+}
+
+/// a read-only memory-mapped cfl for random access into large data sets without reading them
+/// into memory
+pub struct CflView {
+    dims: ArrayDim,
+    map: Mmap,
+}
+
+impl CflView {
+
+    /// memory-maps the data file of a cfl after validating it against the header
+    pub fn open(cfl_file_base_name:impl AsRef<Path>) -> Result<CflView, CflIoError> {
+        let (dims, cfl) = open_cfl(cfl_file_base_name)?;
+        let f = File::open(&cfl).map_err(io_err(&cfl))?;
+        let map = unsafe { Mmap::map(&f) }.map_err(io_err(&cfl))?;
+        Ok(CflView { dims, map })
+    }
+
+    pub fn dims(&self) -> ArrayDim {
+        self.dims
+    }
+
+    /// returns the element at an index. Panics if the index is out of bounds
+    pub fn get(&self, idx:&[usize]) -> Complex32 {
+        check_idx(&self.dims, idx);
+        mapped_get(&self.map, self.dims.calc_addr(idx))
+    }
+
+    /// copies a single index along an axis. Panics if the axis or index is out of bounds
+    pub fn slab(&self, axis:usize, index:usize) -> Vec<Complex32> {
+        mapped_slab(&self.map, &self.dims, axis, index)
+    }
+
+    /// returns the mapped data as a slice, or None if the mapping isn't aligned for Complex32
+    pub fn as_slice(&self) -> Option<&[Complex32]> {
+        bytemuck::try_cast_slice(&self.map).ok()
+    }
+
+    /// copies the entire data set into memory
+    pub fn to_vec(&self) -> Vec<Complex32> {
+        match self.as_slice() {
+            Some(s) => s.to_vec(),
+            None => self.map.chunks_exact(size_of::<Complex32>()).map(bytemuck::pod_read_unaligned).collect(),
+        }
+    }
+}
+
+/// a mutable memory-mapped cfl for editing data in place. Changes are written back to the file
+/// on flush and when the view is dropped
+pub struct CflViewMut {
+    dims: ArrayDim,
+    path: PathBuf,
+    map: MmapMut,
+}
+
+impl CflViewMut {
+
+    /// memory-maps the data file of a cfl for reading and writing after validating it against the
+    /// header
+    pub fn open(cfl_file_base_name:impl AsRef<Path>) -> Result<CflViewMut, CflIoError> {
+        let (dims, cfl) = open_cfl(cfl_file_base_name)?;
+        let f = OpenOptions::new().read(true).write(true).open(&cfl).map_err(io_err(&cfl))?;
+        let map = unsafe { MmapMut::map_mut(&f) }.map_err(io_err(&cfl))?;
+        Ok(CflViewMut { dims, path: cfl, map })
+    }
+
+    /// creates a zero-filled cfl of the given dimensions and memory-maps it for writing, so large
+    /// outputs can be filled in any order without holding them in memory
+    pub fn create(cfl_file_base_name:impl AsRef<Path>, dims:ArrayDim) -> Result<CflViewMut, CflIoError> {
+        let (hdr, cfl) = cfl_paths(cfl_file_base_name);
+        let f = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&cfl).map_err(io_err(&cfl))?;
+        f.set_len((dims.numel() * size_of::<Complex32>()) as u64).map_err(io_err(&cfl))?;
+        write_cfl_hdr(&hdr, &dims, &CflWriteOptions::default())?;
+        let map = unsafe { MmapMut::map_mut(&f) }.map_err(io_err(&cfl))?;
+        Ok(CflViewMut { dims, path: cfl, map })
+    }
+
+    pub fn dims(&self) -> ArrayDim {
+        self.dims
+    }
+
+    /// returns the element at an index. Panics if the index is out of bounds
+    pub fn get(&self, idx:&[usize]) -> Complex32 {
+        check_idx(&self.dims, idx);
+        mapped_get(&self.map, self.dims.calc_addr(idx))
+    }
+
+    /// sets the element at an index. Panics if the index is out of bounds
+    pub fn set(&mut self, idx:&[usize], value:Complex32) {
+        check_idx(&self.dims, idx);
+        let el_size = size_of::<Complex32>();
+        let addr = self.dims.calc_addr(idx);
+        self.map[addr * el_size..(addr + 1) * el_size].copy_from_slice(bytemuck::bytes_of(&value));
+    }
+
+    /// copies a single index along an axis. Panics if the axis or index is out of bounds
+    pub fn slab(&self, axis:usize, index:usize) -> Vec<Complex32> {
+        mapped_slab(&self.map, &self.dims, axis, index)
+    }
+
+    /// returns the mapped data as a slice, or None if the mapping isn't aligned for Complex32
+    pub fn as_slice(&self) -> Option<&[Complex32]> {
+        bytemuck::try_cast_slice(&self.map).ok()
+    }
+
+    /// returns the mapped data as a mutable slice, or None if the mapping isn't aligned for
+    /// Complex32
+    pub fn as_mut_slice(&mut self) -> Option<&mut [Complex32]> {
+        bytemuck::try_cast_slice_mut(&mut self.map).ok()
+    }
+
+    /// writes changes back to the data file
+    pub fn flush(&self) -> Result<(), CflIoError> {
+        self.map.flush().map_err(io_err(&self.path))
+    }
+}
+
+/// writes a cfl in chunks, with fill producing the complex values starting at an address. Only a
+/// small staging buffer of complex values is held in memory
+fn write_cfl_chunked(cfl_file_base_name:impl AsRef<Path>, dims:ArrayDim, mut fill:impl FnMut(usize, &mut [Complex32])) -> Result<(), CflIoError> {
+    let (hdr, cfl) = cfl_paths(cfl_file_base_name);
+    let mut w = BufWriter::new(File::create(&cfl).map_err(io_err(&cfl))?);
+    let mut stage = vec![Complex32::ZERO; CONVERT_CHUNK_SIZE.min(dims.numel())];
+    let mut start = 0;
+    while start < dims.numel() {
+        let n = stage.len().min(dims.numel() - start);
+        fill(start, &mut stage[..n]);
+        w.write_all(bytemuck::cast_slice(&stage[..n])).map_err(io_err(&cfl))?;
+        start += n;
+    }
+    w.flush().map_err(io_err(&cfl))?;
+    write_cfl_hdr(&hdr, &dims, &CflWriteOptions::default())
+}
+
+fn check_len(len:usize, dims:&ArrayDim) -> Result<(), CflIoError> {
+    if len != dims.numel() {
+        return Err(CflIoError::InconsistentArraySize{expected: dims.numel(), actual: len});
+    }
+    Ok(())
+}
+
+/// writes real values to a cfl with the imaginary parts set to zero
+pub fn write_cfl_from_real(cfl_file_base_name:impl AsRef<Path>, data:&[f32], dims:ArrayDim) -> Result<(), CflIoError> {
+    check_len(data.len(), &dims)?;
+    write_cfl_chunked(cfl_file_base_name, dims, |start, stage| {
+        stage.iter_mut().zip(&data[start..]).for_each(|(c, x)| *c = Complex32::new(*x, 0.));
+    })
+}
+
+/// writes double precision real values to a cfl with the imaginary parts set to zero
+pub fn write_cfl_from_real_f64(cfl_file_base_name:impl AsRef<Path>, data:&[f64], dims:ArrayDim) -> Result<(), CflIoError> {
+    check_len(data.len(), &dims)?;
+    write_cfl_chunked(cfl_file_base_name, dims, |start, stage| {
+        stage.iter_mut().zip(&data[start..]).for_each(|(c, x)| *c = Complex32::new(*x as f32, 0.));
+    })
+}
+
+/// writes separate real and imaginary buffers to a cfl
+pub fn write_cfl_from_parts(cfl_file_base_name:impl AsRef<Path>, re:&[f32], im:&[f32], dims:ArrayDim) -> Result<(), CflIoError> {
+    check_len(re.len(), &dims)?;
+    check_len(im.len(), &dims)?;
+    write_cfl_chunked(cfl_file_base_name, dims, |start, stage| {
+        stage.iter_mut().zip(re[start..].iter().zip(&im[start..])).for_each(|(c, (r, i))| *c = Complex32::new(*r, *i));
+    })
+}
+
+/// the number of leading axes spanned by a frame, which is 0 for single element frames
+fn frame_axes(frame_dims:&ArrayDim) -> usize {
+    if frame_dims.numel() == 1 { 0 } else { frame_dims.shape_ns().len() }
+}
+
+/// writes a cfl one frame at a time, stacking frames along the axis following the last
+/// non-singleton axis of the frame dimensions. The header is written on finish, once the number
+/// of frames is known
+pub struct CflChunkWriter {
+    frame_dims: ArrayDim,
+    axis: usize,
+    n_frames: usize,
+    hdr: PathBuf,
+    cfl: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl CflChunkWriter {
+
+    pub fn create(cfl_file_base_name:impl AsRef<Path>, frame_dims:ArrayDim) -> Result<CflChunkWriter, CflIoError> {
+        let axis = frame_axes(&frame_dims);
+        if axis >= N_DIMS {
+            return Err(CflIoError::InvalidRegion(format!("frames with {} dimensions leave no axis to append along", N_DIMS)));
+        }
+        Self::create_along(cfl_file_base_name, frame_dims, axis)
+    }
+
+    /// creates a writer that stacks frames along the given axis, which must be a singleton axis
+    /// following the last non-singleton axis of the frame dimensions
+    pub fn create_along(cfl_file_base_name:impl AsRef<Path>, frame_dims:ArrayDim, axis:usize) -> Result<CflChunkWriter, CflIoError> {
+        if axis >= N_DIMS || axis < frame_axes(&frame_dims) {
+            return Err(CflIoError::InvalidRegion(format!(
+                "frames of shape {:?} can't be appended along axis {}", frame_dims.shape_ns(), axis
+            )));
+        }
+        let (hdr, cfl) = cfl_paths(cfl_file_base_name);
+        let writer = BufWriter::new(File::create(&cfl).map_err(io_err(&cfl))?);
+        Ok(CflChunkWriter { frame_dims, axis, n_frames: 0, hdr, cfl, writer })
+    }
+
+    /// appends a frame, which must have the number of elements of the frame dimensions
+    pub fn append_frame(&mut self, frame:&[Complex32]) -> Result<(), CflIoError> {
+        if frame.len() != self.frame_dims.numel() {
+            return Err(CflIoError::InconsistentArraySize{expected: self.frame_dims.numel(), actual: frame.len()});
+        }
+        self.writer.write_all(bytemuck::cast_slice(frame)).map_err(io_err(&self.cfl))?;
+        self.n_frames += 1;
+        Ok(())
+    }
+
+    /// the number of frames written so far
+    pub fn frames(&self) -> usize {
+        self.n_frames
+    }
+
+    /// flushes the data file and writes the header, returning the dimensions of the written cfl
+    pub fn finish(mut self) -> Result<ArrayDim, CflIoError> {
+        if self.n_frames == 0 {
+            // a cfl can't have a zero-length axis
+            let cfl = self.cfl.clone();
+            self.abort()?;
+            return Err(CflIoError::NoFrames(cfl));
+        }
+        self.writer.flush().map_err(io_err(&self.cfl))?;
+        let dims = self.frame_dims.with_dim(self.axis, self.n_frames);
+        write_cfl_hdr(&self.hdr, &dims, &CflWriteOptions::default())?;
+        Ok(dims)
+    }
+
+    /// flushes the data file and writes the header with the given dimensions in place of the
+    /// stacked frame dimensions, for frames that are written in order but don't match the final
+    /// shape. The dimensions must have as many elements as were written, otherwise the data file is
+    /// removed
+    pub fn finish_with_dims(mut self, dims:ArrayDim) -> Result<ArrayDim, CflIoError> {
+        let written = self.n_frames * self.frame_dims.numel();
+        if self.n_frames == 0 || dims.numel() != written {
+            self.abort()?;
+            return Err(CflIoError::InconsistentArraySize{expected: dims.numel(), actual: written});
+        }
+        self.writer.flush().map_err(io_err(&self.cfl))?;
+        write_cfl_hdr(&self.hdr, &dims, &CflWriteOptions::default())?;
+        Ok(dims)
+    }
+
+    /// stops writing and removes the partially written data file
+    pub fn abort(self) -> Result<(), CflIoError> {
+        let CflChunkWriter { cfl, writer, .. } = self;
+        drop(writer);
+        std::fs::remove_file(&cfl).map_err(io_err(&cfl))
+    }
+}
+
+/// options for process_cfl_chunks
+#[derive(Clone, Debug)]
+pub struct CflPipelineOptions {
+    max_slabs: usize,
+    pipelined: bool,
+}
+
+impl CflPipelineOptions {
+    pub fn new() -> Self {
+        Self { max_slabs: rayon::current_num_threads(), pipelined: false }
+    }
+
+    /// the most input slabs held in memory at once, along with their outputs. Slabs are processed
+    /// in parallel in batches of this size. Defaults to the number of rayon threads
+    pub fn max_slabs(mut self, max_slabs:usize) -> Self {
+        self.max_slabs = max_slabs.max(1);
+        self
+    }
+
+    /// read the next batch of slabs and write the previous results while the current batch is
+    /// processed. The batch size is halved so that max_slabs still holds
+    pub fn pipelined(mut self, pipelined:bool) -> Self {
+        self.pipelined = pipelined;
+        self
+    }
+}
+
+/// reads consecutive slabs from the start of a cfl data file
+struct SlabReader {
+    file: File,
+    cfl: PathBuf,
+    slab_len: usize,
+}
+
+impl SlabReader {
+    fn read(&mut self, n_slabs:usize) -> Result<Vec<Vec<Complex32>>, CflIoError> {
+        (0..n_slabs).map(|_| {
+            let mut slab = vec![Complex32::ZERO; self.slab_len];
+            self.file.read_exact(bytemuck::cast_slice_mut(&mut slab)).map_err(io_err(&self.cfl))?;
+            Ok(slab)
+        }).collect()
+    }
+}
+
+/// writes processed slabs in order, creating the chunk writer once the shape of the first
+/// processed slab is known
+struct SlabSink {
+    output: PathBuf,
+    axis: usize,
+    writer: Option<(CflChunkWriter, ArrayDim)>,
+}
+
+impl SlabSink {
+    fn write(&mut self, slabs:Vec<(Vec<Complex32>, ArrayDim)>) -> Result<(), CflIoError> {
+        for (data, dims) in slabs {
+            if data.len() != dims.numel() {
+                return Err(CflIoError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+            }
+            if self.writer.is_none() {
+                // slabs stay on the input axis unless the processed slabs extend past it
+                let axis = self.axis.max(frame_axes(&dims));
+                if axis >= N_DIMS {
+                    return Err(CflIoError::InvalidRegion(format!(
+                        "processed slabs of shape {:?} leave no axis to stack along", dims.shape_ns()
+                    )));
+                }
+                self.writer = Some((CflChunkWriter::create_along(&self.output, dims, axis)?, dims));
+            }
+            let (writer, expected) = self.writer.as_mut().expect("writer was just created");
+            if expected.shape() != dims.shape() {
+                return Err(CflIoError::SlabShapeMismatch{
+                    index: writer.frames(), expected: expected.shape_ns().to_vec(), actual: dims.shape_ns().to_vec(),
+                });
+            }
+            writer.append_frame(&data)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<ArrayDim, CflIoError> {
+        match self.writer {
+            Some((writer, _)) => writer.finish(),
+            None => Err(CflIoError::NoFrames(cfl_paths(&self.output).1)),
+        }
+    }
+
+    fn abort(self) {
+        if let Some((writer, _)) = self.writer {
+            // the original error is more useful than a failure to clean up
+            let _ = writer.abort();
+        }
+    }
+}
+
+/// processes a cfl one slab at a time along its slowest axis, writing the results to a new cfl
+/// without holding either array in memory. f is called with each slab and its dimensions, which
+/// keep the axis with a size of 1, and returns the processed slab and its dimensions. The
+/// processed slabs may have a different shape to the input as long as it is the same for every
+/// slab. They are stacked along the same axis in the output, or along the axis following the last
+/// non-singleton axis of the processed slabs if they extend past it. Returns the dimensions of the
+/// written cfl
+pub fn process_cfl_chunks(
+    input_base_name:impl AsRef<Path>,
+    output_base_name:impl AsRef<Path>,
+    axis:usize,
+    f:impl Fn(&[Complex32], &ArrayDim) -> (Vec<Complex32>, ArrayDim) + Sync,
+) -> Result<ArrayDim, CflIoError> {
+    process_cfl_chunks_with_options(input_base_name, output_base_name, axis, f, &CflPipelineOptions::new())
+}
+
+/// processes a cfl one slab at a time as process_cfl_chunks does, with control over the number of
+/// slabs held in memory and whether reading and writing overlap with processing
+pub fn process_cfl_chunks_with_options(
+    input_base_name:impl AsRef<Path>,
+    output_base_name:impl AsRef<Path>,
+    axis:usize,
+    f:impl Fn(&[Complex32], &ArrayDim) -> (Vec<Complex32>, ArrayDim) + Sync,
+    opts:&CflPipelineOptions,
+) -> Result<ArrayDim, CflIoError> {
+    let (dims, cfl) = open_cfl(input_base_name)?;
+    // only slabs along the slowest axis are contiguous in the data file
+    if axis >= N_DIMS || axis + 1 < dims.ndim() {
+        return Err(CflIoError::InvalidRegion(format!(
+            "slabs must be taken along the slowest axis of {:?}, not axis {}", dims.shape_ns(), axis
+        )));
+    }
+    let slab_dims = dims.with_dim(axis, 1);
+    let n_slabs = dims.shape()[axis];
+    let batch = if opts.pipelined { (opts.max_slabs / 2).max(1) } else { opts.max_slabs };
+    let batches:Vec<usize> = (0..n_slabs).step_by(batch).map(|start| batch.min(n_slabs - start)).collect();
+
+    let file = File::open(&cfl).map_err(io_err(&cfl))?;
+    let mut reader = SlabReader { file, cfl, slab_len: slab_dims.numel() };
+    let mut sink = SlabSink { output: output_base_name.as_ref().to_path_buf(), axis, writer: None };
+    let process = |slabs:Vec<Vec<Complex32>>| -> Vec<(Vec<Complex32>, ArrayDim)> {
+        slabs.par_iter().map(|slab| f(slab, &slab_dims)).collect()
+    };
+
+    let result = (|| -> Result<(), CflIoError> {
+        if !opts.pipelined {
+            for &n in &batches {
+                let slabs = reader.read(n)?;
+                sink.write(process(slabs))?;
+            }
+            return Ok(());
+        }
+        let mut next = Some(reader.read(batches[0]));
+        let mut pending = None;
+        for k in 0..batches.len() {
+            let slabs = next.take().expect("a batch is read ahead for every iteration")?;
+            let (processed, (written, read)) = rayon::join(
+                || process(slabs),
+                || rayon::join(
+                    || pending.take().map_or(Ok(()), |p| sink.write(p)),
+                    || batches.get(k + 1).map(|&n| reader.read(n)),
+                ),
+            );
+            written?;
+            next = read;
+            pending = Some(processed);
+        }
+        pending.map_or(Ok(()), |p| sink.write(p))
+    })();
+
+    match result {
+        Ok(()) => sink.finish(),
+        Err(e) => {
+            sink.abort();
+            Err(e)
+        }
+    }
+}
+
+/// files at least this many bytes are read in ranges of this size on multiple threads
+const PARALLEL_READ_BYTES: usize = 1 << 26;
+
+/// number of bytes staged per read when filling uninitialized memory
+const READ_STAGE_BYTES: usize = 1 << 22;
+
+/// fills uninitialized elements from the current position of a file through a small staging
+/// buffer, so the destination is only touched once by the copy out of the stage
+fn read_into_uninit(f:&mut File, dst:&mut [MaybeUninit<Complex32>]) -> std::io::Result<()> {
+    let mut stage = vec![Complex32::ZERO; (READ_STAGE_BYTES / size_of::<Complex32>()).min(dst.len())];
+    for chunk in dst.chunks_mut(stage.len().max(1)) {
+        let stage = &mut stage[..chunk.len()];
+        f.read_exact(bytemuck::cast_slice_mut(stage))?;
+        chunk.iter_mut().zip(stage.iter()).for_each(|(d, s)| {
+            d.write(*s);
+        });
+    }
+    Ok(())
+}
+
+/// the size of the ranges written concurrently by parallel writes
+const PARALLEL_WRITE_BYTES: usize = 1 << 26;
+
+/// number of complex elements staged per read when converting cfl data on the fly
+const CONVERT_CHUNK_SIZE: usize = 1 << 16;
+
+/// reads a cfl, converting each element to a real value as it is read. Only a small staging
+/// buffer of complex values is held alongside the output
+fn read_cfl_converted(cfl_file_base_name:impl AsRef<Path>, convert:impl Fn(&Complex32) -> f32) -> Result<(Vec<f32>, ArrayDim), CflIoError> {
+    let (dims, cfl) = open_cfl(cfl_file_base_name)?;
+    let mut f = File::open(&cfl).map_err(io_err(&cfl))?;
+    let mut out = dims.try_with_capacity().map_err(|e| CflIoError::Alloc(cfl.clone(), e))?;
+    let mut stage = vec![Complex32::ZERO; CONVERT_CHUNK_SIZE.min(dims.numel())];
+    while out.len() < dims.numel() {
+        let n = stage.len().min(dims.numel() - out.len());
+        f.read_exact(bytemuck::cast_slice_mut(&mut stage[..n])).map_err(io_err(&cfl))?;
+        out.extend(stage[..n].iter().map(&convert));
+    }
+    Ok((out, dims))
+}
+
+/// reads the magnitude of a cfl
+pub fn try_read_cfl_magnitude(cfl_file_base_name:impl AsRef<Path>) -> Result<(Vec<f32>, ArrayDim), CflIoError> {
+    read_cfl_converted(cfl_file_base_name, |x| x.norm())
+}
+
+/// reads the real part of a cfl
+pub fn try_read_cfl_real(cfl_file_base_name:impl AsRef<Path>) -> Result<(Vec<f32>, ArrayDim), CflIoError> {
+    read_cfl_converted(cfl_file_base_name, |x| x.re)
+}
+
+pub fn read_cfl_magnitude(cfl_file_base_name:impl AsRef<Path>) -> (Vec<f32>, ArrayDim) {
+    try_read_cfl_magnitude(cfl_file_base_name).unwrap()
+}
+
+pub fn read_cfl_real(cfl_file_base_name:impl AsRef<Path>) -> (Vec<f32>, ArrayDim) {
+    try_read_cfl_real(cfl_file_base_name).unwrap()
+}
+
+/// the members of a cfl series
+pub enum CflSeries {
+    /// explicit base names, stacked in the order given
+    Paths(Vec<PathBuf>),
+    /// all base names of the form <prefix><digits>, i.e. "ksp_" for ksp_000, ksp_001, ...,
+    /// stacked in numeric order
+    Numbered(PathBuf),
+}
+
+/// finds the numbered members of a series from their headers
+fn numbered_series(prefix:&Path) -> Result<Vec<PathBuf>, CflIoError> {
+    let dir = match prefix.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _=> PathBuf::from("."),
+    };
+    let stem = prefix.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let mut members = vec![];
+    for entry in std::fs::read_dir(&dir).map_err(io_err(&dir))? {
+        let name = entry.map_err(io_err(&dir))?.file_name().to_string_lossy().to_string();
+        let Some(num) = name.strip_prefix(&stem).and_then(|n| n.strip_suffix(".hdr")) else {
+            continue
+        };
+        if !num.is_empty() && num.chars().all(|c| c.is_ascii_digit()) {
+            let n:u64 = num.parse().map_err(|_| CflIoError::SeriesMismatch{
+                path: dir.join(&name), msg: String::from("series number is too large"),
+            })?;
+            members.push((n, prefix.with_file_name(format!("{}{}", stem, num))));
+        }
+    }
+    members.sort_by_key(|(n, _)| *n);
+    Ok(members.into_iter().map(|(_, p)| p).collect())
+}
+
+/// reads a series of cfls with identical dimensions, stacking them along a new trailing axis.
+/// Every member is validated before any data is read, and each is read directly into its slab of
+/// the output
+pub fn read_cfl_series(series:CflSeries) -> Result<(Vec<Complex32>, ArrayDim), CflIoError> {
+    let paths = match series {
+        CflSeries::Paths(paths) => paths,
+        CflSeries::Numbered(prefix) => numbered_series(&prefix)?,
+    };
+    let first = paths.first().ok_or_else(|| CflIoError::SeriesMismatch{
+        path: PathBuf::new(), msg: String::from("series is empty"),
+    })?;
+
+    let (dims, _) = open_cfl(first)?;
+    let mut members = vec![];
+    for p in &paths {
+        let (d, cfl) = open_cfl(p)?;
+        if d.shape() != dims.shape() {
+            return Err(CflIoError::SeriesMismatch{
+                path: p.to_path_buf(),
+                msg: format!("dims {:?} differ from {:?}", d.shape_ns(), dims.shape_ns()),
+            });
+        }
+        members.push(cfl);
+    }
+
+    let axis = dims.shape_ns().len();
+    if axis >= N_DIMS {
+        return Err(CflIoError::SeriesMismatch{
+            path: first.to_path_buf(), msg: format!("{} dimensional members leave no axis to stack along", N_DIMS),
+        });
+    }
+    let series_dims = dims.with_dim(axis, paths.len());
+
+    let mut out = series_dims.try_alloc(Complex32::ZERO).map_err(|e| CflIoError::Alloc(first.to_path_buf(), e))?;
+    for (slab, cfl) in out.chunks_exact_mut(dims.numel()).zip(members.iter()) {
+        let mut f = File::open(cfl).map_err(io_err(cfl))?;
+        f.read_exact(bytemuck::cast_slice_mut(slab)).map_err(io_err(cfl))?;
+    }
+    Ok((out, series_dims))
+}
+
+/// splits an array along an axis, writing each index to a numbered cfl of the form
+/// <prefix><digits>. At least 3 digits are used. Returns the base names that were written
+pub fn write_cfl_series(prefix:impl AsRef<Path>, data:&[Complex32], dims:ArrayDim, axis:usize) -> Result<Vec<PathBuf>, CflIoError> {
+    check_len(data.len(), &dims)?;
+    if axis >= N_DIMS {
+        return Err(CflIoError::InvalidRegion(format!("axis {} is out of range", axis)));
+    }
+    let prefix = prefix.as_ref().as_os_str();
+    let n = dims.shape()[axis];
+    let width = n.saturating_sub(1).to_string().len().max(3);
+    let mut paths = vec![];
+    for i in 0..n {
+        let (offset, size) = slab_region(&dims, axis, i)?;
+        let (slab, slab_dims) = dims.copy_region(data, &offset, &size);
+        let mut base = OsString::from(prefix);
+        base.push(format!("{:0width$}", i, width = width));
+        let base = PathBuf::from(base);
+        try_write_cfl(&base, &slab, slab_dims)?;
+        paths.push(base);
+    }
+    Ok(paths)
+}
 
 pub fn read_cfl(cfl_file_base_name:impl AsRef<Path>) -> (Vec<Complex32>, ArrayDim)
 {
-    let cfl_dims = cfl::get_dims(&cfl_file_base_name).unwrap();
-    let r = cfl::CflReader::new(cfl_file_base_name).unwrap();
-    let dims = ArrayDim::from_shape(&cfl_dims);
-    let mut data = vec![Complex32::ZERO; dims.numel()];
-    r.read_slice(0,&mut data).unwrap();
-    (data, dims)
+    try_read_cfl(cfl_file_base_name).unwrap()
 }
 
 pub fn write_cfl(cfl_file_base_name:impl AsRef<Path>, data: &[Complex32], dims: ArrayDim) {
-    let mut w = cfl::CflWriter::new(cfl_file_base_name,dims.shape()).unwrap();
-    w.write_slice(0, data).unwrap();
-    w.flush().unwrap();
+    try_write_cfl(cfl_file_base_name, data, dims).unwrap()
 }
 
 /// reads a contiguous slice from a cfl file. You must manually supply the starting offset and length of the
@@ -25,4 +1406,4 @@ pub fn write_cfl(cfl_file_base_name:impl AsRef<Path>, data: &[Complex32], dims:
 pub fn read_cfl_slice(cfl_file_base_name:impl AsRef<Path>,offset:usize, buff:&mut [Complex32]){
     let r = cfl::CflReader::new(&cfl_file_base_name).unwrap();
     r.read_slice(offset,buff).unwrap();
-}
\ No newline at end of file
+}
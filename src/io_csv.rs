@@ -0,0 +1,215 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use crate::ArrayDim;
+
+#[cfg(test)]
+mod tests {
+    use crate::ArrayDim;
+    use crate::io_csv::{read_csv, write_csv, CsvIoError, CsvOptions};
+
+    #[test]
+    fn test_round_trip() {
+        // 3 rows and 2 columns
+        let dims = ArrayDim::from_shape(&[3,2]);
+        let x = [1.,2.,3.,-0.5,1e-7,42.];
+        let opts = CsvOptions::new().header(&["a","b"]);
+        write_csv("test_csv_round_trip.csv",&x,&dims,&opts).unwrap();
+        let text = std::fs::read_to_string("test_csv_round_trip.csv").unwrap();
+        assert_eq!(text,"a,b\n1,-0.5\n2,0.0000001\n3,42\n");
+        let (y,y_dims) = read_csv("test_csv_round_trip.csv",&CsvOptions::new().has_header(true)).unwrap();
+        std::fs::remove_file("test_csv_round_trip.csv").unwrap();
+        assert_eq!(y,x);
+        assert_eq!(y_dims.shape(),dims.shape());
+
+        // a column vector with a tab delimiter and fixed precision, read back with trailing newlines
+        let dims = ArrayDim::from_shape(&[3]);
+        let opts = CsvOptions::new().delimiter('\t').precision(2);
+        write_csv("test_csv_precision.tsv",&[1.,2.126,3.],&dims,&opts).unwrap();
+        let mut f = std::fs::OpenOptions::new().append(true).open("test_csv_precision.tsv").unwrap();
+        std::io::Write::write_all(&mut f,b"\n\r\n").unwrap();
+        drop(f);
+        let (y,y_dims) = read_csv("test_csv_precision.tsv",&opts).unwrap();
+        std::fs::remove_file("test_csv_precision.tsv").unwrap();
+        assert_eq!(y,vec![1.,2.13,3.]);
+        assert_eq!(y_dims.shape_ns(),&[3]);
+
+        let dims = ArrayDim::from_shape(&[2,2,2]);
+        assert!(matches!(write_csv("test_csv_3d.csv",&dims.alloc(0.),&dims,&CsvOptions::new()),Err(CsvIoError::InvalidDims(..))));
+    }
+
+    #[test]
+    fn test_invalid_rows() {
+        std::fs::write("test_csv_ragged.csv","1,2,3\n4,5\n").unwrap();
+        let r = read_csv("test_csv_ragged.csv",&CsvOptions::new());
+        std::fs::remove_file("test_csv_ragged.csv").unwrap();
+        assert!(matches!(r,Err(CsvIoError::RaggedRow{line: 2, expected: 3, found: 2,..})));
+
+        std::fs::write("test_csv_cell.csv","1,2\n3,x\n").unwrap();
+        let r = read_csv("test_csv_cell.csv",&CsvOptions::new());
+        std::fs::remove_file("test_csv_cell.csv").unwrap();
+        assert!(matches!(r,Err(CsvIoError::InvalidCell{line: 2, column: 2,..})));
+    }
+
+}
+
+#[derive(Debug)]
+pub enum CsvIoError {
+    IO(PathBuf, std::io::Error),
+    RaggedRow{path: PathBuf, line: usize, expected: usize, found: usize},
+    InvalidCell{path: PathBuf, line: usize, column: usize, cell: String},
+    Empty(PathBuf),
+    InvalidHeader{expected: usize, found: usize},
+    InconsistentArraySize{expected: usize, actual: usize},
+    InvalidDims(String),
+}
+
+impl Display for CsvIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CsvIoError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            CsvIoError::RaggedRow {path, line, expected, found} => write!(
+                f, "line {} of {} has {} cells but {} were expected", line, path.display(), found, expected
+            ),
+            CsvIoError::InvalidCell {path, line, column, cell} => write!(
+                f, "line {} column {} of {} is not a number: {:?}", line, column, path.display(), cell
+            ),
+            CsvIoError::Empty(path) => write!(f, "{} has no rows", path.display()),
+            CsvIoError::InvalidHeader {expected, found} => write!(
+                f, "header has {} names but the array has {} columns", found, expected
+            ),
+            CsvIoError::InconsistentArraySize {expected, actual} => write!(
+                f, "array dimensions require {} elements but {} were supplied", expected, actual
+            ),
+            CsvIoError::InvalidDims(msg) => write!(f, "invalid dimensions: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for CsvIoError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> CsvIoError {
+    let path = path.to_path_buf();
+    move |e| CsvIoError::IO(path, e)
+}
+
+/// options for reading and writing csv files
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    delimiter: char,
+    precision: Option<usize>,
+    header: Option<Vec<String>>,
+    has_header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            precision: None,
+            header: None,
+            has_header: false,
+        }
+    }
+}
+
+impl CsvOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the cell delimiter. Defaults to a comma
+    pub fn delimiter(mut self, delimiter:char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// the number of decimal places to write. By default values are written with as many digits
+    /// as needed to read them back exactly
+    pub fn precision(mut self, precision:usize) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// column names to write as the first row
+    pub fn header(mut self, names:&[&str]) -> Self {
+        self.header = Some(names.iter().map(|n| n.to_string()).collect());
+        self
+    }
+
+    /// skips the first row when reading
+    pub fn has_header(mut self, has_header:bool) -> Self {
+        self.has_header = has_header;
+        self
+    }
+}
+
+/// writes an array with at most 2 dimensions to a csv file, with one row per index along axis 0
+/// and one column per index along axis 1
+pub fn write_csv(file:impl AsRef<Path>, data:&[f64], dims:&ArrayDim, opts:&CsvOptions) -> Result<(), CsvIoError> {
+    let path = file.as_ref();
+    if data.len() != dims.numel() {
+        return Err(CsvIoError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+    }
+    if dims.shape_ns().len() > 2 {
+        return Err(CsvIoError::InvalidDims(format!(
+            "csv files hold at most 2 dimensions, but the array has shape {:?}. Slice the array first", dims.shape_ns()
+        )));
+    }
+    let [rows, cols, ..] = *dims.shape();
+    if let Some(names) = opts.header.as_ref().filter(|n| n.len() != cols) {
+        return Err(CsvIoError::InvalidHeader{expected: cols, found: names.len()});
+    }
+
+    let delimiter = opts.delimiter.to_string();
+    let mut w = BufWriter::new(File::create(path).map_err(io_err(path))?);
+    if let Some(names) = &opts.header {
+        writeln!(w, "{}", names.join(&delimiter)).map_err(io_err(path))?;
+    }
+    for r in 0..rows {
+        let row:Vec<String> = (0..cols).map(|c| {
+            let x = data[r + c * rows];
+            match opts.precision {
+                Some(p) => format!("{:.*}", p, x),
+                None => x.to_string(),
+            }
+        }).collect();
+        writeln!(w, "{}", row.join(&delimiter)).map_err(io_err(path))?;
+    }
+    w.flush().map_err(io_err(path))
+}
+
+/// reads a csv file of numbers into an array of shape [rows, columns]. Blank lines at the end of
+/// the file are ignored, and every other row must have the same number of cells
+pub fn read_csv(file:impl AsRef<Path>, opts:&CsvOptions) -> Result<(Vec<f64>, ArrayDim), CsvIoError> {
+    let path = file.as_ref();
+    let text = std::fs::read_to_string(path).map_err(io_err(path))?;
+    let lines:Vec<&str> = text.lines().map(|l| l.trim_end_matches('\r')).collect();
+    let n_lines = lines.len() - lines.iter().rev().take_while(|l| l.trim().is_empty()).count();
+
+    let mut rows:Vec<Vec<f64>> = vec![];
+    for (i, line) in lines[..n_lines].iter().enumerate().skip(opts.has_header as usize) {
+        let row = line.split(opts.delimiter).enumerate().map(|(c, cell)| {
+            cell.trim().parse::<f64>().map_err(|_| CsvIoError::InvalidCell{
+                path: path.to_path_buf(), line: i + 1, column: c + 1, cell: cell.to_string()
+            })
+        }).collect::<Result<Vec<f64>, _>>()?;
+        if let Some(first) = rows.first().filter(|f| f.len() != row.len()) {
+            return Err(CsvIoError::RaggedRow{path: path.to_path_buf(), line: i + 1, expected: first.len(), found: row.len()});
+        }
+        rows.push(row);
+    }
+    if rows.is_empty() {
+        return Err(CsvIoError::Empty(path.to_path_buf()));
+    }
+
+    let dims = ArrayDim::from_shape(&[rows.len(), rows[0].len()]);
+    let mut data = dims.alloc(0.);
+    for (r, row) in rows.iter().enumerate() {
+        for (c, x) in row.iter().enumerate() {
+            data[r + c * rows.len()] = *x;
+        }
+    }
+    Ok((data, dims))
+}
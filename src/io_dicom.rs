@@ -0,0 +1,425 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use dicom_core::Tag;
+use dicom_dictionary_std::tags;
+use dicom_object::{open_file, DefaultDicomObject};
+use crate::ArrayDim;
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use dicom_core::{DataElement, PrimitiveValue, Tag, VR};
+    use dicom_dictionary_std::tags;
+    use dicom_object::{FileMetaTableBuilder, InMemDicomObject};
+    use crate::io_dicom::{read_dicom_series, read_dicom_series_with_options, DicomIoError, DicomReadOptions};
+
+    const MR_STORAGE: &str = "1.2.840.10008.5.1.4.1.1.4";
+
+    struct Slice {
+        series: &'static str,
+        position: [f64; 3],
+        instance: i32,
+        temporal: Option<i32>,
+        pixels: Vec<i16>,
+    }
+
+    fn strs(x:&[f64]) -> PrimitiveValue {
+        PrimitiveValue::Strs(x.iter().map(|x| x.to_string()).collect())
+    }
+
+    /// writes a 3 x 2 (columns x rows) slice with a slope of 2 and an intercept of -1
+    fn write_slice(dir:&Path, name:&str, s:&Slice) {
+        let uid = format!("1.2.3.{}.{}", s.instance, s.temporal.unwrap_or(0));
+        let mut obj = InMemDicomObject::new_empty();
+        let mut put = |tag:Tag, vr:VR, v:PrimitiveValue| obj.put(DataElement::new(tag, vr, v));
+        put(tags::SOP_CLASS_UID, VR::UI, PrimitiveValue::from(MR_STORAGE));
+        put(tags::SOP_INSTANCE_UID, VR::UI, PrimitiveValue::from(uid.as_str()));
+        put(tags::SERIES_INSTANCE_UID, VR::UI, PrimitiveValue::from(s.series));
+        put(tags::INSTANCE_NUMBER, VR::IS, PrimitiveValue::from(s.instance.to_string()));
+        if let Some(t) = s.temporal {
+            put(tags::TEMPORAL_POSITION_IDENTIFIER, VR::IS, PrimitiveValue::from(t.to_string()));
+        }
+        put(tags::IMAGE_POSITION_PATIENT, VR::DS, strs(&s.position));
+        put(tags::IMAGE_ORIENTATION_PATIENT, VR::DS, strs(&[1.,0.,0.,0.,1.,0.]));
+        put(tags::PIXEL_SPACING, VR::DS, strs(&[0.7,0.5]));
+        put(tags::RESCALE_SLOPE, VR::DS, strs(&[2.]));
+        put(tags::RESCALE_INTERCEPT, VR::DS, strs(&[-1.]));
+        put(tags::SAMPLES_PER_PIXEL, VR::US, PrimitiveValue::from(1u16));
+        put(tags::ROWS, VR::US, PrimitiveValue::from(2u16));
+        put(tags::COLUMNS, VR::US, PrimitiveValue::from(3u16));
+        put(tags::BITS_ALLOCATED, VR::US, PrimitiveValue::from(16u16));
+        put(tags::BITS_STORED, VR::US, PrimitiveValue::from(16u16));
+        put(tags::PIXEL_REPRESENTATION, VR::US, PrimitiveValue::from(1u16));
+        let bytes:Vec<u8> = s.pixels.iter().flat_map(|p| p.to_le_bytes()).collect();
+        put(tags::PIXEL_DATA, VR::OW, PrimitiveValue::from(bytes));
+        let meta = FileMetaTableBuilder::new()
+            .transfer_syntax("1.2.840.10008.1.2.1")
+            .media_storage_sop_class_uid(MR_STORAGE)
+            .media_storage_sop_instance_uid(uid.as_str());
+        obj.with_meta(meta).unwrap().write_to_file(dir.join(name)).unwrap();
+    }
+
+    fn slice(series:&'static str, z:f64, instance:i32, temporal:Option<i32>) -> Slice {
+        let pixels = (0..6).map(|i| i + 10 * instance as i16).collect();
+        Slice { series, position: [-10., 20., z], instance, temporal, pixels }
+    }
+
+    #[test]
+    fn test_read_dicom_series() {
+        let dir = Path::new("test_dicom_series");
+        std::fs::create_dir_all(dir).unwrap();
+        // file names and instance numbers are out of order with the positions
+        write_slice(dir,"a.dcm",&slice("1.2.3",4.,1,None));
+        write_slice(dir,"b.dcm",&slice("1.2.3",0.,2,None));
+        write_slice(dir,"c.dcm",&slice("1.2.3",2.,3,None));
+        std::fs::write(dir.join("notes.txt"),"not a dicom file").unwrap();
+
+        let (x,dims,geom) = read_dicom_series(dir).unwrap();
+        assert_eq!(dims.shape_ns(),&[3,2,3]);
+        // slices are ordered by position: instances 2, 3, 1
+        for (z,instance) in [2,3,1].into_iter().enumerate() {
+            for i in 0..6 {
+                let stored = (i + 10 * instance) as f32;
+                assert_eq!(x[z * 6 + i],2. * stored - 1.);
+            }
+        }
+        assert_eq!(geom.spacing,[0.5,0.7,2.]);
+        assert_eq!(geom.origin,[-10.,20.,0.]);
+        assert_eq!(geom.slice_dir,[0.,0.,1.]);
+        // LPS to RAS flips the x and y axes
+        let a = geom.nifti_affine();
+        assert_eq!(a[0],[-0.5,0.,0.,10.]);
+        assert_eq!(a[1],[0.,-0.7,0.,-20.]);
+        assert_eq!(a[2],[0.,0.,2.,0.]);
+
+        // a second series in the same directory must be selected by uid
+        write_slice(dir,"d.dcm",&slice("1.2.4",0.,4,None));
+        match read_dicom_series(dir) {
+            Err(DicomIoError::MultipleSeries(uids)) => assert_eq!(uids,vec!["1.2.3".to_string(),"1.2.4".to_string()]),
+            _=> panic!("expected multiple series to be an error"),
+        }
+        let (_,dims,_) = read_dicom_series_with_options(dir,&DicomReadOptions::new().series_uid("1.2.4")).unwrap();
+        assert_eq!(dims.shape_ns(),&[3,2]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_dicom_temporal() {
+        let dir = Path::new("test_dicom_temporal");
+        std::fs::create_dir_all(dir).unwrap();
+        write_slice(dir,"1.dcm",&slice("1.2.5",1.,1,Some(2)));
+        write_slice(dir,"2.dcm",&slice("1.2.5",0.,2,Some(2)));
+        write_slice(dir,"3.dcm",&slice("1.2.5",1.,3,Some(1)));
+        write_slice(dir,"4.dcm",&slice("1.2.5",0.,4,Some(1)));
+        let (x,dims,geom) = read_dicom_series(dir).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(dims.shape_ns(),&[3,2,2,2]);
+        assert_eq!(geom.spacing[2],1.);
+        // [slice, time] -> instance
+        for (z,t,instance) in [(0,0,4),(1,0,3),(0,1,2),(1,1,1)] {
+            assert_eq!(x[dims.calc_addr(&[0,0,z,t])],2. * (10 * instance) as f32 - 1.);
+        }
+    }
+
+}
+
+#[derive(Debug)]
+pub enum DicomIoError {
+    IO(PathBuf, std::io::Error),
+    Dicom{path: PathBuf, msg: String},
+    NoSeries(PathBuf),
+    MultipleSeries(Vec<String>),
+    Inconsistent{path: PathBuf, msg: String},
+    Unsupported{path: PathBuf, msg: String},
+}
+
+impl Display for DicomIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DicomIoError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            DicomIoError::Dicom {path, msg} => write!(f, "dicom error for {}: {}", path.display(), msg),
+            DicomIoError::NoSeries(path) => write!(f, "no dicom series found in {}", path.display()),
+            DicomIoError::MultipleSeries(uids) => write!(
+                f, "found {} series, select one by uid: {}", uids.len(), uids.join(", ")
+            ),
+            DicomIoError::Inconsistent {path, msg} => write!(f, "{} is inconsistent with the series: {}", path.display(), msg),
+            DicomIoError::Unsupported {path, msg} => write!(f, "unsupported dicom file {}: {}", path.display(), msg),
+        }
+    }
+}
+
+impl std::error::Error for DicomIoError {}
+
+fn dicom_err(path:&Path, e:impl Display) -> DicomIoError {
+    DicomIoError::Dicom{path: path.to_path_buf(), msg: e.to_string()}
+}
+
+/// voxel geometry of a dicom series in LPS patient coordinates
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DicomGeometry {
+    pub series_uid: String,
+    /// voxel size along columns, rows and slices in mm
+    pub spacing: [f64; 3],
+    /// position of the center of the first voxel
+    pub origin: [f64; 3],
+    /// direction of increasing column index
+    pub row_dir: [f64; 3],
+    /// direction of increasing row index
+    pub col_dir: [f64; 3],
+    /// direction of increasing slice index
+    pub slice_dir: [f64; 3],
+}
+
+impl DicomGeometry {
+    /// the voxel-to-world affine in RAS coordinates, for use with set_nifti_affine
+    pub fn nifti_affine(&self) -> [[f64; 4]; 4] {
+        let dirs = [self.row_dir, self.col_dir, self.slice_dir];
+        let mut affine = [[0.; 4]; 4];
+        for (r, row) in affine.iter_mut().take(3).enumerate() {
+            // LPS to RAS
+            let flip = if r < 2 { -1. } else { 1. };
+            for (c, (dir, spacing)) in dirs.iter().zip(self.spacing).enumerate() {
+                row[c] = flip * dir[r] * spacing;
+            }
+            row[3] = flip * self.origin[r];
+        }
+        affine[3][3] = 1.;
+        affine
+    }
+}
+
+/// options for reading a dicom series
+#[derive(Clone, Debug, Default)]
+pub struct DicomReadOptions {
+    series_uid: Option<String>,
+}
+
+impl DicomReadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// selects a series from a directory holding more than one
+    pub fn series_uid(mut self, uid:&str) -> Self {
+        self.series_uid = Some(uid.to_string());
+        self
+    }
+}
+
+/// distance between slice positions under which slices are considered to be at the same position
+const POSITION_TOLERANCE: f64 = 1e-3;
+
+/// a parsed dicom slice
+struct DicomSlice {
+    path: PathBuf,
+    obj: DefaultDicomObject,
+    series_uid: String,
+}
+
+impl DicomSlice {
+
+    fn element(&self, tag:Tag) -> Result<&dicom_object::mem::InMemElement, DicomIoError> {
+        self.obj.get(tag).ok_or_else(|| dicom_err(&self.path, format!("missing element {}", tag)))
+    }
+
+    fn f64s(&self, tag:Tag) -> Result<Vec<f64>, DicomIoError> {
+        self.element(tag)?.to_multi_float64().map_err(|e| dicom_err(&self.path, e))
+    }
+
+    fn int(&self, tag:Tag) -> Result<i64, DicomIoError> {
+        self.element(tag)?.to_int::<i64>().map_err(|e| dicom_err(&self.path, e))
+    }
+
+    fn opt_f64(&self, tag:Tag) -> Option<f64> {
+        self.obj.get(tag).and_then(|e| e.to_float64().ok())
+    }
+
+    fn opt_int(&self, tag:Tag) -> Option<i64> {
+        self.obj.get(tag).and_then(|e| e.to_int::<i64>().ok())
+    }
+
+    fn vec3(&self, tag:Tag, offset:usize) -> Result<[f64; 3], DicomIoError> {
+        let v = self.f64s(tag)?;
+        v.get(offset..offset + 3)
+            .map(|v| [v[0], v[1], v[2]])
+            .ok_or_else(|| dicom_err(&self.path, format!("element {} has {} values", tag, v.len())))
+    }
+
+    /// the rescaled pixel values in row-major order, so columns vary fastest
+    fn pixels(&self) -> Result<Vec<f32>, DicomIoError> {
+        let unsupported = |msg:String| DicomIoError::Unsupported{path: self.path.clone(), msg};
+        let ts = self.obj.meta().transfer_syntax().trim_end_matches('\0');
+        if ts != "1.2.840.10008.1.2" && ts != "1.2.840.10008.1.2.1" {
+            return Err(unsupported(format!("transfer syntax {} is compressed or big endian", ts)));
+        }
+        if self.opt_int(tags::SAMPLES_PER_PIXEL).unwrap_or(1) != 1 {
+            return Err(unsupported(String::from("only single sample (grayscale) images are supported")));
+        }
+        if self.opt_int(tags::NUMBER_OF_FRAMES).unwrap_or(1) != 1 {
+            return Err(unsupported(String::from("multi-frame images are not supported")));
+        }
+        let n = (self.int(tags::ROWS)? * self.int(tags::COLUMNS)?) as usize;
+        let signed = self.opt_int(tags::PIXEL_REPRESENTATION).unwrap_or(0) == 1;
+        let bits = self.int(tags::BITS_ALLOCATED)?;
+        let bytes = self.element(tags::PIXEL_DATA)?.to_bytes().map_err(|e| dicom_err(&self.path, e))?;
+        let word = (bits / 8) as usize;
+        if bytes.len() < n * word {
+            return Err(dicom_err(&self.path, format!("pixel data has {} bytes but {} are required", bytes.len(), n * word)));
+        }
+        let bytes = &bytes[..n * word];
+        let stored:Vec<f32> = match (bits, signed) {
+            (8, false) => bytes.iter().map(|&b| b as f32).collect(),
+            (8, true) => bytes.iter().map(|&b| b as i8 as f32).collect(),
+            (16, false) => bytes.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]]) as f32).collect(),
+            (16, true) => bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]) as f32).collect(),
+            (32, false) => bytes.chunks_exact(4).map(|b| u32::from_le_bytes(b.try_into().unwrap()) as f32).collect(),
+            (32, true) => bytes.chunks_exact(4).map(|b| i32::from_le_bytes(b.try_into().unwrap()) as f32).collect(),
+            _=> return Err(unsupported(format!("{} bits allocated", bits))),
+        };
+        let slope = self.opt_f64(tags::RESCALE_SLOPE).unwrap_or(1.) as f32;
+        let intercept = self.opt_f64(tags::RESCALE_INTERCEPT).unwrap_or(0.) as f32;
+        Ok(stored.into_iter().map(|x| x * slope + intercept).collect())
+    }
+}
+
+fn dot(a:[f64; 3], b:[f64; 3]) -> f64 {
+    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+}
+
+fn cross(a:[f64; 3], b:[f64; 3]) -> [f64; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+/// reads the single dicom series in a directory (see read_dicom_series_with_options)
+pub fn read_dicom_series(dir:impl AsRef<Path>) -> Result<(Vec<f32>, ArrayDim, DicomGeometry), DicomIoError> {
+    read_dicom_series_with_options(dir, &DicomReadOptions::default())
+}
+
+/// reads a dicom series from the files in a directory into a volume with axes [columns, rows,
+/// slices] or [columns, rows, slices, time] if more than one image shares each slice position.
+/// Files that are not dicom are skipped. Slices are sorted by their position along the slice
+/// normal and images at the same position are sorted by temporal position and instance number.
+/// If the directory holds more than one series, one must be selected by uid
+pub fn read_dicom_series_with_options(dir:impl AsRef<Path>, opts:&DicomReadOptions) -> Result<(Vec<f32>, ArrayDim, DicomGeometry), DicomIoError> {
+    let dir = dir.as_ref();
+    let mut files:Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| DicomIoError::IO(dir.to_path_buf(), e))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .collect();
+    files.sort();
+
+    let mut slices = vec![];
+    for path in files {
+        let Ok(obj) = open_file(&path) else {
+            continue
+        };
+        let series_uid = match obj.get(tags::SERIES_INSTANCE_UID).map(|e| e.to_str()) {
+            Some(Ok(uid)) => uid.trim_end_matches('\0').trim().to_string(),
+            _=> continue,
+        };
+        slices.push(DicomSlice { path, obj, series_uid });
+    }
+
+    let mut uids:Vec<String> = slices.iter().map(|s| s.series_uid.clone()).collect();
+    uids.sort();
+    uids.dedup();
+    let series_uid = match (&opts.series_uid, uids.len()) {
+        (Some(uid), _) => uid.clone(),
+        (None, 1) => uids[0].clone(),
+        (None, 0) => return Err(DicomIoError::NoSeries(dir.to_path_buf())),
+        (None, _) => return Err(DicomIoError::MultipleSeries(uids)),
+    };
+    slices.retain(|s| s.series_uid == series_uid);
+    let first = slices.first().ok_or_else(|| DicomIoError::NoSeries(dir.to_path_buf()))?;
+
+    let orientation = first.f64s(tags::IMAGE_ORIENTATION_PATIENT)?;
+    if orientation.len() != 6 {
+        return Err(dicom_err(&first.path, "image orientation must have 6 values"));
+    }
+    let row_dir = [orientation[0], orientation[1], orientation[2]];
+    let col_dir = [orientation[3], orientation[4], orientation[5]];
+    let slice_dir = cross(row_dir, col_dir);
+    let pixel_spacing = first.f64s(tags::PIXEL_SPACING)?;
+    if pixel_spacing.len() != 2 {
+        return Err(dicom_err(&first.path, "pixel spacing must have 2 values"));
+    }
+    let rows = first.int(tags::ROWS)? as usize;
+    let cols = first.int(tags::COLUMNS)? as usize;
+    if rows == 0 || cols == 0 {
+        return Err(dicom_err(&first.path, "rows and columns must be non-zero"));
+    }
+
+    // sort key: position along the normal, then temporal position and instance number
+    let mut keyed = vec![];
+    for s in &slices {
+        let o = s.f64s(tags::IMAGE_ORIENTATION_PATIENT)?;
+        if o.len() != 6 || o.iter().zip(orientation.iter()).any(|(a, b)| (a - b).abs() > 1e-4) {
+            return Err(DicomIoError::Inconsistent{path: s.path.clone(), msg: String::from("image orientation differs")});
+        }
+        if s.int(tags::ROWS)? as usize != rows || s.int(tags::COLUMNS)? as usize != cols {
+            return Err(DicomIoError::Inconsistent{path: s.path.clone(), msg: String::from("image size differs")});
+        }
+        let d = dot(s.vec3(tags::IMAGE_POSITION_PATIENT, 0)?, slice_dir);
+        let t = s.opt_int(tags::TEMPORAL_POSITION_IDENTIFIER).unwrap_or(0);
+        let i = s.opt_int(tags::INSTANCE_NUMBER).unwrap_or(0);
+        keyed.push((d, t, i, s));
+    }
+    keyed.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+    // group images by slice position
+    let mut positions:Vec<Vec<&DicomSlice>> = vec![];
+    let mut distances = vec![];
+    for (d, _, _, s) in &keyed {
+        match distances.last() {
+            Some(last) if d - last < POSITION_TOLERANCE => positions.last_mut().unwrap().push(*s),
+            _=> {
+                distances.push(*d);
+                positions.push(vec![*s]);
+            }
+        }
+    }
+    let n_time = positions[0].len();
+    if let Some(p) = positions.iter().find(|p| p.len() != n_time) {
+        return Err(DicomIoError::Inconsistent{path: p[0].path.clone(), msg: format!(
+            "{} images at this slice position but {} at the first", p.len(), n_time
+        )});
+    }
+    let n_slices = positions.len();
+
+    let slice_spacing = if n_slices > 1 {
+        let spacing = (distances[n_slices - 1] - distances[0]) / (n_slices - 1) as f64;
+        if distances.windows(2).any(|w| ((w[1] - w[0]) - spacing).abs() > 0.01 * spacing) {
+            crate::log_event!(warn, "slices of series {} are not evenly spaced", series_uid);
+        }
+        spacing
+    } else {
+        first.opt_f64(tags::SPACING_BETWEEN_SLICES).or(first.opt_f64(tags::SLICE_THICKNESS)).unwrap_or(1.)
+    };
+
+    let dims = if n_time > 1 {
+        ArrayDim::from_shape(&[cols, rows, n_slices, n_time])
+    } else {
+        ArrayDim::from_shape(&[cols, rows, n_slices])
+    };
+    let slice_len = rows * cols;
+    let mut data = dims.alloc(0f32);
+    for (z, images) in positions.iter().enumerate() {
+        for (t, s) in images.iter().enumerate() {
+            let start = dims.calc_addr(&[0, 0, z, t]);
+            data[start..start + slice_len].copy_from_slice(&s.pixels()?);
+        }
+    }
+
+    let geometry = DicomGeometry {
+        series_uid,
+        spacing: [pixel_spacing[1], pixel_spacing[0], slice_spacing],
+        origin: positions[0][0].vec3(tags::IMAGE_POSITION_PATIENT, 0)?,
+        row_dir,
+        col_dir,
+        slice_dir,
+    };
+    Ok((data, dims, geometry))
+}
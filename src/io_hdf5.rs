@@ -0,0 +1,308 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use hdf5::{File, H5Type, Hyperslab, Selection, SliceOrIndex};
+use hdf5::types::{TypeDescriptor, VarLenAscii, VarLenUnicode};
+use crate::ArrayDim;
+
+#[cfg(test)]
+mod tests {
+    use crate::ArrayDim;
+    use crate::io_hdf5::{read_h5_attr, read_h5_dataset, read_h5_dataset_with_order, read_h5_region, write_h5_attr, write_h5_dataset, H5Attr, H5AxisOrder, H5IoError, H5WriteOptions};
+
+    /// writes a dataset the way h5py does for np.arange(24).reshape(2, 3, 4): a row-major
+    /// dataset with shape (2, 3, 4) holding 0..24 in C order
+    fn write_h5py_fixture(path:&str) {
+        let f = hdf5::File::create(path).unwrap();
+        let ds = f.new_dataset::<i32>().shape((2,3,4)).create("x").unwrap();
+        ds.write_raw(&(0..24).collect::<Vec<i32>>()).unwrap();
+    }
+
+    #[test]
+    fn test_h5py_axis_order() {
+        write_h5py_fixture("test_h5py_order.h5");
+
+        // the shape is reversed and the data is unchanged, so x[k,j,i] in python is at [i,j,k]
+        let (x,dims) = read_h5_dataset::<i32>("test_h5py_order.h5","x").unwrap();
+        assert_eq!(dims.shape_ns(),&[4,3,2]);
+        assert_eq!(x,(0..24).collect::<Vec<i32>>());
+        assert_eq!(x[dims.calc_addr(&[3,1,1])],12 + 4 + 3);
+
+        // the data is transposed and the shape matches python, so x[i,j,k] in python is at [i,j,k]
+        let (y,dims) = read_h5_dataset_with_order::<i32>("test_h5py_order.h5","x",H5AxisOrder::Transpose).unwrap();
+        assert_eq!(dims.shape_ns(),&[2,3,4]);
+        assert_eq!(y[dims.calc_addr(&[1,1,3])],12 + 4 + 3);
+        assert_eq!(&y[..4],&[0,12,4,16]);
+
+        std::fs::remove_file("test_h5py_order.h5").unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dims = ArrayDim::from_shape(&[8,6,5]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+        for order in [H5AxisOrder::ReverseShape, H5AxisOrder::Transpose] {
+            let opts = H5WriteOptions::new().chunk(&[4,6,1]).gzip(4).order(order);
+            write_h5_dataset("test_h5_round_trip.h5","group/x",&x,dims,&opts).unwrap();
+            let (y,y_dims) = read_h5_dataset_with_order::<f32>("test_h5_round_trip.h5","group/x",order).unwrap();
+            assert_eq!(y,x);
+            assert_eq!(y_dims.shape(),dims.shape());
+
+            let (r,r_dims) = read_h5_region::<f32>("test_h5_round_trip.h5","group/x",order,&[2,1,3],&[4,2,2]).unwrap();
+            let (expected,expected_dims) = dims.copy_region(&x,&[2,1,3],&[4,2,2]);
+            assert_eq!(r,expected);
+            assert_eq!(r_dims.shape(),expected_dims.shape());
+        }
+
+        let out_of_bounds = read_h5_region::<f32>("test_h5_round_trip.h5","group/x",H5AxisOrder::ReverseShape,&[6,0,0],&[4,1,1]);
+        assert!(matches!(out_of_bounds,Err(H5IoError::InvalidRegion(..))));
+
+        write_h5_attr("test_h5_round_trip.h5","group/x","te",&H5Attr::F64(12.5)).unwrap();
+        write_h5_attr("test_h5_round_trip.h5","group/x","units",&H5Attr::Str(String::from("a.u."))).unwrap();
+        write_h5_attr("test_h5_round_trip.h5","group/x","te",&H5Attr::F64(15.)).unwrap();
+        assert_eq!(read_h5_attr("test_h5_round_trip.h5","group/x","te").unwrap(),H5Attr::F64(15.));
+        assert_eq!(read_h5_attr("test_h5_round_trip.h5","group/x","units").unwrap(),H5Attr::Str(String::from("a.u.")));
+
+        std::fs::remove_file("test_h5_round_trip.h5").unwrap();
+    }
+
+}
+
+#[derive(Debug)]
+pub enum H5IoError {
+    Hdf5{path: PathBuf, source: hdf5::Error},
+    InconsistentArraySize{expected: usize, actual: usize},
+    InvalidRegion(String),
+    Unsupported{path: PathBuf, msg: String},
+}
+
+impl Display for H5IoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            H5IoError::Hdf5 {path, source} => write!(f, "hdf5 error for {}: {}", path.display(), source),
+            H5IoError::InconsistentArraySize {expected, actual} => write!(
+                f, "array dimensions require {} elements but {} were supplied", expected, actual
+            ),
+            H5IoError::InvalidRegion(msg) => write!(f, "invalid region: {}", msg),
+            H5IoError::Unsupported {path, msg} => write!(f, "unsupported hdf5 data in {}: {}", path.display(), msg),
+        }
+    }
+}
+
+impl std::error::Error for H5IoError {}
+
+fn h5_err(path: &Path) -> impl FnOnce(hdf5::Error) -> H5IoError {
+    let path = path.to_path_buf();
+    move |source| H5IoError::Hdf5{path, source}
+}
+
+/// how the axes of a row-major hdf5 dataset map to the column-major axes of this crate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum H5AxisOrder {
+    /// the shape is reversed and the data is left as is. A dataset of shape (z, y, x) becomes an
+    /// array of shape [x, y, z]. This is free and matches how MATLAB and Julia see hdf5 files
+    #[default]
+    ReverseShape,
+    /// the data is transposed so the shape matches the dataset. A dataset of shape (z, y, x)
+    /// becomes an array of shape [z, y, x], indexed the same way as in numpy
+    Transpose,
+}
+
+/// options for writing an hdf5 dataset
+#[derive(Debug, Clone, Default)]
+pub struct H5WriteOptions {
+    chunk: Option<Vec<usize>>,
+    gzip: Option<u8>,
+    order: H5AxisOrder,
+}
+
+impl H5WriteOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// sets the chunk shape, given in the axis order of the array
+    pub fn chunk(mut self, chunk:&[usize]) -> Self {
+        self.chunk = Some(chunk.to_vec());
+        self
+    }
+
+    /// enables gzip compression with a level from 0 to 9. Datasets are chunked by slices of the
+    /// last axis if no chunk shape is set
+    pub fn gzip(mut self, level:u8) -> Self {
+        self.gzip = Some(level.min(9));
+        self
+    }
+
+    pub fn order(mut self, order:H5AxisOrder) -> Self {
+        self.order = order;
+        self
+    }
+}
+
+/// the reversed axis order of a permutation over n axes
+fn reversed_axes(n:usize) -> Vec<usize> {
+    (0..n).rev().collect()
+}
+
+/// converts row-major data with the given dataset shape to column-major data with the same shape
+fn c_to_f<T:Copy + Send + Sync>(data:&[T], h5_shape:&[usize]) -> Vec<T> {
+    let reversed:Vec<usize> = h5_shape.iter().rev().copied().collect();
+    let dims = ArrayDim::from_shape(&reversed);
+    let mut dst = data.to_vec();
+    dims.permute(data, &mut dst, &reversed_axes(dims.shape_ns().len()));
+    dst
+}
+
+/// converts column-major data to row-major data with the same shape
+fn f_to_c<T:Copy + Send + Sync>(data:&[T], dims:&ArrayDim) -> Vec<T> {
+    let mut dst = data.to_vec();
+    dims.permute(data, &mut dst, &reversed_axes(dims.shape_ns().len()));
+    dst
+}
+
+/// maps array axes to dataset axes for the axis order, given the dataset rank
+fn to_h5_axes(x:&[usize], rank:usize, order:H5AxisOrder) -> Vec<usize> {
+    let mut x = x.to_vec();
+    x.resize(rank, 1);
+    if order == H5AxisOrder::ReverseShape {
+        x.reverse();
+    }
+    x
+}
+
+/// the array dimensions of a dataset shape for the axis order
+fn array_dims(path:&Path, h5_shape:&[usize], order:H5AxisOrder) -> Result<ArrayDim, H5IoError> {
+    if h5_shape.len() > crate::N_DIMS {
+        return Err(H5IoError::Unsupported{path: path.to_path_buf(), msg: format!("datasets of up to {} dimensions are supported", crate::N_DIMS)});
+    }
+    let mut shape = h5_shape.to_vec();
+    if order == H5AxisOrder::ReverseShape {
+        shape.reverse();
+    }
+    ArrayDim::try_from_shape(&shape).map_err(|e| H5IoError::Unsupported{path: path.to_path_buf(), msg: e.to_string()})
+}
+
+/// reads a dataset with the shape reversed (see H5AxisOrder::ReverseShape)
+pub fn read_h5_dataset<T:H5Type + Copy + Send + Sync>(file:impl AsRef<Path>, dataset:&str) -> Result<(Vec<T>, ArrayDim), H5IoError> {
+    read_h5_dataset_with_order(file, dataset, H5AxisOrder::ReverseShape)
+}
+
+/// reads a dataset, mapping its axes with the given axis order. The hdf5 library converts the
+/// stored type to T
+pub fn read_h5_dataset_with_order<T:H5Type + Copy + Send + Sync>(file:impl AsRef<Path>, dataset:&str, order:H5AxisOrder) -> Result<(Vec<T>, ArrayDim), H5IoError> {
+    let path = file.as_ref();
+    let ds = File::open(path).and_then(|f| f.dataset(dataset)).map_err(h5_err(path))?;
+    let h5_shape = ds.shape();
+    let dims = array_dims(path, &h5_shape, order)?;
+    let data = ds.read_raw::<T>().map_err(h5_err(path))?;
+    match order {
+        H5AxisOrder::ReverseShape => Ok((data, dims)),
+        H5AxisOrder::Transpose => Ok((c_to_f(&data, &h5_shape), dims)),
+    }
+}
+
+/// reads a hyper-rectangular region of a dataset given by an offset and size in the axes of the
+/// array, as with read_cfl_region. Only the region is read from the file
+pub fn read_h5_region<T:H5Type + Copy + Send + Sync>(file:impl AsRef<Path>, dataset:&str, order:H5AxisOrder, offset:&[usize], size:&[usize]) -> Result<(Vec<T>, ArrayDim), H5IoError> {
+    let path = file.as_ref();
+    let ds = File::open(path).and_then(|f| f.dataset(dataset)).map_err(h5_err(path))?;
+    let h5_shape = ds.shape();
+    let dims = array_dims(path, &h5_shape, order)?;
+    let region_dims = dims.region_dims(offset, size).map_err(H5IoError::InvalidRegion)?;
+
+    // region_dims fills in the axes not covered by the offset and size
+    let rank = h5_shape.len();
+    let full_offset:Vec<usize> = (0..rank).map(|ax| offset.get(ax).copied().unwrap_or(0)).collect();
+    let h5_offset = to_h5_axes(&full_offset, rank, order);
+    let h5_size = to_h5_axes(&region_dims.shape()[..rank], rank, order);
+    let slab:Vec<SliceOrIndex> = h5_offset.iter().zip(h5_size.iter()).map(|(&o, &s)| SliceOrIndex::from(o..o + s)).collect();
+    let selection = Selection::from(Hyperslab::from(slab));
+    let region = ds.read_slice::<T, _, ndarray::IxDyn>(selection).map_err(h5_err(path))?;
+    let data:Vec<T> = region.iter().copied().collect();
+    match order {
+        H5AxisOrder::ReverseShape => Ok((data, region_dims)),
+        H5AxisOrder::Transpose => Ok((c_to_f(&data, &h5_size), region_dims)),
+    }
+}
+
+/// writes an array to a dataset, creating the file if needed and replacing any existing dataset
+/// of the same name. Groups in the dataset path are created as needed
+pub fn write_h5_dataset<T:H5Type + Copy + Send + Sync>(file:impl AsRef<Path>, dataset:&str, data:&[T], dims:ArrayDim, opts:&H5WriteOptions) -> Result<(), H5IoError> {
+    let path = file.as_ref();
+    if data.len() != dims.numel() {
+        return Err(H5IoError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+    }
+    let rank = dims.shape_ns().len();
+    let h5_shape = to_h5_axes(dims.shape_ns(), rank, opts.order);
+
+    let f = File::append(path).map_err(h5_err(path))?;
+    let parts:Vec<&str> = dataset.split('/').filter(|p| !p.is_empty()).collect();
+    let mut group = String::new();
+    for part in parts.iter().take(parts.len().saturating_sub(1)) {
+        group = format!("{}/{}", group, part);
+        if !f.link_exists(&group) {
+            f.create_group(&group).map_err(h5_err(path))?;
+        }
+    }
+    if f.link_exists(dataset) {
+        f.unlink(dataset).map_err(h5_err(path))?;
+    }
+
+    let mut builder = f.new_dataset::<T>().shape(h5_shape.clone());
+    let chunk = match (&opts.chunk, opts.gzip) {
+        (Some(chunk), _) => Some(to_h5_axes(chunk, rank, opts.order)),
+        (None, Some(_)) => Some(std::iter::once(1).chain(h5_shape.iter().skip(1).copied()).collect()),
+        (None, None) => None,
+    };
+    if let Some(chunk) = chunk {
+        let chunk:Vec<usize> = chunk.iter().zip(h5_shape.iter()).map(|(&c, &s)| c.clamp(1, s.max(1))).collect();
+        builder = builder.chunk(chunk);
+    }
+    if let Some(level) = opts.gzip {
+        builder = builder.deflate(level);
+    }
+    let ds = builder.create(dataset).map_err(h5_err(path))?;
+    match opts.order {
+        H5AxisOrder::ReverseShape => ds.write_raw(data),
+        H5AxisOrder::Transpose => ds.write_raw(&f_to_c(data, &dims)),
+    }.map_err(h5_err(path))
+}
+
+/// a scalar attribute value
+#[derive(Debug, Clone, PartialEq)]
+pub enum H5Attr {
+    F64(f64),
+    Str(String),
+}
+
+/// reads a scalar attribute of a dataset. Numeric attributes are converted to f64. Variable length
+/// strings, as written by h5py, are supported
+pub fn read_h5_attr(file:impl AsRef<Path>, dataset:&str, name:&str) -> Result<H5Attr, H5IoError> {
+    let path = file.as_ref();
+    let attr = File::open(path).and_then(|f| f.dataset(dataset)).and_then(|ds| ds.attr(name)).map_err(h5_err(path))?;
+    let descriptor = attr.dtype().and_then(|t| t.to_descriptor()).map_err(h5_err(path))?;
+    match descriptor {
+        TypeDescriptor::Float(_) | TypeDescriptor::Integer(_) | TypeDescriptor::Unsigned(_) => {
+            attr.read_scalar::<f64>().map(H5Attr::F64)
+        }
+        TypeDescriptor::VarLenUnicode => attr.read_scalar::<VarLenUnicode>().map(|s| H5Attr::Str(s.as_str().to_string())),
+        TypeDescriptor::VarLenAscii => attr.read_scalar::<VarLenAscii>().map(|s| H5Attr::Str(s.as_str().to_string())),
+        t => return Err(H5IoError::Unsupported{path: path.to_path_buf(), msg: format!("attribute {} has type {:?}", name, t)}),
+    }.map_err(h5_err(path))
+}
+
+/// writes a scalar attribute to a dataset, replacing any existing attribute of the same name
+pub fn write_h5_attr(file:impl AsRef<Path>, dataset:&str, name:&str, value:&H5Attr) -> Result<(), H5IoError> {
+    let path = file.as_ref();
+    let ds = File::open_rw(path).and_then(|f| f.dataset(dataset)).map_err(h5_err(path))?;
+    if ds.attr_names().map_err(h5_err(path))?.iter().any(|n| n == name) {
+        ds.delete_attr(name).map_err(h5_err(path))?;
+    }
+    match value {
+        H5Attr::F64(x) => ds.new_attr::<f64>().create(name).and_then(|a| a.write_scalar(x)),
+        H5Attr::Str(s) => {
+            let s:VarLenUnicode = s.parse().map_err(|e| H5IoError::Unsupported{path: path.to_path_buf(), msg: format!("invalid string attribute: {}", e)})?;
+            ds.new_attr::<VarLenUnicode>().create(name).and_then(|a| a.write_scalar(&s))
+        }
+    }.map_err(h5_err(path))
+}
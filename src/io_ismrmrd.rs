@@ -0,0 +1,465 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use hdf5::{File, H5Type};
+use hdf5::types::{TypeDescriptor, VarLenArray, VarLenAscii, VarLenUnicode};
+use num_complex::Complex32;
+use crate::{AllocError, ArrayDim};
+
+#[cfg(test)]
+mod tests {
+    use hdf5::H5Type;
+    use hdf5::types::{VarLenArray, VarLenUnicode};
+    use num_complex::Complex32;
+    use crate::io_ismrmrd::{read_ismrmrd, ComplexFloat, EncodingCounters, ACQ_IS_NOISE_MEASUREMENT};
+
+    #[derive(H5Type, Clone, Copy)]
+    #[repr(C)]
+    struct TestHead {
+        version: u16,
+        flags: u64,
+        number_of_samples: u16,
+        active_channels: u16,
+        center_sample: u16,
+        idx: EncodingCounters,
+    }
+
+    #[derive(H5Type, Clone)]
+    #[repr(C)]
+    struct TestAcq {
+        head: TestHead,
+        traj: VarLenArray<f32>,
+        data: VarLenArray<ComplexFloat>,
+    }
+
+    const XML: &str = "<?xml version=\"1.0\"?>
+<ismrmrdHeader xmlns=\"http://www.ismrm.org/ISMRMRD\">
+  <encoding>
+    <encodedSpace>
+      <matrixSize><x>4</x><y>4</y><z>2</z></matrixSize>
+      <fieldOfView_mm><x>100</x><y>100</y><z>20</z></fieldOfView_mm>
+    </encodedSpace>
+    <reconSpace>
+      <matrixSize><x>4</x><y>4</y><z>2</z></matrixSize>
+      <fieldOfView_mm><x>100</x><y>100</y><z>20</z></fieldOfView_mm>
+    </reconSpace>
+    <encodingLimits>
+      <kspace_encoding_step_1><minimum>0</minimum><maximum>3</maximum><center>2</center></kspace_encoding_step_1>
+      <kspace_encoding_step_2><minimum>0</minimum><maximum>1</maximum><center>1</center></kspace_encoding_step_2>
+    </encodingLimits>
+  </encoding>
+</ismrmrdHeader>";
+
+    /// the sample value for a sample, coil and phase encode
+    fn value(s:usize, c:usize, ky:usize, kz:usize) -> Complex32 {
+        Complex32::new((s + 10 * c + 100 * ky + 1000 * kz) as f32, -(c as f32))
+    }
+
+    fn acq(flags:u64, ky:usize, kz:usize) -> TestAcq {
+        let mut data = vec![];
+        for c in 0..2 {
+            for s in 0..4 {
+                let v = if flags == 0 { value(s,c,ky,kz) } else { Complex32::new(-1.,-1.) };
+                data.push(ComplexFloat { real: v.re, imag: v.im });
+            }
+        }
+        let idx = EncodingCounters { kspace_encode_step_1: ky as u16, kspace_encode_step_2: kz as u16, ..Default::default() };
+        TestAcq {
+            head: TestHead { version: 1, flags, number_of_samples: 4, active_channels: 2, center_sample: 2, idx },
+            traj: VarLenArray::from_slice(&[]),
+            data: VarLenArray::from_slice(&data),
+        }
+    }
+
+    #[test]
+    fn test_read_ismrmrd() {
+        // a noise scan followed by lines in a scrambled order, with ky = 3 kz = 1 never acquired
+        let mut acqs = vec![acq(ACQ_IS_NOISE_MEASUREMENT,0,0)];
+        for kz in 0..2 {
+            for ky in [2,0,3,1] {
+                if (ky,kz) != (3,1) {
+                    acqs.push(acq(0,ky,kz));
+                }
+            }
+        }
+        let f = hdf5::File::create("test_ismrmrd.h5").unwrap();
+        let g = f.create_group("dataset").unwrap();
+        let xml:VarLenUnicode = XML.parse().unwrap();
+        g.new_dataset::<VarLenUnicode>().shape(1).create("xml").unwrap().write_raw(&[xml]).unwrap();
+        g.new_dataset::<TestAcq>().shape(acqs.len()).create("data").unwrap().write_raw(&acqs).unwrap();
+        drop(g);
+        drop(f);
+
+        let d = read_ismrmrd("test_ismrmrd.h5").unwrap();
+        std::fs::remove_file("test_ismrmrd.h5").unwrap();
+
+        assert_eq!(d.header.encoded_matrix,[4,4,2]);
+        assert_eq!(d.header.encoded_fov,[100.,100.,20.]);
+        assert_eq!(d.header.limits.kspace_encoding_step_1.unwrap().center,2);
+        assert!(d.header.limits.slice.is_none());
+
+        assert_eq!(d.acquisitions.len(),8);
+        assert!(d.acquisitions[0].is_noise());
+        assert!(!d.acquisitions[0].included);
+        assert!(d.acquisitions[1..].iter().all(|a| a.included && !a.is_noise()));
+
+        assert_eq!(d.dims.shape_ns(),&[4,2,4,2]);
+        for kz in 0..2 {
+            for ky in 0..4 {
+                for c in 0..2 {
+                    for s in 0..4 {
+                        let expected = if (ky,kz) == (3,1) { Complex32::ZERO } else { value(s,c,ky,kz) };
+                        assert_eq!(d.data[d.dims.calc_addr(&[s,c,ky,kz])],expected);
+                    }
+                }
+            }
+        }
+    }
+
+}
+
+#[derive(Debug)]
+pub enum IsmrmrdError {
+    Hdf5{path: PathBuf, source: hdf5::Error},
+    Header{path: PathBuf, msg: String},
+    Acquisition{path: PathBuf, index: usize, msg: String},
+    Alloc{path: PathBuf, source: AllocError},
+}
+
+impl Display for IsmrmrdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IsmrmrdError::Hdf5 {path, source} => write!(f, "hdf5 error for {}: {}", path.display(), source),
+            IsmrmrdError::Header {path, msg} => write!(f, "invalid ismrmrd header in {}: {}", path.display(), msg),
+            IsmrmrdError::Acquisition {path, index, msg} => write!(f, "invalid acquisition {} in {}: {}", index, path.display(), msg),
+            IsmrmrdError::Alloc {path, source} => write!(f, "can't read {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for IsmrmrdError {}
+
+fn h5_err(path: &Path) -> impl FnOnce(hdf5::Error) -> IsmrmrdError {
+    let path = path.to_path_buf();
+    move |source| IsmrmrdError::Hdf5{path, source}
+}
+
+// acquisition flags, bit n - 1 for ismrmrd flag n
+pub const ACQ_IS_NOISE_MEASUREMENT: u64 = 1 << 18;
+pub const ACQ_IS_PARALLEL_CALIBRATION: u64 = 1 << 19;
+pub const ACQ_IS_PARALLEL_CALIBRATION_AND_IMAGING: u64 = 1 << 20;
+pub const ACQ_IS_REVERSE: u64 = 1 << 21;
+pub const ACQ_IS_NAVIGATION_DATA: u64 = 1 << 22;
+pub const ACQ_IS_PHASECORR_DATA: u64 = 1 << 23;
+pub const ACQ_IS_DUMMYSCAN_DATA: u64 = 1 << 26;
+
+/// the encoding counters of an acquisition, read from the idx field of the acquisition header
+#[derive(H5Type, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct EncodingCounters {
+    pub kspace_encode_step_1: u16,
+    pub kspace_encode_step_2: u16,
+    pub average: u16,
+    pub slice: u16,
+    pub contrast: u16,
+    pub phase: u16,
+    pub repetition: u16,
+    pub set: u16,
+    pub segment: u16,
+    pub user: [u16; 8],
+}
+
+impl EncodingCounters {
+    /// the counters in the order of the array axes after samples and coils
+    fn axes(&self) -> [usize; N_COUNTERS] {
+        [
+            self.kspace_encode_step_1,
+            self.kspace_encode_step_2,
+            self.average,
+            self.slice,
+            self.contrast,
+            self.phase,
+            self.repetition,
+            self.set,
+            self.segment,
+        ].map(|c| c as usize)
+    }
+}
+
+const N_COUNTERS: usize = 9;
+
+/// the fields of the acquisition header used for assembly. HDF5 matches compound members by
+/// name, so the remaining header fields are not read
+#[derive(H5Type, Clone, Copy)]
+#[repr(C)]
+struct AcquisitionHead {
+    flags: u64,
+    number_of_samples: u16,
+    active_channels: u16,
+    center_sample: u16,
+    idx: EncodingCounters,
+}
+
+/// a complex sample as stored in the acquisition data
+#[derive(H5Type, Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct ComplexFloat {
+    pub real: f32,
+    pub imag: f32,
+}
+
+#[derive(H5Type, Clone)]
+#[repr(C)]
+struct Acquisition {
+    head: AcquisitionHead,
+    data: VarLenArray<ComplexFloat>,
+}
+
+/// the header of an acquisition along with whether it was placed in the assembled array
+#[derive(Clone, Copy, Debug)]
+pub struct AcquisitionInfo {
+    pub flags: u64,
+    pub number_of_samples: usize,
+    pub active_channels: usize,
+    pub center_sample: usize,
+    pub idx: EncodingCounters,
+    pub included: bool,
+}
+
+impl AcquisitionInfo {
+    pub fn has_flag(&self, flag:u64) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn is_noise(&self) -> bool {
+        self.has_flag(ACQ_IS_NOISE_MEASUREMENT)
+    }
+
+    pub fn is_phase_correction(&self) -> bool {
+        self.has_flag(ACQ_IS_PHASECORR_DATA)
+    }
+}
+
+/// the minimum, maximum and center of an encoding counter
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EncodingLimit {
+    pub minimum: usize,
+    pub maximum: usize,
+    pub center: usize,
+}
+
+/// encoding limits given in the header. Limits that are not in the header are None
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EncodingLimits {
+    pub kspace_encoding_step_1: Option<EncodingLimit>,
+    pub kspace_encoding_step_2: Option<EncodingLimit>,
+    pub average: Option<EncodingLimit>,
+    pub slice: Option<EncodingLimit>,
+    pub contrast: Option<EncodingLimit>,
+    pub phase: Option<EncodingLimit>,
+    pub repetition: Option<EncodingLimit>,
+    pub set: Option<EncodingLimit>,
+    pub segment: Option<EncodingLimit>,
+}
+
+impl EncodingLimits {
+    /// the limits in the order of the array axes after samples and coils
+    fn axes(&self) -> [Option<EncodingLimit>; N_COUNTERS] {
+        [
+            self.kspace_encoding_step_1,
+            self.kspace_encoding_step_2,
+            self.average,
+            self.slice,
+            self.contrast,
+            self.phase,
+            self.repetition,
+            self.set,
+            self.segment,
+        ]
+    }
+}
+
+/// the parts of the ismrmrd xml header describing the first encoding
+#[derive(Clone, Debug, Default)]
+pub struct IsmrmrdHeader {
+    pub encoded_matrix: [usize; 3],
+    pub encoded_fov: [f32; 3],
+    pub recon_matrix: [usize; 3],
+    pub recon_fov: [f32; 3],
+    pub limits: EncodingLimits,
+    /// the full xml header
+    pub xml: String,
+}
+
+/// returns the contents of the first element with the given tag
+fn xml_element<'a>(xml:&'a str, tag:&str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(&xml[start..end])
+}
+
+/// parses the value of the element at a path of nested tags
+fn xml_value<T:FromStr>(xml:&str, tags:&[&str]) -> Option<T> {
+    tags.iter().try_fold(xml, |x, tag| xml_element(x, tag))?.trim().parse().ok()
+}
+
+impl IsmrmrdHeader {
+
+    fn parse(xml:&str, path:&Path) -> Result<IsmrmrdHeader, IsmrmrdError> {
+        let header_err = |msg:&str| IsmrmrdError::Header{path: path.to_path_buf(), msg: msg.to_string()};
+        let encoding = xml_element(xml, "encoding").ok_or_else(|| header_err("missing encoding"))?;
+        let xyz = |space:&str, field:&str| -> Result<[f32; 3], IsmrmrdError> {
+            let mut v = [0.; 3];
+            for (v, ax) in v.iter_mut().zip(["x", "y", "z"]) {
+                *v = xml_value(encoding, &[space, field, ax]).ok_or_else(|| header_err(&format!("missing {}/{}/{}", space, field, ax)))?;
+            }
+            Ok(v)
+        };
+        let limit = |counter:&str| -> Option<EncodingLimit> {
+            let el = xml_element(encoding, "encodingLimits").and_then(|l| xml_element(l, counter))?;
+            Some(EncodingLimit {
+                minimum: xml_value(el, &["minimum"])?,
+                maximum: xml_value(el, &["maximum"])?,
+                center: xml_value(el, &["center"])?,
+            })
+        };
+        Ok(IsmrmrdHeader {
+            encoded_matrix: xyz("encodedSpace", "matrixSize")?.map(|x| x as usize),
+            encoded_fov: xyz("encodedSpace", "fieldOfView_mm")?,
+            recon_matrix: xyz("reconSpace", "matrixSize")?.map(|x| x as usize),
+            recon_fov: xyz("reconSpace", "fieldOfView_mm")?,
+            limits: EncodingLimits {
+                kspace_encoding_step_1: limit("kspace_encoding_step_1"),
+                kspace_encoding_step_2: limit("kspace_encoding_step_2"),
+                average: limit("average"),
+                slice: limit("slice"),
+                contrast: limit("contrast"),
+                phase: limit("phase"),
+                repetition: limit("repetition"),
+                set: limit("set"),
+                segment: limit("segment"),
+            },
+            xml: xml.to_string(),
+        })
+    }
+}
+
+/// options for reading ismrmrd files
+#[derive(Clone, Debug)]
+pub struct IsmrmrdReadOptions {
+    group: String,
+    exclude: u64,
+}
+
+impl Default for IsmrmrdReadOptions {
+    fn default() -> Self {
+        IsmrmrdReadOptions {
+            group: String::from("dataset"),
+            exclude: ACQ_IS_NOISE_MEASUREMENT | ACQ_IS_PHASECORR_DATA | ACQ_IS_NAVIGATION_DATA | ACQ_IS_DUMMYSCAN_DATA,
+        }
+    }
+}
+
+impl IsmrmrdReadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// sets the group holding the xml and data datasets. Defaults to "dataset"
+    pub fn group(mut self, group:&str) -> Self {
+        self.group = group.to_string();
+        self
+    }
+
+    /// sets the acquisition flags that exclude an acquisition from the assembled array. Defaults
+    /// to noise, phase correction, navigation and dummy scans
+    pub fn exclude(mut self, flags:u64) -> Self {
+        self.exclude = flags;
+        self
+    }
+}
+
+/// raw data assembled from an ismrmrd file
+#[derive(Clone, Debug)]
+pub struct IsmrmrdData {
+    pub header: IsmrmrdHeader,
+    /// k-space with axes [samples, coils, ky, kz, average, slice, contrast, phase, repetition, set,
+    /// segment]
+    pub data: Vec<Complex32>,
+    pub dims: ArrayDim,
+    /// every acquisition in the file in order, including excluded ones
+    pub acquisitions: Vec<AcquisitionInfo>,
+}
+
+/// reads an ismrmrd file with the default options
+pub fn read_ismrmrd(file:impl AsRef<Path>) -> Result<IsmrmrdData, IsmrmrdError> {
+    read_ismrmrd_with_options(file, &IsmrmrdReadOptions::default())
+}
+
+/// reads an ismrmrd file, placing each included acquisition in k-space by its encoding counters.
+/// The size of each counter axis covers both the encoding limits in the header and the largest
+/// counter acquired, and lines that were never acquired are left as zero. Acquisitions with fewer
+/// samples than the longest are placed at the start of the readout
+pub fn read_ismrmrd_with_options(file:impl AsRef<Path>, opts:&IsmrmrdReadOptions) -> Result<IsmrmrdData, IsmrmrdError> {
+    let path = file.as_ref();
+    let group = File::open(path).and_then(|f| f.group(&opts.group)).map_err(h5_err(path))?;
+
+    let xml_ds = group.dataset("xml").map_err(h5_err(path))?;
+    let descriptor = xml_ds.dtype().and_then(|t| t.to_descriptor()).map_err(h5_err(path))?;
+    let xml = match descriptor {
+        TypeDescriptor::VarLenUnicode => xml_ds.read_raw::<VarLenUnicode>().map(|x| x.first().map(|s| s.as_str().to_string())),
+        TypeDescriptor::VarLenAscii => xml_ds.read_raw::<VarLenAscii>().map(|x| x.first().map(|s| s.as_str().to_string())),
+        t => return Err(IsmrmrdError::Header{path: path.to_path_buf(), msg: format!("xml has type {:?}", t)}),
+    }.map_err(h5_err(path))?.ok_or_else(|| IsmrmrdError::Header{path: path.to_path_buf(), msg: String::from("xml is empty")})?;
+    let header = IsmrmrdHeader::parse(&xml, path)?;
+
+    let acqs = group.dataset("data").and_then(|ds| ds.read_raw::<Acquisition>()).map_err(h5_err(path))?;
+    let acquisitions:Vec<AcquisitionInfo> = acqs.iter().map(|a| AcquisitionInfo {
+        flags: a.head.flags,
+        number_of_samples: a.head.number_of_samples as usize,
+        active_channels: a.head.active_channels as usize,
+        center_sample: a.head.center_sample as usize,
+        idx: a.head.idx,
+        included: a.head.flags & opts.exclude == 0,
+    }).collect();
+
+    // the array covers the encoding limits and every included acquisition
+    let mut shape = [0usize; 2 + N_COUNTERS];
+    for (s, limit) in shape[2..].iter_mut().zip(header.limits.axes()) {
+        *s = limit.map(|l| l.maximum + 1).unwrap_or(1);
+    }
+    for (i, a) in acquisitions.iter().enumerate().filter(|(_, a)| a.included) {
+        let expected = a.number_of_samples * a.active_channels;
+        if acqs[i].data.len() != expected {
+            return Err(IsmrmrdError::Acquisition{path: path.to_path_buf(), index: i, msg: format!(
+                "expected {} values for {} samples and {} channels but found {}",
+                expected, a.number_of_samples, a.active_channels, acqs[i].data.len()
+            )});
+        }
+        shape[0] = shape[0].max(a.number_of_samples);
+        shape[1] = shape[1].max(a.active_channels);
+        for (s, c) in shape[2..].iter_mut().zip(a.idx.axes()) {
+            *s = (*s).max(c + 1);
+        }
+    }
+    let shape = shape.map(|s| s.max(1));
+    let dims = ArrayDim::from_shape(&shape);
+    let mut data = dims.try_alloc(Complex32::ZERO)
+        .map_err(|source| IsmrmrdError::Alloc{path: path.to_path_buf(), source})?;
+
+    // each acquisition holds its channels one after another
+    for (a, acq) in acquisitions.iter().zip(acqs.iter()).filter(|(a, _)| a.included) {
+        let raw:Vec<Complex32> = acq.data.iter().map(|x| Complex32::new(x.real, x.imag)).collect();
+        let mut idx = [0usize; 2 + N_COUNTERS];
+        idx[2..].copy_from_slice(&a.idx.axes());
+        for (c, channel) in raw.chunks_exact(a.number_of_samples.max(1)).enumerate() {
+            idx[1] = c;
+            let start = dims.calc_addr(&idx);
+            data[start..start + channel.len()].copy_from_slice(channel);
+        }
+    }
+
+    Ok(IsmrmrdData { header, data, dims, acquisitions })
+}
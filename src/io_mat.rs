@@ -0,0 +1,511 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use bytemuck::Pod;
+use flate2::read::ZlibDecoder;
+use num_complex::{Complex32, Complex64};
+use crate::{ArrayDim, N_DIMS};
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use num_complex::Complex32;
+    use crate::ArrayDim;
+    use crate::io_mat::{read_mat, write_mat, MatArray, MatIoError};
+
+    #[test]
+    fn test_round_trip() {
+        let c_dims = ArrayDim::from_shape(&[3,4,2]);
+        let c = MatArray::C64((0..c_dims.numel()).map(|i| Complex32::new(i as f32,-(i as f32) * 0.5)).collect());
+        let i_dims = ArrayDim::from_shape(&[5,2]);
+        let i = MatArray::I16((0..10).map(|i| i as i16 - 5).collect());
+        write_mat("test_mat_round_trip.mat",&[("kspace",&c,c_dims),("mask",&i,i_dims)]).unwrap();
+
+        let (y,y_dims) = read_mat("test_mat_round_trip.mat","kspace").unwrap();
+        assert_eq!(y,c);
+        assert_eq!(y_dims.shape(),c_dims.shape());
+        let (y,y_dims) = read_mat("test_mat_round_trip.mat","mask").unwrap();
+        assert_eq!(y,i);
+        assert_eq!(y_dims.shape(),i_dims.shape());
+        assert!(matches!(read_mat("test_mat_round_trip.mat","missing"),Err(MatIoError::VariableNotFound{..})));
+
+        // the same variables wrapped in a miCOMPRESSED element
+        let bytes = std::fs::read("test_mat_round_trip.mat").unwrap();
+        let mut z = ZlibEncoder::new(vec![],Compression::default());
+        z.write_all(&bytes[128..]).unwrap();
+        let z = z.finish().unwrap();
+        let mut compressed = bytes[..128].to_vec();
+        compressed.extend_from_slice(&15u32.to_le_bytes());
+        compressed.extend_from_slice(&(z.len() as u32).to_le_bytes());
+        compressed.extend_from_slice(&z);
+        std::fs::write("test_mat_round_trip.mat",&compressed).unwrap();
+        let (y,_) = read_mat("test_mat_round_trip.mat","kspace").unwrap();
+        assert_eq!(y,c);
+
+        let invalid = write_mat("test_mat_round_trip.mat",&[("1x",&i,i_dims)]);
+        assert!(matches!(invalid,Err(MatIoError::InvalidName(..))));
+        std::fs::remove_file("test_mat_round_trip.mat").unwrap();
+    }
+
+    #[test]
+    fn test_small_elements() {
+        // x = 2 as saved by matlab: the name and value use the small data element format and
+        // the double is stored as a uint8
+        let mut bytes = vec![b' ';116];
+        bytes.extend_from_slice(&[0;8]);
+        bytes.extend_from_slice(&[0x00,0x01,b'I',b'M']);
+        for w in [14u32,48, 6,8, 6,0, 5,8, 1,1, (1 << 16) | 1, b'x' as u32, (1 << 16) | 2, 2] {
+            bytes.extend_from_slice(&w.to_le_bytes());
+        }
+        std::fs::write("test_mat_small.mat",&bytes).unwrap();
+        let (y,dims) = read_mat("test_mat_small.mat","x").unwrap();
+        std::fs::remove_file("test_mat_small.mat").unwrap();
+        assert_eq!(y,MatArray::F64(vec![2.]));
+        assert_eq!(dims.numel(),1);
+    }
+
+    #[test]
+    fn test_reject_v73() {
+        let mut bytes = b"MATLAB 7.3 MAT-file".to_vec();
+        bytes.resize(124,b' ');
+        bytes.extend_from_slice(&[0x00,0x02,b'I',b'M']);
+        std::fs::write("test_mat_v73.mat",&bytes).unwrap();
+        let r = read_mat("test_mat_v73.mat","x");
+        std::fs::remove_file("test_mat_v73.mat").unwrap();
+        match r {
+            Err(e @ MatIoError::Unsupported{..}) => assert!(e.to_string().contains("io-hdf5")),
+            _=> panic!("expected v7.3 files to be rejected"),
+        }
+    }
+
+}
+
+#[derive(Debug)]
+pub enum MatIoError {
+    IO(PathBuf, std::io::Error),
+    Format{path: PathBuf, msg: String},
+    Unsupported{path: PathBuf, msg: String},
+    VariableNotFound{path: PathBuf, var: String},
+    InvalidName(String),
+    DuplicateName(String),
+    InconsistentArraySize{expected: usize, actual: usize},
+}
+
+impl Display for MatIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatIoError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            MatIoError::Format {path, msg} => write!(f, "invalid mat file {}: {}", path.display(), msg),
+            MatIoError::Unsupported {path, msg} => write!(f, "unsupported mat file {}: {}", path.display(), msg),
+            MatIoError::VariableNotFound {path, var} => write!(f, "variable {} not found in {}", var, path.display()),
+            MatIoError::InvalidName(name) => write!(f, "invalid MATLAB variable name {}", name),
+            MatIoError::DuplicateName(name) => write!(f, "duplicate MATLAB variable {}", name),
+            MatIoError::InconsistentArraySize {expected, actual} => write!(
+                f, "array dimensions require {} elements but {} were supplied", expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MatIoError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> MatIoError {
+    let path = path.to_path_buf();
+    move |e| MatIoError::IO(path, e)
+}
+
+/// a numeric MATLAB array of any supported class. Complex arrays are single or double
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatArray {
+    F64(Vec<f64>),
+    F32(Vec<f32>),
+    I8(Vec<i8>),
+    I16(Vec<i16>),
+    I32(Vec<i32>),
+    I64(Vec<i64>),
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    U64(Vec<u64>),
+    C64(Vec<Complex32>),
+    C128(Vec<Complex64>),
+}
+
+impl MatArray {
+    pub fn len(&self) -> usize {
+        match self {
+            MatArray::F64(x) => x.len(),
+            MatArray::F32(x) => x.len(),
+            MatArray::I8(x) => x.len(),
+            MatArray::I16(x) => x.len(),
+            MatArray::I32(x) => x.len(),
+            MatArray::I64(x) => x.len(),
+            MatArray::U8(x) => x.len(),
+            MatArray::U16(x) => x.len(),
+            MatArray::U32(x) => x.len(),
+            MatArray::U64(x) => x.len(),
+            MatArray::C64(x) => x.len(),
+            MatArray::C128(x) => x.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the mx class, data type and raw real and imaginary parts
+    fn parts(&self) -> (u32, u32, Vec<u8>, Option<Vec<u8>>) {
+        fn real<T:Pod>(x:&[T]) -> Vec<u8> {
+            bytemuck::cast_slice(x).to_vec()
+        }
+        match self {
+            MatArray::F64(x) => (MX_DOUBLE, MI_DOUBLE, real(x), None),
+            MatArray::F32(x) => (MX_SINGLE, MI_SINGLE, real(x), None),
+            MatArray::I8(x) => (MX_INT8, MI_INT8, real(x), None),
+            MatArray::I16(x) => (MX_INT16, MI_INT16, real(x), None),
+            MatArray::I32(x) => (MX_INT32, MI_INT32, real(x), None),
+            MatArray::I64(x) => (MX_INT64, MI_INT64, real(x), None),
+            MatArray::U8(x) => (MX_UINT8, MI_UINT8, real(x), None),
+            MatArray::U16(x) => (MX_UINT16, MI_UINT16, real(x), None),
+            MatArray::U32(x) => (MX_UINT32, MI_UINT32, real(x), None),
+            MatArray::U64(x) => (MX_UINT64, MI_UINT64, real(x), None),
+            MatArray::C64(x) => {
+                let re:Vec<f32> = x.iter().map(|x| x.re).collect();
+                let im:Vec<f32> = x.iter().map(|x| x.im).collect();
+                (MX_SINGLE, MI_SINGLE, real(&re), Some(real(&im)))
+            }
+            MatArray::C128(x) => {
+                let re:Vec<f64> = x.iter().map(|x| x.re).collect();
+                let im:Vec<f64> = x.iter().map(|x| x.im).collect();
+                (MX_DOUBLE, MI_DOUBLE, real(&re), Some(real(&im)))
+            }
+        }
+    }
+}
+
+// data element types
+const MI_INT8: u32 = 1;
+const MI_UINT8: u32 = 2;
+const MI_INT16: u32 = 3;
+const MI_UINT16: u32 = 4;
+const MI_INT32: u32 = 5;
+const MI_UINT32: u32 = 6;
+const MI_SINGLE: u32 = 7;
+const MI_DOUBLE: u32 = 9;
+const MI_INT64: u32 = 12;
+const MI_UINT64: u32 = 13;
+const MI_MATRIX: u32 = 14;
+const MI_COMPRESSED: u32 = 15;
+
+// array classes
+const MX_DOUBLE: u32 = 6;
+const MX_SINGLE: u32 = 7;
+const MX_INT8: u32 = 8;
+const MX_UINT8: u32 = 9;
+const MX_INT16: u32 = 10;
+const MX_UINT16: u32 = 11;
+const MX_INT32: u32 = 12;
+const MX_UINT32: u32 = 13;
+const MX_INT64: u32 = 14;
+const MX_UINT64: u32 = 15;
+
+const COMPLEX_FLAG: u32 = 0x0800;
+const HEADER_SIZE: usize = 128;
+
+/// conversions from the stored data type to the array class. MATLAB stores data in the smallest
+/// type that holds the values, so a double array may be stored as uint8
+trait MatScalar: Sized {
+    fn from_i64(x:i64) -> Self;
+    fn from_u64(x:u64) -> Self;
+    fn from_f64(x:f64) -> Self;
+}
+
+macro_rules! mat_scalar {
+    ($($t:ty),*) => {
+        $(impl MatScalar for $t {
+            fn from_i64(x:i64) -> Self { x as $t }
+            fn from_u64(x:u64) -> Self { x as $t }
+            fn from_f64(x:f64) -> Self { x as $t }
+        })*
+    };
+}
+
+mat_scalar!(f32, f64, i8, i16, i32, i64, u8, u16, u32, u64);
+
+/// a byte reader for data elements in either byte order
+struct Elements<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+    path: &'a Path,
+}
+
+impl<'a> Elements<'a> {
+
+    fn format_err(&self, msg:&str) -> MatIoError {
+        MatIoError::Format{path: self.path.to_path_buf(), msg: msg.to_string()}
+    }
+
+    fn word<const N:usize>(&self, b:&[u8]) -> [u8; N] {
+        let mut w:[u8; N] = b[..N].try_into().unwrap();
+        if self.big_endian {
+            w.reverse();
+        }
+        w
+    }
+
+    fn u32_at(&self, o:usize) -> u32 {
+        u32::from_le_bytes(self.word(&self.bytes[o..]))
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos + 8 > self.bytes.len()
+    }
+
+    /// reads the next data element as its type and data, handling the small element format
+    fn next_element(&mut self) -> Result<(u32, &'a [u8]), MatIoError> {
+        if self.is_done() {
+            return Err(self.format_err("data element is truncated"));
+        }
+        let tag = self.u32_at(self.pos);
+        if tag >> 16 != 0 {
+            let (ty, n) = (tag & 0xffff, (tag >> 16) as usize);
+            if n > 4 {
+                return Err(self.format_err("invalid small data element"));
+            }
+            let data = &self.bytes[self.pos + 4..self.pos + 4 + n];
+            self.pos += 8;
+            return Ok((ty, data));
+        }
+        let n = self.u32_at(self.pos + 4) as usize;
+        let start = self.pos + 8;
+        if start + n > self.bytes.len() {
+            return Err(self.format_err("data element is truncated"));
+        }
+        // compressed elements are not padded
+        let padded = if tag == MI_COMPRESSED { n } else { n.div_ceil(8) * 8 };
+        self.pos = (start + padded).min(self.bytes.len());
+        Ok((tag, &self.bytes[start..start + n]))
+    }
+
+    /// converts numeric element data of any stored type to T
+    fn values<T:MatScalar>(&self, ty:u32, data:&[u8]) -> Result<Vec<T>, MatIoError> {
+        macro_rules! convert {
+            ($s:ty, $f:ident, $as:ty) => {
+                data.chunks_exact(size_of::<$s>())
+                    .map(|c| T::$f(<$s>::from_le_bytes(self.word(c)) as $as))
+                    .collect()
+            };
+        }
+        Ok(match ty {
+            MI_INT8 => convert!(i8, from_i64, i64),
+            MI_UINT8 => convert!(u8, from_u64, u64),
+            MI_INT16 => convert!(i16, from_i64, i64),
+            MI_UINT16 => convert!(u16, from_u64, u64),
+            MI_INT32 => convert!(i32, from_i64, i64),
+            MI_UINT32 => convert!(u32, from_u64, u64),
+            MI_INT64 => convert!(i64, from_i64, i64),
+            MI_UINT64 => convert!(u64, from_u64, u64),
+            MI_SINGLE => convert!(f32, from_f64, f64),
+            MI_DOUBLE => convert!(f64, from_f64, f64),
+            _=> return Err(self.format_err(&format!("unsupported numeric data type {}", ty))),
+        })
+    }
+}
+
+/// the contents of a miMATRIX element
+struct MatVar<'a> {
+    class: u32,
+    complex: bool,
+    dims: Vec<usize>,
+    name: String,
+    real: Option<(u32, &'a [u8])>,
+    imag: Option<(u32, &'a [u8])>,
+}
+
+impl<'a> MatVar<'a> {
+
+    fn parse(matrix:&'a [u8], big_endian:bool, path:&'a Path) -> Result<MatVar<'a>, MatIoError> {
+        let mut e = Elements { bytes: matrix, pos: 0, big_endian, path };
+        let (_, flags) = e.next_element()?;
+        if flags.len() < 4 {
+            return Err(e.format_err("invalid array flags"));
+        }
+        let flags = u32::from_le_bytes(e.word(flags));
+        let (dims_ty, dims) = e.next_element()?;
+        let dims = e.values::<i64>(dims_ty, dims)?.into_iter().map(|d| d.max(0) as usize).collect();
+        let (_, name) = e.next_element()?;
+        let name = String::from_utf8_lossy(name).to_string();
+        let real = if e.is_done() { None } else { Some(e.next_element()?) };
+        let imag = if e.is_done() { None } else { Some(e.next_element()?) };
+        Ok(MatVar { class: flags & 0xff, complex: flags & COMPLEX_FLAG != 0, dims, name, real, imag })
+    }
+
+    fn to_array(&self, e:&Elements) -> Result<(MatArray, ArrayDim), MatIoError> {
+        let unsupported = |msg:String| MatIoError::Unsupported{path: e.path.to_path_buf(), msg};
+        if self.dims.len() > N_DIMS {
+            return Err(unsupported(format!("arrays of up to {} dimensions are supported", N_DIMS)));
+        }
+        let dims = ArrayDim::try_from_shape(&self.dims).map_err(|e| unsupported(format!("{}: {}", self.name, e)))?;
+        let part = |p:Option<(u32, &'a [u8])>| p.ok_or_else(|| e.format_err(&format!("{} is missing data", self.name)));
+        let (re_ty, re) = part(self.real)?;
+
+        macro_rules! real {
+            ($v:ident) => { MatArray::$v(e.values(re_ty, re)?) };
+        }
+        macro_rules! complex {
+            ($v:ident, $c:ty, $t:ty) => {{
+                let (im_ty, im) = part(self.imag)?;
+                let re:Vec<$t> = e.values(re_ty, re)?;
+                let im:Vec<$t> = e.values(im_ty, im)?;
+                if re.len() != im.len() {
+                    return Err(e.format_err(&format!("{} has mismatched real and imaginary parts", self.name)));
+                }
+                MatArray::$v(re.into_iter().zip(im).map(|(re, im)| <$c>::new(re, im)).collect())
+            }};
+        }
+
+        let array = match (self.class, self.complex) {
+            (MX_DOUBLE, false) => real!(F64),
+            (MX_SINGLE, false) => real!(F32),
+            (MX_INT8, false) => real!(I8),
+            (MX_INT16, false) => real!(I16),
+            (MX_INT32, false) => real!(I32),
+            (MX_INT64, false) => real!(I64),
+            (MX_UINT8, false) => real!(U8),
+            (MX_UINT16, false) => real!(U16),
+            (MX_UINT32, false) => real!(U32),
+            (MX_UINT64, false) => real!(U64),
+            (MX_DOUBLE, true) => complex!(C128, Complex64, f64),
+            (MX_SINGLE, true) => complex!(C64, Complex32, f32),
+            (class, complex) => return Err(unsupported(format!(
+                "{} has class {}{}, only numeric arrays are supported",
+                self.name, class, if complex { " (complex)" } else { "" }
+            ))),
+        };
+        if array.len() != dims.numel() {
+            return Err(e.format_err(&format!("{} has {} elements but its dimensions require {}", self.name, array.len(), dims.numel())));
+        }
+        Ok((array, dims))
+    }
+}
+
+/// reads a numeric variable from a Level 5 MAT file. Compressed variables (MAT v7) are
+/// decompressed on read. v7.3 files are HDF5 and are rejected
+pub fn read_mat(file:impl AsRef<Path>, var:&str) -> Result<(MatArray, ArrayDim), MatIoError> {
+    let path = file.as_ref();
+    let mut bytes = vec![];
+    File::open(path).map_err(io_err(path))?.read_to_end(&mut bytes).map_err(io_err(path))?;
+    if bytes.len() < HEADER_SIZE {
+        return Err(MatIoError::Format{path: path.to_path_buf(), msg: String::from("file is smaller than the header")});
+    }
+    let big_endian = match &bytes[126..128] {
+        b"IM" => false,
+        b"MI" => true,
+        _=> return Err(MatIoError::Format{path: path.to_path_buf(), msg: String::from("missing endian indicator")}),
+    };
+    let version = if big_endian { u16::from_be_bytes([bytes[124], bytes[125]]) } else { u16::from_le_bytes([bytes[124], bytes[125]]) };
+    if version == 0x0200 {
+        return Err(MatIoError::Unsupported{path: path.to_path_buf(), msg: String::from("MAT v7.3 files are HDF5, use io-hdf5 to read them")});
+    }
+
+    let mut e = Elements { bytes: &bytes[HEADER_SIZE..], pos: 0, big_endian, path };
+    while !e.is_done() {
+        let (ty, data) = e.next_element()?;
+        let inflated;
+        let matrix = match ty {
+            MI_MATRIX => data,
+            MI_COMPRESSED => {
+                let mut buf = vec![];
+                ZlibDecoder::new(data).read_to_end(&mut buf).map_err(io_err(path))?;
+                inflated = buf;
+                let mut inner = Elements { bytes: &inflated, pos: 0, big_endian, path };
+                match inner.next_element()? {
+                    (MI_MATRIX, matrix) => matrix,
+                    _=> continue,
+                }
+            }
+            _=> continue,
+        };
+        let v = MatVar::parse(matrix, big_endian, path)?;
+        if v.name == var {
+            let inner = Elements { bytes: matrix, pos: 0, big_endian, path };
+            return v.to_array(&inner);
+        }
+    }
+    Err(MatIoError::VariableNotFound{path: path.to_path_buf(), var: var.to_string()})
+}
+
+/// true if name is a valid MATLAB variable name
+fn valid_name(name:&str) -> bool {
+    let mut chars = name.chars();
+    name.len() <= 63
+        && chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// appends a data element with its data padded to 8 bytes
+fn push_element(buf:&mut Vec<u8>, ty:u32, data:&[u8]) {
+    buf.extend_from_slice(&ty.to_le_bytes());
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+    buf.resize(buf.len().div_ceil(8) * 8, 0);
+}
+
+/// writes numeric variables to an uncompressed Level 5 MAT file readable by MATLAB and
+/// scipy.io.loadmat. Arrays keep the column-major layout of this crate. 1-D arrays are written as
+/// column vectors
+pub fn write_mat(file:impl AsRef<Path>, vars:&[(&str, &MatArray, ArrayDim)]) -> Result<(), MatIoError> {
+    let path = file.as_ref();
+    let mut names = BTreeSet::new();
+    for (name, data, dims) in vars {
+        if !valid_name(name) {
+            return Err(MatIoError::InvalidName(name.to_string()));
+        }
+        if !names.insert(*name) {
+            return Err(MatIoError::DuplicateName(name.to_string()));
+        }
+        if data.len() != dims.numel() {
+            return Err(MatIoError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+        }
+    }
+
+    let mut w = BufWriter::new(File::create(path).map_err(io_err(path))?);
+    let mut header = format!("MATLAB 5.0 MAT-file, Platform: {}, Created by: array-lib", std::env::consts::OS).into_bytes();
+    header.resize(116, b' ');
+    header.extend_from_slice(&[0u8; 8]);
+    header.extend_from_slice(&0x0100u16.to_le_bytes());
+    header.extend_from_slice(b"IM");
+    w.write_all(&header).map_err(io_err(path))?;
+
+    for (name, data, dims) in vars {
+        let (class, ty, re, im) = data.parts();
+        let mut shape = dims.shape_ns().to_vec();
+        if shape.len() < 2 {
+            shape.push(1);
+        }
+        let flags = class | if im.is_some() { COMPLEX_FLAG } else { 0 };
+
+        let mut matrix = vec![];
+        push_element(&mut matrix, MI_UINT32, bytemuck::cast_slice(&[flags, 0u32]));
+        let shape:Vec<i32> = shape.iter().map(|&d| d as i32).collect();
+        push_element(&mut matrix, MI_INT32, bytemuck::cast_slice(&shape));
+        push_element(&mut matrix, MI_INT8, name.as_bytes());
+        push_element(&mut matrix, ty, &re);
+        if let Some(im) = &im {
+            push_element(&mut matrix, ty, im);
+        }
+        if matrix.len() > u32::MAX as usize {
+            return Err(MatIoError::Unsupported{path: path.to_path_buf(), msg: format!("{} is larger than 4 GB", name)});
+        }
+
+        w.write_all(&MI_MATRIX.to_le_bytes()).map_err(io_err(path))?;
+        w.write_all(&(matrix.len() as u32).to_le_bytes()).map_err(io_err(path))?;
+        w.write_all(&matrix).map_err(io_err(path))?;
+    }
+    w.flush().map_err(io_err(path))
+}
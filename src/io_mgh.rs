@@ -0,0 +1,409 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use crate::ArrayDim;
+
+#[cfg(test)]
+mod tests {
+    use crate::ArrayDim;
+    use crate::io_mgh::{read_mgh, write_mgh, MghIoError, MghType, MghWriteOptions};
+
+    /// the bytes nibabel writes for a 3 x 2 x 2 short volume with 1.5 x 2 x 3 mm voxels, an LIA
+    /// orientation, c_ras = (10, -5, 2) and TR = 2000 ms
+    fn nibabel_fixture() -> Vec<u8> {
+        let mut b = vec![];
+        for x in [1i32, 3, 2, 2, 1, 4, 0] {
+            b.extend(x.to_be_bytes());
+        }
+        b.extend(1i16.to_be_bytes());
+        for x in [1.5f32, 2., 3., -1., 0., 0., 0., 0., -1., 0., 1., 0., 10., -5., 2.] {
+            b.extend(x.to_be_bytes());
+        }
+        b.resize(284, 0);
+        for x in 0..12i16 {
+            b.extend((x - 6).to_be_bytes());
+        }
+        for x in [2000f32, 0., 0., 0., 0.] {
+            b.extend(x.to_be_bytes());
+        }
+        b
+    }
+
+    #[test]
+    fn test_fixture() {
+        std::fs::write("test_mgh_fixture.mgh",nibabel_fixture()).unwrap();
+        let (x,dims,h) = read_mgh("test_mgh_fixture.mgh").unwrap();
+        std::fs::remove_file("test_mgh_fixture.mgh").unwrap();
+        assert_eq!(dims.shape_ns(),&[3,2,2]);
+        assert_eq!(h.dtype,MghType::I16);
+        assert_eq!(h.tr,2000.);
+        assert_eq!(x,(0..12).map(|x| x as f32 - 6.).collect::<Vec<f32>>());
+
+        // the voxel at the center (1.5, 1, 1) maps to c_ras
+        let a = h.nifti_affine(&dims);
+        let center = [1.5,1.,1.];
+        for (r,c_ras) in a.iter().zip([10.,-5.,2.]) {
+            let p:f64 = r[..3].iter().zip(center).map(|(a,c)| a * c).sum::<f64>() + r[3];
+            assert!((p - c_ras).abs() < 1e-6);
+        }
+        assert_eq!(a[0][0],-1.5);
+        assert_eq!(a[2][1],-2.);
+        assert_eq!(a[1][2],3.);
+
+        // writing the same volume and header reproduces the file
+        let opts = MghWriteOptions::new().header(&h);
+        write_mgh("test_mgh_fixture_w.mgh",&x,dims,&opts).unwrap();
+        let written = std::fs::read("test_mgh_fixture_w.mgh").unwrap();
+        std::fs::remove_file("test_mgh_fixture_w.mgh").unwrap();
+        assert_eq!(written,nibabel_fixture());
+    }
+
+    #[test]
+    fn test_round_trip_mgz() {
+        let dims = ArrayDim::from_shape(&[4,3,2,2]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32 * 0.5).collect();
+        let affine = [
+            [0.,0.,2.,-10.],
+            [1.,0.,0.,4.],
+            [0.,3.,0.,7.],
+            [0.,0.,0.,1.],
+        ];
+        let opts = MghWriteOptions::new().affine(affine,&dims);
+        write_mgh("test_mgh_round_trip.mgz",&x,dims,&opts).unwrap();
+        let (y,y_dims,h) = read_mgh("test_mgh_round_trip.mgz").unwrap();
+        std::fs::remove_file("test_mgh_round_trip.mgz").unwrap();
+        assert_eq!(y,x);
+        assert_eq!(y_dims.shape(),dims.shape());
+        assert_eq!(h.dtype,MghType::F32);
+        let y_affine = h.nifti_affine(&y_dims);
+        for r in 0..4 {
+            for c in 0..4 {
+                assert!((y_affine[r][c] - affine[r][c]).abs() < 1e-5);
+            }
+        }
+
+        let dims = ArrayDim::from_shape(&[2,2,2,2,2]);
+        assert!(matches!(write_mgh("test_mgh_5d.mgh",&dims.alloc(0.),dims,&MghWriteOptions::new()),Err(MghIoError::InvalidDims(..))));
+    }
+
+}
+
+#[derive(Debug)]
+pub enum MghIoError {
+    IO(PathBuf, std::io::Error),
+    Header{path: PathBuf, msg: String},
+    Unsupported{path: PathBuf, msg: String},
+    SizeMismatch{path: PathBuf, expected: usize, actual: usize},
+    InconsistentArraySize{expected: usize, actual: usize},
+    InvalidDims(String),
+}
+
+impl Display for MghIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MghIoError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            MghIoError::Header {path, msg} => write!(f, "invalid mgh header in {}: {}", path.display(), msg),
+            MghIoError::Unsupported {path, msg} => write!(f, "unsupported mgh file {}: {}", path.display(), msg),
+            MghIoError::SizeMismatch {path, expected, actual} => write!(
+                f, "{} has {} bytes of data but the header requires {}", path.display(), actual, expected
+            ),
+            MghIoError::InconsistentArraySize {expected, actual} => write!(
+                f, "array dimensions require {} elements but {} were supplied", expected, actual
+            ),
+            MghIoError::InvalidDims(msg) => write!(f, "invalid dimensions: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for MghIoError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> MghIoError {
+    let path = path.to_path_buf();
+    move |e| MghIoError::IO(path, e)
+}
+
+/// the header is padded to a fixed size before the data
+const MGH_HEADER_BYTES: usize = 284;
+
+/// the sample type of an mgh volume
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MghType {
+    U8,
+    I16,
+    I32,
+    #[default]
+    F32,
+}
+
+impl MghType {
+    fn from_code(code:i32) -> Option<MghType> {
+        match code {
+            0 => Some(MghType::U8),
+            1 => Some(MghType::I32),
+            3 => Some(MghType::F32),
+            4 => Some(MghType::I16),
+            _=> None,
+        }
+    }
+
+    fn code(&self) -> i32 {
+        match self {
+            MghType::U8 => 0,
+            MghType::I32 => 1,
+            MghType::F32 => 3,
+            MghType::I16 => 4,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            MghType::U8 => 1,
+            MghType::I16 => 2,
+            MghType::I32 | MghType::F32 => 4,
+        }
+    }
+}
+
+/// the header of an mgh volume. The voxel-to-RAS transform is stored as voxel sizes, direction
+/// cosines and the RAS coordinate of the center voxel
+#[derive(Debug, Clone, PartialEq)]
+pub struct MghHeader {
+    pub dtype: MghType,
+    pub dof: i32,
+    /// false when the file has no transform, in which case FreeSurfer assumes a coronal (LIA)
+    /// orientation
+    pub good_ras: bool,
+    pub spacing: [f32; 3],
+    /// the direction cosines of the x, y and z voxel axes in RAS
+    pub x_ras: [f32; 3],
+    pub y_ras: [f32; 3],
+    pub z_ras: [f32; 3],
+    pub c_ras: [f32; 3],
+    /// scan parameters from the optional footer, in ms and radians
+    pub tr: f32,
+    pub flip_angle: f32,
+    pub te: f32,
+    pub ti: f32,
+    pub fov: f32,
+}
+
+impl Default for MghHeader {
+    fn default() -> Self {
+        MghHeader {
+            dtype: MghType::F32,
+            dof: 0,
+            good_ras: false,
+            spacing: [1.; 3],
+            x_ras: [-1., 0., 0.],
+            y_ras: [0., 0., -1.],
+            z_ras: [0., 1., 0.],
+            c_ras: [0.; 3],
+            tr: 0.,
+            flip_angle: 0.,
+            te: 0.,
+            ti: 0.,
+            fov: 0.,
+        }
+    }
+}
+
+impl MghHeader {
+    /// the voxel-to-world affine in RAS coordinates, for use with set_nifti_affine. The volume
+    /// dimensions are needed to locate the center voxel
+    pub fn nifti_affine(&self, dims:&ArrayDim) -> [[f64; 4]; 4] {
+        let dirs = self.directions();
+        let mut affine = [[0.; 4]; 4];
+        for (r, row) in affine.iter_mut().take(3).enumerate() {
+            let mut center = 0.;
+            for (c, (dir, spacing)) in dirs.iter().zip(self.spacing).enumerate() {
+                row[c] = dir[r] as f64 * spacing as f64;
+                center += row[c] * dims.shape()[c] as f64 / 2.;
+            }
+            row[3] = self.c_ras[r] as f64 - center;
+        }
+        affine[3][3] = 1.;
+        affine
+    }
+
+    /// sets the transform from a voxel-to-world (RAS) affine, such as one from nifti_affine
+    pub fn set_nifti_affine(&mut self, affine:[[f64; 4]; 4], dims:&ArrayDim) {
+        let mut dirs = [[0f32; 3]; 3];
+        for (c, dir) in dirs.iter_mut().enumerate() {
+            let n = (0..3).map(|r| affine[r][c] * affine[r][c]).sum::<f64>().sqrt();
+            self.spacing[c] = n as f32;
+            for (r, d) in dir.iter_mut().enumerate() {
+                *d = if n > 0. { (affine[r][c] / n) as f32 } else { 0. };
+            }
+        }
+        [self.x_ras, self.y_ras, self.z_ras] = dirs;
+        for (r, c_ras) in self.c_ras.iter_mut().enumerate() {
+            let center:f64 = (0..3).map(|c| affine[r][c] * dims.shape()[c] as f64 / 2.).sum();
+            *c_ras = (center + affine[r][3]) as f32;
+        }
+        self.good_ras = true;
+    }
+
+    fn directions(&self) -> [[f32; 3]; 3] {
+        [self.x_ras, self.y_ras, self.z_ras]
+    }
+}
+
+/// options for writing an mgh volume
+#[derive(Debug, Clone, Default)]
+pub struct MghWriteOptions {
+    header: MghHeader,
+}
+
+impl MghWriteOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// copies the sample type, transform and scan parameters of a header, such as one from read_mgh
+    pub fn header(mut self, header:&MghHeader) -> Self {
+        self.header = header.clone();
+        self
+    }
+
+    /// sets the transform from a voxel-to-world (RAS) affine for a volume of the given dimensions
+    pub fn affine(mut self, affine:[[f64; 4]; 4], dims:&ArrayDim) -> Self {
+        self.header.set_nifti_affine(affine, dims);
+        self
+    }
+
+    /// the sample type. Values are rounded and clamped for integer types. Defaults to F32
+    pub fn dtype(mut self, dtype:MghType) -> Self {
+        self.header.dtype = dtype;
+        self
+    }
+}
+
+/// mgz files are gzip compressed
+fn is_mgz(path:&Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_lowercase();
+    name.ends_with(".mgz") || name.ends_with(".mgh.gz")
+}
+
+fn be_i32(b:&[u8], at:usize) -> i32 {
+    i32::from_be_bytes(b[at..at + 4].try_into().unwrap())
+}
+
+fn be_f32(b:&[u8], at:usize) -> f32 {
+    f32::from_be_bytes(b[at..at + 4].try_into().unwrap())
+}
+
+/// reads a FreeSurfer mgh or mgz volume into an array of shape [width, height, depth, frames]
+pub fn read_mgh(file:impl AsRef<Path>) -> Result<(Vec<f32>, ArrayDim, MghHeader), MghIoError> {
+    let path = file.as_ref();
+    let mut bytes = vec![];
+    let mut r = BufReader::new(File::open(path).map_err(io_err(path))?);
+    r.read_to_end(&mut bytes).map_err(io_err(path))?;
+    // gzip is detected by its magic number as well as the extension
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut decoded = vec![];
+        GzDecoder::new(bytes.as_slice()).read_to_end(&mut decoded).map_err(io_err(path))?;
+        bytes = decoded;
+    }
+    let header_err = |msg:String| MghIoError::Header{path: path.to_path_buf(), msg};
+    if bytes.len() < MGH_HEADER_BYTES {
+        return Err(header_err(format!("file is only {} bytes", bytes.len())));
+    }
+    let version = be_i32(&bytes, 0);
+    if version != 1 {
+        return Err(MghIoError::Unsupported{path: path.to_path_buf(), msg: format!("version {}", version)});
+    }
+    let mut shape = [0usize; 4];
+    for (i, s) in shape.iter_mut().enumerate() {
+        let d = be_i32(&bytes, 4 + 4 * i);
+        if d < 1 {
+            return Err(header_err(format!("invalid dimension {}", d)));
+        }
+        *s = d as usize;
+    }
+    let code = be_i32(&bytes, 20);
+    let dtype = MghType::from_code(code)
+        .ok_or_else(|| MghIoError::Unsupported{path: path.to_path_buf(), msg: format!("data type {}", code)})?;
+
+    let mut h = MghHeader{dtype, dof: be_i32(&bytes, 24), ..MghHeader::default()};
+    h.good_ras = i16::from_be_bytes([bytes[28], bytes[29]]) > 0;
+    if h.good_ras {
+        let f = |i:usize| be_f32(&bytes, 30 + 4 * i);
+        h.spacing = [f(0), f(1), f(2)];
+        h.x_ras = [f(3), f(4), f(5)];
+        h.y_ras = [f(6), f(7), f(8)];
+        h.z_ras = [f(9), f(10), f(11)];
+        h.c_ras = [f(12), f(13), f(14)];
+    }
+
+    let dims = ArrayDim::from_shape(&shape);
+    let n_bytes = dims.numel() * dtype.size();
+    let data_bytes = &bytes[MGH_HEADER_BYTES..];
+    if data_bytes.len() < n_bytes {
+        return Err(MghIoError::SizeMismatch{path: path.to_path_buf(), expected: n_bytes, actual: data_bytes.len()});
+    }
+    let data:Vec<f32> = match dtype {
+        MghType::U8 => data_bytes[..n_bytes].iter().map(|&b| b as f32).collect(),
+        MghType::I16 => data_bytes[..n_bytes].chunks_exact(2).map(|b| i16::from_be_bytes([b[0], b[1]]) as f32).collect(),
+        MghType::I32 => data_bytes[..n_bytes].chunks_exact(4).map(|b| be_i32(b, 0) as f32).collect(),
+        MghType::F32 => data_bytes[..n_bytes].chunks_exact(4).map(|b| be_f32(b, 0)).collect(),
+    };
+
+    // the scan parameters follow the data when present
+    let footer = &data_bytes[n_bytes..];
+    if footer.len() >= 20 {
+        let f = |i:usize| be_f32(footer, 4 * i);
+        [h.tr, h.flip_angle, h.te, h.ti, h.fov] = [f(0), f(1), f(2), f(3), f(4)];
+    }
+    Ok((data, dims, h))
+}
+
+/// writes an array of up to 4 dimensions to a FreeSurfer volume, which is gzip compressed if the
+/// file name ends in .mgz or .mgh.gz
+pub fn write_mgh(file:impl AsRef<Path>, data:&[f32], dims:ArrayDim, opts:&MghWriteOptions) -> Result<(), MghIoError> {
+    let path = file.as_ref();
+    if data.len() != dims.numel() {
+        return Err(MghIoError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+    }
+    if dims.shape_ns().len() > 4 {
+        return Err(MghIoError::InvalidDims(format!("mgh volumes have at most 4 dimensions, got {:?}", dims.shape_ns())));
+    }
+    let h = &opts.header;
+
+    let mut bytes = Vec::with_capacity(MGH_HEADER_BYTES + data.len() * h.dtype.size() + 20);
+    bytes.extend(1i32.to_be_bytes());
+    for &d in &dims.shape()[..4] {
+        bytes.extend((d as i32).to_be_bytes());
+    }
+    bytes.extend(h.dtype.code().to_be_bytes());
+    bytes.extend(h.dof.to_be_bytes());
+    bytes.extend((h.good_ras as i16).to_be_bytes());
+    let geometry = [h.spacing, h.x_ras, h.y_ras, h.z_ras, h.c_ras];
+    for x in geometry.iter().flatten() {
+        bytes.extend(x.to_be_bytes());
+    }
+    bytes.resize(MGH_HEADER_BYTES, 0);
+
+    match h.dtype {
+        MghType::U8 => bytes.extend(data.iter().map(|x| x.round().clamp(0., u8::MAX as f32) as u8)),
+        MghType::I16 => bytes.extend(data.iter().flat_map(|x| (x.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16).to_be_bytes())),
+        MghType::I32 => bytes.extend(data.iter().flat_map(|x| (x.round() as i32).to_be_bytes())),
+        MghType::F32 => bytes.extend(data.iter().flat_map(|x| x.to_be_bytes())),
+    }
+    for x in [h.tr, h.flip_angle, h.te, h.ti, h.fov] {
+        bytes.extend(x.to_be_bytes());
+    }
+
+    let mut w = BufWriter::new(File::create(path).map_err(io_err(path))?);
+    if is_mgz(path) {
+        let mut enc = GzEncoder::new(w, Compression::default());
+        enc.write_all(&bytes).map_err(io_err(path))?;
+        enc.finish().map_err(io_err(path))?.flush().map_err(io_err(path))
+    } else {
+        w.write_all(&bytes).map_err(io_err(path))?;
+        w.flush().map_err(io_err(path))
+    }
+}
@@ -1,14 +1,1067 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
 use mrd_rs::MRD;
 use num_complex::Complex32;
-use crate::ArrayDim;
+use crate::{AllocError, ArrayDim, ReadEvent, ReadReport};
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex32;
+    use crate::{ArrayDim, ReadEvent};
+    use crate::io_mrd::{try_read_mrd_with_options, MrdReadOptions, read_mrd_classified, write_mrd, stream_mrd, MrdStreamError, read_mrd_params, PprValue, read_mrd_subset, MrdSelection, try_read_mrd, MrdIoError, MRD_HEADER_SIZE, inspect_mrd};
+
+    /// writes a minimal MRD with complex f32 samples for testing. The parameter text is written
+    /// after the data if supplied
+    fn write_test_mrd(path:&str, dims:[usize;6], data:&[Complex32], ppr:Option<&str>) {
+        let mut bytes = vec![0u8; MRD_HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&(dims[0] as i32).to_le_bytes());
+        bytes[4..8].copy_from_slice(&(dims[1] as i32).to_le_bytes());
+        bytes[8..12].copy_from_slice(&(dims[2] as i32).to_le_bytes());
+        bytes[12..16].copy_from_slice(&(dims[3] as i32).to_le_bytes());
+        // complex f32
+        bytes[18..20].copy_from_slice(&0x15i16.to_le_bytes());
+        bytes[152..156].copy_from_slice(&(dims[4] as i32).to_le_bytes());
+        bytes[156..160].copy_from_slice(&(dims[5] as i32).to_le_bytes());
+        bytes.extend_from_slice(bytemuck::cast_slice(data));
+        if let Some(ppr) = ppr {
+            bytes.extend_from_slice(&[0u8;120]);
+            bytes.extend_from_slice(ppr.as_bytes());
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_try_read_missing() {
+        let r = try_read_mrd("does_not_exist.mrd");
+        assert!(matches!(r,Err(MrdIoError::IO{..})));
+    }
+
+    #[test]
+    fn test_oversized_dims() {
+        let mut bytes = vec![0u8; MRD_HEADER_SIZE];
+        for o in [0, 4, 8, 12] {
+            bytes[o..o + 4].copy_from_slice(&i32::MAX.to_le_bytes());
+        }
+        bytes[18..20].copy_from_slice(&0x15i16.to_le_bytes());
+        std::fs::write("test_mrd_oversized.mrd", bytes).unwrap();
+        let layout = inspect_mrd("test_mrd_oversized.mrd");
+        let read = try_read_mrd("test_mrd_oversized.mrd");
+        let lenient = try_read_mrd_with_options("test_mrd_oversized.mrd",&MrdReadOptions::new().lenient(true));
+        let params = read_mrd_params("test_mrd_oversized.mrd");
+        std::fs::remove_file("test_mrd_oversized.mrd").unwrap();
+        assert!(matches!(layout,Err(MrdIoError::Header{..})));
+        assert!(matches!(read,Err(MrdIoError::Header{..})));
+        assert!(matches!(lenient,Err(MrdIoError::Header{..})));
+        assert!(matches!(params,Err(MrdIoError::Header{..})));
+    }
+
+    #[test]
+    fn test_try_read_truncated() {
+        // shorter than the header
+        std::fs::write("test_mrd_short_header.mrd",[0u8;100]).unwrap();
+        let r = try_read_mrd("test_mrd_short_header.mrd");
+        std::fs::remove_file("test_mrd_short_header.mrd").unwrap();
+        assert!(matches!(r,Err(MrdIoError::Header{..})));
+
+        // missing the last 10 samples
+        let data = vec![Complex32::ONE; 8 * 4 * 2];
+        write_test_mrd("test_mrd_truncated.mrd",[8,4,1,2,1,1],&data[..data.len() - 10],None);
+        let r = try_read_mrd("test_mrd_truncated.mrd");
+        std::fs::remove_file("test_mrd_truncated.mrd").unwrap();
+        match r {
+            Err(MrdIoError::SizeMismatch {header_elems,decoded_elems,file_size,..}) => {
+                assert_eq!(header_elems,64);
+                assert_eq!(decoded_elems,54);
+                assert_eq!(file_size,512 + 54 * 8);
+            }
+            _=> panic!("expected a size mismatch error"),
+        }
+    }
+
+    #[test]
+    fn test_lenient_read() {
+        let shape = [8,4,1,2,1,1];
+        let data:Vec<Complex32> = (0..64).map(|i| Complex32::new(i as f32 + 1.,0.)).collect();
+        write_test_mrd("test_mrd_lenient.mrd",shape,&data[..54],None);
+        let strict = try_read_mrd("test_mrd_lenient.mrd");
+        let lenient = try_read_mrd_with_options("test_mrd_lenient.mrd",&MrdReadOptions::new().lenient(true));
+        std::fs::remove_file("test_mrd_lenient.mrd").unwrap();
+
+        assert!(matches!(strict,Err(MrdIoError::SizeMismatch{..})));
+        let (salvaged,dims,_,report) = lenient.unwrap();
+        assert_eq!(report.events,vec![ReadEvent::ZeroFilled{path:"test_mrd_lenient.mrd".into(),found:54,expected:64}]);
+        assert_eq!(dims.numel(),64);
+        assert_eq!(&salvaged[..54],&data[..54]);
+        assert!(salvaged[54..].iter().all(|x| *x == Complex32::ZERO));
+    }
+
+    #[test]
+    fn test_read_subset() {
+        let shape = [8,6,1,3,2,2];
+        let dims = ArrayDim::from_shape(&shape);
+        let data:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,-(i as f32))).collect();
+        write_test_mrd("test_mrd_subset.mrd",shape,&data,Some(":PPR test\n"));
+
+        let sel = MrdSelection::new().views(1..4).slices(2..3).echoes(1..2);
+        let (subset,subset_dims,_) = read_mrd_subset("test_mrd_subset.mrd",&sel).unwrap();
+        let (expected,expected_dims) = dims.copy_region(&data,&[0,1,0,2,1,0],&[8,3,1,1,1,2]);
+
+        let bad = read_mrd_subset("test_mrd_subset.mrd",&MrdSelection::new().experiments(1..3));
+        std::fs::remove_file("test_mrd_subset.mrd").unwrap();
+
+        assert_eq!(subset,expected);
+        assert_eq!(subset_dims.shape(),expected_dims.shape());
+        assert!(matches!(bad,Err(MrdIoError::InvalidSelection(..))));
+    }
+
+    #[test]
+    fn test_read_params() {
+        let ppr = ":PATH C:\\data\\scan.ppr\r\n:VAR no_samples, 8\r\n:VAR te, 2.5\r\n:VAR tr, 100\r\n:FOV 40\r\n:EDITTEXT LAST_SCAN_DATE \"2024-01-01\"\r\n";
+        let data = vec![Complex32::ONE; 8 * 4];
+        write_test_mrd("test_mrd_params.mrd",[8,4,1,1,1,1],&data,Some(ppr));
+        let p = read_mrd_params("test_mrd_params.mrd").unwrap();
+        std::fs::remove_file("test_mrd_params.mrd").unwrap();
+
+        assert_eq!(p.samples,8);
+        assert_eq!(p.views,4);
+        assert_eq!(p.slices,1);
+        assert_eq!(p.fov,Some(40.));
+        assert_eq!(p.echo_time,Some(2.5));
+        assert_eq!(p.repetition_time,Some(100.));
+        assert_eq!(p.get("no_samples"),Some(PprValue::Int(8)));
+        assert_eq!(p.get("te"),Some(PprValue::Float(2.5)));
+        assert_eq!(p.get("LAST_SCAN_DATE"),Some(PprValue::Text(String::from("2024-01-01"))));
+        assert_eq!(p.get("PATH"),Some(PprValue::Text(String::from("C:\\data\\scan.ppr"))));
+        assert_eq!(p.get("missing"),None);
+    }
+
+    #[test]
+    fn test_inspect() {
+        let data = vec![Complex32::ONE; 8 * 4 * 2];
+        write_test_mrd("test_mrd_inspect.mrd",[8,4,1,2,1,1],&data,Some(":VAR no_samples, 8\r\n:VAR no_views, 5\r\n:VAR no_slices, 2\r\n"));
+        let layout = inspect_mrd("test_mrd_inspect.mrd").unwrap();
+        let p = read_mrd_params("test_mrd_inspect.mrd").unwrap();
+        write_test_mrd("test_mrd_inspect.mrd",[8,4,1,2,1,1],&data[..60],None);
+        let truncated = inspect_mrd("test_mrd_inspect.mrd").unwrap();
+        std::fs::remove_file("test_mrd_inspect.mrd").unwrap();
+
+        assert_eq!(layout.expected_samples(),64);
+        // the trailing parameter text isn't counted as samples
+        assert_eq!(layout.trailer_size,120 + 57);
+        assert_eq!(layout.available_samples(),64);
+        assert_eq!(layout.expected_data_end(),512 + 64 * 8);
+        assert!(layout.validate().is_ok());
+        assert_eq!(p.dim_mismatches(),vec![("no_views",5,4)]);
+        assert_eq!(truncated.available_samples(),60);
+        assert!(matches!(truncated.validate(),Err(MrdIoError::SizeMismatch{header_elems: 64, decoded_elems: 60, ..})));
+    }
+
+    #[test]
+    fn test_truncated_with_params() {
+        // the missing samples are fewer bytes than the parameter text, so the file is longer than
+        // the header and data alone
+        let ppr = ":VAR no_samples, 8\r\n:VAR no_views, 8\r\n";
+        let data = vec![Complex32::ONE; 64];
+        write_test_mrd("test_mrd_truncated_params.mrd",[8,8,1,1,1,1],&data[..62],Some(ppr));
+        let layout = inspect_mrd("test_mrd_truncated_params.mrd").unwrap();
+        let read = try_read_mrd("test_mrd_truncated_params.mrd");
+        std::fs::remove_file("test_mrd_truncated_params.mrd").unwrap();
+        assert!(layout.file_size > layout.expected_data_end());
+        assert_eq!(layout.available_samples(),62);
+        assert!(matches!(layout.validate(),Err(MrdIoError::SizeMismatch{header_elems: 64, decoded_elems: 62, ..})));
+        assert!(matches!(read,Err(MrdIoError::SizeMismatch{..})));
+    }
+
+    #[test]
+    fn test_stream() {
+        let shape = [16,10,1,3,1,1];
+        let dims = ArrayDim::from_shape(&shape);
+        let data:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,(i % 7) as f32)).collect();
+        write_test_mrd("test_mrd_stream.mrd",shape,&data,Some(":PPR test\n"));
+
+        let mut streamed = vec![];
+        let mut sum = Complex32::ZERO;
+        let (stream_dims,_) = stream_mrd("test_mrd_stream.mrd",100,|chunk,offset| {
+            assert_eq!(offset as usize,streamed.len());
+            assert!(chunk.len() <= 100);
+            sum += chunk.iter().sum::<Complex32>();
+            streamed.extend_from_slice(chunk);
+            Ok::<(),String>(())
+        }).unwrap();
+        let (full,..) = read_mrd_subset("test_mrd_stream.mrd",&MrdSelection::new()).unwrap();
+
+        // callback errors stop the stream and are passed back
+        let mut calls = 0;
+        let r = stream_mrd("test_mrd_stream.mrd",100,|_,_| {
+            calls += 1;
+            if calls == 2 { Err("stop") } else { Ok(()) }
+        });
+        std::fs::remove_file("test_mrd_stream.mrd").unwrap();
+
+        assert_eq!(stream_dims.shape(),dims.shape());
+        assert_eq!(streamed,full);
+        assert_eq!(streamed,data);
+        assert_eq!(sum,full.iter().sum::<Complex32>());
+        assert!(matches!(r,Err(MrdStreamError::Callback("stop"))));
+        assert_eq!(calls,2);
+    }
+
+    #[test]
+    fn test_write_mrd() {
+        let shape = [8,6,1,3,1,1];
+        let dims = ArrayDim::from_shape(&shape);
+        let data:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,-(i as f32))).collect();
+        let ppr = ":VAR no_samples, 8\r\n:VAR no_views, 6\r\n:FOV 40\r\n";
+        write_test_mrd("test_mrd_write_ref.mrd",shape,&data,Some(ppr));
+
+        // unmodified round trip
+        write_mrd("test_mrd_write.mrd",&data,dims,"test_mrd_write_ref.mrd").unwrap();
+        let (y,y_dims,_) = read_mrd_subset("test_mrd_write.mrd",&MrdSelection::new()).unwrap();
+        assert_eq!(y,data);
+        assert_eq!(y_dims.shape(),dims.shape());
+        assert_eq!(std::fs::read("test_mrd_write.mrd").unwrap(),std::fs::read("test_mrd_write_ref.mrd").unwrap());
+
+        // dropping views updates the header and parameters
+        let (cropped,cropped_dims) = dims.copy_region(&data,&[0,1],&[8,4,1,3]);
+        write_mrd("test_mrd_write.mrd",&cropped,cropped_dims,"test_mrd_write_ref.mrd").unwrap();
+        let (y,y_dims,_) = read_mrd_subset("test_mrd_write.mrd",&MrdSelection::new()).unwrap();
+        let p = read_mrd_params("test_mrd_write.mrd").unwrap();
+        assert_eq!(y,cropped);
+        assert_eq!(y_dims.shape(),cropped_dims.shape());
+        assert_eq!(p.views,4);
+        assert_eq!(p.get("no_views"),Some(PprValue::Int(4)));
+        assert_eq!(p.fov,Some(40.));
+
+        let bad_len = write_mrd("test_mrd_write.mrd",&data[1..],dims,"test_mrd_write_ref.mrd");
+        let bad_dims = write_mrd("test_mrd_write.mrd",&data,ArrayDim::from_shape(&[8,1,1,1,1,1,18]),"test_mrd_write_ref.mrd");
+        std::fs::remove_file("test_mrd_write.mrd").unwrap();
+        std::fs::remove_file("test_mrd_write_ref.mrd").unwrap();
+        assert!(matches!(bad_len,Err(MrdIoError::InvalidWrite(..))));
+        assert!(matches!(bad_dims,Err(MrdIoError::InvalidWrite(..))));
+    }
+
+    #[test]
+    fn test_read_classified() {
+        let shape = [8,10,1,2,1,1];
+        let dims = ArrayDim::from_shape(&shape);
+        let data:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,0.)).collect();
+
+        write_test_mrd("test_mrd_classified.mrd",shape,&data,Some(":VAR no_noise_views, 2\r\n"));
+        let d = read_mrd_classified("test_mrd_classified.mrd").unwrap();
+        let (noise,noise_dims) = d.noise.unwrap();
+        assert_eq!(noise_dims.shape_ns(),&[8,2,1,2]);
+        assert_eq!(d.imaging.1.shape_ns(),&[8,8,1,2]);
+        assert!(d.phase_ref.is_none());
+        assert_eq!(noise,dims.copy_region(&data,&[0,0],&[8,2,1,2]).0);
+        assert_eq!(d.imaging.0,dims.copy_region(&data,&[0,2],&[8,8,1,2]).0);
+
+        write_test_mrd("test_mrd_classified.mrd",shape,&data,Some(":VAR no_noise_views, 2\r\n:VAR no_ref_views, 3\r\n"));
+        let d = read_mrd_classified("test_mrd_classified.mrd").unwrap();
+        assert_eq!(d.phase_ref.unwrap().1.shape_ns(),&[8,3,1,2]);
+        assert_eq!(d.imaging.1.shape_ns(),&[8,5,1,2]);
+
+        write_test_mrd("test_mrd_classified.mrd",shape,&data,Some(":FOV 40\r\n"));
+        let d = read_mrd_classified("test_mrd_classified.mrd").unwrap();
+        std::fs::remove_file("test_mrd_classified.mrd").unwrap();
+        assert!(d.noise.is_none());
+        assert!(d.phase_ref.is_none());
+        assert_eq!(d.imaging.0,data);
+    }
+
+}
+
+/// size of the fixed MRD file header in bytes
+pub(crate) const MRD_HEADER_SIZE: usize = 512;
+
+#[derive(Debug)]
+pub enum MrdIoError {
+    IO{path: PathBuf, source: std::io::Error},
+    Header{path: PathBuf, msg: String},
+    /// the number of elements implied by the header dimensions doesn't match the data
+    SizeMismatch{path: PathBuf, header_elems: usize, decoded_elems: usize, file_size: u64},
+    InvalidSelection(String),
+    InvalidWrite(String),
+    /// the array described by the header can't be allocated
+    Alloc{path: PathBuf, source: AllocError},
+}
+
+impl Display for MrdIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MrdIoError::IO {path, source} => write!(f, "IO error for {}: {}", path.display(), source),
+            MrdIoError::Header {path, msg} => write!(f, "failed to parse MRD header of {}: {}", path.display(), msg),
+            MrdIoError::SizeMismatch {path, header_elems, decoded_elems, file_size} => write!(
+                f, "{} has {} elements from its header dimensions but {} elements of data ({} bytes on disk)",
+                path.display(), header_elems, decoded_elems, file_size
+            ),
+            MrdIoError::InvalidSelection(msg) => write!(f, "invalid selection: {}", msg),
+            MrdIoError::InvalidWrite(msg) => write!(f, "unable to write MRD: {}", msg),
+            MrdIoError::Alloc {path, source} => write!(f, "can't read {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for MrdIoError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> MrdIoError {
+    let path = path.to_path_buf();
+    move |source| MrdIoError::IO{path, source}
+}
+
+/// the fields of the MRD file header needed to locate and decode the data
+#[derive(Debug, Clone, Copy)]
+struct MrdHeader {
+    /// samples, views, views_2, slices, echoes, experiments
+    dims: [usize; 6],
+    /// data type code from the header. The lower 4 bits give the word type and bit 5 is set
+    /// for complex data
+    data_type: i16,
+    file_size: u64,
+    /// size of the block and parameter text following the data, located from the end of the file
+    trailer_size: u64,
+}
+
+impl MrdHeader {
+
+    fn read(path:&Path) -> Result<MrdHeader, MrdIoError> {
+        let mut f = File::open(path).map_err(io_err(path))?;
+        let file_size = f.metadata().map_err(io_err(path))?.len();
+        let header_err = |msg:String| MrdIoError::Header{path: path.to_path_buf(), msg};
+        if file_size < MRD_HEADER_SIZE as u64 {
+            return Err(header_err(format!("file is {} bytes, shorter than the {} byte header", file_size, MRD_HEADER_SIZE)));
+        }
+        let mut h = [0u8; MRD_HEADER_SIZE];
+        f.read_exact(&mut h).map_err(io_err(path))?;
+
+        let i32_at = |o:usize| i32::from_le_bytes([h[o], h[o + 1], h[o + 2], h[o + 3]]);
+        let data_type = i16::from_le_bytes([h[18], h[19]]);
+        let mut dims = [0usize; 6];
+        for (d, o) in dims.iter_mut().zip([0, 4, 8, 12, 152, 156]) {
+            let v = i32_at(o);
+            if v < 0 {
+                return Err(header_err(format!("negative dimension {} at byte {}", v, o)));
+            }
+            // unused dimensions may be stored as 0
+            *d = (v as usize).max(1);
+        }
+        let trailer_size = read_trailer_size(&mut f, path, file_size)?;
+        let header = MrdHeader { dims, data_type, file_size, trailer_size };
+        header.word_size().ok_or_else(|| header_err(format!("unknown data type {}", data_type)))?;
+        // the sizes derived from the dimensions are computed unchecked once the header is read
+        dims.iter().try_fold(header.sample_size(), |n, &d| n.checked_mul(d))
+            .and_then(|n| n.checked_add(MRD_HEADER_SIZE + PPR_OFFSET))
+            .filter(|&n| n <= isize::MAX as usize)
+            .ok_or_else(|| header_err(format!("dimensions {:?} are too large", dims)))?;
+        Ok(header)
+    }
+
+    fn is_complex(&self) -> bool {
+        self.data_type & 0x10 != 0
+    }
+
+    /// size of a single real value in bytes
+    fn word_size(&self) -> Option<usize> {
+        match self.data_type & 0xf {
+            0 | 1 => Some(1),
+            2 | 3 => Some(2),
+            4 | 5 => Some(4),
+            6 => Some(8),
+            _=> None,
+        }
+    }
+
+    /// the name of the on-disk element type
+    fn dtype_name(&self) -> &'static str {
+        match (self.data_type & 0xf, self.is_complex()) {
+            (0, false) => "uint8",
+            (1, false) => "int8",
+            (2 | 3, false) => "int16",
+            (4, false) => "int32",
+            (5, false) => "float32",
+            (6, false) => "float64",
+            (0, true) => "complex uint8",
+            (1, true) => "complex int8",
+            (2 | 3, true) => "complex int16",
+            (4, true) => "complex int32",
+            (5, true) => "complex float32",
+            _=> "complex float64",
+        }
+    }
+
+    /// size of a single sample (complex or real) in bytes
+    fn sample_size(&self) -> usize {
+        self.word_size().unwrap_or(1) * if self.is_complex() { 2 } else { 1 }
+    }
+
+    /// decodes samples from their on-disk words into complex values. Real data is given zero
+    /// imaginary parts
+    fn decode(&self, bytes:&[u8], out:&mut [Complex32]) {
+        let word = self.word_size().unwrap_or(1);
+        let words = bytes.chunks_exact(word).map(|w| match self.data_type & 0xf {
+            0 => w[0] as f32,
+            1 => w[0] as i8 as f32,
+            2 | 3 => i16::from_le_bytes([w[0], w[1]]) as f32,
+            4 => i32::from_le_bytes([w[0], w[1], w[2], w[3]]) as f32,
+            5 => f32::from_le_bytes([w[0], w[1], w[2], w[3]]),
+            _=> f64::from_le_bytes([w[0], w[1], w[2], w[3], w[4], w[5], w[6], w[7]]) as f32,
+        });
+        if self.is_complex() {
+            let mut words = words;
+            for o in out.iter_mut() {
+                let re = words.next().unwrap_or(0.);
+                let im = words.next().unwrap_or(0.);
+                *o = Complex32::new(re, im);
+            }
+        } else {
+            out.iter_mut().zip(words).for_each(|(o, re)| *o = Complex32::new(re, 0.));
+        }
+    }
+
+    /// encodes complex values into on-disk words. Integer words are rounded and saturated
+    fn encode(&self, data:&[Complex32], out:&mut Vec<u8>) {
+        let mut push = |x:f32| match self.data_type & 0xf {
+            0 => out.push(x.round() as u8),
+            1 => out.push(x.round() as i8 as u8),
+            2 | 3 => out.extend_from_slice(&(x.round() as i16).to_le_bytes()),
+            4 => out.extend_from_slice(&(x.round() as i32).to_le_bytes()),
+            5 => out.extend_from_slice(&x.to_le_bytes()),
+            _=> out.extend_from_slice(&(x as f64).to_le_bytes()),
+        };
+        for x in data {
+            push(x.re);
+            if self.is_complex() {
+                push(x.im);
+            }
+        }
+    }
+
+    /// reads and decodes samples starting at a sample offset from the start of the data
+    fn read_samples(&self, f:&mut File, path:&Path, offset:usize, out:&mut [Complex32], buf:&mut Vec<u8>) -> Result<(), MrdIoError> {
+        let sample_size = self.sample_size();
+        buf.resize(out.len() * sample_size, 0);
+        f.seek(SeekFrom::Start((MRD_HEADER_SIZE + offset * sample_size) as u64)).map_err(io_err(path))?;
+        f.read_exact(buf).map_err(io_err(path))?;
+        self.decode(buf, out);
+        Ok(())
+    }
+
+    fn numel(&self) -> usize {
+        self.dims.iter().product()
+    }
+
+    /// checks that the file holds at least the number of samples given by the header dimensions
+    fn check_size(&self, path:&Path) -> Result<(), MrdIoError> {
+        self.layout(path).validate()
+    }
+
+    fn layout(&self, path:&Path) -> MrdLayout {
+        MrdLayout {
+            path: path.to_path_buf(),
+            dims: self.dims,
+            dtype: self.dtype_name(),
+            complex: self.is_complex(),
+            sample_size: self.sample_size(),
+            file_size: self.file_size,
+            trailer_size: self.trailer_size,
+        }
+    }
+
+    fn size_err(&self, path:&Path, decoded_elems:usize) -> MrdIoError {
+        MrdIoError::SizeMismatch{
+            path: path.to_path_buf(),
+            header_elems: self.numel(),
+            decoded_elems,
+            file_size: self.file_size,
+        }
+    }
+
+    /// the number of whole samples present in the file after the header, ignoring any trailing
+    /// parameter text
+    fn available_samples(&self) -> usize {
+        ((self.file_size - self.trailer_size - MRD_HEADER_SIZE as u64) / self.sample_size() as u64) as usize
+    }
+}
+
+/// the on-disk layout of an MRD from its header and the size of the file
+#[derive(Debug, Clone)]
+pub struct MrdLayout {
+    pub path: PathBuf,
+    /// samples, views, views_2, slices, echoes, experiments
+    pub dims: [usize; 6],
+    /// the name of the element type, i.e. "complex int16"
+    pub dtype: &'static str,
+    pub complex: bool,
+    /// size of a single sample (complex or real) in bytes
+    pub sample_size: usize,
+    pub file_size: u64,
+    /// size of the block and parameter text found after the data, or 0 if the file has none
+    pub trailer_size: u64,
+}
+
+impl MrdLayout {
+
+    /// the number of samples given by the header dimensions
+    pub fn expected_samples(&self) -> usize {
+        self.dims.iter().product()
+    }
+
+    /// the number of whole samples present in the file after the header, ignoring any trailing
+    /// parameter text
+    pub fn available_samples(&self) -> usize {
+        ((self.file_size - self.trailer_size - MRD_HEADER_SIZE as u64) / self.sample_size as u64) as usize
+    }
+
+    /// the size of the header and data in bytes. Anything past this is parameter text
+    pub fn expected_data_end(&self) -> u64 {
+        (MRD_HEADER_SIZE + self.expected_samples() * self.sample_size) as u64
+    }
+
+    /// checks that the file holds at least the number of samples given by the header
+    /// dimensions. This is the check applied by the MRD readers
+    pub fn validate(&self) -> Result<(), MrdIoError> {
+        if self.available_samples() < self.expected_samples() {
+            return Err(MrdIoError::SizeMismatch{
+                path: self.path.clone(),
+                header_elems: self.expected_samples(),
+                decoded_elems: self.available_samples(),
+                file_size: self.file_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// reads the layout of an MRD from its header without reading the data or validating its size
+pub fn inspect_mrd(file:impl AsRef<Path>) -> Result<MrdLayout, MrdIoError> {
+    let path = file.as_ref();
+    Ok(MrdHeader::read(path)?.layout(path))
+}
+
+/// opens an MRD with mrd_rs, turning a panic from a malformed file into an error
+fn open_mrd(path:&Path) -> Result<MRD, MrdIoError> {
+    std::panic::catch_unwind(AssertUnwindSafe(|| MRD::open(path))).map_err(|_| MrdIoError::Header{
+        path: path.to_path_buf(),
+        msg: String::from("mrd_rs failed to open the file"),
+    })
+}
+
+/// options for reading MRD files
+#[derive(Debug, Clone, Default)]
+pub struct MrdReadOptions {
+    lenient: bool,
+}
+
+impl MrdReadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// salvage files whose data doesn't match the header dimensions by truncating or zero-padding
+    /// the data with a warning instead of returning an error
+    pub fn lenient(mut self, lenient:bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+}
+
+/// read data from an MRD file, returning an error for missing or malformed files, or data that
+/// doesn't agree with the header dimensions
+pub fn try_read_mrd(file:impl AsRef<Path>) -> Result<(Vec<Complex32>, ArrayDim, MRD), MrdIoError> {
+    try_read_mrd_with_options(file, &MrdReadOptions::default()).map(|(data, dims, mrd, _)| (data, dims, mrd))
+}
+
+/// read data from an MRD file with options. The number of elements given by the header
+/// dimensions is checked against the size of the file and the decoded data. The report notes any
+/// samples that were zero-filled or resized by a lenient read
+pub fn try_read_mrd_with_options(file:impl AsRef<Path>, opts:&MrdReadOptions) -> Result<(Vec<Complex32>, ArrayDim, MRD, ReadReport), MrdIoError> {
+    let path = file.as_ref();
+    let header = MrdHeader::read(path)?;
+    read_with_header(path, &header, opts)
+}
+
+/// reads the data of an MRD whose header has already been read
+fn read_with_header(path:&Path, header:&MrdHeader, opts:&MrdReadOptions) -> Result<(Vec<Complex32>, ArrayDim, MRD, ReadReport), MrdIoError> {
+    let mut report = ReadReport::default();
+
+    if opts.lenient && header.available_samples() < header.numel() {
+        // decode what is present and zero-pad the rest
+        let n = header.available_samples();
+        report.warn(ReadEvent::ZeroFilled{path: path.to_path_buf(), found: n, expected: header.numel()});
+        let dims = ArrayDim::from_shape(&header.dims);
+        let mut data = dims.try_alloc(Complex32::ZERO).map_err(|source| MrdIoError::Alloc{path: path.to_path_buf(), source})?;
+        let mut f = File::open(path).map_err(io_err(path))?;
+        header.read_samples(&mut f, path, 0, &mut data[..n], &mut vec![])?;
+        let mrd = open_mrd(path)?;
+        return Ok((data, dims, mrd, report));
+    }
+    header.check_size(path)?;
+
+    let mrd = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        let mrd = MRD::open(path);
+        let data = mrd.complex_stream();
+        (mrd, data)
+    }));
+    let (mrd, mut data) = mrd.map_err(|_| MrdIoError::Header{
+        path: path.to_path_buf(),
+        msg: String::from("mrd_rs failed to decode the file"),
+    })?;
+    let dims = ArrayDim::try_from_shape(&mrd.dimensions()).map_err(|e| MrdIoError::Header{path: path.to_path_buf(), msg: e.to_string()})?;
+    if dims.numel() != data.len() {
+        if !opts.lenient {
+            return Err(header.size_err(path, data.len()));
+        }
+        report.warn(ReadEvent::Resized{path: path.to_path_buf(), found: data.len(), expected: dims.numel()});
+        data.resize(dims.numel(), Complex32::ZERO);
+    }
+    Ok((data, dims, mrd, report))
+}
+
+
+/// index ranges to read from each dimension of an MRD. Dimensions follow the MRD ordering of
+/// samples, views, views_2, slices, echoes, experiments. Unset dimensions are read in full
+#[derive(Debug, Clone, Default)]
+pub struct MrdSelection {
+    ranges: [Option<Range<usize>>; 6],
+}
+
+impl MrdSelection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn samples(mut self, range:Range<usize>) -> Self {
+        self.ranges[0] = Some(range);
+        self
+    }
+
+    pub fn views(mut self, range:Range<usize>) -> Self {
+        self.ranges[1] = Some(range);
+        self
+    }
+
+    pub fn views_2(mut self, range:Range<usize>) -> Self {
+        self.ranges[2] = Some(range);
+        self
+    }
+
+    pub fn slices(mut self, range:Range<usize>) -> Self {
+        self.ranges[3] = Some(range);
+        self
+    }
+
+    pub fn echoes(mut self, range:Range<usize>) -> Self {
+        self.ranges[4] = Some(range);
+        self
+    }
+
+    pub fn experiments(mut self, range:Range<usize>) -> Self {
+        self.ranges[5] = Some(range);
+        self
+    }
+
+    /// sets the range of a dimension by its index in MRD ordering
+    pub fn axis(mut self, axis:usize, range:Range<usize>) -> Self {
+        assert!(axis < 6, "MRD files have 6 dimensions");
+        self.ranges[axis] = Some(range);
+        self
+    }
+
+    /// returns the offset and size of the selected region for the given dimensions
+    fn region(&self, dims:&[usize; 6]) -> ([usize; 6], [usize; 6]) {
+        let mut offset = [0; 6];
+        let mut size = *dims;
+        for (i, r) in self.ranges.iter().enumerate() {
+            if let Some(r) = r {
+                offset[i] = r.start;
+                size[i] = r.end.saturating_sub(r.start);
+            }
+        }
+        (offset, size)
+    }
+}
+
+/// reads a subset of an MRD, only reading the portions of the data needed for the selection. The
+/// returned dimensions are the selected sizes in MRD ordering
+pub fn read_mrd_subset(file:impl AsRef<Path>, selection:&MrdSelection) -> Result<(Vec<Complex32>, ArrayDim, MRD), MrdIoError> {
+    let path = file.as_ref();
+    let header = MrdHeader::read(path)?;
+    header.check_size(path)?;
+
+    let dims = ArrayDim::from_shape(&header.dims);
+    let (offset, size) = selection.region(&header.dims);
+    let region = dims.region_dims(&offset, &size).map_err(MrdIoError::InvalidSelection)?;
+
+    let mut f = File::open(path).map_err(io_err(path))?;
+    let mut out = region.try_alloc(Complex32::ZERO).map_err(|source| MrdIoError::Alloc{path: path.to_path_buf(), source})?;
+    let mut buf = vec![];
+    let mut n_read = 0;
+    for (addr, len) in dims.region_runs(&offset, &size) {
+        header.read_samples(&mut f, path, addr, &mut out[n_read..n_read + len], &mut buf)?;
+        n_read += len;
+    }
+
+    let mrd = open_mrd(path)?;
+    Ok((out, region, mrd))
+}
+
+/// errors from streaming an MRD, either from reading the file or from the chunk callback
+#[derive(Debug)]
+pub enum MrdStreamError<E> {
+    Mrd(MrdIoError),
+    Callback(E),
+}
+
+impl<E:Display> Display for MrdStreamError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MrdStreamError::Mrd(e) => write!(f, "{}", e),
+            MrdStreamError::Callback(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl<E:Display + std::fmt::Debug> std::error::Error for MrdStreamError<E> {}
+
+impl<E> From<MrdIoError> for MrdStreamError<E> {
+    fn from(err: MrdIoError) -> Self {
+        MrdStreamError::Mrd(err)
+    }
+}
+
+/// decodes an MRD in chunks of up to chunk_elems samples, passing each chunk and its sample
+/// offset to f in order. Only a single chunk is held in memory. Returns the dimensions in MRD
+/// ordering along with the header
+pub fn stream_mrd<E>(file:impl AsRef<Path>, chunk_elems:usize, mut f:impl FnMut(&[Complex32], u64) -> Result<(), E>) -> Result<(ArrayDim, MRD), MrdStreamError<E>> {
+    let path = file.as_ref();
+    let header = MrdHeader::read(path)?;
+    header.check_size(path)?;
+    let mrd = open_mrd(path)?;
+
+    let mut file = File::open(path).map_err(io_err(path))?;
+    let mut chunk = vec![Complex32::ZERO; chunk_elems.max(1).min(header.numel())];
+    let mut buf = vec![];
+    let mut offset = 0;
+    while offset < header.numel() {
+        let n = chunk.len().min(header.numel() - offset);
+        header.read_samples(&mut file, path, offset, &mut chunk[..n], &mut buf)?;
+        f(&chunk[..n], offset as u64).map_err(MrdStreamError::Callback)?;
+        offset += n;
+    }
+    Ok((ArrayDim::from_shape(&header.dims), mrd))
+}
+
+/// PPR variable giving the number of noise-only views at the start of the views dimension
+pub const NOISE_VIEWS_VAR: &str = "no_noise_views";
+/// PPR variable giving the number of receive channels
+pub const CHANNELS_VAR: &str = "no_receivers";
+/// PPR variable giving the number of phase reference views following any noise views
+pub const REF_VIEWS_VAR: &str = "no_ref_views";
+
+/// MRD data split into imaging views and any special views acquired with them
+pub struct MrdData {
+    pub imaging: (Vec<Complex32>, ArrayDim),
+    pub noise: Option<(Vec<Complex32>, ArrayDim)>,
+    pub phase_ref: Option<(Vec<Complex32>, ArrayDim)>,
+    pub mrd: MRD,
+    pub params: MrdParams,
+}
+
+/// reads an MRD, separating noise and phase reference views from the imaging views. The MRD
+/// header has no per-view flags, so special views are located from the NOISE_VIEWS_VAR and
+/// REF_VIEWS_VAR parameters: noise views come first along the views dimension, followed by
+/// phase reference views and then imaging views. Files without these parameters are returned
+/// as imaging data only
+pub fn read_mrd_classified(file:impl AsRef<Path>) -> Result<MrdData, MrdIoError> {
+    let path = file.as_ref();
+    let header = MrdHeader::read(path)?;
+    let params = read_params(path, &header)?;
+
+    let count = |var:&str| -> Result<usize, MrdIoError> {
+        match params.get(var) {
+            None => Ok(0),
+            Some(PprValue::Int(n)) if n >= 0 => Ok(n as usize),
+            Some(v) => Err(MrdIoError::Header{path: path.to_path_buf(), msg: format!("invalid {}: {:?}", var, v)}),
+        }
+    };
+    let n_noise = count(NOISE_VIEWS_VAR)?;
+    let n_ref = count(REF_VIEWS_VAR)?;
+    if n_noise + n_ref >= header.dims[1] {
+        return Err(MrdIoError::Header{path: path.to_path_buf(), msg: format!(
+            "{} noise and {} reference views leave no imaging views out of {}", n_noise, n_ref, header.dims[1]
+        )});
+    }
+
+    let views = |start:usize, n:usize| read_mrd_subset(path, &MrdSelection::new().views(start..start + n));
+    let noise = if n_noise > 0 { let (d, dims, _) = views(0, n_noise)?; Some((d, dims)) } else { None };
+    let phase_ref = if n_ref > 0 { let (d, dims, _) = views(n_noise, n_ref)?; Some((d, dims)) } else { None };
+    let (data, dims, mrd) = views(n_noise + n_ref, header.dims[1] - n_noise - n_ref)?;
+
+    Ok(MrdData {
+        imaging: (data, dims),
+        noise,
+        phase_ref,
+        mrd,
+        params,
+    })
+}
+
+/// PPR variables holding the MRD dimensions, in MRD ordering
+const PPR_DIM_VARS: [&str; 6] = ["no_samples", "no_views", "no_views_2", "no_slices", "no_echoes", "no_expts"];
+
+/// writes an MRD using the header, word format and parameters of a reference MRD file. The sizes
+/// in the header and the matching PPR variables are updated from the dimensions, which are in MRD
+/// ordering (samples, views, views_2, slices, echoes, experiments). The reference is given as a
+/// file rather than an MRD, as the MRD from mrd_rs doesn't hold the raw header and parameter text
+/// that are copied
+pub fn write_mrd(file:impl AsRef<Path>, data:&[Complex32], dims:ArrayDim, reference_file:impl AsRef<Path>) -> Result<(), MrdIoError> {
+    let path = file.as_ref();
+    let reference = reference_file.as_ref();
+    if data.len() != dims.numel() {
+        return Err(MrdIoError::InvalidWrite(format!(
+            "dims require {} elements but {} were supplied", dims.numel(), data.len()
+        )));
+    }
+    if dims.shape()[6..].iter().any(|&d| d != 1) {
+        return Err(MrdIoError::InvalidWrite(format!(
+            "MRD files have at most 6 dimensions but got {:?}", dims.shape_ns()
+        )));
+    }
+    let ref_header = MrdHeader::read(reference)?;
+    ref_header.check_size(reference)?;
+
+    let mut ref_file = File::open(reference).map_err(io_err(reference))?;
+    let mut h = vec![0u8; MRD_HEADER_SIZE];
+    ref_file.read_exact(&mut h).map_err(io_err(reference))?;
+    for (d, o) in dims.shape()[..6].iter().zip([0, 4, 8, 12, 152, 156]) {
+        let d = i32::try_from(*d).map_err(|_| MrdIoError::InvalidWrite(format!("dimension {} is too large", d)))?;
+        h[o..o + 4].copy_from_slice(&d.to_le_bytes());
+    }
+
+    // the trailing block and parameter text are copied with the dimension variables updated
+    ref_file.seek(SeekFrom::Start((MRD_HEADER_SIZE + ref_header.numel() * ref_header.sample_size()) as u64))
+        .map_err(io_err(reference))?;
+    let mut trailer = vec![];
+    ref_file.read_to_end(&mut trailer).map_err(io_err(reference))?;
+    if trailer.len() > PPR_OFFSET {
+        let ppr = String::from_utf8_lossy(&trailer[PPR_OFFSET..]).to_string();
+        let updated:String = ppr.split_inclusive('\n').map(|line| {
+            let var = line.trim().strip_prefix(":VAR").and_then(|v| v.split_once(','));
+            match var.and_then(|(name, _)| PPR_DIM_VARS.iter().position(|v| *v == name.trim())) {
+                Some(i) => {
+                    let ending = &line[line.trim_end().len()..];
+                    format!(":VAR {}, {}{}", PPR_DIM_VARS[i], dims.shape()[i], ending)
+                }
+                None => line.to_string(),
+            }
+        }).collect();
+        trailer.truncate(PPR_OFFSET);
+        trailer.extend_from_slice(updated.as_bytes());
+    }
+
+    let mut w = BufWriter::new(File::create(path).map_err(io_err(path))?);
+    w.write_all(&h).map_err(io_err(path))?;
+    let mut buf = vec![];
+    for chunk in data.chunks(1 << 16) {
+        buf.clear();
+        ref_header.encode(chunk, &mut buf);
+        w.write_all(&buf).map_err(io_err(path))?;
+    }
+    w.write_all(&trailer).map_err(io_err(path))?;
+    w.flush().map_err(io_err(path))
+}
+
+/// number of bytes between the end of the data and the start of the parameter text
+const PPR_OFFSET: usize = 120;
+
+/// the most bytes at the end of a file searched for parameter text
+const PPR_MAX_SIZE: u64 = 1 << 20;
+
+/// finds the size of the trailer following the data from the parameter text at the end of the
+/// file, so that the data end is known even when the data is truncated. The text is the run of
+/// text lines at the end of the file starting with ':', and is preceded by the PPR_OFFSET byte
+/// block. Files without parameter text have no trailer
+fn read_trailer_size(f:&mut File, path:&Path, file_size:u64) -> Result<u64, MrdIoError> {
+    let n = (file_size - MRD_HEADER_SIZE as u64).min(PPR_MAX_SIZE);
+    let mut tail = vec![0u8; n as usize];
+    f.seek(SeekFrom::Start(file_size - n)).map_err(io_err(path))?;
+    f.read_exact(&mut tail).map_err(io_err(path))?;
+    let is_text = |b:u8| matches!(b, b'\t' | b'\n' | b'\r' | 0x20..=0x7e | 0x80..=0xff);
+    let text_start = tail.iter().rposition(|&b| !is_text(b)).map_or(0, |i| i + 1);
+    let text = &tail[text_start..];
+    if !text.starts_with(b":") || !text.contains(&b'\n') {
+        return Ok(0);
+    }
+    Ok((text.len() + PPR_OFFSET).min(tail.len()) as u64)
+}
+
+/// a value from the MRD parameter (PPR) text
+#[derive(Debug, Clone, PartialEq)]
+pub enum PprValue {
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl PprValue {
+    fn parse(s:&str) -> PprValue {
+        let s = s.trim();
+        if let Ok(i) = s.parse::<i64>() {
+            PprValue::Int(i)
+        } else if let Ok(f) = s.parse::<f64>() {
+            PprValue::Float(f)
+        } else {
+            PprValue::Text(s.trim_matches('"').to_string())
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            PprValue::Int(i) => Some(*i as f64),
+            PprValue::Float(f) => Some(*f),
+            PprValue::Text(_) => None,
+        }
+    }
+}
+
+/// acquisition parameters of an MRD. Sizes come from the file header and the remaining fields
+/// from the parameter (PPR) text following the data
+#[derive(Debug, Clone)]
+pub struct MrdParams {
+    pub samples: usize,
+    pub views: usize,
+    pub views_2: usize,
+    pub slices: usize,
+    pub echoes: usize,
+    pub experiments: usize,
+    /// field of view from the FOV entry
+    pub fov: Option<f64>,
+    /// echo time from the te variable
+    pub echo_time: Option<f64>,
+    /// repetition time from the tr variable
+    pub repetition_time: Option<f64>,
+    ppr: BTreeMap<String, PprValue>,
+}
+
+impl MrdParams {
+
+    /// the sizes in MRD ordering
+    pub fn shape(&self) -> [usize; 6] {
+        [self.samples, self.views, self.views_2, self.slices, self.echoes, self.experiments]
+    }
+
+    /// the number of receive channels from the CHANNELS_VAR parameter, if present
+    pub fn channels(&self) -> Option<usize> {
+        match self.get(CHANNELS_VAR)? {
+            PprValue::Int(n) if n > 0 => Some(n as usize),
+            _ => None,
+        }
+    }
+
+    /// locates the channel dimension. The MRD header has no channel axis, so this is the slowest
+    /// varying dimension whose size matches the CHANNELS_VAR parameter. Returns None for single
+    /// channel data or when no dimension matches
+    pub fn channel_axis(&self) -> Option<usize> {
+        let n = self.channels().filter(|&n| n > 1)?;
+        self.shape().iter().rposition(|&d| d == n)
+    }
+
+    /// returns any parameter by name. Variables (:VAR name, value) are found by their variable
+    /// name and other entries by their keyword
+    pub fn get(&self, key:&str) -> Option<PprValue> {
+        self.ppr.get(key).cloned()
+    }
+
+    pub fn ppr(&self) -> &BTreeMap<String, PprValue> {
+        &self.ppr
+    }
+
+    /// the PPR dimension variables (no_samples, no_views, ...) that disagree with the header, as
+    /// the variable name, the PPR value and the header size
+    pub fn dim_mismatches(&self) -> Vec<(&'static str, i64, usize)> {
+        PPR_DIM_VARS.iter().zip(self.shape()).filter_map(|(&var, size)| match self.get(var) {
+            Some(PprValue::Int(n)) if n.max(1) as usize != size => Some((var, n, size)),
+            _ => None,
+        }).collect()
+    }
+}
+
+/// parses the entries of PPR text. Lines without a leading ':' are ignored
+fn parse_ppr(text:&str) -> BTreeMap<String, PprValue> {
+    let mut ppr = BTreeMap::new();
+    for line in text.lines().map(|l| l.trim()).filter_map(|l| l.strip_prefix(':')) {
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        if keyword == "VAR" {
+            if let Some((name, value)) = rest.split_once(',') {
+                ppr.insert(name.trim().to_string(), PprValue::parse(value));
+            }
+        } else if keyword == "EDITTEXT" {
+            let (name, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            ppr.insert(name.to_string(), PprValue::parse(value));
+        } else {
+            ppr.insert(keyword.to_string(), PprValue::parse(rest));
+        }
+    }
+    ppr
+}
+
+fn read_params(path:&Path, header:&MrdHeader) -> Result<MrdParams, MrdIoError> {
+    let mut f = File::open(path).map_err(io_err(path))?;
+    let start = (MRD_HEADER_SIZE + header.numel() * header.sample_size() + PPR_OFFSET) as u64;
+    let mut bytes = vec![];
+    if start < header.file_size {
+        f.seek(SeekFrom::Start(start)).map_err(io_err(path))?;
+        f.read_to_end(&mut bytes).map_err(io_err(path))?;
+    }
+    let ppr = parse_ppr(&String::from_utf8_lossy(&bytes));
+    let var = |keys:&[&str]| keys.iter().find_map(|k| ppr.get(*k).and_then(|v| v.as_f64()));
+    Ok(MrdParams {
+        samples: header.dims[0],
+        views: header.dims[1],
+        views_2: header.dims[2],
+        slices: header.dims[3],
+        echoes: header.dims[4],
+        experiments: header.dims[5],
+        fov: var(&["FOV"]),
+        echo_time: var(&["te", "TE", "echo_time"]),
+        repetition_time: var(&["tr", "TR", "repetition_time"]),
+        ppr,
+    })
+}
+
+/// reads the acquisition parameters of an MRD without reading the data
+pub fn read_mrd_params(file:impl AsRef<Path>) -> Result<MrdParams, MrdIoError> {
+    let path = file.as_ref();
+    let header = MrdHeader::read(path)?;
+    read_params(path, &header)
+}
+
+/// reads the data, header and acquisition parameters of an MRD. The file header is parsed once
+/// for both the data and the parameters, since the MRD returned by mrd_rs doesn't hold the
+/// parameter text
+pub fn read_mrd_full(file:impl AsRef<Path>) -> Result<(Vec<Complex32>, ArrayDim, MRD, MrdParams), MrdIoError> {
+    let path = file.as_ref();
+    let header = MrdHeader::read(path)?;
+    let (data, dims, mrd, _) = read_with_header(path, &header, &MrdReadOptions::default())?;
+    let params = read_params(path, &header)?;
+    Ok((data, dims, mrd, params))
+}
 
 /// read data from an MRS MRD file. This also returns the file header
 pub fn read_mrd(file:impl AsRef<Path>) -> (Vec<Complex32>, ArrayDim, MRD) {
-    let mrd = MRD::open(file);
-    let dims = ArrayDim::from_shape(&mrd.dimensions());
-    let data = mrd.complex_stream();
-    (data, dims, mrd)
+    try_read_mrd(file).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// reads the dimensions and the name of the element type of an MRD from its header without
+/// reading the data, i.e. "complex int16". The bool is true for complex data
+pub fn read_mrd_layout(file:impl AsRef<Path>) -> Result<(ArrayDim, &'static str, bool), MrdIoError> {
+    let header = MrdHeader::read(file.as_ref())?;
+    Ok((ArrayDim::from_shape(&header.dims), header.dtype_name(), header.is_complex()))
 }
 
 /// read only the header for the MRD file
@@ -25,4 +1078,4 @@ pub fn read_mrd_buffer_f(file: impl AsRef<Path>, offset:usize, buffer:&mut [Comp
 /// read partial MRD contents to a buffer with some offset from an opened MRD
 pub fn read_mrd_buffer(mrd:&MRD, offset:usize, buffer:&mut [Complex32]) -> std::io::Result<()> {
     mrd.fill_buffer(buffer,offset)
-}
\ No newline at end of file
+}
@@ -1,20 +1,27 @@
-use std::path::Path;
-use bytemuck::Pod;
+use std::any::{Any, TypeId};
+use std::fmt::Display;
+use std::fs::File;
+use std::marker::PhantomData;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use bytemuck::{Pod, Zeroable};
+use flate2::Compression;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
 use nifti;
 pub use nifti::NiftiHeader;
-use nifti::{DataElement, InMemNiftiVolume, NiftiObject, NiftiType, NiftiVolume};
-use ndarray;
-use ndarray::ShapeBuilder;
+use nifti::{DataElement, InMemNiftiVolume, NiftiError, NiftiObject, NiftiType, NiftiVolume};
 use num_complex::Complex;
-use crate::ArrayDim;
-use num_traits::{NumCast, ToPrimitive, Zero};
+use crate::{ArrayDim, ReadEvent, ReadReport};
+use num_traits::{Bounded, NumCast, ToPrimitive, Zero};
+use rayon::prelude::*;
 
 
 #[cfg(test)]
 mod tests {
     use num_complex::{Complex32, Complex64};
-    use crate::ArrayDim;
-    use crate::io_nifti::{read_nifti_complex, read_nifti, write_nifti};
+    use crate::{ArrayDim, ReadEvent, ReadReport};
+    use crate::io_nifti::{stream_real, stream_complex, VoxelStream, cast_elements, cast_pairs, to_complex, nifti_affine, set_nifti_affine, NiftiHeader, nifti_output_path, read_nifti_complex, read_nifti, write_nifti, write_nifti_with_options, NiftiIoError, NiftiWriteOptions, NiftiReadOptions, CastPolicy, try_read_nifti, try_read_nifti_with_options, try_read_nifti_complex_with_options, NiftiStreamReader, NiftiStreamWriter};
 
     #[test]
     fn test_io_nifti() {
@@ -67,14 +74,387 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_stream_writer_and_reader() {
+        let dims = ArrayDim::from_shape(&[6,5,4]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32, -0.5 * i as f32)).collect();
+        for name in ["test_stream_rw.nii", "test_stream_rw.nii.gz"] {
+            let mut w = NiftiStreamWriter::<Complex32>::create(name,dims,None,&NiftiWriteOptions::new()).unwrap();
+            for chunk in x.chunks(7) {
+                w.write_chunk(chunk).unwrap();
+            }
+            assert_eq!(w.remaining(),0);
+            assert!(w.write_chunk(&x[..1]).is_err());
+            assert_eq!(w.finish().unwrap().to_str(),Some(name));
+
+            let mut r = NiftiStreamReader::<Complex32>::open(name).unwrap();
+            assert_eq!(r.dims().shape(),dims.shape());
+            let mut y = r.read_chunk(30).unwrap();
+            y.extend(r.read_chunk(r.remaining()).unwrap());
+            assert!(matches!(r.read_chunk(1),Err(NiftiIoError::InconsistentArraySize{expected:0,actual:1})));
+            let wrong_type = NiftiStreamReader::<f32>::open(name);
+            let (z,..) = read_nifti_complex::<f32>(name);
+            std::fs::remove_file(name).unwrap();
+            assert_eq!(y,x);
+            assert_eq!(z,x);
+            assert!(matches!(wrong_type,Err(NiftiIoError::Unsupported(_))));
+        }
+
+        // finishing early leaves the file incomplete
+        let mut w = NiftiStreamWriter::<f32>::create("test_stream_short.nii",dims,None,&NiftiWriteOptions::new()).unwrap();
+        w.write_chunk(&[0.; 10]).unwrap();
+        let r = w.finish();
+        std::fs::remove_file("test_stream_short.nii").unwrap();
+        assert!(matches!(r,Err(NiftiIoError::InconsistentArraySize{expected:120,actual:10})));
+    }
+
+    #[test]
+    fn test_streamed_matches_nifti_writer() {
+        use ndarray::ShapeBuilder;
+        let dims = ArrayDim::from_shape(&[5,4,3,2]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32 * 0.5).collect();
+        let mut h = NiftiHeader::default();
+        set_nifti_affine(&mut h,[[0.5,0.,0.,-2.],[0.,0.5,0.,3.],[0.,0.,1.,4.],[0.,0.,0.,1.]]);
+        h.descrip = b"streamed".to_vec();
+
+        write_nifti_with_options("test_streamed",&x,dims,Some(&h),&NiftiWriteOptions::default()).unwrap();
+        let arr = ndarray::Array::from_shape_vec([5,4,3,2].f(),x.clone()).unwrap();
+        nifti::writer::WriterOptions::new("test_streamed_ref.nii").reference_header(&h).write_nifti(&arr).unwrap();
+        let streamed = std::fs::read("test_streamed.nii").unwrap();
+        let reference = std::fs::read("test_streamed_ref.nii").unwrap();
+        std::fs::remove_file("test_streamed.nii").unwrap();
+        std::fs::remove_file("test_streamed_ref.nii").unwrap();
+        assert_eq!(streamed.len(),352 + 4 * x.len());
+        assert!(streamed == reference);
+    }
+
+    #[test]
+    fn test_same_type_read() {
+        // reads of the stored type must match reads through a conversion exactly
+        let dims = ArrayDim::from_shape(&[7,5,3]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| (i as f32 * 0.37).sin() * 1e3).collect();
+        write_nifti("test_same_type",&x,dims);
+        let (same,..) = read_nifti::<f32>("test_same_type.nii");
+        let (converted,..) = read_nifti::<f64>("test_same_type.nii");
+        std::fs::remove_file("test_same_type.nii").unwrap();
+        assert!(same.iter().zip(&x).all(|(a,b)| a.to_bits() == b.to_bits()));
+        assert!(converted.iter().zip(&x).all(|(a,b)| *a == *b as f64));
+
+        let z:Vec<Complex32> = x.iter().map(|&v| Complex32::new(v,-0.5 * v)).collect();
+        write_nifti("test_same_type_c",&z,dims);
+        let (same,..) = read_nifti_complex::<f32>("test_same_type_c.nii");
+        let (converted,..) = read_nifti_complex::<f64>("test_same_type_c.nii");
+        std::fs::remove_file("test_same_type_c.nii").unwrap();
+        assert_eq!(same,z);
+        assert!(converted.iter().zip(&z).all(|(a,b)| a.re == b.re as f64 && a.im == b.im as f64));
+    }
+
+    #[test]
+    fn test_streamed_read() {
+        use std::path::Path;
+        use nifti::NiftiObject;
+        let dims = ArrayDim::from_shape(&[6,5,4]);
+        let x:Vec<i16> = (0..dims.numel()).map(|i| (i as i16 - 50) * 7).collect();
+        let z:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,-0.25 * i as f32)).collect();
+        write_nifti("test_streamed_read",&x,dims);
+        write_nifti("test_streamed_read_c.nii.gz",&z,dims);
+
+        // the streamed reads match the nifti reader
+        let (real,real_dims,_) = stream_real::<f32>(Path::new("test_streamed_read.nii"),CastPolicy::Strict,&mut ReadReport::default()).unwrap().unwrap();
+        let reference = nifti::ReaderOptions::new().read_file("test_streamed_read.nii").unwrap()
+            .into_volume().into_nifti_typed_data::<f32>().unwrap();
+        assert_eq!(real,reference);
+        assert_eq!(real_dims.shape(),dims.shape());
+        let (complex,..) = stream_complex::<f64>(Path::new("test_streamed_read.nii"),CastPolicy::Strict).unwrap().unwrap();
+        assert!(complex.iter().zip(&x).all(|(c,&v)| c.re == v as f64 && c.im == 0.));
+
+        let (complex,complex_dims,_) = stream_complex::<f32>(Path::new("test_streamed_read_c.nii.gz"),CastPolicy::Strict).unwrap().unwrap();
+        assert_eq!(complex,z);
+        assert_eq!(complex_dims.shape(),dims.shape());
+        let (real,..) = stream_real::<f64>(Path::new("test_streamed_read_c.nii.gz"),CastPolicy::Strict,&mut ReadReport::default()).unwrap().unwrap();
+        assert!(real.iter().zip(&z).all(|(r,c)| *r == c.re as f64));
+
+        // scaled data is left to the nifti reader
+        let mut h = NiftiHeader::default();
+        h.scl_slope = 2.;
+        write_nifti_with_options("test_streamed_read",&x,dims,Some(&h),&NiftiWriteOptions::new().overwrite(true)).unwrap();
+        assert!(stream_real::<f32>(Path::new("test_streamed_read.nii"),CastPolicy::Strict,&mut ReadReport::default()).unwrap().is_none());
+        std::fs::remove_file("test_streamed_read.nii").unwrap();
+        std::fs::remove_file("test_streamed_read_c.nii.gz").unwrap();
+
+        // values stored in the other byte order are swapped as they are read
+        let swapped:Vec<u8> = x.iter().flat_map(|v| v.swap_bytes().to_ne_bytes()).collect();
+        let mut s = VoxelStream {
+            header: NiftiHeader::default(), dims, reader: Box::new(std::io::Cursor::new(swapped)), swapped: true,
+        };
+        assert_eq!(s.read_real::<i16, i16>(x.len(),CastPolicy::Strict).unwrap(),x);
+    }
+
+    #[test]
+    fn test_cast_policy() {
+        let dims = ArrayDim::from_shape(&[5]);
+        let x = [f32::NAN,f32::INFINITY,1e6,-5.,f32::NEG_INFINITY];
+        write_nifti("test_cast_policy",&x,dims);
+        let read = |policy| try_read_nifti_with_options::<i16>("test_cast_policy.nii",&NiftiReadOptions::new().cast_policy(policy)).map(|(x,..)| x);
+
+        // the legacy reader is strict, reporting the first value that can't be cast
+        assert!(matches!(try_read_nifti::<i16>("test_cast_policy.nii"),Err(NiftiIoError::Cast{addr:0,..})));
+        assert_eq!(read(CastPolicy::Saturate).unwrap(),vec![0,i16::MAX,i16::MAX,-5,i16::MIN]);
+        match read(CastPolicy::NanToZero) {
+            Err(NiftiIoError::Cast{addr,value,..}) => assert_eq!((addr,value),(2,1e6)),
+            r => panic!("expected a cast error, got {:?}",r),
+        }
+        let (y,..) = try_read_nifti_with_options::<f64>("test_cast_policy.nii",&NiftiReadOptions::new().cast_policy(CastPolicy::NanToZero)).unwrap();
+        assert_eq!(y,vec![0.,0.,1e6,-5.,0.]);
+        let (z,..) = try_read_nifti_complex_with_options::<f32>("test_cast_policy.nii",&NiftiReadOptions::new().cast_policy(CastPolicy::NanToZero)).unwrap();
+        assert_eq!(z.iter().map(|z| z.re).collect::<Vec<_>>(),vec![0.,0.,1e6,-5.,0.]);
+        std::fs::remove_file("test_cast_policy.nii").unwrap();
+    }
+
+    /// records every log message so tests can check what was logged
+    mod capture {
+        use std::sync::Mutex;
+        use log::{Level, LevelFilter, Log, Metadata, Record};
+
+        static RECORDS:Mutex<Vec<(Level,String)>> = Mutex::new(vec![]);
+
+        struct Capture;
+
+        impl Log for Capture {
+            fn enabled(&self, _:&Metadata) -> bool { true }
+            fn log(&self, record:&Record) {
+                RECORDS.lock().unwrap().push((record.level(),record.args().to_string()));
+            }
+            fn flush(&self) {}
+        }
+
+        static CAPTURE:Capture = Capture;
+
+        pub fn install() {
+            let _ = log::set_logger(&CAPTURE);
+            log::set_max_level(LevelFilter::Trace);
+        }
+
+        /// the number of warnings that mention a file
+        pub fn warnings_for(file:&str) -> usize {
+            RECORDS.lock().unwrap().iter().filter(|(l,m)| *l == Level::Warn && m.contains(file)).count()
+        }
+    }
+
+    #[test]
+    fn test_read_report() {
+        capture::install();
+        let dims = ArrayDim::from_shape(&[4,3]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,1.)).collect();
+        write_nifti("test_read_report_c",&x,dims);
+        write_nifti("test_read_report_r",&x.iter().map(|x| x.re).collect::<Vec<f32>>(),dims);
+
+        // reading complex data as real is lossy
+        let (re,_,_,report) = try_read_nifti_with_options::<f32>("test_read_report_c.nii",&NiftiReadOptions::default()).unwrap();
+        assert_eq!(re[5],5.);
+        assert_eq!(report.events,vec![ReadEvent::DiscardedImaginary{path:"test_read_report_c.nii".into(),dtype:"Complex32"}]);
+
+        let (..,report) = try_read_nifti_complex_with_options::<f32>("test_read_report_c.nii",&NiftiReadOptions::default()).unwrap();
+        assert!(report.is_clean());
+        let (..,report) = try_read_nifti_with_options::<f32>("test_read_report_r.nii",&NiftiReadOptions::default()).unwrap();
+        assert_eq!(report,ReadReport::default());
+        std::fs::remove_file("test_read_report_c.nii").unwrap();
+        std::fs::remove_file("test_read_report_r.nii").unwrap();
+
+        assert_eq!(capture::warnings_for("test_read_report_c.nii"),1);
+        assert_eq!(capture::warnings_for("test_read_report_r.nii"),0);
+    }
+
+    #[test]
+    fn test_parallel_cast() {
+        let x:Vec<i16> = (0..10_000).map(|i| (i * 37 % 65_536 - 32_768) as i16).collect();
+        let serial:Vec<f32> = cast_elements(&x,false,CastPolicy::Strict).unwrap();
+        let parallel:Vec<f32> = cast_elements(&x,true,CastPolicy::Strict).unwrap();
+        assert!(serial.iter().zip(&parallel).all(|(a,b)| a.to_bits() == b.to_bits()));
+        assert_eq!(serial[5],x[5] as f32);
+
+        let bytes:Vec<u8> = x.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let serial:Vec<Complex64> = cast_pairs::<i16,f64>(&bytes,false,CastPolicy::Strict).unwrap();
+        let parallel:Vec<Complex64> = cast_pairs::<i16,f64>(&bytes,true,CastPolicy::Strict).unwrap();
+        assert_eq!(serial,parallel);
+        assert_eq!(serial[1],Complex64::new(x[2] as f64,x[3] as f64));
+
+        assert_eq!(to_complex(&x,false),to_complex(&x,true));
+    }
+
+    #[test]
+    fn test_nifti_output_path() {
+        assert_eq!(nifti_output_path("subj.01_scan"),std::path::PathBuf::from("subj.01_scan.nii"));
+        assert_eq!(nifti_output_path("dir.v2/subj"),std::path::PathBuf::from("dir.v2/subj.nii"));
+        assert_eq!(nifti_output_path("subj.nii"),std::path::PathBuf::from("subj.nii"));
+        assert_eq!(nifti_output_path("subj.01.nii.gz"),std::path::PathBuf::from("subj.01.nii.gz"));
+    }
+
+    #[test]
+    fn test_write_nifti_dotted_basename() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x = dims.alloc(2f32);
+        write_nifti("test_dotted.01_scan",&x,dims);
+        let (data,..) = read_nifti::<f32>("test_dotted.01_scan.nii");
+        std::fs::remove_file("test_dotted.01_scan.nii").unwrap();
+        assert_eq!(x,data);
+    }
+
+    #[test]
+    fn test_write_nifti_gz() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x = dims.alloc(3f32);
+        let out = write_nifti_with_options("test_gz.nii.gz",&x,dims,None,&NiftiWriteOptions::default()).unwrap();
+        assert_eq!(out,std::path::PathBuf::from("test_gz.nii.gz"));
+        let mut magic = [0u8;2];
+        std::io::Read::read_exact(&mut std::fs::File::open(&out).unwrap(),&mut magic).unwrap();
+        let (data,..) = read_nifti::<f32>(&out);
+        std::fs::remove_file(&out).unwrap();
+        // gzip magic number
+        assert_eq!(magic,[0x1f,0x8b]);
+        assert_eq!(x,data);
+    }
+
+    #[test]
+    fn test_write_nifti_dim_limit() {
+        // 2 x 2 x 1 x 200 x 200 collapses to a 4th dim of 40000
+        let dims = ArrayDim::from_shape(&[2,2,1,200,200]);
+        let x = vec![0u8; dims.numel()];
+        let r = write_nifti_with_options("test_write_nifti_dim_limit",&x,dims,None,&NiftiWriteOptions::default());
+        assert!(matches!(r,Err(NiftiIoError::DimTooLarge{dim:4,size:40000,collapsed:true})));
+        let h = NiftiHeader::default();
+        let dims = ArrayDim::from_shape(&[2,2,1,40000]);
+        let x = vec![0u8; dims.numel()];
+        let r = write_nifti_with_options("test_write_nifti_dim_limit",&x,dims,Some(&h),&NiftiWriteOptions::default());
+        assert!(matches!(r,Err(NiftiIoError::DimTooLarge{dim:4,size:40000,collapsed:false})));
+        assert!(!std::path::Path::new("test_write_nifti_dim_limit.nii").exists());
+    }
+
+    #[test]
+    fn test_write_nifti_size_mismatch() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let r = write_nifti_with_options("test_write_nifti_size_mismatch",&[0f32; 20],dims,None,&NiftiWriteOptions::default());
+        assert!(matches!(r,Err(NiftiIoError::InconsistentArraySize{expected:24,actual:20})));
+        assert!(!std::path::Path::new("test_write_nifti_size_mismatch.nii").exists());
+    }
+
+    #[test]
+    fn test_write_nifti_overwrite() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x = dims.alloc(1f32);
+        let opts = NiftiWriteOptions::new().overwrite(false);
+        write_nifti_with_options("test_exists",&x,dims,None,&opts).unwrap();
+        let r = write_nifti_with_options("test_exists",&x,dims,None,&opts);
+        assert!(matches!(r,Err(NiftiIoError::FileExists(_))));
+        // default options clobber the existing file
+        write_nifti_with_options("test_exists",&x,dims,None,&NiftiWriteOptions::default()).unwrap();
+        std::fs::remove_file("test_exists.nii").unwrap();
+    }
+
+    #[test]
+    fn test_write_nifti_create_dirs() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x = dims.alloc(1f32);
+        let r = write_nifti_with_options("test_nifti_dirs/a/out",&x,dims,None,&NiftiWriteOptions::default());
+        assert!(matches!(r,Err(NiftiIoError::ParentDirNotFound(_))));
+        let opts = NiftiWriteOptions::new().create_dirs(true);
+        let out = write_nifti_with_options("test_nifti_dirs/a/out",&x,dims,None,&opts).unwrap();
+        assert!(out.is_file());
+        std::fs::remove_dir_all("test_nifti_dirs").unwrap();
+    }
+
+    #[test]
+    fn test_nifti_qform_affine() {
+        // a 90 degree rotation about z with a qform only
+        let mut h = NiftiHeader::default();
+        h.sform_code = 0;
+        h.qform_code = 1;
+        h.quatern_b = 0.;
+        h.quatern_c = 0.;
+        h.quatern_d = std::f32::consts::FRAC_1_SQRT_2;
+        h.quatern_x = 10.;
+        h.quatern_y = 20.;
+        h.quatern_z = 30.;
+        h.pixdim = [1., 2., 3., 4., 1., 1., 1., 1.];
+        let expected = [
+            [0., -3., 0., 10.],
+            [2., 0., 0., 20.],
+            [0., 0., 4., 30.],
+            [0., 0., 0., 1.],
+        ];
+        let check = |affine:[[f64;4];4], expected:[[f64;4];4]| {
+            for r in 0..4 {
+                for c in 0..4 {
+                    assert!((affine[r][c] - expected[r][c]).abs() < 1e-5, "{:?}", affine);
+                }
+            }
+        };
+        check(nifti_affine(&h), expected);
+
+        // a negative qfac flips the third axis
+        h.pixdim[0] = -1.;
+        let mut flipped = expected;
+        flipped[2][2] = -4.;
+        check(nifti_affine(&h), flipped);
+
+        // stamping the affine into the sform keeps it
+        set_nifti_affine(&mut h, flipped);
+        assert_eq!(h.sform_code, 1);
+        check(nifti_affine(&h), flipped);
+    }
+
+    #[test]
+    fn test_nifti_affine() {
+        let affine = [
+            [-0.5, 0.1, 0., 12.],
+            [0., 0.75, 0., -30.],
+            [0., 0., 2., 4.],
+            [0., 0., 0., 1.],
+        ];
+        let mut h = NiftiHeader::default();
+        set_nifti_affine(&mut h,affine);
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        write_nifti_with_options("test_affine",&dims.alloc(0f32),dims,Some(&h),&NiftiWriteOptions::default()).unwrap();
+        let (_,_,read_h) = read_nifti::<f32>("test_affine.nii");
+        std::fs::remove_file("test_affine.nii").unwrap();
+        let read_affine = nifti_affine(&read_h);
+        for r in 0..4 {
+            for c in 0..4 {
+                assert!((read_affine[r][c] - affine[r][c]).abs() < 1e-6);
+            }
+        }
+        assert!((read_h.pixdim[1] - (0.5f32*0.5 + 0.1*0.1).sqrt()).abs() < 1e-6);
+    }
+
 }
 
 /// read data from a nifti file assumed to be storing real data. If the data is complex, then only
 /// the real part is read. The returns the data as a vec, an array dimension helper type, and the
 /// nifti header
-pub fn read_nifti<T:ToPrimitive + NumCast + 'static + Pod>(file:impl AsRef<Path>) -> (Vec<T>, ArrayDim, NiftiHeader) {
+pub fn read_nifti<T:ToPrimitive + NumCast + Bounded + 'static + Pod + Send + Sync>(file:impl AsRef<Path>) -> (Vec<T>, ArrayDim, NiftiHeader) {
+    try_read_nifti(file).expect("failed to read nifti file")
+}
+
+/// read data from a nifti file as real values as with read_nifti, returning an error instead of
+/// panicking if the file cannot be read, has an unsupported data type or holds a value that can't
+/// be cast to T
+pub fn try_read_nifti<T:ToPrimitive + NumCast + Bounded + 'static + Pod + Send + Sync>(file:impl AsRef<Path>) -> Result<(Vec<T>, ArrayDim, NiftiHeader), NiftiIoError> {
+    try_read_nifti_with_options(file, &NiftiReadOptions::default()).map(|(data, dims, h, _)| (data, dims, h))
+}
+
+/// read data from a nifti file as real values as with try_read_nifti, casting values to T as set
+/// by the options. The report notes when the imaginary parts of complex data were dropped
+pub fn try_read_nifti_with_options<T:ToPrimitive + NumCast + Bounded + 'static + Pod + Send + Sync>(file:impl AsRef<Path>, opts:&NiftiReadOptions) -> Result<(Vec<T>, ArrayDim, NiftiHeader, ReadReport), NiftiIoError> {
+
+    let policy = opts.cast_policy;
+    let mut report = ReadReport::default();
+
+    // single file niftis are converted straight from the file into the output
+    if let Some((data, dims, header)) = stream_real::<T>(file.as_ref(), policy, &mut report)? {
+        return Ok((data, dims, header, report));
+    }
 
-    let nii = nifti::ReaderOptions::new().read_file(file.as_ref()).expect("failed to read nifti file");
+    let nii = nifti::ReaderOptions::new().read_file(file.as_ref())?;
     let nii_header = nii.header().clone();
     let volume = nii.into_volume();
 
@@ -82,40 +462,71 @@ pub fn read_nifti<T:ToPrimitive + NumCast + 'static + Pod>(file:impl AsRef<Path>
     let dims = ArrayDim::from_shape(&dims);
 
     let data:Vec<T> = match volume.data_type() {
-        NiftiType::Uint8 => cast_data::<u8, T>(volume),
-        NiftiType::Int16 => cast_data::<i16, T>(volume),
-        NiftiType::Int32 => cast_data::<i32, T>(volume),
-        NiftiType::Float32 => cast_data::<f32, T>(volume),
-        NiftiType::Float64 => cast_data::<f64, T>(volume),
-        NiftiType::Int8 => cast_data::<i8, T>(volume),
-        NiftiType::Uint16 => cast_data::<u16, T>(volume),
-        NiftiType::Uint32 => cast_data::<u32, T>(volume),
-        NiftiType::Int64 => cast_data::<i64, T>(volume),
-        NiftiType::Uint64 => cast_data::<u64, T>(volume),
+        NiftiType::Uint8 => cast_data::<u8, T>(volume, policy)?,
+        NiftiType::Int16 => cast_data::<i16, T>(volume, policy)?,
+        NiftiType::Int32 => cast_data::<i32, T>(volume, policy)?,
+        NiftiType::Float32 => cast_data::<f32, T>(volume, policy)?,
+        NiftiType::Float64 => cast_data::<f64, T>(volume, policy)?,
+        NiftiType::Int8 => cast_data::<i8, T>(volume, policy)?,
+        NiftiType::Uint16 => cast_data::<u16, T>(volume, policy)?,
+        NiftiType::Uint32 => cast_data::<u32, T>(volume, policy)?,
+        NiftiType::Int64 => cast_data::<i64, T>(volume, policy)?,
+        NiftiType::Uint64 => cast_data::<u64, T>(volume, policy)?,
         NiftiType::Complex64 => {
-            println!("WARNING: reading only real component from Complex32: {}",file.as_ref().display());
-            extract_real(cast_complex_data::<f32, T>(volume))
+            report.warn(ReadEvent::DiscardedImaginary{path: file.as_ref().to_path_buf(), dtype: "Complex32"});
+            extract_real(cast_complex_data::<f32, T>(volume, policy)?)
         } ,
         NiftiType::Complex128 => {
-            println!("WARNING: reading only real component from Complex64: {}",file.as_ref().display());
-            extract_real(cast_complex_data::<f64, T>(volume))
+            report.warn(ReadEvent::DiscardedImaginary{path: file.as_ref().to_path_buf(), dtype: "Complex64"});
+            extract_real(cast_complex_data::<f64, T>(volume, policy)?)
         } ,
-        NiftiType::Rgba32 => panic!("Rgba32 not supported for now."),
-        NiftiType::Float128 => panic!("Float128 not supported."),
-        NiftiType::Rgb24 => panic!("Rgb24 not supported for now."),
-        NiftiType::Complex256 => panic!("Complex256 not supported."),
+        t => return Err(NiftiIoError::Unsupported(format!("{:?} data in {}", t, file.as_ref().display()))),
     };
 
-    (data,dims,nii_header)
+    Ok((data,dims,nii_header,report))
+
+}
+
+/// read the header of a nifti file without reading the volume, returning the array dimensions
+/// given by the header
+pub fn read_nifti_header(file:impl AsRef<Path>) -> Result<(ArrayDim, NiftiHeader), NiftiIoError> {
+    let header = NiftiHeader::from_file(file.as_ref())?;
+    Ok((header_dims(&header), header))
+}
 
+/// the array dimensions given by a nifti header
+fn header_dims(header:&NiftiHeader) -> ArrayDim {
+    let n = (header.dim[0] as usize).clamp(1, 7);
+    let shape:Vec<usize> = header.dim[1..=n].iter().map(|&d| (d as usize).max(1)).collect();
+    ArrayDim::from_shape(&shape)
 }
 
 /// read data from a nifti file assumed to be storing complex data. If the data is real, then the imaginary
 /// component is set to 0. The returns the data as a vec, an array dimension helper type, and the
 /// nifti header
-pub fn read_nifti_complex<T:ToPrimitive + Zero + NumCast + 'static + Pod>(file:impl AsRef<Path>) -> (Vec<Complex<T>>, ArrayDim, NiftiHeader) {
+pub fn read_nifti_complex<T:ToPrimitive + Zero + NumCast + Bounded + 'static + Pod + Send + Sync>(file:impl AsRef<Path>) -> (Vec<Complex<T>>, ArrayDim, NiftiHeader) {
+    try_read_nifti_complex(file).expect("failed to read nifti file")
+}
+
+/// read data from a nifti file as complex values as with read_nifti_complex, returning an error
+/// instead of panicking if the file cannot be read, has an unsupported data type or holds a value
+/// that can't be cast to T
+pub fn try_read_nifti_complex<T:ToPrimitive + Zero + NumCast + Bounded + 'static + Pod + Send + Sync>(file:impl AsRef<Path>) -> Result<(Vec<Complex<T>>, ArrayDim, NiftiHeader), NiftiIoError> {
+    try_read_nifti_complex_with_options(file, &NiftiReadOptions::default()).map(|(data, dims, h, _)| (data, dims, h))
+}
+
+/// read data from a nifti file as complex values as with try_read_nifti_complex, casting values to
+/// T as set by the options. Complex reads are never lossy, so the report is always clean
+pub fn try_read_nifti_complex_with_options<T:ToPrimitive + Zero + NumCast + Bounded + 'static + Pod + Send + Sync>(file:impl AsRef<Path>, opts:&NiftiReadOptions) -> Result<(Vec<Complex<T>>, ArrayDim, NiftiHeader, ReadReport), NiftiIoError> {
+
+    let policy = opts.cast_policy;
 
-    let nii = nifti::ReaderOptions::new().read_file(file.as_ref()).expect("failed to read nifti file");
+    // single file niftis are converted straight from the file into the output
+    if let Some((data, dims, header)) = stream_complex::<T>(file.as_ref(), policy)? {
+        return Ok((data, dims, header, ReadReport::default()));
+    }
+
+    let nii = nifti::ReaderOptions::new().read_file(file.as_ref())?;
     let nii_header = nii.header().clone();
     let volume = nii.into_volume();
 
@@ -123,24 +534,162 @@ pub fn read_nifti_complex<T:ToPrimitive + Zero + NumCast + 'static + Pod>(file:i
     let dims = ArrayDim::from_shape(dims.as_slice());
 
     let data:Vec<Complex<T>> = match volume.data_type() {
-        NiftiType::Uint8 => convert_real(cast_data::<u8, T>(volume)),
-        NiftiType::Int16 => convert_real(cast_data::<i16, T>(volume)),
-        NiftiType::Int32 => convert_real(cast_data::<i32, T>(volume)),
-        NiftiType::Float32 => convert_real(cast_data::<f32, T>(volume)),
-        NiftiType::Float64 => convert_real(cast_data::<f64, T>(volume)),
-        NiftiType::Int8 => convert_real(cast_data::<i8, T>(volume)),
-        NiftiType::Uint16 => convert_real(cast_data::<u16, T>(volume)),
-        NiftiType::Uint32 => convert_real(cast_data::<u32, T>(volume)),
-        NiftiType::Int64 => convert_real(cast_data::<i64, T>(volume)),
-        NiftiType::Uint64 => convert_real(cast_data::<u64, T>(volume)),
-        NiftiType::Complex64 => cast_complex_data::<f32, T>(volume),
-        NiftiType::Complex128 => cast_complex_data::<f64, T>(volume),
-        NiftiType::Rgba32 => panic!("Rgba32 not supported for now."),
-        NiftiType::Float128 => panic!("Float128 not supported."),
-        NiftiType::Rgb24 => panic!("Rgb24 not supported for now."),
-        NiftiType::Complex256 => panic!("Complex256 not supported."),
+        NiftiType::Uint8 => convert_real(cast_data::<u8, T>(volume, policy)?),
+        NiftiType::Int16 => convert_real(cast_data::<i16, T>(volume, policy)?),
+        NiftiType::Int32 => convert_real(cast_data::<i32, T>(volume, policy)?),
+        NiftiType::Float32 => convert_real(cast_data::<f32, T>(volume, policy)?),
+        NiftiType::Float64 => convert_real(cast_data::<f64, T>(volume, policy)?),
+        NiftiType::Int8 => convert_real(cast_data::<i8, T>(volume, policy)?),
+        NiftiType::Uint16 => convert_real(cast_data::<u16, T>(volume, policy)?),
+        NiftiType::Uint32 => convert_real(cast_data::<u32, T>(volume, policy)?),
+        NiftiType::Int64 => convert_real(cast_data::<i64, T>(volume, policy)?),
+        NiftiType::Uint64 => convert_real(cast_data::<u64, T>(volume, policy)?),
+        NiftiType::Complex64 => cast_complex_data::<f32, T>(volume, policy)?,
+        NiftiType::Complex128 => cast_complex_data::<f64, T>(volume, policy)?,
+        t => return Err(NiftiIoError::Unsupported(format!("{:?} data in {}", t, file.as_ref().display()))),
     };
-    (data,dims,nii_header)
+    Ok((data,dims,nii_header,ReadReport::default()))
+}
+
+/// errors that can occur when reading or writing nifti files
+#[derive(Debug)]
+pub enum NiftiIoError {
+    /// the output file already exists and overwriting was not requested
+    FileExists(PathBuf),
+    /// the parent directory of the output file does not exist
+    ParentDirNotFound(PathBuf),
+    IO(std::io::Error),
+    Nifti(NiftiError),
+    /// the file holds a data type that can't be read
+    Unsupported(String),
+    /// a stored value can't be cast to the requested type under the cast policy. The address is
+    /// the linear index of the element holding the value
+    Cast{addr: usize, value: f64, target: &'static str},
+    /// a nifti dim (numbered from 1) is larger than the header can hold. Collapsed is set for the
+    /// 4th dim when it holds the product of the array dims above 3
+    DimTooLarge{dim: usize, size: usize, collapsed: bool},
+    /// the number of elements written or read doesn't match the dims
+    InconsistentArraySize{expected: usize, actual: usize},
+}
+
+impl Display for NiftiIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NiftiIoError::FileExists(p) => write!(f, "file already exists: {}", p.display()),
+            NiftiIoError::ParentDirNotFound(p) => write!(f, "parent directory not found: {}", p.display()),
+            NiftiIoError::IO(e) => write!(f, "io error: {}", e),
+            NiftiIoError::Nifti(e) => write!(f, "nifti error: {}", e),
+            NiftiIoError::Unsupported(msg) => write!(f, "unsupported nifti: {}", msg),
+            NiftiIoError::Cast{addr, value, target} => write!(f, "value {} at address {} can't be cast to {}", value, addr, target),
+            NiftiIoError::DimTooLarge{dim, size, collapsed} => {
+                write!(f, "dim {} of size {} exceeds the nifti-1 limit of {}", dim, size, i16::MAX)?;
+                if *collapsed {
+                    write!(f, " after collapsing the dims above 3")?;
+                }
+                write!(f, ". Write the array as a series of files")
+            },
+            NiftiIoError::InconsistentArraySize{expected, actual} => write!(f, "expected {} elements, got {}", expected, actual),
+        }
+    }
+}
+
+impl std::error::Error for NiftiIoError {}
+
+impl From<std::io::Error> for NiftiIoError {
+    fn from(err: std::io::Error) -> Self {
+        NiftiIoError::IO(err)
+    }
+}
+
+impl From<NiftiError> for NiftiIoError {
+    fn from(err: NiftiError) -> Self {
+        NiftiIoError::Nifti(err)
+    }
+}
+
+/// how stored values that can't be represented in the requested type are handled when reading
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CastPolicy {
+    /// a value outside the range of the requested type is an error. This includes NaN and infinite
+    /// values read as an integer type
+    #[default]
+    Strict,
+    /// values are clamped to the range of the requested type, with NaN read as zero. Float types
+    /// keep NaN and infinite values as they are
+    Saturate,
+    /// NaN and infinite values are read as zero. Any other value outside the range of the
+    /// requested type is an error, as with Strict
+    NanToZero,
+}
+
+/// options controlling how nifti files are read
+#[derive(Debug, Clone, Default)]
+pub struct NiftiReadOptions {
+    /// how values are cast to the requested type
+    pub cast_policy: CastPolicy,
+}
+
+impl NiftiReadOptions {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cast_policy(mut self, cast_policy: CastPolicy) -> Self {
+        self.cast_policy = cast_policy;
+        self
+    }
+
+}
+
+/// options controlling how nifti files are written
+#[derive(Debug, Clone)]
+pub struct NiftiWriteOptions {
+    /// clobber an existing file at the output path. If false, an error is returned instead
+    pub overwrite: bool,
+    /// create any missing parent directories of the output path
+    pub create_dirs: bool,
+}
+
+impl Default for NiftiWriteOptions {
+    fn default() -> Self {
+        NiftiWriteOptions {
+            overwrite: true,
+            create_dirs: false,
+        }
+    }
+}
+
+impl NiftiWriteOptions {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    pub fn create_dirs(mut self, create_dirs: bool) -> Self {
+        self.create_dirs = create_dirs;
+        self
+    }
+
+}
+
+/// resolves the output path for a nifti file. Explicit .nii and .nii.gz extensions are respected.
+/// Anything else gets .nii appended, so dots in the file name are never treated as an extension
+pub fn nifti_output_path(file: impl AsRef<Path>) -> PathBuf {
+    let file = file.as_ref();
+    let name = file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    if name.ends_with(".nii") || name.ends_with(".nii.gz") {
+        file.to_path_buf()
+    } else {
+        let mut s = file.as_os_str().to_os_string();
+        s.push(".nii");
+        PathBuf::from(s)
+    }
 }
 
 /// write a nifti file from a raw data array and a set of dimensions. If the number of dimensions
@@ -148,16 +697,7 @@ pub fn read_nifti_complex<T:ToPrimitive + Zero + NumCast + 'static + Pod>(file:i
 pub fn write_nifti<T>(file: impl AsRef<Path>, array:&[T], dims:ArrayDim)
 where T:Sized + DataElement + Pod
 {
-    assert_eq!(dims.numel(), array.len(), "data buffer and array dims must be consistent");
-    // collapse any dims above 3 into the 4th dim
-    let dim4:usize = dims.shape()[3..].iter().product();
-    let arr = if dim4 > 1 {
-        ndarray::Array::from_shape_vec([dims.size(0),dims.size(1),dims.size(2),dim4].as_slice().f(), array.to_vec()).unwrap()
-    }else {
-        ndarray::Array::from_shape_vec([dims.size(0),dims.size(1),dims.size(2)].as_slice().f(), array.to_vec()).unwrap()
-    };
-    let writer = nifti::writer::WriterOptions::new(file.as_ref().with_extension("nii"));
-    writer.write_nifti(&arr).expect("failed to write nifti");
+    write_nifti_with_options(file, array, dims, None, &NiftiWriteOptions::default()).expect("failed to write nifti");
 }
 
 /// write a nifti file from a raw data array and a set of dimensions. If the number of dimensions
@@ -166,41 +706,421 @@ where T:Sized + DataElement + Pod
 pub fn write_nifti_with_header<T>(file: impl AsRef<Path>, array:&[T], dims:ArrayDim, ref_header:&NiftiHeader)
 where T:Sized + DataElement + Pod
 {
-    assert_eq!(dims.numel(), array.len(), "data buffer and array dims must be consistent");
-    // collapse any dims above 3 into the 4th dim
+    write_nifti_with_options(file, array, dims, Some(ref_header), &NiftiWriteOptions::default()).expect("failed to write nifti");
+}
+
+/// write a nifti file with an optional reference header and write options, returning the path
+/// actually written. A .nii.gz extension results in a compressed file. The data is streamed to the
+/// file directly from the array without an intermediate copy
+pub fn write_nifti_with_options<T>(file: impl AsRef<Path>, array:&[T], dims:ArrayDim, ref_header:Option<&NiftiHeader>, opts:&NiftiWriteOptions) -> Result<PathBuf, NiftiIoError>
+where T:Sized + DataElement + Pod
+{
+    if dims.numel() != array.len() {
+        return Err(NiftiIoError::InconsistentArraySize{expected: dims.numel(), actual: array.len()});
+    }
+    let mut w = NiftiStreamWriter::create(file, dims, ref_header, opts)?;
+    w.write_chunk(array)?;
+    w.finish()
+}
+
+/// the type real data is stored as by write_nifti_scaled. Integer types are scaled to their full
+/// range with scl_slope and scl_inter
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NiftiDtype {
+    #[default]
+    Float32,
+    Int16,
+    Uint16,
+}
+
+/// the nifti scl_slope and scl_inter that map an integer range of [0, t_max] (or [-t_max, t_max]
+/// for signed types) onto the range of the data
+fn int_scaling(x:&[f32], signed:bool, t_max:f32) -> (f32, f32) {
+    let (lo, hi) = x.iter().filter(|x| x.is_finite())
+        .fold((0f32, 0f32), |(lo, hi), &x| (lo.min(x), hi.max(x)));
+    let (range, inter) = if signed { (lo.abs().max(hi), 0.) } else { (hi - lo, lo) };
+    let slope = if range > 0. { range / t_max } else { 1. };
+    (slope, inter)
+}
+
+/// writes real data stored as the given type, returning the path actually written. Integer types
+/// get the scl_slope and scl_inter that span the range of the data. Float data is written
+/// unscaled, so the scaling of a reference header read with its data already scaled isn't
+/// applied twice
+pub fn write_nifti_scaled(file: impl AsRef<Path>, x:&[f32], dims:ArrayDim, ref_header:Option<&NiftiHeader>, dtype:NiftiDtype, opts:&NiftiWriteOptions) -> Result<PathBuf, NiftiIoError> {
+    let header = |(slope, inter):(f32, f32)| {
+        let mut h = ref_header.cloned().unwrap_or_default();
+        h.scl_slope = slope;
+        h.scl_inter = inter;
+        h
+    };
+    match dtype {
+        NiftiDtype::Float32 => write_nifti_with_options(file, x, dims, Some(&header((1., 0.))), opts),
+        NiftiDtype::Int16 => {
+            let (slope, inter) = int_scaling(x, true, i16::MAX as f32);
+            let stored:Vec<i16> = x.iter().map(|x| ((x - inter) / slope).round() as i16).collect();
+            write_nifti_with_options(file, &stored, dims, Some(&header((slope, inter))), opts)
+        }
+        NiftiDtype::Uint16 => {
+            let (slope, inter) = int_scaling(x, false, u16::MAX as f32);
+            let stored:Vec<u16> = x.iter().map(|x| ((x - inter) / slope).round() as u16).collect();
+            write_nifti_with_options(file, &stored, dims, Some(&header((slope, inter))), opts)
+        }
+    }
+}
+
+/// number of bytes handed to the writer at a time
+const WRITE_CHUNK_BYTES:usize = 1 << 22;
+
+/// the destination of the header and voxel data, compressed for .nii.gz files
+enum NiftiSink {
+    Raw(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl Write for NiftiSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            NiftiSink::Raw(w) => w.write(buf),
+            NiftiSink::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            NiftiSink::Raw(w) => w.flush(),
+            NiftiSink::Gzip(w) => w.flush(),
+        }
+    }
+}
+
+/// writes a single file nifti incrementally for arrays that are too large to hold in memory. The
+/// header is written on creation, followed by any number of sequential chunks totalling the number
+/// of elements in the array. The data is written in native byte order, as is the header
+pub struct NiftiStreamWriter<T:DataElement + Pod> {
+    out: PathBuf,
+    sink: NiftiSink,
+    expected: usize,
+    written: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T:DataElement + Pod> NiftiStreamWriter<T> {
+
+    /// writes the header for an array of the given dimensions, taken from the reference header as
+    /// with write_nifti_with_options, and prepares for the data chunks
+    pub fn create(file: impl AsRef<Path>, dims: ArrayDim, ref_header: Option<&NiftiHeader>, opts: &NiftiWriteOptions) -> Result<NiftiStreamWriter<T>, NiftiIoError> {
+        let out = nifti_output_path(file);
+
+        if out.exists() && !opts.overwrite {
+            return Err(NiftiIoError::FileExists(out));
+        }
+
+        if let Some(parent) = out.parent().filter(|p| !p.as_os_str().is_empty()) {
+            if !parent.is_dir() {
+                if opts.create_dirs {
+                    std::fs::create_dir_all(parent)?;
+                } else {
+                    return Err(NiftiIoError::ParentDirNotFound(parent.to_path_buf()));
+                }
+            }
+        }
+
+        let header = header_bytes(&output_header::<T>(&nifti_shape(&dims)?, ref_header));
+        let f = BufWriter::new(File::create(&out)?);
+        let mut sink = if out.to_string_lossy().ends_with(".gz") {
+            NiftiSink::Gzip(GzEncoder::new(f, Compression::fast()))
+        } else {
+            NiftiSink::Raw(f)
+        };
+        sink.write_all(&header)?;
+        Ok(NiftiStreamWriter {
+            out,
+            sink,
+            expected: dims.numel(),
+            written: 0,
+            _marker: PhantomData,
+        })
+    }
+
+    /// appends the next chunk of elements. Writing beyond the number of elements declared by the
+    /// header is an error
+    pub fn write_chunk(&mut self, chunk: &[T]) -> Result<(), NiftiIoError> {
+        if self.written + chunk.len() > self.expected {
+            return Err(NiftiIoError::InconsistentArraySize{expected: self.expected, actual: self.written + chunk.len()});
+        }
+        for bytes in bytemuck::cast_slice::<T, u8>(chunk).chunks(WRITE_CHUNK_BYTES) {
+            self.sink.write_all(bytes)?;
+        }
+        self.written += chunk.len();
+        Ok(())
+    }
+
+    /// number of elements still expected before the writer can be finished
+    pub fn remaining(&self) -> usize {
+        self.expected - self.written
+    }
+
+    /// flushes the file, returning the path written or an error if fewer elements were written
+    /// than declared
+    pub fn finish(self) -> Result<PathBuf, NiftiIoError> {
+        if self.written != self.expected {
+            return Err(NiftiIoError::InconsistentArraySize{expected: self.expected, actual: self.written});
+        }
+        match self.sink {
+            NiftiSink::Raw(mut w) => w.flush()?,
+            NiftiSink::Gzip(w) => w.finish()?.flush()?,
+        }
+        Ok(self.out)
+    }
+
+}
+
+/// the nifti dims of an array. Any dims above 3 are collapsed into the 4th, which is dropped if
+/// it is a singleton
+fn nifti_shape(dims:&ArrayDim) -> Result<Vec<usize>, NiftiIoError> {
+    let mut shape = dims.shape()[..3].to_vec();
     let dim4:usize = dims.shape()[3..].iter().product();
-    let arr = if dim4 > 1 {
-        ndarray::Array::from_shape_vec([dims.size(0),dims.size(1),dims.size(2),dim4].as_slice().f(), array.to_vec()).unwrap()
-    }else {
-        ndarray::Array::from_shape_vec([dims.size(0),dims.size(1),dims.size(2)].as_slice().f(), array.to_vec()).unwrap()
+    if dim4 > 1 {
+        shape.push(dim4);
+    }
+    // the header holds each dim as an i16, so larger dims would wrap into a corrupt header
+    if let Some((i, &size)) = shape.iter().enumerate().find(|&(_, &d)| d > i16::MAX as usize) {
+        return Err(NiftiIoError::DimTooLarge { dim: i + 1, size, collapsed: i == 3 && dims.shape()[4..].iter().any(|&d| d > 1) });
+    }
+    Ok(shape)
+}
+
+/// the header of a single-file nifti-1 holding T data with the given dims. Everything apart from
+/// the dims, data type and data offset is taken from the reference header if supplied
+fn output_header<T:DataElement>(shape:&[usize], ref_header:Option<&NiftiHeader>) -> NiftiHeader {
+    let mut h = ref_header.cloned().unwrap_or_default();
+    h.dim = [1; 8];
+    h.dim[0] = shape.len() as u16;
+    for (d, s) in h.dim[1..].iter_mut().zip(shape) {
+        *d = *s as u16;
+    }
+    h.sizeof_hdr = 348;
+    h.datatype = T::DATA_TYPE as i16;
+    h.bitpix = (T::DATA_TYPE.size_of() * 8) as i16;
+    h.vox_offset = NIFTI_VOX_OFFSET as f32;
+    h.magic = *b"n+1\0";
+    h
+}
+
+/// size of the nifti-1 header and the empty extension flags that follow it
+const NIFTI_VOX_OFFSET:usize = 352;
+
+/// serializes a nifti-1 header in native byte order, followed by the 4 extension bytes marking
+/// no extensions
+fn header_bytes(h:&NiftiHeader) -> Vec<u8> {
+    let mut b = Vec::with_capacity(NIFTI_VOX_OFFSET);
+    // fixed-length text fields are truncated or zero-padded
+    let text = |b:&mut Vec<u8>, s:&[u8], len:usize| {
+        let n = s.len().min(len);
+        b.extend_from_slice(&s[..n]);
+        b.resize(b.len() + len - n, 0);
     };
-    let writer = nifti::writer::WriterOptions::new(file.as_ref().with_extension("nii")).reference_header(ref_header);
-    writer.write_nifti(&arr).expect("failed to write nifti");
+    b.extend_from_slice(&h.sizeof_hdr.to_ne_bytes());
+    text(&mut b, &h.data_type[..], 10);
+    text(&mut b, &h.db_name[..], 18);
+    b.extend_from_slice(&h.extents.to_ne_bytes());
+    b.extend_from_slice(&h.session_error.to_ne_bytes());
+    b.push(h.regular);
+    b.push(h.dim_info);
+    h.dim.iter().for_each(|d| b.extend_from_slice(&d.to_ne_bytes()));
+    for p in [h.intent_p1, h.intent_p2, h.intent_p3] {
+        b.extend_from_slice(&p.to_ne_bytes());
+    }
+    for v in [h.intent_code, h.datatype, h.bitpix, h.slice_start] {
+        b.extend_from_slice(&v.to_ne_bytes());
+    }
+    h.pixdim.iter().for_each(|p| b.extend_from_slice(&p.to_ne_bytes()));
+    for v in [h.vox_offset, h.scl_slope, h.scl_inter] {
+        b.extend_from_slice(&v.to_ne_bytes());
+    }
+    b.extend_from_slice(&h.slice_end.to_ne_bytes());
+    b.push(h.slice_code);
+    b.push(h.xyzt_units);
+    for v in [h.cal_max, h.cal_min, h.slice_duration, h.toffset] {
+        b.extend_from_slice(&v.to_ne_bytes());
+    }
+    b.extend_from_slice(&h.glmax.to_ne_bytes());
+    b.extend_from_slice(&h.glmin.to_ne_bytes());
+    text(&mut b, &h.descrip[..], 80);
+    text(&mut b, &h.aux_file[..], 24);
+    b.extend_from_slice(&h.qform_code.to_ne_bytes());
+    b.extend_from_slice(&h.sform_code.to_ne_bytes());
+    for v in [h.quatern_b, h.quatern_c, h.quatern_d, h.quatern_x, h.quatern_y, h.quatern_z] {
+        b.extend_from_slice(&v.to_ne_bytes());
+    }
+    for row in [h.srow_x, h.srow_y, h.srow_z] {
+        row.iter().for_each(|v| b.extend_from_slice(&v.to_ne_bytes()));
+    }
+    text(&mut b, &h.intent_name[..], 16);
+    text(&mut b, &h.magic[..], 4);
+    b.resize(NIFTI_VOX_OFFSET, 0);
+    b
 }
 
-fn cast_data<N, T>(volume:InMemNiftiVolume)
-                   -> Vec<T>
+/// returns the voxel-to-world (RAS) affine of a nifti header. The sform is used when present,
+/// then the qform, otherwise the affine is built from the voxel sizes alone
+pub fn nifti_affine(header:&NiftiHeader) -> [[f64;4];4] {
+    if header.sform_code > 0 {
+        let rows = [header.srow_x, header.srow_y, header.srow_z];
+        let mut affine = [[0.;4];4];
+        for (a, r) in affine.iter_mut().zip(rows.iter()) {
+            for (a, r) in a.iter_mut().zip(r.iter()) {
+                *a = *r as f64;
+            }
+        }
+        affine[3][3] = 1.;
+        affine
+    } else if header.qform_code > 0 {
+        nifti_qform_affine(header)
+    } else {
+        let p = header.pixdim;
+        [
+            [p[1] as f64, 0., 0., 0.],
+            [0., p[2] as f64, 0., 0.],
+            [0., 0., p[3] as f64, 0.],
+            [0., 0., 0., 1.],
+        ]
+    }
+}
+
+/// returns the voxel-to-world (RAS) affine of the qform of a nifti header, built from the
+/// quaternion, voxel sizes and offsets as described in nifti1.h (method 2). The qform code isn't
+/// checked
+pub fn nifti_qform_affine(header:&NiftiHeader) -> [[f64;4];4] {
+    let (mut b, mut c, mut d) = (header.quatern_b as f64, header.quatern_c as f64, header.quatern_d as f64);
+    let mut a = 1. - (b * b + c * c + d * d);
+    if a < 1e-7 {
+        // a is 0 and the rotation is 180 degrees, so (b, c, d) is renormalized
+        let n = (b * b + c * c + d * d).sqrt();
+        (b, c, d) = (b / n, c / n, d / n);
+        a = 0.;
+    } else {
+        a = a.sqrt();
+    }
+    let p = header.pixdim;
+    let size = |x:f32| if x > 0. { x as f64 } else { 1. };
+    let qfac = if p[0] < 0. { -1. } else { 1. };
+    let (dx, dy, dz) = (size(p[1]), size(p[2]), size(p[3]) * qfac);
+    [
+        [(a * a + b * b - c * c - d * d) * dx, 2. * (b * c - a * d) * dy, 2. * (b * d + a * c) * dz, header.quatern_x as f64],
+        [2. * (b * c + a * d) * dx, (a * a + c * c - b * b - d * d) * dy, 2. * (c * d - a * b) * dz, header.quatern_y as f64],
+        [2. * (b * d - a * c) * dx, 2. * (c * d + a * b) * dy, (a * a + d * d - c * c - b * b) * dz, header.quatern_z as f64],
+        [0., 0., 0., 1.],
+    ]
+}
+
+/// sets the sform of a nifti header from a voxel-to-world (RAS) affine, along with the voxel
+/// sizes implied by the lengths of the affine columns. An existing sform code is kept, otherwise
+/// the sform is marked as scanner anatomical coordinates
+pub fn set_nifti_affine(header:&mut NiftiHeader, affine:[[f64;4];4]) {
+    header.srow_x = [affine[0][0] as f32, affine[0][1] as f32, affine[0][2] as f32, affine[0][3] as f32];
+    header.srow_y = [affine[1][0] as f32, affine[1][1] as f32, affine[1][2] as f32, affine[1][3] as f32];
+    header.srow_z = [affine[2][0] as f32, affine[2][1] as f32, affine[2][2] as f32, affine[2][3] as f32];
+    if header.sform_code <= 0 {
+        header.sform_code = 1;
+    }
+    for col in 0..3 {
+        let n = (0..3).map(|row| affine[row][col] * affine[row][col]).sum::<f64>().sqrt();
+        header.pixdim[col + 1] = n as f32;
+    }
+}
+
+fn cast_data<N, T>(volume:InMemNiftiVolume, policy:CastPolicy)
+                   -> Result<Vec<T>, NiftiIoError>
 where
-    N: ToPrimitive +  DataElement + 'static,
-    T: NumCast + 'static,
+    N: ToPrimitive +  DataElement + 'static + Copy + Sync,
+    T: NumCast + Bounded + 'static + Send,
 {
     let typed = volume
         .into_nifti_typed_data::<N>()
         .expect("Failed to convert to typed volume");
 
-    typed
-        .into_iter()
-        .map(|x| NumCast::from(x).expect("Failed to cast value"))
-        .collect()
+    // data already of the target type is moved out as is, unless non-finite values are zeroed
+    let typed:Box<dyn Any> = Box::new(typed);
+    let typed = match typed.downcast::<Vec<T>>() {
+        Ok(same) if policy != CastPolicy::NanToZero => return Ok(*same),
+        Ok(same) => Box::new(*same) as Box<dyn Any>,
+        Err(other) => other,
+    }.downcast::<Vec<N>>().expect("typed data is a Vec<N>");
+
+    cast_elements(&typed, typed.len() >= PARALLEL_CAST_MIN, policy)
+}
+
+/// arrays with at least this many elements are converted in parallel
+const PARALLEL_CAST_MIN:usize = 1 << 20;
+
+/// casts a single value under the cast policy, giving the value as f64 if it can't be cast
+fn cast_value<N:ToPrimitive + Copy, T:NumCast + Bounded>(x:N, policy:CastPolicy) -> Result<T, f64> {
+    let value = || x.to_f64().unwrap_or(f64::NAN);
+    let zero = || -> T { NumCast::from(0).expect("zero can be cast to any numeric type") };
+    match policy {
+        CastPolicy::Strict => NumCast::from(x).ok_or_else(value),
+        CastPolicy::NanToZero if !value().is_finite() => Ok(zero()),
+        CastPolicy::NanToZero => NumCast::from(x).ok_or_else(value),
+        CastPolicy::Saturate => Ok(NumCast::from(x).unwrap_or_else(|| match value() {
+            v if v.is_nan() => zero(),
+            v if v > 0. => T::max_value(),
+            _ => T::min_value(),
+        })),
+    }
+}
+
+/// the error for a value at addr that can't be cast to T
+fn cast_err<T>(addr:usize, value:f64) -> NiftiIoError {
+    NiftiIoError::Cast { addr, value, target: std::any::type_name::<T>() }
 }
 
-fn cast_complex_data<N, T>(volume: InMemNiftiVolume) -> Vec<Complex<T>>
+/// casts each element under the cast policy, keeping the order
+fn cast_elements<N:ToPrimitive + Copy + Sync, T:NumCast + Bounded + Send>(x:&[N], parallel:bool, policy:CastPolicy) -> Result<Vec<T>, NiftiIoError> {
+    let cast = |(i, x):(usize, &N)| cast_value(*x, policy).map_err(|v| cast_err::<T>(i, v));
+    if parallel {
+        x.par_iter().enumerate().map(cast).collect()
+    } else {
+        x.iter().enumerate().map(cast).collect()
+    }
+}
+
+/// casts interleaved real-imaginary pairs of N stored as bytes under the cast policy, keeping the
+/// order
+fn cast_pairs<N:ToPrimitive + Pod, T:NumCast + Bounded + Send>(raw:&[u8], parallel:bool, policy:CastPolicy) -> Result<Vec<Complex<T>>, NiftiIoError> {
+    let n = size_of::<N>();
+    let cast = |(i, pair):(usize, &[u8])| -> Result<Complex<T>, NiftiIoError> {
+        let re:N = bytemuck::pod_read_unaligned(&pair[..n]);
+        let im:N = bytemuck::pod_read_unaligned(&pair[n..]);
+        let re_t = cast_value(re, policy).map_err(|v| cast_err::<T>(i, v))?;
+        let im_t = cast_value(im, policy).map_err(|v| cast_err::<T>(i, v))?;
+        Ok(Complex::new(re_t, im_t))
+    };
+    if parallel {
+        raw.par_chunks_exact(2 * n).enumerate().map(cast).collect()
+    } else {
+        raw.chunks_exact(2 * n).enumerate().map(cast).collect()
+    }
+}
+
+/// gives each element a zero imaginary part, keeping the order
+fn to_complex<T:Zero + Copy + Send + Sync>(x:&[T], parallel:bool) -> Vec<Complex<T>> {
+    if parallel {
+        let mut out = Vec::with_capacity(x.len());
+        x.par_iter().map(|&x| Complex::new(x, T::zero())).collect_into_vec(&mut out);
+        out
+    } else {
+        x.iter().map(|&x| Complex::new(x, T::zero())).collect()
+    }
+}
+
+fn cast_complex_data<N, T>(volume: InMemNiftiVolume, policy:CastPolicy) -> Result<Vec<Complex<T>>, NiftiIoError>
 where
-    N: DataElement + ToPrimitive + Zero + 'static,
-    T: NumCast + 'static + Copy + Pod,
+    N: DataElement + ToPrimitive + Zero + 'static + Pod,
+    T: NumCast + Bounded + 'static + Copy + Pod + Send,
 {
 
-
     match volume.data_type() {
         NiftiType::Complex64 => (),
         NiftiType::Complex128 => (),
@@ -208,30 +1128,264 @@ where
         _=> assert!(false,"volume is not complex"),
     }
 
-    // 1. Interpret raw buffer as real-valued N data
-    // let raw = volume
-    //     .into_nifti_typed_data::<N>()
-    //     .expect("Failed to convert volume to raw complex buffer");
-
     let raw = volume.into_raw_data();
-    let raw = bytemuck::cast_slice::<u8, T>(&raw).to_vec();
-
-    // 2. Chunk into real-imag pairs
-    raw.chunks(2)
-        .map(|chunk| {
-            let re = chunk.get(0).copied().unwrap();
-            let im = chunk.get(1).copied().unwrap();
-            let re_t = NumCast::from(re).expect("Failed to cast real part");
-            let im_t = NumCast::from(im).expect("Failed to cast imag part");
-            Complex::new(re_t, im_t)
-        })
-        .collect()
+
+    // components already of the target type are copied straight into place
+    if TypeId::of::<N>() == TypeId::of::<T>() && policy != CastPolicy::NanToZero {
+        let mut out = vec![Complex::<T>::zeroed(); raw.len() / size_of::<Complex<T>>()];
+        let n_bytes = out.len() * size_of::<Complex<T>>();
+        bytemuck::cast_slice_mut::<Complex<T>, u8>(&mut out).copy_from_slice(&raw[..n_bytes]);
+        return Ok(out);
+    }
+
+    // otherwise read real-imag pairs of N and cast each
+    let parallel = raw.len() / (2 * size_of::<N>()) >= PARALLEL_CAST_MIN;
+    cast_pairs::<N, T>(&raw, parallel, policy)
 }
 
-fn convert_real<T:ToPrimitive + Zero>(x:Vec<T>) -> Vec<Complex<T>> {
-    x.into_iter().map(|x| Complex::new(x,T::zero())).collect()
+fn convert_real<T:ToPrimitive + Zero + Copy + Send + Sync>(x:Vec<T>) -> Vec<Complex<T>> {
+    to_complex(&x, x.len() >= PARALLEL_CAST_MIN)
 }
 
 fn extract_real<T:Sized>(x:Vec<Complex<T>>) -> Vec<T> {
     x.into_iter().map(|x| x.re).collect()
+}
+
+/// number of elements converted at a time when streaming voxel data
+const READ_CHUNK:usize = 1 << 20;
+
+/// the voxel data of a single file nifti, positioned at the first voxel
+struct VoxelStream {
+    header: NiftiHeader,
+    dims: ArrayDim,
+    reader: Box<dyn Read>,
+    /// the data is stored in the opposite byte order to this machine
+    swapped: bool,
+}
+
+impl VoxelStream {
+
+    /// opens the voxel data of a nifti for reading without the nifti reader, which holds the raw
+    /// bytes alongside the converted data. Header and image pairs and unknown data types give None
+    /// and are left to the nifti reader
+    fn open(file:&Path) -> Result<Option<VoxelStream>, NiftiIoError> {
+        let header = NiftiHeader::from_file(file)?;
+        if header.data_type().is_err() {
+            return Ok(None);
+        }
+        let mut f = BufReader::new(File::open(file)?);
+        let gz = f.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+        let mut reader:Box<dyn Read> = if gz { Box::new(MultiGzDecoder::new(f)) } else { Box::new(f) };
+        let mut h = [0u8; 348];
+        reader.read_exact(&mut h)?;
+        if &h[344..] != b"n+1\0" {
+            return Ok(None);
+        }
+        let swapped = i32::from_ne_bytes([h[0], h[1], h[2], h[3]]) != 348;
+        let skip = (header.vox_offset as u64).saturating_sub(h.len() as u64);
+        std::io::copy(&mut reader.by_ref().take(skip), &mut std::io::sink())?;
+        let dims = header_dims(&header);
+        Ok(Some(VoxelStream { header, dims, reader, swapped }))
+    }
+
+    /// real data is stored without scaling, so it reads the same as through the nifti reader
+    fn unscaled(&self) -> bool {
+        self.header.scl_slope == 0. || (self.header.scl_slope == 1. && self.header.scl_inter == 0.)
+    }
+
+    /// fills bytes with values of the given size, swapping them to native byte order
+    fn read_into(&mut self, bytes:&mut [u8], size:usize) -> std::io::Result<()> {
+        self.reader.read_exact(bytes)?;
+        if self.swapped && size > 1 {
+            bytes.chunks_exact_mut(size).for_each(|v| v.reverse());
+        }
+        Ok(())
+    }
+
+    /// reads n values of N straight into the output, for data already of the requested type.
+    /// Complex values are read with N as the component type
+    fn read_same<N:Pod, E:Pod>(&mut self, n:usize) -> std::io::Result<Vec<E>> {
+        let mut out = vec![E::zeroed(); n];
+        self.read_into(bytemuck::cast_slice_mut(&mut out), size_of::<N>())?;
+        Ok(out)
+    }
+
+    /// reads n elements, each made of per consecutive values of N, converting each element with
+    /// f, which is given the address of the element. Only a chunk of the stored values is held
+    /// alongside the output
+    fn read_converted<N:Pod + Sync, E:Send>(&mut self, n:usize, per:usize, f:impl Fn(usize, &[N]) -> Result<E, NiftiIoError> + Sync + Send) -> Result<Vec<E>, NiftiIoError> {
+        let parallel = n >= PARALLEL_CAST_MIN;
+        let mut out = Vec::with_capacity(n);
+        let mut stage = vec![N::zeroed(); READ_CHUNK.min(n) * per];
+        while out.len() < n {
+            let start = out.len();
+            let stage = &mut stage[..READ_CHUNK.min(n - start) * per];
+            self.read_into(bytemuck::cast_slice_mut(stage), size_of::<N>())?;
+            if parallel {
+                let converted:Vec<E> = stage.par_chunks_exact(per).enumerate().map(|(i, x)| f(start + i, x)).collect::<Result<_, _>>()?;
+                out.extend(converted);
+            } else {
+                for (i, x) in stage.chunks_exact(per).enumerate() {
+                    out.push(f(start + i, x)?);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// reads n real values of N as T
+    fn read_real<N:ToPrimitive + Pod + Sync, T:NumCast + Bounded + Pod + Send>(&mut self, n:usize, policy:CastPolicy) -> Result<Vec<T>, NiftiIoError> {
+        if TypeId::of::<N>() == TypeId::of::<T>() && policy != CastPolicy::NanToZero {
+            return Ok(self.read_same::<N, T>(n)?);
+        }
+        self.read_converted(n, 1, |i, x:&[N]| cast_value(x[0], policy).map_err(|v| cast_err::<T>(i, v)))
+    }
+
+    /// reads n real values of N as complex values of T with a zero imaginary part
+    fn read_real_as_complex<N:ToPrimitive + Pod + Sync, T:NumCast + Bounded + Zero + Pod + Send>(&mut self, n:usize, policy:CastPolicy) -> Result<Vec<Complex<T>>, NiftiIoError> {
+        self.read_converted(n, 1, |i, x:&[N]| {
+            let re = cast_value(x[0], policy).map_err(|v| cast_err::<T>(i, v))?;
+            Ok(Complex::new(re, T::zero()))
+        })
+    }
+
+    /// reads n real-imaginary pairs of N as complex values of T
+    fn read_complex<N:ToPrimitive + Pod + Sync, T:NumCast + Bounded + Pod + Send>(&mut self, n:usize, policy:CastPolicy) -> Result<Vec<Complex<T>>, NiftiIoError> {
+        if TypeId::of::<N>() == TypeId::of::<T>() && policy != CastPolicy::NanToZero {
+            return Ok(self.read_same::<N, Complex<T>>(n)?);
+        }
+        self.read_converted(n, 2, |i, p:&[N]| {
+            let re = cast_value(p[0], policy).map_err(|v| cast_err::<T>(i, v))?;
+            let im = cast_value(p[1], policy).map_err(|v| cast_err::<T>(i, v))?;
+            Ok(Complex::new(re, im))
+        })
+    }
+
+    /// reads the real parts of n real-imaginary pairs of N as T
+    fn read_complex_as_real<N:ToPrimitive + Pod + Sync, T:NumCast + Bounded + Send>(&mut self, n:usize, policy:CastPolicy) -> Result<Vec<T>, NiftiIoError> {
+        self.read_converted(n, 2, |i, p:&[N]| cast_value(p[0], policy).map_err(|v| cast_err::<T>(i, v)))
+    }
+}
+
+/// reads a single file nifti as real values in one pass from the file, giving None for files left
+/// to the nifti reader. Scaled real data is also left to the nifti reader, which applies the scaling
+fn stream_real<T:NumCast + Bounded + Pod + Send>(file:&Path, policy:CastPolicy, report:&mut ReadReport) -> Result<Option<(Vec<T>, ArrayDim, NiftiHeader)>, NiftiIoError> {
+    let Some(mut s) = VoxelStream::open(file)? else {
+        return Ok(None);
+    };
+    let n = s.dims.numel();
+    let data_type = s.header.data_type()?;
+    let complex = matches!(data_type, NiftiType::Complex64 | NiftiType::Complex128);
+    if !complex && !s.unscaled() {
+        return Ok(None);
+    }
+    let data = match data_type {
+        NiftiType::Uint8 => s.read_real::<u8, T>(n, policy)?,
+        NiftiType::Int16 => s.read_real::<i16, T>(n, policy)?,
+        NiftiType::Int32 => s.read_real::<i32, T>(n, policy)?,
+        NiftiType::Float32 => s.read_real::<f32, T>(n, policy)?,
+        NiftiType::Float64 => s.read_real::<f64, T>(n, policy)?,
+        NiftiType::Int8 => s.read_real::<i8, T>(n, policy)?,
+        NiftiType::Uint16 => s.read_real::<u16, T>(n, policy)?,
+        NiftiType::Uint32 => s.read_real::<u32, T>(n, policy)?,
+        NiftiType::Int64 => s.read_real::<i64, T>(n, policy)?,
+        NiftiType::Uint64 => s.read_real::<u64, T>(n, policy)?,
+        NiftiType::Complex64 => {
+            report.warn(ReadEvent::DiscardedImaginary{path: file.to_path_buf(), dtype: "Complex32"});
+            s.read_complex_as_real::<f32, T>(n, policy)?
+        },
+        NiftiType::Complex128 => {
+            report.warn(ReadEvent::DiscardedImaginary{path: file.to_path_buf(), dtype: "Complex64"});
+            s.read_complex_as_real::<f64, T>(n, policy)?
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some((data, s.dims, s.header)))
+}
+
+/// reads a single file nifti as complex values in one pass from the file, as with stream_real
+fn stream_complex<T:NumCast + Bounded + Zero + Pod + Send>(file:&Path, policy:CastPolicy) -> Result<Option<(Vec<Complex<T>>, ArrayDim, NiftiHeader)>, NiftiIoError> {
+    let Some(mut s) = VoxelStream::open(file)? else {
+        return Ok(None);
+    };
+    let n = s.dims.numel();
+    let data_type = s.header.data_type()?;
+    let complex = matches!(data_type, NiftiType::Complex64 | NiftiType::Complex128);
+    if !complex && !s.unscaled() {
+        return Ok(None);
+    }
+    let data = match data_type {
+        NiftiType::Uint8 => s.read_real_as_complex::<u8, T>(n, policy)?,
+        NiftiType::Int16 => s.read_real_as_complex::<i16, T>(n, policy)?,
+        NiftiType::Int32 => s.read_real_as_complex::<i32, T>(n, policy)?,
+        NiftiType::Float32 => s.read_real_as_complex::<f32, T>(n, policy)?,
+        NiftiType::Float64 => s.read_real_as_complex::<f64, T>(n, policy)?,
+        NiftiType::Int8 => s.read_real_as_complex::<i8, T>(n, policy)?,
+        NiftiType::Uint16 => s.read_real_as_complex::<u16, T>(n, policy)?,
+        NiftiType::Uint32 => s.read_real_as_complex::<u32, T>(n, policy)?,
+        NiftiType::Int64 => s.read_real_as_complex::<i64, T>(n, policy)?,
+        NiftiType::Uint64 => s.read_real_as_complex::<u64, T>(n, policy)?,
+        NiftiType::Complex64 => s.read_complex::<f32, T>(n, policy)?,
+        NiftiType::Complex128 => s.read_complex::<f64, T>(n, policy)?,
+        _ => return Ok(None),
+    };
+    Ok(Some((data, s.dims, s.header)))
+}
+
+/// reads the voxels of a single file nifti sequentially in their stored type, for volumes too
+/// large to hold in memory. Values are returned as stored, without the scaling of the header
+pub struct NiftiStreamReader<T:DataElement + Pod> {
+    stream: VoxelStream,
+    remaining: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T:DataElement + Pod> NiftiStreamReader<T> {
+
+    /// opens a nifti positioned at its first voxel. The stored type must be T, and header and
+    /// image pairs are not supported
+    pub fn open(file: impl AsRef<Path>) -> Result<NiftiStreamReader<T>, NiftiIoError> {
+        let file = file.as_ref();
+        let stream = VoxelStream::open(file)?
+            .ok_or_else(|| NiftiIoError::Unsupported(format!("{} is not a single file nifti", file.display())))?;
+        let data_type = stream.header.data_type()?;
+        if data_type != T::DATA_TYPE {
+            return Err(NiftiIoError::Unsupported(format!("{:?} data in {}, expected {:?}", data_type, file.display(), T::DATA_TYPE)));
+        }
+        let remaining = stream.dims.numel();
+        Ok(NiftiStreamReader {
+            stream,
+            remaining,
+            _marker: PhantomData,
+        })
+    }
+
+    /// the array dimensions given by the header
+    pub fn dims(&self) -> ArrayDim {
+        self.stream.dims
+    }
+
+    pub fn header(&self) -> &NiftiHeader {
+        &self.stream.header
+    }
+
+    /// number of elements not yet read
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// reads the next n elements. Reading past the last element is an error
+    pub fn read_chunk(&mut self, n: usize) -> Result<Vec<T>, NiftiIoError> {
+        if n > self.remaining {
+            return Err(NiftiIoError::InconsistentArraySize{expected: self.remaining, actual: n});
+        }
+        let mut out = vec![T::zeroed(); n];
+        // complex values are swapped one component at a time
+        let complex = matches!(T::DATA_TYPE, NiftiType::Complex64 | NiftiType::Complex128);
+        let size = if complex { size_of::<T>() / 2 } else { size_of::<T>() };
+        self.stream.read_into(bytemuck::cast_slice_mut(&mut out), size)?;
+        self.remaining -= n;
+        Ok(out)
+    }
+
 }
\ No newline at end of file
@@ -0,0 +1,831 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use bytemuck::Pod;
+use flate2::{Compression, Crc};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use num_complex::{Complex, Complex32, Complex64};
+use crate::{AllocError, ArrayDim, N_DIMS};
+
+#[cfg(test)]
+mod tests {
+    use num_complex::{Complex32, Complex64};
+    use crate::ArrayDim;
+    use crate::io_npy::{read_npy, read_npy_dyn, read_npz, read_npz_dyn, write_npy, write_npz, NpyArray, NpyError, Order};
+
+    #[test]
+    fn test_round_trip() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+        for order in [Order::C, Order::Fortran] {
+            write_npy("test_npy_round_trip.npy",&x,dims,order).unwrap();
+            let (y,y_dims) = read_npy::<f32>("test_npy_round_trip.npy").unwrap();
+            assert_eq!(y,x);
+            assert_eq!(y_dims.shape(),dims.shape());
+        }
+
+        let z:Vec<Complex64> = (0..dims.numel()).map(|i| Complex64::new(i as f64,-1.)).collect();
+        write_npy("test_npy_round_trip.npy",&z,dims,Order::C).unwrap();
+        let (y,_) = read_npy::<Complex64>("test_npy_round_trip.npy").unwrap();
+        assert_eq!(y,z);
+
+        let i:Vec<u16> = (0..dims.numel()).map(|i| i as u16).collect();
+        write_npy("test_npy_round_trip.npy",&i,dims,Order::Fortran).unwrap();
+        let wrong = read_npy::<i16>("test_npy_round_trip.npy");
+        std::fs::remove_file("test_npy_round_trip.npy").unwrap();
+        match wrong {
+            Err(NpyError::DtypeMismatch {expected,found,..}) => {
+                assert_eq!(expected,"<i2");
+                assert_eq!(found,"<u2");
+            }
+            _=> panic!("expected a dtype mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_read_numpy_fixture() {
+        // np.save(f, np.array([[1, 2, 3], [4, 5, 6]], dtype='<i2'))
+        let mut bytes = b"\x93NUMPY\x01\x00\x76\x00".to_vec();
+        bytes.extend_from_slice(b"{'descr': '<i2', 'fortran_order': False, 'shape': (2, 3), }");
+        bytes.extend_from_slice(&[b' ';58]);
+        bytes.push(b'\n');
+        [1i16,2,3,4,5,6].iter().for_each(|x| bytes.extend_from_slice(&x.to_le_bytes()));
+        assert_eq!(bytes.len(),128 + 12);
+        std::fs::write("test_npy_fixture.npy",&bytes).unwrap();
+
+        let (y,dims) = read_npy::<i16>("test_npy_fixture.npy").unwrap();
+        assert_eq!(dims.shape_ns(),&[2,3]);
+        // column-major: [1,4,2,5,3,6]
+        assert_eq!(y,vec![1,4,2,5,3,6]);
+
+        // writing the same array in C order reproduces the numpy file
+        write_npy("test_npy_fixture.npy",&y,dims,Order::C).unwrap();
+        let written = std::fs::read("test_npy_fixture.npy").unwrap();
+        std::fs::remove_file("test_npy_fixture.npy").unwrap();
+        assert_eq!(written,bytes);
+    }
+
+    #[test]
+    fn test_empty_array() {
+        // np.save(f, np.zeros((0, 3), dtype='<f4'))
+        let mut bytes = b"\x93NUMPY\x01\x00\x76\x00".to_vec();
+        let h = "{'descr': '<f4', 'fortran_order': False, 'shape': (0, 3), }";
+        bytes.extend_from_slice(h.as_bytes());
+        bytes.resize(10 + 117,b' ');
+        bytes.push(b'\n');
+        std::fs::write("test_npy_empty.npy",&bytes).unwrap();
+        let r = read_npy::<f32>("test_npy_empty.npy");
+        std::fs::remove_file("test_npy_empty.npy").unwrap();
+        assert!(matches!(r,Err(NpyError::Format{..})));
+    }
+
+    #[test]
+    fn test_big_endian() {
+        let mut bytes = b"\x93NUMPY\x01\x00\x76\x00".to_vec();
+        let h = "{'descr': '>c8', 'fortran_order': True, 'shape': (3,), }";
+        bytes.extend_from_slice(h.as_bytes());
+        bytes.extend(std::iter::repeat(b' ').take(117 - h.len()));
+        bytes.push(b'\n');
+        let x = [Complex32::new(1.,2.),Complex32::new(-3.,4.5),Complex32::new(0.,-1.)];
+        x.iter().for_each(|x| {
+            bytes.extend_from_slice(&x.re.to_be_bytes());
+            bytes.extend_from_slice(&x.im.to_be_bytes());
+        });
+        std::fs::write("test_npy_be.npy",&bytes).unwrap();
+        let (y,dims) = read_npy::<Complex32>("test_npy_be.npy").unwrap();
+        std::fs::remove_file("test_npy_be.npy").unwrap();
+        assert_eq!(y,x.to_vec());
+        assert_eq!(dims.shape_ns(),&[3]);
+    }
+
+    #[test]
+    fn test_npz_zip64() {
+        let dims = ArrayDim::from_shape(&[4,3]);
+        let traj = NpyArray::F32((0..12).map(|i| i as f32 * 0.5).collect());
+        write_npz("test_npz_zip64.npz",&[("traj",&traj,dims)],false).unwrap();
+
+        // rewrite the end of central directory as np.savez does for large archives, with the
+        // saturated fields given by a zip64 record and locator
+        let mut bytes = std::fs::read("test_npz_zip64.npz").unwrap();
+        let end = bytes.split_off(bytes.len() - 22);
+        let cd_size = u32::from_le_bytes(end[12..16].try_into().unwrap()) as u64;
+        let cd_offset = u32::from_le_bytes(end[16..20].try_into().unwrap()) as u64;
+        let record_offset = bytes.len() as u64;
+        bytes.extend_from_slice(&0x06064b50u32.to_le_bytes());
+        bytes.extend_from_slice(&44u64.to_le_bytes());
+        bytes.extend_from_slice(&[45,0,45,0,0,0,0,0,0,0,0,0]);
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.extend_from_slice(&cd_size.to_le_bytes());
+        bytes.extend_from_slice(&cd_offset.to_le_bytes());
+        bytes.extend_from_slice(&0x07064b50u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&record_offset.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&end[..10]);
+        bytes.extend_from_slice(&[0xff; 10]);
+        bytes.extend_from_slice(&end[20..]);
+        std::fs::write("test_npz_zip64.npz",&bytes).unwrap();
+
+        let arrays = read_npz_dyn("test_npz_zip64.npz");
+        // without the locator the saturated fields can't be resolved
+        let n = bytes.len();
+        bytes[n - 42..n - 38].copy_from_slice(&[0; 4]);
+        std::fs::write("test_npz_zip64.npz",&bytes).unwrap();
+        let no_locator = read_npz_dyn("test_npz_zip64.npz");
+        std::fs::remove_file("test_npz_zip64.npz").unwrap();
+
+        let arrays = arrays.unwrap();
+        assert_eq!(arrays["traj"].0,traj);
+        assert_eq!(arrays["traj"].1.shape(),dims.shape());
+        assert!(matches!(no_locator,Err(NpyError::Zip{..})));
+    }
+
+    #[test]
+    fn test_npz() {
+        let k_dims = ArrayDim::from_shape(&[2,2,2]);
+        let kspace = NpyArray::C64((0..8).map(|i| Complex32::new(i as f32,1.)).collect());
+        let t_dims = ArrayDim::from_shape(&[4,3]);
+        let traj = NpyArray::F32((0..12).map(|i| i as f32 * 0.5).collect());
+        let m_dims = ArrayDim::from_shape(&[5]);
+        let mask = NpyArray::I16(vec![1,0,1,0,1]);
+
+        for compress in [false, true] {
+            let entries = [("kspace",&kspace,k_dims),("traj/x",&traj,t_dims),("mask.npy",&mask,m_dims)];
+            write_npz("test_npz.npz",&entries,compress).unwrap();
+
+            // member names are sanitized and given a single .npy suffix
+            let bytes = std::fs::read("test_npz.npz").unwrap();
+            let contains = |name:&[u8]| bytes.windows(name.len()).any(|w| w == name);
+            assert!(contains(b"kspace.npy"));
+            assert!(contains(b"traj_x.npy"));
+            assert!(contains(b"mask.npy"));
+            assert!(!contains(b"mask.npy.npy"));
+
+            let arrays = read_npz_dyn("test_npz.npz").unwrap();
+            assert_eq!(arrays.len(),3);
+            assert_eq!(arrays["kspace"].0,kspace);
+            assert_eq!(arrays["kspace"].1.shape(),k_dims.shape());
+            assert_eq!(arrays["traj_x"].0,traj);
+            assert_eq!(arrays["traj_x"].1.shape(),t_dims.shape());
+            assert_eq!(arrays["mask"].0,mask);
+
+            assert!(matches!(read_npz::<f32>("test_npz.npz"),Err(NpyError::DtypeMismatch{..})));
+        }
+
+        write_npz("test_npz.npz",&[("traj",&traj,t_dims)],true).unwrap();
+        let arrays = read_npz::<f32>("test_npz.npz").unwrap();
+        let NpyArray::F32(t) = &traj else { unreachable!() };
+        assert_eq!(&arrays["traj"].0,t);
+
+        let dup = write_npz("test_npz.npz",&[("traj",&traj,t_dims),("traj.npy",&traj,t_dims)],false);
+        assert!(matches!(dup,Err(NpyError::DuplicateName(..))));
+        std::fs::remove_file("test_npz.npz").unwrap();
+
+        let many:Vec<_> = (0..u16::MAX).map(|_| ("x",&mask,m_dims)).collect();
+        assert!(matches!(write_npz("test_npz_many.npz",&many,false),Err(NpyError::Zip{..})));
+        assert!(!std::path::Path::new("test_npz_many.npz").exists());
+
+        write_npy("test_npy_dyn.npy",&[1u8,2,3],ArrayDim::from_shape(&[3]),Order::C).unwrap();
+        let (y,_) = read_npy_dyn("test_npy_dyn.npy").unwrap();
+        std::fs::remove_file("test_npy_dyn.npy").unwrap();
+        assert_eq!(y,NpyArray::U8(vec![1,2,3]));
+    }
+
+}
+
+#[derive(Debug)]
+pub enum NpyError {
+    IO(PathBuf, std::io::Error),
+    Format{path: PathBuf, msg: String},
+    DtypeMismatch{path: PathBuf, expected: String, found: String},
+    InconsistentArraySize{expected: usize, actual: usize},
+    DuplicateName(String),
+    Zip{path: PathBuf, msg: String},
+    /// the array described by a header can't be allocated
+    Alloc(PathBuf, AllocError),
+}
+
+impl Display for NpyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NpyError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            NpyError::Format {path, msg} => write!(f, "invalid npy file {}: {}", path.display(), msg),
+            NpyError::DtypeMismatch {path, expected, found} => write!(
+                f, "{} has dtype {} but {} was requested", path.display(), found, expected
+            ),
+            NpyError::InconsistentArraySize {expected, actual} => write!(
+                f, "array dimensions require {} elements but {} were supplied", expected, actual
+            ),
+            NpyError::DuplicateName(name) => write!(f, "duplicate npz member {}", name),
+            NpyError::Zip {path, msg} => write!(f, "invalid npz archive {}: {}", path.display(), msg),
+            NpyError::Alloc(path, e) => write!(f, "can't read {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for NpyError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> NpyError {
+    let path = path.to_path_buf();
+    move |e| NpyError::IO(path, e)
+}
+
+/// memory layout of npy data. Fortran order matches the column-major layout of this crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    C,
+    Fortran,
+}
+
+/// element types that can be stored in npy files
+pub trait NpyElement: Pod + Send + Sync {
+    /// numpy type kind character (f, i, u or c)
+    const KIND: char;
+    /// size of each scalar component in bytes, used for byte swapping
+    const WORD: usize;
+}
+
+macro_rules! npy_element {
+    ($t:ty, $kind:expr, $word:expr) => {
+        impl NpyElement for $t {
+            const KIND: char = $kind;
+            const WORD: usize = $word;
+        }
+    };
+}
+
+npy_element!(f32, 'f', 4);
+npy_element!(f64, 'f', 8);
+npy_element!(i8, 'i', 1);
+npy_element!(i16, 'i', 2);
+npy_element!(i32, 'i', 4);
+npy_element!(i64, 'i', 8);
+npy_element!(u8, 'u', 1);
+npy_element!(u16, 'u', 2);
+npy_element!(u32, 'u', 4);
+npy_element!(u64, 'u', 8);
+npy_element!(Complex<f32>, 'c', 4);
+npy_element!(Complex<f64>, 'c', 8);
+
+/// the little-endian numpy type descriptor for T, i.e. '<f4'
+fn descr<T:NpyElement>() -> String {
+    let endian = if T::WORD == 1 { '|' } else { '<' };
+    format!("{}{}{}", endian, T::KIND, size_of::<T>())
+}
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// header fields of an npy file
+struct NpyHeader {
+    descr: String,
+    fortran_order: bool,
+    shape: Vec<usize>,
+}
+
+impl NpyHeader {
+
+    /// reads the header, leaving the reader at the start of the data
+    fn read(r:&mut impl Read, path:&Path) -> Result<NpyHeader, NpyError> {
+        let format_err = |msg:&str| NpyError::Format{path: path.to_path_buf(), msg: msg.to_string()};
+        let mut pre = [0u8; 8];
+        r.read_exact(&mut pre).map_err(io_err(path))?;
+        if &pre[..6] != MAGIC {
+            return Err(format_err("missing magic string"));
+        }
+        let len = match pre[6] {
+            1 => {
+                let mut l = [0u8; 2];
+                r.read_exact(&mut l).map_err(io_err(path))?;
+                u16::from_le_bytes(l) as usize
+            }
+            2 | 3 => {
+                let mut l = [0u8; 4];
+                r.read_exact(&mut l).map_err(io_err(path))?;
+                u32::from_le_bytes(l) as usize
+            }
+            _=> return Err(format_err(&format!("unsupported version {}.{}", pre[6], pre[7]))),
+        };
+        let mut h = vec![0u8; len];
+        r.read_exact(&mut h).map_err(io_err(path))?;
+        let h = String::from_utf8_lossy(&h).to_string();
+
+        // the value following a dict key, up to the next delimiter
+        let value = |key:&str| -> Result<String, NpyError> {
+            let k = format!("'{}'", key);
+            let start = h.find(&k).ok_or_else(|| format_err(&format!("missing key {}", key)))? + k.len();
+            let rest = h[start..].trim_start().strip_prefix(':').ok_or_else(|| format_err("malformed header dict"))?.trim_start();
+            let end = if rest.starts_with('(') {
+                rest.find(')').map(|e| e + 1)
+            } else if rest.starts_with('\'') {
+                rest[1..].find('\'').map(|e| e + 2)
+            } else {
+                rest.find([',', '}'])
+            }.ok_or_else(|| format_err("malformed header dict"))?;
+            Ok(rest[..end].trim().to_string())
+        };
+
+        let descr = value("descr")?.trim_matches('\'').to_string();
+        let fortran_order = match value("fortran_order")?.as_str() {
+            "True" => true,
+            "False" => false,
+            v => return Err(format_err(&format!("invalid fortran_order {}", v))),
+        };
+        let shape = value("shape")?
+            .trim_matches(['(', ')'])
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.trim_end_matches('L').parse::<usize>().map_err(|_| format_err(&format!("invalid shape entry {}", s))))
+            .collect::<Result<Vec<_>, _>>()?;
+        if shape.len() > N_DIMS {
+            return Err(format_err(&format!("arrays of up to {} dimensions are supported", N_DIMS)));
+        }
+        // empty arrays can't be represented
+        ArrayDim::try_from_shape(&shape).map_err(|e| format_err(&e.to_string()))?;
+        Ok(NpyHeader { descr, fortran_order, shape })
+    }
+
+    /// writes the header padded so the data starts on a 64 byte boundary
+    fn write(&self, w:&mut impl Write) -> std::io::Result<()> {
+        let shape = match self.shape.len() {
+            1 => format!("({},)", self.shape[0]),
+            _=> format!("({})", self.shape.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(", ")),
+        };
+        let dict = format!(
+            "{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}",
+            self.descr, if self.fortran_order { "True" } else { "False" }, shape
+        );
+        // version 1 headers are limited to a 16 bit length
+        let v1 = 10 + dict.len() + 1 <= u16::MAX as usize;
+        let pre = if v1 { 10 } else { 12 };
+        let pad = (64 - (pre + dict.len() + 1) % 64) % 64;
+        let len = dict.len() + pad + 1;
+        w.write_all(MAGIC)?;
+        if v1 {
+            w.write_all(&[1, 0])?;
+            w.write_all(&(len as u16).to_le_bytes())?;
+        } else {
+            w.write_all(&[2, 0])?;
+            w.write_all(&(len as u32).to_le_bytes())?;
+        }
+        w.write_all(dict.as_bytes())?;
+        w.write_all(&vec![b' '; pad])?;
+        w.write_all(b"\n")
+    }
+}
+
+/// reorders data laid out column-major over shape into column-major over the reversed shape,
+/// which is the C order layout of the original shape. Applying it twice is the identity
+fn reverse_axes<T:Copy>(src:&[T], shape:&[usize]) -> Vec<T> {
+    let mut c_strides = vec![1usize; shape.len()];
+    for k in (0..shape.len().saturating_sub(1)).rev() {
+        c_strides[k] = c_strides[k + 1] * shape[k + 1];
+    }
+    let mut dst = src.to_vec();
+    let mut idx = vec![0usize; shape.len()];
+    for x in src {
+        let j:usize = idx.iter().zip(c_strides.iter()).map(|(i, s)| i * s).sum();
+        dst[j] = *x;
+        for (i, d) in idx.iter_mut().zip(shape.iter()) {
+            *i += 1;
+            if *i < *d {
+                break;
+            }
+            *i = 0;
+        }
+    }
+    dst
+}
+
+/// checks a type descriptor against T, returning true if the data needs byte swapping
+fn check_descr<T:NpyElement>(path:&Path, found:&str) -> Result<bool, NpyError> {
+    let expected = descr::<T>();
+    let (endian, ty) = match found.chars().next() {
+        Some(c @ ('<' | '>' | '|' | '=')) => (c, &found[1..]),
+        _=> ('=', found),
+    };
+    if ty != &expected[1..] {
+        return Err(NpyError::DtypeMismatch{path: path.to_path_buf(), expected, found: found.to_string()});
+    }
+    let big = endian == '>' || (endian == '=' && cfg!(target_endian = "big"));
+    Ok(T::WORD > 1 && big != cfg!(target_endian = "big"))
+}
+
+fn swap_words<T:NpyElement>(data:&mut [T]) {
+    bytemuck::cast_slice_mut::<T, u8>(data).chunks_exact_mut(T::WORD).for_each(|w| w.reverse());
+}
+
+/// reads npy data following the header into an array in column-major order
+fn read_npy_data<T:NpyElement>(r:&mut impl Read, path:&Path, h:&NpyHeader) -> Result<(Vec<T>, ArrayDim), NpyError> {
+    let swap = check_descr::<T>(path, &h.descr)?;
+    let dims = ArrayDim::from_shape(&h.shape);
+    let mut data = dims.try_alloc(T::zeroed()).map_err(|e| NpyError::Alloc(path.to_path_buf(), e))?;
+    r.read_exact(bytemuck::cast_slice_mut(&mut data)).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => NpyError::Format{path: path.to_path_buf(), msg: String::from("data is truncated")},
+        _=> NpyError::IO(path.to_path_buf(), e),
+    })?;
+    if swap {
+        swap_words(&mut data);
+    }
+    if !h.fortran_order {
+        let reversed:Vec<usize> = h.shape.iter().rev().copied().collect();
+        data = reverse_axes(&data, &reversed);
+    }
+    Ok((data, dims))
+}
+
+/// writes an npy header and data to a writer
+fn write_npy_to<T:NpyElement>(w:&mut impl Write, data:&[T], dims:ArrayDim, order:Order) -> std::io::Result<()> {
+    let shape = dims.shape_ns().to_vec();
+    let h = NpyHeader { descr: descr::<T>(), fortran_order: order == Order::Fortran, shape };
+    h.write(w)?;
+    match order {
+        Order::Fortran => w.write_all(bytemuck::cast_slice(data)),
+        Order::C => w.write_all(bytemuck::cast_slice(&reverse_axes(data, &h.shape))),
+    }
+}
+
+/// reads an npy file (format version 1, 2 or 3) into a column-major array with the same axis order
+/// as the numpy shape. C ordered data is reordered on read and big-endian data is byte swapped
+pub fn read_npy<T:NpyElement>(file:impl AsRef<Path>) -> Result<(Vec<T>, ArrayDim), NpyError> {
+    let path = file.as_ref();
+    let mut r = BufReader::new(File::open(path).map_err(io_err(path))?);
+    let h = NpyHeader::read(&mut r, path)?;
+    read_npy_data(&mut r, path, &h)
+}
+
+/// writes an array to an npy file in the requested order. The numpy shape is the shape of the
+/// array without trailing singleton dimensions
+pub fn write_npy<T:NpyElement>(file:impl AsRef<Path>, data:&[T], dims:ArrayDim, order:Order) -> Result<(), NpyError> {
+    let path = file.as_ref();
+    if data.len() != dims.numel() {
+        return Err(NpyError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+    }
+    let mut w = BufWriter::new(File::create(path).map_err(io_err(path))?);
+    write_npy_to(&mut w, data, dims, order).map_err(io_err(path))?;
+    w.flush().map_err(io_err(path))
+}
+
+/// an npy array of any supported element type
+#[derive(Debug, Clone, PartialEq)]
+pub enum NpyArray {
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+    I8(Vec<i8>),
+    I16(Vec<i16>),
+    I32(Vec<i32>),
+    I64(Vec<i64>),
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+    U64(Vec<u64>),
+    C64(Vec<Complex32>),
+    C128(Vec<Complex64>),
+}
+
+impl NpyArray {
+    pub fn len(&self) -> usize {
+        match self {
+            NpyArray::F32(x) => x.len(),
+            NpyArray::F64(x) => x.len(),
+            NpyArray::I8(x) => x.len(),
+            NpyArray::I16(x) => x.len(),
+            NpyArray::I32(x) => x.len(),
+            NpyArray::I64(x) => x.len(),
+            NpyArray::U8(x) => x.len(),
+            NpyArray::U16(x) => x.len(),
+            NpyArray::U32(x) => x.len(),
+            NpyArray::U64(x) => x.len(),
+            NpyArray::C64(x) => x.len(),
+            NpyArray::C128(x) => x.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn write_to(&self, w:&mut impl Write, dims:ArrayDim) -> std::io::Result<()> {
+        match self {
+            NpyArray::F32(x) => write_npy_to(w, x, dims, Order::Fortran),
+            NpyArray::F64(x) => write_npy_to(w, x, dims, Order::Fortran),
+            NpyArray::I8(x) => write_npy_to(w, x, dims, Order::Fortran),
+            NpyArray::I16(x) => write_npy_to(w, x, dims, Order::Fortran),
+            NpyArray::I32(x) => write_npy_to(w, x, dims, Order::Fortran),
+            NpyArray::I64(x) => write_npy_to(w, x, dims, Order::Fortran),
+            NpyArray::U8(x) => write_npy_to(w, x, dims, Order::Fortran),
+            NpyArray::U16(x) => write_npy_to(w, x, dims, Order::Fortran),
+            NpyArray::U32(x) => write_npy_to(w, x, dims, Order::Fortran),
+            NpyArray::U64(x) => write_npy_to(w, x, dims, Order::Fortran),
+            NpyArray::C64(x) => write_npy_to(w, x, dims, Order::Fortran),
+            NpyArray::C128(x) => write_npy_to(w, x, dims, Order::Fortran),
+        }
+    }
+
+    /// reads npy data of the type given in the header
+    fn read_from(r:&mut impl Read, path:&Path, h:&NpyHeader) -> Result<(NpyArray, ArrayDim), NpyError> {
+        let ty = h.descr.trim_start_matches(['<', '>', '|', '=']);
+        Ok(match ty {
+            "f4" => { let (x, d) = read_npy_data(r, path, h)?; (NpyArray::F32(x), d) }
+            "f8" => { let (x, d) = read_npy_data(r, path, h)?; (NpyArray::F64(x), d) }
+            "i1" => { let (x, d) = read_npy_data(r, path, h)?; (NpyArray::I8(x), d) }
+            "i2" => { let (x, d) = read_npy_data(r, path, h)?; (NpyArray::I16(x), d) }
+            "i4" => { let (x, d) = read_npy_data(r, path, h)?; (NpyArray::I32(x), d) }
+            "i8" => { let (x, d) = read_npy_data(r, path, h)?; (NpyArray::I64(x), d) }
+            "u1" => { let (x, d) = read_npy_data(r, path, h)?; (NpyArray::U8(x), d) }
+            "u2" => { let (x, d) = read_npy_data(r, path, h)?; (NpyArray::U16(x), d) }
+            "u4" => { let (x, d) = read_npy_data(r, path, h)?; (NpyArray::U32(x), d) }
+            "u8" => { let (x, d) = read_npy_data(r, path, h)?; (NpyArray::U64(x), d) }
+            "c8" => { let (x, d) = read_npy_data(r, path, h)?; (NpyArray::C64(x), d) }
+            "c16" => { let (x, d) = read_npy_data(r, path, h)?; (NpyArray::C128(x), d) }
+            _=> return Err(NpyError::Format{path: path.to_path_buf(), msg: format!("unsupported dtype {}", h.descr)}),
+        })
+    }
+}
+
+/// reads the type descriptor and shape of an npy file from its header without reading the data,
+/// i.e. "<f4"
+pub fn read_npy_header(file:impl AsRef<Path>) -> Result<(String, ArrayDim), NpyError> {
+    let path = file.as_ref();
+    let mut r = BufReader::new(File::open(path).map_err(io_err(path))?);
+    let h = NpyHeader::read(&mut r, path)?;
+    Ok((h.descr, ArrayDim::from_shape(&h.shape)))
+}
+
+/// reads an npy file of any supported element type
+pub fn read_npy_dyn(file:impl AsRef<Path>) -> Result<(NpyArray, ArrayDim), NpyError> {
+    let path = file.as_ref();
+    let mut r = BufReader::new(File::open(path).map_err(io_err(path))?);
+    let h = NpyHeader::read(&mut r, path)?;
+    NpyArray::read_from(&mut r, path, &h)
+}
+
+const ZIP_LOCAL_SIG: u32 = 0x04034b50;
+const ZIP_CENTRAL_SIG: u32 = 0x02014b50;
+const ZIP_END_SIG: u32 = 0x06054b50;
+const ZIP64_END_SIG: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x07064b50;
+/// dos date for 1980-01-01, the earliest date a zip can hold
+const ZIP_DATE: u16 = (1 << 5) | 1;
+
+/// converts an array name to a zip member name that np.load exposes under the same name
+fn npz_member_name(name:&str) -> String {
+    let base = name.strip_suffix(".npy").unwrap_or(name);
+    let base:String = base.chars().map(|c| {
+        if c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' { c } else { '_' }
+    }).collect();
+    format!("{}.npy", base)
+}
+
+/// a member of a zip archive as described by the central directory
+struct ZipEntry {
+    name: String,
+    method: u16,
+    compressed_size: u64,
+    header_offset: u64,
+}
+
+fn u16_at(b:&[u8], o:usize) -> u16 {
+    u16::from_le_bytes([b[o], b[o + 1]])
+}
+
+fn u32_at(b:&[u8], o:usize) -> u32 {
+    u32::from_le_bytes([b[o], b[o + 1], b[o + 2], b[o + 3]])
+}
+
+fn u64_at(b:&[u8], o:usize) -> u64 {
+    u64::from_le_bytes(b[o..o + 8].try_into().unwrap())
+}
+
+/// reads the central directory of a zip archive
+fn zip_entries(f:&mut File, path:&Path) -> Result<Vec<ZipEntry>, NpyError> {
+    let zip_err = |msg:&str| NpyError::Zip{path: path.to_path_buf(), msg: msg.to_string()};
+    let len = f.metadata().map_err(io_err(path))?.len();
+    // the end of central directory record is 22 bytes plus a comment of up to 64 kB
+    let tail_len = len.min(22 + u16::MAX as u64);
+    f.seek(SeekFrom::Start(len - tail_len)).map_err(io_err(path))?;
+    let mut tail = vec![0u8; tail_len as usize];
+    f.read_exact(&mut tail).map_err(io_err(path))?;
+    let end = (0..tail.len().saturating_sub(21)).rev()
+        .find(|&i| u32_at(&tail, i) == ZIP_END_SIG)
+        .ok_or_else(|| zip_err("missing end of central directory"))?;
+    let mut n_entries = u16_at(&tail, end + 10) as u64;
+    let mut cd_size = u32_at(&tail, end + 12) as u64;
+    let mut cd_offset = u32_at(&tail, end + 16) as u64;
+
+    // saturated fields are given by the zip64 end of central directory record, which is found
+    // from the locator that immediately precedes the end of central directory record
+    if n_entries == u16::MAX as u64 || cd_size == u32::MAX as u64 || cd_offset == u32::MAX as u64 {
+        if end < 20 || u32_at(&tail, end - 20) != ZIP64_LOCATOR_SIG {
+            return Err(zip_err("missing zip64 end of central directory locator"));
+        }
+        let record_offset = u64_at(&tail, end - 20 + 8);
+        let mut record = [0u8; 56];
+        f.seek(SeekFrom::Start(record_offset)).map_err(io_err(path))?;
+        f.read_exact(&mut record).map_err(io_err(path))?;
+        if u32_at(&record, 0) != ZIP64_END_SIG {
+            return Err(zip_err("malformed zip64 end of central directory"));
+        }
+        n_entries = u64_at(&record, 32);
+        cd_size = u64_at(&record, 40);
+        cd_offset = u64_at(&record, 48);
+    }
+    if cd_offset.checked_add(cd_size).is_none_or(|cd_end| cd_end > len) {
+        return Err(zip_err("central directory extends past the end of the file"));
+    }
+
+    f.seek(SeekFrom::Start(cd_offset)).map_err(io_err(path))?;
+    let mut cd = vec![0u8; cd_size as usize];
+    f.read_exact(&mut cd).map_err(io_err(path))?;
+
+    let mut entries = vec![];
+    let mut o = 0;
+    for _ in 0..n_entries {
+        if o + 46 > cd.len() || u32_at(&cd, o) != ZIP_CENTRAL_SIG {
+            return Err(zip_err("malformed central directory"));
+        }
+        let method = u16_at(&cd, o + 10);
+        let mut compressed_size = u32_at(&cd, o + 20) as u64;
+        let mut uncompressed_size = u32_at(&cd, o + 24) as u64;
+        let name_len = u16_at(&cd, o + 28) as usize;
+        let extra_len = u16_at(&cd, o + 30) as usize;
+        let comment_len = u16_at(&cd, o + 32) as usize;
+        let mut header_offset = u32_at(&cd, o + 42) as u64;
+        if o + 46 + name_len + extra_len > cd.len() {
+            return Err(zip_err("malformed central directory"));
+        }
+        let name = String::from_utf8_lossy(&cd[o + 46..o + 46 + name_len]).to_string();
+
+        // zip64 extended sizes replace any fields saturated at u32::MAX, in order
+        let extra = &cd[o + 46 + name_len..o + 46 + name_len + extra_len];
+        let mut e = 0;
+        while e + 4 <= extra.len() {
+            let id = u16_at(extra, e);
+            let size = u16_at(extra, e + 2) as usize;
+            if id == 1 {
+                let mut fields = extra[e + 4..(e + 4 + size).min(extra.len())].chunks_exact(8)
+                    .map(|c| u64::from_le_bytes(c.try_into().unwrap()));
+                for v in [&mut uncompressed_size, &mut compressed_size, &mut header_offset] {
+                    if *v == u32::MAX as u64 {
+                        *v = fields.next().ok_or_else(|| zip_err("malformed zip64 extra field"))?;
+                    }
+                }
+            }
+            e += 4 + size;
+        }
+
+        entries.push(ZipEntry { name, method, compressed_size, header_offset });
+        o += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// reads the stored bytes of a zip member, decompressing deflated members
+fn zip_member(f:&mut File, path:&Path, entry:&ZipEntry) -> Result<Vec<u8>, NpyError> {
+    let zip_err = |msg:String| NpyError::Zip{path: path.to_path_buf(), msg};
+    let mut local = [0u8; 30];
+    f.seek(SeekFrom::Start(entry.header_offset)).map_err(io_err(path))?;
+    f.read_exact(&mut local).map_err(io_err(path))?;
+    if u32_at(&local, 0) != ZIP_LOCAL_SIG {
+        return Err(zip_err(format!("malformed local header for {}", entry.name)));
+    }
+    // the local extra field can differ from the central directory
+    let skip = u16_at(&local, 26) as i64 + u16_at(&local, 28) as i64;
+    f.seek(SeekFrom::Current(skip)).map_err(io_err(path))?;
+    let mut stored = vec![0u8; entry.compressed_size as usize];
+    f.read_exact(&mut stored).map_err(io_err(path))?;
+    match entry.method {
+        0 => Ok(stored),
+        8 => {
+            let mut bytes = vec![];
+            DeflateDecoder::new(stored.as_slice()).read_to_end(&mut bytes).map_err(io_err(path))?;
+            Ok(bytes)
+        }
+        m => Err(zip_err(format!("unsupported compression method {} for {}", m, entry.name))),
+    }
+}
+
+/// reads every .npy member of an npz archive, keyed by name without the .npy suffix as with
+/// np.load
+pub fn read_npz_dyn(file:impl AsRef<Path>) -> Result<BTreeMap<String, (NpyArray, ArrayDim)>, NpyError> {
+    let path = file.as_ref();
+    let mut f = File::open(path).map_err(io_err(path))?;
+    let mut arrays = BTreeMap::new();
+    for entry in zip_entries(&mut f, path)? {
+        let Some(name) = entry.name.strip_suffix(".npy") else {
+            continue
+        };
+        let bytes = zip_member(&mut f, path, &entry)?;
+        let mut r = Cursor::new(bytes);
+        let h = NpyHeader::read(&mut r, path)?;
+        arrays.insert(name.to_string(), NpyArray::read_from(&mut r, path, &h)?);
+    }
+    Ok(arrays)
+}
+
+/// reads every .npy member of an npz archive, which must all have the element type T
+pub fn read_npz<T:NpyElement>(file:impl AsRef<Path>) -> Result<BTreeMap<String, (Vec<T>, ArrayDim)>, NpyError> {
+    let path = file.as_ref();
+    let mut f = File::open(path).map_err(io_err(path))?;
+    let mut arrays = BTreeMap::new();
+    for entry in zip_entries(&mut f, path)? {
+        let Some(name) = entry.name.strip_suffix(".npy") else {
+            continue
+        };
+        let bytes = zip_member(&mut f, path, &entry)?;
+        let mut r = Cursor::new(bytes);
+        let h = NpyHeader::read(&mut r, path)?;
+        arrays.insert(name.to_string(), read_npy_data(&mut r, path, &h)?);
+    }
+    Ok(arrays)
+}
+
+/// writes named arrays to an npz archive that np.load can read. Names are sanitized to member
+/// names ending in .npy, and names that collide after sanitizing are rejected. Members are
+/// deflated if compress is set, as with np.savez_compressed, and stored otherwise
+pub fn write_npz(file:impl AsRef<Path>, entries:&[(&str, &NpyArray, ArrayDim)], compress:bool) -> Result<(), NpyError> {
+    let path = file.as_ref();
+    let too_large = || NpyError::Zip{path: path.to_path_buf(), msg: String::from("archives over 4 GB are not supported")};
+    // an entry count of 0xffff marks a zip64 archive, which isn't written
+    if entries.len() >= u16::MAX as usize {
+        let msg = format!("{} entries exceeds the limit of {}", entries.len(), u16::MAX - 1);
+        return Err(NpyError::Zip{path: path.to_path_buf(), msg});
+    }
+
+    let mut names = BTreeSet::new();
+    for (name, data, dims) in entries {
+        if data.len() != dims.numel() {
+            return Err(NpyError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+        }
+        let member = npz_member_name(name);
+        if !names.insert(member.clone()) {
+            return Err(NpyError::DuplicateName(member));
+        }
+    }
+
+    let mut w = BufWriter::new(File::create(path).map_err(io_err(path))?);
+    let mut central = vec![];
+    let mut offset = 0u64;
+    for (name, data, dims) in entries {
+        let member = npz_member_name(name);
+        let mut npy = vec![];
+        data.write_to(&mut npy, *dims).map_err(io_err(path))?;
+        let mut crc = Crc::new();
+        crc.update(&npy);
+        let stored = if compress {
+            let mut enc = DeflateEncoder::new(vec![], Compression::default());
+            enc.write_all(&npy).map_err(io_err(path))?;
+            enc.finish().map_err(io_err(path))?
+        } else {
+            npy.clone()
+        };
+        let method:u16 = if compress { 8 } else { 0 };
+        let csize = u32::try_from(stored.len()).map_err(|_| too_large())?;
+        let usize_ = u32::try_from(npy.len()).map_err(|_| too_large())?;
+        let header_offset = u32::try_from(offset).map_err(|_| too_large())?;
+
+        // fields shared by the local and central headers from the version needed onwards
+        let mut common = vec![];
+        common.extend_from_slice(&20u16.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&method.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+        common.extend_from_slice(&ZIP_DATE.to_le_bytes());
+        common.extend_from_slice(&crc.sum().to_le_bytes());
+        common.extend_from_slice(&csize.to_le_bytes());
+        common.extend_from_slice(&usize_.to_le_bytes());
+        common.extend_from_slice(&(member.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        let mut local = ZIP_LOCAL_SIG.to_le_bytes().to_vec();
+        local.extend_from_slice(&common);
+        local.extend_from_slice(member.as_bytes());
+        w.write_all(&local).map_err(io_err(path))?;
+        w.write_all(&stored).map_err(io_err(path))?;
+        offset += (local.len() + stored.len()) as u64;
+
+        central.extend_from_slice(&ZIP_CENTRAL_SIG.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes());
+        central.extend_from_slice(&common);
+        // comment length, disk number, internal and external attributes
+        central.extend_from_slice(&[0u8; 10]);
+        central.extend_from_slice(&header_offset.to_le_bytes());
+        central.extend_from_slice(member.as_bytes());
+    }
+
+    let cd_offset = u32::try_from(offset).map_err(|_| too_large())?;
+    let mut end = ZIP_END_SIG.to_le_bytes().to_vec();
+    end.extend_from_slice(&[0u8; 4]);
+    end.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    end.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    end.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    end.extend_from_slice(&cd_offset.to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes());
+    w.write_all(&central).map_err(io_err(path))?;
+    w.write_all(&end).map_err(io_err(path))?;
+    w.flush().map_err(io_err(path))
+}
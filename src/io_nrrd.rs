@@ -1,11 +1,1436 @@
-use std::path::Path;
-use crate::ArrayDim;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::marker::PhantomData;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use bytemuck::Pod;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use crate::{AllocError, ArrayDim};
+use num_complex::Complex;
+use num_traits::{Bounded, FromPrimitive, NumCast, ToPrimitive, Zero};
 pub use nrrd_rs::NRRD;
 use nrrd_rs::read_nrrd_to;
-use nrrd_rs::header_defs::{NRRDType};
-use num_traits::FromPrimitive;
+use nrrd_rs::header_defs::NRRDType;
 pub use nrrd_rs::header_defs::Encoding;
 
+#[cfg(test)]
+mod tests {
+    use num_complex::{Complex32, Complex64};
+    use crate::ArrayDim;
+    use std::collections::BTreeMap;
+    use crate::io_nrrd::{read_nrrd_with_axes, write_header_and_data, NrrdAxisInfo, NrrdHeader, NrrdReadOptions, geometry_to_affine, nifti_affine_to_nrrd_geometry, nrrd_geometry_to_nifti_affine, read_nrrd_scaled, write_nrrd_as, ScalePolicy, collapse_seg_layers, read_seg_nrrd, write_seg_nrrd, SegmentInfo, read_nrrd_series, nrrd_dtype, read_nrrd_header, NrrdDtype, DataFilePolicy, nrrd_meta, read_nrrd_meta, write_nrrd_with_meta, NrrdStreamWriter, read_nrrd_region, read_nrrd_complex, write_nrrd_complex, parse_header, read_nrrd, try_read_nrrd, try_write_nrrd, NrrdFiles, write_nrrd_with_options, Encoding, NrrdGeometry, NrrdIoError, NrrdWriteOptions, Space, NRRD};
+
+    #[test]
+    fn test_try_read_missing() {
+        let r = try_read_nrrd::<f32>("does_not_exist.nrrd");
+        assert!(matches!(r,Err(NrrdIoError::IO(..))));
+    }
+
+    #[test]
+    fn test_try_write_ref_shape_mismatch() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x = dims.alloc(1f32);
+        let h = NRRD::new_from_dims::<f32>(&[4,3]);
+        let r = try_write_nrrd("test_ref_mismatch.nrrd",&x,dims,Some(&h),true,Encoding::Raw);
+        match r {
+            Err(NrrdIoError::ShapeMismatch {expected,found}) => {
+                assert_eq!(expected,vec![4,3,2]);
+                assert_eq!(found,vec![4,3]);
+            }
+            _=> panic!("expected a shape mismatch error"),
+        }
+        assert!(!std::path::Path::new("test_ref_mismatch.nrrd").exists());
+    }
+
+    #[test]
+    fn test_write_layout() {
+        let dims = ArrayDim::from_shape(&[4,3]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+        let written = |prefix:&str| {
+            let mut files:Vec<_> = std::fs::read_dir(".").unwrap()
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .filter(|n| n.starts_with(prefix))
+                .collect();
+            files.sort();
+            files
+        };
+
+        // extension and flag agree
+        let files = try_write_nrrd("test_layout_a.nrrd",&x,dims,None,true,Encoding::Raw).unwrap();
+        assert_eq!(files, NrrdFiles{header: "test_layout_a.nrrd".into(), data: None});
+        assert_eq!(written("test_layout_a"), vec!["test_layout_a.nrrd"]);
+        assert_eq!(read_nrrd::<f32>("test_layout_a.nrrd").0, x);
+        std::fs::remove_file("test_layout_a.nrrd").unwrap();
+
+        let files = try_write_nrrd("test_layout_b.nhdr",&x,dims,None,false,Encoding::Raw).unwrap();
+        let data = files.data.clone().unwrap();
+        assert_eq!(files.header, std::path::PathBuf::from("test_layout_b.nhdr"));
+        assert!(data.is_file());
+        let mut expected:Vec<String> = files.paths().iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        expected.sort();
+        assert_eq!(written("test_layout_b"), expected);
+        assert_eq!(read_nrrd::<f32>("test_layout_b.nhdr").0, x);
+        for p in files.paths() {
+            std::fs::remove_file(p).unwrap();
+        }
+
+        // extension contradicts the flag, so nothing is written
+        let r = try_write_nrrd("test_layout_c.nrrd",&x,dims,None,false,Encoding::Raw);
+        assert!(matches!(r, Err(NrrdIoError::LayoutMismatch{attached: false, ..})));
+        assert!(written("test_layout_c").is_empty());
+        let r = try_write_nrrd("test_layout_d.nhdr",&x,dims,None,true,Encoding::Raw);
+        assert!(matches!(r, Err(NrrdIoError::LayoutMismatch{attached: true, ..})));
+        assert!(written("test_layout_d").is_empty());
+
+        // extension-less paths get the extension for the layout
+        let files = try_write_nrrd("test_layout_e",&x,dims,None,true,Encoding::Raw).unwrap();
+        assert_eq!(files.paths(), vec![std::path::Path::new("test_layout_e.nrrd")]);
+        assert_eq!(written("test_layout_e"), vec!["test_layout_e.nrrd"]);
+        std::fs::remove_file("test_layout_e.nrrd").unwrap();
+        let files = try_write_nrrd("test_layout_f",&x,dims,None,false,Encoding::Raw).unwrap();
+        assert_eq!(files.header, std::path::PathBuf::from("test_layout_f.nhdr"));
+        assert_eq!(written("test_layout_f").len(), 2);
+        for p in files.paths() {
+            std::fs::remove_file(p).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_try_round_trip() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+        try_write_nrrd("test_try_round_trip.nrrd",&x,dims,None,true,Encoding::Raw).unwrap();
+        let (data,read_dims,_) = try_read_nrrd::<f32>("test_try_round_trip.nrrd").unwrap();
+        std::fs::remove_file("test_try_round_trip.nrrd").unwrap();
+        assert_eq!(data,x);
+        assert_eq!(read_dims.shape(),dims.shape());
+    }
+
+    #[test]
+    fn test_try_read_truncated() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+        for (name,encoding) in [("test_truncated_raw.nrrd",Encoding::Raw),("test_truncated_gz.nrrd",Encoding::Gzip)] {
+            write_nrrd_with_options(name,&x,dims,&NrrdWriteOptions::new().encoding(encoding)).unwrap();
+            let len = std::fs::metadata(name).unwrap().len();
+            std::fs::OpenOptions::new().write(true).open(name).unwrap().set_len(len - 40).unwrap();
+            let r = try_read_nrrd::<f32>(name);
+            std::fs::remove_file(name).unwrap();
+            assert!(matches!(r,Err(NrrdIoError::IO(..))));
+        }
+    }
+
+    #[test]
+    fn test_try_read_wrong_type() {
+        let dims = ArrayDim::from_shape(&[4,3]);
+        let x:Vec<i16> = (0..dims.numel()).map(|i| i as i16).collect();
+        write_nrrd_with_options("test_try_wrong_type.nrrd",&x,dims,&NrrdWriteOptions::new()).unwrap();
+        let r = try_read_nrrd::<f32>("test_try_wrong_type.nrrd");
+        let (data,..) = try_read_nrrd::<i16>("test_try_wrong_type.nrrd").unwrap();
+        std::fs::remove_file("test_try_wrong_type.nrrd").unwrap();
+        assert!(matches!(r,Err(NrrdIoError::DtypeMismatch{expected: NrrdDtype::Float32, found: NrrdDtype::Int16, ..})));
+        assert_eq!(data,x);
+    }
+
+    #[test]
+    fn test_write_geometry() {
+        let dims = ArrayDim::from_shape(&[4,3,2,5]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+        let geom = NrrdGeometry::new(vec![0.5,0.25,2.0], Space::LeftPosteriorSuperior)
+            .with_origin([10.,-5.,3.5]);
+        let opts = NrrdWriteOptions::new().with_geometry(geom.clone());
+        write_nrrd_with_options("test_geometry.nrrd",&x,dims,&opts).unwrap();
+
+        // the header is checked by nrrd_rs and as raw text, rather than with this module's parser
+        let (data,nrrd) = nrrd_rs::read_nrrd_to::<f32>("test_geometry.nrrd");
+        let bytes = std::fs::read("test_geometry.nrrd").unwrap();
+        let (_,h) = read_nrrd_header("test_geometry.nrrd").unwrap();
+        std::fs::remove_file("test_geometry.nrrd").unwrap();
+        assert_eq!(data,x);
+        assert_eq!(nrrd.shape(),dims.shape_ns());
+
+        let text = String::from_utf8_lossy(&bytes[..bytes.windows(2).position(|w| w == b"\n\n").unwrap()]).into_owned();
+        let lines:Vec<&str> = text.lines().collect();
+        assert!(lines.contains(&"space: left-posterior-superior"));
+        assert!(lines.contains(&"space directions: (0.5,0,0) (0,0.25,0) (0,0,2) none"));
+        assert!(lines.contains(&"kinds: domain domain domain none"));
+        assert!(lines.contains(&"space origin: (10,-5,3.5)"));
+
+        let read_geom = h.geometry().unwrap();
+        assert_eq!(read_geom.spacings,geom.spacings);
+        assert_eq!(read_geom.origin,geom.origin);
+    }
+
+    #[test]
+    fn test_geometry_axis_positions() {
+        // a list axis ahead of the spatial axes keeps its kind and a 'none' direction
+        let mut h = NrrdHeader::new(NrrdDtype::Float32, &[3,4,5,6]);
+        h.set_field("kinds", "list domain domain domain");
+        let geom = NrrdGeometry::new(vec![0.5,0.25,2.0], Space::LeftPosteriorSuperior)
+            .with_axes(vec![1,2,3]);
+        h.set_geometry(&geom).unwrap();
+        assert_eq!(h.field("space directions"), Some("none (0.5,0,0) (0,0.25,0) (0,0,2)"));
+        assert_eq!(h.kinds(), vec!["list","domain","domain","domain"]);
+
+        let read_geom = h.geometry().unwrap();
+        assert_eq!(read_geom.spatial_axes(), vec![1,2,3]);
+        assert_eq!(read_geom.spacings, geom.spacings);
+
+        // the leading axes are the default
+        h.set_geometry(&NrrdGeometry::new(vec![1.,1.], Space::LeftPosteriorSuperior)).unwrap();
+        assert_eq!(h.kinds(), vec!["domain","domain","none","none"]);
+        assert_eq!(h.geometry().unwrap().axes, None);
+        assert!(h.set_geometry(&geom.clone().with_axes(vec![2,1,3])).is_err());
+    }
+
+    #[test]
+    fn test_complex_round_trip() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32, -(i as f32))).collect();
+        write_nrrd_complex("test_complex32.nrrd",&x,dims,&NrrdWriteOptions::default()).unwrap();
+        let (data,read_dims,_) = read_nrrd_complex::<f32>("test_complex32.nrrd").unwrap();
+        assert_eq!(data,x);
+        assert_eq!(read_dims.shape(),dims.shape());
+
+        // plain reader sees a leading axis of 2
+        let (real,real_dims,_) = read_nrrd::<f32>("test_complex32.nrrd");
+        std::fs::remove_file("test_complex32.nrrd").unwrap();
+        assert_eq!(real_dims.shape_ns(),&[2,4,3,2]);
+        assert_eq!(real[2],1.);
+        assert_eq!(real[3],-1.);
+
+        let x:Vec<Complex64> = (0..dims.numel()).map(|i| Complex64::new(0.5 * i as f64, 2.)).collect();
+        write_nrrd_complex("test_complex64.nrrd",&x,dims,&NrrdWriteOptions::default()).unwrap();
+        let (data,read_dims,_) = read_nrrd_complex::<f64>("test_complex64.nrrd").unwrap();
+        std::fs::remove_file("test_complex64.nrrd").unwrap();
+        assert_eq!(data,x);
+        assert_eq!(read_dims.shape(),dims.shape());
+    }
+
+    #[test]
+    fn test_axis_kinds() {
+        // a 3-vector field on a 4 x 3 x 2 grid, with the vector axis last
+        let sizes = [4,3,2,3];
+        let x:Vec<f32> = (0..72).map(|i| i as f32).collect();
+        let mut h = NrrdHeader::new(NrrdDtype::Float32, &sizes);
+        h.set_field("kinds", "domain domain domain 3-vector");
+        write_header_and_data("test_axis_kinds.nrrd", h, &x, &NrrdWriteOptions::default()).unwrap();
+
+        let (data, dims, info, _) = read_nrrd_with_axes::<f32>("test_axis_kinds.nrrd", &NrrdReadOptions::default()).unwrap();
+        assert_eq!(data, x);
+        assert_eq!(dims.shape_ns(), &sizes);
+        assert_eq!(info.kinds, vec!["domain", "domain", "domain", "3-vector"]);
+        assert_eq!(info.non_domain_axes(), vec![3]);
+
+        // the vector axis is moved ahead of the domain axes
+        let opts = NrrdReadOptions::new().non_domain_leading(true);
+        let (data, dims, info, _) = read_nrrd_with_axes::<f32>("test_axis_kinds.nrrd", &opts).unwrap();
+        std::fs::remove_file("test_axis_kinds.nrrd").unwrap();
+        assert_eq!(dims.shape_ns(), &[3,4,3,2]);
+        assert_eq!(info, NrrdAxisInfo{kinds: vec!["3-vector", "domain", "domain", "domain"].into_iter().map(String::from).collect(), file_axes: vec![3,0,1,2]});
+        for (i, v) in data.iter().enumerate() {
+            let (c, p) = (i % 3, i / 3);
+            assert_eq!(*v, x[p + 24 * c]);
+        }
+    }
+
+    #[test]
+    fn test_axis_kinds_singleton() {
+        // reordering singleton axes leaves the data as it is
+        for (sizes, expected) in [([1,1,1], [1,1,1]), ([4,1,1], [1,4,1])] {
+            let n:usize = sizes.iter().product();
+            let x:Vec<f32> = (0..n).map(|i| i as f32).collect();
+            let mut h = NrrdHeader::new(NrrdDtype::Float32, &sizes);
+            h.set_field("kinds", "domain list domain");
+            write_header_and_data("test_axis_kinds_singleton.nrrd", h, &x, &NrrdWriteOptions::default()).unwrap();
+            let opts = NrrdReadOptions::new().non_domain_leading(true);
+            let (data, dims, info, _) = read_nrrd_with_axes::<f32>("test_axis_kinds_singleton.nrrd", &opts).unwrap();
+            std::fs::remove_file("test_axis_kinds_singleton.nrrd").unwrap();
+            assert_eq!(data, x);
+            assert_eq!(dims.shape(), ArrayDim::from_shape(&expected).shape());
+            assert_eq!(info.file_axes, vec![1,0,2]);
+        }
+    }
+
+    #[test]
+    fn test_read_real_as_complex() {
+        let dims = ArrayDim::from_shape(&[2,3,2]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+        write_nrrd_with_options("test_real_as_complex.nrrd",&x,dims,&NrrdWriteOptions::default()).unwrap();
+        let (data,read_dims,_) = read_nrrd_complex::<f32>("test_real_as_complex.nrrd").unwrap();
+        std::fs::remove_file("test_real_as_complex.nrrd").unwrap();
+        // a leading axis of 2 without a complex kind is not treated as complex
+        assert_eq!(read_dims.shape(),dims.shape());
+        assert!(data.iter().zip(x.iter()).all(|(c,r)| c.re == *r && c.im == 0.));
+    }
+
+    #[test]
+    fn test_read_region() {
+        let dims = ArrayDim::from_shape(&[7,5,4,3]);
+        let x:Vec<i16> = (0..dims.numel()).map(|i| i as i16).collect();
+        let offset = [2,1,1,1];
+        let size = [3,3,2,2];
+        let (expected,expected_dims) = dims.copy_region(&x,&offset,&size);
+
+        for (name,encoding) in [("test_region_raw.nrrd",Encoding::Raw),("test_region_gz.nrrd",Encoding::Gzip)] {
+            let opts = NrrdWriteOptions::new().encoding(encoding);
+            write_nrrd_with_options(name,&x,dims,&opts).unwrap();
+            let (r,rd,_) = read_nrrd_region::<i16>(name,&offset,&size).unwrap();
+            let bad = read_nrrd_region::<i16>(name,&[5,0,0,0],&[3,1,1,1]);
+            let wrong_type = read_nrrd_region::<f32>(name,&offset,&size);
+            std::fs::remove_file(name).unwrap();
+            assert_eq!(r,expected);
+            assert_eq!(rd.shape(),expected_dims.shape());
+            assert!(matches!(bad,Err(NrrdIoError::InvalidRegion(_))));
+            assert!(matches!(wrong_type,Err(NrrdIoError::DtypeMismatch{..})));
+        }
+    }
+
+    #[test]
+    fn test_stream_writer() {
+        let dims = ArrayDim::from_shape(&[6,5,4]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32 * 0.5).collect();
+        let opts = NrrdWriteOptions::default();
+
+        write_nrrd_with_options("test_stream_mono.nrrd",&x,dims,&opts).unwrap();
+        let mut w = NrrdStreamWriter::<f32>::create("test_stream_chunked.nrrd",dims,&opts).unwrap();
+        for chunk in [&x[0..17],&x[17..60],&x[60..]] {
+            w.write_chunk(chunk).unwrap();
+        }
+        assert_eq!(w.remaining(),0);
+        assert!(w.write_chunk(&x[0..1]).is_err());
+        w.finish().unwrap();
+
+        let mono = std::fs::read("test_stream_mono.nrrd").unwrap();
+        let chunked = std::fs::read("test_stream_chunked.nrrd").unwrap();
+        let (data,..) = read_nrrd::<f32>("test_stream_chunked.nrrd");
+        std::fs::remove_file("test_stream_mono.nrrd").unwrap();
+        std::fs::remove_file("test_stream_chunked.nrrd").unwrap();
+        assert_eq!(mono,chunked);
+        assert_eq!(data,x);
+
+        // gzip encoding, finishing early
+        let opts = NrrdWriteOptions::new().encoding(Encoding::Gzip);
+        let mut w = NrrdStreamWriter::<f32>::create("test_stream_gz.nrrd",dims,&opts).unwrap();
+        w.write_chunk(&x[0..60]).unwrap();
+        assert!(matches!(w.finish(),Err(NrrdIoError::InconsistentArraySize{expected:120,actual:60})));
+        let mut w = NrrdStreamWriter::<f32>::create("test_stream_gz.nrrd",dims,&opts).unwrap();
+        w.write_chunk(&x[0..60]).unwrap();
+        w.write_chunk(&x[60..]).unwrap();
+        w.finish().unwrap();
+        let (data,..) = read_nrrd::<f32>("test_stream_gz.nrrd");
+        std::fs::remove_file("test_stream_gz.nrrd").unwrap();
+        assert_eq!(data,x);
+    }
+
+    #[test]
+    fn test_meta_round_trip() {
+        let dims = ArrayDim::from_shape(&[3,2]);
+        let x = dims.alloc(1u16);
+        let mut meta = BTreeMap::new();
+        meta.insert(String::from("scanner"),String::from("Bruker BioSpec 9.4T"));
+        meta.insert(String::from("te_ms"),String::from("4.5"));
+        meta.insert(String::from("history"),String::from("line one\nline two"));
+        write_nrrd_with_meta("test_meta.nrrd",&x,dims,&NrrdWriteOptions::default(),&meta).unwrap();
+        let read_meta = read_nrrd_meta("test_meta.nrrd").unwrap();
+        assert_eq!(read_meta,meta);
+
+        // read-modify-write keeps existing pairs
+        let (data,read_dims,h) = read_nrrd_region::<u16>("test_meta.nrrd",&[0,0],&[3,2]).unwrap();
+        let mut meta2 = nrrd_meta(&h).clone();
+        meta2.insert(String::from("processed"),String::from("yes"));
+        write_nrrd_with_meta("test_meta.nrrd",&data,read_dims,&NrrdWriteOptions::default(),&meta2).unwrap();
+        let read_meta = read_nrrd_meta("test_meta.nrrd").unwrap();
+        std::fs::remove_file("test_meta.nrrd").unwrap();
+        assert_eq!(read_meta.len(),4);
+        assert_eq!(read_meta["scanner"],"Bruker BioSpec 9.4T");
+        assert_eq!(read_meta["processed"],"yes");
+
+        let mut bad = BTreeMap::new();
+        bad.insert(String::from("a:=b"),String::from("c"));
+        let r = write_nrrd_with_meta("test_meta_bad.nrrd",&x,dims,&NrrdWriteOptions::default(),&bad);
+        assert!(matches!(r,Err(NrrdIoError::InvalidMeta(_))));
+    }
+
+    #[test]
+    fn test_parse_header_separators() {
+        // each line is split on whichever separator comes first
+        let text = "NRRD0005\ntype: float\ndimension: 1\nsizes: 2\nencoding: raw\ncontent: a:=b\nnote:=x: y\n\n";
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.extend_from_slice(bytemuck::cast_slice(&[1f32, 2.]));
+        std::fs::write("test_parse_header_separators.nrrd",&bytes).unwrap();
+        let h = parse_header("test_parse_header_separators.nrrd").unwrap();
+        std::fs::remove_file("test_parse_header_separators.nrrd").unwrap();
+        assert_eq!(h.field("content"),Some("a:=b"));
+        assert_eq!(h.key_values()["note"],"x: y");
+        assert!(!h.key_values().contains_key("content"));
+    }
+
+    #[test]
+    fn test_data_file_policy() {
+        let dir = std::env::temp_dir().join("array_lib_test_data_file_policy");
+        std::fs::create_dir_all(dir.join("data")).unwrap();
+        let dims = ArrayDim::from_shape(&[4,3]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+
+        // relative sibling with a custom extension
+        let hdr = dir.join("sibling.nhdr");
+        let opts = NrrdWriteOptions::new().attached(false).with_data_file(DataFilePolicy::Sibling(String::from("dat")));
+        write_nrrd_with_options(&hdr,&x,dims,&opts).unwrap();
+        assert_eq!(parse_header(&hdr).unwrap().field("data file"),Some("sibling.dat"));
+        assert!(dir.join("sibling.dat").is_file());
+        assert_eq!(read_nrrd_region::<f32>(&hdr,&[0,0],&[4,3]).unwrap().0,x);
+
+        // explicit relative path in a sub directory
+        let hdr = dir.join("explicit.nhdr");
+        let opts = NrrdWriteOptions::new().attached(false).with_data_file(DataFilePolicy::Explicit("data/explicit.raw".into()));
+        write_nrrd_with_options(&hdr,&x,dims,&opts).unwrap();
+        assert_eq!(parse_header(&hdr).unwrap().field("data file"),Some("data/explicit.raw"));
+        assert_eq!(read_nrrd_region::<f32>(&hdr,&[0,0],&[4,3]).unwrap().0,x);
+
+        // absolute path
+        let abs = dir.join("data").join("absolute.raw");
+        let hdr = dir.join("absolute.nhdr");
+        let opts = NrrdWriteOptions::new().attached(false).with_data_file(DataFilePolicy::Explicit(abs.clone()));
+        write_nrrd_with_options(&hdr,&x,dims,&opts).unwrap();
+        let abs_str = abs.to_string_lossy().to_string();
+        assert_eq!(parse_header(&hdr).unwrap().field("data file"),Some(abs_str.as_str()));
+        assert_eq!(read_nrrd_region::<f32>(&hdr,&[0,0],&[4,3]).unwrap().0,x);
+
+        // reusing an existing blob with the wrong size fails
+        let hdr = dir.join("reuse.nhdr");
+        let bigger = ArrayDim::from_shape(&[4,4]);
+        let opts = NrrdWriteOptions::new().attached(false).with_data_file(DataFilePolicy::ReuseExisting("data/explicit.raw".into()));
+        let r = write_nrrd_with_options::<f32>(&hdr,&[],bigger,&opts);
+        assert!(matches!(r,Err(NrrdIoError::DataFileSizeMismatch{expected:64,actual:48,..})));
+        write_nrrd_with_options::<f32>(&hdr,&[],dims,&opts).unwrap();
+        assert_eq!(read_nrrd_region::<f32>(&hdr,&[0,0],&[4,3]).unwrap().0,x);
+
+        // missing data file on read
+        std::fs::remove_file(dir.join("sibling.dat")).unwrap();
+        let r = read_nrrd_region::<f32>(dir.join("sibling.nhdr"),&[0,0],&[4,3]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(r,Err(NrrdIoError::MissingDataFile(_))));
+    }
+
+    #[test]
+    fn test_read_header_only() {
+        let dims = ArrayDim::from_shape(&[10,8,6]);
+        let x = dims.alloc(0i16);
+        let opts = NrrdWriteOptions::new().attached(false).encoding(Encoding::Gzip);
+        write_nrrd_with_options("test_header_only.nhdr",&x,dims,&opts).unwrap();
+        // without the data file, only the header can be read
+        std::fs::remove_file("test_header_only.raw.gz").unwrap();
+        let r = read_nrrd_header("test_header_only.nhdr");
+        let data = read_nrrd_region::<i16>("test_header_only.nhdr",&[0,0,0],&[1,1,1]);
+        std::fs::remove_file("test_header_only.nhdr").unwrap();
+        let (read_dims,h) = r.unwrap();
+        assert!(matches!(data,Err(NrrdIoError::MissingDataFile(_))));
+        assert_eq!(read_dims.shape(),dims.shape());
+        assert_eq!(nrrd_dtype(&h),Some(NrrdDtype::of::<i16>()));
+        assert!(nrrd_dtype(&h).unwrap().is::<i16>());
+        assert!(!nrrd_dtype(&h).unwrap().is::<u16>());
+    }
+
+    #[test]
+    fn test_zero_size() {
+        std::fs::write("test_zero_size.nrrd","NRRD0004\ntype: float\ndimension: 2\nsizes: 4 0\nencoding: raw\n\n").unwrap();
+        let r = parse_header("test_zero_size.nrrd");
+        std::fs::remove_file("test_zero_size.nrrd").unwrap();
+        assert!(matches!(r,Err(NrrdIoError::Parse{..})));
+    }
+
+    #[test]
+    fn test_read_series() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let names:Vec<std::path::PathBuf> = (0..3).map(|i| format!("test_series_{}.nrrd",i).into()).collect();
+        for (i,name) in names.iter().enumerate() {
+            let x = dims.alloc(i as f32);
+            write_nrrd_with_options(name,&x,dims,&NrrdWriteOptions::default()).unwrap();
+        }
+        let (data,series_dims,_) = read_nrrd_series::<f32>(&names).unwrap();
+
+        // shape mismatch names the offending file
+        let other = ArrayDim::from_shape(&[4,3]);
+        write_nrrd_with_options(&names[1],&other.alloc(0f32),other,&NrrdWriteOptions::default()).unwrap();
+        let r = read_nrrd_series::<f32>(&names);
+        names.iter().for_each(|n| std::fs::remove_file(n).unwrap());
+
+        assert_eq!(series_dims.shape_ns(),&[4,3,2,3]);
+        for i in 0..3 {
+            let idx = series_dims.calc_addr(&[1,2,1,i]);
+            assert_eq!(data[idx],i as f32);
+        }
+        match r {
+            Err(NrrdIoError::SeriesMismatch {path,..}) => assert_eq!(path,names[1]),
+            _=> panic!("expected a series mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_seg_nrrd() {
+        let dims = ArrayDim::from_shape(&[8,8,4]);
+        let mut labels = dims.alloc(0u8);
+        // segment 1 is a 2x2x2 block, segment 2 is a 3x1x1 line
+        for z in 1..3 { for y in 1..3 { for x in 1..3 {
+            labels[dims.calc_addr(&[x,y,z])] = 1;
+        }}}
+        for x in 4..7 {
+            labels[dims.calc_addr(&[x,6,3])] = 2;
+        }
+        let segments = vec![
+            SegmentInfo::new("tumor",1,[1.,0.,0.]),
+            SegmentInfo::new("vessel",2,[0.,0.5,1.]),
+        ];
+        let opts = NrrdWriteOptions::new().encoding(Encoding::Gzip);
+        write_seg_nrrd("test_seg.seg.nrrd",&labels,dims,&segments,&opts).unwrap();
+        let (data,read_dims,read_segments) = read_seg_nrrd("test_seg.seg.nrrd").unwrap();
+        std::fs::remove_file("test_seg.seg.nrrd").unwrap();
+
+        assert_eq!(read_dims.shape(),dims.shape());
+        assert_eq!(read_segments.len(),2);
+        assert_eq!(read_segments[0].name,"tumor");
+        assert_eq!(read_segments[0].label_value,1);
+        assert_eq!(read_segments[0].extent,Some([1,2,1,2,1,2]));
+        assert_eq!(read_segments[1].name,"vessel");
+        assert_eq!(read_segments[1].color,[0.,0.5,1.]);
+        assert_eq!(read_segments[1].extent,Some([4,6,6,6,3,3]));
+        assert_eq!(data.iter().filter(|v| **v == 1).count(),8);
+        assert_eq!(data.iter().filter(|v| **v == 2).count(),3);
+
+        // two layers with an overlapping voxel
+        let layered = ArrayDim::from_shape(&[2,2,1,1]);
+        let (collapsed,cd) = collapse_seg_layers(&[1,2,0,3],layered,0);
+        assert_eq!(cd.shape_ns(),&[2]);
+        assert_eq!(collapsed,vec![2,3]);
+    }
+
+    #[test]
+    fn test_seg_nrrd_layer_axis_last() {
+        // 4x3 voxels with 2 layers stored along the last axis
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let mut labels = dims.alloc(0u8);
+        labels[dims.calc_addr(&[1,1,0])] = 1;
+        labels[dims.calc_addr(&[1,1,1])] = 2;
+        labels[dims.calc_addr(&[3,2,1])] = 2;
+        let mut h = NrrdHeader::new(NrrdDtype::UInt8,&[4,3,2]);
+        h.set_field("kinds","domain domain list");
+        let mut seg = SegmentInfo::new("vessel",2,[0.,0.5,1.]);
+        seg.layer = 1;
+        let mut meta = Default::default();
+        seg.to_meta(&mut meta,0);
+        h.set_key_values(&meta).unwrap();
+        write_header_and_data("test_seg_last.seg.nrrd",h,&labels,&NrrdWriteOptions::new()).unwrap();
+
+        let (data,read_dims,segments) = read_seg_nrrd("test_seg_last.seg.nrrd").unwrap();
+        std::fs::remove_file("test_seg_last.seg.nrrd").unwrap();
+        assert_eq!(segments[0].layer,1);
+        assert_eq!(read_dims.shape_ns(),&[2,4,3]);
+        assert_eq!(data[read_dims.calc_addr(&[0,1,1])],1);
+        assert_eq!(data[read_dims.calc_addr(&[1,1,1])],2);
+
+        let (collapsed,cd) = collapse_seg_layers(&data,read_dims,0);
+        let (in_place,_) = collapse_seg_layers(&labels,dims,2);
+        assert_eq!(cd.shape_ns(),&[4,3]);
+        assert_eq!(collapsed,in_place);
+        assert_eq!(collapsed[cd.calc_addr(&[1,1])],2);
+        assert_eq!(collapsed[cd.calc_addr(&[3,2])],2);
+        assert_eq!(collapsed.iter().filter(|v| **v != 0).count(),2);
+
+        // a trailing singleton axis after the layers, and a single layer before a domain axis
+        for (sizes,kinds,expected) in [([4,2,1],"domain list domain",[2,4,1]),([4,1,3],"domain list domain",[1,4,3])] {
+            let seg_dims = ArrayDim::from_shape(&sizes);
+            let x:Vec<u8> = (0..seg_dims.numel() as u8).collect();
+            let mut h = NrrdHeader::new(NrrdDtype::UInt8,&sizes);
+            h.set_field("kinds",kinds);
+            write_header_and_data("test_seg_singleton.seg.nrrd",h,&x,&NrrdWriteOptions::new()).unwrap();
+            let (data,read_dims,_) = read_seg_nrrd("test_seg_singleton.seg.nrrd").unwrap();
+            std::fs::remove_file("test_seg_singleton.seg.nrrd").unwrap();
+            assert_eq!(&read_dims.shape()[..3],&expected);
+            assert_eq!(data[read_dims.calc_addr(&[sizes[1] - 1,3,sizes[2] - 1])],x[seg_dims.calc_addr(&[3,sizes[1] - 1,sizes[2] - 1])]);
+        }
+    }
+
+    #[test]
+    fn test_write_as_min_max() {
+        let dims = ArrayDim::from_shape(&[50,40]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| -3.0 + (i as f32).sqrt() * 0.37).collect();
+        let lo = x.iter().cloned().fold(f32::INFINITY,f32::min);
+        let hi = x.iter().cloned().fold(f32::NEG_INFINITY,f32::max);
+
+        write_nrrd_as("test_write_as.nrrd",&x,dims,NrrdDtype::UInt16,ScalePolicy::MinMax,&NrrdWriteOptions::default()).unwrap();
+        let (read_dims,h) = read_nrrd_header("test_write_as.nrrd").unwrap();
+        let (y,..) = read_nrrd_scaled("test_write_as.nrrd").unwrap();
+        std::fs::remove_file("test_write_as.nrrd").unwrap();
+
+        assert_eq!(nrrd_dtype(&h),Some(NrrdDtype::UInt16));
+        assert_eq!(read_dims.shape(),dims.shape());
+        // rounding error is at most half a quantization step
+        let tol = 0.5 * (hi - lo) / 65535. + 1e-5;
+        assert!(x.iter().zip(y.iter()).all(|(a,b)| (a - b).abs() <= tol));
+    }
+
+    #[test]
+    fn test_write_as_min_max_float() {
+        let dims = ArrayDim::from_shape(&[5]);
+        let x = [-2.5f32,0.,1e-3,7.25,3e30];
+        write_nrrd_as("test_write_as_float.nrrd",&x,dims,NrrdDtype::Float64,ScalePolicy::MinMax,&NrrdWriteOptions::default()).unwrap();
+        let h = parse_header("test_write_as_float.nrrd").unwrap();
+        let (y,..) = read_nrrd_region::<f64>("test_write_as_float.nrrd",&[0],&[5]).unwrap();
+        std::fs::remove_file("test_write_as_float.nrrd").unwrap();
+        // float outputs are stored unscaled
+        assert!(!h.key_values.contains_key("value_scale"));
+        assert_eq!(y,x.iter().map(|&v| v as f64).collect::<Vec<f64>>());
+    }
+
+    #[test]
+    fn test_write_as_clamp_and_overflow() {
+        let dims = ArrayDim::from_shape(&[4]);
+        let x = [1.4f64,-300.,300.,f64::NAN];
+
+        write_nrrd_as("test_write_as_clamp.nrrd",&x,dims,NrrdDtype::Int8,ScalePolicy::None,&NrrdWriteOptions::default()).unwrap();
+        let (y,..) = read_nrrd_region::<i8>("test_write_as_clamp.nrrd",&[0],&[4]).unwrap();
+        std::fs::remove_file("test_write_as_clamp.nrrd").unwrap();
+        assert_eq!(y,vec![1,-128,127,0]);
+
+        let r = write_nrrd_as("test_write_as_overflow.nrrd",&x,dims,NrrdDtype::Int8,ScalePolicy::ErrorOnOverflow,&NrrdWriteOptions::default());
+        let _ = std::fs::remove_file("test_write_as_overflow.nrrd");
+        assert!(matches!(r,Err(NrrdIoError::Overflow{addr:1,..})));
+    }
+
+    #[test]
+    fn test_nifti_affine_round_trip() {
+        // oblique geometry rotated 30 degrees about z with anisotropic spacing
+        let (c,s) = (30f64.to_radians().cos(),30f64.to_radians().sin());
+        let geom = NrrdGeometry::new(vec![0.5,0.75,2.0],Space::LeftPosteriorSuperior)
+            .with_directions(vec![[c,s,0.],[-s,c,0.],[0.,0.,1.]])
+            .with_origin([12.,-30.,4.]);
+
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let opts = NrrdWriteOptions::new().with_geometry(geom.clone());
+        write_nrrd_with_options("test_affine.nrrd",&dims.alloc(0u8),dims,&opts).unwrap();
+        let (_,h) = read_nrrd_header("test_affine.nrrd").unwrap();
+        std::fs::remove_file("test_affine.nrrd").unwrap();
+
+        let affine = nrrd_geometry_to_nifti_affine(&h).unwrap();
+        assert_eq!(affine,geometry_to_affine(&geom).unwrap());
+        // LPS -> RAS flips the sign of the first two world axes
+        assert!((affine[0][0] + 0.5 * c).abs() < 1e-9);
+        assert!((affine[1][0] + 0.5 * s).abs() < 1e-9);
+        assert!((affine[0][3] + 12.).abs() < 1e-9);
+        assert!((affine[1][3] - 30.).abs() < 1e-9);
+        assert!((affine[2][3] - 4.).abs() < 1e-9);
+
+        let back = nifti_affine_to_nrrd_geometry(affine,Space::LeftPosteriorSuperior).unwrap();
+        back.spacings.iter().zip(geom.spacings.iter()).for_each(|(a,b)| assert!((a - b).abs() < 1e-9));
+        back.directions.unwrap().iter().zip(geom.directions.unwrap().iter()).for_each(|(a,b)| {
+            (0..3).for_each(|i| assert!((a[i] - b[i]).abs() < 1e-9));
+        });
+        (0..3).for_each(|i| assert!((back.origin.unwrap()[i] - geom.origin.unwrap()[i]).abs() < 1e-9));
+
+        // expressing the same affine in RAS leaves directions unflipped
+        let ras = nifti_affine_to_nrrd_geometry(affine,Space::RightAnteriorSuperior).unwrap();
+        assert!((ras.origin.unwrap()[0] + 12.).abs() < 1e-9);
+        assert_eq!(geometry_to_affine(&ras).unwrap(),affine);
+
+        // spaces without an anatomical orientation can't be mapped
+        for space in [Space::ScannerXYZ, Space::RightHanded3D, Space::LeftHanded3D] {
+            let g = NrrdGeometry::new(vec![1.,1.,1.],space);
+            assert!(matches!(geometry_to_affine(&g),Err(NrrdIoError::InvalidGeometry(_))));
+            assert!(matches!(nifti_affine_to_nrrd_geometry(affine,space),Err(NrrdIoError::InvalidGeometry(_))));
+        }
+    }
+
+}
+
+/// errors that can occur when reading or writing nrrd files
+#[derive(Debug)]
+pub enum NrrdIoError {
+    IO(PathBuf, std::io::Error),
+    /// the header could not be parsed
+    Parse{path: PathBuf, msg: String},
+    /// the element type of the file does not match what was requested
+    DtypeMismatch{path: PathBuf, expected: NrrdDtype, found: NrrdDtype},
+    /// the array dimensions don't agree with the reference header
+    ShapeMismatch{expected: Vec<usize>, found: Vec<usize>},
+    /// the data buffer is inconsistent with the array dimensions
+    InconsistentArraySize{expected: usize, actual: usize},
+    /// the space geometry is inconsistent with the array
+    InvalidGeometry(String),
+    /// the requested encoding is not supported by this writer
+    UnsupportedEncoding(String),
+    /// the requested region does not lie within the array
+    InvalidRegion(String),
+    /// a key-value pair can't be represented in a header
+    InvalidMeta(String),
+    /// the data file referenced by a detached header does not exist
+    MissingDataFile(PathBuf),
+    /// an existing data file doesn't have the size implied by the header
+    DataFileSizeMismatch{path: PathBuf, expected: u64, actual: u64},
+    /// the data file options are inconsistent
+    DataFile(String),
+    /// a file in a series doesn't match the first file of the series
+    SeriesMismatch{path: PathBuf, msg: String},
+    /// a value can't be represented by the output element type
+    Overflow{addr: usize, value: f64, dtype: NrrdDtype},
+    /// the array described by a header can't be allocated
+    Alloc(PathBuf, AllocError),
+    /// the extension of a path contradicts the requested attached/detached layout
+    LayoutMismatch{path: PathBuf, attached: bool},
+}
+
+impl Display for NrrdIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NrrdIoError::IO(p, e) => write!(f, "io error for {}: {}", p.display(), e),
+            NrrdIoError::Parse{path, msg} => write!(f, "failed to parse nrrd header {}: {}", path.display(), msg),
+            NrrdIoError::DtypeMismatch{path, expected, found} => write!(f, "{} stores {:?}, expected {:?}", path.display(), found, expected),
+            NrrdIoError::ShapeMismatch{expected, found} => write!(f, "reference header has shape {:?}, array has shape {:?}", found, expected),
+            NrrdIoError::InconsistentArraySize{expected, actual} => write!(f, "expected {} elements, got {}", expected, actual),
+            NrrdIoError::InvalidGeometry(msg) => write!(f, "invalid geometry: {}", msg),
+            NrrdIoError::UnsupportedEncoding(e) => write!(f, "unsupported encoding: {}", e),
+            NrrdIoError::InvalidRegion(msg) => write!(f, "invalid region: {}", msg),
+            NrrdIoError::InvalidMeta(msg) => write!(f, "invalid key-value pair: {}", msg),
+            NrrdIoError::MissingDataFile(p) => write!(f, "data file not found: {}", p.display()),
+            NrrdIoError::DataFileSizeMismatch{path, expected, actual} => write!(f, "data file {} has {} bytes, expected {}", path.display(), actual, expected),
+            NrrdIoError::DataFile(msg) => write!(f, "data file error: {}", msg),
+            NrrdIoError::SeriesMismatch{path, msg} => write!(f, "{} doesn't match the series: {}", path.display(), msg),
+            NrrdIoError::Overflow{addr, value, dtype} => write!(f, "value {} at address {} can't be stored as {:?}", value, addr, dtype),
+            NrrdIoError::Alloc(p, e) => write!(f, "can't read {}: {}", p.display(), e),
+            NrrdIoError::LayoutMismatch{path, attached} => write!(
+                f, "{} can't hold a {} header, use a .{} extension",
+                path.display(), if *attached { "attached" } else { "detached" }, if *attached { "nrrd" } else { "nhdr" }
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NrrdIoError {}
+
+/// element types that can be stored in a nrrd file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NrrdDtype {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Int64,
+    UInt64,
+    Float32,
+    Float64,
+}
+
+impl NrrdDtype {
+
+    /// parses the type field of a nrrd header, accepting all the aliases from the spec
+    pub fn from_header(s: &str) -> Option<NrrdDtype> {
+        use NrrdDtype::*;
+        let t = match s.trim() {
+            "signed char" | "int8" | "int8_t" => Int8,
+            "uchar" | "unsigned char" | "uint8" | "uint8_t" => UInt8,
+            "short" | "short int" | "signed short" | "signed short int" | "int16" | "int16_t" => Int16,
+            "ushort" | "unsigned short" | "unsigned short int" | "uint16" | "uint16_t" => UInt16,
+            "int" | "signed int" | "int32" | "int32_t" => Int32,
+            "uint" | "unsigned int" | "uint32" | "uint32_t" => UInt32,
+            "longlong" | "long long" | "long long int" | "signed long long" | "signed long long int" | "int64" | "int64_t" => Int64,
+            "ulonglong" | "unsigned long long" | "unsigned long long int" | "uint64" | "uint64_t" => UInt64,
+            "float" => Float32,
+            "double" => Float64,
+            _=> return None,
+        };
+        Some(t)
+    }
+
+    /// the canonical type string written to headers
+    pub fn header_str(&self) -> &'static str {
+        match self {
+            NrrdDtype::Int8 => "int8",
+            NrrdDtype::UInt8 => "uint8",
+            NrrdDtype::Int16 => "int16",
+            NrrdDtype::UInt16 => "uint16",
+            NrrdDtype::Int32 => "int32",
+            NrrdDtype::UInt32 => "uint32",
+            NrrdDtype::Int64 => "int64",
+            NrrdDtype::UInt64 => "uint64",
+            NrrdDtype::Float32 => "float",
+            NrrdDtype::Float64 => "double",
+        }
+    }
+
+    /// the nrrd element type of a rust type
+    pub fn of<T:NrrdElement>() -> NrrdDtype {
+        T::DTYPE
+    }
+
+    /// returns true if this is the element type of T
+    pub fn is<T:NrrdElement>(&self) -> bool {
+        *self == T::DTYPE
+    }
+
+    /// returns true for integer element types
+    pub fn is_integer(&self) -> bool {
+        !matches!(self, NrrdDtype::Float32 | NrrdDtype::Float64)
+    }
+
+    /// size of a single element in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            NrrdDtype::Int8 | NrrdDtype::UInt8 => 1,
+            NrrdDtype::Int16 | NrrdDtype::UInt16 => 2,
+            NrrdDtype::Int32 | NrrdDtype::UInt32 | NrrdDtype::Float32 => 4,
+            NrrdDtype::Int64 | NrrdDtype::UInt64 | NrrdDtype::Float64 => 8,
+        }
+    }
+
+}
+
+/// rust types that map directly onto a nrrd element type
+pub trait NrrdElement: Pod {
+    const DTYPE: NrrdDtype;
+}
+
+impl NrrdElement for i8 { const DTYPE: NrrdDtype = NrrdDtype::Int8; }
+impl NrrdElement for u8 { const DTYPE: NrrdDtype = NrrdDtype::UInt8; }
+impl NrrdElement for i16 { const DTYPE: NrrdDtype = NrrdDtype::Int16; }
+impl NrrdElement for u16 { const DTYPE: NrrdDtype = NrrdDtype::UInt16; }
+impl NrrdElement for i32 { const DTYPE: NrrdDtype = NrrdDtype::Int32; }
+impl NrrdElement for u32 { const DTYPE: NrrdDtype = NrrdDtype::UInt32; }
+impl NrrdElement for i64 { const DTYPE: NrrdDtype = NrrdDtype::Int64; }
+impl NrrdElement for u64 { const DTYPE: NrrdDtype = NrrdDtype::UInt64; }
+impl NrrdElement for f32 { const DTYPE: NrrdDtype = NrrdDtype::Float32; }
+impl NrrdElement for f64 { const DTYPE: NrrdDtype = NrrdDtype::Float64; }
+
+/// world coordinate systems a nrrd header can declare
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Space {
+    RightAnteriorSuperior,
+    LeftAnteriorSuperior,
+    LeftPosteriorSuperior,
+    ScannerXYZ,
+    RightHanded3D,
+    LeftHanded3D,
+}
+
+impl Space {
+
+    pub fn header_str(&self) -> &'static str {
+        match self {
+            Space::RightAnteriorSuperior => "right-anterior-superior",
+            Space::LeftAnteriorSuperior => "left-anterior-superior",
+            Space::LeftPosteriorSuperior => "left-posterior-superior",
+            Space::ScannerXYZ => "scanner-xyz",
+            Space::RightHanded3D => "3D-right-handed",
+            Space::LeftHanded3D => "3D-left-handed",
+        }
+    }
+
+    /// parses the space field of a nrrd header, including the abbreviated forms
+    pub fn from_header(s: &str) -> Option<Space> {
+        let space = match s.trim() {
+            "right-anterior-superior" | "RAS" => Space::RightAnteriorSuperior,
+            "left-anterior-superior" | "LAS" => Space::LeftAnteriorSuperior,
+            "left-posterior-superior" | "LPS" => Space::LeftPosteriorSuperior,
+            "scanner-xyz" => Space::ScannerXYZ,
+            "3D-right-handed" => Space::RightHanded3D,
+            "3D-left-handed" => Space::LeftHanded3D,
+            _=> return None,
+        };
+        Some(space)
+    }
+
+}
+
+/// voxel spacing and world-space placement of the spatial (domain) axes of a nrrd. The spatial
+/// axes are the first `spacings.len()` axes of the array unless their positions are given, and
+/// any remaining axes are non-spatial
+#[derive(Debug, Clone, PartialEq)]
+pub struct NrrdGeometry {
+    /// voxel spacing of each spatial axis
+    pub spacings: Vec<f64>,
+    pub space: Space,
+    /// unit direction vector of each spatial axis. The identity is assumed if not set
+    pub directions: Option<Vec<[f64;3]>>,
+    /// world position of the center of the first voxel
+    pub origin: Option<[f64;3]>,
+    /// the array axis of each spatial axis, in increasing order. The leading axes are assumed if
+    /// not set
+    pub axes: Option<Vec<usize>>,
+}
+
+impl NrrdGeometry {
+
+    pub fn new(spacings: Vec<f64>, space: Space) -> Self {
+        NrrdGeometry {
+            spacings,
+            space,
+            directions: None,
+            origin: None,
+            axes: None,
+        }
+    }
+
+    pub fn with_directions(mut self, directions: Vec<[f64;3]>) -> Self {
+        self.directions = Some(directions);
+        self
+    }
+
+    pub fn with_origin(mut self, origin: [f64;3]) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    pub fn with_axes(mut self, axes: Vec<usize>) -> Self {
+        self.axes = Some(axes);
+        self
+    }
+
+    /// the array axis of each spatial axis
+    pub fn spatial_axes(&self) -> Vec<usize> {
+        self.axes.clone().unwrap_or_else(|| (0..self.spacings.len()).collect())
+    }
+
+    /// returns the space direction vectors, scaled by the spacing of each axis
+    pub fn space_directions(&self) -> Vec<[f64;3]> {
+        self.spacings.iter().enumerate().map(|(ax, &s)| {
+            let dir = match &self.directions {
+                Some(d) => d[ax],
+                None => {
+                    let mut d = [0.;3];
+                    d[ax] = 1.;
+                    d
+                }
+            };
+            [dir[0] * s, dir[1] * s, dir[2] * s]
+        }).collect()
+    }
+
+    fn validate(&self, n_axes: usize) -> Result<(), NrrdIoError> {
+        let n = self.spacings.len();
+        if n == 0 || n > 3 {
+            return Err(NrrdIoError::InvalidGeometry(format!("expected 1 to 3 spatial axes, got {}", n)));
+        }
+        if n > n_axes {
+            return Err(NrrdIoError::InvalidGeometry(format!("{} spacings given for an array with {} axes", n, n_axes)));
+        }
+        if let Some(d) = &self.directions {
+            if d.len() != n {
+                return Err(NrrdIoError::InvalidGeometry(format!("{} directions given for {} spatial axes", d.len(), n)));
+            }
+        }
+        if let Some(axes) = &self.axes {
+            if axes.len() != n || axes.windows(2).any(|w| w[0] >= w[1]) || axes.iter().any(|&a| a >= n_axes) {
+                return Err(NrrdIoError::InvalidGeometry(format!(
+                    "spatial axes {:?} must be {} increasing axes of an array with {} axes", axes, n, n_axes
+                )));
+            }
+        }
+        Ok(())
+    }
+
+}
+
+/// returns true for axis kinds that describe a spatial or temporal sampling of a domain
+fn is_domain_kind(kind: &str) -> bool {
+    matches!(kind, "domain" | "space" | "time")
+}
+
+/// sign flips taking coordinates in a space to RAS (and back, as the flip is its own inverse).
+/// Scanner and generic 3D spaces have no defined relation to patient anatomy, so they can't be
+/// mapped
+fn ras_flip(space: Space) -> Result<[f64;3], NrrdIoError> {
+    match space {
+        Space::RightAnteriorSuperior => Ok([1., 1., 1.]),
+        Space::LeftPosteriorSuperior => Ok([-1., -1., 1.]),
+        Space::LeftAnteriorSuperior => Ok([-1., 1., 1.]),
+        _=> Err(NrrdIoError::InvalidGeometry(format!("space {} can't be mapped to RAS", space.header_str()))),
+    }
+}
+
+/// converts the geometry of a nrrd header to a NIfTI style (RAS) voxel-to-world affine. Headers
+/// without a space and space directions give the identity. Spatial axes missing from the header
+/// get a unit spacing along the remaining world axes. Spaces that can't be mapped to RAS are an
+/// error
+pub fn nrrd_geometry_to_nifti_affine(header: &NrrdHeader) -> Result<[[f64;4];4], NrrdIoError> {
+    match header.geometry() {
+        Some(g) => geometry_to_affine(&g),
+        None => Ok([[1.,0.,0.,0.],[0.,1.,0.,0.],[0.,0.,1.,0.],[0.,0.,0.,1.]]),
+    }
+}
+
+/// converts a nrrd geometry to a NIfTI style (RAS) voxel-to-world affine, returning an error for
+/// spaces that can't be mapped to RAS
+pub fn geometry_to_affine(geometry: &NrrdGeometry) -> Result<[[f64;4];4], NrrdIoError> {
+    let flip = ras_flip(geometry.space)?;
+    let dirs = geometry.space_directions();
+    let axes = geometry.spatial_axes();
+    let mut affine = [[0.;4];4];
+    // columns follow the array axes, so a non-spatial leading axis gets a unit column
+    for col in 0..3 {
+        let d = axes.iter().position(|&a| a == col).map(|i| dirs[i]).unwrap_or_else(|| {
+            let mut d = [0.;3];
+            d[col] = 1.;
+            d
+        });
+        for row in 0..3 {
+            affine[row][col] = flip[row] * d[row];
+        }
+    }
+    let origin = geometry.origin.unwrap_or([0.;3]);
+    for row in 0..3 {
+        affine[row][3] = flip[row] * origin[row];
+    }
+    affine[3][3] = 1.;
+    Ok(affine)
+}
+
+/// converts a NIfTI style (RAS) voxel-to-world affine to a nrrd geometry in the given space. The
+/// spacing of each axis is the length of its column in the affine. Spaces that can't be mapped
+/// to RAS are an error
+pub fn nifti_affine_to_nrrd_geometry(affine: [[f64;4];4], space: Space) -> Result<NrrdGeometry, NrrdIoError> {
+    let flip = ras_flip(space)?;
+    let mut spacings = vec![];
+    let mut directions = vec![];
+    for col in 0..3 {
+        let d = [flip[0] * affine[0][col], flip[1] * affine[1][col], flip[2] * affine[2][col]];
+        let s = (d[0]*d[0] + d[1]*d[1] + d[2]*d[2]).sqrt();
+        spacings.push(s);
+        directions.push(if s > 0. { [d[0] / s, d[1] / s, d[2] / s] } else { d });
+    }
+    let origin = [flip[0] * affine[0][3], flip[1] * affine[1][3], flip[2] * affine[2][3]];
+    Ok(NrrdGeometry {
+        spacings,
+        space,
+        directions: Some(directions),
+        origin: Some(origin),
+        axes: None,
+    })
+}
+
+/// escapes backslashes and line breaks in key-value pairs per the nrrd spec
+fn escape_kv(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn unescape_kv(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn format_vector(v: &[f64;3]) -> String {
+    format!("({},{},{})", v[0], v[1], v[2])
+}
+
+fn parse_vector(s: &str) -> Option<[f64;3]> {
+    let s = s.trim().strip_prefix('(')?.strip_suffix(')')?;
+    let v:Vec<f64> = s.split(',').map(|x| x.trim().parse::<f64>().ok()).collect::<Option<_>>()?;
+    v.try_into().ok()
+}
+
+/// a parsed nrrd header. Fields are kept as raw strings in file order, with key-value pairs
+/// (key:=value) stored separately
+#[derive(Debug, Clone, Default)]
+pub struct NrrdHeader {
+    fields: Vec<(String, String)>,
+    key_values: BTreeMap<String, String>,
+    /// length of the header in bytes, which is where the data starts for attached files
+    header_len: u64,
+}
+
+impl NrrdHeader {
+
+    /// returns the raw value of a header field
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    /// sets a header field, replacing an existing value in place
+    pub fn set_field(&mut self, name: &str, value: impl Into<String>) {
+        let value = value.into();
+        match self.fields.iter_mut().find(|(k, _)| k == name) {
+            Some((_, v)) => *v = value,
+            None => self.fields.push((name.to_string(), value)),
+        }
+    }
+
+    /// removes a header field if present
+    pub fn remove_field(&mut self, name: &str) {
+        self.fields.retain(|(k, _)| k != name);
+    }
+
+    /// all header fields in file order
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.fields
+    }
+
+    /// the key-value pairs of the header
+    pub fn key_values(&self) -> &BTreeMap<String, String> {
+        &self.key_values
+    }
+
+    /// sets the key-value pairs of the header. Keys can't contain ':=', ': ' or line breaks, and
+    /// line breaks in values are escaped as \\n
+    pub fn set_key_values(&mut self, meta: &BTreeMap<String, String>) -> Result<(), NrrdIoError> {
+        for k in meta.keys() {
+            if k.is_empty() || k.contains(":=") || k.contains(": ") || k.contains(['\n', '\r']) {
+                return Err(NrrdIoError::InvalidMeta(format!("invalid key '{}'", k.escape_debug())));
+            }
+        }
+        self.key_values = meta.clone();
+        Ok(())
+    }
+
+    /// returns the element type declared by the header
+    pub fn dtype(&self) -> Option<NrrdDtype> {
+        self.field("type").and_then(NrrdDtype::from_header)
+    }
+
+    /// returns the size of each axis
+    pub fn sizes(&self) -> Option<Vec<usize>> {
+        self.field("sizes")?.split_whitespace().map(|s| s.parse::<usize>().ok()).collect()
+    }
+
+    /// number of bytes occupied by the header in the header file
+    pub fn header_len(&self) -> u64 {
+        self.header_len
+    }
+
+    /// creates a minimal header for an array of the given element type and shape
+    pub fn new(dtype: NrrdDtype, sizes: &[usize]) -> NrrdHeader {
+        let mut h = NrrdHeader::default();
+        h.set_field("type", dtype.header_str());
+        h.set_field("dimension", sizes.len().to_string());
+        h.set_field("sizes", sizes.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(" "));
+        h
+    }
+
+    /// returns the kind of each axis, or an empty vec if kinds are not declared
+    pub fn kinds(&self) -> Vec<String> {
+        self.field("kinds").map(|k| k.split_whitespace().map(|s| s.to_string()).collect()).unwrap_or_default()
+    }
+
+    /// inserts a new non-spatial axis in front of the existing axes, keeping the per-axis fields
+    /// consistent
+    pub fn prepend_axis(&mut self, size: usize, kind: &str) {
+        let sizes = self.sizes().unwrap_or_default();
+        let n = sizes.len();
+        self.set_field("dimension", (n + 1).to_string());
+        self.set_field("sizes", std::iter::once(size).chain(sizes).map(|s| s.to_string()).collect::<Vec<_>>().join(" "));
+        if let Some(d) = self.field("space directions") {
+            let d = format!("none {}", d);
+            self.set_field("space directions", d);
+        }
+        let mut kinds = self.kinds();
+        if kinds.is_empty() {
+            kinds = vec![String::from("none"); n];
+        }
+        kinds.insert(0, kind.to_string());
+        self.set_field("kinds", kinds.join(" "));
+    }
+
+    /// sets the space, space directions, kinds and space origin fields. The spatial axes of the
+    /// geometry get their direction and a 'domain' kind. Other axes get a 'none' direction and
+    /// keep their kind, except that domain kinds become 'none'
+    pub fn set_geometry(&mut self, geometry: &NrrdGeometry) -> Result<(), NrrdIoError> {
+        let n_axes = self.sizes().map(|s| s.len()).unwrap_or(0);
+        geometry.validate(n_axes)?;
+
+        let mut directions = vec![String::from("none"); n_axes];
+        let mut kinds = self.kinds();
+        if kinds.len() != n_axes {
+            kinds = vec![String::from("none"); n_axes];
+        }
+        kinds.iter_mut().filter(|k| is_domain_kind(k)).for_each(|k| *k = String::from("none"));
+        for (&axis, dir) in geometry.spatial_axes().iter().zip(geometry.space_directions()) {
+            directions[axis] = format_vector(&dir);
+            kinds[axis] = String::from("domain");
+        }
+
+        self.remove_field("spacings");
+        self.set_field("space", geometry.space.header_str());
+        self.set_field("space directions", directions.join(" "));
+        self.set_field("kinds", kinds.join(" "));
+        match &geometry.origin {
+            Some(o) => self.set_field("space origin", format_vector(o)),
+            None => self.remove_field("space origin"),
+        }
+        Ok(())
+    }
+
+    /// returns the geometry of the spatial axes if the header declares a space and space directions
+    pub fn geometry(&self) -> Option<NrrdGeometry> {
+        let space = Space::from_header(self.field("space")?)?;
+        // the array axis of each direction is kept, skipping the non-spatial axes
+        let (axes, dirs):(Vec<usize>, Vec<[f64;3]>) = self.field("space directions")?
+            .split_whitespace()
+            .enumerate()
+            .filter(|(_, d)| *d != "none")
+            .map(|(axis, d)| parse_vector(d).map(|d| (axis, d)))
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        let leading = axes.iter().enumerate().all(|(i, &a)| i == a);
+        let spacings:Vec<f64> = dirs.iter().map(|d| (d[0]*d[0] + d[1]*d[1] + d[2]*d[2]).sqrt()).collect();
+        let directions = dirs.iter().zip(spacings.iter()).map(|(d, &s)| {
+            if s > 0. { [d[0] / s, d[1] / s, d[2] / s] } else { *d }
+        }).collect();
+        let origin = self.field("space origin").and_then(parse_vector);
+        Some(NrrdGeometry {
+            spacings,
+            space,
+            directions: Some(directions),
+            origin,
+            axes: (!leading).then_some(axes),
+        })
+    }
+
+    /// writes the header text, including the terminating blank line for attached headers
+    fn write_to(&self, w: &mut impl Write, attached: bool) -> std::io::Result<()> {
+        writeln!(w, "NRRD0005")?;
+        writeln!(w, "# Complete NRRD file format specification at:")?;
+        writeln!(w, "# http://teem.sourceforge.net/nrrd/format.html")?;
+        for (k, v) in &self.fields {
+            writeln!(w, "{}: {}", k, v)?;
+        }
+        for (k, v) in &self.key_values {
+            writeln!(w, "{}:={}", escape_kv(k), escape_kv(v))?;
+        }
+        if attached {
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+}
+
+/// options for writing nrrd files with this crate's header writer
+#[derive(Debug, Clone)]
+pub struct NrrdWriteOptions {
+    /// write the data in the same file as the header (.nrrd), or to a separate data file (.nhdr)
+    pub attached: bool,
+    pub encoding: Encoding,
+    pub geometry: Option<NrrdGeometry>,
+    /// custom key-value pairs (key:=value) written to the header
+    pub meta: BTreeMap<String, String>,
+    /// where the data of a detached header is written. Defaults to a sibling of the header with a
+    /// .raw or .raw.gz extension
+    pub data_file: Option<DataFilePolicy>,
+}
+
+/// naming of the data file for detached headers
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataFilePolicy {
+    /// next to the header with the same name and a custom extension, e.g. "raw.gz"
+    Sibling(String),
+    /// a specific path. Relative paths are relative to the directory of the header
+    Explicit(PathBuf),
+    /// an existing data file that is referenced without being rewritten. Relative paths are
+    /// relative to the directory of the header. The size of the file is validated for raw encoding
+    ReuseExisting(PathBuf),
+}
+
+impl Default for NrrdWriteOptions {
+    fn default() -> Self {
+        NrrdWriteOptions {
+            attached: true,
+            encoding: Encoding::Raw,
+            geometry: None,
+            meta: BTreeMap::new(),
+            data_file: None,
+        }
+    }
+}
+
+impl NrrdWriteOptions {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn attached(mut self, attached: bool) -> Self {
+        self.attached = attached;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn with_geometry(mut self, geometry: NrrdGeometry) -> Self {
+        self.geometry = Some(geometry);
+        self
+    }
+
+    pub fn with_meta(mut self, meta: BTreeMap<String, String>) -> Self {
+        self.meta = meta;
+        self
+    }
+
+    pub fn with_data_file(mut self, policy: DataFilePolicy) -> Self {
+        self.data_file = Some(policy);
+        self
+    }
+
+    fn reuses_data(&self) -> bool {
+        matches!(self.data_file, Some(DataFilePolicy::ReuseExisting(_)))
+    }
+
+}
+
+/// maps an io error to a NrrdIoError for the given path
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> NrrdIoError {
+    let path = path.to_path_buf();
+    move |e| NrrdIoError::IO(path, e)
+}
+
+/// returns true for gzip encoding and false for raw. Other encodings aren't supported by the writer
+fn is_gzip(encoding: &Encoding) -> Result<bool, NrrdIoError> {
+    match encoding {
+        Encoding::Raw => Ok(false),
+        Encoding::Gzip => Ok(true),
+        other => Err(NrrdIoError::UnsupportedEncoding(format!("{:?}", other))),
+    }
+}
+
+/// destination of the data payload, either written directly or through a gzip encoder
+enum PayloadSink {
+    Raw(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl PayloadSink {
+
+    fn new(f: File, gzip: bool) -> PayloadSink {
+        if gzip {
+            PayloadSink::Gzip(GzEncoder::new(BufWriter::new(f), Compression::default()))
+        } else {
+            PayloadSink::Raw(BufWriter::new(f))
+        }
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        match self {
+            PayloadSink::Raw(w) => w.write_all(bytes),
+            PayloadSink::Gzip(w) => w.write_all(bytes),
+        }
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            PayloadSink::Raw(mut w) => w.flush(),
+            PayloadSink::Gzip(w) => w.finish()?.flush(),
+        }
+    }
+
+}
+
+/// parses the header of a nrrd file without reading any of the data payload
+fn parse_header(file: impl AsRef<Path>) -> Result<NrrdHeader, NrrdIoError> {
+
+    let path = file.as_ref().to_path_buf();
+    let parse_err = |msg: String| NrrdIoError::Parse {path: path.clone(), msg};
+
+    let f = File::open(&path).map_err(io_err(&path))?;
+    let mut reader = BufReader::new(f);
+
+    let mut header = NrrdHeader::default();
+    let mut line = String::new();
+    let mut first = true;
+
+    loop {
+        line.clear();
+        let n = reader.read_line(&mut line).map_err(io_err(&path))?;
+        if n == 0 {
+            // end of file, which is fine for detached headers
+            break;
+        }
+        header.header_len += n as u64;
+        let l = line.trim_end_matches(['\n', '\r']);
+
+        if first {
+            if !l.starts_with("NRRD000") {
+                return Err(parse_err(format!("missing NRRD magic, found '{}'", l)));
+            }
+            first = false;
+            continue;
+        }
+
+        // a blank line marks the end of the header
+        if l.is_empty() {
+            break;
+        }
+
+        if l.starts_with('#') {
+            continue;
+        }
+
+        // the line is split on the first separator, so field values may contain ':=' and
+        // values of key-value pairs may contain ': '
+        match (l.find(":="), l.find(": ")) {
+            (Some(kv), field) if field.is_none_or(|f| kv < f) => {
+                let (k, v) = (&l[..kv], &l[kv + 2..]);
+                header.key_values.insert(unescape_kv(k), unescape_kv(v));
+            }
+            (_, Some(f)) => {
+                let (k, v) = (&l[..f], &l[f + 2..]);
+                header.fields.push((k.trim().to_string(), v.trim().to_string()));
+            }
+            _ => return Err(parse_err(format!("malformed header line '{}'", l))),
+        }
+    }
+
+    if first {
+        return Err(parse_err(String::from("file is empty")));
+    }
+
+    let dim = header.field("dimension").ok_or_else(|| parse_err(String::from("missing dimension field")))?;
+    let dim:usize = dim.parse().map_err(|_| parse_err(format!("invalid dimension '{}'", dim)))?;
+
+    let sizes = header.sizes().ok_or_else(|| parse_err(String::from("missing or invalid sizes field")))?;
+    if sizes.len() != dim {
+        return Err(parse_err(format!("dimension is {} but {} sizes were given", dim, sizes.len())));
+    }
+    ArrayDim::try_from_shape(&sizes).map_err(|e| parse_err(e.to_string()))?;
+
+    let t = header.field("type").ok_or_else(|| parse_err(String::from("missing type field")))?;
+    if NrrdDtype::from_header(t).is_none() {
+        return Err(parse_err(format!("unsupported type '{}'", t)));
+    }
+
+    Ok(header)
+}
+
+/// read only the header of a nrrd file, returning the array dimensions and header. The data
+/// payload is never read, so this works for detached headers whose data file is missing
+pub fn read_nrrd_header(file:impl AsRef<Path>) -> Result<(ArrayDim, NrrdHeader), NrrdIoError> {
+    let h = parse_header(file)?;
+    let dims = ArrayDim::from_shape(&h.sizes().unwrap_or_default());
+    Ok((dims, h))
+}
+
+/// returns the element type declared by a header
+pub fn nrrd_dtype(header: &NrrdHeader) -> Option<NrrdDtype> {
+    header.dtype()
+}
+
 /// read data from a nrrd, either attached (.nrrd) or detached (.nhdr)
 pub fn read_nrrd<T>(file:impl AsRef<Path>) -> (Vec<T>, ArrayDim, NRRD)
 where T:NRRDType + FromPrimitive
@@ -15,17 +1440,924 @@ where T:NRRDType + FromPrimitive
     (data, dims, nrrd)
 }
 
+/// read data from a nrrd, either attached (.nrrd) or detached (.nhdr). The header is validated
+/// and the stored element type must match T before any data is decoded. A payload that is
+/// shorter than the header declares is an IO error
+pub fn try_read_nrrd<T>(file:impl AsRef<Path>) -> Result<(Vec<T>, ArrayDim, NrrdHeader), NrrdIoError>
+where T:NrrdElement
+{
+    let path = file.as_ref();
+    let h = parse_header(path)?;
+    check_dtype::<T>(path, &h)?;
+    let dims = ArrayDim::from_shape(&h.sizes().unwrap_or_default());
+    let mut data = dims.try_alloc(T::zeroed()).map_err(|e| NrrdIoError::Alloc(path.to_path_buf(), e))?;
+    read_payload_into(path, &h, &mut data)?;
+    Ok((data, dims, h))
+}
+
+/// the kind of each axis of an array read from a nrrd, and the axis of the file it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NrrdAxisInfo {
+    /// the kind of each axis, such as domain, space, time, 3-vector, RGB-color, complex or list.
+    /// Axes are "none" when the header doesn't declare kinds
+    pub kinds: Vec<String>,
+    /// the axis of the file for each axis of the array
+    pub file_axes: Vec<usize>,
+}
+
+impl NrrdAxisInfo {
+
+    /// returns true for axes that sample a spatial or temporal domain
+    pub fn is_domain(&self, axis: usize) -> bool {
+        self.kinds.get(axis).is_some_and(|k| is_domain_kind(k))
+    }
+
+    /// the axes that don't sample a domain, such as vector or color components
+    pub fn non_domain_axes(&self) -> Vec<usize> {
+        (0..self.kinds.len()).filter(|&a| !self.is_domain(a)).collect()
+    }
+
+}
+
+/// options controlling how nrrd files are read
+#[derive(Debug, Clone, Default)]
+pub struct NrrdReadOptions {
+    /// move every axis that doesn't sample a domain (vector, color, complex and list axes, as well
+    /// as axes of kind none) ahead of the domain axes, keeping the order within each group. The
+    /// domain axes then always start after the non-domain axes
+    pub non_domain_leading: bool,
+}
+
+impl NrrdReadOptions {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn non_domain_leading(mut self, non_domain_leading: bool) -> Self {
+        self.non_domain_leading = non_domain_leading;
+        self
+    }
+
+}
+
+/// read data from a nrrd as with try_read_nrrd, also returning the kind of each axis. Axes are
+/// reordered as set by the options
+pub fn read_nrrd_with_axes<T>(file:impl AsRef<Path>, opts:&NrrdReadOptions) -> Result<(Vec<T>, ArrayDim, NrrdAxisInfo, NrrdHeader), NrrdIoError>
+where T:NrrdElement + Send + Sync
+{
+    let (data, dims, h) = try_read_nrrd::<T>(&file)?;
+    let sizes = h.sizes().unwrap_or_default();
+    let mut kinds = h.kinds();
+    if kinds.is_empty() {
+        kinds = vec![String::from("none"); sizes.len()];
+    }
+    if kinds.len() != sizes.len() {
+        return Err(NrrdIoError::Parse{path: file.as_ref().to_path_buf(), msg: format!("{} kinds were given for {} axes", kinds.len(), sizes.len())});
+    }
+
+    let mut info = NrrdAxisInfo{kinds, file_axes: (0..sizes.len()).collect()};
+    if !opts.non_domain_leading {
+        return Ok((data, dims, info, h));
+    }
+
+    let (mut order, domain):(Vec<usize>, Vec<usize>) = info.file_axes.iter().partition(|&&a| !info.is_domain(a));
+    order.extend(domain);
+    if order == info.file_axes {
+        return Ok((data, dims, info, h));
+    }
+
+    // singleton axes don't change the layout, so only the others are permuted. If the others
+    // keep their order, the data is already laid out as requested
+    let kept:Vec<usize> = (0..sizes.len()).filter(|&a| sizes[a] > 1).collect();
+    let kept_order:Vec<usize> = order.iter().filter_map(|a| kept.iter().position(|k| k == a)).collect();
+    let data = if kept_order.iter().enumerate().all(|(i, &k)| i == k) {
+        data
+    } else {
+        let kept_dims = ArrayDim::from_shape(&kept.iter().map(|&a| sizes[a]).collect::<Vec<_>>());
+        let mut permuted = kept_dims.try_alloc(T::zeroed()).map_err(|e| NrrdIoError::Alloc(file.as_ref().to_path_buf(), e))?;
+        kept_dims.permute(&data, &mut permuted, &kept_order);
+        permuted
+    };
+
+    let dims = ArrayDim::from_shape(&order.iter().map(|&a| sizes[a]).collect::<Vec<_>>());
+    info.kinds = order.iter().map(|&a| info.kinds[a].clone()).collect();
+    info.file_axes = order;
+    Ok((data, dims, info, h))
+}
+
+/// the files produced by write_nrrd
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NrrdFiles {
+    /// the header, which also holds the data when attached
+    pub header: PathBuf,
+    /// the data file referenced by a detached header
+    pub data: Option<PathBuf>,
+}
+
+impl NrrdFiles {
+
+    /// every file that was written
+    pub fn paths(&self) -> Vec<&Path> {
+        std::iter::once(self.header.as_path()).chain(self.data.as_deref()).collect()
+    }
+
+}
+
+/// resolves the header path and layout for a write. A .nrrd extension is always attached and a
+/// .nhdr extension is always detached, and asking for the other layout is an error. Any other
+/// path, including one without an extension, has .nrrd or .nhdr appended to match the layout
+fn resolve_layout(path: &Path, attached: bool) -> Result<PathBuf, NrrdIoError> {
+    let ext = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("nrrd") | Some("nhdr") if (ext.as_deref() == Some("nrrd")) != attached => {
+            Err(NrrdIoError::LayoutMismatch{path: path.to_path_buf(), attached})
+        }
+        Some("nrrd") | Some("nhdr") => Ok(path.to_path_buf()),
+        _ => {
+            let mut p = path.as_os_str().to_owned();
+            p.push(if attached { ".nrrd" } else { ".nhdr" });
+            Ok(PathBuf::from(p))
+        }
+    }
+}
+
 /// write a nrrd file from an array given a set of dimensions and an optional reference header.
-/// The dimensions of the reference header must match the dimensions given.
-pub fn write_nrrd<T>(file: impl AsRef<Path>, array:&[T], dims:ArrayDim, reference_header:Option<&NRRD>, attached:bool, encoding: Encoding)
+/// The dimensions of the reference header must match the dimensions given. The layout must agree
+/// with the extension of the file as described for try_write_nrrd
+pub fn write_nrrd<T>(file: impl AsRef<Path>, array:&[T], dims:ArrayDim, reference_header:Option<&NRRD>, attached:bool, encoding: Encoding) -> NrrdFiles
+where T:NRRDType
+{
+    try_write_nrrd(file, array, dims, reference_header, attached, encoding).expect("failed to write nrrd")
+}
+
+/// write a nrrd file from an array given a set of dimensions and an optional reference header,
+/// returning an error if the reference header doesn't match the dimensions given. Files ending in
+/// .nrrd must be attached and files ending in .nhdr must be detached. Other paths have the
+/// extension for the layout appended, so "img" is written as "img.nrrd" or "img.nhdr". The header
+/// and any detached data file that were written are returned
+pub fn try_write_nrrd<T>(file: impl AsRef<Path>, array:&[T], dims:ArrayDim, reference_header:Option<&NRRD>, attached:bool, encoding: Encoding) -> Result<NrrdFiles, NrrdIoError>
 where T:NRRDType
 {
-    assert_eq!(dims.numel(), array.len(), "data buffer and array dims must be consistent");
+    if dims.numel() != array.len() {
+        return Err(NrrdIoError::InconsistentArraySize {expected: dims.numel(), actual: array.len()});
+    }
+
+    let path = resolve_layout(file.as_ref(), attached)?;
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        if !parent.is_dir() {
+            let e = std::io::Error::new(std::io::ErrorKind::NotFound, "parent directory does not exist");
+            return Err(NrrdIoError::IO(path.to_path_buf(), e));
+        }
+    }
+
     if let Some(ref_header) = reference_header {
-        assert_eq!(ref_header.shape(),dims.shape_ns(),"reference nhdr must have the same dimensionality as the array");
-        nrrd_rs::write_nrrd(file, ref_header, array, attached, encoding);
+        if ref_header.shape() != dims.shape_ns() {
+            return Err(NrrdIoError::ShapeMismatch {expected: dims.shape_ns().to_vec(), found: ref_header.shape().to_vec()});
+        }
+        nrrd_rs::write_nrrd(&path, ref_header, array, attached, encoding);
     }else {
         let h = NRRD::new_from_dims::<T>(dims.shape_ns());
-        nrrd_rs::write_nrrd(file, &h, array, attached, encoding);
+        nrrd_rs::write_nrrd(&path, &h, array, attached, encoding);
+    };
+
+    if attached {
+        return Ok(NrrdFiles{header: path, data: None});
+    }
+    // the data file is named by nrrd_rs, so it's found from the header that was written
+    let (data, _) = data_location(&path, &parse_header(&path)?)?;
+    Ok(NrrdFiles{header: path, data: Some(data)})
+}
+
+/// write a nrrd file with options controlling the encoding, layout and geometry of the output.
+/// Detached headers (attached = false) write the data next to the header with a .raw or .raw.gz
+/// extension unless a data file policy is given. When reusing an existing data file, the array
+/// is not written and may be empty
+pub fn write_nrrd_with_options<T>(file: impl AsRef<Path>, array:&[T], dims:ArrayDim, opts:&NrrdWriteOptions) -> Result<(), NrrdIoError>
+where T:NrrdElement
+{
+    if !opts.reuses_data() && dims.numel() != array.len() {
+        return Err(NrrdIoError::InconsistentArraySize {expected: dims.numel(), actual: array.len()});
+    }
+    let mut h = NrrdHeader::new(T::DTYPE, dims.shape_ns());
+    if let Some(geom) = &opts.geometry {
+        h.set_geometry(geom)?;
+    }
+    h.set_key_values(&opts.meta)?;
+    write_header_and_data(file, h, array, opts)
+}
+
+/// write a nrrd file with custom key-value pairs in the header
+pub fn write_nrrd_with_meta<T>(file: impl AsRef<Path>, array:&[T], dims:ArrayDim, opts:&NrrdWriteOptions, meta:&BTreeMap<String, String>) -> Result<(), NrrdIoError>
+where T:NrrdElement
+{
+    let opts = opts.clone().with_meta(meta.clone());
+    write_nrrd_with_options(file, array, dims, &opts)
+}
+
+/// returns the custom key-value pairs of a header
+pub fn nrrd_meta(header: &NrrdHeader) -> &BTreeMap<String, String> {
+    header.key_values()
+}
+
+/// reads the custom key-value pairs from the header of a nrrd file without reading the data
+pub fn read_nrrd_meta(file: impl AsRef<Path>) -> Result<BTreeMap<String, String>, NrrdIoError> {
+    Ok(parse_header(file)?.key_values)
+}
+
+/// write complex data to a nrrd file. Complex values are stored as a leading axis of length 2
+/// with kind 'complex', so the file reads as a 2 x .. real array with readers that don't know
+/// about complex data. Any geometry applies to the axes following the complex axis
+pub fn write_nrrd_complex<T>(file: impl AsRef<Path>, array:&[Complex<T>], dims:ArrayDim, opts:&NrrdWriteOptions) -> Result<(), NrrdIoError>
+where T:NrrdElement, Complex<T>:Pod
+{
+    if !opts.reuses_data() && dims.numel() != array.len() {
+        return Err(NrrdIoError::InconsistentArraySize {expected: dims.numel(), actual: array.len()});
+    }
+    let mut h = NrrdHeader::new(T::DTYPE, dims.shape_ns());
+    if let Some(geom) = &opts.geometry {
+        h.set_geometry(geom)?;
+    }
+    h.prepend_axis(2, "complex");
+    h.set_key_values(&opts.meta)?;
+    // complex values are already interleaved (re, im) in memory
+    let interleaved:&[T] = bytemuck::cast_slice(array);
+    write_header_and_data(file, h, interleaved, opts)
+}
+
+/// read complex data from a nrrd file. A leading non-domain axis of length 2 is interpreted as
+/// the real and imaginary components, and the remaining axes make up the array dimensions. Files
+/// without a complex axis are read as real data with the imaginary part set to 0
+pub fn read_nrrd_complex<T>(file:impl AsRef<Path>) -> Result<(Vec<Complex<T>>, ArrayDim, NrrdHeader), NrrdIoError>
+where T:NrrdElement + Zero
+{
+    let (data, dims, h) = try_read_nrrd::<T>(file)?;
+    let is_complex = h.sizes().map(|s| s.first() == Some(&2)).unwrap_or(false) &&
+        h.kinds().first().map(|k| !is_domain_kind(k)).unwrap_or(false);
+
+    if is_complex {
+        let dims = ArrayDim::from_shape(&dims.shape_ns()[1..]);
+        let data = data.chunks_exact(2).map(|c| Complex::new(c[0], c[1])).collect();
+        Ok((data, dims, h))
+    } else {
+        let data = data.into_iter().map(|x| Complex::new(x, T::zero())).collect();
+        Ok((data, dims, h))
+    }
+}
+
+/// writes the header and data payload for a fully populated header. The type, dimension and sizes
+/// must already be set
+fn write_header_and_data<T:Pod>(file: impl AsRef<Path>, h: NrrdHeader, array:&[T], opts:&NrrdWriteOptions) -> Result<(), NrrdIoError> {
+    match start_nrrd::<T>(file.as_ref(), h, opts)? {
+        Some((data_path, mut sink)) => {
+            sink.write_all(bytemuck::cast_slice(array)).map_err(io_err(&data_path))?;
+            sink.finish().map_err(io_err(&data_path))
+        }
+        // the data file already exists and has been validated
+        None => Ok(()),
+    }
+}
+
+/// writes the header for a fully populated header and returns the path and sink for the data
+/// payload. For attached headers the payload follows the header in the same file. Nothing is
+/// returned when the header refers to an existing data file
+fn start_nrrd<T:Pod>(path: &Path, mut h: NrrdHeader, opts:&NrrdWriteOptions) -> Result<Option<(PathBuf, PayloadSink)>, NrrdIoError> {
+
+    let gzip = is_gzip(&opts.encoding)?;
+
+    if size_of::<T>() > 1 {
+        h.set_field("endian", if cfg!(target_endian = "big") { "big" } else { "little" });
+    }
+    h.set_field("encoding", if gzip { "gzip" } else { "raw" });
+
+    if opts.attached {
+        if opts.data_file.is_some() {
+            return Err(NrrdIoError::DataFile(String::from("a data file policy can't be used with an attached header")));
+        }
+        let mut f = File::create(path).map_err(io_err(path))?;
+        // header is small, so it's written unbuffered ahead of the payload writer
+        let mut header = Vec::new();
+        h.write_to(&mut header, true).map_err(io_err(path))?;
+        f.write_all(&header).map_err(io_err(path))?;
+        return Ok(Some((path.to_path_buf(), PayloadSink::new(f, gzip))));
+    }
+
+    let header_dir = path.parent().unwrap_or(Path::new(""));
+    let default_ext = if gzip { "raw.gz" } else { "raw" };
+
+    // the path written to the data file field, and where that file lives relative to us
+    let (data_field, data_path, reuse) = match &opts.data_file {
+        None => {
+            let p = path.with_extension(default_ext);
+            (PathBuf::from(p.file_name().unwrap()), p, false)
+        }
+        Some(DataFilePolicy::Sibling(ext)) => {
+            let p = path.with_extension(ext.trim_start_matches('.'));
+            (PathBuf::from(p.file_name().unwrap()), p, false)
+        }
+        Some(DataFilePolicy::Explicit(p)) => (p.clone(), header_dir.join(p), false),
+        Some(DataFilePolicy::ReuseExisting(p)) => (p.clone(), header_dir.join(p), true),
+    };
+
+    if reuse {
+        let actual = std::fs::metadata(&data_path).map_err(|_| NrrdIoError::MissingDataFile(data_path.clone()))?.len();
+        // the size of compressed data can't be known ahead of time
+        if !gzip {
+            let expected = (h.sizes().unwrap_or_default().iter().product::<usize>() * size_of::<T>()) as u64;
+            if actual != expected {
+                return Err(NrrdIoError::DataFileSizeMismatch {path: data_path, expected, actual});
+            }
+        }
+    }
+
+    h.set_field("data file", data_field.to_string_lossy());
+
+    let f = File::create(path).map_err(io_err(path))?;
+    let mut w = BufWriter::new(f);
+    h.write_to(&mut w, false).map_err(io_err(path))?;
+    w.flush().map_err(io_err(path))?;
+
+    if reuse {
+        return Ok(None);
+    }
+
+    let f = File::create(&data_path).map_err(io_err(&data_path))?;
+    Ok(Some((data_path, PayloadSink::new(f, gzip))))
+}
+
+/// writes a nrrd file incrementally for arrays that are too large to hold in memory. The header
+/// is written on creation, followed by any number of sequential chunks totalling the number of
+/// elements in the array. Gzip encoding is streamed through the encoder as chunks arrive
+pub struct NrrdStreamWriter<T:NrrdElement> {
+    data_path: PathBuf,
+    sink: PayloadSink,
+    expected: usize,
+    written: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T:NrrdElement> NrrdStreamWriter<T> {
+
+    /// writes the header for an array of the given dimensions and prepares for the data chunks
+    pub fn create(file: impl AsRef<Path>, dims: ArrayDim, opts: &NrrdWriteOptions) -> Result<NrrdStreamWriter<T>, NrrdIoError> {
+        let mut h = NrrdHeader::new(T::DTYPE, dims.shape_ns());
+        if let Some(geom) = &opts.geometry {
+            h.set_geometry(geom)?;
+        }
+        h.set_key_values(&opts.meta)?;
+        let (data_path, sink) = start_nrrd::<T>(file.as_ref(), h, opts)?
+            .ok_or_else(|| NrrdIoError::DataFile(String::from("can't stream data into an existing data file")))?;
+        Ok(NrrdStreamWriter {
+            data_path,
+            sink,
+            expected: dims.numel(),
+            written: 0,
+            _marker: PhantomData,
+        })
+    }
+
+    /// appends the next chunk of elements. Writing beyond the number of elements declared by the
+    /// header is an error
+    pub fn write_chunk(&mut self, chunk: &[T]) -> Result<(), NrrdIoError> {
+        if self.written + chunk.len() > self.expected {
+            return Err(NrrdIoError::InconsistentArraySize {expected: self.expected, actual: self.written + chunk.len()});
+        }
+        self.sink.write_all(bytemuck::cast_slice(chunk)).map_err(io_err(&self.data_path))?;
+        self.written += chunk.len();
+        Ok(())
+    }
+
+    /// number of elements still expected before the writer can be finished
+    pub fn remaining(&self) -> usize {
+        self.expected - self.written
+    }
+
+    /// flushes the payload, returning an error if fewer elements were written than declared
+    pub fn finish(self) -> Result<(), NrrdIoError> {
+        if self.written != self.expected {
+            return Err(NrrdIoError::InconsistentArraySize {expected: self.expected, actual: self.written});
+        }
+        self.sink.finish().map_err(io_err(&self.data_path))
+    }
+
+}
+
+/// read a hyper-rectangular region of a nrrd given an offset and size for each axis. For raw
+/// encoding, only the requested region is read from disk. Gzip encoded data must be decoded from
+/// the start of the stream up to the end of the region, which is much slower for large files
+pub fn read_nrrd_region<T>(file:impl AsRef<Path>, offset:&[usize], size:&[usize]) -> Result<(Vec<T>, ArrayDim, NrrdHeader), NrrdIoError>
+where T:NrrdElement
+{
+    let path = file.as_ref();
+    let h = parse_header(path)?;
+    check_dtype::<T>(path, &h)?;
+
+    let dims = ArrayDim::from_shape(&h.sizes().unwrap_or_default());
+    let region = dims.region_dims(offset, size).map_err(NrrdIoError::InvalidRegion)?;
+
+    let (data_path, start) = data_location(path, &h)?;
+    let gzip = data_encoding(path, &h)?;
+    let el_size = size_of::<T>();
+
+    let mut out = region.try_alloc(T::zeroed()).map_err(|e| NrrdIoError::Alloc(path.to_path_buf(), e))?;
+    let mut n_read = 0;
+
+    let f = File::open(&data_path).map_err(io_err(&data_path))?;
+
+    if gzip {
+        let mut f = f;
+        f.seek(SeekFrom::Start(start)).map_err(io_err(&data_path))?;
+        let mut dec = GzDecoder::new(BufReader::new(f));
+        // position in the decoded stream in elements
+        let mut pos = 0;
+        for (addr, len) in dims.region_runs(offset, size) {
+            let skip = ((addr - pos) * el_size) as u64;
+            std::io::copy(&mut (&mut dec).take(skip), &mut std::io::sink()).map_err(io_err(&data_path))?;
+            let dst:&mut [u8] = bytemuck::cast_slice_mut(&mut out[n_read..n_read + len]);
+            dec.read_exact(dst).map_err(io_err(&data_path))?;
+            n_read += len;
+            pos = addr + len;
+        }
+    } else {
+        let mut f = f;
+        f.seek(SeekFrom::Start(start)).map_err(io_err(&data_path))?;
+        let mut f = BufReader::new(f);
+        // runs are skipped to with relative seeks, which keep the buffer when the next run is in
+        // it. The position is in elements from the start of the data
+        let mut pos = 0;
+        for (addr, len) in dims.region_runs(offset, size) {
+            f.seek_relative(((addr - pos) * el_size) as i64).map_err(io_err(&data_path))?;
+            let dst:&mut [u8] = bytemuck::cast_slice_mut(&mut out[n_read..n_read + len]);
+            f.read_exact(dst).map_err(io_err(&data_path))?;
+            n_read += len;
+            pos = addr + len;
+        }
+    }
+
+    if needs_byte_swap(&h) {
+        swap_bytes(&mut out);
+    }
+
+    Ok((out, region, h))
+}
+
+/// read a series of nrrd files with identical shape and element type, stacking them along a new
+/// trailing axis. The header of the first file is returned for its geometry. Each file is read
+/// directly into its slab of the output, so no more than the output is held in memory
+pub fn read_nrrd_series<T>(paths:&[PathBuf]) -> Result<(Vec<T>, ArrayDim, NrrdHeader), NrrdIoError>
+where T:NrrdElement
+{
+    let first = paths.first().ok_or_else(|| NrrdIoError::DataFile(String::from("series is empty")))?;
+
+    // validate every header before reading any data
+    let headers = paths.iter().map(|p| parse_header(p)).collect::<Result<Vec<_>, _>>()?;
+    let shape = headers[0].sizes().unwrap_or_default();
+    for (p, h) in paths.iter().zip(headers.iter()) {
+        check_dtype::<T>(p, h)?;
+        let s = h.sizes().unwrap_or_default();
+        if s != shape {
+            return Err(NrrdIoError::SeriesMismatch {path: p.clone(), msg: format!("shape {:?} differs from {:?} of {}", s, shape, first.display())});
+        }
+    }
+
+    let vol_dims = ArrayDim::from_shape(&shape);
+    let n_axes = vol_dims.shape_ns().len();
+    if n_axes >= crate::N_DIMS {
+        return Err(NrrdIoError::SeriesMismatch {path: first.clone(), msg: String::from("no axis left to stack along")});
+    }
+    // a single element volume has no leading axes to keep
+    let stack_axis = if vol_dims.numel() == 1 { 0 } else { n_axes };
+    let dims = vol_dims.with_dim(stack_axis, paths.len());
+
+    let mut out = dims.try_alloc(T::zeroed()).map_err(|e| NrrdIoError::Alloc(first.clone(), e))?;
+    for ((p, h), slab) in paths.iter().zip(headers.iter()).zip(out.chunks_exact_mut(vol_dims.numel())) {
+        read_payload_into(p, h, slab)?;
+    }
+
+    let h = headers.into_iter().next().unwrap();
+    Ok((out, dims, h))
+}
+
+/// reads the entire data payload of a nrrd into a buffer of the same size
+fn read_payload_into<T:NrrdElement>(path: &Path, h: &NrrdHeader, dst: &mut [T]) -> Result<(), NrrdIoError> {
+    let (data_path, start) = data_location(path, h)?;
+    let gzip = data_encoding(path, h)?;
+    let mut f = File::open(&data_path).map_err(io_err(&data_path))?;
+    f.seek(SeekFrom::Start(start)).map_err(io_err(&data_path))?;
+    let bytes:&mut [u8] = bytemuck::cast_slice_mut(dst);
+    if gzip {
+        GzDecoder::new(BufReader::new(f)).read_exact(bytes).map_err(io_err(&data_path))?;
+    } else {
+        f.read_exact(bytes).map_err(io_err(&data_path))?;
+    }
+    if needs_byte_swap(h) {
+        swap_bytes(dst);
+    }
+    Ok(())
+}
+
+/// a segment described by the key-value pairs of a Slicer segmentation (.seg.nrrd)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentInfo {
+    pub id: String,
+    pub name: String,
+    /// voxel value of the segment within its layer
+    pub label_value: u8,
+    /// rgb color in the range 0 to 1
+    pub color: [f32;3],
+    /// inclusive voxel bounds of the segment as [x0, x1, y0, y1, z0, z1]
+    pub extent: Option<[usize;6]>,
+    /// layer of the segment for segmentations with overlapping segments
+    pub layer: usize,
+}
+
+impl SegmentInfo {
+
+    pub fn new(name: &str, label_value: u8, color: [f32;3]) -> SegmentInfo {
+        SegmentInfo {
+            id: name.to_string(),
+            name: name.to_string(),
+            label_value,
+            color,
+            extent: None,
+            layer: 0,
+        }
+    }
+
+    /// parses segment `i` from the key-value pairs of a header
+    fn from_meta(meta: &BTreeMap<String, String>, i: usize) -> Option<Result<SegmentInfo, String>> {
+        let key = |k: &str| format!("Segment{}_{}", i, k);
+        let id = meta.get(&key("ID"))?.clone();
+        let name = meta.get(&key("Name")).cloned().unwrap_or_else(|| id.clone());
+
+        let parse = || -> Result<SegmentInfo, String> {
+            let label_value = match meta.get(&key("LabelValue")) {
+                Some(v) => v.trim().parse().map_err(|_| format!("invalid label value '{}' for segment {}", v, i))?,
+                // older segmentations have one segment per layer with a value of 1
+                None => 1,
+            };
+            let layer = match meta.get(&key("Layer")) {
+                Some(v) => v.trim().parse().map_err(|_| format!("invalid layer '{}' for segment {}", v, i))?,
+                None => 0,
+            };
+            let color = match meta.get(&key("Color")) {
+                Some(v) => {
+                    let c:Vec<f32> = v.split_whitespace().map(|x| x.parse().ok()).collect::<Option<_>>()
+                        .ok_or_else(|| format!("invalid color '{}' for segment {}", v, i))?;
+                    c.try_into().map_err(|_| format!("expected 3 color components for segment {}", i))?
+                }
+                None => [0.5;3],
+            };
+            let extent = match meta.get(&key("Extent")) {
+                Some(v) => {
+                    let e:Vec<usize> = v.split_whitespace().map(|x| x.parse().ok()).collect::<Option<_>>()
+                        .ok_or_else(|| format!("invalid extent '{}' for segment {}", v, i))?;
+                    Some(e.try_into().map_err(|_| format!("expected 6 extent values for segment {}", i))?)
+                }
+                None => None,
+            };
+            Ok(SegmentInfo {id: id.clone(), name: name.clone(), label_value, color, extent, layer})
+        };
+        Some(parse())
+    }
+
+    /// writes the key-value pairs for segment `i`
+    fn to_meta(&self, meta: &mut BTreeMap<String, String>, i: usize) {
+        let key = |k: &str| format!("Segment{}_{}", i, k);
+        meta.insert(key("ID"), self.id.clone());
+        meta.insert(key("Name"), self.name.clone());
+        meta.insert(key("NameAutoGenerated"), String::from("0"));
+        meta.insert(key("LabelValue"), self.label_value.to_string());
+        meta.insert(key("Layer"), self.layer.to_string());
+        meta.insert(key("Color"), format!("{} {} {}", self.color[0], self.color[1], self.color[2]));
+        meta.insert(key("ColorAutoGenerated"), String::from("0"));
+        if let Some(e) = &self.extent {
+            meta.insert(key("Extent"), e.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(" "));
+        }
+        meta.insert(key("Tags"), String::from("|"));
+    }
+
+}
+
+/// read a Slicer segmentation file (.seg.nrrd), returning the label voxels, their dimensions and
+/// the segments described in the header. Segmentations with overlapping segments have a layer
+/// axis of kind 'list', which is moved to the first axis of the returned dimensions wherever it is
+/// in the file. The layer of each segment is given by its SegmentInfo (see collapse_seg_layers)
+pub fn read_seg_nrrd(file:impl AsRef<Path>) -> Result<(Vec<u8>, ArrayDim, Vec<SegmentInfo>), NrrdIoError> {
+    let path = file.as_ref();
+    let h = parse_header(path)?;
+    check_dtype::<u8>(path, &h)?;
+
+    let mut segments = vec![];
+    let mut i = 0;
+    while let Some(seg) = SegmentInfo::from_meta(&h.key_values, i) {
+        segments.push(seg.map_err(|msg| NrrdIoError::Parse {path: path.to_path_buf(), msg})?);
+        i += 1;
+    }
+
+    let dims = ArrayDim::from_shape(&h.sizes().unwrap_or_default());
+    let mut data = dims.try_alloc(0u8).map_err(|e| NrrdIoError::Alloc(path.to_path_buf(), e))?;
+    read_payload_into(path, &h, &mut data)?;
+
+    match h.kinds().iter().position(|k| k == "list") {
+        // a single layer is moved to the front without moving any data
+        Some(axis) if axis > 0 && dims.size(axis) == 1 => {
+            let mut shape = dims.shape().to_vec();
+            shape.remove(axis);
+            shape.insert(0, 1);
+            Ok((data, ArrayDim::from_shape(&shape), segments))
+        }
+        Some(axis) if axis > 0 => {
+            // the order spans the non-singleton dims, which include the layer axis
+            let ndim = dims.shape_ns().len();
+            let order:Vec<usize> = std::iter::once(axis).chain((0..ndim).filter(|&a| a != axis)).collect();
+            let mut layered = dims.try_alloc(0u8).map_err(|e| NrrdIoError::Alloc(path.to_path_buf(), e))?;
+            let dims = dims.permute(&data, &mut layered, &order);
+            Ok((layered, dims, segments))
+        }
+        _=> Ok((data, dims, segments)),
+    }
+}
+
+/// collapses the layer axis of a segmentation into a single label volume. Later layers overwrite
+/// earlier layers where segments overlap, so this is lossy for overlapping segments. The layer
+/// axis is 0 for segmentations from read_seg_nrrd
+pub fn collapse_seg_layers(data:&[u8], dims:ArrayDim, layer_axis:usize) -> (Vec<u8>, ArrayDim) {
+    let n_layers = dims.size(layer_axis);
+    let stride:usize = dims.shape()[..layer_axis].iter().product();
+    let mut shape = dims.shape().to_vec();
+    shape.remove(layer_axis);
+    let vol_dims = ArrayDim::from_shape(&shape);
+    let mut out = vol_dims.alloc(0u8);
+    out.iter_mut().enumerate().for_each(|(i, o)| {
+        let base = (i / stride) * stride * n_layers + i % stride;
+        if let Some(l) = (0..n_layers).rev().map(|l| data[base + l * stride]).find(|l| *l != 0) {
+            *o = l;
+        }
+    });
+    (out, vol_dims)
+}
+
+/// write a Slicer segmentation file (.seg.nrrd) from a label volume and its segments. Segment
+/// extents are computed from the labels if not given. Any custom key-value pairs in the options
+/// are written alongside the segment metadata
+pub fn write_seg_nrrd(file:impl AsRef<Path>, data:&[u8], dims:ArrayDim, segments:&[SegmentInfo], opts:&NrrdWriteOptions) -> Result<(), NrrdIoError> {
+    let mut meta = opts.meta.clone();
+    meta.insert(String::from("Segmentation_MasterRepresentation"), String::from("Binary labelmap"));
+    meta.insert(String::from("Segmentation_ContainedRepresentationNames"), String::from("Binary labelmap|"));
+    for (i, seg) in segments.iter().enumerate() {
+        let mut seg = seg.clone();
+        if seg.extent.is_none() {
+            seg.extent = Some(label_extent(data, dims, seg.label_value));
+        }
+        seg.to_meta(&mut meta, i);
+    }
+    let opts = opts.clone().with_meta(meta);
+    write_nrrd_with_options(file, data, dims, &opts)
+}
+
+/// inclusive bounding box of the voxels with a given label over the first 3 axes
+fn label_extent(data:&[u8], dims:ArrayDim, label:u8) -> [usize;6] {
+    let mut e = [usize::MAX, 0, usize::MAX, 0, usize::MAX, 0];
+    let mut found = false;
+    data.iter().enumerate().filter(|(_, v)| **v == label).for_each(|(addr, _)| {
+        let idx = dims.calc_idx(addr);
+        for ax in 0..3 {
+            e[2 * ax] = e[2 * ax].min(idx[ax]);
+            e[2 * ax + 1] = e[2 * ax + 1].max(idx[ax]);
+        }
+        found = true;
+    });
+    if found {
+        e
+    } else {
+        // no voxel has the label, so the extent falls back to the whole volume
+        [0, dims.size(0) - 1, 0, dims.size(1) - 1, 0, dims.size(2) - 1]
+    }
+}
+
+/// key-value pair holding the scale that maps stored values to real values
+pub const VALUE_SCALE_KEY: &str = "value_scale";
+/// key-value pair holding the offset that maps stored values to real values
+pub const VALUE_OFFSET_KEY: &str = "value_offset";
+
+/// how values are mapped onto a different element type when writing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalePolicy {
+    /// values are rounded (for integer types) and clamped to the range of the output type
+    None,
+    /// the range of the data is mapped onto the full range of an integer output type. The scale
+    /// and offset are stored in the header such that value = stored * value_scale + value_offset.
+    /// Float output types are written without scaling, as for None
+    MinMax,
+    /// values are rounded (for integer types), and any value outside the range of the output type
+    /// is an error
+    ErrorOnOverflow,
+}
+
+/// number of elements converted at a time when writing with a different element type
+const CONVERT_CHUNK_SIZE: usize = 1 << 16;
+
+/// write a nrrd file, converting the data to a different element type during the write. Data is
+/// converted in small chunks so no full-size copy of the array is made
+pub fn write_nrrd_as<T>(file: impl AsRef<Path>, array:&[T], dims:ArrayDim, dtype:NrrdDtype, scale:ScalePolicy, opts:&NrrdWriteOptions) -> Result<(), NrrdIoError>
+where T:ToPrimitive + Copy
+{
+    match dtype {
+        NrrdDtype::Int8 => write_converted::<T, i8>(file, array, dims, scale, opts),
+        NrrdDtype::UInt8 => write_converted::<T, u8>(file, array, dims, scale, opts),
+        NrrdDtype::Int16 => write_converted::<T, i16>(file, array, dims, scale, opts),
+        NrrdDtype::UInt16 => write_converted::<T, u16>(file, array, dims, scale, opts),
+        NrrdDtype::Int32 => write_converted::<T, i32>(file, array, dims, scale, opts),
+        NrrdDtype::UInt32 => write_converted::<T, u32>(file, array, dims, scale, opts),
+        NrrdDtype::Int64 => write_converted::<T, i64>(file, array, dims, scale, opts),
+        NrrdDtype::UInt64 => write_converted::<T, u64>(file, array, dims, scale, opts),
+        NrrdDtype::Float32 => write_converted::<T, f32>(file, array, dims, scale, opts),
+        NrrdDtype::Float64 => write_converted::<T, f64>(file, array, dims, scale, opts),
+    }
+}
+
+fn write_converted<T, U>(file: impl AsRef<Path>, array:&[T], dims:ArrayDim, policy:ScalePolicy, opts:&NrrdWriteOptions) -> Result<(), NrrdIoError>
+where T:ToPrimitive + Copy, U:NrrdElement + NumCast + Bounded + ToPrimitive
+{
+    if dims.numel() != array.len() {
+        return Err(NrrdIoError::InconsistentArraySize {expected: dims.numel(), actual: array.len()});
+    }
+
+    let u_min = U::min_value().to_f64().unwrap();
+    let u_max = U::max_value().to_f64().unwrap();
+    let round = U::DTYPE.is_integer();
+    // the range of float64 overflows, and floats don't need their range filled
+    let policy = if !round && policy == ScalePolicy::MinMax { ScalePolicy::None } else { policy };
+
+    // stored = (value - offset) / scale
+    let (scale, offset) = match policy {
+        ScalePolicy::MinMax => {
+            let (lo, hi) = array.iter().filter_map(|x| x.to_f64()).filter(|x| x.is_finite())
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| (lo.min(x), hi.max(x)));
+            if lo > hi || lo == hi {
+                // empty, non-finite or constant data
+                (1., if lo.is_finite() { lo } else { 0. })
+            } else {
+                let scale = (hi - lo) / (u_max - u_min);
+                (scale, lo - u_min * scale)
+            }
+        }
+        _=> (1., 0.),
     };
-}
\ No newline at end of file
+
+    let mut opts = opts.clone();
+    if policy == ScalePolicy::MinMax {
+        opts.meta.insert(VALUE_SCALE_KEY.to_string(), format!("{:e}", scale));
+        opts.meta.insert(VALUE_OFFSET_KEY.to_string(), format!("{:e}", offset));
+    }
+
+    let mut w = NrrdStreamWriter::<U>::create(file, dims, &opts)?;
+    let mut staging = Vec::<U>::with_capacity(CONVERT_CHUNK_SIZE.min(array.len()));
+
+    for (chunk_idx, chunk) in array.chunks(CONVERT_CHUNK_SIZE).enumerate() {
+        staging.clear();
+        for (i, x) in chunk.iter().enumerate() {
+            let v = x.to_f64().unwrap_or(f64::NAN);
+            let mut s = (v - offset) / scale;
+            if round {
+                s = s.round();
+            }
+            let out_of_range = !(s >= u_min && s <= u_max) && (round || s.is_finite());
+            if out_of_range && policy == ScalePolicy::ErrorOnOverflow {
+                return Err(NrrdIoError::Overflow {addr: chunk_idx * CONVERT_CHUNK_SIZE + i, value: v, dtype: U::DTYPE});
+            }
+            if round && s.is_nan() {
+                s = 0.;
+            }
+            let s = if round || s.is_finite() { s.clamp(u_min, u_max) } else { s };
+            // the f64 bounds of 64-bit integers round up past the end of their range
+            staging.push(NumCast::from(s).unwrap_or_else(|| if s < 0. { U::min_value() } else { U::max_value() }));
+        }
+        w.write_chunk(&staging)?;
+    }
+    w.finish()
+}
+
+/// read a nrrd of any element type as f32, applying the value_scale and value_offset key-value
+/// pairs written by write_nrrd_as when they are present
+pub fn read_nrrd_scaled(file:impl AsRef<Path>) -> Result<(Vec<f32>, ArrayDim, NrrdHeader), NrrdIoError> {
+    let path = file.as_ref();
+    let h = parse_header(path)?;
+    let dims = ArrayDim::from_shape(&h.sizes().unwrap_or_default());
+
+    let parse_key = |key: &str, default: f64| -> Result<f64, NrrdIoError> {
+        match h.key_values.get(key) {
+            Some(v) => v.trim().parse().map_err(|_| NrrdIoError::Parse {path: path.to_path_buf(), msg: format!("invalid {} '{}'", key, v)}),
+            None => Ok(default),
+        }
+    };
+    let scale = parse_key(VALUE_SCALE_KEY, 1.)?;
+    let offset = parse_key(VALUE_OFFSET_KEY, 0.)?;
+
+    let data = match h.dtype().unwrap() {
+        NrrdDtype::Int8 => read_converted::<i8>(path, &h, dims, scale, offset)?,
+        NrrdDtype::UInt8 => read_converted::<u8>(path, &h, dims, scale, offset)?,
+        NrrdDtype::Int16 => read_converted::<i16>(path, &h, dims, scale, offset)?,
+        NrrdDtype::UInt16 => read_converted::<u16>(path, &h, dims, scale, offset)?,
+        NrrdDtype::Int32 => read_converted::<i32>(path, &h, dims, scale, offset)?,
+        NrrdDtype::UInt32 => read_converted::<u32>(path, &h, dims, scale, offset)?,
+        NrrdDtype::Int64 => read_converted::<i64>(path, &h, dims, scale, offset)?,
+        NrrdDtype::UInt64 => read_converted::<u64>(path, &h, dims, scale, offset)?,
+        NrrdDtype::Float32 => read_converted::<f32>(path, &h, dims, scale, offset)?,
+        NrrdDtype::Float64 => read_converted::<f64>(path, &h, dims, scale, offset)?,
+    };
+    Ok((data, dims, h))
+}
+
+fn read_converted<U:NrrdElement + ToPrimitive>(path: &Path, h: &NrrdHeader, dims: ArrayDim, scale: f64, offset: f64) -> Result<Vec<f32>, NrrdIoError> {
+    let mut stored = dims.try_alloc(U::zeroed()).map_err(|e| NrrdIoError::Alloc(path.to_path_buf(), e))?;
+    read_payload_into(path, h, &mut stored)?;
+    Ok(stored.iter().map(|x| (x.to_f64().unwrap() * scale + offset) as f32).collect())
+}
+
+/// checks that the element type of the header matches T
+fn check_dtype<T:NrrdElement>(path: &Path, h: &NrrdHeader) -> Result<(), NrrdIoError> {
+    let found = h.dtype().ok_or_else(|| NrrdIoError::Parse {path: path.to_path_buf(), msg: String::from("missing type field")})?;
+    if found != T::DTYPE {
+        return Err(NrrdIoError::DtypeMismatch {path: path.to_path_buf(), expected: T::DTYPE, found});
+    }
+    Ok(())
+}
+
+/// returns true for gzip encoded data and false for raw. Other encodings are not supported
+fn data_encoding(path: &Path, h: &NrrdHeader) -> Result<bool, NrrdIoError> {
+    match h.field("encoding") {
+        Some("raw") => Ok(false),
+        Some("gzip") | Some("gz") => Ok(true),
+        Some(e) => Err(NrrdIoError::UnsupportedEncoding(e.to_string())),
+        None => Err(NrrdIoError::Parse {path: path.to_path_buf(), msg: String::from("missing encoding field")}),
+    }
+}
+
+/// returns true if the data was written with the opposite byte order of this machine
+fn needs_byte_swap(h: &NrrdHeader) -> bool {
+    let native = if cfg!(target_endian = "big") { "big" } else { "little" };
+    h.field("endian").map(|e| e != native).unwrap_or(false)
+}
+
+/// reverses the byte order of every element
+fn swap_bytes<T:Pod>(x: &mut [T]) {
+    let n = size_of::<T>();
+    let bytes:&mut [u8] = bytemuck::cast_slice_mut(x);
+    bytes.chunks_exact_mut(n).for_each(|c| c.reverse());
+}
+
+/// resolves the file holding the data and the byte offset where the (possibly encoded) data
+/// starts. Detached data files are resolved relative to the directory of the header
+fn data_location(path: &Path, h: &NrrdHeader) -> Result<(PathBuf, u64), NrrdIoError> {
+
+    let parse_err = |msg: String| NrrdIoError::Parse {path: path.to_path_buf(), msg};
+
+    let (data_path, mut start) = match h.field("data file").or(h.field("datafile")) {
+        Some(df) => {
+            if df.starts_with("LIST") || df.split_whitespace().count() > 1 {
+                return Err(parse_err(format!("multi-file data is not supported: '{}'", df)));
+            }
+            let df = PathBuf::from(df);
+            let df = if df.is_absolute() {
+                df
+            } else {
+                path.parent().map(|p| p.join(&df)).unwrap_or(df)
+            };
+            (df, 0u64)
+        }
+        None => (path.to_path_buf(), h.header_len()),
+    };
+
+    if !data_path.is_file() {
+        return Err(NrrdIoError::MissingDataFile(data_path));
+    }
+
+    let line_skip:usize = match h.field("line skip").or(h.field("lineskip")) {
+        Some(l) => l.parse().map_err(|_| parse_err(format!("invalid line skip '{}'", l)))?,
+        None => 0,
+    };
+
+    if line_skip > 0 {
+        let f = File::open(&data_path).map_err(io_err(&data_path))?;
+        let mut r = BufReader::new(f);
+        r.seek(SeekFrom::Start(start)).map_err(io_err(&data_path))?;
+        let mut line = Vec::new();
+        for _ in 0..line_skip {
+            line.clear();
+            start += r.read_until(b'\n', &mut line).map_err(io_err(&data_path))? as u64;
+        }
+    }
+
+    let byte_skip:i64 = match h.field("byte skip").or(h.field("byteskip")) {
+        Some(b) => b.parse().map_err(|_| parse_err(format!("invalid byte skip '{}'", b)))?,
+        None => 0,
+    };
+
+    if byte_skip < 0 {
+        return Err(parse_err(String::from("negative byte skip is not supported")));
+    }
+
+    Ok((data_path, start + byte_skip as u64))
+}
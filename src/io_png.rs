@@ -0,0 +1,300 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use png::{BitDepth, ColorType, Transformations};
+use crate::ArrayDim;
+
+#[cfg(test)]
+mod tests {
+    use crate::ArrayDim;
+    use crate::io_png::{read_png_gray, write_png_montage, write_png_slice, write_png_slice_with_options, PngBitDepth, PngIoError, PngSliceOptions};
+
+    #[test]
+    fn test_window() {
+        let dims = ArrayDim::from_shape(&[5,1]);
+        let x = [-5.,0.,5.,10.,15.];
+        write_png_slice("test_png_window.png",&x,&dims,2,0,Some((0.,10.))).unwrap();
+        let (y,y_dims) = read_png_gray("test_png_window.png").unwrap();
+        std::fs::remove_file("test_png_window.png").unwrap();
+        assert_eq!(y_dims.shape_ns(),&[5]);
+        // values outside the window are clamped
+        assert_eq!(y,vec![0,0,128,255,255]);
+    }
+
+    #[test]
+    fn test_slice_extraction() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| (i * 1000) as f32).collect();
+        let opts = PngSliceOptions::new().window(0.,65535.).bit_depth(PngBitDepth::Sixteen);
+        write_png_slice_with_options("test_png_slice.png",&x,&dims,1,2,&opts).unwrap();
+        let (y,y_dims) = read_png_gray("test_png_slice.png").unwrap();
+        std::fs::remove_file("test_png_slice.png").unwrap();
+
+        let (expected,_) = dims.copy_region(&x,&[0,2,0],&[4,1,2]);
+        assert_eq!(y_dims.shape_ns(),&[4,2]);
+        assert_eq!(y,expected.iter().map(|&x| x as u16).collect::<Vec<u16>>());
+
+        assert!(matches!(write_png_slice("test_png_slice.png",&x,&dims,1,3,None),Err(PngIoError::InvalidSlice(..))));
+        assert!(matches!(write_png_slice("test_png_slice.png",&x,&dims,3,0,None),Err(PngIoError::InvalidSlice(..))));
+    }
+
+    #[test]
+    fn test_auto_window() {
+        // the 2nd and 98th percentiles of 0..=100 are 2 and 98
+        let dims = ArrayDim::from_shape(&[101]);
+        let x:Vec<f32> = (0..=100).map(|i| i as f32).collect();
+        write_png_slice("test_png_auto.png",&x,&dims,1,0,None).unwrap();
+        let (y,_) = read_png_gray("test_png_auto.png").unwrap();
+        std::fs::remove_file("test_png_auto.png").unwrap();
+        assert_eq!(y[2],0);
+        assert_eq!(y[98],255);
+        assert_eq!(y[50],128);
+    }
+
+    #[test]
+    fn test_montage() {
+        // 5 slices of 3x2 in a grid of 2 columns and 3 rows
+        let dims = ArrayDim::from_shape(&[3,2,5]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+        let opts = PngSliceOptions::new().window(0.,255.);
+        write_png_montage("test_png_montage.png",&x,&dims,2,2,&opts).unwrap();
+        let (y,y_dims) = read_png_gray("test_png_montage.png").unwrap();
+        std::fs::remove_file("test_png_montage.png").unwrap();
+
+        assert_eq!(y_dims.shape_ns(),&[6,6]);
+        let px = |col:usize, row:usize| y[row * 6 + col];
+        // the first row of slice 0, then slice 1 to its right
+        assert_eq!([px(0,0),px(2,0),px(3,0)],[0,2,6]);
+        // slice 3 starts the second row of tiles at its second column
+        assert_eq!(px(3,2),18);
+        // the second row of slice 4, with its unused neighbour left black
+        assert_eq!([px(0,5),px(4,5)],[27,0]);
+
+        // slices along axis 3 are 3 dimensional
+        let bad = write_png_montage("test_png_montage.png",&x,&dims,3,2,&opts);
+        assert!(matches!(bad,Err(PngIoError::InvalidSlice(..))));
+    }
+
+}
+
+#[derive(Debug)]
+pub enum PngIoError {
+    IO(PathBuf, std::io::Error),
+    Encoding{path: PathBuf, source: png::EncodingError},
+    Decoding{path: PathBuf, source: png::DecodingError},
+    Unsupported{path: PathBuf, msg: String},
+    InconsistentArraySize{expected: usize, actual: usize},
+    InvalidSlice(String),
+}
+
+impl Display for PngIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PngIoError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            PngIoError::Encoding {path, source} => write!(f, "png encoding error for {}: {}", path.display(), source),
+            PngIoError::Decoding {path, source} => write!(f, "png decoding error for {}: {}", path.display(), source),
+            PngIoError::Unsupported {path, msg} => write!(f, "unsupported png {}: {}", path.display(), msg),
+            PngIoError::InconsistentArraySize {expected, actual} => write!(
+                f, "array dimensions require {} elements but {} were supplied", expected, actual
+            ),
+            PngIoError::InvalidSlice(msg) => write!(f, "invalid slice: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PngIoError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> PngIoError {
+    let path = path.to_path_buf();
+    move |e| PngIoError::IO(path, e)
+}
+
+/// the bit depth of an exported slice
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PngBitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+/// options for exporting a slice to png
+#[derive(Debug, Clone, Default)]
+pub struct PngSliceOptions {
+    window: Option<(f32, f32)>,
+    bit_depth: PngBitDepth,
+}
+
+impl PngSliceOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// maps min to black and max to white. Defaults to the 2nd and 98th percentiles of the slice
+    pub fn window(mut self, min:f32, max:f32) -> Self {
+        self.window = Some((min, max));
+        self
+    }
+
+    pub fn bit_depth(mut self, bit_depth:PngBitDepth) -> Self {
+        self.bit_depth = bit_depth;
+        self
+    }
+}
+
+/// the percentiles (from 0 to 1) of the finite values of x by the nearest rank
+fn percentiles<const N:usize>(x:&[f32], p:[f32; N]) -> [f32; N] {
+    let mut sorted:Vec<f32> = x.iter().copied().filter(|x| x.is_finite()).collect();
+    if sorted.is_empty() {
+        return [0.; N];
+    }
+    sorted.sort_by(f32::total_cmp);
+    p.map(|p| sorted[(p * (sorted.len() - 1) as f32).round() as usize])
+}
+
+/// writes a 2D slice of an array to an 8 bit grayscale png (see write_png_slice_with_options)
+pub fn write_png_slice(file:impl AsRef<Path>, data:&[f32], dims:&ArrayDim, slice_axis:usize, index:usize, window:Option<(f32, f32)>) -> Result<(), PngIoError> {
+    let mut opts = PngSliceOptions::new();
+    opts.window = window;
+    write_png_slice_with_options(file, data, dims, slice_axis, index, &opts)
+}
+
+/// writes the slice at index along slice_axis to a grayscale png. The remaining axes form the
+/// image, with the first non-singleton axis along the width and the second down the height, so
+/// at most two of them may be larger than 1. Values are windowed and clamped to the range of the
+/// bit depth, and values that are not finite are written as black
+pub fn write_png_slice_with_options(file:impl AsRef<Path>, data:&[f32], dims:&ArrayDim, slice_axis:usize, index:usize, opts:&PngSliceOptions) -> Result<(), PngIoError> {
+    if data.len() != dims.numel() {
+        return Err(PngIoError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+    }
+    if slice_axis >= crate::N_DIMS || index >= dims.shape()[slice_axis] {
+        return Err(PngIoError::InvalidSlice(format!(
+            "index {} along axis {} is out of range for an array of shape {:?}", index, slice_axis, dims.shape_ns()
+        )));
+    }
+    let (width, height) = slice_shape(dims, slice_axis)?;
+    let slice = copy_slice(data, dims, slice_axis, index);
+    write_gray(file.as_ref(), &slice, width, height, opts)
+}
+
+/// writes every slice along slice_axis to a single grayscale png, tiled left to right and then
+/// top to bottom in a grid of cols columns. Slices are laid out as in write_png_slice_with_options
+/// and unused tiles are black. The default window is taken over the whole array, so all tiles
+/// share the same scaling
+pub fn write_png_montage(file:impl AsRef<Path>, data:&[f32], dims:&ArrayDim, slice_axis:usize, cols:usize, opts:&PngSliceOptions) -> Result<(), PngIoError> {
+    if data.len() != dims.numel() {
+        return Err(PngIoError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+    }
+    if slice_axis >= crate::N_DIMS {
+        return Err(PngIoError::InvalidSlice(format!("axis {} is out of range", slice_axis)));
+    }
+    if cols == 0 {
+        return Err(PngIoError::InvalidSlice(String::from("a montage needs at least one column")));
+    }
+    let (width, height) = slice_shape(dims, slice_axis)?;
+    let n = dims.shape()[slice_axis];
+    let cols = cols.min(n);
+    let rows = n.div_ceil(cols);
+
+    let montage_width = width * cols;
+    let mut montage = vec![f32::NAN; montage_width * height * rows];
+    for i in 0..n {
+        let slice = copy_slice(data, dims, slice_axis, i);
+        let (tile_x, tile_y) = ((i % cols) * width, (i / cols) * height);
+        for (y, row) in slice.chunks_exact(width).enumerate() {
+            let start = (tile_y + y) * montage_width + tile_x;
+            montage[start..start + width].copy_from_slice(row);
+        }
+    }
+
+    let mut opts = opts.clone();
+    if opts.window.is_none() {
+        let [min, max] = percentiles(data, [0.02, 0.98]);
+        opts.window = Some((min, max));
+    }
+    write_gray(file.as_ref(), &montage, montage_width, height * rows, &opts)
+}
+
+/// the width and height of the slices along slice_axis
+fn slice_shape(dims:&ArrayDim, slice_axis:usize) -> Result<(usize, usize), PngIoError> {
+    let image_shape:Vec<usize> = dims.shape().iter().enumerate()
+        .filter(|&(ax, &d)| ax != slice_axis && d > 1)
+        .map(|(_, &d)| d)
+        .collect();
+    if image_shape.len() > 2 {
+        return Err(PngIoError::InvalidSlice(format!(
+            "slices along axis {} of an array of shape {:?} have more than 2 dimensions", slice_axis, dims.shape_ns()
+        )));
+    }
+    Ok((image_shape.first().copied().unwrap_or(1), image_shape.get(1).copied().unwrap_or(1)))
+}
+
+/// copies the slice at index along slice_axis
+fn copy_slice(data:&[f32], dims:&ArrayDim, slice_axis:usize, index:usize) -> Vec<f32> {
+    let mut offset = [0usize; crate::N_DIMS];
+    let mut size = *dims.shape();
+    offset[slice_axis] = index;
+    size[slice_axis] = 1;
+    dims.copy_region(data, &offset, &size).0
+}
+
+/// windows an image and writes it as a grayscale png. The window defaults to the 2nd and 98th
+/// percentiles of the image
+fn write_gray(path:&Path, image:&[f32], width:usize, height:usize, opts:&PngSliceOptions) -> Result<(), PngIoError> {
+    let (min, max) = opts.window.unwrap_or_else(|| {
+        let [min, max] = percentiles(image, [0.02, 0.98]);
+        (min, max)
+    });
+    let (depth, white) = match opts.bit_depth {
+        PngBitDepth::Eight => (BitDepth::Eight, u8::MAX as f32),
+        PngBitDepth::Sixteen => (BitDepth::Sixteen, u16::MAX as f32),
+    };
+    let scale = if max > min { white / (max - min) } else { 0. };
+    let levels = image.iter().map(|&x| {
+        if x.is_finite() { ((x - min) * scale).round().clamp(0., white) } else { 0. }
+    });
+    // 16 bit samples are big-endian
+    let bytes:Vec<u8> = match opts.bit_depth {
+        PngBitDepth::Eight => levels.map(|x| x as u8).collect(),
+        PngBitDepth::Sixteen => levels.flat_map(|x| (x as u16).to_be_bytes()).collect(),
+    };
+
+    let w = BufWriter::new(File::create(path).map_err(io_err(path))?);
+    let mut enc = png::Encoder::new(w, width as u32, height as u32);
+    enc.set_color(ColorType::Grayscale);
+    enc.set_depth(depth);
+    let encoding_err = |source| PngIoError::Encoding{path: path.to_path_buf(), source};
+    let mut writer = enc.write_header().map_err(encoding_err)?;
+    writer.write_image_data(&bytes).map_err(encoding_err)?;
+    writer.finish().map_err(encoding_err)
+}
+
+/// reads a png as grayscale into an array of shape [width, height]. Color images are reduced to
+/// the largest of their color channels so masks drawn in any color are kept, and alpha is
+/// dropped. 8 bit and lower bit depths are returned unscaled
+pub fn read_png_gray(file:impl AsRef<Path>) -> Result<(Vec<u16>, ArrayDim), PngIoError> {
+    let path = file.as_ref();
+    let decoding_err = |source| PngIoError::Decoding{path: path.to_path_buf(), source};
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path).map_err(io_err(path))?));
+    // palettes are expanded to rgb and low bit depths to 8 bits
+    decoder.set_transformations(Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(decoding_err)?;
+    let mut buf = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(decoding_err)?;
+    buf.truncate(info.buffer_size());
+
+    let samples:Vec<u16> = match info.bit_depth {
+        BitDepth::Eight => buf.iter().map(|&b| b as u16).collect(),
+        BitDepth::Sixteen => buf.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect(),
+        d => return Err(PngIoError::Unsupported{path: path.to_path_buf(), msg: format!("bit depth {:?}", d)}),
+    };
+    let (channels, colors) = match info.color_type {
+        ColorType::Grayscale => (1, 1),
+        ColorType::GrayscaleAlpha => (2, 1),
+        ColorType::Rgb => (3, 3),
+        ColorType::Rgba => (4, 3),
+        c => return Err(PngIoError::Unsupported{path: path.to_path_buf(), msg: format!("color type {:?}", c)}),
+    };
+    let gray = samples.chunks_exact(channels).map(|px| px[..colors].iter().copied().max().unwrap()).collect();
+    Ok((gray, ArrayDim::from_shape(&[info.width as usize, info.height as usize])))
+}
@@ -0,0 +1,332 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use bytemuck::Pod;
+use num_complex::Complex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::{AllocError, ArrayDim};
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex32;
+    use serde_json::json;
+    use crate::ArrayDim;
+    use crate::io_raw::{raw_json_paths, read_raw_json, read_raw_json_with_options, write_raw_json, RawIoError, RawReadOptions};
+
+    fn remove(base:&str) {
+        let (json,raw) = raw_json_paths(base);
+        std::fs::remove_file(json).unwrap();
+        std::fs::remove_file(raw).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x:Vec<Complex32> = (0..dims.numel()).map(|i| Complex32::new(i as f32,-1.)).collect();
+        let meta = json!({"te_ms": 12.5, "scanner": "9.4T", "flip": [10, 20]});
+        write_raw_json("test_raw_round_trip",&x,dims,Some(meta.clone())).unwrap();
+        let (y,y_dims,y_meta) = read_raw_json::<Complex32>("test_raw_round_trip").unwrap();
+        assert_eq!(y,x);
+        assert_eq!(y_dims.shape(),dims.shape());
+        assert_eq!(y_meta,Some(meta));
+
+        write_raw_json("test_raw_round_trip",&[1u8,2,3],ArrayDim::from_shape(&[3]),None).unwrap();
+        let (_,_,y_meta) = read_raw_json::<u8>("test_raw_round_trip").unwrap();
+        assert_eq!(y_meta,None);
+
+        // the raw file must match the declared shape
+        std::fs::write(raw_json_paths("test_raw_round_trip").1,[1u8,2]).unwrap();
+        let r = read_raw_json::<u8>("test_raw_round_trip");
+        remove("test_raw_round_trip");
+        assert!(matches!(r,Err(RawIoError::SizeMismatch{expected: 3, actual: 2,..})));
+    }
+
+    #[test]
+    fn test_dtype_mismatch() {
+        let dims = ArrayDim::from_shape(&[5]);
+        let x = [1.5f32,-2.,3.,40000.,0.];
+        write_raw_json("test_raw_dtype",&x,dims,None).unwrap();
+        match read_raw_json::<i16>("test_raw_dtype") {
+            Err(RawIoError::DtypeMismatch {expected,found,..}) => {
+                assert_eq!(expected,"i16");
+                assert_eq!(found,"f32");
+            }
+            _=> panic!("expected a dtype mismatch"),
+        }
+        // casting is opt-in and saturates as with `as`
+        let (y,..) = read_raw_json_with_options::<i16>("test_raw_dtype",&RawReadOptions::new().cast(true)).unwrap();
+        assert_eq!(y,vec![1,-2,3,i16::MAX,0]);
+        // complex data is never cast to a real type
+        write_raw_json("test_raw_dtype",&[Complex32::new(1.,1.)],ArrayDim::from_shape(&[1]),None).unwrap();
+        let r = read_raw_json_with_options::<f32>("test_raw_dtype",&RawReadOptions::new().cast(true));
+        remove("test_raw_dtype");
+        assert!(matches!(r,Err(RawIoError::DtypeMismatch{..})));
+    }
+
+    #[test]
+    fn test_big_endian() {
+        let (json,raw) = raw_json_paths("test_raw_be");
+        std::fs::write(json,r#"{"dtype": "u16", "shape": [2, 2], "endian": "big"}"#).unwrap();
+        let bytes:Vec<u8> = [1u16,2,300,4].iter().flat_map(|x| x.to_be_bytes()).collect();
+        std::fs::write(raw,bytes).unwrap();
+        let (y,dims,_) = read_raw_json::<u16>("test_raw_be").unwrap();
+        let (z,..) = read_raw_json_with_options::<f64>("test_raw_be",&RawReadOptions::new().cast(true)).unwrap();
+        remove("test_raw_be");
+        assert_eq!(y,vec![1,2,300,4]);
+        assert_eq!(z,vec![1.,2.,300.,4.]);
+        assert_eq!(dims.shape_ns(),&[2,2]);
+    }
+
+}
+
+#[derive(Debug)]
+pub enum RawIoError {
+    IO(PathBuf, std::io::Error),
+    Json{path: PathBuf, source: serde_json::Error},
+    Header{path: PathBuf, msg: String},
+    DtypeMismatch{path: PathBuf, expected: String, found: String},
+    SizeMismatch{path: PathBuf, expected: u64, actual: u64},
+    InconsistentArraySize{expected: usize, actual: usize},
+    /// the array described by a sidecar can't be allocated
+    Alloc(PathBuf, AllocError),
+}
+
+impl Display for RawIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RawIoError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            RawIoError::Json {path, source} => write!(f, "invalid json in {}: {}", path.display(), source),
+            RawIoError::Header {path, msg} => write!(f, "invalid sidecar {}: {}", path.display(), msg),
+            RawIoError::DtypeMismatch {path, expected, found} => write!(
+                f, "{} has dtype {} but {} was requested", path.display(), found, expected
+            ),
+            RawIoError::SizeMismatch {path, expected, actual} => write!(
+                f, "{} is {} bytes but the sidecar requires {}", path.display(), actual, expected
+            ),
+            RawIoError::InconsistentArraySize {expected, actual} => write!(
+                f, "array dimensions require {} elements but {} were supplied", expected, actual
+            ),
+            RawIoError::Alloc(path, e) => write!(f, "can't read {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for RawIoError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> RawIoError {
+    let path = path.to_path_buf();
+    move |e| RawIoError::IO(path, e)
+}
+
+/// element types that can be stored in raw files
+pub trait RawElement: Pod {
+    /// dtype name written to the sidecar
+    const DTYPE: &'static str;
+    /// size of each scalar component in bytes, used for byte swapping
+    const WORD: usize;
+    const COMPLEX: bool;
+    /// converts a value when casting from another dtype. Real types drop the imaginary part,
+    /// which is always zero when casting from a real dtype
+    fn from_parts(re:f64, im:f64) -> Self;
+}
+
+macro_rules! raw_element {
+    (complex $t:ty, $dtype:expr) => {
+        impl RawElement for Complex<$t> {
+            const DTYPE: &'static str = $dtype;
+            const WORD: usize = size_of::<$t>();
+            const COMPLEX: bool = true;
+            fn from_parts(re:f64, im:f64) -> Self {
+                Complex::new(re as $t, im as $t)
+            }
+        }
+    };
+    ($t:ty, $dtype:expr) => {
+        impl RawElement for $t {
+            const DTYPE: &'static str = $dtype;
+            const WORD: usize = size_of::<$t>();
+            const COMPLEX: bool = false;
+            fn from_parts(re:f64, _im:f64) -> Self {
+                re as $t
+            }
+        }
+    };
+}
+
+raw_element!(f32, "f32");
+raw_element!(f64, "f64");
+raw_element!(i8, "i8");
+raw_element!(i16, "i16");
+raw_element!(i32, "i32");
+raw_element!(i64, "i64");
+raw_element!(u8, "u8");
+raw_element!(u16, "u16");
+raw_element!(u32, "u32");
+raw_element!(u64, "u64");
+raw_element!(complex f32, "c64");
+raw_element!(complex f64, "c128");
+
+/// returns the word size in bytes and whether a dtype is complex
+fn dtype_layout(dtype:&str) -> Option<(usize, bool)> {
+    Some(match dtype {
+        "i8" | "u8" => (1, false),
+        "i16" | "u16" => (2, false),
+        "f32" | "i32" | "u32" => (4, false),
+        "f64" | "i64" | "u64" => (8, false),
+        "c64" => (4, true),
+        "c128" => (8, true),
+        _=> return None,
+    })
+}
+
+/// the json sidecar
+#[derive(Serialize, Deserialize)]
+struct RawHeader {
+    dtype: String,
+    shape: Vec<usize>,
+    #[serde(default = "little_endian")]
+    endian: String,
+    /// the layout of the data, which is always column-major
+    #[serde(default = "column_major")]
+    order: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    meta: Option<Value>,
+}
+
+fn little_endian() -> String {
+    String::from("little")
+}
+
+fn column_major() -> String {
+    String::from("F")
+}
+
+/// returns the paths of the json sidecar and raw data for a base name
+pub fn raw_json_paths(base:impl AsRef<Path>) -> (PathBuf, PathBuf) {
+    let base = base.as_ref().as_os_str().to_owned();
+    let mut json = base.clone();
+    json.push(".json");
+    let mut raw = base;
+    raw.push(".raw");
+    (PathBuf::from(json), PathBuf::from(raw))
+}
+
+/// options for reading raw files
+#[derive(Clone, Debug, Default)]
+pub struct RawReadOptions {
+    cast: bool,
+}
+
+impl RawReadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// converts data of another dtype to the requested type with `as` semantics instead of
+    /// returning a dtype mismatch. Complex data is only cast to complex types
+    pub fn cast(mut self, cast:bool) -> Self {
+        self.cast = cast;
+        self
+    }
+}
+
+/// writes little-endian column-major data to base.raw and its dtype, shape and optional metadata
+/// to base.json
+pub fn write_raw_json<T:RawElement>(base:impl AsRef<Path>, data:&[T], dims:ArrayDim, meta:Option<Value>) -> Result<(), RawIoError> {
+    if data.len() != dims.numel() {
+        return Err(RawIoError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+    }
+    let (json, raw) = raw_json_paths(base);
+    let header = RawHeader {
+        dtype: T::DTYPE.to_string(),
+        shape: dims.shape_ns().to_vec(),
+        endian: little_endian(),
+        order: column_major(),
+        meta,
+    };
+    let text = serde_json::to_string_pretty(&header).map_err(|source| RawIoError::Json{path: json.clone(), source})?;
+    std::fs::write(&json, text).map_err(io_err(&json))?;
+
+    let mut w = BufWriter::new(File::create(&raw).map_err(io_err(&raw))?);
+    w.write_all(bytemuck::cast_slice(data)).map_err(io_err(&raw))?;
+    w.flush().map_err(io_err(&raw))
+}
+
+/// reads base.raw as described by base.json, requiring the declared dtype to be T
+pub fn read_raw_json<T:RawElement>(base:impl AsRef<Path>) -> Result<(Vec<T>, ArrayDim, Option<Value>), RawIoError> {
+    read_raw_json_with_options(base, &RawReadOptions::default())
+}
+
+/// reads base.raw as described by base.json, returning the data, its dimensions and the metadata
+/// from the sidecar. Big-endian data is byte swapped on read
+pub fn read_raw_json_with_options<T:RawElement>(base:impl AsRef<Path>, opts:&RawReadOptions) -> Result<(Vec<T>, ArrayDim, Option<Value>), RawIoError> {
+    let (json, raw) = raw_json_paths(base);
+    let text = std::fs::read_to_string(&json).map_err(io_err(&json))?;
+    let header:RawHeader = serde_json::from_str(&text).map_err(|source| RawIoError::Json{path: json.clone(), source})?;
+    let header_err = |msg:String| RawIoError::Header{path: json.clone(), msg};
+
+    let (word, complex) = dtype_layout(&header.dtype).ok_or_else(|| header_err(format!("unknown dtype {}", header.dtype)))?;
+    let big_endian = match header.endian.as_str() {
+        "little" => false,
+        "big" => true,
+        e => return Err(header_err(format!("unknown endianness {}", e))),
+    };
+    if header.order != "F" {
+        return Err(header_err(format!("order {} is not supported, data must be column-major (F)", header.order)));
+    }
+    if header.shape.len() > crate::N_DIMS {
+        return Err(header_err(format!("arrays of up to {} dimensions are supported", crate::N_DIMS)));
+    }
+    let dims = ArrayDim::try_from_shape(&header.shape).map_err(|e| header_err(e.to_string()))?;
+    let mismatch = || RawIoError::DtypeMismatch{path: json.clone(), expected: T::DTYPE.to_string(), found: header.dtype.clone()};
+    let same_type = header.dtype == T::DTYPE;
+    if !same_type && (!opts.cast || (complex && !T::COMPLEX)) {
+        return Err(mismatch());
+    }
+
+    let n_words = dims.numel() * if complex { 2 } else { 1 };
+    let expected = (n_words * word) as u64;
+    let mut r = BufReader::new(File::open(&raw).map_err(io_err(&raw))?);
+    let actual = r.get_ref().metadata().map_err(io_err(&raw))?.len();
+    if actual != expected {
+        return Err(RawIoError::SizeMismatch{path: raw, expected, actual});
+    }
+
+    if same_type {
+        let mut data = dims.try_alloc(T::zeroed()).map_err(|e| RawIoError::Alloc(raw.clone(), e))?;
+        r.read_exact(bytemuck::cast_slice_mut(&mut data)).map_err(io_err(&raw))?;
+        if big_endian {
+            bytemuck::cast_slice_mut::<T, u8>(&mut data).chunks_exact_mut(T::WORD).for_each(|w| w.reverse());
+        }
+        return Ok((data, dims, header.meta));
+    }
+
+    let mut bytes = vec![0u8; expected as usize];
+    r.read_exact(&mut bytes).map_err(io_err(&raw))?;
+    let words = bytes.chunks_exact_mut(word).map(|w| {
+        if big_endian {
+            w.reverse();
+        }
+        let w:&[u8] = w;
+        match header.dtype.as_str() {
+            "i8" => w[0] as i8 as f64,
+            "u8" => w[0] as f64,
+            "i16" => i16::from_le_bytes(w.try_into().unwrap()) as f64,
+            "u16" => u16::from_le_bytes(w.try_into().unwrap()) as f64,
+            "i32" => i32::from_le_bytes(w.try_into().unwrap()) as f64,
+            "u32" => u32::from_le_bytes(w.try_into().unwrap()) as f64,
+            "f32" | "c64" => f32::from_le_bytes(w.try_into().unwrap()) as f64,
+            "i64" => i64::from_le_bytes(w.try_into().unwrap()) as f64,
+            "u64" => u64::from_le_bytes(w.try_into().unwrap()) as f64,
+            _=> f64::from_le_bytes(w.try_into().unwrap()),
+        }
+    }).collect::<Vec<f64>>();
+    let data = if complex {
+        words.chunks_exact(2).map(|c| T::from_parts(c[0], c[1])).collect()
+    } else {
+        words.into_iter().map(|x| T::from_parts(x, 0.)).collect()
+    };
+    Ok((data, dims, header.meta))
+}
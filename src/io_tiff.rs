@@ -0,0 +1,243 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Seek, Write};
+use std::path::{Path, PathBuf};
+use tiff::decoder::{Decoder, DecodingResult, Limits};
+use tiff::encoder::{colortype, TiffEncoder, TiffKind, TiffValue};
+use tiff::ColorType;
+use crate::ArrayDim;
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use tiff::encoder::{colortype, TiffEncoder};
+    use crate::ArrayDim;
+    use crate::io_tiff::{read_tiff_stack, write_tiff_stack, TiffIoError, TiffStack};
+
+    #[test]
+    fn test_round_trip_u16() {
+        let dims = ArrayDim::from_shape(&[5,4,3]);
+        let x:Vec<u16> = (0..dims.numel()).map(|i| (i * 100) as u16).collect();
+        write_tiff_stack("test_tiff_u16.tif",&x,dims,2).unwrap();
+        let (y,y_dims) = read_tiff_stack("test_tiff_u16.tif").unwrap();
+        std::fs::remove_file("test_tiff_u16.tif").unwrap();
+        assert_eq!(y,TiffStack::U16(x));
+        assert_eq!(y_dims.shape(),dims.shape());
+    }
+
+    #[test]
+    fn test_round_trip_f32() {
+        // one page per index along axis 0, so each page is 4 x 3
+        let dims = ArrayDim::from_shape(&[5,4,3]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32 * 0.25 - 3.).collect();
+        write_tiff_stack("test_tiff_f32.tif",&x,dims,0).unwrap();
+        let (y,y_dims) = read_tiff_stack("test_tiff_f32.tif").unwrap();
+        std::fs::remove_file("test_tiff_f32.tif").unwrap();
+        assert_eq!(y_dims.shape_ns(),&[4,3,5]);
+        let TiffStack::F32(y) = y else { panic!("expected f32 samples") };
+        for i in 0..5 {
+            for j in 0..4 {
+                for k in 0..3 {
+                    assert_eq!(y[y_dims.calc_addr(&[j,k,i])],x[dims.calc_addr(&[i,j,k])]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_inconsistent_pages() {
+        let mut enc = TiffEncoder::new(File::create("test_tiff_pages.tif").unwrap()).unwrap();
+        enc.write_image::<colortype::Gray8>(4,4,&[0u8;16]).unwrap();
+        enc.write_image::<colortype::Gray8>(4,2,&[0u8;8]).unwrap();
+        drop(enc);
+        let r = read_tiff_stack("test_tiff_pages.tif");
+        std::fs::remove_file("test_tiff_pages.tif").unwrap();
+        assert!(matches!(r,Err(TiffIoError::InconsistentPage{page: 1,..})));
+    }
+
+}
+
+#[derive(Debug)]
+pub enum TiffIoError {
+    IO(PathBuf, std::io::Error),
+    Tiff{path: PathBuf, source: tiff::TiffError},
+    InconsistentPage{path: PathBuf, page: usize, msg: String},
+    Unsupported{path: PathBuf, page: usize, msg: String},
+    InconsistentArraySize{expected: usize, actual: usize},
+    InvalidPageAxis(String),
+}
+
+impl Display for TiffIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TiffIoError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            TiffIoError::Tiff {path, source} => write!(f, "tiff error for {}: {}", path.display(), source),
+            TiffIoError::InconsistentPage {path, page, msg} => write!(f, "page {} of {} {}", page, path.display(), msg),
+            TiffIoError::Unsupported {path, page, msg} => write!(f, "page {} of {} is unsupported: {}", page, path.display(), msg),
+            TiffIoError::InconsistentArraySize {expected, actual} => write!(
+                f, "array dimensions require {} elements but {} were supplied", expected, actual
+            ),
+            TiffIoError::InvalidPageAxis(msg) => write!(f, "invalid page axis: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for TiffIoError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> TiffIoError {
+    let path = path.to_path_buf();
+    move |e| TiffIoError::IO(path, e)
+}
+
+fn tiff_err(path: &Path) -> impl FnOnce(tiff::TiffError) -> TiffIoError {
+    let path = path.to_path_buf();
+    move |source| TiffIoError::Tiff{path, source}
+}
+
+/// the samples of a grayscale tiff stack
+#[derive(Debug, Clone, PartialEq)]
+pub enum TiffStack {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    F32(Vec<f32>),
+}
+
+impl TiffStack {
+    fn name(&self) -> &'static str {
+        match self {
+            TiffStack::U8(_) => "8 bit",
+            TiffStack::U16(_) => "16 bit",
+            TiffStack::F32(_) => "32 bit float",
+        }
+    }
+
+    /// appends the samples of a page of the same type
+    fn append(&mut self, page:TiffStack) -> Result<(), TiffStack> {
+        match (self, page) {
+            (TiffStack::U8(x), TiffStack::U8(p)) => x.extend(p),
+            (TiffStack::U16(x), TiffStack::U16(p)) => x.extend(p),
+            (TiffStack::F32(x), TiffStack::F32(p)) => x.extend(p),
+            (_, page) => return Err(page),
+        }
+        Ok(())
+    }
+}
+
+/// sample types that can be written to a grayscale tiff
+pub trait TiffSample: Copy {
+    type Color: colortype::ColorType<Inner = Self>;
+}
+
+impl TiffSample for u8 {
+    type Color = colortype::Gray8;
+}
+
+impl TiffSample for u16 {
+    type Color = colortype::Gray16;
+}
+
+impl TiffSample for f32 {
+    type Color = colortype::Gray32Float;
+}
+
+/// reads the pages of a grayscale tiff into an array of shape [width, height, pages]. Pages must
+/// all have the same size and sample type
+pub fn read_tiff_stack(file:impl AsRef<Path>) -> Result<(TiffStack, ArrayDim), TiffIoError> {
+    let path = file.as_ref();
+    let r = BufReader::new(File::open(path).map_err(io_err(path))?);
+    let mut decoder = Decoder::new(r).map_err(tiff_err(path))?.with_limits(Limits::unlimited());
+
+    let mut stack:Option<TiffStack> = None;
+    let mut size = (0, 0);
+    let mut page = 0;
+    loop {
+        let unsupported = |msg:String| TiffIoError::Unsupported{path: path.to_path_buf(), page, msg};
+        let inconsistent = |msg:String| TiffIoError::InconsistentPage{path: path.to_path_buf(), page, msg};
+        let page_size = decoder.dimensions().map_err(tiff_err(path))?;
+        match decoder.colortype().map_err(tiff_err(path))? {
+            ColorType::Gray(_) => {}
+            c => return Err(unsupported(format!("color type {:?} is not grayscale", c))),
+        }
+        let samples = match decoder.read_image().map_err(tiff_err(path))? {
+            DecodingResult::U8(x) => TiffStack::U8(x),
+            DecodingResult::U16(x) => TiffStack::U16(x),
+            DecodingResult::F32(x) => TiffStack::F32(x),
+            _=> return Err(unsupported(String::from("only 8 bit, 16 bit and 32 bit float samples are supported"))),
+        };
+        match stack.as_mut() {
+            None => {
+                stack = Some(samples);
+                size = page_size;
+            }
+            Some(stack) => {
+                if page_size != size {
+                    return Err(inconsistent(format!(
+                        "is {} x {} but the first page is {} x {}", page_size.0, page_size.1, size.0, size.1
+                    )));
+                }
+                let first = stack.name();
+                stack.append(samples).map_err(|s| inconsistent(format!(
+                    "has {} samples but the first page has {} samples", s.name(), first
+                )))?;
+            }
+        }
+        page += 1;
+        if !decoder.more_images() {
+            break;
+        }
+        decoder.next_image().map_err(tiff_err(path))?;
+    }
+    let dims = ArrayDim::from_shape(&[size.0 as usize, size.1 as usize, page]);
+    Ok((stack.expect("a tiff has at least one page"), dims))
+}
+
+/// tiff offsets are 32 bit, so larger files are written as BigTIFF. This leaves room for the
+/// directories
+const BIG_TIFF_BYTES: usize = u32::MAX as usize - (1 << 24);
+
+/// writes an array to a grayscale tiff with one page per index along page_axis. The remaining axes
+/// form the page, with the first non-singleton axis along the width, so at most two of them may
+/// be larger than 1. Files over 4 GB are written as BigTIFF, which not all readers support
+pub fn write_tiff_stack<T:TiffSample>(file:impl AsRef<Path>, data:&[T], dims:ArrayDim, page_axis:usize) -> Result<(), TiffIoError>
+where [T]: TiffValue {
+    let path = file.as_ref();
+    if data.len() != dims.numel() {
+        return Err(TiffIoError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+    }
+    if page_axis >= crate::N_DIMS {
+        return Err(TiffIoError::InvalidPageAxis(format!("axis {} is out of range", page_axis)));
+    }
+    let page_shape:Vec<usize> = dims.shape().iter().enumerate()
+        .filter(|&(ax, &d)| ax != page_axis && d > 1)
+        .map(|(_, &d)| d)
+        .collect();
+    if page_shape.len() > 2 {
+        return Err(TiffIoError::InvalidPageAxis(format!(
+            "pages along axis {} of an array of shape {:?} have more than 2 dimensions", page_axis, dims.shape_ns()
+        )));
+    }
+    let width = page_shape.first().copied().unwrap_or(1) as u32;
+    let height = page_shape.get(1).copied().unwrap_or(1) as u32;
+
+    let w = BufWriter::new(File::create(path).map_err(io_err(path))?);
+    if size_of_val(data) > BIG_TIFF_BYTES {
+        let mut enc = TiffEncoder::new_big(w).map_err(tiff_err(path))?;
+        write_pages(&mut enc, path, data, dims, page_axis, width, height)
+    } else {
+        let mut enc = TiffEncoder::new(w).map_err(tiff_err(path))?;
+        write_pages(&mut enc, path, data, dims, page_axis, width, height)
+    }
+}
+
+fn write_pages<T:TiffSample, W:Write + Seek, K:TiffKind>(enc:&mut TiffEncoder<W, K>, path:&Path, data:&[T], dims:ArrayDim, page_axis:usize, width:u32, height:u32) -> Result<(), TiffIoError>
+where [T]: TiffValue {
+    let mut size = *dims.shape();
+    size[page_axis] = 1;
+    let mut offset = [0usize; crate::N_DIMS];
+    for i in 0..dims.shape()[page_axis] {
+        offset[page_axis] = i;
+        let (page, _) = dims.copy_region(data, &offset, &size);
+        enc.write_image::<T::Color>(width, height, &page).map_err(tiff_err(path))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,225 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use crate::ArrayDim;
+
+#[cfg(test)]
+mod tests {
+    use crate::ArrayDim;
+    use crate::io_vtk::{read_vtk_structured_points, write_vtk_structured_points, VtkIoError};
+
+    #[test]
+    fn test_binary_round_trip() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32 * 0.1 - 1.).collect();
+        write_vtk_structured_points("test_vtk_binary.vtk",&x,dims,[0.5,1.,2.],[-1.,0.,10.],true).unwrap();
+        let (y,y_dims,geometry) = read_vtk_structured_points("test_vtk_binary.vtk").unwrap();
+        std::fs::remove_file("test_vtk_binary.vtk").unwrap();
+        assert_eq!(y,x);
+        assert_eq!(y_dims.shape(),dims.shape());
+        assert_eq!(geometry.spacing,[0.5,1.,2.]);
+        assert_eq!(geometry.origin,[-1.,0.,10.]);
+    }
+
+    #[test]
+    fn test_ascii_header() {
+        let dims = ArrayDim::from_shape(&[3,2]);
+        let x = [0.,1.5,-2.,3.,4.25,5.];
+        write_vtk_structured_points("test_vtk_ascii.vtk",&x,dims,[1.,1.,1.],[0.,0.,0.],false).unwrap();
+        let text = std::fs::read_to_string("test_vtk_ascii.vtk").unwrap();
+        let lines:Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0],"# vtk DataFile Version 3.0");
+        assert_eq!(lines[2],"ASCII");
+        assert_eq!(lines[3],"DATASET STRUCTURED_POINTS");
+        assert_eq!(lines[4],"DIMENSIONS 3 2 1");
+        assert_eq!(lines[5],"SPACING 1 1 1");
+        assert_eq!(lines[6],"ORIGIN 0 0 0");
+        assert_eq!(lines[7],"POINT_DATA 6");
+        assert_eq!(lines[8],"SCALARS scalars float 1");
+        assert_eq!(lines[9],"LOOKUP_TABLE default");
+
+        let (y,y_dims,_) = read_vtk_structured_points("test_vtk_ascii.vtk").unwrap();
+        std::fs::remove_file("test_vtk_ascii.vtk").unwrap();
+        assert_eq!(y,x);
+        assert_eq!(y_dims.shape_ns(),&[3,2]);
+
+        let dims = ArrayDim::from_shape(&[2,2,2,2]);
+        let r = write_vtk_structured_points("test_vtk_4d.vtk",&dims.alloc(0.),dims,[1.;3],[0.;3],true);
+        assert!(matches!(r,Err(VtkIoError::InvalidDims(..))));
+    }
+
+}
+
+#[derive(Debug)]
+pub enum VtkIoError {
+    IO(PathBuf, std::io::Error),
+    Format{path: PathBuf, msg: String},
+    Unsupported{path: PathBuf, msg: String},
+    InconsistentArraySize{expected: usize, actual: usize},
+    InvalidDims(String),
+}
+
+impl Display for VtkIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VtkIoError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            VtkIoError::Format {path, msg} => write!(f, "invalid vtk file {}: {}", path.display(), msg),
+            VtkIoError::Unsupported {path, msg} => write!(f, "unsupported vtk file {}: {}", path.display(), msg),
+            VtkIoError::InconsistentArraySize {expected, actual} => write!(
+                f, "array dimensions require {} elements but {} were supplied", expected, actual
+            ),
+            VtkIoError::InvalidDims(msg) => write!(f, "invalid dimensions: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for VtkIoError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> VtkIoError {
+    let path = path.to_path_buf();
+    move |e| VtkIoError::IO(path, e)
+}
+
+/// the grid geometry of a structured points dataset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VtkGeometry {
+    pub spacing: [f64; 3],
+    pub origin: [f64; 3],
+}
+
+/// writes a volume of up to 3 dimensions to a legacy vtk file as structured points with a single
+/// float scalar field, for viewing in ParaView. Binary files are big-endian as the format requires
+pub fn write_vtk_structured_points(file:impl AsRef<Path>, data:&[f32], dims:ArrayDim, spacing:[f64; 3], origin:[f64; 3], binary:bool) -> Result<(), VtkIoError> {
+    let path = file.as_ref();
+    if data.len() != dims.numel() {
+        return Err(VtkIoError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+    }
+    if dims.shape_ns().len() > 3 {
+        return Err(VtkIoError::InvalidDims(format!("structured points have at most 3 dimensions, got {:?}", dims.shape_ns())));
+    }
+    let [nx, ny, nz, ..] = *dims.shape();
+
+    let mut w = BufWriter::new(File::create(path).map_err(io_err(path))?);
+    let mut header = String::from("# vtk DataFile Version 3.0\narray-lib volume\n");
+    header.push_str(if binary { "BINARY\n" } else { "ASCII\n" });
+    header.push_str("DATASET STRUCTURED_POINTS\n");
+    header.push_str(&format!("DIMENSIONS {} {} {}\n", nx, ny, nz));
+    header.push_str(&format!("SPACING {} {} {}\n", spacing[0], spacing[1], spacing[2]));
+    header.push_str(&format!("ORIGIN {} {} {}\n", origin[0], origin[1], origin[2]));
+    header.push_str(&format!("POINT_DATA {}\n", data.len()));
+    header.push_str("SCALARS scalars float 1\nLOOKUP_TABLE default\n");
+    w.write_all(header.as_bytes()).map_err(io_err(path))?;
+
+    if binary {
+        let bytes:Vec<u8> = data.iter().flat_map(|x| x.to_be_bytes()).collect();
+        w.write_all(&bytes).map_err(io_err(path))?;
+        w.write_all(b"\n").map_err(io_err(path))?;
+    } else {
+        // one line per row along x
+        for row in data.chunks(nx) {
+            let line:Vec<String> = row.iter().map(|x| x.to_string()).collect();
+            writeln!(w, "{}", line.join(" ")).map_err(io_err(path))?;
+        }
+    }
+    w.flush().map_err(io_err(path))
+}
+
+/// reads a legacy vtk file of structured points with a scalar field, as written by
+/// write_vtk_structured_points. Only the first scalar field is read, with a single component
+pub fn read_vtk_structured_points(file:impl AsRef<Path>) -> Result<(Vec<f32>, ArrayDim, VtkGeometry), VtkIoError> {
+    let path = file.as_ref();
+    let bytes = std::fs::read(path).map_err(io_err(path))?;
+    let format_err = |msg:String| VtkIoError::Format{path: path.to_path_buf(), msg};
+    let unsupported = |msg:String| VtkIoError::Unsupported{path: path.to_path_buf(), msg};
+
+    // header lines are read up to the lookup table, after which the data begins
+    let mut pos = 0;
+    let mut lines = vec![];
+    while !lines.last().is_some_and(|l:&String| l.starts_with("LOOKUP_TABLE")) {
+        if pos >= bytes.len() {
+            return Err(format_err(String::from("no scalar data found")));
+        }
+        let end = bytes[pos..].iter().position(|&b| b == b'\n').map(|e| pos + e).unwrap_or(bytes.len());
+        lines.push(String::from_utf8_lossy(&bytes[pos..end]).trim().to_string());
+        pos = end + 1;
+    }
+    if !lines[0].starts_with("# vtk DataFile") {
+        return Err(format_err(String::from("missing vtk file identifier")));
+    }
+    let binary = match lines.get(2).map(|l| l.as_str()) {
+        Some("BINARY") => true,
+        Some("ASCII") => false,
+        f => return Err(format_err(format!("expected ASCII or BINARY, found {:?}", f))),
+    };
+
+    let mut shape = None;
+    let mut geometry = VtkGeometry{spacing: [1.; 3], origin: [0.; 3]};
+    let mut n_points = None;
+    let mut scalar_type = None;
+    for line in &lines[3..] {
+        let fields:Vec<&str> = line.split_whitespace().collect();
+        let numbers = |n:usize| -> Result<Vec<f64>, VtkIoError> {
+            fields.get(1..=n).and_then(|f| f.iter().map(|x| x.parse().ok()).collect())
+                .ok_or_else(|| format_err(format!("invalid line {}", line)))
+        };
+        match fields.first().copied() {
+            Some("DATASET") if fields.get(1) != Some(&"STRUCTURED_POINTS") => {
+                return Err(unsupported(format!("dataset {}", fields.get(1).unwrap_or(&""))));
+            }
+            Some("DIMENSIONS") => shape = Some(numbers(3)?.iter().map(|&d| d as usize).collect::<Vec<_>>()),
+            Some("SPACING") | Some("ASPECT_RATIO") => geometry.spacing.copy_from_slice(&numbers(3)?),
+            Some("ORIGIN") => geometry.origin.copy_from_slice(&numbers(3)?),
+            Some("POINT_DATA") => n_points = Some(numbers(1)?[0] as usize),
+            Some("SCALARS") => {
+                if fields.get(3).is_some_and(|c| *c != "1") {
+                    return Err(unsupported(String::from("scalars with more than 1 component")));
+                }
+                scalar_type = fields.get(2).map(|t| t.to_string());
+            }
+            _=> {}
+        }
+    }
+    let shape = shape.ok_or_else(|| format_err(String::from("DIMENSIONS not found")))?;
+    let dims = ArrayDim::try_from_shape(&shape).map_err(|e| format_err(e.to_string()))?;
+    let n = n_points.unwrap_or(dims.numel());
+    if n != dims.numel() {
+        return Err(format_err(format!("POINT_DATA {} does not match DIMENSIONS {:?}", n, shape)));
+    }
+    let scalar_type = scalar_type.ok_or_else(|| format_err(String::from("SCALARS not found")))?;
+
+    let data:Vec<f32> = if binary {
+        let word_size = match scalar_type.as_str() {
+            "unsigned_char" | "char" => 1,
+            "short" | "unsigned_short" => 2,
+            "int" | "unsigned_int" | "float" => 4,
+            "double" => 8,
+            t => return Err(unsupported(format!("scalar type {}", t))),
+        };
+        let body = &bytes[pos.min(bytes.len())..];
+        if body.len() < n * word_size {
+            return Err(format_err(format!("expected {} bytes of data, found {}", n * word_size, body.len())));
+        }
+        let samples = body[..n * word_size].chunks_exact(word_size);
+        match scalar_type.as_str() {
+            "unsigned_char" => samples.map(|b| b[0] as f32).collect(),
+            "char" => samples.map(|b| b[0] as i8 as f32).collect(),
+            "short" => samples.map(|b| i16::from_be_bytes([b[0], b[1]]) as f32).collect(),
+            "unsigned_short" => samples.map(|b| u16::from_be_bytes([b[0], b[1]]) as f32).collect(),
+            "int" => samples.map(|b| i32::from_be_bytes(b.try_into().unwrap()) as f32).collect(),
+            "unsigned_int" => samples.map(|b| u32::from_be_bytes(b.try_into().unwrap()) as f32).collect(),
+            "float" => samples.map(|b| f32::from_be_bytes(b.try_into().unwrap())).collect(),
+            _=> samples.map(|b| f64::from_be_bytes(b.try_into().unwrap()) as f32).collect(),
+        }
+    } else {
+        let text = String::from_utf8_lossy(&bytes[pos.min(bytes.len())..]);
+        let data = text.split_whitespace().take(n).map(|x| x.parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|e| format_err(format!("invalid value: {}", e)))?;
+        if data.len() != n {
+            return Err(format_err(format!("expected {} values, found {}", n, data.len())));
+        }
+        data
+    };
+    Ok((data, dims, geometry))
+}
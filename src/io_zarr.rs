@@ -0,0 +1,444 @@
+use std::fmt::Display;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use flate2::Compression;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::{AllocError, ArrayDim};
+
+#[cfg(test)]
+mod tests {
+    use crate::ArrayDim;
+    use crate::io_zarr::{read_zarr, read_zarr_region, write_zarr, write_zarr_with_order, ZarrCompressor, ZarrIoError, ZarrOrder};
+
+    #[test]
+    fn test_edge_chunks() {
+        // 3 x 2 x 2 chunks that do not evenly divide 7 x 5 x 3
+        let dims = ArrayDim::from_shape(&[7,5,3]);
+        let x:Vec<f32> = (0..dims.numel()).map(|i| i as f32 - 10.).collect();
+        for (order,compressor) in [
+            (ZarrOrder::F,ZarrCompressor::None),
+            (ZarrOrder::C,ZarrCompressor::Gzip(5)),
+            (ZarrOrder::F,ZarrCompressor::Zlib(1)),
+        ] {
+            write_zarr_with_order("test_zarr_edge.zarr",&x,dims,&[3,2,2],compressor,order).unwrap();
+            let (y,y_dims) = read_zarr("test_zarr_edge.zarr").unwrap();
+            assert_eq!(y,x);
+            assert_eq!(y_dims.shape(),dims.shape());
+
+            let (r,r_dims) = read_zarr_region("test_zarr_edge.zarr",&[2,3,1],&[5,2,2]).unwrap();
+            let (expected,expected_dims) = dims.copy_region(&x,&[2,3,1],&[5,2,2]);
+            assert_eq!(r,expected);
+            assert_eq!(r_dims.shape(),expected_dims.shape());
+        }
+        // 3 x 3 x 2 chunks
+        assert_eq!(std::fs::read_dir("test_zarr_edge.zarr").unwrap().count(),18 + 1);
+        assert!(matches!(read_zarr_region("test_zarr_edge.zarr",&[5,0,0],&[3,1,1]),Err(ZarrIoError::InvalidRegion(..))));
+        std::fs::remove_dir_all("test_zarr_edge.zarr").unwrap();
+    }
+
+    #[test]
+    fn test_c_order_layout() {
+        // a single 2 x 3 chunk in C order holds the rows one after another
+        let dims = ArrayDim::from_shape(&[2,3]);
+        let x = [0.,1.,2.,3.,4.,5.];
+        write_zarr_with_order("test_zarr_c.zarr",&x,dims,&[2,3],ZarrCompressor::None,ZarrOrder::C).unwrap();
+        let raw = std::fs::read("test_zarr_c.zarr/0.0").unwrap();
+        let stored:Vec<f32> = raw.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect();
+        assert_eq!(stored,vec![0.,2.,4.,1.,3.,5.]);
+
+        // missing chunks read as the fill value
+        write_zarr("test_zarr_c.zarr",&x,dims,&[1,3],ZarrCompressor::None).unwrap();
+        std::fs::remove_file("test_zarr_c.zarr/1.0").unwrap();
+        let (y,_) = read_zarr("test_zarr_c.zarr").unwrap();
+        std::fs::remove_dir_all("test_zarr_c.zarr").unwrap();
+        assert_eq!(y,vec![0.,0.,2.,0.,4.,0.]);
+    }
+
+}
+
+#[derive(Debug)]
+pub enum ZarrIoError {
+    IO(PathBuf, std::io::Error),
+    Json{path: PathBuf, source: serde_json::Error},
+    Metadata{path: PathBuf, msg: String},
+    Unsupported{path: PathBuf, msg: String},
+    ChunkSize{path: PathBuf, expected: usize, actual: usize},
+    InvalidRegion(String),
+    InconsistentArraySize{expected: usize, actual: usize},
+    Alloc{path: PathBuf, source: AllocError},
+}
+
+impl Display for ZarrIoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ZarrIoError::IO(path, e) => write!(f, "IO error for {}: {}", path.display(), e),
+            ZarrIoError::Json {path, source} => write!(f, "invalid json in {}: {}", path.display(), source),
+            ZarrIoError::Metadata {path, msg} => write!(f, "invalid zarr metadata in {}: {}", path.display(), msg),
+            ZarrIoError::Unsupported {path, msg} => write!(f, "unsupported zarr array {}: {}", path.display(), msg),
+            ZarrIoError::ChunkSize {path, expected, actual} => write!(
+                f, "chunk {} has {} bytes but {} were expected", path.display(), actual, expected
+            ),
+            ZarrIoError::InvalidRegion(msg) => write!(f, "invalid region: {}", msg),
+            ZarrIoError::InconsistentArraySize {expected, actual} => write!(
+                f, "array dimensions require {} elements but {} were supplied", expected, actual
+            ),
+            ZarrIoError::Alloc {path, source} => write!(f, "can't read {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for ZarrIoError {}
+
+fn io_err(path: &Path) -> impl FnOnce(std::io::Error) -> ZarrIoError {
+    let path = path.to_path_buf();
+    move |e| ZarrIoError::IO(path, e)
+}
+
+/// compression of chunk files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZarrCompressor {
+    None,
+    /// gzip with a level from 0 to 9
+    Gzip(u32),
+    /// zlib with a level from 0 to 9
+    Zlib(u32),
+}
+
+/// memory layout of the data within each chunk. The array axes are the zarr axes in both cases,
+/// so element [i, j, k] of the array is element [i, j, k] in zarr (and numpy)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ZarrOrder {
+    /// row-major chunks, the default for zarr-python. Chunks are transposed on read and write
+    C,
+    /// column-major chunks that match the layout of this crate
+    #[default]
+    F,
+}
+
+/// the .zarray metadata
+#[derive(Serialize, Deserialize)]
+struct ZarrMeta {
+    zarr_format: u32,
+    shape: Vec<usize>,
+    chunks: Vec<usize>,
+    dtype: String,
+    compressor: Option<Value>,
+    fill_value: Value,
+    order: String,
+    filters: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dimension_separator: Option<String>,
+}
+
+/// a zarr array opened for reading
+struct ZarrArray {
+    path: PathBuf,
+    dims: ArrayDim,
+    chunk_dims: ArrayDim,
+    rank: usize,
+    big_endian: bool,
+    compressor: ZarrCompressor,
+    fill_value: f32,
+    order: ZarrOrder,
+    separator: String,
+}
+
+impl ZarrArray {
+
+    fn open(path:&Path) -> Result<ZarrArray, ZarrIoError> {
+        let meta_path = path.join(".zarray");
+        let text = std::fs::read_to_string(&meta_path).map_err(io_err(&meta_path))?;
+        let meta:ZarrMeta = serde_json::from_str(&text).map_err(|source| ZarrIoError::Json{path: meta_path.clone(), source})?;
+        let meta_err = |msg:String| ZarrIoError::Metadata{path: meta_path.clone(), msg};
+        let unsupported = |msg:String| ZarrIoError::Unsupported{path: path.to_path_buf(), msg};
+
+        if meta.zarr_format != 2 {
+            return Err(unsupported(format!("zarr format {}", meta.zarr_format)));
+        }
+        if meta.shape.len() != meta.chunks.len() || meta.shape.len() > crate::N_DIMS {
+            return Err(meta_err(format!("shape {:?} and chunks {:?} must have the same rank of at most {}", meta.shape, meta.chunks, crate::N_DIMS)));
+        }
+        if meta.chunks.contains(&0) {
+            return Err(meta_err(String::from("chunk sizes must be non-zero")));
+        }
+        let big_endian = match meta.dtype.as_str() {
+            "<f4" => false,
+            ">f4" => true,
+            d => return Err(unsupported(format!("dtype {}, only f4 is supported", d))),
+        };
+        if meta.filters.as_ref().is_some_and(|f| !f.is_null()) {
+            return Err(unsupported(String::from("filters are not supported")));
+        }
+        let compressor = match &meta.compressor {
+            None | Some(Value::Null) => ZarrCompressor::None,
+            Some(c) => {
+                let level = c["level"].as_u64().unwrap_or(6) as u32;
+                match c["id"].as_str() {
+                    Some("gzip") => ZarrCompressor::Gzip(level),
+                    Some("zlib") => ZarrCompressor::Zlib(level),
+                    id => return Err(unsupported(format!("compressor {}, only gzip and zlib are supported", id.unwrap_or("without an id")))),
+                }
+            }
+        };
+        let fill_value = match &meta.fill_value {
+            Value::Null => 0.,
+            Value::Number(n) => n.as_f64().unwrap_or(0.) as f32,
+            Value::String(s) if s == "NaN" => f32::NAN,
+            Value::String(s) if s == "Infinity" => f32::INFINITY,
+            Value::String(s) if s == "-Infinity" => f32::NEG_INFINITY,
+            v => return Err(meta_err(format!("invalid fill value {}", v))),
+        };
+        let order = match meta.order.as_str() {
+            "C" => ZarrOrder::C,
+            "F" => ZarrOrder::F,
+            o => return Err(meta_err(format!("invalid order {}", o))),
+        };
+        Ok(ZarrArray {
+            path: path.to_path_buf(),
+            dims: ArrayDim::try_from_shape(&meta.shape).map_err(|e| meta_err(e.to_string()))?,
+            chunk_dims: ArrayDim::try_from_shape(&meta.chunks).map_err(|e| meta_err(e.to_string()))?,
+            rank: meta.shape.len(),
+            big_endian,
+            compressor,
+            fill_value,
+            order,
+            separator: meta.dimension_separator.unwrap_or_else(|| String::from(".")),
+        })
+    }
+
+    /// the path of the chunk at grid coordinates
+    fn chunk_path(&self, coords:&[usize]) -> PathBuf {
+        let key:Vec<String> = coords[..self.rank.max(1)].iter().map(|c| c.to_string()).collect();
+        self.path.join(key.join(&self.separator))
+    }
+
+    /// reads a chunk as column-major data of the full chunk shape, or None if it was never written
+    fn read_chunk(&self, coords:&[usize]) -> Result<Option<Vec<f32>>, ZarrIoError> {
+        let path = self.chunk_path(coords);
+        let stored = match std::fs::read(&path) {
+            Ok(b) => b,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(ZarrIoError::IO(path, e)),
+        };
+        let bytes = match self.compressor {
+            ZarrCompressor::None => stored,
+            ZarrCompressor::Gzip(_) => {
+                let mut b = vec![];
+                GzDecoder::new(stored.as_slice()).read_to_end(&mut b).map_err(io_err(&path))?;
+                b
+            }
+            ZarrCompressor::Zlib(_) => {
+                let mut b = vec![];
+                ZlibDecoder::new(stored.as_slice()).read_to_end(&mut b).map_err(io_err(&path))?;
+                b
+            }
+        };
+        let expected = self.chunk_dims.numel() * 4;
+        if bytes.len() != expected {
+            return Err(ZarrIoError::ChunkSize{path, expected, actual: bytes.len()});
+        }
+        let data:Vec<f32> = bytes.chunks_exact(4).map(|b| {
+            let b:[u8; 4] = b.try_into().unwrap();
+            if self.big_endian { f32::from_be_bytes(b) } else { f32::from_le_bytes(b) }
+        }).collect();
+        Ok(Some(match self.order {
+            ZarrOrder::F => data,
+            ZarrOrder::C => c_to_f(&data, &self.chunk_dims.shape()[..self.rank]),
+        }))
+    }
+}
+
+fn reversed_axes(rank:usize) -> Vec<usize> {
+    (0..rank).rev().collect()
+}
+
+/// converts row-major data of a chunk shape to column-major data with the same shape
+fn c_to_f(data:&[f32], chunk_shape:&[usize]) -> Vec<f32> {
+    let reversed:Vec<usize> = chunk_shape.iter().rev().copied().collect();
+    let dims = ArrayDim::from_shape(&reversed);
+    let mut dst = data.to_vec();
+    dims.permute(data, &mut dst, &reversed_axes(dims.shape_ns().len()));
+    dst
+}
+
+/// converts column-major data to row-major data with the same shape
+fn f_to_c(data:&[f32], dims:&ArrayDim) -> Vec<f32> {
+    let mut dst = data.to_vec();
+    dims.permute(data, &mut dst, &reversed_axes(dims.shape_ns().len()));
+    dst
+}
+
+/// the contiguous runs along axis 0 that copy the part of a chunk inside a region, as
+/// (chunk address, region address, length)
+fn chunk_runs(chunk_dims:&ArrayDim, chunk_start:&[usize], region_dims:&ArrayDim, region_start:&[usize]) -> Vec<(usize, usize, usize)> {
+    let mut lo = [0usize; crate::N_DIMS];
+    let mut len = [1usize; crate::N_DIMS];
+    for ax in 0..crate::N_DIMS {
+        let start = chunk_start[ax].max(region_start[ax]);
+        let end = (chunk_start[ax] + chunk_dims.shape()[ax]).min(region_start[ax] + region_dims.shape()[ax]);
+        if end <= start {
+            return vec![];
+        }
+        lo[ax] = start;
+        len[ax] = end - start;
+    }
+    let mut outer = len;
+    outer[0] = 1;
+    let outer = ArrayDim::from_shape(&outer);
+    (0..outer.numel()).map(|i| {
+        let idx = outer.calc_idx(i);
+        let mut c = [0usize; crate::N_DIMS];
+        let mut r = [0usize; crate::N_DIMS];
+        for ax in 0..crate::N_DIMS {
+            c[ax] = lo[ax] + idx[ax] - chunk_start[ax];
+            r[ax] = lo[ax] + idx[ax] - region_start[ax];
+        }
+        (chunk_dims.calc_addr(&c), region_dims.calc_addr(&r), len[0])
+    }).collect()
+}
+
+/// reads a whole zarr v2 array of f4 data
+pub fn read_zarr(path:impl AsRef<Path>) -> Result<(Vec<f32>, ArrayDim), ZarrIoError> {
+    let z = ZarrArray::open(path.as_ref())?;
+    let shape = z.dims.shape()[..z.rank].to_vec();
+    read_zarr_array_region(&z, &vec![0; z.rank], &shape)
+}
+
+/// reads a hyper-rectangular region of a zarr v2 array given by an offset and size, as with
+/// read_cfl_region. Only the chunks that intersect the region are read, and chunks that were
+/// never written read as the fill value
+pub fn read_zarr_region(path:impl AsRef<Path>, offset:&[usize], size:&[usize]) -> Result<(Vec<f32>, ArrayDim), ZarrIoError> {
+    let z = ZarrArray::open(path.as_ref())?;
+    read_zarr_array_region(&z, offset, size)
+}
+
+fn read_zarr_array_region(z:&ZarrArray, offset:&[usize], size:&[usize]) -> Result<(Vec<f32>, ArrayDim), ZarrIoError> {
+    let region_dims = z.dims.region_dims(offset, size).map_err(ZarrIoError::InvalidRegion)?;
+    let mut region_start = [0usize; crate::N_DIMS];
+    region_start[..offset.len()].copy_from_slice(offset);
+
+    // the box of chunk grid coordinates that intersect the region
+    let mut first = [0usize; crate::N_DIMS];
+    let mut count = [1usize; crate::N_DIMS];
+    for ax in 0..crate::N_DIMS {
+        let c = z.chunk_dims.shape()[ax];
+        first[ax] = region_start[ax] / c;
+        count[ax] = (region_start[ax] + region_dims.shape()[ax] - 1) / c - first[ax] + 1;
+    }
+    let grid = ArrayDim::from_shape(&count);
+
+    let mut data = region_dims.try_alloc(z.fill_value)
+        .map_err(|source| ZarrIoError::Alloc{path: z.path.clone(), source})?;
+    for i in 0..grid.numel() {
+        let mut coords = grid.calc_idx(i);
+        let mut chunk_start = [0usize; crate::N_DIMS];
+        for ax in 0..crate::N_DIMS {
+            coords[ax] += first[ax];
+            chunk_start[ax] = coords[ax] * z.chunk_dims.shape()[ax];
+        }
+        let Some(chunk) = z.read_chunk(&coords)? else {
+            continue
+        };
+        for (c, r, len) in chunk_runs(&z.chunk_dims, &chunk_start, &region_dims, &region_start) {
+            data[r..r + len].copy_from_slice(&chunk[c..c + len]);
+        }
+    }
+    Ok((data, region_dims))
+}
+
+/// writes an array to a zarr v2 directory store with column-major (F order) chunks (see
+/// write_zarr_with_order)
+pub fn write_zarr(path:impl AsRef<Path>, data:&[f32], dims:ArrayDim, chunk_shape:&[usize], compressor:ZarrCompressor) -> Result<(), ZarrIoError> {
+    write_zarr_with_order(path, data, dims, chunk_shape, compressor, ZarrOrder::F)
+}
+
+/// writes an array to a zarr v2 directory store of little-endian f4 data with the given chunk
+/// shape. Chunks at the edges of the array are padded to the full chunk shape with zeros as the
+/// format requires. Existing chunk files of the same name are overwritten
+pub fn write_zarr_with_order(path:impl AsRef<Path>, data:&[f32], dims:ArrayDim, chunk_shape:&[usize], compressor:ZarrCompressor, order:ZarrOrder) -> Result<(), ZarrIoError> {
+    let path = path.as_ref();
+    if data.len() != dims.numel() {
+        return Err(ZarrIoError::InconsistentArraySize{expected: dims.numel(), actual: data.len()});
+    }
+    let shape = dims.shape_ns().to_vec();
+    let rank = shape.len();
+    if chunk_shape.len() != rank || chunk_shape.contains(&0) {
+        return Err(ZarrIoError::Metadata{path: path.to_path_buf(), msg: format!(
+            "chunk shape {:?} must be non-zero with one size for each axis of {:?}", chunk_shape, shape
+        )});
+    }
+    std::fs::create_dir_all(path).map_err(io_err(path))?;
+
+    let (compressor_meta, c) = match compressor {
+        ZarrCompressor::None => (Value::Null, ZarrCompressor::None),
+        ZarrCompressor::Gzip(level) => (json!({"id": "gzip", "level": level.min(9)}), ZarrCompressor::Gzip(level.min(9))),
+        ZarrCompressor::Zlib(level) => (json!({"id": "zlib", "level": level.min(9)}), ZarrCompressor::Zlib(level.min(9))),
+    };
+    let meta = ZarrMeta {
+        zarr_format: 2,
+        shape: shape.clone(),
+        chunks: chunk_shape.to_vec(),
+        dtype: String::from("<f4"),
+        compressor: Some(compressor_meta),
+        fill_value: json!(0.0),
+        order: String::from(if order == ZarrOrder::C { "C" } else { "F" }),
+        filters: None,
+        dimension_separator: Some(String::from(".")),
+    };
+    let meta_path = path.join(".zarray");
+    let text = serde_json::to_string_pretty(&meta).map_err(|source| ZarrIoError::Json{path: meta_path.clone(), source})?;
+    std::fs::write(&meta_path, text).map_err(io_err(&meta_path))?;
+
+    let z = ZarrArray {
+        path: path.to_path_buf(),
+        dims,
+        chunk_dims: ArrayDim::from_shape(chunk_shape),
+        rank,
+        big_endian: false,
+        compressor: c,
+        fill_value: 0.,
+        order,
+        separator: String::from("."),
+    };
+    let count:Vec<usize> = shape.iter().zip(chunk_shape).map(|(s, c)| s.div_ceil(*c)).collect();
+    let grid = ArrayDim::from_shape(&count);
+    let origin = [0usize; crate::N_DIMS];
+    for i in 0..grid.numel() {
+        let coords = grid.calc_idx(i);
+        let mut chunk_start = [0usize; crate::N_DIMS];
+        for ax in 0..rank {
+            chunk_start[ax] = coords[ax] * chunk_shape[ax];
+        }
+        let mut chunk = z.chunk_dims.alloc(0f32);
+        for (c, r, len) in chunk_runs(&z.chunk_dims, &chunk_start, &dims, &origin) {
+            chunk[c..c + len].copy_from_slice(&data[r..r + len]);
+        }
+        if order == ZarrOrder::C {
+            chunk = f_to_c(&chunk, &z.chunk_dims);
+        }
+        write_chunk(&z, &coords, &chunk)?;
+    }
+    Ok(())
+}
+
+fn write_chunk(z:&ZarrArray, coords:&[usize], chunk:&[f32]) -> Result<(), ZarrIoError> {
+    let path = z.chunk_path(coords);
+    let bytes:Vec<u8> = chunk.iter().flat_map(|x| x.to_le_bytes()).collect();
+    let stored = match z.compressor {
+        ZarrCompressor::None => bytes,
+        ZarrCompressor::Gzip(level) => {
+            let mut enc = GzEncoder::new(vec![], Compression::new(level));
+            enc.write_all(&bytes).map_err(io_err(&path))?;
+            enc.finish().map_err(io_err(&path))?
+        }
+        ZarrCompressor::Zlib(level) => {
+            let mut enc = ZlibEncoder::new(vec![], Compression::new(level));
+            enc.write_all(&bytes).map_err(io_err(&path))?;
+            enc.finish().map_err(io_err(&path))?
+        }
+    };
+    std::fs::write(&path, stored).map_err(io_err(&path))
+}
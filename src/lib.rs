@@ -2,14 +2,24 @@
     Minimal library for working with column-major array layouts
     The number of dimensions is static for efficient address calculations
     This is useful for batched matrix calculations and image/signal processing routines
+
+    Without the default std feature the crate is no_std, leaving the dimension and addressing math
+    on core alone. The alloc feature adds the parts that build vectors
  */
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// std builds take the alloc types and macros from the std prelude
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+#[macro_use]
+extern crate alloc;
+
 #[cfg(feature = "io-nifti")]
 pub mod io_nifti;
 
 #[cfg(feature = "io-nrrd")]
 pub mod io_nrrd;
 
-use std::fmt::Display;
+use core::fmt::Display;
 
 #[cfg(feature = "io-nrrd")]
 pub use nrrd_rs;
@@ -23,25 +33,143 @@ pub use mrd_rs;
 #[cfg(feature = "io-cfl")]
 pub mod io_cfl;
 
+#[cfg(all(feature = "io-cfl", feature = "io-nifti"))]
+pub mod convert;
+
+#[cfg(all(feature = "io-nrrd", feature = "io-nifti"))]
+pub mod convert_volume;
+
+#[cfg(feature = "io-npy")]
+pub mod io_npy;
+
+#[cfg(feature = "io-mat")]
+pub mod io_mat;
+
+#[cfg(feature = "io-hdf5")]
+pub mod io_hdf5;
+
+#[cfg(feature = "io-ismrmrd")]
+pub mod io_ismrmrd;
+
+#[cfg(feature = "io-dicom")]
+pub mod io_dicom;
+
+#[cfg(feature = "io-tiff")]
+pub mod io_tiff;
+
+#[cfg(feature = "io-png")]
+pub mod io_png;
+
+#[cfg(feature = "io-raw")]
+pub mod io_raw;
+
+#[cfg(feature = "io-zarr")]
+pub mod io_zarr;
+
+#[cfg(feature = "io-mgh")]
+pub mod io_mgh;
+
+#[cfg(feature = "io-analyze")]
+pub mod io_analyze;
+
+#[cfg(feature = "io-vtk")]
+pub mod io_vtk;
+
+#[cfg(feature = "io-csv")]
+pub mod io_csv;
+
 #[cfg(feature = "io-bruker")]
 pub mod io_bruker;
 
 #[cfg(feature = "io-agilent")]
 pub mod io_agilent;
 
+#[cfg(feature = "info")]
+pub mod info;
+
+#[cfg(feature = "fft")]
+pub mod fft;
+
+#[cfg(all(feature = "ndarray", feature = "std"))]
+pub mod ndarray_interop;
+
+#[cfg(all(feature = "nalgebra", feature = "std"))]
+pub mod nalgebra_interop;
+
+#[cfg(feature = "python")]
+pub mod python;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "std")]
+pub mod io;
+
+pub mod par;
+
+#[cfg(feature = "std")]
+pub mod workspace;
+
+#[cfg(feature = "alloc")]
+pub mod display;
+
+#[cfg(feature = "std")]
+pub mod describe;
+
+#[cfg(feature = "std")]
+pub mod phantom;
+
 #[cfg(feature = "io-cfl")]
 pub use cfl;
 
 pub use num_complex;
 
+#[cfg(feature = "log")]
+pub use log;
+
+#[cfg(feature = "std")]
+pub mod logging;
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use alloc::{string::String, vec::Vec};
+#[cfg(feature = "std")]
+use std::path::PathBuf;
 use num_complex::Complex32;
 use num_traits::Zero;
+#[cfg(feature = "std")]
 use rayon::prelude::*;
+// element loops run in parallel when std is available
+#[cfg(feature = "std")]
+use crate::par::par_for_each_indexed_mut as for_each_indexed_mut;
+#[cfg(not(feature = "std"))]
+use crate::par::for_each_indexed_mut;
+#[cfg(feature = "std")]
+use crate::workspace::Workspace;
 use serde::{Deserialize, Serialize};
 
 const N_DIMS:usize = 16;
 
-#[cfg(test)]
+/// forwards a diagnostic to the log crate at the given level. std turns on the log feature, so
+/// the readers that report diagnostics always have it
+#[cfg(feature = "std")]
+macro_rules! log_event {
+    ($level:ident, $($arg:tt)*) => {
+        log::$level!($($arg)*)
+    };
+}
+#[cfg(feature = "std")]
+pub(crate) use log_event;
+
+/// arrays with at least this many elements are permuted with cache-blocked copies
+pub const PERMUTE_BLOCKED_MIN:usize = 1 << 16;
+
+/// edge length of the square tiles used by permute_blocked
+const PERMUTE_TILE:usize = 32;
+
+/// number of coordinates handled together by calc_addrs_simd and calc_addrs4_simd
+pub const ADDR_LANES:usize = 8;
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
 
     use super::*;
@@ -99,6 +227,104 @@ mod tests {
         assert_eq!(addr,3);
     }
 
+    #[test]
+    fn test_indexer() {
+        for shape in [vec![3,5,7], vec![1,9,1,11,2], vec![13], vec![4,1,1], vec![6,10,1,3,5,1,7]] {
+            let dims = ArrayDim::from_shape(&shape);
+            let indexer = dims.indexer();
+            for addr in 0..dims.numel() {
+                assert_eq!(indexer.calc_idx(addr),dims.calc_idx(addr),"address {} of {:?}",addr,shape);
+            }
+        }
+        // axis sizes that aren't powers of 2 and need the 65 bit magic numbers
+        for d in [3u64, 7, 641, 1 << 40, (1 << 40) + 1, 6700417, u64::MAX] {
+            let div = FastDiv::new(d);
+            for n in [0, 1, d - 1, d, d.wrapping_add(1), 1 << 48, u64::MAX / 3, u64::MAX] {
+                assert_eq!(div.div(n),n / d,"{} / {}",n,d);
+            }
+        }
+    }
+
+    #[test]
+    fn test_calc_addrs_simd() {
+        let mut state = 7u64;
+        let mut rand = |n:usize| -> Vec<u32> {
+            (0..1003).map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state % n as u64) as u32
+            }).collect()
+        };
+        // a length that leaves a partial chunk
+        let d = ArrayDim::from_shape(&[37,29,13]);
+        let (is, js, ks) = (rand(37), rand(29), rand(13));
+        let mut scalar = vec![0u32; is.len()];
+        let mut simd = vec![0u32; is.len()];
+        d.calc_addrs(&is,&js,&ks,&mut scalar);
+        d.calc_addrs_simd(&is,&js,&ks,&mut simd);
+        assert_eq!(scalar,simd);
+        assert_eq!(scalar[5] as usize,d.calc_addr(&[is[5] as usize, js[5] as usize, ks[5] as usize]));
+
+        let d = ArrayDim::from_shape(&[11,7,5,3]);
+        let ls = rand(3);
+        let (is, js, ks) = (rand(11), rand(7), rand(5));
+        d.calc_addrs4(&is,&js,&ks,&ls,&mut scalar);
+        d.calc_addrs4_simd(&is,&js,&ks,&ls,&mut simd);
+        assert_eq!(scalar,simd);
+        assert!(simd.iter().all(|&a| (a as usize) < d.numel()));
+    }
+
+    #[test]
+    fn test_rank() {
+        let cases = [
+            (ArrayDim::new(), 1),
+            (ArrayDim::from_shape(&[]), 1),
+            (ArrayDim::from_shape(&[1,1,1]), 1),
+            (ArrayDim::from_shape(&[4,5,6]), 3),
+            (ArrayDim::from_shape(&[4,1,6,1,1]), 3),
+            (ArrayDim::new().with_dim(2,3), 3),
+            (ArrayDim::from_shape(&[4,5,6]).with_dim(2,1), 2),
+            (ArrayDim::from_shape(&[4,5,6]).with_dim(9,2), 10),
+            (ArrayDim::from([2,3,1,1,1,1,1,1,1,1,1,1,1,1,1,1]), 2),
+        ];
+        for (dims, rank) in cases {
+            assert_eq!(dims.ndim(),rank,"{:?}",dims.shape());
+            assert_eq!(dims.shape_ns().len(),rank);
+            assert_eq!(dims.numel(),dims.shape().iter().product::<usize>());
+        }
+
+        let dims = ArrayDim::from_shape(&[3,1,4,5]);
+        let mut dst = dims.alloc(0);
+        let permuted = dims.permute(&dims.alloc(0), &mut dst, &[2,3,0,1]);
+        assert_eq!(permuted.ndim(),3);
+        assert_eq!(permuted.shape_ns(),&[4,5,3]);
+
+        // the bounded loops agree with a full walk over all axes
+        for addr in 0..dims.numel() {
+            let idx = dims.calc_idx(addr);
+            assert_eq!(dims.calc_addr(&idx),addr);
+            let full:usize = idx.iter().zip(dims.strides()).map(|(i, s)| i * s).sum();
+            assert_eq!(full,addr);
+            assert_eq!(dims.calc_idx_signed(addr).map(|i| i as usize),idx);
+        }
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_rank_serde() {
+        let dims = ArrayDim::from_shape(&[7,1,3]);
+        let json = serde_json::to_string(&dims).unwrap();
+        // the rank isn't serialized, and comes back from the shape
+        assert!(!json.contains("rank"));
+        let back:ArrayDim = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.ndim(),3);
+        assert_eq!(back.strides(),dims.strides());
+        // zero-length axes are rejected
+        let empty = json.replacen("[7,1,3","[7,0,3",1);
+        assert!(serde_json::from_str::<ArrayDim>(&empty).is_err());
+    }
+
     #[test]
     fn test_calc_idx_signed() {
         let dims = ArrayDim::from_shape(&[3,4]);
@@ -114,6 +340,99 @@ mod tests {
         assert_eq!(addr,11);
     }
 
+    #[test]
+    fn test_too_many_dims() {
+        let shape = [2;17];
+        let err = ArrayDim::try_from_shape(&shape).unwrap_err();
+        assert_eq!(err,ShapeError::TooManyDims{shape:shape.to_vec()});
+        assert!(err.to_string().contains("at most 16"));
+        assert!(std::panic::catch_unwind(|| ArrayDim::from_shape(&[2;20])).is_err());
+        // singleton axes past the limit hold no elements, so nothing is lost by dropping them
+        let mut shape = vec![2;16];
+        shape.extend([1;4]);
+        let d = ArrayDim::from_shape(&shape);
+        assert_eq!(d.numel(),shape.iter().product::<usize>());
+        assert!(std::panic::catch_unwind(|| ArrayDim::new().with_dim(16,2)).is_err());
+    }
+
+    #[test]
+    fn test_zero_length_dims() {
+        // empty arrays are rejected when they are constructed
+        assert_eq!(ArrayDim::try_from_shape(&[4,0,3]).unwrap_err(),ShapeError::ZeroLength{axis:1,shape:vec![4,0,3]});
+        assert_eq!(ArrayDim::try_from_shape(&[4,3]).unwrap().numel(),12);
+        assert!(std::panic::catch_unwind(|| ArrayDim::from_shape(&[0])).is_err());
+        assert!(std::panic::catch_unwind(|| ArrayDim::from_shape(&[3,4]).with_dim(2,0)).is_err());
+        let mut shape = [1;16];
+        shape[15] = 0;
+        assert!(std::panic::catch_unwind(|| ArrayDim::from(shape)).is_err());
+        // regions can't be empty either
+        let d = ArrayDim::from_shape(&[4,3]);
+        assert!(d.region_dims(&[0,0],&[0,3]).is_err());
+        assert!(std::panic::catch_unwind(|| d.crop_pad(&[0;12],&[0,0],&[4,0],PadMode::Constant,0)).is_err());
+    }
+
+    #[test]
+    fn test_try_alloc() {
+        let d = ArrayDim::from_shape(&[4,3,2]);
+        assert_eq!(d.try_alloc(1f32).unwrap(),d.alloc(1f32));
+        assert_eq!(d.checked_numel(),Some(24));
+
+        // 2^62 elements can be counted, but not as 4 byte values
+        let huge = ArrayDim::from_shape(&[1 << 21,1 << 21,1 << 20]);
+        assert_eq!(huge.checked_numel(),Some(1 << 62));
+        assert_eq!(huge.try_alloc(0f32).unwrap_err(),AllocError::TooLarge{shape:vec![1 << 21,1 << 21,1 << 20],elem_size:4});
+        let overflowing = ArrayDim::from_shape(&[1 << 32,1 << 32,2]);
+        assert_eq!(overflowing.checked_numel(),None);
+        assert!(matches!(overflowing.try_alloc(0u8),Err(AllocError::TooLarge{..})));
+    }
+
+    #[test]
+    fn test_into_shape() {
+        let expected = ArrayDim::new().with_dim(0,4).with_dim(1,3).with_dim(2,2);
+        let v = vec![4,3,2];
+        assert_eq!(ArrayDim::of(&v[..]),expected);
+        assert_eq!(ArrayDim::of([4,3,2]),expected);
+        assert_eq!(ArrayDim::of(&[4,3,2]),expected);
+        assert_eq!(ArrayDim::of(&v),expected);
+        assert_eq!(ArrayDim::of(v.clone()),expected);
+        assert_eq!(ArrayDim::of((4,3,2)),expected);
+        assert_eq!(ArrayDim::of(ShapeIter((2..5).rev())),expected);
+        assert_eq!(ArrayDim::from((4,3,2)),expected);
+        assert_eq!(ArrayDim::from(&v[..]),expected);
+        assert_eq!(ArrayDim::from(v),expected);
+        assert_eq!(ArrayDim::of((4,3,2,1,1,1)),expected);
+        assert_ne!(ArrayDim::of((4,3)),expected);
+
+        // every form is validated the same way
+        assert!(matches!(ArrayDim::try_of([2;17]),Err(ShapeError::TooManyDims{..})));
+        assert!(matches!(ArrayDim::try_of(ShapeIter(std::iter::repeat(2).take(17))),Err(ShapeError::TooManyDims{..})));
+        assert!(matches!(ArrayDim::try_of((4,0)),Err(ShapeError::ZeroLength{axis:1,..})));
+        assert!(ArrayDim::try_of(vec![1;20]).is_ok());
+    }
+
+    #[test]
+    fn test_full_strides() {
+        // every axis steps over the product of the axes before it, including axes past the shape
+        let check = |d:&ArrayDim| {
+            for k in 0..16 {
+                assert_eq!(d.strides()[k],d.shape()[..k].iter().product::<usize>(),"stride of axis {} for {:?}",k,d.shape());
+            }
+        };
+        check(&ArrayDim::from_shape(&[4,3]));
+        check(&ArrayDim::from_shape(&[4,1,3,1]));
+        check(&ArrayDim::from_shape(&[4,3]).with_dim(5,2));
+        let mut shape = [1;16];
+        shape[2] = 5;
+        shape[7] = 2;
+        check(&ArrayDim::from(shape));
+        check(&ArrayDim::new());
+
+        // the start of the second coil slab was 1 rather than a whole 4 x 3 x 2 volume
+        let d = ArrayDim::from_shape(&[4,3,2]);
+        assert_eq!(d.strides_by_label(DimLabel::COIL),24);
+        assert_eq!(d.strides()[15],d.numel());
+    }
+
     #[test]
     fn test_shape_ns() {
         let dims = ArrayDim::from_shape(&[3,4,5,1,6]);
@@ -164,6 +483,41 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_copy_region() {
+        let d = ArrayDim::from_shape(&[4,3,2]);
+        let x:Vec<usize> = (0..d.numel()).collect();
+        let (r,rd) = d.copy_region(&x,&[1,1,1],&[2,2,1]);
+        assert_eq!(rd.shape_ns(),&[2,2]);
+        assert_eq!(r,vec![17,18,21,22]);
+        assert!(d.region_dims(&[3,0],&[2,1]).is_err());
+        assert!(d.region_dims(&[0,0],&[4,0]).is_err());
+    }
+
+    #[test]
+    fn test_crop_pad() {
+        let d = ArrayDim::from_shape(&[4,3]);
+        let x:Vec<usize> = (0..d.numel()).collect();
+
+        // a centered crop keeps the center sample at the center
+        let offset = d.centered_offset(&[2,1]);
+        assert_eq!(&offset[..2],&[1,1]);
+        let (c,cd) = d.crop_pad(&x,&offset,&[2,1],PadMode::Constant,0);
+        assert_eq!(cd.shape_ns(),&[2]);
+        assert_eq!(c,vec![5,6]);
+
+        // a centered pad of the first axis only
+        let offset = d.centered_offset(&[6]);
+        assert_eq!(offset[0],-1);
+        let (p,pd) = d.crop_pad(&x,&offset[..1],&[6],PadMode::Constant,99);
+        assert_eq!(pd.shape_ns(),&[6,3]);
+        assert_eq!(&p[..6],&[99,0,1,2,3,99]);
+        let (p,_) = d.crop_pad(&x,&offset[..1],&[6],PadMode::Edge,99);
+        assert_eq!(&p[6..12],&[4,4,5,6,7,7]);
+        let (p,_) = d.crop_pad(&x,&offset[..1],&[6],PadMode::Wrap,99);
+        assert_eq!(&p[..6],&[3,0,1,2,3,0]);
+    }
+
     #[test]
     fn test_permute() {
 
@@ -183,6 +537,98 @@ mod tests {
 
     }
 
+    /// all orderings of 0..n
+    fn permutations(n:usize) -> Vec<Vec<usize>> {
+        if n == 0 {
+            return vec![vec![]];
+        }
+        permutations(n - 1).into_iter().flat_map(|p| {
+            (0..n).map(move |i| {
+                let mut q = p.clone();
+                q.insert(i, n - 1);
+                q
+            })
+        }).collect()
+    }
+
+    #[test]
+    fn test_permute_blocked() {
+        // odd sizes larger than a tile leave partial tiles along every axis
+        for shape in [vec![37,3,41,5], vec![5,70,3,2,33], vec![67,45], vec![1,1,1,40]] {
+            let d = ArrayDim::from_shape(&shape);
+            let x:Vec<u32> = (0..d.numel() as u32).collect();
+            for order in permutations(d.shape_ns().len()) {
+                let mut naive = x.clone();
+                let mut blocked = x.clone();
+                let naive_dims = d.permute_naive(&x,&mut naive,&order);
+                let blocked_dims = d.permute_blocked(&x,&mut blocked,&order);
+                assert_eq!(naive_dims.shape(),blocked_dims.shape());
+                assert!(naive == blocked,"permute_blocked differs for shape {:?} and order {:?}",shape,order);
+            }
+        }
+    }
+
+}
+
+/// tests of the core-only API, which also run without default features
+#[cfg(test)]
+mod core_tests {
+
+    use crate::{ArrayDim, PadMode};
+
+    #[test]
+    fn test_no_std_addressing() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        assert_eq!(dims.shape_ns(), &[4,3,2]);
+        let indexer = dims.indexer();
+        for addr in 0..dims.numel() {
+            assert_eq!(dims.calc_addr(&dims.calc_idx(addr)), addr);
+            assert_eq!(indexer.calc_idx(addr), dims.calc_idx(addr));
+        }
+
+        let mut shifted = [0usize; 3];
+        let mut back = [0usize; 3];
+        dims.fft_shift_coords(&[1,2,0], &mut shifted);
+        dims.ifft_shift_coords(&shifted, &mut back);
+        assert_eq!(back, [1,2,0]);
+
+        // a 2 x 2 region has a run of 2 for each row
+        let mut runs = dims.region_runs(&[1,1,1], &[2,2,1]);
+        assert_eq!(runs.next(), Some((dims.calc_addr(&[1,1,1]), 2)));
+        assert_eq!(runs.next(), Some((dims.calc_addr(&[1,2,1]), 2)));
+        assert_eq!(runs.next(), None);
+    }
+
+    #[test]
+    fn test_no_std_shifts() {
+        let dims = ArrayDim::from_shape(&[4,3]);
+        let x:[u32; 12] = core::array::from_fn(|i| i as u32);
+        let mut shifted = [0u32; 12];
+        let mut back = [0u32; 12];
+        dims.fftshift(&x, &mut shifted, true);
+        assert_eq!(shifted[dims.calc_addr(&[2,1])], x[0]);
+        dims.fftshift(&shifted, &mut back, false);
+        assert_eq!(back, x);
+
+        dims.circshift(&[1,0], &x, &mut shifted);
+        assert_eq!(shifted[dims.calc_addr(&[1,0])], x[0]);
+
+        // edge padding by one sample on each side of the first axis
+        let mut padded = [0u32; 18];
+        let region = dims.crop_pad_into(&x, &[-1], &[6], PadMode::Edge, 0, &mut padded);
+        assert_eq!(region, ArrayDim::from_shape(&[6,3]));
+        assert_eq!(padded[region.calc_addr(&[0,1])], x[dims.calc_addr(&[0,1])]);
+        assert_eq!(padded[region.calc_addr(&[5,2])], x[dims.calc_addr(&[3,2])]);
+    }
+
+    #[test]
+    fn test_dim_macro() {
+        assert_eq!(crate::dim![4,3,2], ArrayDim::from_shape(&[4,3,2]));
+        assert_eq!(crate::dim![64,64,], ArrayDim::from_shape(&[64,64]));
+        assert_eq!(crate::dim![2; 3], ArrayDim::from_shape(&[2,2,2]));
+        assert_eq!(crate::dim![], ArrayDim::new());
+    }
+
 }
 
 /// Dimension definitions from BART. This encodes a 'meaning' for each array axis
@@ -289,16 +735,177 @@ impl From<&DimSize> for DimLabel {
 }
 
 
-#[derive(Clone,Copy,Debug, Serialize, Deserialize)]
+/// how values are filled where a region extends past the edges of an array
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq, Serialize, Deserialize)]
+pub enum PadMode {
+    /// a constant fill value
+    #[default]
+    Constant,
+    /// the nearest value on the edge of the array
+    Edge,
+    /// values from the opposite edge, as for a periodic array
+    Wrap,
+}
+
+/// shapes that can't be described by an ArrayDim
+#[cfg(feature = "alloc")]
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum ShapeError {
+    /// an axis has a length of 0
+    ZeroLength{axis: usize, shape: Vec<usize>},
+    /// a non-singleton axis lies past the 16 supported axes
+    TooManyDims{shape: Vec<usize>},
+}
+
+#[cfg(feature = "alloc")]
+impl Display for ShapeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ShapeError::ZeroLength {axis, shape} => InvalidShape(ShapeFault::ZeroLength(*axis), shape).fmt(f),
+            ShapeError::TooManyDims {shape} => InvalidShape(ShapeFault::TooManyDims, shape).fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::error::Error for ShapeError {}
+
+/// the ShapeError kinds without the shape, so shapes can be checked without allocating
+#[derive(Clone,Copy,Debug)]
+enum ShapeFault {
+    ZeroLength(usize),
+    TooManyDims,
+}
+
+/// a shape fault with the shape it was found in, for messages
+struct InvalidShape<S>(ShapeFault, S);
+
+impl<S:AsRef<[usize]>> Display for InvalidShape<S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let shape = self.1.as_ref();
+        match self.0 {
+            ShapeFault::ZeroLength(axis) => write!(f, "axis {} of shape {:?} has a length of 0", axis, shape),
+            ShapeFault::TooManyDims => write!(
+                f, "shape {:?} has {} dimensions but at most {} are supported", shape, shape.len(), N_DIMS
+            ),
+        }
+    }
+}
+
+/// arrays that can't be allocated
+#[cfg(feature = "alloc")]
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum AllocError {
+    /// the array holds more bytes than can be addressed
+    TooLarge{shape: Vec<usize>, elem_size: usize},
+    /// the allocator couldn't provide the memory
+    OutOfMemory{bytes: usize},
+}
+
+#[cfg(feature = "alloc")]
+impl Display for AllocError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            AllocError::TooLarge {shape, elem_size} => write!(
+                f, "an array of shape {:?} with {} byte elements is too large to allocate", shape, elem_size
+            ),
+            AllocError::OutOfMemory {bytes} => write!(f, "failed to allocate {} bytes", bytes),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::error::Error for AllocError {}
+
+/// something a reader did to the data that the caller may want to know about
+#[cfg(feature = "std")]
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum ReadEvent {
+    /// complex data was read as real values, dropping the imaginary parts
+    DiscardedImaginary{path: PathBuf, dtype: &'static str},
+    /// the file held fewer samples than its dimensions require, and the rest were zero-filled
+    ZeroFilled{path: PathBuf, found: usize, expected: usize},
+    /// the decoded data didn't match the dimensions and was resized to fit
+    Resized{path: PathBuf, found: usize, expected: usize},
+}
+
+#[cfg(feature = "std")]
+impl Display for ReadEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadEvent::DiscardedImaginary {path, dtype} => write!(
+                f, "reading only real component from {}: {}", dtype, path.display()
+            ),
+            ReadEvent::ZeroFilled {path, found, expected} => write!(
+                f, "{} has {} of {} samples. Zero-padding the missing samples", path.display(), found, expected
+            ),
+            ReadEvent::Resized {path, found, expected} => write!(
+                f, "{} decoded to {} samples but its dimensions require {}. Resizing the data", path.display(), found, expected
+            ),
+        }
+    }
+}
+
+/// what happened during a read, returned by the readers that take options so callers can check
+/// for lossy reads. Each event is also logged as a warning
+#[cfg(feature = "std")]
+#[derive(Clone,Debug,Default,PartialEq,Eq)]
+pub struct ReadReport {
+    pub events: Vec<ReadEvent>,
+}
+
+#[cfg(feature = "std")]
+impl ReadReport {
+
+    /// true if the data was read without any of the events
+    pub fn is_clean(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// logs an event as a warning and records it
+    pub(crate) fn warn(&mut self, event:ReadEvent) {
+        log_event!(warn, "{}", event);
+        self.events.push(event);
+    }
+
+}
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq, Serialize, Deserialize)]
+#[serde(try_from = "ArrayDimRepr", into = "ArrayDimRepr")]
 pub struct ArrayDim {
     shape: [usize; N_DIMS],
     strides: [usize; N_DIMS],
+    /// the number of axes up to and including the last non-singleton one (at least 1). Index
+    /// calculations stop here since every axis after it has a single element
+    rank: usize,
+}
+
+/// the serialized form of ArrayDim. The rank is derived from the shape, so it is left out to keep
+/// the format unchanged
+#[derive(Serialize, Deserialize)]
+struct ArrayDimRepr {
+    shape: [usize; N_DIMS],
+    strides: [usize; N_DIMS],
+}
+
+impl TryFrom<ArrayDimRepr> for ArrayDim {
+    type Error = InvalidShape<[usize; N_DIMS]>;
+    fn try_from(repr: ArrayDimRepr) -> Result<Self, Self::Error> {
+        ArrayDim::check_shape(&repr.shape).map_err(|fault| InvalidShape(fault, repr.shape))
+    }
+}
+
+impl From<ArrayDim> for ArrayDimRepr {
+    fn from(dims: ArrayDim) -> Self {
+        ArrayDimRepr { shape: dims.shape, strides: dims.strides }
+    }
 }
 
 impl Display for ArrayDim {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let shape = self.shape_squeeze();
-        writeln!(f, "{:?}", shape)
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        // the squeezed shape, listed without collecting it
+        f.debug_list().entries(self.shape.iter().filter(|&&dim| dim != 1)).finish()?;
+        writeln!(f)
     }
 }
 
@@ -308,6 +915,7 @@ impl ArrayDim {
         ArrayDim{
             shape: [1;N_DIMS],
             strides: [1;N_DIMS],
+            rank: 1,
         }
     }
 
@@ -347,7 +955,46 @@ impl ArrayDim {
         self.strides[axis]
     }
 
+    /// constructs an array from its shape, where axes past the end of the shape are singleton.
+    /// Panics if the shape isn't supported, as described by try_from_shape
     pub fn from_shape(shape: &[usize]) -> ArrayDim {
+        Self::check_shape(shape).unwrap_or_else(|fault| panic!("{}", InvalidShape(fault, shape)))
+    }
+
+    /// constructs an array from anything shape-like, such as a slice, array, vector, tuple or
+    /// ShapeIter. Panics if the shape isn't supported, as described by try_from_shape
+    #[cfg(feature = "alloc")]
+    pub fn of(shape: impl IntoShape) -> ArrayDim {
+        Self::from_shape(&shape.into_shape())
+    }
+
+    /// constructs an array from anything shape-like as of does, returning an error if the shape
+    /// isn't supported
+    #[cfg(feature = "alloc")]
+    pub fn try_of(shape: impl IntoShape) -> Result<ArrayDim, ShapeError> {
+        Self::try_from_shape(&shape.into_shape())
+    }
+
+    /// constructs an array from its shape as from_shape does, returning an error if an axis has a
+    /// length of 0 or if there are non-singleton axes past the 16 supported. Empty arrays aren't
+    /// supported, so every array holds at least one element
+    #[cfg(feature = "alloc")]
+    pub fn try_from_shape(shape: &[usize]) -> Result<ArrayDim, ShapeError> {
+        Self::check_shape(shape).map_err(|fault| match fault {
+            ShapeFault::ZeroLength(axis) => ShapeError::ZeroLength{axis, shape: shape.to_vec()},
+            ShapeFault::TooManyDims => ShapeError::TooManyDims{shape: shape.to_vec()},
+        })
+    }
+
+    /// constructs an array from its shape, or returns what is wrong with the shape
+    fn check_shape(shape: &[usize]) -> Result<ArrayDim, ShapeFault> {
+        if let Some(axis) = shape.iter().position(|&d| d == 0) {
+            return Err(ShapeFault::ZeroLength(axis));
+        }
+        // trailing singleton axes past the limit don't change the layout
+        if shape.len() > N_DIMS && shape[N_DIMS..].iter().any(|&d| d != 1) {
+            return Err(ShapeFault::TooManyDims);
+        }
 
         let mut dims = [1;N_DIMS];
         let mut strides = [1;N_DIMS];
@@ -356,15 +1003,17 @@ impl ArrayDim {
             *d = *s;
         }
 
-        Self::calc_strides(shape, &mut strides);
-        Self {
+        Self::calc_strides(&dims, &mut strides);
+        Ok(Self {
             shape: dims,
             strides,
-        }
+            rank: Self::calc_rank(&dims),
+        })
 
     }
 
     /// finds the index of the largest element based on the squared norm
+    #[cfg(feature = "std")]
     pub fn argmax_cf32(&self, x:&[Complex32]) -> Option<[usize;N_DIMS]> {
         x.par_iter().enumerate()
             .map(|(i, v)| (i, v.norm_sqr()))
@@ -374,6 +1023,7 @@ impl ArrayDim {
     }
 
     /// finds the index of the smallest element based on the squared norm
+    #[cfg(feature = "std")]
     pub fn argmin_cf32(&self, x:&[Complex32]) -> Option<[usize;N_DIMS]> {
         x.par_iter().enumerate()
             .map(|(i, v)| (i, v.norm_sqr()))
@@ -383,6 +1033,7 @@ impl ArrayDim {
     }
 
     /// finds the index of the largest value
+    #[cfg(feature = "std")]
     pub fn argmax_f32(&self,x:&[f32]) -> Option<[usize;N_DIMS]> {
         x.par_iter().enumerate()
             .reduce_with(|a, b| if a.1 >= b.1 { a } else { b })
@@ -391,6 +1042,7 @@ impl ArrayDim {
     }
 
     /// finds this index of the smallest value
+    #[cfg(feature = "std")]
     pub fn argmin_f32(&self,x:&[f32]) -> Option<[usize;N_DIMS]> {
         x.par_iter().enumerate()
             .reduce_with(|a, b| if a.1 < b.1 { a } else { b })
@@ -399,6 +1051,7 @@ impl ArrayDim {
     }
 
     /// returns the element index with the lowest energy in the array
+    #[cfg(feature = "std")]
     pub fn argmin_norm_sqr<T>(
         &self,
         x: &[T],
@@ -414,6 +1067,7 @@ impl ArrayDim {
     }
 
     /// returns the element index with the maximum energy in the array
+    #[cfg(feature = "std")]
     pub fn argmax_norm_sqr<T>(
         &self,
         x: &[T],
@@ -429,6 +1083,7 @@ impl ArrayDim {
     }
 
     /// returns the index of the smallest element in the array
+    #[cfg(feature = "std")]
     pub fn argmin<T>(
         &self,
         x: &[T],
@@ -442,6 +1097,7 @@ impl ArrayDim {
     }
 
     /// returns the index of the largest element in the array
+    #[cfg(feature = "std")]
     pub fn argmax<T>(
         &self,
         x: &[T],
@@ -458,12 +1114,10 @@ impl ArrayDim {
     pub fn circshift<T:Sized + Copy + Send + Sync>(&self,shift:&[isize],src:&[T],dst: &mut [T]) {
         assert_eq!(src.len(), self.numel(), "src must be the same size as array");
         assert_eq!(dst.len(), self.numel(), "dst must be the same size as array");
-        let shape = self.shape();
-        dst.par_iter_mut().enumerate().for_each(|(addr,x)|{
-            // get index of destination
-            let mut idx = self.calc_idx_signed(addr);
+        for_each_indexed_mut(dst, self, |idx, x| {
             // perform inverse shift to calculate source index (can be negative or too large)
-            idx.iter_mut().zip(shift.iter().zip(shape.iter())).for_each(|(i,(s,d))|{
+            let mut idx = idx.map(|i| i as isize);
+            idx.iter_mut().zip(shift.iter()).for_each(|(i,s)|{
                 *i -= *s;
             });
             // calculate source address and read into dest
@@ -472,6 +1126,15 @@ impl ArrayDim {
         });
     }
 
+    /// performs an fft shift as fftshift does into a buffer from the workspace, which can be given
+    /// back once it is no longer needed
+    #[cfg(feature = "std")]
+    pub fn fftshift_with_workspace<T:Copy + Default + Send + Sync + 'static>(&self, src:&[T], forward:bool, workspace:&mut Workspace) -> Vec<T> {
+        let mut dst = workspace.take(self.numel());
+        self.fftshift(src, &mut dst, forward);
+        dst
+    }
+
     /// performs an fft shift on an n-d array. The forward flag specifies the forward shift, shifting
     /// the DC sample to the center of the array. If forward is false, the center DC sample is
     /// shifted to the front of the array
@@ -479,25 +1142,26 @@ impl ArrayDim {
         assert_eq!(src.len(), self.numel(), "src must be the same size as array");
         assert_eq!(dst.len(), self.numel(), "dst must be the same size as array");
 
-        if forward {
-            dst.par_iter_mut().enumerate().for_each(|(dst_addr,x)|{
-                let dst_idx = self.calc_idx(dst_addr);
-                let mut src_idx = [0;N_DIMS];
-                // inverse shift because we need to find where the source was
+        for_each_indexed_mut(dst, self, |dst_idx, x| {
+            let mut src_idx = [0;N_DIMS];
+            // the opposite shift finds where the source was
+            if forward {
                 self.ifft_shift_coords(&dst_idx, &mut src_idx);
-                let src_addr = self.calc_addr(&src_idx);
-                *x = src[src_addr];
-            });
-        }else {
-            dst.par_iter_mut().enumerate().for_each(|(dst_addr,x)|{
-                let dst_idx = self.calc_idx(dst_addr);
-                let mut src_idx = [0;N_DIMS];
-                // forward shift because we need to find where the source was
+            } else {
                 self.fft_shift_coords(&dst_idx, &mut src_idx);
-                let src_addr = self.calc_addr(&src_idx);
-                *x = src[src_addr];
-            });
-        }
+            }
+            let src_addr = self.calc_addr(&src_idx);
+            *x = src[src_addr];
+        });
+    }
+
+    /// permutes the axes as permute does into a buffer from the workspace, which can be given back
+    /// once it is no longer needed. Returns the buffer and the permuted dimensions
+    #[cfg(feature = "std")]
+    pub fn permute_with_workspace<T:Copy + Default + Send + Sync + 'static>(&self, src:&[T], order:&[usize], workspace:&mut Workspace) -> (Vec<T>, ArrayDim) {
+        let mut dst = workspace.take(self.numel());
+        let dims = self.permute(src, &mut dst, order);
+        (dst, dims)
     }
 
     /// Permute axes of an array, similar to MATLAB `permute`.
@@ -508,18 +1172,32 @@ impl ArrayDim {
     /// original shape [x, y, z]
     /// order = [1, 2, 0]
     /// result shape   [y, z, x]
+    ///
+    /// Arrays of at least PERMUTE_BLOCKED_MIN elements are copied with permute_blocked, and
+    /// smaller arrays with permute_naive
+    #[cfg(feature = "std")]
     pub fn permute<T:Copy + Sized + Send + Sync>(
         &self,
         src: &[T],
         dst: &mut [T],
         order: &[usize],
     ) -> ArrayDim {
+        if self.numel() >= PERMUTE_BLOCKED_MIN {
+            self.permute_blocked(src, dst, order)
+        } else {
+            self.permute_naive(src, dst, order)
+        }
+    }
+
+    /// validates a permutation of the axes, returning the permuted dimensions
+    #[cfg(feature = "std")]
+    fn permuted_dims(&self, src_len:usize, dst_len:usize, order:&[usize]) -> ArrayDim {
         let old_shape = self.shape_ns();
         let ndim = old_shape.len();
 
         assert_eq!(order.len(), ndim, "order length must match number of dimensions");
-        assert_eq!(src.len(), self.numel(), "src length must match dims.numel()");
-        assert_eq!(dst.len(), self.numel(), "dst length must match dims.numel()");
+        assert_eq!(src_len, self.numel(), "src length must match dims.numel()");
+        assert_eq!(dst_len, self.numel(), "dst length must match dims.numel()");
 
         // Validate that `order` is a true permutation of 0..ndim
         let mut seen = vec![false; ndim];
@@ -531,7 +1209,20 @@ impl ArrayDim {
 
         // Build new shape: new_shape[new_axis] = old_shape[old_axis]
         let new_shape: Vec<usize> = order.iter().map(|&old_axis| old_shape[old_axis]).collect();
-        let new_dims = ArrayDim::from_shape(&new_shape);
+        ArrayDim::from_shape(&new_shape)
+    }
+
+    /// permute with the source address calculated for every element. This is the reference
+    /// implementation for permute_blocked
+    #[cfg(feature = "std")]
+    pub fn permute_naive<T:Copy + Sized + Send + Sync>(
+        &self,
+        src: &[T],
+        dst: &mut [T],
+        order: &[usize],
+    ) -> ArrayDim {
+        let new_dims = self.permuted_dims(src.len(), dst.len(), order);
+        let ndim = order.len();
 
         dst.par_iter_mut().enumerate().for_each(|(dst_linear, out)| {
             // Multi-index in permuted array
@@ -550,7 +1241,199 @@ impl ArrayDim {
 
         new_dims
     }
+
+    /// permute with cache-blocked copies, run in parallel over slabs of the output. When the
+    /// first axis stays first, whole rows are copied. Otherwise the plane of the first output
+    /// axis and the output axis holding the first source axis is transposed in square tiles, so
+    /// both reads and writes stay within a few cache lines
+    #[cfg(feature = "std")]
+    pub fn permute_blocked<T:Copy + Sized + Send + Sync>(
+        &self,
+        src: &[T],
+        dst: &mut [T],
+        order: &[usize],
+    ) -> ArrayDim {
+        let new_dims = self.permuted_dims(src.len(), dst.len(), order);
+        let src_strides = self.strides();
+        let new_shape = new_dims.shape();
+        let indexer = new_dims.indexer();
+        // the source address of the start of a block of the output
+        let src_base = |dst_addr:usize| -> usize {
+            let idx = indexer.calc_idx(dst_addr);
+            order.iter().zip(idx).map(|(&o, i)| i * src_strides[o]).sum()
+        };
+
+        // the output axis holding the first source axis
+        let b = order.iter().position(|&o| o == 0).expect("order is a permutation");
+        if b == 0 {
+            let run = new_shape[0];
+            dst.par_chunks_exact_mut(run).enumerate().for_each(|(i, out)| {
+                let s = src_base(i * run);
+                out.copy_from_slice(&src[s..s + run]);
+            });
+            return new_dims;
+        }
+
+        // each slab spans output axes 0 through b. The output axes between 0 and b are looped
+        // over within a slab, with a tiled transpose for each
+        let (n0, nb) = (new_shape[0], new_shape[b]);
+        let src_stride_0 = src_strides[order[0]];
+        let dst_stride_b = new_dims.strides()[b];
+        let slab = dst_stride_b * nb;
+        dst.par_chunks_exact_mut(slab).enumerate().for_each(|(s, out)| {
+            let base = src_base(s * slab);
+            for m in 0..dst_stride_b / n0 {
+                let mut src_m = base;
+                let mut r = m;
+                for (&size, &o) in new_shape[1..b].iter().zip(&order[1..b]) {
+                    src_m += (r % size) * src_strides[o];
+                    r /= size;
+                }
+                let dst_m = m * n0;
+                for j0 in (0..nb).step_by(PERMUTE_TILE) {
+                    for i0 in (0..n0).step_by(PERMUTE_TILE) {
+                        let i1 = (i0 + PERMUTE_TILE).min(n0);
+                        for j in j0..(j0 + PERMUTE_TILE).min(nb) {
+                            let d = dst_m + j * dst_stride_b;
+                            let col = src[src_m + j + i0 * src_stride_0..].iter().step_by(src_stride_0);
+                            out[d + i0..d + i1].iter_mut().zip(col).for_each(|(o, x)| *o = *x);
+                        }
+                    }
+                }
+            }
+        });
+
+        new_dims
+    }
     
+    /// checks that a hyper-rectangular region given by an offset and size lies within the array,
+    /// returning the dimensions of the region. Axes not covered by offset and size default to an
+    /// offset of 0 and a size of 1
+    #[cfg(feature = "alloc")]
+    pub fn region_dims(&self, offset:&[usize], size:&[usize]) -> Result<ArrayDim, String> {
+        if offset.len() > N_DIMS || size.len() > N_DIMS {
+            return Err(format!("regions of up to {} dimensions are supported", N_DIMS));
+        }
+        let mut region = ArrayDim::new();
+        for ax in 0..N_DIMS {
+            let o = offset.get(ax).copied().unwrap_or(0);
+            let s = size.get(ax).copied().unwrap_or(1);
+            if s == 0 {
+                return Err(format!("region size along axis {} must be non-zero", ax));
+            }
+            if o + s > self.shape[ax] {
+                return Err(format!(
+                    "region [{}..{}) along axis {} exceeds the axis size of {}", o, o + s, ax, self.shape[ax]
+                ));
+            }
+            region = region.with_dim(ax, s);
+        }
+        Ok(region)
+    }
+
+    /// returns the starting address and length of each contiguous run (along axis 0) of a
+    /// region of the array. Runs are returned in order of increasing address. The region is
+    /// assumed to be valid (see region_dims)
+    pub fn region_runs(&self, offset:&[usize], size:&[usize]) -> impl Iterator<Item=(usize, usize)> + use<> {
+        let mut outer = [1usize; N_DIMS];
+        for (o, s) in outer.iter_mut().zip(size.iter()).skip(1) {
+            *o = *s;
+        }
+        let outer = ArrayDim::from_shape(&outer).indexer();
+        let mut off = [0usize; N_DIMS];
+        off[..offset.len()].copy_from_slice(offset);
+        let run_len = size.first().copied().unwrap_or(1);
+        let dims = *self;
+        let n_runs = size.iter().skip(1).product::<usize>();
+        (0..n_runs).map(move |i| {
+            let mut idx = outer.calc_idx(i);
+            idx.iter_mut().zip(off.iter()).for_each(|(i, o)| *i += *o);
+            (dims.calc_addr(&idx), run_len)
+        })
+    }
+
+    /// copies a hyper-rectangular region out of an array, returning the region data and its
+    /// dimensions
+    #[cfg(feature = "alloc")]
+    pub fn copy_region<T:Copy>(&self, src:&[T], offset:&[usize], size:&[usize]) -> (Vec<T>, ArrayDim) {
+        assert_eq!(src.len(), self.numel(), "src must be the same size as array");
+        let region = self.region_dims(offset, size).unwrap_or_else(|e| panic!("invalid region: {}", e));
+        let mut dst = Vec::with_capacity(region.numel());
+        for (addr, len) in self.region_runs(offset, size) {
+            dst.extend_from_slice(&src[addr..addr + len]);
+        }
+        (dst, region)
+    }
+
+    /// returns the offset of a region of the given size centered on the array, where the center
+    /// of an axis is the sample at index d / 2. The offset is negative along axes where the region
+    /// is larger than the array. Axes not covered by size keep their full extent
+    pub fn centered_offset(&self, size:&[usize]) -> [isize; N_DIMS] {
+        let mut offset = [0isize; N_DIMS];
+        for ((o, &s), &d) in offset.iter_mut().zip(size).zip(self.shape.iter()) {
+            *o = (d / 2) as isize - (s / 2) as isize;
+        }
+        offset
+    }
+
+    /// copies a region of any size at a signed offset, cropping where the region lies inside the
+    /// array and padding where it extends past the edges. Padded values are filled according to
+    /// the pad mode, with fill used for constant padding. Axes not covered by offset and size keep
+    /// their full extent
+    #[cfg(feature = "alloc")]
+    pub fn crop_pad<T:Copy + Send + Sync>(&self, src:&[T], offset:&[isize], size:&[usize], mode:PadMode, fill:T) -> (Vec<T>, ArrayDim) {
+        let region = self.crop_pad_dims(size);
+        let mut dst = vec![fill; region.numel()];
+        self.crop_pad_into(src, offset, size, mode, fill, &mut dst);
+        (dst, region)
+    }
+
+    /// copies a region as crop_pad does into a buffer from the workspace, which can be given back
+    /// once it is no longer needed
+    #[cfg(feature = "std")]
+    pub fn crop_pad_with_workspace<T:Copy + Default + Send + Sync + 'static>(&self, src:&[T], offset:&[isize], size:&[usize], mode:PadMode, fill:T, workspace:&mut Workspace) -> (Vec<T>, ArrayDim) {
+        let region = self.crop_pad_dims(size);
+        let mut dst = workspace.take(region.numel());
+        self.crop_pad_into(src, offset, size, mode, fill, &mut dst);
+        (dst, region)
+    }
+
+    /// copies a region as crop_pad does into dst, which must be the size of the region. Returns
+    /// the dimensions of the region
+    pub fn crop_pad_into<T:Copy + Send + Sync>(&self, src:&[T], offset:&[isize], size:&[usize], mode:PadMode, fill:T, dst:&mut [T]) -> ArrayDim {
+        assert_eq!(src.len(), self.numel(), "src must be the same size as array");
+        assert!(offset.len() <= N_DIMS, "regions of up to {} dimensions are supported", N_DIMS);
+        let region = self.crop_pad_dims(size);
+        assert_eq!(dst.len(), region.numel(), "dst must be the same size as the region");
+        let mut off = [0isize; N_DIMS];
+        off[..offset.len()].copy_from_slice(offset);
+
+        for_each_indexed_mut(dst, &region, |idx, x| {
+            let mut idx = idx.map(|i| i as isize);
+            idx.iter_mut().zip(off.iter()).for_each(|(i, o)| *i += *o);
+            let inside = idx.iter().zip(self.shape.iter()).all(|(&i, &d)| i >= 0 && i < d as isize);
+            match mode {
+                _ if inside => *x = src[self.calc_addr_signed(&idx)],
+                PadMode::Constant => *x = fill,
+                PadMode::Edge => {
+                    idx.iter_mut().zip(self.shape.iter()).for_each(|(i, &d)| *i = (*i).clamp(0, d as isize - 1));
+                    *x = src[self.calc_addr_signed(&idx)];
+                }
+                PadMode::Wrap => *x = src[self.calc_addr_signed(&idx)],
+            }
+        });
+        region
+    }
+
+    /// the dimensions of a crop_pad region of the given size
+    fn crop_pad_dims(&self, size:&[usize]) -> ArrayDim {
+        assert!(size.len() <= N_DIMS, "regions of up to {} dimensions are supported", N_DIMS);
+        let mut shape = self.shape;
+        shape[..size.len()].copy_from_slice(size);
+        assert!(shape.iter().all(|&d| d > 0), "region sizes must be non-zero");
+        ArrayDim::from_shape(&shape)
+    }
+
     /// return the shape with all singleton dimensions intact
     pub fn shape(&self) -> &[usize; N_DIMS] {
         &self.shape
@@ -558,16 +1441,18 @@ impl ArrayDim {
 
     /// return the shape with trailing singleton dimensions removed
     pub fn shape_ns(&self) -> &[usize] {
-        if let Some(i) = self.shape.iter().rev().position(|&dim| dim != 1) {
-            let new_len = self.shape.len() - i;
-            &self.shape[..new_len]
-        } else {
-            // All dims are 1, return scalar shape (or empty, up to convention)
-            &[1]
-        }
+        // all dims are 1 for a rank of 1 with a singleton first axis, giving a scalar shape of [1]
+        &self.shape[..self.rank]
+    }
+
+    /// the number of axes up to and including the last non-singleton one, or 1 if all axes are
+    /// singleton. This is the length of shape_ns
+    pub fn ndim(&self) -> usize {
+        self.rank
     }
 
     /// returns the shape of the array with all singleton dimensions removed
+    #[cfg(feature = "alloc")]
     pub fn shape_squeeze(&self) -> Vec<usize> {
         self.shape.iter().filter_map(|dim| if *dim != 1 { Some(*dim) } else { None }).collect()
     }
@@ -578,33 +1463,42 @@ impl ArrayDim {
     }
 
     pub fn numel(&self) -> usize {
-        self.shape.iter().product()
+        self.shape[..self.rank].iter().product()
     }
 
     pub fn with_dim(mut self,axis:usize,dim:usize) -> ArrayDim {
         assert!(axis < N_DIMS,"only axes of up to 16 are supported");
+        assert!(dim > 0,"axis {} has a length of 0, empty arrays are not supported",axis);
         self.shape[axis] = dim;
         self.update_strides();
         self
     }
 
     fn calc_strides(dims:&[usize],strides:&mut [usize]) {
-        let mut stride = 1;
+        let mut stride:usize = 1;
         for (dim,s) in dims.iter().zip(strides.iter_mut()) {
             *s = stride;
-            stride *= dim;
+            // shapes too large to address saturate rather than overflow, so they can be described
+            // and rejected when allocated
+            stride = stride.saturating_mul(*dim);
         }
     }
 
+    fn calc_rank(shape:&[usize; N_DIMS]) -> usize {
+        shape.iter().rposition(|&dim| dim != 1).map(|i| i + 1).unwrap_or(1)
+    }
+
     fn update_strides(&mut self) {
         Self::calc_strides(&self.shape,&mut self.strides);
+        self.rank = Self::calc_rank(&self.shape);
     }
 
     #[inline]
     /// calculate the element address from the index (subscripts)
     pub fn calc_addr(&self,idx: &[usize]) -> usize {
+        // subscripts past the rank index singleton axes, so they are 0 for valid indices
         let mut offset = 0;
-        for (i,stride) in idx.iter().zip(self.strides.iter()) {
+        for (i,stride) in idx.iter().zip(self.strides[..self.rank].iter()) {
             offset += i * stride;
         }
         offset
@@ -615,7 +1509,7 @@ impl ArrayDim {
     /// larger than the axis dimension
     pub fn calc_addr_signed(&self, idx: &[isize]) -> usize {
         let mut offset = 0;
-        let shape = self.shape();
+        let shape = &self.shape[..self.rank];
         for (i,(stride,dim)) in idx.iter().zip(self.strides.iter().zip(shape.iter())) {
             let i = i.rem_euclid(*dim as isize) as usize;
             offset += i * stride;
@@ -627,24 +1521,92 @@ impl ArrayDim {
     /// calculate the element index (subscript) from the address
     pub fn calc_idx(&self,addr:usize) -> [usize;16] {
         let mut addr = addr;
-        let total: usize = self.shape.iter().product();
+        let total = self.numel();
         debug_assert!(addr < total, "offset {} exceeds total number of elements {}", addr, total);
         let mut idx = [0usize; N_DIMS];
-        for k in 0..N_DIMS {
+        for k in 0..self.rank {
             idx[k] = addr % self.shape[k];
             addr /= self.shape[k];
         }
         idx
     }
 
+    /// calculates the addresses of a list of 3D coordinates given as separate lists of i, j and k
+    /// subscripts, one coordinate at a time. This is the reference for calc_addrs_simd
+    pub fn calc_addrs(&self, is:&[u32], js:&[u32], ks:&[u32], out:&mut [u32]) {
+        self.check_addr_lists(out.len(), &[is.len(), js.len(), ks.len()]);
+        for (((o, &i), &j), &k) in out.iter_mut().zip(is).zip(js).zip(ks) {
+            *o = self.calc_addr(&[i as usize, j as usize, k as usize]) as u32;
+        }
+    }
+
+    /// calculates the addresses of a list of 3D coordinates as calc_addrs does, processing
+    /// ADDR_LANES coordinates at a time in loops the compiler vectorizes. Coordinates are assumed
+    /// to be in range
+    pub fn calc_addrs_simd(&self, is:&[u32], js:&[u32], ks:&[u32], out:&mut [u32]) {
+        self.check_addr_lists(out.len(), &[is.len(), js.len(), ks.len()]);
+        let (s1, s2) = (self.strides[1] as u32, self.strides[2] as u32);
+        let mut out_chunks = out.chunks_exact_mut(ADDR_LANES);
+        let mut i_chunks = is.chunks_exact(ADDR_LANES);
+        let mut j_chunks = js.chunks_exact(ADDR_LANES);
+        let mut k_chunks = ks.chunks_exact(ADDR_LANES);
+        for (((o, i), j), k) in out_chunks.by_ref().zip(i_chunks.by_ref()).zip(j_chunks.by_ref()).zip(k_chunks.by_ref()) {
+            for (((o, &i), &j), &k) in o.iter_mut().zip(i).zip(j).zip(k) {
+                *o = i.wrapping_add(j.wrapping_mul(s1)).wrapping_add(k.wrapping_mul(s2));
+            }
+        }
+        // the remainder one at a time
+        self.calc_addrs(i_chunks.remainder(), j_chunks.remainder(), k_chunks.remainder(), out_chunks.into_remainder());
+    }
+
+    /// calculates the addresses of a list of 4D coordinates, one coordinate at a time. This is the
+    /// reference for calc_addrs4_simd
+    pub fn calc_addrs4(&self, is:&[u32], js:&[u32], ks:&[u32], ls:&[u32], out:&mut [u32]) {
+        self.check_addr_lists(out.len(), &[is.len(), js.len(), ks.len(), ls.len()]);
+        for ((((o, &i), &j), &k), &l) in out.iter_mut().zip(is).zip(js).zip(ks).zip(ls) {
+            *o = self.calc_addr(&[i as usize, j as usize, k as usize, l as usize]) as u32;
+        }
+    }
+
+    /// calculates the addresses of a list of 4D coordinates as calc_addrs4 does, processing
+    /// ADDR_LANES coordinates at a time in loops the compiler vectorizes. Coordinates are assumed
+    /// to be in range
+    pub fn calc_addrs4_simd(&self, is:&[u32], js:&[u32], ks:&[u32], ls:&[u32], out:&mut [u32]) {
+        self.check_addr_lists(out.len(), &[is.len(), js.len(), ks.len(), ls.len()]);
+        let (s1, s2, s3) = (self.strides[1] as u32, self.strides[2] as u32, self.strides[3] as u32);
+        let mut out_chunks = out.chunks_exact_mut(ADDR_LANES);
+        let mut i_chunks = is.chunks_exact(ADDR_LANES);
+        let mut j_chunks = js.chunks_exact(ADDR_LANES);
+        let mut k_chunks = ks.chunks_exact(ADDR_LANES);
+        let mut l_chunks = ls.chunks_exact(ADDR_LANES);
+        let chunks = out_chunks.by_ref().zip(i_chunks.by_ref()).zip(j_chunks.by_ref()).zip(k_chunks.by_ref()).zip(l_chunks.by_ref());
+        for ((((o, i), j), k), l) in chunks {
+            for ((((o, &i), &j), &k), &l) in o.iter_mut().zip(i).zip(j).zip(k).zip(l) {
+                *o = i.wrapping_add(j.wrapping_mul(s1)).wrapping_add(k.wrapping_mul(s2)).wrapping_add(l.wrapping_mul(s3));
+            }
+        }
+        // the remainder one at a time
+        self.calc_addrs4(i_chunks.remainder(), j_chunks.remainder(), k_chunks.remainder(), l_chunks.remainder(), out_chunks.into_remainder());
+    }
+
+    fn check_addr_lists(&self, n:usize, lens:&[usize]) {
+        assert!(lens.iter().all(|&l| l == n), "coordinate lists and output must be the same length");
+        assert!(self.numel() <= u32::MAX as usize + 1, "addresses of the array must fit in u32");
+    }
+
+    /// precomputes the divisors for fast address to subscript conversion
+    pub fn indexer(&self) -> DimIndexer {
+        DimIndexer::new(self)
+    }
+
     #[inline]
     /// calculate the element index (subscript) from the address
     pub fn calc_idx_signed(&self,addr:usize) -> [isize;16] {
         let mut addr = addr as isize;
-        let total: isize = self.shape.iter().product::<usize>() as isize;
+        let total = self.numel() as isize;
         debug_assert!(addr < total, "offset {} exceeds total number of elements {}", addr, total);
         let mut idx = [0isize; N_DIMS];
-        for k in 0..N_DIMS {
+        for k in 0..self.rank {
             idx[k] = addr % self.shape[k] as isize;
             addr /= self.shape[k] as isize;
         }
@@ -652,10 +1614,36 @@ impl ArrayDim {
     }
 
     /// allocates a vector of values the size of dims
+    #[cfg(feature = "alloc")]
     pub fn alloc<T:Sized + Clone>(&self,value:T) -> Vec<T> {
         vec![value;self.numel()]
     }
 
+    /// allocates a vector of values the size of dims as alloc does, returning an error instead of
+    /// aborting if the array is too large to address or the allocator can't provide the memory
+    #[cfg(feature = "alloc")]
+    pub fn try_alloc<T:Clone>(&self,value:T) -> Result<Vec<T>, AllocError> {
+        let mut v = self.try_with_capacity()?;
+        v.resize(self.numel(), value);
+        Ok(v)
+    }
+
+    /// an empty vector with room for every element, or an error if it can't be allocated
+    #[cfg(feature = "alloc")]
+    pub(crate) fn try_with_capacity<T>(&self) -> Result<Vec<T>, AllocError> {
+        let too_large = || AllocError::TooLarge{shape: self.shape_ns().to_vec(), elem_size: size_of::<T>()};
+        let numel = self.checked_numel().ok_or_else(too_large)?;
+        let bytes = numel.checked_mul(size_of::<T>()).filter(|&b| b <= isize::MAX as usize).ok_or_else(too_large)?;
+        let mut v = Vec::new();
+        v.try_reserve_exact(numel).map_err(|_| AllocError::OutOfMemory{bytes})?;
+        Ok(v)
+    }
+
+    /// the number of elements, or None if it overflows a usize
+    pub fn checked_numel(&self) -> Option<usize> {
+        self.shape[..self.rank].iter().try_fold(1usize, |n, &d| n.checked_mul(d))
+    }
+
     #[inline]
     /// perform a forward fft shift of the input coordinates
     pub fn fft_shift_coords(&self,input: &[usize], out: &mut [usize]) {
@@ -695,6 +1683,91 @@ impl ArrayDim {
 
 }
 
+/// unsigned division by a fixed divisor with a multiply and shift, after libdivide. Power of 2
+/// divisors are a shift alone
+#[derive(Clone, Copy, Debug)]
+struct FastDiv {
+    /// 0 for power of 2 divisors
+    magic: u64,
+    shift: u32,
+    /// the magic number needs 65 bits, so the quotient takes an extra add and shift
+    add: bool,
+}
+
+impl FastDiv {
+
+    fn new(d:u64) -> FastDiv {
+        assert!(d > 0, "divisor must be non-zero");
+        let log2 = 63 - d.leading_zeros();
+        if d.is_power_of_two() {
+            return FastDiv { magic: 0, shift: log2, add: false };
+        }
+        let num = 1u128 << (64 + log2);
+        let mut m = (num / d as u128) as u64;
+        let rem = (num % d as u128) as u64;
+        let add = d - rem >= 1u64 << log2;
+        if add {
+            // a 65 bit magic number, with the top bit implied
+            m = m.wrapping_add(m);
+            let twice_rem = rem.wrapping_add(rem);
+            if twice_rem >= d || twice_rem < rem {
+                m = m.wrapping_add(1);
+            }
+        }
+        FastDiv { magic: m.wrapping_add(1), shift: log2, add }
+    }
+
+    #[inline]
+    fn div(&self, n:u64) -> u64 {
+        if self.magic == 0 {
+            return n >> self.shift;
+        }
+        let q = ((self.magic as u128 * n as u128) >> 64) as u64;
+        if self.add {
+            (((n - q) >> 1) + q) >> self.shift
+        } else {
+            q >> self.shift
+        }
+    }
+}
+
+/// converts addresses of an array to subscripts as ArrayDim::calc_idx does, with the divisions
+/// replaced by precomputed multiplies. Build one per array for loops that need the subscripts of
+/// many addresses
+#[derive(Clone, Copy, Debug)]
+pub struct DimIndexer {
+    shape: [usize; N_DIMS],
+    divisors: [FastDiv; N_DIMS],
+    /// the number of axes up to the last non-singleton axis. Later axes always have a
+    /// subscript of 0
+    ndim: usize,
+}
+
+impl DimIndexer {
+
+    pub fn new(dims:&ArrayDim) -> DimIndexer {
+        let divisors = dims.shape.map(|d| FastDiv::new(d as u64));
+        DimIndexer { shape: dims.shape, divisors, ndim: dims.ndim() }
+    }
+
+    #[inline]
+    /// calculate the element index (subscript) from the address
+    pub fn calc_idx(&self, addr:usize) -> [usize; N_DIMS] {
+        debug_assert!(addr < self.shape.iter().product(), "offset {} exceeds total number of elements", addr);
+        let mut idx = [0usize; N_DIMS];
+        let mut addr = addr as u64;
+        // the last axis takes what is left of the address
+        let last = self.ndim - 1;
+        for ((i, div), &d) in idx[..last].iter_mut().zip(&self.divisors).zip(&self.shape) {
+            let q = div.div(addr);
+            *i = (addr - q * d as u64) as usize;
+            addr = q;
+        }
+        idx[last] = addr as usize;
+        idx
+    }
+}
+
 impl From<[usize;16]> for ArrayDim {
     fn from(shape:[usize;N_DIMS]) -> ArrayDim {
         let mut arr_dim = ArrayDim::new();
@@ -705,6 +1778,111 @@ impl From<[usize;16]> for ArrayDim {
     }
 }
 
+/// types that describe the shape of an array, accepted by ArrayDim::of
+#[cfg(feature = "alloc")]
+pub trait IntoShape {
+    fn into_shape(self) -> Vec<usize>;
+}
+
+#[cfg(feature = "alloc")]
+impl IntoShape for &[usize] {
+    fn into_shape(self) -> Vec<usize> {
+        self.to_vec()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<const N:usize> IntoShape for [usize;N] {
+    fn into_shape(self) -> Vec<usize> {
+        self.to_vec()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<const N:usize> IntoShape for &[usize;N] {
+    fn into_shape(self) -> Vec<usize> {
+        self.to_vec()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl IntoShape for Vec<usize> {
+    fn into_shape(self) -> Vec<usize> {
+        self
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl IntoShape for &Vec<usize> {
+    fn into_shape(self) -> Vec<usize> {
+        self.clone()
+    }
+}
+
+/// wraps an iterator of axis lengths so it can be used as a shape, as in
+/// ArrayDim::of(ShapeIter(sizes.iter().map(|s| s / 2)))
+#[derive(Clone,Debug)]
+pub struct ShapeIter<I>(pub I);
+
+#[cfg(feature = "alloc")]
+impl<I:Iterator<Item=usize>> IntoShape for ShapeIter<I> {
+    fn into_shape(self) -> Vec<usize> {
+        self.0.collect()
+    }
+}
+
+macro_rules! impl_tuple_shape {
+    ($($v:ident:$t:ty),+) => {
+        #[cfg(feature = "alloc")]
+        impl IntoShape for ($($t,)+) {
+            fn into_shape(self) -> Vec<usize> {
+                let ($($v,)+) = self;
+                vec![$($v),+]
+            }
+        }
+
+        #[cfg(feature = "alloc")]
+        impl From<($($t,)+)> for ArrayDim {
+            fn from(shape:($($t,)+)) -> ArrayDim {
+                ArrayDim::of(shape)
+            }
+        }
+    };
+}
+
+impl_tuple_shape!(a:usize);
+impl_tuple_shape!(a:usize,b:usize);
+impl_tuple_shape!(a:usize,b:usize,c:usize);
+impl_tuple_shape!(a:usize,b:usize,c:usize,d:usize);
+impl_tuple_shape!(a:usize,b:usize,c:usize,d:usize,e:usize);
+impl_tuple_shape!(a:usize,b:usize,c:usize,d:usize,e:usize,f:usize);
+
+impl From<&[usize]> for ArrayDim {
+    fn from(shape:&[usize]) -> ArrayDim {
+        ArrayDim::from_shape(shape)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<Vec<usize>> for ArrayDim {
+    fn from(shape:Vec<usize>) -> ArrayDim {
+        ArrayDim::from_shape(&shape)
+    }
+}
+
+/// builds an ArrayDim from a list of axis lengths, as in dim![64, 64, 32], or from a length
+/// repeated over a number of axes, as in dim![64; 3]. This expands to ArrayDim::from_shape, so it
+/// panics if the shape isn't supported
+#[macro_export]
+macro_rules! dim {
+    ($len:expr; $n:expr) => {
+        $crate::ArrayDim::from_shape(&[$len; $n])
+    };
+    ($($len:expr),* $(,)?) => {
+        $crate::ArrayDim::from_shape(&[$($len),*])
+    };
+}
+
 pub trait NormSqr {
     type Output: Send + Sync + Copy + PartialOrd;
     fn norm_sqr(&self) -> Self::Output;
@@ -0,0 +1,28 @@
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// writes log records to stderr as "LEVEL: message", keeping stdout free for data
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}: {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// sends log records at or above the given level to stderr. This is meant for the binaries, and
+/// does nothing if a logger is already installed
+pub fn init_stderr(level: LevelFilter) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
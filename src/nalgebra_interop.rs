@@ -0,0 +1,90 @@
+use nalgebra::{DMatrix, DMatrixView, DMatrixViewMut, Scalar};
+use crate::{ArrayDim, ShapeError};
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{DMatrix, DVector};
+    use crate::ArrayDim;
+    use crate::nalgebra_interop::{as_dmatrix_view, as_dmatrix_view_mut, batch_dmatrix_iter, from_dmatrix};
+
+    #[test]
+    fn test_matrix_round_trip() {
+        let dims = ArrayDim::from_shape(&[4,3]);
+        let mut x:Vec<f64> = (0..dims.numel()).map(|i| i as f64).collect();
+        let m = as_dmatrix_view(&x, &dims);
+        assert_eq!(m.as_ptr(), x.as_ptr());
+        assert_eq!(m.shape(), (4,3));
+        assert_eq!(m[(1,2)], x[dims.calc_addr(&[1,2])]);
+
+        let (data, read_dims) = from_dmatrix(m.clone_owned()).unwrap();
+        assert_eq!(data, x);
+        assert_eq!(read_dims, dims);
+
+        as_dmatrix_view_mut(&mut x, &dims)[(3,1)] = -1.;
+        assert_eq!(x[dims.calc_addr(&[3,1])], -1.);
+
+        // column vectors are 1 column matrices
+        let (data, dims) = from_dmatrix(DMatrix::from_column_slice(5, 1, &[1.,2.,3.,4.,5.])).unwrap();
+        assert_eq!(dims.shape_ns(), &[5]);
+        assert_eq!(as_dmatrix_view(&data, &dims).shape(), (5,1));
+    }
+
+    #[test]
+    fn test_batch_solve() {
+        // a batch of 2 x 3 diagonally dominant 3 x 3 systems
+        let dims = ArrayDim::from_shape(&[3,3,2,3]);
+        let x:Vec<f64> = (0..dims.numel()).map(|i| {
+            let idx = dims.calc_idx(i);
+            if idx[0] == idx[1] { 10. + (idx[2] + idx[3]) as f64 } else { ((i % 5) as f64) - 2. }
+        }).collect();
+
+        let views:Vec<_> = batch_dmatrix_iter(&x, &dims).collect();
+        assert_eq!(views.len(), 6);
+        for (k, v) in views.iter().enumerate() {
+            assert_eq!(v.as_ptr(), x[k * 9..].as_ptr());
+        }
+
+        let a = views[4];
+        let expected = DVector::from_column_slice(&[1.,-2.,0.5]);
+        let b = a * &expected;
+        let solved = a.clone_owned().lu().solve(&b).unwrap();
+        assert!((solved - expected).norm() < 1e-10);
+    }
+}
+
+/// a view of a 2D column-major array as an nalgebra matrix, without copying. Axis 0 indexes rows
+/// and axis 1 indexes columns, so 1D arrays are single column matrices. Panics if the array has
+/// non-singleton axes past the first two
+pub fn as_dmatrix_view<'a, T: Scalar>(data: &'a [T], dims: &ArrayDim) -> DMatrixView<'a, T> {
+    let (rows, cols) = matrix_shape(data.len(), dims);
+    DMatrixView::from_slice(data, rows, cols)
+}
+
+/// a mutable view of a 2D column-major array as an nalgebra matrix, without copying, as with
+/// as_dmatrix_view
+pub fn as_dmatrix_view_mut<'a, T: Scalar>(data: &'a mut [T], dims: &ArrayDim) -> DMatrixViewMut<'a, T> {
+    let (rows, cols) = matrix_shape(data.len(), dims);
+    DMatrixViewMut::from_slice(data, rows, cols)
+}
+
+/// views every matrix of a batched array without copying. Axes 0 and 1 are the rows and columns
+/// of each matrix, and the remaining axes index the batch with axis 2 varying fastest, so the nth
+/// view is the matrix at batch address n
+pub fn batch_dmatrix_iter<'a, T: Scalar>(data: &'a [T], dims: &ArrayDim) -> impl ExactSizeIterator<Item = DMatrixView<'a, T>> {
+    assert_eq!(data.len(), dims.numel(), "data must be the same size as array");
+    let (rows, cols) = (dims.shape()[0], dims.shape()[1]);
+    data.chunks_exact(rows * cols).map(move |m| DMatrixView::from_slice(m, rows, cols))
+}
+
+/// converts an nalgebra matrix to a column-major array and its dims without copying. A matrix
+/// with one column has the dims of a 1D array. Returns an error for matrices with no rows or columns
+pub fn from_dmatrix<T: Scalar>(m: DMatrix<T>) -> Result<(Vec<T>, ArrayDim), ShapeError> {
+    let dims = ArrayDim::try_from_shape(&[m.nrows(), m.ncols()])?;
+    Ok((m.data.into(), dims))
+}
+
+fn matrix_shape(len: usize, dims: &ArrayDim) -> (usize, usize) {
+    assert_eq!(len, dims.numel(), "data must be the same size as array");
+    assert!(dims.shape_ns().len() <= 2, "array with shape {:?} is not a matrix", dims.shape_ns());
+    (dims.shape()[0], dims.shape()[1])
+}
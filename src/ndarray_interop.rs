@@ -0,0 +1,90 @@
+use ndarray::{ArrayD, ArrayViewD, ArrayViewMutD, IxDyn, ShapeBuilder};
+use crate::{ArrayDim, ShapeError};
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{ArrayD, IxDyn, ShapeBuilder};
+    use crate::ArrayDim;
+    use crate::ndarray_interop::{from_ndarray, to_ndarray_view, to_ndarray_view_mut};
+
+    #[test]
+    fn test_view_is_zero_copy() {
+        let dims = ArrayDim::from_shape(&[4,3,2]);
+        let mut x:Vec<f32> = (0..dims.numel()).map(|i| i as f32).collect();
+        let view = to_ndarray_view(&x, &dims);
+        assert_eq!(view.as_ptr(), x.as_ptr());
+        assert_eq!(view.shape(), &[4,3,2]);
+        assert_eq!(view[[1,2,1]], x[dims.calc_addr(&[1,2,1])]);
+
+        let mut view = to_ndarray_view_mut(&mut x, &dims);
+        view[[3,0,1]] = -1.;
+        assert_eq!(x[dims.calc_addr(&[3,0,1])], -1.);
+    }
+
+    #[test]
+    fn test_from_ndarray() {
+        // F-contiguous arrays hand over their buffer
+        let x:Vec<f32> = (0..24).map(|i| i as f32).collect();
+        let arr = ArrayD::from_shape_vec(IxDyn(&[4,3,2]).f(), x.clone()).unwrap();
+        let ptr = arr.as_ptr();
+        let (data, dims) = from_ndarray(arr).unwrap();
+        assert_eq!(data.as_ptr(), ptr);
+        assert_eq!(data, x);
+        assert_eq!(dims.shape_ns(), &[4,3,2]);
+
+        // a permuted C-order array is copied to column-major order
+        let arr = ArrayD::from_shape_vec(IxDyn(&[2,3,4]), x.clone()).unwrap().permuted_axes(IxDyn(&[2,0,1]));
+        let expected = arr.clone();
+        let (data, dims) = from_ndarray(arr).unwrap();
+        assert_eq!(dims.shape_ns(), &[4,2,3]);
+        for (i, v) in data.iter().enumerate() {
+            let idx = dims.calc_idx(i);
+            assert_eq!(*v, expected[&idx[..3]]);
+        }
+
+        // trailing singletons are dropped from views and kept as singleton axes when read
+        let arr = ArrayD::from_shape_vec(IxDyn(&[4,3,1,1]).f(), x[..12].to_vec()).unwrap();
+        let (data, dims) = from_ndarray(arr).unwrap();
+        assert_eq!(dims.shape_ns(), &[4,3]);
+        assert_eq!(to_ndarray_view(&data, &dims).shape(), &[4,3]);
+        assert!(from_ndarray(ArrayD::<f32>::zeros(IxDyn(&[4,0]))).is_err());
+    }
+}
+
+/// a view of a column-major array as an F-order ndarray, without copying. The view has one axis
+/// for each axis up to the last non-singleton one (see ArrayDim::shape_ns), so trailing singleton
+/// axes are dropped and a single element array is a one-dimensional view of length 1
+pub fn to_ndarray_view<'a, T>(data: &'a [T], dims: &ArrayDim) -> ArrayViewD<'a, T> {
+    assert_eq!(data.len(), dims.numel(), "data must be the same size as array");
+    ArrayViewD::from_shape(IxDyn(dims.shape_ns()).f(), data).expect("array dims must describe the data")
+}
+
+/// a mutable view of a column-major array as an F-order ndarray, without copying. Axes are as
+/// described for to_ndarray_view
+pub fn to_ndarray_view_mut<'a, T>(data: &'a mut [T], dims: &ArrayDim) -> ArrayViewMutD<'a, T> {
+    assert_eq!(data.len(), dims.numel(), "data must be the same size as array");
+    ArrayViewMutD::from_shape(IxDyn(dims.shape_ns()).f(), data).expect("array dims must describe the data")
+}
+
+/// converts an ndarray to a column-major array and its dims. F-contiguous arrays give up their
+/// buffer without copying, and any other layout (C-order, permuted or strided) is copied to
+/// column-major order. Axes of the ndarray map to the axes of the dims in order, and trailing
+/// singleton axes, including any past the 16 supported, become singleton axes of the dims. Returns
+/// an error for arrays with an empty axis or more than 16 non-singleton axes
+pub fn from_ndarray<T: Clone>(arr: ArrayD<T>) -> Result<(Vec<T>, ArrayDim), ShapeError> {
+    let dims = ArrayDim::try_from_shape(arr.shape())?;
+    let n = arr.len();
+
+    // reversing the axes of an F-contiguous array gives a C-contiguous one
+    if !arr.t().is_standard_layout() {
+        return Ok((arr.t().iter().cloned().collect(), dims));
+    }
+
+    let (mut data, offset) = arr.into_raw_vec_and_offset();
+    let offset = offset.unwrap_or(0);
+    if offset != 0 || data.len() != n {
+        // the array only covers part of its buffer
+        data = data[offset..offset + n].to_vec();
+    }
+    Ok((data, dims))
+}
@@ -0,0 +1,131 @@
+#[cfg(feature = "std")]
+use rayon::prelude::*;
+use crate::ArrayDim;
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::sync::Mutex;
+    use rayon::prelude::*;
+    use crate::ArrayDim;
+    use crate::par::{axis_chunks, for_each_indexed_mut, par_axis_chunks, par_axis_chunks_mut, par_for_each_indexed, par_for_each_indexed_mut};
+
+    #[test]
+    fn test_axis_chunks() {
+        let dims = ArrayDim::from_shape(&[3,4,5]);
+        let mut x:Vec<usize> = (0..dims.numel()).collect();
+        for axis in 0..4 {
+            let serial:Vec<&[usize]> = axis_chunks(&x,&dims,axis).collect();
+            let parallel:Vec<&[usize]> = par_axis_chunks(&x,&dims,axis).collect();
+            assert_eq!(serial,parallel);
+            assert_eq!(serial.len(),dims.numel() / dims.strides()[axis]);
+        }
+        // each slab along the slowest axis holds one index of that axis
+        par_axis_chunks_mut(&mut x,&dims,2).enumerate().for_each(|(k, slab)| {
+            assert_eq!(slab.len(),12);
+            slab.iter_mut().for_each(|v| *v = k);
+        });
+        assert!(x.iter().enumerate().all(|(i, &k)| dims.calc_idx(i)[2] == k));
+    }
+
+    #[test]
+    fn test_for_each_indexed() {
+        let dims = ArrayDim::from_shape(&[7,5,1,3]);
+        let mut x = dims.alloc([0usize; 16]);
+        par_for_each_indexed_mut(&mut x,&dims,|idx, v| *v = idx);
+        assert!(x.iter().enumerate().all(|(i, idx)| *idx == dims.calc_idx(i)));
+
+        let mut serial = dims.alloc([0usize; 16]);
+        for_each_indexed_mut(&mut serial,&dims,|idx, v| *v = idx);
+        assert_eq!(serial,x);
+
+        let seen = Mutex::new(vec![]);
+        par_for_each_indexed(&x,&dims,|idx, v| {
+            assert_eq!(idx,*v);
+            seen.lock().unwrap().push(dims.calc_addr(&idx));
+        });
+        let mut seen = seen.into_inner().unwrap();
+        seen.sort();
+        assert_eq!(seen,(0..dims.numel()).collect::<Vec<_>>());
+    }
+}
+
+/// the slabs of an array along an axis. Each slab holds the elements sharing the same indices along
+/// the axis and every slower axis, so for the slowest non-singleton axis there is one slab per
+/// index
+pub fn axis_chunks<'a, T>(data:&'a [T], dims:&ArrayDim, axis:usize) -> core::slice::ChunksExact<'a, T> {
+    data.chunks_exact(slab_len(data.len(), dims, axis))
+}
+
+/// the mutable slabs of an array along an axis, as with axis_chunks
+pub fn axis_chunks_mut<'a, T>(data:&'a mut [T], dims:&ArrayDim, axis:usize) -> core::slice::ChunksExactMut<'a, T> {
+    let len = slab_len(data.len(), dims, axis);
+    data.chunks_exact_mut(len)
+}
+
+/// the slabs of an array along an axis as with axis_chunks, as a parallel iterator
+#[cfg(feature = "std")]
+pub fn par_axis_chunks<'a, T:Sync>(data:&'a [T], dims:&ArrayDim, axis:usize) -> rayon::slice::ChunksExact<'a, T> {
+    data.par_chunks_exact(slab_len(data.len(), dims, axis))
+}
+
+/// the mutable slabs of an array along an axis as with axis_chunks, as a parallel iterator
+#[cfg(feature = "std")]
+pub fn par_axis_chunks_mut<'a, T:Send>(data:&'a mut [T], dims:&ArrayDim, axis:usize) -> rayon::slice::ChunksExactMut<'a, T> {
+    let len = slab_len(data.len(), dims, axis);
+    data.par_chunks_exact_mut(len)
+}
+
+/// calls f with the index (subscripts) and value of every element in parallel
+#[cfg(feature = "std")]
+pub fn par_for_each_indexed<T:Sync>(data:&[T], dims:&ArrayDim, f:impl Fn([usize; 16], &T) + Sync + Send) {
+    let run = check_len(data.len(), dims);
+    let indexer = dims.indexer();
+    data.par_chunks_exact(run).enumerate().for_each(|(r, row)| {
+        let mut idx = indexer.calc_idx(r * run);
+        for (i, x) in row.iter().enumerate() {
+            idx[0] = i;
+            f(idx, x);
+        }
+    });
+}
+
+/// calls f with the index (subscripts) and a mutable reference to every element in parallel
+#[cfg(feature = "std")]
+pub fn par_for_each_indexed_mut<T:Send>(data:&mut [T], dims:&ArrayDim, f:impl Fn([usize; 16], &mut T) + Sync + Send) {
+    let run = check_len(data.len(), dims);
+    let indexer = dims.indexer();
+    data.par_chunks_exact_mut(run).enumerate().for_each(|(r, row)| {
+        let mut idx = indexer.calc_idx(r * run);
+        for (i, x) in row.iter_mut().enumerate() {
+            idx[0] = i;
+            f(idx, x);
+        }
+    });
+}
+
+/// calls f with the index (subscripts) and a mutable reference to every element in turn, as
+/// par_for_each_indexed_mut does without threads
+pub fn for_each_indexed_mut<T>(data:&mut [T], dims:&ArrayDim, mut f:impl FnMut([usize; 16], &mut T)) {
+    let run = check_len(data.len(), dims);
+    let indexer = dims.indexer();
+    for (r, row) in data.chunks_exact_mut(run).enumerate() {
+        let mut idx = indexer.calc_idx(r * run);
+        for (i, x) in row.iter_mut().enumerate() {
+            idx[0] = i;
+            f(idx, x);
+        }
+    }
+}
+
+/// checks the data matches the array, returning the length of a row along the first axis. Rows
+/// are handed out whole, so only the first subscript changes within a row
+fn check_len(len:usize, dims:&ArrayDim) -> usize {
+    assert_eq!(len, dims.numel(), "data must be the same size as array");
+    dims.shape()[0]
+}
+
+fn slab_len(len:usize, dims:&ArrayDim, axis:usize) -> usize {
+    assert_eq!(len, dims.numel(), "data must be the same size as array");
+    assert!(axis < dims.shape().len(), "axis {} is out of range", axis);
+    dims.strides()[axis]
+}
@@ -0,0 +1,240 @@
+use std::f64::consts::PI;
+use num_complex::Complex32;
+use crate::{ArrayDim, N_DIMS};
+
+#[cfg(test)]
+mod tests {
+    use crate::dim;
+    use crate::describe::describe;
+    use crate::phantom::{checkerboard, delta, gaussian_blob, linear_ramp, shepp_logan_2d, shepp_logan_3d, with_smooth_phase};
+
+    #[test]
+    fn test_shepp_logan_2d() {
+        let dims = dim![256,256];
+        let x = shepp_logan_2d(&dims);
+        assert_eq!(x.len(), dims.numel());
+        // the skull is the ring between the two outer ellipses, and is the only region of value 1
+        let skull = x.iter().filter(|&&v| v == 1.).count() as f64;
+        let expected = std::f64::consts::PI * (0.69 * 0.92 - 0.6624 * 0.874) / 4. * 65536.;
+        assert!((skull - expected).abs() / expected < 0.01, "{} pixels in the skull, expected {}", skull, expected);
+        // the center is in the skull and brain ellipses only, and (0, 0.35) is in ellipse 5 as well
+        assert!((x[dims.calc_addr(&[128,128])] - 0.2).abs() < 1e-6);
+        assert!((x[dims.calc_addr(&[128,172])] - 0.3).abs() < 1e-6);
+        assert_eq!(x[dims.calc_addr(&[0,0])], 0.);
+
+        // higher axes repeat the slice
+        let dims3 = dim![64,64,3];
+        let y = shepp_logan_2d(&dims3);
+        assert_eq!(&y[..64 * 64], &y[2 * 64 * 64..]);
+    }
+
+    #[test]
+    fn test_shepp_logan_3d() {
+        let dims = dim![64,64,64];
+        let x = shepp_logan_3d(&dims);
+        let skull = x.iter().filter(|&&v| v == 1.).count() as f64;
+        let expected = 4. / 3. * std::f64::consts::PI * (0.69 * 0.92 * 0.81 - 0.6624 * 0.874 * 0.78) / 8. * 64f64.powi(3);
+        assert!((skull - expected).abs() / expected < 0.01, "{} voxels in the skull, expected {}", skull, expected);
+        assert!((x[dims.calc_addr(&[32,32,32])] - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_delta_and_ramp() {
+        let dims = dim![4,5,3];
+        let x = delta(&dims, &[1,4,2]);
+        assert_eq!(x.iter().sum::<f32>(), 1.);
+        assert_eq!(x[dims.calc_addr(&[1,4,2])], 1.);
+
+        let r = linear_ramp(&dims, 1);
+        assert_eq!(r[dims.calc_addr(&[3,0,2])], 0.);
+        assert_eq!(r[dims.calc_addr(&[3,4,2])], 1.);
+        assert_eq!(r[dims.calc_addr(&[0,2,1])], 0.5);
+        // singleton axes give a constant 0
+        assert!(linear_ramp(&dims, 3).iter().all(|&v| v == 0.));
+    }
+
+    #[test]
+    fn test_checkerboard() {
+        let dims = dim![8,6];
+        let x = checkerboard(&dims, 2);
+        assert_eq!(x.iter().sum::<f32>(), 24.);
+        assert_eq!(x[dims.calc_addr(&[0,0])], 1.);
+        assert_eq!(x[dims.calc_addr(&[1,1])], 1.);
+        assert_eq!(x[dims.calc_addr(&[2,0])], 0.);
+        assert_eq!(x[dims.calc_addr(&[2,2])], 1.);
+    }
+
+    #[test]
+    fn test_gaussian_blob() {
+        let dims = dim![20,15,9];
+        let x = gaussian_blob(&dims, &[12.,4.,6.], 2.);
+        let s = describe(&x, &dims);
+        assert_eq!(s.argmax, Some(vec![12,4,6]));
+        assert_eq!(s.max, 1.);
+        // one sigma away along an axis
+        assert!((x[dims.calc_addr(&[14,4,6])] - (-0.5f32).exp()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_smooth_phase() {
+        let dims = dim![16,12];
+        let x = shepp_logan_2d(&dims);
+        let z = with_smooth_phase(&x, &dims);
+        assert!(x.iter().zip(&z).all(|(a, b)| (a - b.norm()).abs() < 1e-6));
+        // the phase is smooth, so neighbours differ by well under a radian
+        let phase = with_smooth_phase(&vec![1.; dims.numel()], &dims);
+        for j in 0..12 {
+            for i in 1..16 {
+                let d = phase[dims.calc_addr(&[i,j])] / phase[dims.calc_addr(&[i - 1,j])];
+                assert!(d.arg().abs() < 0.5);
+            }
+        }
+    }
+}
+
+/// the ellipses of the modified Shepp-Logan phantom of Toft, as intensity, semi-axes along x and
+/// y, center and rotation in degrees, on a field of view of [-1, 1] along each axis
+const SHEPP_LOGAN_2D:[[f64; 6]; 10] = [
+    [1.0, 0.69, 0.92, 0.0, 0.0, 0.0],
+    [-0.8, 0.6624, 0.874, 0.0, -0.0184, 0.0],
+    [-0.2, 0.11, 0.31, 0.22, 0.0, -18.0],
+    [-0.2, 0.16, 0.41, -0.22, 0.0, 18.0],
+    [0.1, 0.21, 0.25, 0.0, 0.35, 0.0],
+    [0.1, 0.046, 0.046, 0.0, 0.1, 0.0],
+    [0.1, 0.046, 0.046, 0.0, -0.1, 0.0],
+    [0.1, 0.046, 0.023, -0.08, -0.605, 0.0],
+    [0.1, 0.023, 0.023, 0.0, -0.606, 0.0],
+    [0.1, 0.023, 0.046, 0.06, -0.605, 0.0],
+];
+
+/// the ellipsoids of the modified 3D Shepp-Logan phantom, as intensity, semi-axes along x, y and
+/// z, center and the Euler angles phi, theta and psi in degrees
+const SHEPP_LOGAN_3D:[[f64; 10]; 10] = [
+    [1.0, 0.69, 0.92, 0.81, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [-0.8, 0.6624, 0.874, 0.78, 0.0, -0.0184, 0.0, 0.0, 0.0, 0.0],
+    [-0.2, 0.11, 0.31, 0.22, 0.22, 0.0, 0.0, -18.0, 0.0, 10.0],
+    [-0.2, 0.16, 0.41, 0.28, -0.22, 0.0, 0.0, 18.0, 0.0, 10.0],
+    [0.1, 0.21, 0.25, 0.41, 0.0, 0.35, -0.15, 0.0, 0.0, 0.0],
+    [0.1, 0.046, 0.046, 0.05, 0.0, 0.1, 0.25, 0.0, 0.0, 0.0],
+    [0.1, 0.046, 0.046, 0.05, 0.0, -0.1, 0.25, 0.0, 0.0, 0.0],
+    [0.1, 0.046, 0.023, 0.05, -0.08, -0.605, 0.0, 0.0, 0.0, 0.0],
+    [0.1, 0.023, 0.023, 0.02, 0.0, -0.606, 0.0, 0.0, 0.0, 0.0],
+    [0.1, 0.023, 0.046, 0.02, 0.06, -0.605, 0.0, 0.0, 0.0, 0.0],
+];
+
+/// the modified Shepp-Logan head phantom in the plane of axes 0 (x) and 1 (y), repeated along
+/// any higher axes. The field of view spans [-1, 1] along both axes, sampled at pixel centers
+pub fn shepp_logan_2d(dims:&ArrayDim) -> Vec<f32> {
+    let (nx, ny) = (dims.shape()[0], dims.shape()[1]);
+    let ellipses = SHEPP_LOGAN_2D.map(|[a, sx, sy, x0, y0, phi]| {
+        let (sin, cos) = phi.to_radians().sin_cos();
+        (a, sx, sy, x0, y0, sin, cos)
+    });
+    generate(dims, |idx| {
+        let (x, y) = (centered_coord(idx[0], nx), centered_coord(idx[1], ny));
+        ellipses.iter().filter(|&&(_, sx, sy, x0, y0, sin, cos)| {
+            let (dx, dy) = (x - x0, y - y0);
+            let (u, v) = (dx * cos + dy * sin, -dx * sin + dy * cos);
+            (u / sx).powi(2) + (v / sy).powi(2) <= 1.
+        }).map(|e| e.0).sum::<f64>() as f32
+    })
+}
+
+/// the modified 3D Shepp-Logan head phantom in the volume of axes 0, 1 and 2, repeated along any
+/// higher axes. The field of view spans [-1, 1] along each axis, sampled at voxel centers
+pub fn shepp_logan_3d(dims:&ArrayDim) -> Vec<f32> {
+    let (nx, ny, nz) = (dims.shape()[0], dims.shape()[1], dims.shape()[2]);
+    let ellipsoids = SHEPP_LOGAN_3D.map(|[a, sx, sy, sz, x0, y0, z0, phi, theta, psi]| {
+        (a, [sx, sy, sz], [x0, y0, z0], euler_rotation(phi.to_radians(), theta.to_radians(), psi.to_radians()))
+    });
+    generate(dims, |idx| {
+        let p = [centered_coord(idx[0], nx), centered_coord(idx[1], ny), centered_coord(idx[2], nz)];
+        ellipsoids.iter().filter(|(_, axes, center, rot)| {
+            let d = [p[0] - center[0], p[1] - center[1], p[2] - center[2]];
+            rot.iter().zip(axes).map(|(row, s)| {
+                let u = row[0] * d[0] + row[1] * d[1] + row[2] * d[2];
+                (u / s).powi(2)
+            }).sum::<f64>() <= 1.
+        }).map(|e| e.0).sum::<f64>() as f32
+    })
+}
+
+/// an array of zeros with a 1 at position, where missing subscripts are 0
+pub fn delta(dims:&ArrayDim, position:&[usize]) -> Vec<f32> {
+    assert!(position.len() <= N_DIMS && position.iter().zip(dims.shape()).all(|(&i, &n)| i < n), "position {:?} is out of range", position);
+    let mut x = dims.alloc(0f32);
+    x[dims.calc_addr(position)] = 1.;
+    x
+}
+
+/// an array rising linearly from 0 at the first index of axis to 1 at the last. Singleton axes
+/// give a constant 0
+pub fn linear_ramp(dims:&ArrayDim, axis:usize) -> Vec<f32> {
+    assert!(axis < N_DIMS, "axis {} is out of range", axis);
+    let n = dims.shape()[axis];
+    let scale = if n > 1 { 1. / (n - 1) as f64 } else { 0. };
+    generate(dims, |idx| (idx[axis] as f64 * scale) as f32)
+}
+
+/// an array of alternating blocks of 1 and 0, where each block spans period samples along every
+/// axis. The block at the origin is 1
+pub fn checkerboard(dims:&ArrayDim, period:usize) -> Vec<f32> {
+    assert!(period > 0, "period must be at least 1");
+    generate(dims, |idx| {
+        let parity = idx.iter().map(|&i| i / period).sum::<usize>() % 2;
+        if parity == 0 { 1. } else { 0. }
+    })
+}
+
+/// an isotropic gaussian with a peak of 1 at center, with sigma in samples. The center is given
+/// in samples along each axis and may fall between samples, where missing coordinates are 0
+pub fn gaussian_blob(dims:&ArrayDim, center:&[f32], sigma:f32) -> Vec<f32> {
+    assert!(center.len() <= N_DIMS, "center has more than {} coordinates", N_DIMS);
+    assert!(sigma > 0., "sigma must be positive");
+    let mut c = [0f64; N_DIMS];
+    c.iter_mut().zip(center).for_each(|(c, &x)| *c = x as f64);
+    let scale = -0.5 / (sigma as f64 * sigma as f64);
+    generate(dims, |idx| {
+        let r2 = idx.iter().zip(&c).map(|(&i, &c)| (i as f64 - c).powi(2)).sum::<f64>();
+        (r2 * scale).exp() as f32
+    })
+}
+
+/// makes a complex array from magnitudes by applying a smooth phase, for testing fft and fftshift
+/// paths on data that isn't real. The phase is a sum of a linear and a quadratic term along each
+/// non-singleton axis, on the [-1, 1] field of view, and reaches a little over pi at the corners
+/// of a 2D array
+pub fn with_smooth_phase(magnitude:&[f32], dims:&ArrayDim) -> Vec<Complex32> {
+    assert_eq!(magnitude.len(), dims.numel(), "data must be the same size as array");
+    let shape = dims.shape();
+    magnitude.iter().enumerate().map(|(addr, &m)| {
+        let idx = dims.calc_idx(addr);
+        let phase = idx.iter().zip(shape).filter(|&(_, &n)| n > 1).map(|(&i, &n)| {
+            let x = centered_coord(i, n);
+            0.25 * PI * x + 0.3 * PI * x * x
+        }).sum::<f64>();
+        Complex32::from_polar(m, phase as f32)
+    }).collect()
+}
+
+/// evaluates f at the index of each element
+fn generate(dims:&ArrayDim, f:impl Fn(&[usize; N_DIMS]) -> f32) -> Vec<f32> {
+    (0..dims.numel()).map(|addr| f(&dims.calc_idx(addr))).collect()
+}
+
+/// the position of sample i of n on [-1, 1], at the center of its pixel
+fn centered_coord(i:usize, n:usize) -> f64 {
+    (2 * i + 1) as f64 / n as f64 - 1.
+}
+
+/// the rotation matrix of the z-x-z Euler angles phi, theta and psi
+fn euler_rotation(phi:f64, theta:f64, psi:f64) -> [[f64; 3]; 3] {
+    let (sphi, cphi) = phi.sin_cos();
+    let (stheta, ctheta) = theta.sin_cos();
+    let (spsi, cpsi) = psi.sin_cos();
+    [
+        [cpsi * cphi - ctheta * sphi * spsi, cpsi * sphi + ctheta * cphi * spsi, spsi * stheta],
+        [-spsi * cphi - ctheta * sphi * cpsi, -spsi * sphi + ctheta * cphi * cpsi, cpsi * stheta],
+        [stheta * sphi, -stheta * cphi, ctheta],
+    ]
+}
@@ -0,0 +1,149 @@
+/*
+    Python bindings for the readers and writers. The array_lib extension module is built from the
+    array-lib-py crate in python/ with maturin (see python/pyproject.toml), which registers these
+    functions. Arrays cross to numpy as F-ordered arrays, so array[i, j, k] is the element at
+    calc_addr(&[i, j, k]). Shapes are the ones declared by the file, including trailing singleton
+    axes, so they round-trip exactly
+ */
+
+use std::borrow::Cow;
+use std::fmt::Display;
+use num_complex::Complex32;
+use numpy::{Element, PyArray1, PyArrayDyn, PyArrayMethods, PyReadonlyArrayDyn, PyUntypedArrayMethods, NPY_ORDER};
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use crate::ArrayDim;
+use crate::io_bruker::{decode_fid as decode_fid_bytes, read_fid_params, resolve_fid_layout, BrukerDataError, ByteOrder, FidEncoding, FidLayout, SampleFormat};
+use crate::io_cfl::{cfl_base_name, read_cfl_shape, try_read_cfl, try_write_cfl_with_options, CflWriteOptions};
+use crate::io_mrd::try_read_mrd;
+use crate::io_nifti::{read_nifti_header, try_read_nifti, try_read_nifti_complex};
+
+/// the number of axes of a Bruker fid array
+const FID_RANK: usize = 6;
+
+/// adds the functions to the array_lib python module
+pub fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(read_cfl, m)?)?;
+    m.add_function(wrap_pyfunction!(write_cfl, m)?)?;
+    m.add_function(wrap_pyfunction!(read_nifti, m)?)?;
+    m.add_function(wrap_pyfunction!(read_mrd, m)?)?;
+    m.add_function(wrap_pyfunction!(read_fid, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_fid, m)?)?;
+    Ok(())
+}
+
+/// reads a cfl file pair to a complex64 array with the shape listed in the header
+#[pyfunction]
+fn read_cfl<'py>(py: Python<'py>, path: &str) -> PyResult<Bound<'py, PyArrayDyn<Complex32>>> {
+    let base = cfl_base_name(path);
+    let (data, _) = try_read_cfl(&base).map_err(io_err)?;
+    let shape = read_cfl_shape(&base).map_err(io_err)?;
+    to_numpy(py, data, &shape)
+}
+
+/// writes a complex64 array to a cfl file pair, listing every axis of the array in the header.
+/// F-contiguous arrays are written without copying, and any other layout is copied to
+/// column-major order first
+#[pyfunction]
+fn write_cfl(path: &str, array: PyReadonlyArrayDyn<'_, Complex32>) -> PyResult<()> {
+    let (data, dims) = from_numpy(&array)?;
+    let opts = CflWriteOptions::new().rank(array.ndim());
+    try_write_cfl_with_options(cfl_base_name(path), &data, dims, &opts).map_err(io_err)
+}
+
+/// reads a nifti file to a float32 array, or to a complex64 array if complex is set. The array
+/// has the number of axes declared in dim[0] of the header
+#[pyfunction]
+#[pyo3(signature = (path, complex = false))]
+fn read_nifti(py: Python<'_>, path: &str, complex: bool) -> PyResult<PyObject> {
+    let (_, header) = read_nifti_header(path).map_err(io_err)?;
+    let rank = (header.dim[0] as usize).clamp(1, 7);
+    if complex {
+        let (data, dims, _) = try_read_nifti_complex::<f32>(path).map_err(io_err)?;
+        Ok(to_numpy(py, data, &dims.shape()[..rank])?.into_any().unbind())
+    } else {
+        let (data, dims, _) = try_read_nifti::<f32>(path).map_err(io_err)?;
+        Ok(to_numpy(py, data, &dims.shape()[..rank])?.into_any().unbind())
+    }
+}
+
+/// reads an MRD file to a complex64 array of [samples, views, views_2, slices, echoes,
+/// experiments]
+#[pyfunction]
+fn read_mrd<'py>(py: Python<'py>, path: &str) -> PyResult<Bound<'py, PyArrayDyn<Complex32>>> {
+    let (data, dims, mrd) = try_read_mrd(path).map_err(io_err)?;
+    to_numpy(py, data, &dims.shape()[..mrd.dimensions().len()])
+}
+
+/// reads the fid file of a Bruker acquisition directory to a complex64 array of
+/// [samples, receivers, echoes, y, z, repeats]. The sample encoding is read from acqp and the
+/// layout is checked against the size of the fid file
+#[pyfunction]
+fn read_fid<'py>(py: Python<'py>, acq_dir: &str) -> PyResult<Bound<'py, PyArrayDyn<Complex32>>> {
+    let acq_dir = std::path::Path::new(acq_dir);
+    let params = read_fid_params(acq_dir.join("acqp")).map_err(io_err)?;
+    let dims = params.dims();
+    let fid_file = acq_dir.join("fid");
+    let enc = resolve_fid_layout(&fid_file, &dims, params.encoding).map_err(io_err)?;
+    let fid_bytes = std::fs::read(&fid_file)
+        .map_err(|e| io_err(BrukerDataError::IO{path: fid_file.clone(), msg: e.to_string()}))?;
+    to_numpy(py, decode_fid_bytes(&fid_bytes, enc, &dims), &dims.shape()[..FID_RANK])
+}
+
+/// decodes fid bytes held in memory to a complex64 array of the given shape, where the first 3
+/// axes make up each readout group. format is one of "i16", "i32" or "f32", byte_order is
+/// "little" or "big" and layout is "padded" or "continuous"
+#[pyfunction]
+#[pyo3(signature = (fid_bytes, shape, format = "i32", byte_order = "little", layout = "padded"))]
+fn decode_fid<'py>(py: Python<'py>, fid_bytes: &[u8], shape: Vec<usize>, format: &str, byte_order: &str, layout: &str) -> PyResult<Bound<'py, PyArrayDyn<Complex32>>> {
+    let format = match format {
+        "i16" => SampleFormat::I16,
+        "i32" => SampleFormat::I32,
+        "f32" => SampleFormat::F32,
+        _ => return Err(PyValueError::new_err(format!("unknown sample format {}", format))),
+    };
+    let byte_order = match byte_order {
+        "little" => ByteOrder::Little,
+        "big" => ByteOrder::Big,
+        _ => return Err(PyValueError::new_err(format!("unknown byte order {}", byte_order))),
+    };
+    let layout = match layout {
+        "padded" => FidLayout::Padded,
+        "continuous" => FidLayout::Continuous,
+        _ => return Err(PyValueError::new_err(format!("unknown fid layout {}", layout))),
+    };
+    let dims = ArrayDim::try_from_shape(&shape).map_err(value_err)?;
+    let chunk_size_samples = dims.shape()[..3].iter().product::<usize>();
+    let expected = layout.file_size(chunk_size_samples, dims.numel(), format);
+    if fid_bytes.len() < expected {
+        return Err(PyValueError::new_err(format!(
+            "shape {:?} needs {} bytes of fid data but {} were given", shape, expected, fid_bytes.len()
+        )));
+    }
+    let data = decode_fid_bytes(fid_bytes, FidEncoding { layout, format, byte_order }, &dims);
+    to_numpy(py, data, &shape)
+}
+
+/// hands a column-major array to numpy without copying, as an F-ordered array of the given shape
+fn to_numpy<'py, T: Element>(py: Python<'py>, data: Vec<T>, shape: &[usize]) -> PyResult<Bound<'py, PyArrayDyn<T>>> {
+    PyArray1::from_vec(py, data).reshape_with_order(shape, NPY_ORDER::NPY_FORTRANORDER)
+}
+
+/// the column-major data and dims of a numpy array. F-contiguous arrays are borrowed, and other
+/// layouts are copied to column-major order
+fn from_numpy<'a, T: Element + Copy>(array: &'a PyReadonlyArrayDyn<'_, T>) -> PyResult<(Cow<'a, [T]>, ArrayDim)> {
+    let dims = ArrayDim::try_from_shape(array.shape()).map_err(value_err)?;
+    if array.is_fortran_contiguous() {
+        return Ok((Cow::Borrowed(array.as_slice()?), dims));
+    }
+    // reversing the axes visits the elements in column-major order
+    Ok((Cow::Owned(array.as_array().t().iter().copied().collect()), dims))
+}
+
+fn io_err(e: impl Display) -> PyErr {
+    PyIOError::new_err(e.to_string())
+}
+
+fn value_err(e: impl Display) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
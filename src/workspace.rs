@@ -0,0 +1,67 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests {
+    use crate::workspace::Workspace;
+
+    #[test]
+    fn test_take_give_back() {
+        let mut ws = Workspace::new();
+        let mut a = ws.take::<f32>(10);
+        assert_eq!(a,vec![0.;10]);
+        a[3] = 1.;
+        let ptr = a.as_ptr();
+        ws.give_back(a);
+
+        // a different length or type gets a new buffer
+        assert_eq!(ws.take::<f32>(11).len(),11);
+        assert_eq!(ws.take::<u32>(10).len(),10);
+        let b = ws.take::<f32>(10);
+        assert_eq!(b.as_ptr(),ptr);
+        assert_eq!(b[3],1.);
+        ws.give_back(b);
+        ws.clear();
+        assert_ne!(ws.take::<f32>(10)[3],1.);
+    }
+}
+
+/// reusable buffers for pipelines that process many arrays of the same sizes, such as one frame at
+/// a time. Buffers are keyed by element type and length, so a buffer given back is handed out
+/// again by the next take of the same type and length instead of being freed and allocated again
+#[derive(Default)]
+pub struct Workspace {
+    /// buffers of each element type, held as a map from length to buffers
+    buffers: HashMap<TypeId, Box<dyn Any + Send>>,
+}
+
+impl Workspace {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// returns a buffer of numel elements, reusing one that was given back if there is one. A
+    /// reused buffer holds the values it had when it was given back, so every element must be
+    /// written before it is read. New buffers are filled with the default value
+    pub fn take<T:Copy + Default + Send + 'static>(&mut self, numel:usize) -> Vec<T> {
+        self.buffers_of::<T>().get_mut(&numel).and_then(|b| b.pop()).unwrap_or_else(|| vec![T::default(); numel])
+    }
+
+    /// keeps a buffer for a later take of the same type and length
+    pub fn give_back<T:Copy + Send + 'static>(&mut self, buffer:Vec<T>) {
+        self.buffers_of::<T>().entry(buffer.len()).or_default().push(buffer);
+    }
+
+    /// frees all held buffers
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+    }
+
+    fn buffers_of<T:Send + 'static>(&mut self) -> &mut HashMap<usize, Vec<Vec<T>>> {
+        self.buffers.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(HashMap::<usize, Vec<Vec<T>>>::new()))
+            .downcast_mut()
+            .expect("buffers are keyed by element type")
+    }
+}
@@ -0,0 +1,89 @@
+//! checks that a frame pipeline using a Workspace stops allocating once it is warmed up. The
+//! counting allocator replaces the global allocator of this test binary, so the binary holds this
+//! one test to keep other tests from adding to the count
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use num_complex::Complex32;
+use array_lib::{ArrayDim, PadMode};
+use array_lib::workspace::Workspace;
+
+/// counts the bytes allocated by all threads, including the thread pool workers
+struct CountingAlloc;
+
+static ALLOCATED:AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout:Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr:*mut u8, layout:Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOC:CountingAlloc = CountingAlloc;
+
+fn allocated() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+/// pads a frame, shifts it, moves the coil axis to the front and sums the coils into out
+fn process_frame(dims:&ArrayDim, frame:&[Complex32], out:&mut [Complex32]) {
+    let offset = dims.centered_offset(&[48,40]);
+    let (padded, pd) = dims.crop_pad(frame,&offset,&[48,40],PadMode::Constant,Complex32::ZERO);
+    let mut shifted = pd.alloc(Complex32::ZERO);
+    pd.fftshift(&padded,&mut shifted,true);
+    let mut permuted = pd.alloc(Complex32::ZERO);
+    pd.permute(&shifted,&mut permuted,&[2,0,1]);
+    sum_coils(&permuted,pd.shape()[2],out);
+}
+
+/// process_frame with buffers from a workspace
+fn process_frame_with_workspace(dims:&ArrayDim, frame:&[Complex32], ws:&mut Workspace, out:&mut [Complex32]) {
+    let offset = dims.centered_offset(&[48,40]);
+    let (padded, pd) = dims.crop_pad_with_workspace(frame,&offset,&[48,40],PadMode::Constant,Complex32::ZERO,ws);
+    let shifted = pd.fftshift_with_workspace(&padded,true,ws);
+    let (permuted, _) = pd.permute_with_workspace(&shifted,&[2,0,1],ws);
+    sum_coils(&permuted,pd.shape()[2],out);
+    ws.give_back(padded);
+    ws.give_back(shifted);
+    ws.give_back(permuted);
+}
+
+fn sum_coils(x:&[Complex32], n_coils:usize, out:&mut [Complex32]) {
+    out.iter_mut().zip(x.chunks_exact(n_coils)).for_each(|(o, coils)| *o += coils.iter().sum::<Complex32>());
+}
+
+#[test]
+fn test_bounded_allocations() {
+    let dims = ArrayDim::from_shape(&[32,30,4]);
+    let frames:Vec<Vec<Complex32>> = (0..100).map(|f| {
+        (0..dims.numel()).map(|i| Complex32::new((i * f % 17) as f32,f as f32)).collect()
+    }).collect();
+    let out_len = 48 * 40;
+    let frame_bytes = out_len * 4 * size_of::<Complex32>();
+
+    let mut expected = vec![Complex32::ZERO; out_len];
+    let before = allocated();
+    frames.iter().for_each(|f| process_frame(&dims,f,&mut expected));
+    let without_workspace = allocated() - before;
+
+    let mut ws = Workspace::new();
+    let mut out = vec![Complex32::ZERO; out_len];
+    // the first frame warms up the workspace
+    process_frame_with_workspace(&dims,&frames[0],&mut ws,&mut out);
+    let before = allocated();
+    for f in &frames[1..] {
+        process_frame_with_workspace(&dims,f,&mut ws,&mut out);
+    }
+    let with_workspace = allocated() - before;
+    assert_eq!(out,expected);
+
+    // without a workspace every frame allocates its padded buffers, while all 99 warmed up frames
+    // together allocate less than one padded frame, leaving room for thread pool bookkeeping
+    assert!(without_workspace >= 100 * frame_bytes,"{} bytes allocated without a workspace",without_workspace);
+    assert!(with_workspace < frame_bytes,"{} bytes allocated after warm up",with_workspace);
+}